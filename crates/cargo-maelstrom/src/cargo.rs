@@ -177,11 +177,11 @@ pub struct ManifestOptions {
     #[arg(long, value_name = "PATH")]
     pub manifest_path: Option<PathBuf>,
 
-    /// Require Cargo.lock and cache are up to date
+    /// Require Cargo.lock, maelstrom-container-tags.lock, and cache are up to date
     #[arg(long)]
     pub frozen: bool,
 
-    /// Require Cargo.lock is up to date
+    /// Require Cargo.lock and maelstrom-container-tags.lock are up to date
     #[arg(long)]
     pub locked: bool,
 }
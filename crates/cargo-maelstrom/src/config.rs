@@ -1,5 +1,6 @@
 use derive_more::From;
-use maelstrom_util::config::BrokerAddr;
+use maelstrom_base::proto::ArtifactCompression;
+use maelstrom_util::config::{BrokerAddr, LogFormat, LogLevel};
 use serde::{Deserialize, Serialize};
 use serde_with::skip_serializing_none;
 use std::fmt::{self, Debug, Formatter};
@@ -24,6 +25,9 @@ impl Debug for Quiet {
 #[serde(deny_unknown_fields)]
 pub struct Config {
     pub broker: BrokerAddr,
+    pub artifact_compression: ArtifactCompression,
+    pub log_level: LogLevel,
+    pub log_format: LogFormat,
     pub run: RunConfig,
 }
 
@@ -37,6 +41,9 @@ pub struct RunConfig {
 #[derive(Serialize)]
 pub struct ConfigOptions {
     pub broker: Option<String>,
+    pub artifact_compression: Option<ArtifactCompression>,
+    pub log_level: Option<LogLevel>,
+    pub log_format: Option<LogFormat>,
     pub run: RunConfigOptions,
 }
 
@@ -50,6 +57,10 @@ impl Default for ConfigOptions {
     fn default() -> Self {
         ConfigOptions {
             broker: None,
+            // Test binaries compress very well, so it's almost always worth compressing artifacts.
+            artifact_compression: Some(ArtifactCompression::Zstd),
+            log_level: Some(LogLevel::Warning),
+            log_format: Some(LogFormat::Text),
             run: RunConfigOptions { quiet: Some(false) },
         }
     }
@@ -16,14 +16,22 @@ use cargo::{
 use cargo_metadata::{Artifact as CargoArtifact, Package as CargoPackage, PackageId};
 use config::Quiet;
 use indicatif::{ProgressBar, TermLike};
-use maelstrom_base::{ArtifactType, JobSpec, NonEmpty, Sha256Digest, Timeout};
+use maelstrom_base::{
+    proto::ArtifactCompression, ArtifactType, JobSpec, NonEmpty, Sha256Digest, Timeout,
+};
 use maelstrom_client::{spec::ImageConfig, Client, ClientBgProcess, ClientDriverMode};
-use maelstrom_util::{config::BrokerAddr, process::ExitCode};
+use maelstrom_container::split_image_tag;
+use maelstrom_util::{
+    config::{BrokerAddr, LogFormat, LogLevel},
+    log,
+    process::ExitCode,
+};
 use metadata::{AllMetadata, TestMetadata};
 use progress::{
     MultipleProgressBars, NoBar, ProgressDriver, ProgressIndicator, QuietNoBar, QuietProgressBar,
     TestListingProgress, TestListingProgressNoSpinner,
 };
+use slog::{debug, o, Logger};
 use std::{
     collections::{BTreeMap, HashSet},
     io,
@@ -34,7 +42,9 @@ use std::{
         Arc, Mutex,
     },
 };
-use test_listing::{load_test_listing, write_test_listing, TestListing, LAST_TEST_LISTING_NAME};
+use test_listing::{
+    load_test_listing, write_test_listing, ArtifactKey, TestListing, LAST_TEST_LISTING_NAME,
+};
 use visitor::{JobStatusTracker, JobStatusVisitor};
 
 pub enum ListAction {
@@ -57,13 +67,13 @@ fn filter_package(package: &CargoPackage, p: &pattern::Pattern) -> bool {
 fn filter_case(
     package_name: &str,
     artifact: &CargoArtifact,
-    case: &str,
+    case: pattern::Case,
     p: &pattern::Pattern,
 ) -> bool {
     let c = pattern::Context {
         package: package_name.into(),
         artifact: Some(pattern::Artifact::from_target(&artifact.target)),
-        case: Some(pattern::Case { name: case.into() }),
+        case: Some(case),
     };
     pattern::interpret_pattern(p, &c).expect("case is provided")
 }
@@ -83,11 +93,12 @@ struct JobQueuingDeps<StdErrT> {
     jobs_queued: AtomicU64,
     test_metadata: AllMetadata,
     expected_job_count: u64,
-    test_listing: Mutex<TestListing>,
+    test_listing: Arc<Mutex<TestListing>>,
     list_action: Option<ListAction>,
     feature_selection_options: FeatureSelectionOptions,
     compilation_options: CompilationOptions,
     manifest_options: ManifestOptions,
+    log: Logger,
 }
 
 impl<StdErrT> JobQueuingDeps<StdErrT> {
@@ -104,6 +115,7 @@ impl<StdErrT> JobQueuingDeps<StdErrT> {
         feature_selection_options: FeatureSelectionOptions,
         compilation_options: CompilationOptions,
         manifest_options: ManifestOptions,
+        log: Logger,
     ) -> Self {
         let expected_job_count = test_listing.expected_job_count(&filter);
 
@@ -117,11 +129,12 @@ impl<StdErrT> JobQueuingDeps<StdErrT> {
             jobs_queued: AtomicU64::new(0),
             test_metadata,
             expected_job_count,
-            test_listing: Mutex::new(test_listing),
+            test_listing: Arc::new(Mutex::new(test_listing)),
             list_action,
             feature_selection_options,
             compilation_options,
             manifest_options,
+            log,
         }
     }
 }
@@ -173,9 +186,13 @@ where
     let mut cases = get_cases_from_binary(&binary, &None)?;
 
     let mut listing = queuing_deps.test_listing.lock().unwrap();
-    listing.add_cases(package_name, artifact, &cases[..]);
+    listing.add_cases(package_name, artifact, &cases[..], &ignored_cases);
 
-    cases.retain(|c| filter_case(package_name, artifact, c, &queuing_deps.filter));
+    let artifact_key = ArtifactKey::from_target(&artifact.target);
+    cases.retain(|c| {
+        let case = listing.case(package_name, &artifact_key, c);
+        filter_case(package_name, artifact, case, &queuing_deps.filter)
+    });
     Ok(TestListingResult {
         cases,
         ignored_cases,
@@ -270,7 +287,8 @@ where
         }
 
         let image_lookup = |image: &str| {
-            let (image, version) = image.split_once(':').unwrap_or((image, "latest"));
+            let (image, version) = split_image_tag(image);
+            let version = version.unwrap_or("latest");
             let prog = self
                 .ind
                 .new_side_progress(format!("downloading image {image}"))
@@ -281,13 +299,19 @@ where
                 layers: image.layers.clone(),
                 environment: image.env().cloned(),
                 working_directory: image.working_dir().map(From::from),
+                stop_signal: image.stop_signal().cloned(),
             })
         };
 
+        let artifact_key = ArtifactKey::from_target(&self.artifact.target);
         let filter_context = pattern::Context {
             package: self.package_name.clone(),
             artifact: Some(pattern::Artifact::from_target(&self.artifact.target)),
-            case: Some(pattern::Case { name: case.into() }),
+            case: Some(self.queuing_deps.test_listing.lock().unwrap().case(
+                &self.package_name,
+                &artifact_key,
+                case,
+            )),
         };
 
         let test_metadata = self
@@ -305,6 +329,7 @@ where
             count + 1,
         ));
 
+        let log = self.queuing_deps.log.new(o!("case" => case_str.clone()));
         let visitor = JobStatusVisitor::new(
             self.queuing_deps.tracker.clone(),
             case_str,
@@ -318,6 +343,9 @@ where
         }
 
         let binary_name = self.binary.file_name().unwrap().to_str().unwrap();
+        let test_listing = self.queuing_deps.test_listing.clone();
+        let package_name = self.package_name.clone();
+        let case_name = case.to_owned();
         self.client.lock().unwrap().add_job(
             JobSpec {
                 program: format!("/{binary_name}").into(),
@@ -326,14 +354,30 @@ where
                 layers,
                 devices: test_metadata.devices,
                 mounts: test_metadata.mounts,
-                enable_loopback: test_metadata.enable_loopback,
+                network: test_metadata.network,
                 enable_writable_file_system: test_metadata.enable_writable_file_system,
                 working_directory: test_metadata.working_directory,
                 user: test_metadata.user,
                 group: test_metadata.group,
                 timeout: self.timeout_override.unwrap_or(test_metadata.timeout),
+                stop_signal: test_metadata.stop_signal,
+                grace_period: test_metadata.grace_period,
+                stack_dump_on_timeout: test_metadata.stack_dump_on_timeout,
+                seccomp: test_metadata.seccomp,
+                rlimits: test_metadata.rlimits,
+                hostname: test_metadata.hostname,
+                domainname: test_metadata.domainname,
             },
-            Box::new(move |cjid, result| visitor.job_finished(cjid, result)),
+            Box::new(move |cjid, result| {
+                debug!(log, "job completed"; "cjid" => %cjid);
+                test_listing.lock().unwrap().record_result(
+                    &package_name,
+                    &artifact_key,
+                    &case_name,
+                    &result,
+                );
+                visitor.job_finished(cjid, result)
+            }),
         )?;
 
         Ok(EnqueueResult::Enqueued {
@@ -500,6 +544,9 @@ impl<StdErrT> MainAppDeps<StdErrT> {
     /// `workspace_root`: the path to the root of the workspace
     /// `workspace_packages`: a listing of the packages in the workspace
     /// `broker_addr`: the network address of the broker which we connect to
+    /// `artifact_compression`: how to compress artifacts pushed to the broker
+    /// `log_level`: the minimum level of log messages written to stderr
+    /// `log_format`: the format log messages are written in
     /// `client_driver`: an object which drives the background work of the `Client`
     #[allow(clippy::too_many_arguments)]
     pub fn new(
@@ -513,18 +560,28 @@ impl<StdErrT> MainAppDeps<StdErrT> {
         workspace_root: &impl AsRef<Path>,
         workspace_packages: &[&CargoPackage],
         broker_addr: BrokerAddr,
+        artifact_compression: ArtifactCompression,
+        log_level: LogLevel,
+        log_format: LogFormat,
         driver_mode: ClientDriverMode,
         feature_selection_options: FeatureSelectionOptions,
         compilation_options: CompilationOptions,
         manifest_options: ManifestOptions,
     ) -> Result<Self> {
         let cache_dir = workspace_root.as_ref().join("target");
+        let locked = manifest_options.locked || manifest_options.frozen;
+        let log = log::root_logger(log_level, log_format);
+        debug!(log, "connecting to broker"; "broker_addr" => %broker_addr);
         let client = Mutex::new(Client::new(
             bg_proc,
             driver_mode,
             broker_addr,
+            artifact_compression,
             workspace_root,
             cache_dir.clone(),
+            locked,
+            log_level,
+            log_format,
         )?);
         let test_metadata = AllMetadata::load(workspace_root)?;
         let mut test_listing =
@@ -552,6 +609,7 @@ impl<StdErrT> MainAppDeps<StdErrT> {
                 feature_selection_options,
                 compilation_options,
                 manifest_options,
+                log,
             ),
             cache_dir,
         })
@@ -14,9 +14,14 @@ use figment::{
     providers::{Env, Format, Serialized, Toml},
     Figment,
 };
-use maelstrom_base::Timeout;
+use maelstrom_base::{proto::ArtifactCompression, Timeout};
 use maelstrom_client::ClientBgProcess;
-use maelstrom_util::{clap as clap_util, process::ExitCode};
+use maelstrom_container::update_container_tags;
+use maelstrom_util::{
+    clap as clap_util,
+    config::{LogFormat, LogLevel},
+    process::ExitCode,
+};
 use std::{
     env,
     io::IsTerminal as _,
@@ -45,6 +50,19 @@ struct CliOptions {
     #[arg(long, short, value_name = "SOCKADDR")]
     broker: Option<String>,
 
+    /// How to compress artifacts pushed to the broker. One of none, gzip, or zstd. If the broker
+    /// doesn't support it, artifacts are pushed uncompressed.
+    #[arg(long, value_name = "COMPRESSION")]
+    artifact_compression: Option<ArtifactCompression>,
+
+    /// Minimum log level to output.
+    #[arg(long, value_name = "LEVEL", value_enum)]
+    log_level: Option<LogLevel>,
+
+    /// The format to write log messages in.
+    #[arg(long, value_name = "FORMAT", value_enum)]
+    log_format: Option<LogFormat>,
+
     #[command(subcommand)]
     command: CliCommand,
 
@@ -65,6 +83,22 @@ enum CliCommand {
 
     /// List tests, binaries, or packages
     List(CliList),
+
+    /// Update the digests pinned in maelstrom-container-tags.lock to what their tags currently
+    /// resolve to, printing the changes
+    UpdateContainerTags(CliUpdateContainerTags),
+}
+
+#[derive(Args, Debug)]
+struct CliUpdateContainerTags {
+    /// Only update these images. Each is either a name, which selects all of its tags, or a
+    /// "name:tag" pair. If none are given, every pinned tag is updated
+    #[arg(value_name = "IMAGE")]
+    images: Vec<String>,
+
+    /// Don't modify the lockfile. Exit with a failure if any pinned digest is out of date
+    #[arg(long)]
+    check: bool,
 }
 
 #[derive(Args, Debug)]
@@ -173,6 +207,9 @@ pub fn main() -> Result<ExitCode> {
     };
 
     let (config, include, exclude, list_action, timeout_override) = match cli_options.command {
+        CliCommand::UpdateContainerTags(CliUpdateContainerTags { images, check }) => {
+            return update_container_tags(&cargo_metadata.workspace_root, &images, check);
+        }
         CliCommand::List(CliList {
             what,
             include,
@@ -183,6 +220,9 @@ pub fn main() -> Result<ExitCode> {
                 config_file,
                 ConfigOptions {
                     broker: cli_options.broker,
+                    artifact_compression: cli_options.artifact_compression,
+                    log_level: cli_options.log_level,
+                    log_format: cli_options.log_format,
                     run: RunConfigOptions { quiet: None },
                 },
             )?;
@@ -213,6 +253,9 @@ pub fn main() -> Result<ExitCode> {
                 config_file,
                 ConfigOptions {
                     broker: cli_options.broker,
+                    artifact_compression: cli_options.artifact_compression,
+                    log_level: cli_options.log_level,
+                    log_format: cli_options.log_format,
                     run: RunConfigOptions {
                         quiet: quiet.then_some(true),
                     },
@@ -237,6 +280,9 @@ pub fn main() -> Result<ExitCode> {
         &cargo_metadata.workspace_root,
         &cargo_metadata.workspace_packages(),
         config.broker,
+        config.artifact_compression,
+        config.log_level,
+        config.log_format,
         Default::default(),
         cli_options.cargo_feature_selection_options,
         cli_options.cargo_compilation_options,
@@ -3,7 +3,10 @@ mod directive;
 use crate::pattern;
 use anyhow::{Context as _, Error, Result};
 use directive::TestDirective;
-use maelstrom_base::{EnumSet, GroupId, JobDevice, JobMount, Timeout, UserId, Utf8PathBuf};
+use maelstrom_base::{
+    EnumSet, GroupId, JobDevice, JobMount, JobNetwork, JobRlimit, JobSeccomp, JobSignal, Timeout,
+    UserId, Utf8PathBuf,
+};
 use maelstrom_client::spec::{self, substitute, ImageConfig, ImageOption, Layer, PossiblyImage};
 use maelstrom_util::fs::Fs;
 use serde::Deserialize;
@@ -18,12 +21,19 @@ pub struct AllMetadata {
 #[derive(Debug, Eq, PartialEq)]
 pub struct TestMetadata {
     include_shared_libraries: Option<bool>,
-    pub enable_loopback: bool,
+    pub network: JobNetwork,
     pub enable_writable_file_system: bool,
     pub working_directory: Utf8PathBuf,
     pub user: UserId,
     pub group: GroupId,
     pub timeout: Option<Timeout>,
+    pub stop_signal: Option<JobSignal>,
+    pub grace_period: Option<u32>,
+    pub stack_dump_on_timeout: bool,
+    pub seccomp: Option<JobSeccomp>,
+    pub rlimits: Vec<JobRlimit>,
+    pub hostname: Option<String>,
+    pub domainname: Option<String>,
     pub layers: Vec<Layer>,
     environment: BTreeMap<String, String>,
     pub mounts: Vec<JobMount>,
@@ -34,12 +44,19 @@ impl Default for TestMetadata {
     fn default() -> Self {
         Self {
             include_shared_libraries: Default::default(),
-            enable_loopback: Default::default(),
+            network: Default::default(),
             enable_writable_file_system: Default::default(),
             working_directory: Utf8PathBuf::from("/"),
             user: UserId::from(0),
             group: GroupId::from(0),
             timeout: None,
+            stop_signal: None,
+            grace_period: None,
+            stack_dump_on_timeout: false,
+            seccomp: None,
+            rlimits: Default::default(),
+            hostname: None,
+            domainname: None,
             layers: Default::default(),
             environment: Default::default(),
             mounts: Default::default(),
@@ -74,11 +91,19 @@ impl TestMetadata {
             filter: _,
             ref image,
             include_shared_libraries,
-            enable_loopback,
+            ref network,
             enable_writable_file_system,
             user,
             group,
             timeout,
+            stop_signal,
+            grace_period,
+            stack_dump_on_timeout,
+            ref seccomp,
+            ref rlimits,
+            ref added_rlimits,
+            ref hostname,
+            ref domainname,
             ref layers,
             ref added_layers,
             ref mounts,
@@ -95,12 +120,21 @@ impl TestMetadata {
         let image = ImageOption::new(image, image_lookup)?;
 
         self.include_shared_libraries = include_shared_libraries.or(self.include_shared_libraries);
-        self.enable_loopback = enable_loopback.unwrap_or(self.enable_loopback);
+        self.network = network.clone().unwrap_or(self.network);
         self.enable_writable_file_system =
             enable_writable_file_system.unwrap_or(self.enable_writable_file_system);
         self.user = user.unwrap_or(self.user);
         self.group = group.unwrap_or(self.group);
         self.timeout = timeout.unwrap_or(self.timeout);
+        self.stop_signal = match stop_signal {
+            Some(stop_signal) => Some(stop_signal),
+            None => image.stop_signal()?.or(self.stop_signal),
+        };
+        self.grace_period = grace_period.or(self.grace_period);
+        self.stack_dump_on_timeout = stack_dump_on_timeout.unwrap_or(self.stack_dump_on_timeout);
+        self.seccomp = seccomp.clone().or(self.seccomp);
+        self.hostname = hostname.clone().or(self.hostname);
+        self.domainname = domainname.clone().or(self.domainname);
 
         match layers {
             Some(PossiblyImage::Explicit(layers)) => {
@@ -118,6 +152,11 @@ impl TestMetadata {
             .map_or(self.mounts, |mounts| mounts.to_vec());
         self.mounts.extend(added_mounts.iter().cloned());
 
+        self.rlimits = rlimits
+            .as_ref()
+            .map_or(self.rlimits, |rlimits| rlimits.to_vec());
+        self.rlimits.extend(added_rlimits.iter().cloned());
+
         self.devices = devices.unwrap_or(self.devices).union(added_devices);
 
         fn substitute_environment(
@@ -217,7 +256,10 @@ impl AllMetadata {
 #[cfg(test)]
 mod test {
     use super::*;
-    use maelstrom_base::{enum_set, JobMountFsType};
+    use maelstrom_base::{
+        enum_set, JobMountFsType, JobRlimitResource, JobRlimitValue, JobSeccompAction,
+        JobSeccompPolicy, TmpfsOptions,
+    };
     use maelstrom_test::{path_buf_vec, string, string_vec, tar_layer, utf8_path_buf};
     use toml::de::Error as TomlError;
 
@@ -228,7 +270,10 @@ mod test {
                 name: package.into(),
                 kind: pattern::ArtifactKind::Library,
             }),
-            case: Some(pattern::Case { name: test.into() }),
+            case: Some(pattern::Case {
+                name: test.into(),
+                ..Default::default()
+            }),
         }
     }
 
@@ -324,20 +369,60 @@ mod test {
             "#,
         )
         .unwrap();
-        assert!(
-            !all.get_metadata_for_test(&test_ctx("package1", "test1"), empty_env, no_containers)
+        assert_eq!(
+            all.get_metadata_for_test(&test_ctx("package1", "test1"), empty_env, no_containers)
                 .unwrap()
-                .enable_loopback
+                .network,
+            JobNetwork::Disabled,
         );
-        assert!(
+        assert_eq!(
             all.get_metadata_for_test(&test_ctx("package1", "test2"), empty_env, no_containers)
                 .unwrap()
-                .enable_loopback
+                .network,
+            JobNetwork::Loopback,
         );
-        assert!(
-            !all.get_metadata_for_test(&test_ctx("package2", "test1"), empty_env, no_containers)
+        assert_eq!(
+            all.get_metadata_for_test(&test_ctx("package2", "test1"), empty_env, no_containers)
+                .unwrap()
+                .network,
+            JobNetwork::Disabled,
+        );
+    }
+
+    #[test]
+    fn network() {
+        let all = AllMetadata::from_str(
+            r#"
+            [[directives]]
+            network = "loopback"
+
+            [[directives]]
+            filter = "package.equals(package1)"
+            network = "nat"
+
+            [[directives]]
+            filter = "package.equals(package1) && name.equals(test1)"
+            enable_loopback = false
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            all.get_metadata_for_test(&test_ctx("package1", "test1"), empty_env, no_containers)
+                .unwrap()
+                .network,
+            JobNetwork::Disabled,
+        );
+        assert_eq!(
+            all.get_metadata_for_test(&test_ctx("package1", "test2"), empty_env, no_containers)
                 .unwrap()
-                .enable_loopback
+                .network,
+            JobNetwork::Nat(Default::default()),
+        );
+        assert_eq!(
+            all.get_metadata_for_test(&test_ctx("package2", "test1"), empty_env, no_containers)
+                .unwrap()
+                .network,
+            JobNetwork::Loopback,
         );
     }
 
@@ -525,6 +610,185 @@ mod test {
         );
     }
 
+    #[test]
+    fn seccomp() {
+        let all = AllMetadata::from_str(
+            r#"
+            [[directives]]
+            filter = "package.equals(package1)"
+            seccomp = "default"
+
+            [[directives]]
+            filter = "package.equals(package1) && name.equals(test1)"
+            seccomp = {}
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            all.get_metadata_for_test(&test_ctx("package1", "test1"), empty_env, no_containers)
+                .unwrap()
+                .seccomp,
+            Some(JobSeccomp::Inline(JobSeccompPolicy {
+                default_action: JobSeccompAction::Allow,
+                rules: vec![],
+            })),
+        );
+        assert_eq!(
+            all.get_metadata_for_test(&test_ctx("package1", "test2"), empty_env, no_containers)
+                .unwrap()
+                .seccomp,
+            Some(JobSeccomp::Named("default".into())),
+        );
+        assert_eq!(
+            all.get_metadata_for_test(&test_ctx("package2", "test1"), empty_env, no_containers)
+                .unwrap()
+                .seccomp,
+            None,
+        );
+    }
+
+    #[test]
+    fn rlimits() {
+        let all = AllMetadata::from_str(
+            r#"
+            [[directives]]
+            rlimits = [ { resource = "nofile", soft = 1024, hard = 1024 } ]
+
+            [[directives]]
+            filter = "package.equals(package1)"
+            added_rlimits = [ { resource = "core", soft = 0, hard = 0 } ]
+
+            [[directives]]
+            filter = "package.equals(package1) && name.equals(test1)"
+            rlimits = []
+            "#,
+        )
+        .unwrap();
+        let nofile = JobRlimit {
+            resource: JobRlimitResource::Nofile,
+            soft: JobRlimitValue::Limited(1024),
+            hard: JobRlimitValue::Limited(1024),
+        };
+        let core = JobRlimit {
+            resource: JobRlimitResource::Core,
+            soft: JobRlimitValue::Limited(0),
+            hard: JobRlimitValue::Limited(0),
+        };
+        assert_eq!(
+            all.get_metadata_for_test(&test_ctx("package1", "test1"), empty_env, no_containers)
+                .unwrap()
+                .rlimits,
+            vec![],
+        );
+        assert_eq!(
+            all.get_metadata_for_test(&test_ctx("package1", "test2"), empty_env, no_containers)
+                .unwrap()
+                .rlimits,
+            vec![nofile.clone(), core],
+        );
+        assert_eq!(
+            all.get_metadata_for_test(&test_ctx("package2", "test1"), empty_env, no_containers)
+                .unwrap()
+                .rlimits,
+            vec![nofile],
+        );
+    }
+
+    #[test]
+    fn hostname() {
+        let all = AllMetadata::from_str(
+            r#"
+            [[directives]]
+            hostname = "foo"
+
+            [[directives]]
+            filter = "package.equals(package1)"
+            hostname = "bar"
+            domainname = "example.com"
+            "#,
+        )
+        .unwrap();
+        let metadata = all
+            .get_metadata_for_test(&test_ctx("package1", "test1"), empty_env, no_containers)
+            .unwrap();
+        assert_eq!(metadata.hostname, Some(string!("bar")));
+        assert_eq!(metadata.domainname, Some(string!("example.com")));
+        let metadata = all
+            .get_metadata_for_test(&test_ctx("package2", "test1"), empty_env, no_containers)
+            .unwrap();
+        assert_eq!(metadata.hostname, Some(string!("foo")));
+        assert_eq!(metadata.domainname, None);
+    }
+
+    #[test]
+    fn stop_signal() {
+        let image_lookup = |name: &_| match name {
+            "image1" => Ok(ImageConfig {
+                stop_signal: Some(string!("SIGINT")),
+                ..Default::default()
+            }),
+            _ => panic!(),
+        };
+        let all = AllMetadata::from_str(
+            r#"
+            [[directives]]
+            stop_signal = "SIGTERM"
+            grace_period = 5
+
+            [[directives]]
+            filter = "package.equals(package1)"
+            image.name = "image1"
+            image.use = []
+
+            [[directives]]
+            filter = "package.equals(package1) && name.equals(test1)"
+            stop_signal = "SIGUSR1"
+            grace_period = 0
+            "#,
+        )
+        .unwrap();
+        let metadata = all
+            .get_metadata_for_test(&test_ctx("package1", "test1"), empty_env, image_lookup)
+            .unwrap();
+        assert_eq!(metadata.stop_signal, Some(JobSignal::Sigusr1));
+        assert_eq!(metadata.grace_period, Some(0));
+        let metadata = all
+            .get_metadata_for_test(&test_ctx("package1", "test2"), empty_env, image_lookup)
+            .unwrap();
+        assert_eq!(metadata.stop_signal, Some(JobSignal::Sigint));
+        assert_eq!(metadata.grace_period, Some(5));
+        let metadata = all
+            .get_metadata_for_test(&test_ctx("package2", "test1"), empty_env, image_lookup)
+            .unwrap();
+        assert_eq!(metadata.stop_signal, Some(JobSignal::Sigterm));
+        assert_eq!(metadata.grace_period, Some(5));
+    }
+
+    #[test]
+    fn stack_dump_on_timeout() {
+        let all = AllMetadata::from_str(
+            r#"
+            [[directives]]
+            stack_dump_on_timeout = true
+
+            [[directives]]
+            filter = "package.equals(package1)"
+            stack_dump_on_timeout = false
+            "#,
+        )
+        .unwrap();
+        assert!(
+            !all.get_metadata_for_test(&test_ctx("package1", "test1"), empty_env, no_containers)
+                .unwrap()
+                .stack_dump_on_timeout
+        );
+        assert!(
+            all.get_metadata_for_test(&test_ctx("package2", "test1"), empty_env, no_containers)
+                .unwrap()
+                .stack_dump_on_timeout
+        );
+    }
+
     #[test]
     fn layers() {
         let image_lookup = |name: &_| match name {
@@ -808,7 +1072,7 @@ mod test {
                 .mounts,
             vec![
                 JobMount {
-                    fs_type: JobMountFsType::Tmp,
+                    fs_type: JobMountFsType::Tmp(TmpfsOptions::default()),
                     mount_point: utf8_path_buf!("/tmp"),
                 },
                 JobMount {
@@ -887,7 +1151,7 @@ mod test {
                     mount_point: utf8_path_buf!("/sys"),
                 },
                 JobMount {
-                    fs_type: JobMountFsType::Tmp,
+                    fs_type: JobMountFsType::Tmp(TmpfsOptions::default()),
                     mount_point: utf8_path_buf!("/tmp"),
                 },
             ],
@@ -906,7 +1170,7 @@ mod test {
                     mount_point: utf8_path_buf!("/sys"),
                 },
                 JobMount {
-                    fs_type: JobMountFsType::Tmp,
+                    fs_type: JobMountFsType::Tmp(TmpfsOptions::default()),
                     mount_point: utf8_path_buf!("/tmp"),
                 },
                 JobMount {
@@ -920,7 +1184,7 @@ mod test {
                 .unwrap()
                 .mounts,
             vec![JobMount {
-                fs_type: JobMountFsType::Tmp,
+                fs_type: JobMountFsType::Tmp(TmpfsOptions::default()),
                 mount_point: utf8_path_buf!("/tmp"),
             },],
         );
@@ -929,7 +1193,7 @@ mod test {
                 .unwrap()
                 .mounts,
             vec![JobMount {
-                fs_type: JobMountFsType::Tmp,
+                fs_type: JobMountFsType::Tmp(TmpfsOptions::default()),
                 mount_point: utf8_path_buf!("/tmp"),
             },],
         );
@@ -1,7 +1,8 @@
 use crate::pattern;
 use anyhow::Result;
 use maelstrom_base::{
-    EnumSet, GroupId, JobDevice, JobDeviceListDeserialize, JobMount, Timeout, UserId, Utf8PathBuf,
+    EnumSet, GroupId, JobDevice, JobDeviceListDeserialize, JobMount, JobNetwork, JobRlimit,
+    JobSeccomp, JobSignal, Timeout, UserId, Utf8PathBuf,
 };
 use maelstrom_client::spec::{incompatible, Image, ImageUse, Layer, PossiblyImage, UntaggedLayer};
 use serde::{de, Deserialize, Deserializer};
@@ -14,11 +15,19 @@ pub struct TestDirective {
     // This will be Some if any of the other fields are Some(AllMetadata::Image).
     pub image: Option<String>,
     pub include_shared_libraries: Option<bool>,
-    pub enable_loopback: Option<bool>,
+    pub network: Option<JobNetwork>,
     pub enable_writable_file_system: Option<bool>,
     pub user: Option<UserId>,
     pub group: Option<GroupId>,
     pub timeout: Option<Option<Timeout>>,
+    pub stop_signal: Option<JobSignal>,
+    pub grace_period: Option<u32>,
+    pub stack_dump_on_timeout: Option<bool>,
+    pub seccomp: Option<JobSeccomp>,
+    pub rlimits: Option<Vec<JobRlimit>>,
+    pub added_rlimits: Vec<JobRlimit>,
+    pub hostname: Option<String>,
+    pub domainname: Option<String>,
     pub layers: Option<PossiblyImage<Vec<Layer>>>,
     pub added_layers: Vec<Layer>,
     pub mounts: Option<Vec<JobMount>>,
@@ -35,11 +44,20 @@ pub struct TestDirective {
 enum DirectiveField {
     Filter,
     IncludeSharedLibraries,
+    Network,
     EnableLoopback,
     EnableWritableFileSystem,
     User,
     Group,
     Timeout,
+    StopSignal,
+    GracePeriod,
+    StackDumpOnTimeout,
+    Seccomp,
+    Rlimits,
+    AddedRlimits,
+    Hostname,
+    Domainname,
     Mounts,
     AddedMounts,
     Devices,
@@ -67,11 +85,20 @@ impl<'de> de::Visitor<'de> for DirectiveVisitor {
     {
         let mut filter = None;
         let mut include_shared_libraries = None;
+        let mut network = None;
         let mut enable_loopback = None;
         let mut enable_writable_file_system = None;
         let mut user = None;
         let mut group = None;
         let mut timeout = None;
+        let mut stop_signal = None;
+        let mut grace_period = None;
+        let mut stack_dump_on_timeout = None;
+        let mut seccomp = None;
+        let mut rlimits = None;
+        let mut added_rlimits = None;
+        let mut hostname = None;
+        let mut domainname = None;
         let mut mounts = None;
         let mut added_mounts = None;
         let mut devices = None;
@@ -94,6 +121,9 @@ impl<'de> de::Visitor<'de> for DirectiveVisitor {
                 DirectiveField::IncludeSharedLibraries => {
                     include_shared_libraries = Some(map.next_value()?);
                 }
+                DirectiveField::Network => {
+                    network = Some(map.next_value()?);
+                }
                 DirectiveField::EnableLoopback => {
                     enable_loopback = Some(map.next_value()?);
                 }
@@ -109,6 +139,34 @@ impl<'de> de::Visitor<'de> for DirectiveVisitor {
                 DirectiveField::Timeout => {
                     timeout = Some(Timeout::new(map.next_value()?));
                 }
+                DirectiveField::StopSignal => {
+                    stop_signal = Some(map.next_value()?);
+                }
+                DirectiveField::GracePeriod => {
+                    grace_period = Some(map.next_value()?);
+                }
+                DirectiveField::StackDumpOnTimeout => {
+                    stack_dump_on_timeout = Some(map.next_value()?);
+                }
+                DirectiveField::Seccomp => {
+                    seccomp = Some(map.next_value()?);
+                }
+                DirectiveField::Rlimits => {
+                    incompatible(
+                        &added_rlimits,
+                        "field `rlimits` cannot be set after `added_rlimits`",
+                    )?;
+                    rlimits = Some(map.next_value()?);
+                }
+                DirectiveField::AddedRlimits => {
+                    added_rlimits = Some(map.next_value()?);
+                }
+                DirectiveField::Hostname => {
+                    hostname = Some(map.next_value()?);
+                }
+                DirectiveField::Domainname => {
+                    domainname = Some(map.next_value()?);
+                }
                 DirectiveField::Mounts => {
                     incompatible(
                         &added_mounts,
@@ -209,14 +267,33 @@ impl<'de> de::Visitor<'de> for DirectiveVisitor {
                 }
             }
         }
+        // `enable_loopback` predates `network`, and is still accepted as a shorthand for it.
+        let network = match (network, enable_loopback) {
+            (Some(_), Some(_)) => {
+                return Err(de::Error::custom(format_args!(
+                    "field `enable_loopback` cannot be set with `network` field"
+                )));
+            }
+            (None, Some(true)) => Some(JobNetwork::Loopback),
+            (None, Some(false)) => Some(JobNetwork::Disabled),
+            (network, None) => network,
+        };
         Ok(TestDirective {
             filter,
             include_shared_libraries,
-            enable_loopback,
+            network,
             enable_writable_file_system,
             user,
             group,
             timeout,
+            stop_signal,
+            grace_period,
+            stack_dump_on_timeout,
+            seccomp,
+            rlimits,
+            added_rlimits: added_rlimits.unwrap_or_default(),
+            hostname,
+            domainname,
             layers,
             added_layers: added_layers.unwrap_or_default(),
             mounts,
@@ -244,8 +321,12 @@ impl<'de> de::Deserialize<'de> for TestDirective {
 mod test {
     use super::*;
     use anyhow::Error;
-    use maelstrom_base::{enum_set, JobMountFsType};
-    use maelstrom_client::spec::SymlinkSpec;
+    use maelstrom_base::{
+        enum_set, JobMountFsType, JobNatOptions, JobPortForward, JobPortProtocol,
+        JobRlimitResource, JobRlimitValue, JobSeccompAction, JobSeccompPolicy, JobSeccompRule,
+        TmpfsOptions,
+    };
+    use maelstrom_client::spec::{FileContents, FileSpec, PrefixOptions, SymlinkSpec};
     use maelstrom_test::{glob_layer, paths_layer, string, tar_layer, utf8_path_buf};
     use toml::de::Error as TomlError;
 
@@ -313,7 +394,7 @@ mod test {
                         .unwrap()
                 ),
                 include_shared_libraries: Some(true),
-                enable_loopback: Some(false),
+                network: Some(JobNetwork::Disabled),
                 enable_writable_file_system: Some(true),
                 user: Some(UserId::from(101)),
                 group: Some(GroupId::from(202)),
@@ -323,6 +404,53 @@ mod test {
         );
     }
 
+    #[test]
+    fn stop_signal_and_grace_period() {
+        assert_eq!(
+            parse_test_directive(
+                r#"
+                stop_signal = "SIGTERM"
+                grace_period = 5
+                "#
+            )
+            .unwrap(),
+            TestDirective {
+                stop_signal: Some(JobSignal::Sigterm),
+                grace_period: Some(5),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn stack_dump_on_timeout() {
+        assert_eq!(
+            parse_test_directive(
+                r#"
+                stack_dump_on_timeout = true
+                "#
+            )
+            .unwrap(),
+            TestDirective {
+                stack_dump_on_timeout: Some(true),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn unknown_stop_signal() {
+        assert_toml_error(
+            parse_test_directive(
+                r#"
+                stop_signal = "SIGWINCH"
+                "#,
+            )
+            .unwrap_err(),
+            "unknown variant `SIGWINCH`",
+        );
+    }
+
     #[test]
     fn zero_timeout() {
         assert_eq!(
@@ -364,6 +492,258 @@ mod test {
         );
     }
 
+    #[test]
+    fn bind_devpts_and_mqueue_mounts() {
+        assert_eq!(
+            parse_test_directive(
+                r#"
+                mounts = [
+                    { fs_type = { bind = { source = "/data" } }, mount_point = "/data" },
+                    { fs_type = { bind = { source = "/ro", read_only = true } }, mount_point = "/ro" },
+                    { fs_type = "devpts", mount_point = "/dev/pts" },
+                    { fs_type = "mqueue", mount_point = "/dev/mqueue" },
+                ]
+                "#
+            )
+            .unwrap(),
+            TestDirective {
+                mounts: Some(vec![
+                    JobMount {
+                        fs_type: JobMountFsType::Bind {
+                            source: utf8_path_buf!("/data"),
+                            read_only: false,
+                        },
+                        mount_point: utf8_path_buf!("/data"),
+                    },
+                    JobMount {
+                        fs_type: JobMountFsType::Bind {
+                            source: utf8_path_buf!("/ro"),
+                            read_only: true,
+                        },
+                        mount_point: utf8_path_buf!("/ro"),
+                    },
+                    JobMount {
+                        fs_type: JobMountFsType::Devpts,
+                        mount_point: utf8_path_buf!("/dev/pts"),
+                    },
+                    JobMount {
+                        fs_type: JobMountFsType::Mqueue,
+                        mount_point: utf8_path_buf!("/dev/mqueue"),
+                    },
+                ]),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn network() {
+        assert_eq!(
+            parse_test_directive(
+                r#"
+                network = { nat = { port_forwards = [ { host_port = 8080, job_port = 80 } ], allowed_hosts = [ "10.0.0.0/8" ] } }
+                "#
+            )
+            .unwrap(),
+            TestDirective {
+                network: Some(JobNetwork::Nat(JobNatOptions {
+                    port_forwards: vec![JobPortForward {
+                        protocol: JobPortProtocol::Tcp,
+                        host_port: 8080,
+                        job_port: 80,
+                    }],
+                    allowed_hosts: vec![string!("10.0.0.0/8")],
+                })),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn network_and_enable_loopback() {
+        assert_toml_error(
+            parse_test_directive(
+                r#"
+                enable_loopback = true
+                network = "loopback"
+                "#,
+            )
+            .unwrap_err(),
+            "field `enable_loopback` cannot be set with `network` field",
+        );
+    }
+
+    #[test]
+    fn named_seccomp() {
+        assert_eq!(
+            parse_test_directive(
+                r#"
+                seccomp = "default"
+                "#
+            )
+            .unwrap(),
+            TestDirective {
+                seccomp: Some(JobSeccomp::Named(string!("default"))),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn rlimits() {
+        assert_eq!(
+            parse_test_directive(
+                r#"
+                rlimits = [
+                    { resource = "nofile", soft = 1024, hard = 4096 },
+                    { resource = "stack", soft = "unlimited", hard = "unlimited" },
+                ]
+                added_rlimits = [ { resource = "core", soft = 0, hard = 0 } ]
+                "#
+            )
+            .unwrap(),
+            TestDirective {
+                rlimits: Some(vec![
+                    JobRlimit {
+                        resource: JobRlimitResource::Nofile,
+                        soft: JobRlimitValue::Limited(1024),
+                        hard: JobRlimitValue::Limited(4096),
+                    },
+                    JobRlimit {
+                        resource: JobRlimitResource::Stack,
+                        soft: JobRlimitValue::Unlimited,
+                        hard: JobRlimitValue::Unlimited,
+                    },
+                ]),
+                added_rlimits: vec![JobRlimit {
+                    resource: JobRlimitResource::Core,
+                    soft: JobRlimitValue::Limited(0),
+                    hard: JobRlimitValue::Limited(0),
+                }],
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn rlimits_after_added_rlimits() {
+        assert_toml_error(
+            parse_test_directive(
+                r#"
+                added_rlimits = [ { resource = "core", soft = 0, hard = 0 } ]
+                rlimits = [ { resource = "nofile", soft = 1024, hard = 4096 } ]
+                "#,
+            )
+            .unwrap_err(),
+            "field `rlimits` cannot be set after `added_rlimits`",
+        );
+    }
+
+    #[test]
+    fn hostname_and_domainname() {
+        assert_eq!(
+            parse_test_directive(
+                r#"
+                hostname = "foo"
+                domainname = "example.com"
+                "#
+            )
+            .unwrap(),
+            TestDirective {
+                hostname: Some(string!("foo")),
+                domainname: Some(string!("example.com")),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn inline_seccomp() {
+        assert_eq!(
+            parse_test_directive(
+                r#"
+                [seccomp]
+                default_action = { errno = 1 }
+                rules = [
+                    { syscalls = [ "read", "write" ], action = "allow" },
+                    { syscalls = [ "ptrace" ], action = "kill" },
+                ]
+                "#
+            )
+            .unwrap(),
+            TestDirective {
+                seccomp: Some(JobSeccomp::Inline(JobSeccompPolicy {
+                    default_action: JobSeccompAction::Errno(1),
+                    rules: vec![
+                        JobSeccompRule {
+                            syscalls: vec![string!("read"), string!("write")],
+                            action: JobSeccompAction::Allow,
+                        },
+                        JobSeccompRule {
+                            syscalls: vec![string!("ptrace")],
+                            action: JobSeccompAction::Kill,
+                        },
+                    ],
+                })),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn tmpfs_mounts_with_options() {
+        assert_eq!(
+            parse_test_directive(
+                r#"
+                mounts = [
+                    { fs_type = "tmp", mount_point = "/tmp" },
+                    { fs_type = { tmp = { size = "1MiB", nr_inodes = 100, mode = "1777" } }, mount_point = "/a" },
+                    { fs_type = { tmp = { size = 4096, mode = 0o700 } }, mount_point = "/b" },
+                ]
+                "#
+            )
+            .unwrap(),
+            TestDirective {
+                mounts: Some(vec![
+                    JobMount {
+                        fs_type: JobMountFsType::Tmp(TmpfsOptions::default()),
+                        mount_point: utf8_path_buf!("/tmp"),
+                    },
+                    JobMount {
+                        fs_type: JobMountFsType::Tmp(TmpfsOptions {
+                            size: Some(1 << 20),
+                            nr_inodes: Some(100),
+                            mode: Some(0o1777),
+                        }),
+                        mount_point: utf8_path_buf!("/a"),
+                    },
+                    JobMount {
+                        fs_type: JobMountFsType::Tmp(TmpfsOptions {
+                            size: Some(4096),
+                            nr_inodes: None,
+                            mode: Some(0o700),
+                        }),
+                        mount_point: utf8_path_buf!("/b"),
+                    },
+                ]),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn tmpfs_mount_with_bad_mode() {
+        assert_toml_error(
+            parse_test_directive(
+                r#"
+                mounts = [ { fs_type = { tmp = { mode = "10777" } }, mount_point = "/tmp" } ]
+                "#,
+            )
+            .unwrap_err(),
+            "mode 10777 has bits set other than permission bits",
+        );
+    }
+
     #[test]
     fn added_mounts() {
         assert_eq!(
@@ -399,7 +779,7 @@ mod test {
                     mount_point: utf8_path_buf!("/proc"),
                 }]),
                 added_mounts: vec![JobMount {
-                    fs_type: JobMountFsType::Tmp,
+                    fs_type: JobMountFsType::Tmp(TmpfsOptions::default()),
                     mount_point: utf8_path_buf!("/tmp"),
                 }],
                 ..Default::default()
@@ -803,6 +1183,96 @@ mod test {
         );
     }
 
+    #[test]
+    fn layers_files() {
+        assert_eq!(
+            parse_test_directive(
+                r#"
+                layers = [{ files = [
+                    { path = "/etc/foo.conf", contents = "foo = 1" },
+                    { path = "/bin/run", mode = 0o755, base64_contents = "IyEvYmluL3No" },
+                ] }]
+                "#
+            )
+            .unwrap(),
+            TestDirective {
+                layers: Some(PossiblyImage::Explicit(vec![Layer::Files {
+                    files: vec![
+                        FileSpec {
+                            path: "/etc/foo.conf".into(),
+                            mode: 0o644,
+                            contents: FileContents::Text("foo = 1".into()),
+                        },
+                        FileSpec {
+                            path: "/bin/run".into(),
+                            mode: 0o755,
+                            contents: FileContents::Base64("IyEvYmluL3No".into()),
+                        },
+                    ],
+                }])),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn layers_squash() {
+        assert_eq!(
+            parse_test_directive(
+                r#"
+                layers = [{ squash = [{ tar = "foo.tar" }, { stubs = ["/tmp/"] }] }]
+                "#
+            )
+            .unwrap(),
+            TestDirective {
+                layers: Some(PossiblyImage::Explicit(vec![Layer::Squash {
+                    layers: vec![
+                        tar_layer!("foo.tar"),
+                        Layer::Stubs {
+                            stubs: vec!["/tmp/".into()]
+                        },
+                    ],
+                }])),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn layers_git() {
+        assert_eq!(
+            parse_test_directive(
+                r#"
+                layers = [
+                    { git = "." },
+                    { git = "vendor/fixtures", rev = "v1.0", paths = ["data"], strip_prefix = "data" },
+                ]
+                "#
+            )
+            .unwrap(),
+            TestDirective {
+                layers: Some(PossiblyImage::Explicit(vec![
+                    Layer::Git {
+                        repo: ".".into(),
+                        rev: "HEAD".into(),
+                        paths: vec![],
+                        prefix_options: Default::default(),
+                    },
+                    Layer::Git {
+                        repo: "vendor/fixtures".into(),
+                        rev: "v1.0".into(),
+                        paths: vec!["data".into()],
+                        prefix_options: PrefixOptions {
+                            strip_prefix: Some("data".into()),
+                            ..Default::default()
+                        },
+                    },
+                ])),
+                ..Default::default()
+            }
+        );
+    }
+
     #[test]
     fn image_with_layers() {
         assert_eq!(
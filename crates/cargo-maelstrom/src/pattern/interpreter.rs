@@ -1,7 +1,7 @@
 use crate::pattern::parser::*;
 use cargo_metadata::Target as CargoTarget;
 use serde::{Deserialize, Serialize};
-use std::fmt;
+use std::{fmt, time::Duration};
 
 #[cfg(test)]
 use crate::parse_str;
@@ -52,9 +52,25 @@ impl Artifact {
     }
 }
 
-#[derive(Clone, PartialEq, Eq, Debug)]
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
 pub struct Case {
     pub name: String,
+    pub ignored: bool,
+    /// Whether the test failed the last time it was run. This is false if it has never been run.
+    pub failed_last_run: bool,
+    /// How long the test ran for the last time it was run, if it has been run.
+    pub duration: Option<Duration>,
+}
+
+impl Case {
+    /// The path of the module containing the test. This is the test's name without its last
+    /// component.
+    fn path(&self) -> &str {
+        self.name
+            .rsplit_once("::")
+            .map(|(path, _)| path)
+            .unwrap_or("")
+    }
 }
 
 #[derive(Clone, PartialEq, Eq, Debug)]
@@ -107,11 +123,14 @@ pub fn interpret_simple_selector(s: &SimpleSelector, c: &Context) -> Option<bool
         All | Any | True => true,
         None | False => false,
         Library => matches!(c.artifact()?.kind, ArtifactKind::Library),
+        Ignored => c.case()?.ignored,
+        FailedLastRun => c.case()?.failed_last_run,
         Compound(Binary) => matches!(c.artifact()?.kind, ArtifactKind::Binary),
         Compound(Benchmark) => matches!(c.artifact()?.kind, ArtifactKind::Benchmark),
         Compound(Test) => matches!(c.artifact()?.kind, ArtifactKind::Test),
         Compound(Example) => matches!(c.artifact()?.kind, ArtifactKind::Example),
         Compound(Name) => unreachable!("should be parser error"),
+        Compound(Path) => unreachable!("should be parser error"),
         Compound(Package) => unreachable!("should be parser error"),
     })
 }
@@ -132,6 +151,7 @@ pub fn interpret_compound_selector(s: &CompoundSelector, c: &Context) -> Option<
     use CompoundSelectorName::*;
     Some(match s.name {
         Name => interpret_matcher(&c.case()?.name, &s.matcher),
+        Path => interpret_matcher(c.case()?.path(), &s.matcher),
         Package => interpret_matcher(&c.package, &s.matcher),
         Binary => {
             matches!(&c.artifact()?.kind, ArtifactKind::Binary)
@@ -152,6 +172,15 @@ pub fn interpret_compound_selector(s: &CompoundSelector, c: &Context) -> Option<
     })
 }
 
+/// A test that has never been run has no duration, so it doesn't match any duration comparison.
+pub fn interpret_duration_selector(s: &DurationSelector, c: &Context) -> Option<bool> {
+    Some(
+        c.case()?
+            .duration
+            .is_some_and(|duration| s.operator.compare(duration, s.value)),
+    )
+}
+
 fn interpret_not_expression(n: &NotExpression, c: &Context) -> Option<bool> {
     use NotExpression::*;
     match n {
@@ -192,6 +221,7 @@ pub fn interpret_simple_expression(s: &SimpleExpression, c: &Context) -> Option<
         Or(o) => interpret_or_expression(o, c),
         SimpleSelector(s) => interpret_simple_selector(s, c),
         CompoundSelector(s) => interpret_compound_selector(s, c),
+        DurationSelector(s) => interpret_duration_selector(s, c),
     }
 }
 
@@ -349,6 +379,7 @@ fn test_compound_sel_case(
         }),
         case: Some(Case {
             name: case_name.into(),
+            ..Default::default()
         }),
     };
     let actual = interpret_simple_expression(&parse_str!(SimpleExpression, s).unwrap(), &c);
@@ -429,6 +460,7 @@ fn and_or_not_diff_expressions() {
             }),
             case: Some(Case {
                 name: "foo_test".into(),
+                ..Default::default()
             }),
         };
         let actual = interpret_pattern(&parse_str!(Pattern, s).unwrap(), &c);
@@ -491,3 +523,79 @@ fn and_or_not_diff_maybe_expressions() {
     test_it("package.equals(foo) - name.equals(bar_test)", None);
     test_it("package.equals(foo) - name.equals(foo_test)", None);
 }
+
+#[cfg(test)]
+fn test_case_sel(s: &str, case: Option<Case>, expected: Option<bool>) {
+    let c = Context {
+        package: "foo".into(),
+        artifact: None,
+        case,
+    };
+    let actual = interpret_pattern(&parse_str!(Pattern, s).unwrap(), &c);
+    assert_eq!(actual, expected);
+}
+
+#[test]
+fn simple_expression_ignored() {
+    let case = |ignored| Case {
+        name: "test".into(),
+        ignored,
+        ..Default::default()
+    };
+    test_case_sel("ignored", Some(case(true)), Some(true));
+    test_case_sel("ignored", Some(case(false)), Some(false));
+    test_case_sel("ignored", None, None);
+    test_case_sel("!ignored", Some(case(true)), Some(false));
+}
+
+#[test]
+fn simple_expression_failed_last_run() {
+    let case = |failed_last_run| Case {
+        name: "test".into(),
+        failed_last_run,
+        ..Default::default()
+    };
+    test_case_sel("failed_last_run", Some(case(true)), Some(true));
+    test_case_sel("failed_last_run", Some(case(false)), Some(false));
+    test_case_sel("failed_last_run", None, None);
+    test_case_sel("failed_last_run || all", None, Some(true));
+}
+
+#[test]
+fn simple_expression_duration() {
+    let case = |secs| Case {
+        name: "test".into(),
+        duration: Some(Duration::from_secs(secs)),
+        ..Default::default()
+    };
+    test_case_sel("duration > 10s", Some(case(11)), Some(true));
+    test_case_sel("duration > 10s", Some(case(10)), Some(false));
+    test_case_sel("duration >= 10s", Some(case(10)), Some(true));
+    test_case_sel("duration < 1m", Some(case(59)), Some(true));
+    test_case_sel("duration <= 1m", Some(case(61)), Some(false));
+    test_case_sel("duration == 10s", Some(case(10)), Some(true));
+    test_case_sel("duration != 10s", Some(case(10)), Some(false));
+    test_case_sel("duration > 10s", None, None);
+
+    let never_run = Case {
+        name: "test".into(),
+        ..Default::default()
+    };
+    test_case_sel("duration > 10s", Some(never_run.clone()), Some(false));
+    test_case_sel("duration <= 10s", Some(never_run), Some(false));
+}
+
+#[test]
+fn simple_expression_compound_selector_path() {
+    let case = |name: &str| {
+        Some(Case {
+            name: name.into(),
+            ..Default::default()
+        })
+    };
+    test_case_sel("path.equals(foo::bar)", case("foo::bar::test"), Some(true));
+    test_case_sel("path.equals(foo)", case("foo::bar::test"), Some(false));
+    test_case_sel("path.starts_with(foo)", case("foo::bar::test"), Some(true));
+    test_case_sel("path.equals()", case("test"), Some(true));
+    test_case_sel("path.equals(foo)", None, None);
+}
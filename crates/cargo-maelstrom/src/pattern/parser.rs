@@ -2,6 +2,7 @@ use crate::parse_str;
 use anyhow::{anyhow, Error, Result};
 use combine::{
     attempt, between, choice, many, many1, optional, parser,
+    parser::char::digit,
     parser::{
         char::{space, spaces, string},
         combinator::{lazy, no_partial},
@@ -11,7 +12,7 @@ use combine::{
 use derive_more::From;
 use globset::{Glob, GlobMatcher};
 use regex::Regex;
-use std::str::FromStr;
+use std::{str::FromStr, time::Duration};
 
 #[cfg(test)]
 use regex_macro::regex;
@@ -204,6 +205,7 @@ impl Matcher {
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum CompoundSelectorName {
     Name,
+    Path,
     Binary,
     Benchmark,
     Example,
@@ -215,6 +217,7 @@ impl CompoundSelectorName {
     pub fn parser<InputT: Stream<Token = char>>() -> impl Parser<InputT, Output = Self> {
         choice((
             attempt(prefix("name", 1)).map(|_| Self::Name),
+            attempt(prefix("path", 3)).map(|_| Self::Path),
             attempt(prefix("package", 1)).map(|_| Self::Package),
             Self::parser_for_simple_selector(),
         ))
@@ -255,6 +258,8 @@ pub enum SimpleSelectorName {
     None,
     False,
     Library,
+    Ignored,
+    FailedLastRun,
     #[from]
     Compound(CompoundSelectorName),
 }
@@ -266,8 +271,10 @@ impl SimpleSelectorName {
             attempt(prefix("any", 2)).map(|_| Self::Any),
             attempt(prefix("true", 2)).map(|_| Self::True),
             attempt(prefix("none", 1)).map(|_| Self::None),
+            attempt(prefix("failed_last_run", 6)).map(|_| Self::FailedLastRun),
             attempt(prefix("false", 1)).map(|_| Self::False),
             attempt(prefix("library", 1)).map(|_| Self::Library),
+            attempt(prefix("ignored", 1)).map(|_| Self::Ignored),
             CompoundSelectorName::parser_for_simple_selector().map(Self::Compound),
         ))
     }
@@ -287,6 +294,85 @@ impl SimpleSelector {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ComparisonOperator {
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Equal,
+    NotEqual,
+}
+
+impl ComparisonOperator {
+    pub fn parser<InputT: Stream<Token = char>>() -> impl Parser<InputT, Output = Self> {
+        choice((
+            attempt(string("<=")).map(|_| Self::LessOrEqual),
+            attempt(string(">=")).map(|_| Self::GreaterOrEqual),
+            attempt(string("==")).map(|_| Self::Equal),
+            attempt(string("!=")).map(|_| Self::NotEqual),
+            string("<").map(|_| Self::Less),
+            string(">").map(|_| Self::Greater),
+        ))
+    }
+
+    pub fn compare<T: Ord>(&self, a: T, b: T) -> bool {
+        match self {
+            Self::Less => a < b,
+            Self::LessOrEqual => a <= b,
+            Self::Greater => a > b,
+            Self::GreaterOrEqual => a >= b,
+            Self::Equal => a == b,
+            Self::NotEqual => a != b,
+        }
+    }
+}
+
+/// Parse a duration like `10s`, `1.5m`, or `250ms`. The units are `ms`, `s`, `m`, and `h`.
+fn parse_duration(s: &str) -> std::result::Result<Duration, std::time::TryFromFloatSecsError> {
+    let unit_start = s.find(|c: char| c.is_ascii_alphabetic()).unwrap();
+    let (number, unit) = s.split_at(unit_start);
+    let scale = match unit {
+        "ms" => 0.001,
+        "s" => 1.,
+        "m" => 60.,
+        "h" => 3600.,
+        _ => unreachable!(),
+    };
+    Duration::try_from_secs_f64(number.parse::<f64>().unwrap() * scale)
+}
+
+fn duration<InputT: Stream<Token = char>>() -> impl Parser<InputT, Output = Duration> {
+    let number = (many1(digit()), optional(token('.').with(many1(digit())))).map(
+        |(whole, fraction): (String, Option<String>)| match fraction {
+            Some(fraction) => format!("{whole}.{fraction}"),
+            None => whole,
+        },
+    );
+    let unit = choice((attempt(string("ms")), string("s"), string("m"), string("h")));
+    err_construct(
+        (number, unit).map(|(number, unit)| number + unit),
+        parse_duration,
+    )
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DurationSelector {
+    pub operator: ComparisonOperator,
+    pub value: Duration,
+}
+
+impl DurationSelector {
+    pub fn parser<InputT: Stream<Token = char>>() -> impl Parser<InputT, Output = Self> {
+        (
+            prefix("duration", 1).skip(spaces()),
+            ComparisonOperator::parser().skip(spaces()),
+            duration(),
+        )
+            .map(|(_, operator, value)| Self { operator, value })
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, From)]
 pub enum SimpleExpression {
     #[from(types(OrExpression))]
@@ -295,6 +381,8 @@ pub enum SimpleExpression {
     SimpleSelector(SimpleSelector),
     #[from]
     CompoundSelector(CompoundSelector),
+    #[from]
+    DurationSelector(DurationSelector),
 }
 
 impl From<AndExpression> for SimpleExpression {
@@ -314,6 +402,7 @@ impl SimpleExpression {
             ))
             .map(|o| Self::Or(Box::new(o))),
             attempt(CompoundSelector::parser().map(Self::CompoundSelector)),
+            attempt(DurationSelector::parser().map(Self::DurationSelector)),
             attempt(SimpleSelector::parser().map(Self::SimpleSelector)),
         ))
     }
@@ -326,7 +415,13 @@ fn not_operator<InputT: Stream<Token = char>>() -> impl Parser<InputT, Output =
 #[derive(Clone, Debug, PartialEq, Eq, From)]
 pub enum NotExpression {
     Not(Box<NotExpression>),
-    #[from(types(SimpleSelector, SimpleSelectorName, CompoundSelector, OrExpression))]
+    #[from(types(
+        SimpleSelector,
+        SimpleSelectorName,
+        CompoundSelector,
+        DurationSelector,
+        OrExpression
+    ))]
     Simple(SimpleExpression),
 }
 
@@ -371,7 +466,8 @@ pub enum AndExpression {
         SimpleExpression,
         SimpleSelector,
         SimpleSelectorName,
-        CompoundSelector
+        CompoundSelector,
+        DurationSelector
     ))]
     Not(NotExpression),
 }
@@ -406,7 +502,8 @@ pub enum OrExpression {
         SimpleExpression,
         SimpleSelector,
         SimpleSelectorName,
-        CompoundSelector
+        CompoundSelector,
+        DurationSelector
     ))]
     And(AndExpression),
 }
@@ -487,6 +584,10 @@ fn simple_expr() {
     test_it("false()", False);
     test_it("library", Library);
     test_it("library()", Library);
+    test_it("ignored", Ignored);
+    test_it("ignored()", Ignored);
+    test_it("failed_last_run", FailedLastRun);
+    test_it("failed_last_run()", FailedLastRun);
 
     test_it("binary", Binary);
     test_it("binary()", Binary);
@@ -504,6 +605,9 @@ fn simple_expr() {
     test_it_err("name()");
     test_it_err("package");
     test_it_err("package()");
+    test_it_err("path");
+    test_it_err("path()");
+    test_it_err("duration");
 }
 
 #[test]
@@ -524,6 +628,8 @@ fn simple_expr_prefix() {
     test_it("none", 1, None);
     test_it("false", 1, False);
     test_it("library", 1, Library);
+    test_it("ignored", 1, Ignored);
+    test_it("failed_last_run", 6, FailedLastRun);
 
     test_it("binary", 2, Binary);
     test_it("benchmark", 2, Benchmark);
@@ -543,6 +649,9 @@ fn simple_expr_compound() {
         );
     }
     test_it("name.matches<foo>", Name, Matches(regex!("foo").into()));
+    test_it("path.equals(foo::bar)", Path, Equals("foo::bar".into()));
+    test_it("pat.equals(foo::bar)", Path, Equals("foo::bar".into()));
+    test_it("pa.equals(foo)", Package, Equals("foo".into()));
     test_it("test.equals([a-z].*)", Test, Equals("[a-z].*".into()));
     test_it(
         "binary.starts_with<(hi)>",
@@ -561,6 +670,34 @@ fn simple_expr_compound() {
     );
 }
 
+#[test]
+fn simple_expr_duration() {
+    use ComparisonOperator::*;
+
+    fn test_it(a: &str, operator: ComparisonOperator, value: Duration) {
+        assert_eq!(
+            parse_str!(SimpleExpression, a),
+            Ok(DurationSelector { operator, value }.into())
+        );
+    }
+    test_it("duration > 10s", Greater, Duration::from_secs(10));
+    test_it("duration>=10s", GreaterOrEqual, Duration::from_secs(10));
+    test_it("duration < 250ms", Less, Duration::from_millis(250));
+    test_it("duration <= 1.5m", LessOrEqual, Duration::from_secs(90));
+    test_it("duration == 2h", Equal, Duration::from_secs(7200));
+    test_it("duration != 0s", NotEqual, Duration::ZERO);
+    test_it("d > 1s", Greater, Duration::from_secs(1));
+
+    fn test_it_err(a: &str) {
+        assert!(parse_str!(SimpleExpression, a).is_err());
+    }
+    test_it_err("duration > 10");
+    test_it_err("duration > s");
+    test_it_err("duration > 10d");
+    test_it_err("duration = 10s");
+    test_it_err("duration > 1e300h");
+}
+
 #[test]
 fn matcher_prefixes() {
     use CompoundSelectorName::*;
@@ -758,3 +895,30 @@ fn pattern_complicated_boolean_expr_compound() {
         ),
     );
 }
+
+#[test]
+fn pattern_boolean_expr_history() {
+    fn test_it(a: &str, pattern: impl Into<Pattern>) {
+        assert_eq!(parse_str!(Pattern, a), Ok(pattern.into()));
+    }
+
+    test_it(
+        "failed_last_run || duration > 10s && !ignored",
+        OrExpression::Or(
+            SimpleSelectorName::FailedLastRun.into(),
+            Box::new(
+                AndExpression::And(
+                    DurationSelector {
+                        operator: ComparisonOperator::Greater,
+                        value: Duration::from_secs(10),
+                    }
+                    .into(),
+                    Box::new(
+                        NotExpression::Not(Box::new(SimpleSelectorName::Ignored.into())).into(),
+                    ),
+                )
+                .into(),
+            ),
+        ),
+    );
+}
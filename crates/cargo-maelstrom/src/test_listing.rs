@@ -1,12 +1,14 @@
 use crate::pattern;
 use anyhow::{anyhow, Result};
 use cargo_metadata::{Artifact as CargoArtifact, Package as CargoPackage, Target as CargoTarget};
+use maelstrom_base::{JobOutcome, JobOutcomeResult, JobStatus};
 use maelstrom_util::fs::Fs;
 use serde::{Deserialize, Serialize};
 use serde_repr::{Deserialize_repr, Serialize_repr};
-use serde_with::{serde_as, FromInto};
-use std::collections::{BTreeMap, HashMap, HashSet};
+use serde_with::{serde_as, DurationSecondsWithFrac, FromInto};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::path::Path;
+use std::time::Duration;
 
 pub use crate::pattern::ArtifactKind;
 
@@ -25,7 +27,7 @@ pub struct ArtifactKey {
 }
 
 impl ArtifactKey {
-    fn from_target(target: &CargoTarget) -> Self {
+    pub fn from_target(target: &CargoTarget) -> Self {
         Self {
             name: target.name.clone(),
             kind: ArtifactKind::from_target(target),
@@ -33,9 +35,56 @@ impl ArtifactKey {
     }
 }
 
+/// What happened the last time a test was run.
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CaseHistory {
+    pub failed: bool,
+    /// How long the test ran. This is missing if it couldn't be run.
+    #[serde_as(as = "Option<DurationSecondsWithFrac<f64>>")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration: Option<Duration>,
+}
+
+impl CaseHistory {
+    pub fn from_result(result: &JobOutcomeResult) -> Self {
+        match result {
+            Ok(JobOutcome::Completed { status, effects }) => Self {
+                failed: *status != JobStatus::Exited(0),
+                duration: Some(effects.duration),
+            },
+            Ok(JobOutcome::TimedOut(effects))
+            | Ok(JobOutcome::TimedOutGracefully { effects, .. }) => Self {
+                failed: true,
+                duration: Some(effects.duration),
+            },
+            Err(_) => Self {
+                failed: true,
+                duration: None,
+            },
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
 pub struct ArtifactCases {
     pub cases: Vec<String>,
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub ignored: BTreeSet<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub history: BTreeMap<String, CaseHistory>,
+}
+
+impl ArtifactCases {
+    fn case(&self, name: &str) -> pattern::Case {
+        let history = self.history.get(name);
+        pattern::Case {
+            name: name.into(),
+            ignored: self.ignored.contains(name),
+            failed_last_run: history.is_some_and(|h| h.failed),
+            duration: history.and_then(|h| h.duration),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -83,6 +132,7 @@ pub struct TestListing {
 fn filter_case(
     package: &str,
     artifact: &ArtifactKey,
+    cases: &ArtifactCases,
     case: &str,
     filter: &pattern::Pattern,
 ) -> bool {
@@ -92,23 +142,76 @@ fn filter_case(
             name: artifact.name.clone(),
             kind: artifact.kind,
         }),
-        case: Some(pattern::Case { name: case.into() }),
+        case: Some(cases.case(case)),
     };
     pattern::interpret_pattern(filter, &c).expect("case is provided")
 }
 
 impl TestListing {
-    pub fn add_cases(&mut self, package_name: &str, artifact: &CargoArtifact, cases: &[String]) {
+    /// Replace the cases for the given artifact. The history of cases that still exist is kept.
+    pub fn add_cases(
+        &mut self,
+        package_name: &str,
+        artifact: &CargoArtifact,
+        cases: &[String],
+        ignored: &HashSet<String>,
+    ) {
         let artifact_key = ArtifactKey::from_target(&artifact.target);
         let package = self.packages.entry(package_name.into()).or_default();
+        let mut history = package
+            .artifacts
+            .remove(&artifact_key)
+            .map(|old| old.history)
+            .unwrap_or_default();
+        history.retain(|case, _| cases.contains(case));
         package.artifacts.insert(
             artifact_key,
             ArtifactCases {
                 cases: cases.to_vec(),
+                ignored: cases
+                    .iter()
+                    .filter(|case| ignored.contains(*case))
+                    .cloned()
+                    .collect(),
+                history,
             },
         );
     }
 
+    /// Return what's known about the given case, for evaluating filters.
+    pub fn case(&self, package_name: &str, artifact: &ArtifactKey, case: &str) -> pattern::Case {
+        match self
+            .packages
+            .get(package_name)
+            .and_then(|package| package.artifacts.get(artifact))
+        {
+            Some(cases) => cases.case(case),
+            None => pattern::Case {
+                name: case.into(),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Remember the result of running the given case, so it can be selected by later runs.
+    pub fn record_result(
+        &mut self,
+        package_name: &str,
+        artifact: &ArtifactKey,
+        case: &str,
+        result: &JobOutcomeResult,
+    ) {
+        if let Some(cases) = self
+            .packages
+            .get_mut(package_name)
+            .and_then(|package| package.artifacts.get_mut(artifact))
+        {
+            cases
+                .history
+                .insert(case.into(), CaseHistory::from_result(result));
+        }
+    }
+
     pub fn remove_package(&mut self, package: &str) {
         self.packages.remove(package);
     }
@@ -119,9 +222,9 @@ impl TestListing {
             .flat_map(|(p, a)| {
                 a.artifacts
                     .iter()
-                    .flat_map(move |(a, c)| c.cases.iter().map(move |c| (p, a, c)))
+                    .flat_map(move |(a, cases)| cases.cases.iter().map(move |c| (p, a, cases, c)))
             })
-            .filter(|(p, a, c)| filter_case(p, a, c, filter))
+            .filter(|(p, a, cases, c)| filter_case(p, a, cases, c, filter))
             .count() as u64
     }
 
@@ -176,3 +279,155 @@ pub fn write_test_listing(path: &Path, job_listing: &TestListing) -> Result<()>
     fs.write(path, toml::to_string_pretty(job_listing)?)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use maelstrom_base::{JobEffects, JobError, JobOutputResult};
+
+    fn effects(secs: u64) -> JobEffects {
+        JobEffects {
+            stdout: JobOutputResult::None,
+            stderr: JobOutputResult::None,
+            diagnostics: JobOutputResult::None,
+            duration: Duration::from_secs(secs),
+        }
+    }
+
+    #[test]
+    fn case_history_from_result() {
+        assert_eq!(
+            CaseHistory::from_result(&Ok(JobOutcome::Completed {
+                status: JobStatus::Exited(0),
+                effects: effects(1),
+            })),
+            CaseHistory {
+                failed: false,
+                duration: Some(Duration::from_secs(1)),
+            }
+        );
+        assert_eq!(
+            CaseHistory::from_result(&Ok(JobOutcome::Completed {
+                status: JobStatus::Signaled(9),
+                effects: effects(2),
+            })),
+            CaseHistory {
+                failed: true,
+                duration: Some(Duration::from_secs(2)),
+            }
+        );
+        assert_eq!(
+            CaseHistory::from_result(&Ok(JobOutcome::TimedOut(effects(3)))),
+            CaseHistory {
+                failed: true,
+                duration: Some(Duration::from_secs(3)),
+            }
+        );
+        assert_eq!(
+            CaseHistory::from_result(&Err(JobError::Execution("oops".into()))),
+            CaseHistory {
+                failed: true,
+                duration: None,
+            }
+        );
+    }
+
+    #[test]
+    fn history_round_trips() {
+        let key = ArtifactKey {
+            name: "foo".into(),
+            kind: ArtifactKind::Library,
+        };
+        let listing = TestListing {
+            version: Default::default(),
+            packages: [(
+                "foo".into(),
+                Package {
+                    artifacts: [(
+                        key.clone(),
+                        ArtifactCases {
+                            cases: vec!["a".into(), "b".into(), "c".into()],
+                            ignored: ["c".into()].into_iter().collect(),
+                            history: [
+                                (
+                                    "a".into(),
+                                    CaseHistory {
+                                        failed: true,
+                                        duration: Some(Duration::from_millis(1500)),
+                                    },
+                                ),
+                                (
+                                    "b".into(),
+                                    CaseHistory {
+                                        failed: false,
+                                        duration: None,
+                                    },
+                                ),
+                            ]
+                            .into_iter()
+                            .collect(),
+                        },
+                    )]
+                    .into_iter()
+                    .collect(),
+                },
+            )]
+            .into_iter()
+            .collect(),
+        };
+        let contents = toml::to_string_pretty(&listing).unwrap();
+        assert_eq!(toml::from_str::<TestListing>(&contents).unwrap(), listing);
+
+        assert_eq!(
+            listing.case("foo", &key, "a"),
+            pattern::Case {
+                name: "a".into(),
+                ignored: false,
+                failed_last_run: true,
+                duration: Some(Duration::from_millis(1500)),
+            }
+        );
+        assert_eq!(
+            listing.case("foo", &key, "c"),
+            pattern::Case {
+                name: "c".into(),
+                ignored: true,
+                failed_last_run: false,
+                duration: None,
+            }
+        );
+        assert_eq!(
+            listing.case("bar", &key, "a"),
+            pattern::Case {
+                name: "a".into(),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn listing_without_history_still_loads() {
+        let listing: TestListing = toml::from_str(
+            r#"
+            version = 1
+
+            [[foo.artifacts]]
+            name = "foo"
+            kind = "Library"
+            cases = ["a"]
+            "#,
+        )
+        .unwrap();
+        let key = ArtifactKey {
+            name: "foo".into(),
+            kind: ArtifactKind::Library,
+        };
+        assert_eq!(
+            listing.packages["foo"].artifacts[&key],
+            ArtifactCases {
+                cases: vec!["a".into()],
+                ..Default::default()
+            }
+        );
+    }
+}
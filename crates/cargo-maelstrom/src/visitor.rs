@@ -99,6 +99,38 @@ impl JobStatusTracker {
     }
 }
 
+fn push_stderr_lines(cjid: ClientJobId, stderr: JobOutputResult, lines: &mut Vec<String>) {
+    match stderr {
+        JobOutputResult::None => {}
+        JobOutputResult::Inline(bytes) => {
+            lines.push(String::from_utf8_lossy(&bytes).into());
+        }
+        JobOutputResult::Truncated { first, truncated } => {
+            lines.push(String::from_utf8_lossy(&first).into());
+            lines.push(format!(
+                "job {cjid}: stderr truncated, {truncated} bytes lost"
+            ));
+        }
+    }
+}
+
+fn push_stack_dump_lines(cjid: ClientJobId, diagnostics: JobOutputResult, lines: &mut Vec<String>) {
+    match diagnostics {
+        JobOutputResult::None => {}
+        JobOutputResult::Inline(bytes) => {
+            lines.push(format!("job {cjid}: stack dump:"));
+            lines.push(String::from_utf8_lossy(&bytes).into());
+        }
+        JobOutputResult::Truncated { first, truncated } => {
+            lines.push(format!("job {cjid}: stack dump:"));
+            lines.push(String::from_utf8_lossy(&first).into());
+            lines.push(format!(
+                "job {cjid}: stack dump truncated, {truncated} bytes lost"
+            ));
+        }
+    }
+}
+
 pub struct JobStatusVisitor<ProgressIndicatorT> {
     tracker: Arc<JobStatusTracker>,
     case: String,
@@ -180,25 +212,38 @@ impl<ProgressIndicatorT: ProgressIndicator> JobStatusVisitor<ProgressIndicatorT>
                     }
                 };
                 if job_failed {
-                    match stderr {
-                        JobOutputResult::None => {}
-                        JobOutputResult::Inline(bytes) => {
-                            test_output_lines.push(String::from_utf8_lossy(&bytes).into());
-                        }
-                        JobOutputResult::Truncated { first, truncated } => {
-                            test_output_lines.push(String::from_utf8_lossy(&first).into());
-                            test_output_lines.push(format!(
-                                "job {cjid}: stderr truncated, {truncated} bytes lost"
-                            ));
-                        }
-                    }
+                    push_stderr_lines(cjid, stderr, &mut test_output_lines);
                 }
             }
-            Ok(JobOutcome::TimedOut(_)) => {
+            Ok(JobOutcome::TimedOut(JobEffects { diagnostics, .. })) => {
                 result_str = "TIMEOUT".red();
                 result_details = Some("timed out".into());
                 self.tracker
                     .job_exited(self.case.clone(), ExitCode::FAILURE);
+                push_stack_dump_lines(cjid, diagnostics, &mut test_output_lines);
+            }
+            Ok(JobOutcome::TimedOutGracefully {
+                status,
+                effects:
+                    JobEffects {
+                        stderr,
+                        diagnostics,
+                        ..
+                    },
+            }) => {
+                result_str = "TIMEOUT".red();
+                result_details = Some(match status {
+                    JobStatus::Exited(code) => {
+                        format!("timed out, then exited with code {code} after its stop signal")
+                    }
+                    JobStatus::Signaled(signo) => {
+                        format!("timed out, then was killed by signal {signo}")
+                    }
+                });
+                self.tracker
+                    .job_exited(self.case.clone(), ExitCode::FAILURE);
+                push_stderr_lines(cjid, stderr, &mut test_output_lines);
+                push_stack_dump_lines(cjid, diagnostics, &mut test_output_lines);
             }
             Err(JobError::Execution(err)) => {
                 result_str = "ERR".yellow();
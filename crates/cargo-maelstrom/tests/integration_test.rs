@@ -5,13 +5,14 @@ use cargo_maelstrom::{
     main_app_new,
     progress::{ProgressDriver, ProgressIndicator},
     test_listing::{
-        load_test_listing, ArtifactCases, ArtifactKey, ArtifactKind, Package, TestListing,
-        LAST_TEST_LISTING_NAME,
+        load_test_listing, ArtifactCases, ArtifactKey, ArtifactKind, CaseHistory, Package,
+        TestListing, LAST_TEST_LISTING_NAME,
     },
     EnqueueResult, ListAction, MainAppDeps,
 };
 use indicatif::InMemoryTerm;
 use maelstrom_base::{
+    proto::ArtifactCompression,
     stats::{JobState, JobStateCounts},
     JobEffects, JobOutcome, JobOutputResult, JobStatus,
 };
@@ -19,10 +20,13 @@ use maelstrom_client::{
     test::fake_broker::{FakeBroker, FakeBrokerJobAction, FakeBrokerState, JobSpecMatcher},
     Client, ClientBgProcess, ClientDriverMode,
 };
-use maelstrom_util::fs::Fs;
+use maelstrom_util::{
+    config::{LogFormat, LogLevel},
+    fs::Fs,
+};
 use std::{
     cell::RefCell, io::Write as _, os::unix::fs::PermissionsExt as _, path::Path, rc::Rc,
-    sync::Mutex,
+    sync::Mutex, time::Duration,
 };
 use tempfile::{tempdir, TempDir};
 
@@ -161,6 +165,26 @@ impl FakeTests {
                                 },
                                 ArtifactCases {
                                     cases: b.tests.iter().map(|t| t.name.clone()).collect(),
+                                    ignored: b
+                                        .tests
+                                        .iter()
+                                        .filter(|t| t.ignored)
+                                        .map(|t| t.name.clone())
+                                        .collect(),
+                                    history: b
+                                        .tests
+                                        .iter()
+                                        .filter(|t| !t.ignored)
+                                        .map(|t| {
+                                            (
+                                                t.name.clone(),
+                                                CaseHistory {
+                                                    failed: false,
+                                                    duration: Some(Duration::ZERO),
+                                                },
+                                            )
+                                        })
+                                        .collect(),
                                 },
                             )]
                             .into_iter()
@@ -261,6 +285,9 @@ fn run_app(
         &workspace_root,
         &cargo_metadata.workspace_packages(),
         b.address().clone(),
+        ArtifactCompression::Zstd,
+        LogLevel::Warning,
+        LogFormat::Text,
         ClientDriverMode::SingleThreaded,
         FeatureSelectionOptions::default(),
         CompilationOptions::default(),
@@ -336,6 +363,8 @@ fn run_or_list_all_tests_sync(
                 effects: JobEffects {
                     stdout: JobOutputResult::None,
                     stderr: JobOutputResult::Inline(Box::new(*b"this output should be ignored")),
+                    diagnostics: JobOutputResult::None,
+                    duration: Duration::ZERO,
                 },
             })),
         );
@@ -875,6 +904,8 @@ fn run_failed_tests(fake_tests: FakeTests) -> String {
                 effects: JobEffects {
                     stdout: JobOutputResult::None,
                     stderr: JobOutputResult::Inline(Box::new(*b"error output")),
+                    diagnostics: JobOutputResult::None,
+                    duration: Duration::ZERO,
                 },
             })),
         );
@@ -950,6 +981,8 @@ fn run_in_progress_test(fake_tests: FakeTests, quiet: Quiet, expected_output: &s
                     effects: JobEffects {
                         stdout: JobOutputResult::None,
                         stderr: JobOutputResult::None,
+                        diagnostics: JobOutputResult::None,
+                        duration: Duration::ZERO,
                     },
                 })),
             );
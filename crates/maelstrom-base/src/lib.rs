@@ -12,11 +12,12 @@ use enumset::EnumSetType;
 pub use enumset::{enum_set, EnumSet};
 use hex::{self, FromHexError};
 pub use nonempty::{nonempty, NonEmpty};
-use serde::{Deserialize, Serialize};
+use serde::{de, Deserialize, Deserializer, Serialize};
 use std::{
     error::Error,
     fmt::{self, Debug, Formatter},
     hash::Hash,
+    marker::PhantomData,
     num::NonZeroU32,
     result::Result,
     str::{self, FromStr},
@@ -50,6 +51,13 @@ pub struct JobId {
     pub cjid: ClientJobId,
 }
 
+/// Job IDs are displayed as `<cid>.<cjid>`. This is how they appear in logs and traces.
+impl fmt::Display for JobId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.cid, self.cjid)
+    }
+}
+
 #[derive(Debug, Deserialize, EnumSetType, Serialize)]
 #[enumset(serialize_deny_unknown)]
 pub enum JobDevice {
@@ -86,33 +94,467 @@ impl From<JobDeviceListDeserialize> for JobDevice {
     }
 }
 
+/// Options for a tmpfs mount. Options that aren't given get the kernel's defaults, which means a
+/// tmpfs can grow to half of the worker's RAM.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct TmpfsOptions {
+    /// Maximum size of the file system in bytes. Writes past this fail with `ENOSPC`. In TOML
+    /// and JSON, this can also be given as a string like `"64MiB"`.
+    #[serde(default, deserialize_with = "deserialize_tmpfs_size")]
+    pub size: Option<u64>,
+
+    /// Maximum number of inodes in the file system.
+    #[serde(default)]
+    pub nr_inodes: Option<u64>,
+
+    /// Permissions of the root directory of the file system. In TOML and JSON, this can also be
+    /// given as an octal string like `"1777"`.
+    #[serde(default, deserialize_with = "deserialize_tmpfs_mode")]
+    pub mode: Option<u32>,
+}
+
+fn deserialize_tmpfs_size<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    if !deserializer.is_human_readable() {
+        return Option::<u64>::deserialize(deserializer);
+    }
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Size {
+        Bytes(u64),
+        String(String),
+    }
+
+    match Option::<Size>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(Size::Bytes(bytes)) => Ok(Some(bytes)),
+        Some(Size::String(size)) => size
+            .parse::<bytesize::ByteSize>()
+            .map(|size| Some(size.as_u64()))
+            .map_err(|err| de::Error::custom(format_args!("invalid size {size:?}: {err}"))),
+    }
+}
+
+fn deserialize_tmpfs_mode<'de, D>(deserializer: D) -> Result<Option<u32>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    if !deserializer.is_human_readable() {
+        return Option::<u32>::deserialize(deserializer);
+    }
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Mode {
+        Number(u32),
+        Octal(String),
+    }
+
+    let mode = match Option::<Mode>::deserialize(deserializer)? {
+        None => return Ok(None),
+        Some(Mode::Number(mode)) => mode,
+        Some(Mode::Octal(mode)) => u32::from_str_radix(&mode, 8)
+            .map_err(|_| de::Error::custom(format_args!("invalid octal mode {mode:?}")))?,
+    };
+    if mode & !0o7777 != 0 {
+        return Err(de::Error::custom(format_args!(
+            "mode {mode:o} has bits set other than permission bits"
+        )));
+    }
+    Ok(Some(mode))
+}
+
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
 #[serde(rename_all = "kebab-case")]
 pub enum JobMountFsType {
     Proc,
-    Tmp,
+    /// A tmpfs. In TOML and JSON, the string `"tmp"` is short for a tmpfs with default options.
+    Tmp(TmpfsOptions),
     Sys,
+    /// A bind mount of `source` from the worker's host. Workers only allow this for paths in
+    /// their `allowed_bind_mounts` configuration.
+    Bind {
+        source: Utf8PathBuf,
+        #[serde(default)]
+        read_only: bool,
+    },
+    Devpts,
+    Mqueue,
 }
 
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct JobMount {
+    #[serde(deserialize_with = "deserialize_job_mount_fs_type")]
     pub fs_type: JobMountFsType,
     pub mount_point: Utf8PathBuf,
 }
 
+/// Deserialize a [`JobMountFsType`], also accepting a bare `"tmp"` in human-readable formats.
+fn deserialize_job_mount_fs_type<'de, D>(deserializer: D) -> Result<JobMountFsType, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_with_shorthand::<_, _, JobMountFsType>(
+        deserializer,
+        "a file system type",
+        "tmp",
+        || JobMountFsType::Tmp(TmpfsOptions::default()),
+    )
+}
+
+/// Deserialize an externally-tagged enum using the derived implementation of `U`, but also accept
+/// the bare string `shorthand` in human-readable formats, meaning `value()`. This lets a variant
+/// with options be given by just its name when the defaults are fine. Binary formats like bincode
+/// can't handle `deserialize_any`, so they always get the derived implementation.
+fn deserialize_with_shorthand<'de, D, T, U>(
+    deserializer: D,
+    expecting: &'static str,
+    shorthand: &'static str,
+    value: fn() -> T,
+) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    U: Deserialize<'de> + Into<T>,
+{
+    struct ShorthandVisitor<T, U> {
+        expecting: &'static str,
+        shorthand: &'static str,
+        value: fn() -> T,
+        derived: PhantomData<U>,
+    }
+
+    impl<'de, T, U> de::Visitor<'de> for ShorthandVisitor<T, U>
+    where
+        U: Deserialize<'de> + Into<T>,
+    {
+        type Value = T;
+
+        fn expecting(&self, formatter: &mut Formatter) -> fmt::Result {
+            formatter.write_str(self.expecting)
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+            if v == self.shorthand {
+                Ok((self.value)())
+            } else {
+                U::deserialize(de::value::StrDeserializer::new(v)).map(Into::into)
+            }
+        }
+
+        fn visit_map<A: de::MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+            U::deserialize(de::value::MapAccessDeserializer::new(map)).map(Into::into)
+        }
+    }
+
+    if deserializer.is_human_readable() {
+        deserializer.deserialize_any(ShorthandVisitor {
+            expecting,
+            shorthand,
+            value,
+            derived: PhantomData::<U>,
+        })
+    } else {
+        U::deserialize(deserializer).map(Into::into)
+    }
+}
+
+/// The transport protocol of a [`JobPortForward`].
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum JobPortProtocol {
+    #[default]
+    Tcp,
+    Udp,
+}
+
+/// A port on the worker's host that is forwarded to a port in the job's network namespace.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct JobPortForward {
+    #[serde(default)]
+    pub protocol: JobPortProtocol,
+    pub host_port: u16,
+    pub job_port: u16,
+}
+
+/// Options for [`JobNetwork::Nat`].
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct JobNatOptions {
+    /// Ports on the worker's loopback interface to forward into the job.
+    #[serde(default)]
+    pub port_forwards: Vec<JobPortForward>,
+
+    /// Host names, IPv4 addresses, and IPv4 networks in CIDR notation that the job may connect
+    /// to. Host names are resolved by the worker when the job starts. If empty, the job may
+    /// connect anywhere the worker can. Otherwise, the job can't reach the worker's loopback
+    /// interface unless 10.0.2.2 is allowed, and it can't reconfigure its network to get around
+    /// the list.
+    #[serde(default)]
+    pub allowed_hosts: Vec<String>,
+}
+
+/// The network set up for a job. Every job gets its own network namespace, so this only controls
+/// what is in that namespace.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum JobNetwork {
+    /// No network interfaces at all, not even loopback.
+    #[default]
+    Disabled,
+    /// Just a loopback interface.
+    Loopback,
+    /// A loopback interface plus an interface that is NATed to the worker's network by a
+    /// user-mode network stack. The worker's own loopback interface is reachable at 10.0.2.2, and
+    /// a DNS forwarder is at 10.0.2.3. In TOML and JSON, the string `"nat"` is short for this with
+    /// default options.
+    Nat(JobNatOptions),
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum JobNetworkDeserialize {
+    Disabled,
+    Loopback,
+    Nat(JobNatOptions),
+}
+
+impl From<JobNetworkDeserialize> for JobNetwork {
+    fn from(network: JobNetworkDeserialize) -> Self {
+        match network {
+            JobNetworkDeserialize::Disabled => JobNetwork::Disabled,
+            JobNetworkDeserialize::Loopback => JobNetwork::Loopback,
+            JobNetworkDeserialize::Nat(options) => JobNetwork::Nat(options),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for JobNetwork {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_with_shorthand::<_, _, JobNetworkDeserialize>(
+            deserializer,
+            "a network type",
+            "nat",
+            || JobNetwork::Nat(JobNatOptions::default()),
+        )
+    }
+}
+
+/// What the kernel does when a job makes a system call matched by a seccomp rule.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum JobSeccompAction {
+    Allow,
+    /// Fail the system call with the given errno.
+    Errno(u32),
+    /// Allow the system call, but log it to the worker's kernel log.
+    Log,
+    /// Kill the job. The worker reports this as an execution error.
+    Kill,
+}
+
+fn default_seccomp_action() -> JobSeccompAction {
+    JobSeccompAction::Allow
+}
+
+/// A list of system calls, by name, and what to do when the job makes one of them. If a system
+/// call is in more than one rule, the most restrictive action wins.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct JobSeccompRule {
+    pub syscalls: Vec<String>,
+    pub action: JobSeccompAction,
+}
+
+/// An inline seccomp profile.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct JobSeccompPolicy {
+    /// The action for system calls that aren't in any rule.
+    #[serde(default = "default_seccomp_action")]
+    pub default_action: JobSeccompAction,
+    #[serde(default)]
+    pub rules: Vec<JobSeccompRule>,
+}
+
+/// The seccomp profile the worker installs right before executing the job's program. In TOML and
+/// JSON, a string names one of the worker's built-in profiles, and a table is an inline profile.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum JobSeccomp {
+    Named(String),
+    Inline(JobSeccompPolicy),
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum JobSeccompDeserialize {
+    Named(String),
+    Inline(JobSeccompPolicy),
+}
+
+impl From<JobSeccompDeserialize> for JobSeccomp {
+    fn from(seccomp: JobSeccompDeserialize) -> Self {
+        match seccomp {
+            JobSeccompDeserialize::Named(name) => JobSeccomp::Named(name),
+            JobSeccompDeserialize::Inline(policy) => JobSeccomp::Inline(policy),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for JobSeccomp {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct SeccompVisitor;
+
+        impl<'de> de::Visitor<'de> for SeccompVisitor {
+            type Value = JobSeccomp;
+
+            fn expecting(&self, formatter: &mut Formatter) -> fmt::Result {
+                formatter.write_str("a seccomp profile name or table")
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                Ok(JobSeccomp::Named(v.into()))
+            }
+
+            fn visit_map<A: de::MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                JobSeccompPolicy::deserialize(de::value::MapAccessDeserializer::new(map))
+                    .map(JobSeccomp::Inline)
+            }
+        }
+
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(SeccompVisitor)
+        } else {
+            JobSeccompDeserialize::deserialize(deserializer).map(Into::into)
+        }
+    }
+}
+
+/// A resource that can be limited with an rlimit. These correspond to the `RLIMIT_*` constants.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobRlimitResource {
+    As,
+    Core,
+    Cpu,
+    Data,
+    Fsize,
+    Locks,
+    Memlock,
+    Msgqueue,
+    Nice,
+    Nofile,
+    Nproc,
+    Rss,
+    Rtprio,
+    Rttime,
+    Sigpending,
+    Stack,
+}
+
+/// One of the two values of an rlimit. In TOML and JSON, this is either a non-negative integer or
+/// the string `"unlimited"`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum JobRlimitValue {
+    Limited(u64),
+    Unlimited,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum JobRlimitValueDeserialize {
+    Limited(u64),
+    Unlimited,
+}
+
+impl From<JobRlimitValueDeserialize> for JobRlimitValue {
+    fn from(value: JobRlimitValueDeserialize) -> Self {
+        match value {
+            JobRlimitValueDeserialize::Limited(limit) => JobRlimitValue::Limited(limit),
+            JobRlimitValueDeserialize::Unlimited => JobRlimitValue::Unlimited,
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for JobRlimitValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct RlimitValueVisitor;
+
+        impl<'de> de::Visitor<'de> for RlimitValueVisitor {
+            type Value = JobRlimitValue;
+
+            fn expecting(&self, formatter: &mut Formatter) -> fmt::Result {
+                formatter.write_str(r#"a non-negative integer or "unlimited""#)
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+                Ok(JobRlimitValue::Limited(v))
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+                u64::try_from(v)
+                    .map(JobRlimitValue::Limited)
+                    .map_err(|_| E::invalid_value(de::Unexpected::Signed(v), &self))
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                if v == "unlimited" {
+                    Ok(JobRlimitValue::Unlimited)
+                } else {
+                    Err(E::invalid_value(de::Unexpected::Str(v), &self))
+                }
+            }
+        }
+
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(RlimitValueVisitor)
+        } else {
+            JobRlimitValueDeserialize::deserialize(deserializer).map(Into::into)
+        }
+    }
+}
+
+/// A resource limit the worker sets right before executing the job's program. The soft limit can't
+/// be more than the hard limit, and the hard limit can't be raised above the worker's.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct JobRlimit {
+    pub resource: JobRlimitResource,
+    pub soft: JobRlimitValue,
+    pub hard: JobRlimitValue,
+}
+
 /// ID of a user. This should be compatible with uid_t.
 #[derive(
     Copy, Clone, Debug, Deserialize, Display, Eq, From, Hash, Ord, PartialEq, PartialOrd, Serialize,
 )]
 pub struct UserId(u32);
 
+impl UserId {
+    pub fn as_u32(self) -> u32 {
+        self.0
+    }
+}
+
 /// ID of a group. This should be compatible with gid_t.
 #[derive(
     Copy, Clone, Debug, Deserialize, Display, Eq, From, Hash, Ord, PartialEq, PartialOrd, Serialize,
 )]
 pub struct GroupId(u32);
 
+impl GroupId {
+    pub fn as_u32(self) -> u32 {
+        self.0
+    }
+}
+
 /// A count of seconds.
 #[derive(Copy, Clone, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
 pub struct Timeout(NonZeroU32);
@@ -129,6 +571,40 @@ impl From<Timeout> for Duration {
     }
 }
 
+/// A signal the worker can send a job to ask it to stop. In JSON and TOML, these are written like
+/// `"SIGTERM"`.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum JobSignal {
+    Sighup,
+    Sigint,
+    Sigquit,
+    Sigkill,
+    Sigusr1,
+    Sigusr2,
+    Sigterm,
+}
+
+/// Parse a signal the way container images give their `StopSignal`: either a name, with or without
+/// the `SIG` prefix, or a number.
+impl FromStr for JobSignal {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let name = value.strip_prefix("SIG").unwrap_or(value);
+        match name {
+            "HUP" | "1" => Ok(JobSignal::Sighup),
+            "INT" | "2" => Ok(JobSignal::Sigint),
+            "QUIT" | "3" => Ok(JobSignal::Sigquit),
+            "KILL" | "9" => Ok(JobSignal::Sigkill),
+            "USR1" | "10" => Ok(JobSignal::Sigusr1),
+            "USR2" | "12" => Ok(JobSignal::Sigusr2),
+            "TERM" | "15" => Ok(JobSignal::Sigterm),
+            _ => Err(format!("unsupported stop signal {value:?}")),
+        }
+    }
+}
+
 /// All necessary information for the worker to execute a job.
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct JobSpec {
@@ -138,12 +614,26 @@ pub struct JobSpec {
     pub layers: NonEmpty<(Sha256Digest, ArtifactType)>,
     pub devices: EnumSet<JobDevice>,
     pub mounts: Vec<JobMount>,
-    pub enable_loopback: bool,
+    pub network: JobNetwork,
     pub enable_writable_file_system: bool,
     pub working_directory: Utf8PathBuf,
     pub user: UserId,
     pub group: GroupId,
     pub timeout: Option<Timeout>,
+    /// The signal sent to the job when it times out or is canceled. If it is `None` or
+    /// [`JobSignal::Sigkill`], the job is killed right away.
+    pub stop_signal: Option<JobSignal>,
+    /// How many seconds the job has to terminate after being sent its stop signal before it is
+    /// killed. If it is `None`, the worker uses a default of 10 seconds. If it is 0, the job is
+    /// killed right away.
+    pub grace_period: Option<u32>,
+    /// Whether to collect a description of what the job's threads are doing when it times out,
+    /// before it is stopped. This is returned in [`JobEffects::diagnostics`].
+    pub stack_dump_on_timeout: bool,
+    pub seccomp: Option<JobSeccomp>,
+    pub rlimits: Vec<JobRlimit>,
+    pub hostname: Option<String>,
+    pub domainname: Option<String>,
 }
 
 impl JobSpec {
@@ -158,12 +648,19 @@ impl JobSpec {
             environment: Default::default(),
             devices: Default::default(),
             mounts: Default::default(),
-            enable_loopback: false,
+            network: Default::default(),
             enable_writable_file_system: Default::default(),
             working_directory: Utf8PathBuf::from("/"),
             user: UserId::from(0),
             group: GroupId::from(0),
             timeout: None,
+            stop_signal: None,
+            grace_period: None,
+            stack_dump_on_timeout: false,
+            seccomp: None,
+            rlimits: Default::default(),
+            hostname: None,
+            domainname: None,
         }
     }
 
@@ -195,8 +692,8 @@ impl JobSpec {
         self
     }
 
-    pub fn enable_loopback(mut self, enable_loopback: bool) -> Self {
-        self.enable_loopback = enable_loopback;
+    pub fn network(mut self, network: JobNetwork) -> Self {
+        self.network = network;
         self
     }
 
@@ -224,6 +721,41 @@ impl JobSpec {
         self.timeout = timeout.into();
         self
     }
+
+    pub fn stop_signal(mut self, stop_signal: impl Into<Option<JobSignal>>) -> Self {
+        self.stop_signal = stop_signal.into();
+        self
+    }
+
+    pub fn grace_period(mut self, grace_period: impl Into<Option<u32>>) -> Self {
+        self.grace_period = grace_period.into();
+        self
+    }
+
+    pub fn stack_dump_on_timeout(mut self, stack_dump_on_timeout: bool) -> Self {
+        self.stack_dump_on_timeout = stack_dump_on_timeout;
+        self
+    }
+
+    pub fn seccomp(mut self, seccomp: impl Into<Option<JobSeccomp>>) -> Self {
+        self.seccomp = seccomp.into();
+        self
+    }
+
+    pub fn rlimits(mut self, rlimits: impl IntoIterator<Item = JobRlimit>) -> Self {
+        self.rlimits = rlimits.into_iter().collect();
+        self
+    }
+
+    pub fn hostname(mut self, hostname: impl Into<Option<String>>) -> Self {
+        self.hostname = hostname.into();
+        self
+    }
+
+    pub fn domainname(mut self, domainname: impl Into<Option<String>>) -> Self {
+        self.domainname = domainname.into();
+        self
+    }
 }
 
 /// How a job's process terminated. A process can either exit of its own accord or be killed by a
@@ -280,6 +812,11 @@ impl Debug for JobOutputResult {
 pub struct JobEffects {
     pub stdout: JobOutputResult,
     pub stderr: JobOutputResult,
+    /// Output collected by the worker about the job, rather than output of the job itself. This is
+    /// where the stack dump of a timed-out job goes.
+    pub diagnostics: JobOutputResult,
+    /// How long the job ran on the worker, from when it was started until it terminated.
+    pub duration: Duration,
 }
 
 /// The outcome of a job. This doesn't include error outcomes, which are handled with JobError.
@@ -289,7 +826,14 @@ pub enum JobOutcome {
         status: JobStatus,
         effects: JobEffects,
     },
+    /// The job timed out and was killed with `SIGKILL`, either right away or because it didn't
+    /// terminate within its grace period after being sent its stop signal.
     TimedOut(JobEffects),
+    /// The job timed out, was sent its stop signal, and terminated within its grace period.
+    TimedOutGracefully {
+        status: JobStatus,
+        effects: JobEffects,
+    },
 }
 
 /// A job failed to execute for some reason. We separate the universe of errors into "execution"
@@ -429,6 +973,7 @@ impl Error for Sha256DigestVerificationError {}
 #[cfg(test)]
 mod tests {
     use super::*;
+    use serde_test::{assert_de_tokens, assert_de_tokens_error, Configure as _, Readable, Token};
 
     #[test]
     fn client_id_display() {
@@ -446,6 +991,15 @@ mod tests {
         assert_eq!(format!("{:3}", ClientJobId::from(43)), " 43");
     }
 
+    #[test]
+    fn job_id_display() {
+        let jid = JobId {
+            cid: ClientId::from(1),
+            cjid: ClientJobId::from(100),
+        };
+        assert_eq!(format!("{jid}"), "1.100");
+    }
+
     #[test]
     fn user_id_display() {
         assert_eq!(format!("{}", UserId::from(100)), "100");
@@ -618,4 +1172,44 @@ mod tests {
 )"
         );
     }
+
+    #[test]
+    fn rlimit_value_deserialize() {
+        assert_de_tokens(&JobRlimitValue::Limited(10).readable(), &[Token::I64(10)]);
+        assert_de_tokens(&JobRlimitValue::Limited(10).readable(), &[Token::U64(10)]);
+        assert_de_tokens(
+            &JobRlimitValue::Unlimited.readable(),
+            &[Token::Str("unlimited")],
+        );
+        assert_de_tokens_error::<Readable<JobRlimitValue>>(
+            &[Token::I64(-1)],
+            r#"invalid value: integer `-1`, expected a non-negative integer or "unlimited""#,
+        );
+        assert_de_tokens_error::<Readable<JobRlimitValue>>(
+            &[Token::Str("infinity")],
+            r#"invalid value: string "infinity", expected a non-negative integer or "unlimited""#,
+        );
+    }
+
+    #[test]
+    fn job_signal_from_str() {
+        assert_eq!("SIGTERM".parse(), Ok(JobSignal::Sigterm));
+        assert_eq!("INT".parse(), Ok(JobSignal::Sigint));
+        assert_eq!("9".parse(), Ok(JobSignal::Sigkill));
+        assert_eq!(
+            "SIGWINCH".parse::<JobSignal>(),
+            Err(r#"unsupported stop signal "SIGWINCH""#.to_string())
+        );
+    }
+
+    #[test]
+    fn job_signal_deserialize() {
+        assert_de_tokens(
+            &JobSignal::Sigusr1,
+            &[Token::UnitVariant {
+                name: "JobSignal",
+                variant: "SIGUSR1",
+            }],
+        );
+    }
 }
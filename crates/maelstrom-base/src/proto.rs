@@ -2,23 +2,41 @@
 
 use crate::{
     stats::{BrokerStatistics, JobStateCounts},
-    ArtifactType, ClientJobId, JobId, JobOutcomeResult, JobSpec, Sha256Digest,
+    ClientJobId, JobId, JobOutcomeResult, JobSpec, Sha256Digest,
 };
 use bincode::Options;
 use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
 
 /// The first message sent by a connector to the broker. It identifies what the connector is, and
 /// provides any relevant information.
 #[derive(Serialize, Deserialize, Debug)]
 pub enum Hello {
     Client,
-    Worker { slots: u32 },
+    /// A worker. If it has a `peer_port`, it serves the artifacts it has stored to other workers'
+    /// artifact fetchers on that port, at the same address it connected from.
+    Worker {
+        slots: u32,
+        peer_port: Option<u16>,
+    },
     ArtifactPusher,
-    ArtifactFetcher,
+    /// An artifact fetcher for a worker. It has the same `peer_port` as its worker.
+    ArtifactFetcher {
+        peer_port: Option<u16>,
+    },
+}
+
+/// The first message sent by the broker to a worker, in response to its [`Hello::Worker`].
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct WorkerWelcome {
+    /// A random value the broker picks each time it starts. It goes into job trace IDs, so that
+    /// the traces of jobs from different runs of the broker don't collide.
+    pub broker_nonce: u64,
 }
 
 /// Message sent from the broker to a worker. The broker won't send a message until it has received
-/// a [`Hello`] and determined the type of its interlocutor.
+/// a [`Hello`] and sent back a [`WorkerWelcome`].
+#[allow(clippy::large_enum_variant)]
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub enum BrokerToWorker {
     EnqueueJob(JobId, JobSpec),
@@ -49,19 +67,93 @@ pub enum ClientToBroker {
     JobRequest(ClientJobId, JobSpec),
     StatisticsRequest,
     JobStateCountsRequest,
+    /// The client couldn't push an artifact the broker asked for with
+    /// [`BrokerToClient::TransferArtifact`], and has given up. The message describes what went
+    /// wrong. The broker fails the client's jobs that were waiting for the artifact.
+    ArtifactTransferFailed(Sha256Digest, String),
+}
+
+/// How the body of an artifact is compressed while it's being transferred. Artifacts are always
+/// identified by the digest of their uncompressed contents, and are stored uncompressed.
+///
+/// When an artifact is compressed, the compressed bytes are sent in chunks, as written by
+/// `maelstrom_util::io::ChunkedWriter`, since the compressed size isn't known ahead of time.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    Deserialize,
+    Eq,
+    PartialEq,
+    Serialize,
+    strum::Display,
+    strum::EnumString,
+)]
+#[serde(rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case")]
+pub enum ArtifactCompression {
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+}
+
+/// Where an artifact fetcher gets the body of an artifact from.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub enum ArtifactSource {
+    /// The broker sends the body right after the [`BrokerToArtifactFetcher`].
+    Broker,
+
+    /// The artifact fetcher should fetch the artifact from the worker listening at the given
+    /// address, with an [`ArtifactFetcherToPeer`] message.
+    Peer(SocketAddr),
 }
 
 /// Message sent from the broker to an artifact fetcher. This will be in response to an
-/// [`ArtifactFetcherToBroker`] message. On failure to get the artifact, the result contains
-/// details about what went wrong. After a failure, the broker will close the artifact fetcher
-/// connection.
+/// [`ArtifactFetcherToBroker::Fetch`] or [`ArtifactFetcherToBroker::FetchFromPeerOrBroker`]
+/// message. On failure to get the artifact, the result contains details about what went wrong.
+/// After a failure, the broker will close the artifact fetcher connection.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct BrokerToArtifactFetcher(pub Result<ArtifactSource, String>);
+
+/// Message sent from an artifact fetcher to the broker.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub enum ArtifactFetcherToBroker {
+    /// Fetch an artifact from the broker. It will be answered with a [`BrokerToArtifactFetcher`]
+    /// with [`ArtifactSource::Broker`], followed by the artifact's body, compressed as requested.
+    /// The body is the artifact as the broker stores it, so manifests are sent as manifests. An
+    /// artifact fetcher can then fetch the files a manifest refers to that it doesn't already
+    /// have, one at a time, over the same connection.
+    Fetch(Sha256Digest, ArtifactCompression),
+
+    /// Like [`Self::Fetch`], except that the broker may answer with [`ArtifactSource::Peer`]
+    /// instead, if another worker has the artifact. No body follows in that case. If the artifact
+    /// fetcher can't get the artifact from the peer, it should send a [`Self::Fetch`] for it next,
+    /// which tells the broker that the peer doesn't have it.
+    FetchFromPeerOrBroker(Sha256Digest, ArtifactCompression),
+
+    /// The artifact fetcher's worker has stored the given artifacts, and will serve them to other
+    /// workers. This is only sent by workers with a peer port, and isn't answered.
+    Stored(Vec<Sha256Digest>),
+
+    /// The artifact fetcher's worker has removed the given artifacts, which it previously said it
+    /// stored, and won't serve them anymore. This isn't answered.
+    Removed(Vec<Sha256Digest>),
+}
+
+/// Message sent from a worker to an artifact fetcher of another worker. This will be in response
+/// to an [`ArtifactFetcherToPeer`] message. On success, it's followed by the artifact's body,
+/// compressed as requested. On failure, the result contains details about what went wrong, and
+/// the worker will close the connection.
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
-pub struct BrokerToArtifactFetcher(pub Result<(), String>);
+pub struct PeerToArtifactFetcher(pub Result<(), String>);
 
-/// Message sent from an artifact fetcher to the broker. It will be answered with a
-/// [`BrokerToArtifactFetcher`].
+/// Message sent from an artifact fetcher to another worker, after the broker redirected it there
+/// with [`ArtifactSource::Peer`]. It will be answered with a [`PeerToArtifactFetcher`]. Any number
+/// of artifacts can be fetched over the same connection, one at a time.
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
-pub struct ArtifactFetcherToBroker(pub Sha256Digest, pub ArtifactType);
+pub struct ArtifactFetcherToPeer(pub Sha256Digest, pub ArtifactCompression);
 
 /// Message sent from the broker to an artifact pusher. This will be in response to an
 /// [`ArtifactPusherToBroker`] message and the artifact's body. On success, the message contains no
@@ -71,11 +163,11 @@ pub struct ArtifactFetcherToBroker(pub Sha256Digest, pub ArtifactType);
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct BrokerToArtifactPusher(pub Result<(), String>);
 
-/// Message sent from an artifact pusher to the broker. It contains the digest and size of the
-/// artifact. The body of the artifact will immediately follow this message. It will be answered
-/// with a [`BrokerToArtifactPusher`].
+/// Message sent from an artifact pusher to the broker. It contains the digest and uncompressed size
+/// of the artifact, and how its body is compressed. The body of the artifact will immediately
+/// follow this message. It will be answered with a [`BrokerToArtifactPusher`].
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
-pub struct ArtifactPusherToBroker(pub Sha256Digest, pub u64);
+pub struct ArtifactPusherToBroker(pub Sha256Digest, pub u64, pub ArtifactCompression);
 
 fn bincode() -> impl Options {
     bincode::options().with_big_endian()
@@ -6,7 +6,11 @@ use crate::{
 };
 use enum_map::EnumMap;
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, fmt, time::Duration};
+use std::{
+    collections::HashMap,
+    fmt,
+    time::{Duration, SystemTime},
+};
 
 /// The amount of time between broker statistic samples
 pub const BROKER_STATISTICS_INTERVAL: Duration = Duration::from_millis(500);
@@ -68,9 +72,10 @@ fn job_state_iter() {
 pub type JobStateCounts = EnumMap<JobState, u64>;
 
 /// Single point-in-time snapshot
-/// TODO: This should contain a timestamp
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
 pub struct JobStatisticsSample {
+    /// When the snapshot was taken, according to the broker's clock.
+    pub timestamp: SystemTime,
     pub client_to_stats: HashMap<ClientId, JobStateCounts>,
 }
 
@@ -134,6 +139,43 @@ impl JobStatisticsTimeSeries {
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct WorkerStatistics {
     pub slots: usize,
+    /// How many of the jobs sent to the worker it's running. The broker doesn't hear when a worker
+    /// starts a job, so this assumes the worker runs as many of its jobs as it has slots for.
+    pub running: usize,
+    /// How many of the jobs sent to the worker are waiting for a free slot.
+    pub pending: usize,
+}
+
+/// The size of the broker's artifact cache, and counts of what has happened to it since the broker
+/// started.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheStatistics {
+    /// How many artifacts are in the cache.
+    pub entries: u64,
+    /// How many bytes the artifacts in the cache use.
+    pub bytes_used: u64,
+    /// How many times a job asked for an artifact that was in the cache.
+    pub hits: u64,
+    /// How many times a job asked for an artifact that wasn't in the cache.
+    pub misses: u64,
+    /// How many artifacts were removed to keep the cache under its target size.
+    pub evictions: u64,
+}
+
+/// Progress of the broker's cache scrubber, which periodically re-verifies the digests of the
+/// artifacts in the broker's cache.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScrubStatistics {
+    /// How many times the scrubber has gone through the whole cache.
+    pub passes_completed: u64,
+    /// How many artifacts the current pass has gotten through, out of `pass_artifacts`.
+    pub pass_artifacts_scrubbed: u64,
+    /// How many artifacts were in the cache when the current pass started.
+    pub pass_artifacts: u64,
+    /// How many bytes the scrubber has read, over all passes.
+    pub bytes_scrubbed: u64,
+    /// How many artifacts the scrubber has found to be corrupt and quarantined.
+    pub corrupt_artifacts: u64,
 }
 
 /// Useful information for a client to display about the broker's state.
@@ -141,4 +183,12 @@ pub struct WorkerStatistics {
 pub struct BrokerStatistics {
     pub worker_statistics: HashMap<WorkerId, WorkerStatistics>,
     pub job_statistics: JobStatisticsTimeSeries,
+    pub scrub_statistics: ScrubStatistics,
+    pub cache_statistics: CacheStatistics,
+    /// The average time from a job being requested until it was first sent to a worker, over all
+    /// jobs sent to workers since the broker started. This is `None` if there haven't been any.
+    pub average_queue_wait: Option<Duration>,
+    /// The average time from a job last being sent to a worker until it completed, over all jobs
+    /// completed by workers since the broker started. This is `None` if there haven't been any.
+    pub average_run_duration: Option<Duration>,
 }
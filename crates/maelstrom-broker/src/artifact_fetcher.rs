@@ -1,98 +1,48 @@
-use crate::scheduler_task::{SchedulerMessage, SchedulerSender};
+use crate::scheduler_task::{ArtifactForWorker, SchedulerMessage, SchedulerSender};
 use anyhow::Result;
 use maelstrom_base::{
-    manifest::{ManifestEntry, ManifestEntryData, ManifestReader},
-    proto::{ArtifactFetcherToBroker, BrokerToArtifactFetcher},
-    ArtifactType, Sha256Digest,
+    proto::{
+        ArtifactCompression, ArtifactFetcherToBroker, ArtifactSource, BrokerToArtifactFetcher,
+    },
+    Sha256Digest,
 };
 use maelstrom_util::{
     fs::{File, Fs},
-    io::ChunkedWriter,
+    io::{ChunkedWriter, CompressingWriter},
     net,
 };
 use slog::{debug, Logger};
-use std::io::Seek as _;
-use std::{io, net::TcpStream, str, sync::mpsc};
+use std::{
+    io,
+    net::{SocketAddr, TcpStream},
+    sync::mpsc,
+};
+
+enum Artifact<'fs> {
+    Broker(File<'fs>, u64),
+    Peer(SocketAddr),
+}
 
-fn get_file<'fs>(
+fn get_artifact<'fs>(
     fs: &'fs Fs,
     digest: &Sha256Digest,
+    requester: Option<SocketAddr>,
     scheduler_sender: &SchedulerSender,
-) -> Result<(File<'fs>, u64)> {
+) -> Result<Artifact<'fs>> {
     let (channel_sender, channel_receiver) = mpsc::channel();
     scheduler_sender.send(SchedulerMessage::GetArtifactForWorker(
         digest.clone(),
+        requester,
         channel_sender,
     ))?;
 
-    let (path, size) = channel_receiver.recv()??;
-    let f = fs.open_file(path)?;
-    Ok((f, size))
-}
-
-fn add_entry_to_tar(
-    fs: &Fs,
-    tar: &mut tar::Builder<impl io::Write>,
-    scheduler_sender: &SchedulerSender,
-    entry: &ManifestEntry,
-) -> Result<()> {
-    let mut header = tar::Header::new_gnu();
-    header.set_size(entry.metadata.size);
-    header.set_mode(entry.metadata.mode.into());
-    header.set_mtime(i64::from(entry.metadata.mtime) as u64);
-
-    let path = entry.path.as_str().trim_start_matches('/');
-    match &entry.data {
-        ManifestEntryData::File(Some(digest)) => {
-            header.set_entry_type(tar::EntryType::Regular);
-            let (mut f, size) = get_file(fs, digest, scheduler_sender)?;
-            tar.append_data(&mut header, path, &mut f)?;
-            assert_eq!(f.stream_position()?, size);
-            scheduler_sender.send(SchedulerMessage::DecrementRefcount(digest.clone()))?;
-        }
-        ManifestEntryData::File(None) => {
-            header.set_entry_type(tar::EntryType::Regular);
-            tar.append_data(&mut header, path, io::empty())?;
-        }
-        ManifestEntryData::Directory => {
-            header.set_entry_type(tar::EntryType::Directory);
-            tar.append_data(&mut header, path, io::empty())?;
-        }
-        ManifestEntryData::Symlink(data) => {
-            header.set_entry_type(tar::EntryType::Symlink);
-            let target = str::from_utf8(data)?;
-            tar.append_link(&mut header, path, target)?;
-        }
-        ManifestEntryData::Hardlink(target) => {
-            header.set_entry_type(tar::EntryType::Link);
-            tar.append_link(&mut header, path, target)?;
-        }
+    match channel_receiver.recv()?? {
+        ArtifactForWorker::Broker(path, size) => Ok(Artifact::Broker(fs.open_file(path)?, size)),
+        ArtifactForWorker::Peer(peer) => Ok(Artifact::Peer(peer)),
     }
-
-    Ok(())
 }
 
-fn send_manifest(
-    fs: &Fs,
-    scheduler_sender: &SchedulerSender,
-    mut file: &mut File<'_>,
-    mut socket: &mut impl io::Write,
-    size: u64,
-    digest: Sha256Digest,
-) -> Result<()> {
-    let mut tar = tar::Builder::new(&mut socket);
-    for entry in ManifestReader::new(&mut file)? {
-        add_entry_to_tar(fs, &mut tar, scheduler_sender, &entry?)?;
-    }
-    tar.finish()?;
-
-    assert_eq!(file.stream_position()?, size);
-    scheduler_sender.send(SchedulerMessage::DecrementRefcount(digest))?;
-
-    Ok(())
-}
-
-fn send_tar(
+fn send_artifact(
     scheduler_sender: &SchedulerSender,
     mut file: &mut File<'_>,
     mut socket: &mut impl io::Write,
@@ -107,51 +57,150 @@ fn send_tar(
 
 const MAX_CHUNK_SIZE: usize = 1024 * 1024;
 
-fn handle_one_message(
-    msg: ArtifactFetcherToBroker,
+/// Answer a request for an artifact, either with its body or by sending the artifact fetcher to
+/// another worker. Only artifact fetchers whose workers serve artifacts, at `requester`, can be
+/// sent to another worker. When they are, that worker's address is recorded
+/// in `redirected`, so that the transfer can be ended when the artifact fetcher moves on.
+fn fetch(
+    digest: Sha256Digest,
+    requester: Option<SocketAddr>,
+    compression: ArtifactCompression,
     mut socket: &mut impl io::Write,
     scheduler_sender: &SchedulerSender,
+    redirected: &mut Option<(Sha256Digest, SocketAddr)>,
     log: &mut Logger,
 ) -> Result<()> {
-    debug!(log, "received artifact fetcher message"; "msg" => ?msg);
-    let ArtifactFetcherToBroker(digest, type_) = msg;
     let fs = Fs::new();
-    let result = get_file(&fs, &digest, scheduler_sender);
-    let msg = BrokerToArtifactFetcher(result.as_ref().map(|_| ()).map_err(|e| e.to_string()));
+    let result = get_artifact(&fs, &digest, requester, scheduler_sender);
+    let msg = BrokerToArtifactFetcher(
+        result
+            .as_ref()
+            .map(|artifact| match artifact {
+                Artifact::Broker(..) => ArtifactSource::Broker,
+                Artifact::Peer(peer) => ArtifactSource::Peer(*peer),
+            })
+            .map_err(|e| e.to_string()),
+    );
+    if let Ok(Artifact::Peer(peer)) = &result {
+        *redirected = Some((digest.clone(), *peer));
+    }
     debug!(log, "sending artifact fetcher message"; "msg" => ?msg);
     net::write_message_to_socket(&mut socket, msg)?;
 
-    let mut socket = ChunkedWriter::new(socket, MAX_CHUNK_SIZE);
-    let (mut f, size) = result?;
-    match type_ {
-        ArtifactType::Manifest => {
-            send_manifest(&fs, scheduler_sender, &mut f, &mut socket, size, digest)?
-        }
-        ArtifactType::Tar => send_tar(scheduler_sender, &mut f, &mut socket, size, digest)?,
+    if let Artifact::Broker(mut f, size) = result? {
+        let mut socket =
+            CompressingWriter::new(ChunkedWriter::new(socket, MAX_CHUNK_SIZE), compression)?;
+        send_artifact(scheduler_sender, &mut f, &mut socket, size, digest)?;
+        socket.finish()?.finish()?;
     }
-    socket.finish()?;
 
     Ok(())
 }
 
+fn handle_one_message(
+    msg: ArtifactFetcherToBroker,
+    socket: &mut impl io::Write,
+    scheduler_sender: &SchedulerSender,
+    peer_server_addr: Option<SocketAddr>,
+    redirected: &mut Option<(Sha256Digest, SocketAddr)>,
+    log: &mut Logger,
+) -> Result<()> {
+    debug!(log, "received artifact fetcher message"; "msg" => ?msg);
+
+    // An artifact fetcher that was sent to another worker asks us for the same artifact next only
+    // if it couldn't get it from that worker.
+    if let Some((redirected_digest, peer)) = redirected.take() {
+        scheduler_sender.send(match &msg {
+            ArtifactFetcherToBroker::Fetch(digest, _) if *digest == redirected_digest => {
+                SchedulerMessage::PeerTransferFailed(redirected_digest, peer)
+            }
+            _ => SchedulerMessage::PeerTransferDone(peer),
+        })?;
+    }
+
+    match msg {
+        ArtifactFetcherToBroker::Fetch(digest, compression) => fetch(
+            digest,
+            None,
+            compression,
+            socket,
+            scheduler_sender,
+            redirected,
+            log,
+        ),
+        ArtifactFetcherToBroker::FetchFromPeerOrBroker(digest, compression) => fetch(
+            digest,
+            peer_server_addr,
+            compression,
+            socket,
+            scheduler_sender,
+            redirected,
+            log,
+        ),
+        ArtifactFetcherToBroker::Stored(digests) => {
+            if let Some(peer_server_addr) = peer_server_addr {
+                scheduler_sender.send(SchedulerMessage::PeerStoredArtifacts(
+                    digests,
+                    peer_server_addr,
+                ))?;
+            }
+            Ok(())
+        }
+        ArtifactFetcherToBroker::Removed(digests) => {
+            if let Some(peer_server_addr) = peer_server_addr {
+                scheduler_sender.send(SchedulerMessage::PeerRemovedArtifacts(
+                    digests,
+                    peer_server_addr,
+                ))?;
+            }
+            Ok(())
+        }
+    }
+}
+
 fn connection_loop(
     mut socket: TcpStream,
     scheduler_sender: &SchedulerSender,
+    peer_server_addr: Option<SocketAddr>,
+    redirected: &mut Option<(Sha256Digest, SocketAddr)>,
     log: &mut Logger,
 ) -> Result<()> {
     loop {
         let msg = net::read_message_from_socket(&mut socket)?;
-        handle_one_message(msg, &mut socket, scheduler_sender, log)?;
+        handle_one_message(
+            msg,
+            &mut socket,
+            scheduler_sender,
+            peer_server_addr,
+            redirected,
+            log,
+        )?;
     }
 }
 
+/// Handle an artifact fetcher connection. The artifact fetcher's worker serves artifacts to other
+/// workers at `peer_server_addr`, if it has one.
 pub fn connection_main(
     socket: TcpStream,
+    peer_server_addr: Option<SocketAddr>,
     scheduler_sender: SchedulerSender,
     mut log: Logger,
 ) -> Result<()> {
     debug!(log, "artifact fetcher connected");
-    let err = connection_loop(socket, &scheduler_sender, &mut log).unwrap_err();
+    let mut redirected = None;
+    let err = connection_loop(
+        socket,
+        &scheduler_sender,
+        peer_server_addr,
+        &mut redirected,
+        &mut log,
+    )
+    .unwrap_err();
+    if let Some((_, peer)) = redirected {
+        scheduler_sender
+            .send(SchedulerMessage::PeerTransferDone(peer))
+            .ok();
+    }
     debug!(log, "artifact fetcher disconnected"; "err" => %err);
     Err(err)
 }
@@ -160,55 +209,36 @@ pub fn connection_main(
 mod tests {
     use super::*;
     use assert_matches::assert_matches;
-    use maelstrom_base::manifest::{ManifestEntryMetadata, ManifestWriter, Mode, UnixTimestamp};
+    use maelstrom_base::manifest::{
+        ManifestEntry, ManifestEntryData, ManifestEntryMetadata, ManifestWriter, Mode,
+        UnixTimestamp,
+    };
     use maelstrom_test::*;
-    use maelstrom_util::io::ChunkedReader;
-    use std::io::Read as _;
-    use std::os::unix::fs::MetadataExt as _;
-    use std::path::Path;
+    use maelstrom_util::io::{ChunkedReader, DecompressingReader};
+    use std::io::{BufReader, Read as _};
     use std::thread;
     use tempfile::{tempdir, TempDir};
     use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
 
-    fn write_manifest(path: &Path, entries: Vec<ManifestEntry>) -> u64 {
-        let fs = Fs::new();
-        let mut f = fs.create_file(path).unwrap();
-        let mut writer = ManifestWriter::new(&mut f).unwrap();
-        writer.write_entries(&entries).unwrap();
-        f.stream_position().unwrap()
-    }
-
-    async fn send_manifest(
-        tmp_dir: &TempDir,
-        receiver: &mut UnboundedReceiver<SchedulerMessage>,
-        manifest_entries: Vec<ManifestEntry>,
-    ) {
-        let SchedulerMessage::GetArtifactForWorker(digest, sender) = receiver.recv().await.unwrap()
-        else {
-            panic!()
-        };
-        let manifest_path = tmp_dir.path().join(format!("{digest}.manifest"));
-        let size = write_manifest(&manifest_path, manifest_entries);
-        sender.send(Ok((manifest_path, size))).unwrap();
-    }
-
-    fn put_file(path: &Path, data: &[u8]) {
-        let fs = Fs::new();
-        fs.write(path, data).unwrap();
+    fn peer_server_addr(n: u8) -> SocketAddr {
+        SocketAddr::from(([10, 0, 0, n], 5000))
     }
 
     async fn send_binary(
         tmp_dir: &TempDir,
         receiver: &mut UnboundedReceiver<SchedulerMessage>,
-        data: &[u8],
+        data: Vec<u8>,
     ) {
-        let SchedulerMessage::GetArtifactForWorker(digest, sender) = receiver.recv().await.unwrap()
+        let SchedulerMessage::GetArtifactForWorker(digest, None, sender) =
+            receiver.recv().await.unwrap()
         else {
             panic!()
         };
         let bin_path = tmp_dir.path().join(format!("{digest}.bin"));
-        put_file(&bin_path, data);
-        sender.send(Ok((bin_path, data.len() as u64))).unwrap();
+        Fs::new().write(&bin_path, &data).unwrap();
+        sender
+            .send(Ok(ArtifactForWorker::Broker(bin_path, data.len() as u64)))
+            .unwrap();
     }
 
     async fn wait_for_ref_dec(
@@ -221,15 +251,14 @@ mod tests {
         );
     }
 
-    fn artifact_fetcher_test<FutureT: std::future::Future>(
-        broker_body: impl FnOnce(UnboundedReceiver<SchedulerMessage>) -> FutureT + Send,
-        worker_body: impl FnOnce(&Path),
-    ) {
+    /// Have the broker send `data` as the artifact with digest 42, and return what the artifact
+    /// fetcher would read after decompressing it.
+    fn artifact_fetcher_test(compression: ArtifactCompression, data: Vec<u8>) -> Vec<u8> {
         let tmp_dir = tempdir().unwrap();
         let artifact_msg = tmp_dir.path().join("sent_data.bin");
 
-        let msg = ArtifactFetcherToBroker(digest![42], ArtifactType::Manifest);
-        let (sender, receiver) = unbounded_channel();
+        let msg = ArtifactFetcherToBroker::Fetch(digest![42], compression);
+        let (sender, mut receiver) = unbounded_channel();
         let mut log = Logger::root(slog::Discard, slog::o!());
         thread::scope(|scope| {
             scope.spawn(move || {
@@ -237,7 +266,11 @@ mod tests {
                     .enable_all()
                     .build()
                     .unwrap()
-                    .block_on(broker_body(receiver));
+                    .block_on(async move {
+                        let tmp_dir = tempdir().unwrap();
+                        send_binary(&tmp_dir, &mut receiver, data).await;
+                        wait_for_ref_dec(&mut receiver, digest![42]).await;
+                    });
             });
 
             let fs = Fs::new();
@@ -245,178 +278,237 @@ mod tests {
                 msg,
                 &mut fs.create_file(&artifact_msg).unwrap(),
                 &sender,
+                Some(peer_server_addr(1)),
+                &mut None,
                 &mut log,
             )
             .unwrap()
         });
 
         let fs = Fs::new();
-        let extracted_path = tmp_dir.path().join("extracted");
         let mut sent_data = fs.open_file(artifact_msg).unwrap();
 
         let msg: BrokerToArtifactFetcher = net::read_message_from_socket(&mut sent_data).unwrap();
-        msg.0.unwrap();
-
-        let mut reader = ChunkedReader::new(sent_data);
-        let mut tar = tar::Archive::new(&mut reader);
-        tar.set_preserve_mtime(true);
-        tar.set_preserve_permissions(true);
-        tar.unpack(&extracted_path).unwrap();
-        worker_body(&extracted_path);
+        assert_eq!(msg.0.unwrap(), ArtifactSource::Broker);
+
+        let mut reader =
+            DecompressingReader::new(BufReader::new(ChunkedReader::new(sent_data)), compression)
+                .unwrap();
+        let mut received = vec![];
+        reader.read_to_end(&mut received).unwrap();
+        received
     }
 
-    fn assert_file(path: &Path, data: &[u8], mode: u32, mtime: i64) {
-        let fs = Fs::new();
+    fn send_binary_test(compression: ArtifactCompression) {
+        let data = b"hello world".repeat(1000);
+        assert_eq!(artifact_fetcher_test(compression, data.clone()), data);
+    }
 
-        let mut f = fs.open_file(path).unwrap();
+    #[test]
+    fn send_binary_uncompressed() {
+        send_binary_test(ArtifactCompression::None);
+    }
 
-        let mut contents = Vec::new();
-        f.read_to_end(&mut contents).unwrap();
-        assert_eq!(contents, data);
+    #[test]
+    fn send_binary_gzip() {
+        send_binary_test(ArtifactCompression::Gzip);
+    }
 
-        let meta = f.metadata().unwrap();
-        assert_eq!(meta.len(), data.len() as u64);
-        assert_eq!(meta.mode(), mode);
-        assert_eq!(meta.mtime(), mtime);
-        assert!(meta.file_type().is_file());
+    #[test]
+    fn send_binary_zstd() {
+        send_binary_test(ArtifactCompression::Zstd);
     }
 
-    fn assert_dir(path: &Path, mode: u32) {
-        let fs = Fs::new();
+    #[test]
+    fn manifests_are_sent_as_they_are() {
+        let mut manifest = vec![];
+        ManifestWriter::new(&mut manifest)
+            .unwrap()
+            .write_entries(&[ManifestEntry {
+                path: "foobar.txt".into(),
+                metadata: ManifestEntryMetadata {
+                    size: 11,
+                    mode: Mode(0o0555),
+                    mtime: UnixTimestamp(1705538554),
+                },
+                data: ManifestEntryData::File(Some(digest![43])),
+            }])
+            .unwrap();
+        assert_eq!(
+            artifact_fetcher_test(ArtifactCompression::Zstd, manifest.clone()),
+            manifest
+        );
+    }
+
+    #[test]
+    fn fetch_from_peer_sends_no_body() {
+        let (sender, mut receiver) = unbounded_channel();
+        let mut log = Logger::root(slog::Discard, slog::o!());
+        let mut socket = vec![];
+        let mut redirected = None;
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                let SchedulerMessage::GetArtifactForWorker(digest, requester, sender) =
+                    receiver.blocking_recv().unwrap()
+                else {
+                    panic!()
+                };
+                assert_eq!(digest, digest![42]);
+                assert_eq!(requester, Some(peer_server_addr(1)));
+                sender
+                    .send(Ok(ArtifactForWorker::Peer(peer_server_addr(2))))
+                    .unwrap();
+            });
+            handle_one_message(
+                ArtifactFetcherToBroker::FetchFromPeerOrBroker(
+                    digest![42],
+                    ArtifactCompression::Zstd,
+                ),
+                &mut socket,
+                &sender,
+                Some(peer_server_addr(1)),
+                &mut redirected,
+                &mut log,
+            )
+            .unwrap();
+        });
+
+        let mut sent_data = socket.as_slice();
+        let msg: BrokerToArtifactFetcher = net::read_message_from_socket(&mut sent_data).unwrap();
+        assert_eq!(msg.0.unwrap(), ArtifactSource::Peer(peer_server_addr(2)));
+        assert!(sent_data.is_empty());
+        assert_eq!(redirected, Some((digest![42], peer_server_addr(2))));
+    }
+
+    /// Handle `msg` after the artifact fetcher was sent to another worker for digest 42, and return
+    /// the first message sent to the scheduler. Any request for an artifact goes unanswered.
+    fn message_after_redirect(msg: ArtifactFetcherToBroker) -> SchedulerMessage {
+        let (sender, mut receiver) = unbounded_channel();
+        let mut log = Logger::root(slog::Discard, slog::o!());
+        let mut redirected = Some((digest![42], peer_server_addr(2)));
+        thread::scope(|scope| {
+            let first = scope.spawn(move || {
+                let first = receiver.blocking_recv().unwrap();
+                while receiver.blocking_recv().is_some() {}
+                first
+            });
+            handle_one_message(
+                msg,
+                &mut vec![],
+                &sender,
+                Some(peer_server_addr(1)),
+                &mut redirected,
+                &mut log,
+            )
+            .ok();
+            drop(sender);
+            first.join().unwrap()
+        })
+    }
 
-        let f = fs.open_file(path).unwrap();
+    #[test]
+    fn fetch_of_same_artifact_after_redirect_is_transfer_failure() {
+        assert_matches!(
+            message_after_redirect(ArtifactFetcherToBroker::Fetch(
+                digest![42],
+                ArtifactCompression::None
+            )),
+            SchedulerMessage::PeerTransferFailed(digest, peer)
+                if digest == digest![42] && peer == peer_server_addr(2)
+        );
+    }
 
-        let meta = f.metadata().unwrap();
-        assert_eq!(meta.mode(), mode);
-        assert!(meta.file_type().is_dir());
+    #[test]
+    fn other_message_after_redirect_ends_transfer() {
+        assert_matches!(
+            message_after_redirect(ArtifactFetcherToBroker::Stored(vec![digest![42]])),
+            SchedulerMessage::PeerTransferDone(peer) if peer == peer_server_addr(2)
+        );
+        assert_matches!(
+            message_after_redirect(ArtifactFetcherToBroker::Fetch(
+                digest![43],
+                ArtifactCompression::None
+            )),
+            SchedulerMessage::PeerTransferDone(peer) if peer == peer_server_addr(2)
+        );
     }
 
     #[test]
-    fn manifest_to_tar_two_files() {
-        artifact_fetcher_test(
-            |mut receiver| async move {
-                let tmp_dir = tempdir().unwrap();
-                send_manifest(
-                    &tmp_dir,
-                    &mut receiver,
-                    vec![
-                        ManifestEntry {
-                            path: "foobar.txt".into(),
-                            metadata: ManifestEntryMetadata {
-                                size: 11,
-                                mode: Mode(0o0555),
-                                mtime: UnixTimestamp(1705538554),
-                            },
-                            data: ManifestEntryData::File(Some(digest![43])),
-                        },
-                        ManifestEntry {
-                            path: "empty_file".into(),
-                            metadata: ManifestEntryMetadata {
-                                size: 0,
-                                mode: Mode(0o0555),
-                                mtime: UnixTimestamp(1705538554),
-                            },
-                            data: ManifestEntryData::File(None),
-                        },
-                    ],
-                )
-                .await;
-                send_binary(&tmp_dir, &mut receiver, b"hello world").await;
-                wait_for_ref_dec(&mut receiver, digest![43]).await;
-                wait_for_ref_dec(&mut receiver, digest![42]).await;
-            },
-            |extracted_path| {
-                assert_file(
-                    &extracted_path.join("foobar.txt"),
-                    b"hello world",
-                    0o100555,
-                    1705538554,
-                );
-
-                assert_file(
-                    &extracted_path.join("empty_file"),
-                    b"",
-                    0o100555,
-                    1705538554,
-                );
-            },
+    fn stored_artifacts_are_announced() {
+        let (sender, mut receiver) = unbounded_channel();
+        let mut log = Logger::root(slog::Discard, slog::o!());
+        handle_one_message(
+            ArtifactFetcherToBroker::Stored(vec![digest![42], digest![43]]),
+            &mut vec![],
+            &sender,
+            Some(peer_server_addr(1)),
+            &mut None,
+            &mut log,
+        )
+        .unwrap();
+        assert_matches!(
+            receiver.try_recv().unwrap(),
+            SchedulerMessage::PeerStoredArtifacts(digests, peer)
+                if digests == vec![digest![42], digest![43]] && peer == peer_server_addr(1)
         );
     }
 
     #[test]
-    fn manifest_to_tar_directories_and_links() {
-        artifact_fetcher_test(
-            |mut receiver| async move {
-                let tmp_dir = tempdir().unwrap();
-                send_manifest(
-                    &tmp_dir,
-                    &mut receiver,
-                    vec![
-                        ManifestEntry {
-                            path: "/foobar/a_file".into(),
-                            metadata: ManifestEntryMetadata {
-                                size: 0,
-                                mode: Mode(0o0555),
-                                mtime: UnixTimestamp(1705538554),
-                            },
-                            data: ManifestEntryData::File(None),
-                        },
-                        ManifestEntry {
-                            path: "foobar/a_symlink".into(),
-                            metadata: ManifestEntryMetadata {
-                                size: 0,
-                                mode: Mode(0o0555),
-                                mtime: UnixTimestamp(1705538554),
-                            },
-                            data: ManifestEntryData::Symlink(b"./a_file".into()),
-                        },
-                        ManifestEntry {
-                            path: "foobar/a_hardlink".into(),
-                            metadata: ManifestEntryMetadata {
-                                size: 0,
-                                mode: Mode(0o0555),
-                                mtime: UnixTimestamp(1705538554),
-                            },
-                            data: ManifestEntryData::Hardlink("foobar/a_file".into()),
-                        },
-                        ManifestEntry {
-                            path: "foobar".into(),
-                            metadata: ManifestEntryMetadata {
-                                size: 0,
-                                mode: Mode(0o0555),
-                                mtime: UnixTimestamp(1705538554),
-                            },
-                            data: ManifestEntryData::Directory,
-                        },
-                        ManifestEntry {
-                            path: "baz".into(),
-                            metadata: ManifestEntryMetadata {
-                                size: 0,
-                                mode: Mode(0o0555),
-                                mtime: UnixTimestamp(1705538554),
-                            },
-                            data: ManifestEntryData::Directory,
-                        },
-                    ],
-                )
-                .await;
-                wait_for_ref_dec(&mut receiver, digest![42]).await;
-            },
-            |extracted_path| {
-                let a_file = extracted_path.join("foobar/a_file");
-                let hardlink = &extracted_path.join("foobar/a_hardlink");
-                assert_file(&a_file, b"", 0o100555, 1705538554);
-                assert_file(&hardlink, b"", 0o100555, 1705538554);
-
-                let fs = Fs::new();
-                assert_eq!(
-                    fs.metadata(a_file).unwrap().ino(),
-                    fs.metadata(hardlink).unwrap().ino()
-                );
-
-                assert_dir(&extracted_path.join("baz"), 0o40555);
-                assert_dir(&extracted_path.join("foobar"), 0o40555);
-            },
+    fn removed_artifacts_are_announced() {
+        let (sender, mut receiver) = unbounded_channel();
+        let mut log = Logger::root(slog::Discard, slog::o!());
+        handle_one_message(
+            ArtifactFetcherToBroker::Removed(vec![digest![42]]),
+            &mut vec![],
+            &sender,
+            Some(peer_server_addr(1)),
+            &mut None,
+            &mut log,
+        )
+        .unwrap();
+        assert_matches!(
+            receiver.try_recv().unwrap(),
+            SchedulerMessage::PeerRemovedArtifacts(digests, peer)
+                if digests == vec![digest![42]] && peer == peer_server_addr(1)
         );
     }
+
+    #[test]
+    fn fetcher_without_peer_server_is_not_redirected_or_announced() {
+        let (sender, mut receiver) = unbounded_channel();
+        let mut log = Logger::root(slog::Discard, slog::o!());
+        handle_one_message(
+            ArtifactFetcherToBroker::Stored(vec![digest![42]]),
+            &mut vec![],
+            &sender,
+            None,
+            &mut None,
+            &mut log,
+        )
+        .unwrap();
+        assert!(receiver.try_recv().is_err());
+
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                let SchedulerMessage::GetArtifactForWorker(_, requester, _) =
+                    receiver.blocking_recv().unwrap()
+                else {
+                    panic!()
+                };
+                assert_eq!(requester, None);
+            });
+            handle_one_message(
+                ArtifactFetcherToBroker::FetchFromPeerOrBroker(
+                    digest![42],
+                    ArtifactCompression::Zstd,
+                ),
+                &mut vec![],
+                &sender,
+                None,
+                &mut None,
+                &mut log,
+            )
+            .unwrap_err();
+        });
+    }
 }
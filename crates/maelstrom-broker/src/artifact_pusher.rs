@@ -1,36 +1,115 @@
-use crate::scheduler_task::{SchedulerMessage, SchedulerSender};
-use anyhow::Result;
-use maelstrom_base::proto::{ArtifactPusherToBroker, BrokerToArtifactPusher};
+use crate::{
+    blob_store::BlobStoreWorkers,
+    scheduler_task::{SchedulerMessage, SchedulerSender},
+};
+use anyhow::{bail, Result};
+use maelstrom_base::{
+    proto::{ArtifactCompression, ArtifactPusherToBroker, BrokerToArtifactPusher},
+    Sha256Digest,
+};
 use maelstrom_util::{
-    io::{FixedSizeReader, Sha256Reader},
+    io::{ChunkedReader, DecompressingReader, FixedSizeReader, Sha256Reader},
     net,
 };
 use slog::{debug, Logger};
 use std::{
-    io,
+    fs,
+    io::{self, BufReader, Read as _},
     net::TcpStream,
     path::{Path, PathBuf},
+    time::{Duration, Instant},
 };
 
+/// How often to tell the scheduler that an artifact is still arriving. This only has to be often
+/// enough to keep the artifact transfer timeout from expiring while data is flowing.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(10);
+
+/// A reader that calls `report` whenever data has been read and it's been at least
+/// [`PROGRESS_INTERVAL`] since the last time.
+struct ProgressReader<ReaderT, ReportT> {
+    reader: ReaderT,
+    report: ReportT,
+    last_report: Instant,
+}
+
+impl<ReaderT, ReportT> ProgressReader<ReaderT, ReportT> {
+    fn new(reader: ReaderT, report: ReportT) -> Self {
+        Self {
+            reader,
+            report,
+            last_report: Instant::now(),
+        }
+    }
+}
+
+impl<ReaderT: io::Read, ReportT: FnMut()> io::Read for ProgressReader<ReaderT, ReportT> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.reader.read(buf)?;
+        if read > 0 && self.last_report.elapsed() >= PROGRESS_INTERVAL {
+            (self.report)();
+            self.last_report = Instant::now();
+        }
+        Ok(read)
+    }
+}
+
+/// Read the body of an artifact from `socket` into `file`, returning the uncompressed size and the
+/// digest of the uncompressed contents. The whole body is consumed, so that the next message can be
+/// read from the socket. We stop decompressing once we've seen more than `size` bytes, so that a
+/// bad client can't fill up our disk.
+fn read_body(
+    mut socket: impl io::Read,
+    size: u64,
+    compression: ArtifactCompression,
+    file: &mut impl io::Write,
+) -> Result<(u64, Sha256Digest)> {
+    if compression == ArtifactCompression::None {
+        let mut sha_reader = Sha256Reader::new(FixedSizeReader::new(&mut socket, size));
+        let copied = io::copy(&mut sha_reader, file)?;
+        let (_, digest) = sha_reader.finalize();
+        return Ok((copied, digest));
+    }
+    let mut chunked_reader = ChunkedReader::new(&mut socket);
+    let decompressor = DecompressingReader::new(BufReader::new(&mut chunked_reader), compression)?;
+    let mut sha_reader = Sha256Reader::new(decompressor.take(size.saturating_add(1)));
+    let copied = io::copy(&mut sha_reader, file)?;
+    let (_, digest) = sha_reader.finalize();
+    io::copy(&mut chunked_reader, &mut io::sink())?;
+    Ok((copied, digest))
+}
+
 fn handle_one_message(
     msg: ArtifactPusherToBroker,
     socket: &mut TcpStream,
     scheduler_sender: &SchedulerSender,
     cache_tmp_path: &Path,
+    blob_store: Option<&BlobStoreWorkers>,
 ) -> Result<()> {
-    let ArtifactPusherToBroker(digest, size) = msg;
+    let ArtifactPusherToBroker(digest, size, compression) = msg;
     let mut tmp = tempfile::Builder::new()
         .prefix(&digest.to_string())
         .suffix(".tar")
         .tempfile_in(cache_tmp_path)?;
-    let fixed_size_reader = FixedSizeReader::new(socket, size);
-    let mut sha_reader = Sha256Reader::new(fixed_size_reader);
-    let copied = io::copy(&mut sha_reader, &mut tmp)?;
-    assert_eq!(copied, size);
-    let (_, actual_digest) = sha_reader.finalize();
+    let socket = ProgressReader::new(socket, || {
+        // If the scheduler is gone, we'll find out when we send it the artifact.
+        let _ = scheduler_sender.send(SchedulerMessage::ArtifactTransferProgress(digest.clone()));
+    });
+    let (copied, actual_digest) = read_body(socket, size, compression, &mut tmp)?;
+    if copied != size {
+        bail!("artifact has {copied} bytes uncompressed, expected {size}");
+    }
     actual_digest.verify(&digest)?;
     let (_, path) = tmp.keep()?;
-    scheduler_sender.send(SchedulerMessage::GotArtifact(digest, size, path))?;
+
+    // The scheduler moves the file into the cache, so give the uploader its own link to it.
+    let upload_path = path.with_extension("upload");
+    if blob_store.is_some() {
+        fs::hard_link(&path, &upload_path)?;
+    }
+    scheduler_sender.send(SchedulerMessage::GotArtifact(digest.clone(), size, path))?;
+    if let Some(blob_store) = blob_store {
+        blob_store.upload(digest, upload_path, size);
+    }
     Ok(())
 }
 
@@ -38,12 +117,19 @@ fn connection_loop(
     mut socket: TcpStream,
     scheduler_sender: &SchedulerSender,
     cache_tmp_path: &Path,
+    blob_store: Option<&BlobStoreWorkers>,
     log: &mut Logger,
 ) -> Result<()> {
     loop {
         let msg = net::read_message_from_socket(&mut socket)?;
         debug!(log, "received artifact pusher message"; "msg" => ?msg);
-        let result = handle_one_message(msg, &mut socket, scheduler_sender, cache_tmp_path);
+        let result = handle_one_message(
+            msg,
+            &mut socket,
+            scheduler_sender,
+            cache_tmp_path,
+            blob_store,
+        );
         let msg = BrokerToArtifactPusher(result.as_ref().map(|_| ()).map_err(|e| e.to_string()));
         debug!(log, "sending artifact pusher message"; "msg" => ?msg);
         net::write_message_to_socket(&mut socket, msg)?;
@@ -55,10 +141,71 @@ pub fn connection_main(
     socket: TcpStream,
     scheduler_sender: SchedulerSender,
     cache_tmp_path: PathBuf,
+    blob_store: Option<BlobStoreWorkers>,
     mut log: Logger,
 ) -> Result<()> {
     debug!(log, "artifact pusher connected");
-    let err = connection_loop(socket, &scheduler_sender, &cache_tmp_path, &mut log).unwrap_err();
+    let err = connection_loop(
+        socket,
+        &scheduler_sender,
+        &cache_tmp_path,
+        blob_store.as_ref(),
+        &mut log,
+    )
+    .unwrap_err();
     debug!(log, "artifact pusher disconnected"; "err" => %err);
     Err(err)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use maelstrom_util::io::{ChunkedWriter, CompressingWriter};
+    use std::io::Write as _;
+
+    fn compress(data: &[u8], compression: ArtifactCompression) -> Vec<u8> {
+        let mut encoded = vec![];
+        let mut writer =
+            CompressingWriter::new(ChunkedWriter::new(&mut encoded, 100), compression).unwrap();
+        writer.write_all(data).unwrap();
+        writer.finish().unwrap().finish().unwrap();
+        encoded
+    }
+
+    fn sha256(data: &[u8]) -> Sha256Digest {
+        let mut reader = Sha256Reader::new(data);
+        io::copy(&mut reader, &mut io::sink()).unwrap();
+        reader.finalize().1
+    }
+
+    #[test]
+    fn read_compressed_body_then_next_message() {
+        let data = b"hello world ".repeat(100);
+        for compression in [ArtifactCompression::Gzip, ArtifactCompression::Zstd] {
+            let mut input = compress(&data, compression);
+            input.extend(b"next");
+            let mut input = &input[..];
+
+            let mut file = vec![];
+            let (size, digest) =
+                read_body(&mut input, data.len() as u64, compression, &mut file).unwrap();
+            assert_eq!(size, data.len() as u64);
+            assert_eq!(digest, sha256(&data));
+            assert_eq!(file, data);
+
+            let mut rest = vec![];
+            input.read_to_end(&mut rest).unwrap();
+            assert_eq!(rest, b"next");
+        }
+    }
+
+    #[test]
+    fn read_compressed_body_larger_than_size() {
+        let data = vec![0; 10_000];
+        let input = compress(&data, ArtifactCompression::Zstd);
+        let mut file = vec![];
+        let (size, _) = read_body(&input[..], 10, ArtifactCompression::Zstd, &mut file).unwrap();
+        assert_eq!(size, 11);
+        assert_eq!(file.len(), 11);
+    }
+}
@@ -1,6 +1,6 @@
 use anyhow::Result;
 use derive_more::From;
-use maelstrom_util::config::{CacheBytesUsedTarget, CacheRoot, LogLevel};
+use maelstrom_util::config::{CacheBytesUsedTarget, CacheRoot, LogFormat, LogLevel};
 use serde::{Deserialize, Serialize};
 use serde_with::skip_serializing_none;
 use std::{
@@ -48,6 +48,64 @@ impl Debug for HttpPort {
     }
 }
 
+#[derive(Deserialize, From)]
+#[serde(from = "u64")]
+pub struct CacheScrubRate(u64);
+
+impl CacheScrubRate {
+    pub fn inner(&self) -> &u64 {
+        &self.0
+    }
+
+    pub fn into_inner(self) -> u64 {
+        self.0
+    }
+}
+
+impl Debug for CacheScrubRate {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        self.0.fmt(f)
+    }
+}
+
+#[derive(Deserialize, From)]
+#[serde(from = "u64")]
+pub struct ArtifactTransferTimeout(u64);
+
+impl ArtifactTransferTimeout {
+    pub fn inner(&self) -> &u64 {
+        &self.0
+    }
+
+    pub fn into_inner(self) -> u64 {
+        self.0
+    }
+}
+
+impl Debug for ArtifactTransferTimeout {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        self.0.fmt(f)
+    }
+}
+
+/// The secret access key used to sign requests to the blob store. It's kept out of debug output,
+/// since the broker logs its configuration.
+#[derive(Clone, Deserialize, From)]
+#[serde(from = "String")]
+pub struct SecretAccessKey(String);
+
+impl SecretAccessKey {
+    pub fn inner(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Debug for SecretAccessKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        f.write_str("<redacted>")
+    }
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct Config {
@@ -64,8 +122,41 @@ pub struct Config {
     /// strictly, so it's best to be conservative.
     pub cache_bytes_used_target: CacheBytesUsedTarget,
 
+    /// The number of bytes per second to spend re-reading cached artifacts to check them for
+    /// corruption. A value of 0 disables scrubbing.
+    pub cache_scrub_rate: CacheScrubRate,
+
+    /// The number of seconds a client has to start pushing an artifact it was asked for, or to make
+    /// more progress pushing it. If it doesn't, the jobs waiting for the artifact fail. A value of
+    /// 0 means clients have as long as they need.
+    pub artifact_transfer_timeout: ArtifactTransferTimeout,
+
+    /// The URL of an S3-compatible bucket to use as a remote blob store behind the cache,
+    /// optionally including a prefix within the bucket.
+    pub blob_store_url: Option<String>,
+
+    /// The region to use when signing requests to the blob store.
+    pub blob_store_region: String,
+
+    /// The access key ID to use when signing requests to the blob store. Requests aren't signed
+    /// if this isn't set.
+    pub blob_store_access_key_id: Option<String>,
+
+    /// The secret access key to use when signing requests to the blob store.
+    pub blob_store_secret_access_key: Option<SecretAccessKey>,
+
     /// Minimum log level to output.
     pub log_level: LogLevel,
+
+    /// The format to write log messages in.
+    pub log_format: LogFormat,
+
+    /// A file to append a trace of each job to, in the OTLP JSON encoding.
+    pub trace_file: Option<PathBuf>,
+
+    /// The URL of an OTLP collector to send a trace of each job to. Only `http://` and `https://`
+    /// URLs are supported.
+    pub otlp_endpoint: Option<String>,
 }
 
 #[skip_serializing_none]
@@ -75,7 +166,15 @@ pub struct ConfigOptions {
     pub http_port: Option<u16>,
     pub cache_root: Option<PathBuf>,
     pub cache_bytes_used_target: Option<u64>,
+    pub cache_scrub_rate: Option<u64>,
+    pub artifact_transfer_timeout: Option<u64>,
+    pub blob_store_url: Option<String>,
+    pub blob_store_region: Option<String>,
+    pub blob_store_access_key_id: Option<String>,
     pub log_level: Option<LogLevel>,
+    pub log_format: Option<LogFormat>,
+    pub trace_file: Option<PathBuf>,
+    pub otlp_endpoint: Option<String>,
 }
 
 impl Default for ConfigOptions {
@@ -85,7 +184,15 @@ impl Default for ConfigOptions {
             http_port: Some(0),
             cache_root: Some(".cache/maelstrom-broker".into()),
             cache_bytes_used_target: Some(1_000_000_000),
+            cache_scrub_rate: Some(10_000_000),
+            artifact_transfer_timeout: Some(600),
+            blob_store_url: None,
+            blob_store_region: Some("us-east-1".into()),
+            blob_store_access_key_id: None,
             log_level: Some(LogLevel::Info),
+            log_format: Some(LogFormat::Text),
+            trace_file: None,
+            otlp_endpoint: None,
         }
     }
 }
@@ -1,14 +1,18 @@
 use crate::{
     artifact_fetcher, artifact_pusher,
+    blob_store::BlobStoreWorkers,
     scheduler_task::{SchedulerMessage, SchedulerSender},
     IdVendor,
 };
 use anyhow::Result;
-use maelstrom_base::{proto::Hello, ClientId, WorkerId};
+use maelstrom_base::{
+    proto::{Hello, WorkerWelcome},
+    ClientId, WorkerId,
+};
 use maelstrom_util::net;
 use serde::Serialize;
 use slog::{debug, error, info, o, warn, Logger};
-use std::{future::Future, path::PathBuf, sync::Arc, thread};
+use std::{future::Future, net::SocketAddr, path::PathBuf, sync::Arc, thread};
 use tokio::{
     io::BufReader,
     net::{TcpListener, TcpStream},
@@ -96,11 +100,19 @@ pub async fn connection_main<IdT, FromSchedulerMessageT, ReaderFutureT, WriterFu
     scheduler_sender.send(disconnected_msg_builder(id)).ok();
 }
 
+/// Return the address a worker serves artifacts to other workers on, if it does. It's the address
+/// the worker connected to us from, with its `peer_port`.
+fn peer_server_addr(peer_addr: SocketAddr, peer_port: Option<u16>) -> Option<SocketAddr> {
+    peer_port.map(|peer_port| SocketAddr::new(peer_addr.ip(), peer_port))
+}
+
 async fn unassigned_connection_main(
     mut socket: TcpStream,
     scheduler_sender: SchedulerSender,
     id_vendor: Arc<IdVendor>,
     cache_tmp_path: PathBuf,
+    blob_store: Option<BlobStoreWorkers>,
+    broker_nonce: u64,
     log: Logger,
 ) {
     match net::read_message_from_async_socket(&mut socket).await {
@@ -127,16 +139,28 @@ async fn unassigned_connection_main(
             .await;
             debug!(log, "client disconnected");
         }
-        Ok(Hello::Worker { slots }) => {
-            let (read_stream, write_stream) = socket.into_split();
-            let read_stream = BufReader::new(read_stream);
+        Ok(Hello::Worker { slots, peer_port }) => {
             let id: WorkerId = id_vendor.vend();
             let log = log.new(o!("wid" => id.to_string(), "slots" => slots));
+            let peer_server_addr = match socket.peer_addr() {
+                Ok(peer_addr) => peer_server_addr(peer_addr, peer_port),
+                Err(err) => {
+                    warn!(log, "error getting worker's address"; "err" => %err);
+                    return;
+                }
+            };
+            let welcome = WorkerWelcome { broker_nonce };
+            if let Err(err) = net::write_message_to_async_socket(&mut socket, welcome).await {
+                warn!(log, "error writing welcome message"; "err" => %err);
+                return;
+            }
+            let (read_stream, write_stream) = socket.into_split();
+            let read_stream = BufReader::new(read_stream);
             info!(log, "worker connected");
             let log_clone = log.clone();
             let log_clone2 = log.clone();
             connection_main(
-                scheduler_sender,
+                scheduler_sender.clone(),
                 id,
                 |id, sender| SchedulerMessage::WorkerConnected(id, slots as usize, sender),
                 SchedulerMessage::WorkerDisconnected,
@@ -153,14 +177,21 @@ async fn unassigned_connection_main(
                 },
             )
             .await;
+            // Whatever the worker was serving to other workers went away with it.
+            if let Some(peer_server_addr) = peer_server_addr {
+                scheduler_sender
+                    .send(SchedulerMessage::PeerDisconnected(peer_server_addr))
+                    .ok();
+            }
             info!(log, "worker disconnected");
         }
-        Ok(Hello::ArtifactFetcher) => {
+        Ok(Hello::ArtifactFetcher { peer_port }) => {
             let log = log.clone();
             let socket = socket.into_std().unwrap();
             socket.set_nonblocking(false).unwrap();
             thread::spawn(move || -> Result<()> {
-                artifact_fetcher::connection_main(socket, scheduler_sender, log)
+                let peer_server_addr = peer_server_addr(socket.peer_addr()?, peer_port);
+                artifact_fetcher::connection_main(socket, peer_server_addr, scheduler_sender, log)
             });
         }
         Ok(Hello::ArtifactPusher) => {
@@ -168,7 +199,13 @@ async fn unassigned_connection_main(
             let socket = socket.into_std().unwrap();
             socket.set_nonblocking(false).unwrap();
             thread::spawn(move || -> Result<()> {
-                artifact_pusher::connection_main(socket, scheduler_sender, cache_tmp_path, log)
+                artifact_pusher::connection_main(
+                    socket,
+                    scheduler_sender,
+                    cache_tmp_path,
+                    blob_store,
+                    log,
+                )
             });
         }
         Err(err) => {
@@ -185,6 +222,8 @@ pub async fn listener_main(
     scheduler_sender: SchedulerSender,
     id_vendor: Arc<IdVendor>,
     cache_tmp_path: PathBuf,
+    blob_store: Option<BlobStoreWorkers>,
+    broker_nonce: u64,
     log: Logger,
 ) {
     loop {
@@ -197,6 +236,8 @@ pub async fn listener_main(
                     scheduler_sender.clone(),
                     id_vendor.clone(),
                     cache_tmp_path.clone(),
+                    blob_store.clone(),
+                    broker_nonce,
                     log,
                 ));
             }
@@ -6,12 +6,14 @@
 //! a tar file. The tar file is then embedded in this module as compile time.
 //!
 //! Second, it handles WebSockets. These are treated just like client connections.
+//!
+//! Third, it serves the broker's metrics at `/metrics`, for Prometheus to scrape.
 use crate::{
-    connection,
+    connection, metrics,
     scheduler_task::{SchedulerMessage, SchedulerSender},
     IdVendor,
 };
-use anyhow::{Error, Result};
+use anyhow::{anyhow, Error, Result};
 use futures::{
     sink::SinkExt,
     stream::{SplitSink, SplitStream, StreamExt},
@@ -22,6 +24,7 @@ use maelstrom_base::{
     proto::{self, BrokerToClient},
     ClientId,
 };
+use maelstrom_util::metrics::CONTENT_TYPE;
 use maelstrom_web::WASM_TAR;
 use slog::{debug, error, o, Logger};
 use std::{
@@ -33,7 +36,10 @@ use std::{
     task::{Context, Poll},
 };
 use tar::Archive;
-use tokio::{net::TcpListener, sync::mpsc::UnboundedReceiver};
+use tokio::{
+    net::TcpListener,
+    sync::mpsc::{self, UnboundedReceiver},
+};
 use tungstenite::Message;
 
 pub struct TarHandler {
@@ -161,6 +167,26 @@ async fn websocket_main(
     debug!(log, "received websocket client disconnect")
 }
 
+/// Ask the scheduler for its metrics and render them.
+async fn metrics_response(
+    scheduler_sender: SchedulerSender,
+    log: Logger,
+) -> Result<Response<Body>> {
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    scheduler_sender
+        .send(SchedulerMessage::MetricsRequest(sender))
+        .map_err(|_| anyhow!("scheduler exited"))?;
+    let metrics = receiver
+        .recv()
+        .await
+        .ok_or_else(|| anyhow!("scheduler exited"))?;
+    debug!(log, "received http get request"; "path" => "/metrics", "resp" => 200);
+    Ok(Response::builder()
+        .status(200)
+        .header("Content-Type", CONTENT_TYPE)
+        .body(Body::from(metrics::encode(&metrics)))?)
+}
+
 struct Handler {
     tar_handler: Arc<TarHandler>,
     scheduler_sender: SchedulerSender,
@@ -178,6 +204,13 @@ impl Service<Request<Body>> for Handler {
     }
 
     fn call(&mut self, mut request: Request<Body>) -> Self::Future {
+        if request.uri().path() == "/metrics" {
+            return Box::pin(metrics_response(
+                self.scheduler_sender.clone(),
+                self.log.clone(),
+            ));
+        }
+
         let resp = (|| {
             if hyper_tungstenite::is_upgrade_request(&request) {
                 let (response, websocket) = hyper_tungstenite::upgrade(&mut request, None)?;
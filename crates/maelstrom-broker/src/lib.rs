@@ -2,18 +2,28 @@
 
 mod artifact_fetcher;
 mod artifact_pusher;
+pub mod blob_store;
 pub mod config;
 mod connection;
 mod http;
+mod metrics;
 mod scheduler_task;
 
+use blob_store::{BlobStore, BlobStoreWorkers, BLOB_STORE_WORKER_THREADS};
+use config::{ArtifactTransferTimeout, CacheScrubRate};
 use maelstrom_base::stats::BROKER_STATISTICS_INTERVAL;
-use maelstrom_util::config::{CacheBytesUsedTarget, CacheRoot};
+use maelstrom_util::{
+    config::{CacheBytesUsedTarget, CacheRoot},
+    trace::JobTracer,
+};
 use scheduler_task::{SchedulerMessage, SchedulerSender, SchedulerTask};
 use slog::{error, Logger};
-use std::sync::{
-    atomic::{AtomicU32, Ordering},
-    Arc,
+use std::{
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+    time::Duration,
 };
 use tokio::{
     net::TcpListener,
@@ -50,17 +60,60 @@ async fn stats_heartbeat(sender: SchedulerSender) {
     }
 }
 
+const CACHE_SCRUB_INTERVAL: Duration = Duration::from_millis(100);
+
+/// "Main loop" for the cache scrubber. Every [`CACHE_SCRUB_INTERVAL`], this tells the scheduler to
+/// scrub however many bytes of the cache it takes to keep up with `rate`.
+async fn cache_scrubber(sender: SchedulerSender, rate: u64) {
+    let bytes = (rate as u128 * CACHE_SCRUB_INTERVAL.as_millis() / 1000).max(1) as u64;
+    let mut interval = tokio::time::interval(CACHE_SCRUB_INTERVAL);
+    while sender.send(SchedulerMessage::ScrubCache(bytes)).is_ok() {
+        interval.tick().await;
+    }
+}
+
+const ARTIFACT_TRANSFER_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// "Main loop" for the artifact transfer checker. Every [`ARTIFACT_TRANSFER_CHECK_INTERVAL`], this
+/// tells the scheduler to fail the jobs waiting for artifacts that clients didn't push in time.
+async fn artifact_transfer_checker(sender: SchedulerSender) {
+    let mut interval = tokio::time::interval(ARTIFACT_TRANSFER_CHECK_INTERVAL);
+    while sender
+        .send(SchedulerMessage::CheckArtifactTransfers)
+        .is_ok()
+    {
+        interval.tick().await;
+    }
+}
+
 /// The main function for the broker. This should be called on a task of its own. It will return
 /// when a signal is received, or when the broker or http listener socket returns an error at
 /// accept time.
+#[allow(clippy::too_many_arguments)]
 pub async fn main(
     listener: TcpListener,
     http_listener: TcpListener,
     cache_root: CacheRoot,
     cache_bytes_used_target: CacheBytesUsedTarget,
+    cache_scrub_rate: CacheScrubRate,
+    artifact_transfer_timeout: ArtifactTransferTimeout,
+    blob_store: Option<Arc<dyn BlobStore>>,
+    tracer: JobTracer,
     log: Logger,
 ) {
-    let scheduler_task = SchedulerTask::new(cache_root, cache_bytes_used_target, log.clone());
+    let broker_nonce = tracer.nonce();
+    let blob_store = blob_store
+        .map(|store| BlobStoreWorkers::new(store, BLOB_STORE_WORKER_THREADS, log.clone()));
+    let artifact_transfer_timeout = (*artifact_transfer_timeout.inner() > 0)
+        .then(|| Duration::from_secs(artifact_transfer_timeout.into_inner()));
+    let scheduler_task = SchedulerTask::new(
+        cache_root,
+        cache_bytes_used_target,
+        artifact_transfer_timeout,
+        blob_store.clone(),
+        tracer,
+        log.clone(),
+    );
     let id_vendor = Arc::new(IdVendor {
         id: AtomicU32::new(0),
     });
@@ -78,9 +131,22 @@ pub async fn main(
         scheduler_task.scheduler_sender().clone(),
         id_vendor,
         scheduler_task.cache_tmp_path().to_owned(),
+        blob_store,
+        broker_nonce,
         log.clone(),
     ));
     join_set.spawn(stats_heartbeat(scheduler_task.scheduler_sender().clone()));
+    if *cache_scrub_rate.inner() > 0 {
+        join_set.spawn(cache_scrubber(
+            scheduler_task.scheduler_sender().clone(),
+            cache_scrub_rate.into_inner(),
+        ));
+    }
+    if artifact_transfer_timeout.is_some() {
+        join_set.spawn(artifact_transfer_checker(
+            scheduler_task.scheduler_sender().clone(),
+        ));
+    }
     join_set.spawn(scheduler_task.run());
     join_set.spawn(signal_handler(
         SignalKind::interrupt(),
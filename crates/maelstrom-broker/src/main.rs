@@ -5,15 +5,21 @@ use figment::{
     providers::{Env, Format, Serialized, Toml},
     Figment,
 };
-use maelstrom_broker::config::{Config, ConfigOptions};
-use maelstrom_util::config::LogLevel;
-use slog::{info, o, Drain, Level, LevelFilter, Logger};
-use slog_async::Async;
-use slog_term::{FullFormat, TermDecorator};
+use maelstrom_broker::{
+    blob_store::{BlobStore, S3BlobStore, S3Credentials},
+    config::{Config, ConfigOptions},
+};
+use maelstrom_util::{
+    config::{LogFormat, LogLevel},
+    log,
+    trace::JobTracer,
+};
+use slog::info;
 use std::{
     net::{Ipv6Addr, SocketAddrV6},
     path::PathBuf,
     process,
+    sync::Arc,
 };
 use tokio::{net::TcpListener, runtime::Runtime};
 
@@ -59,9 +65,48 @@ struct CliOptions {
     #[arg(long, short = 'B', value_name = "BYTES")]
     cache_bytes_used_target: Option<u64>,
 
+    /// The number of bytes per second to spend re-reading cached artifacts to check them for
+    /// corruption. A value of 0 disables scrubbing.
+    #[arg(long, value_name = "BYTES")]
+    cache_scrub_rate: Option<u64>,
+
+    /// The number of seconds a client has to start pushing an artifact it was asked for, or to make
+    /// more progress pushing it. If it doesn't, the jobs waiting for the artifact fail. A value of
+    /// 0 means clients have as long as they need.
+    #[arg(long, value_name = "SECONDS")]
+    artifact_transfer_timeout: Option<u64>,
+
+    /// The URL of an S3-compatible bucket to use as a remote blob store behind the cache,
+    /// optionally including a prefix within the bucket. The secret access key can only be set in
+    /// the configuration file or through the environment.
+    #[arg(long, value_name = "URL")]
+    blob_store_url: Option<String>,
+
+    /// The region to use when signing requests to the blob store.
+    #[arg(long, value_name = "REGION")]
+    blob_store_region: Option<String>,
+
+    /// The access key ID to use when signing requests to the blob store. Requests aren't signed if
+    /// this isn't set.
+    #[arg(long, value_name = "ID")]
+    blob_store_access_key_id: Option<String>,
+
     /// Minimum log level to output.
     #[arg(long, short, value_name = "LEVEL", value_enum)]
     log_level: Option<LogLevel>,
+
+    /// The format to write log messages in.
+    #[arg(long, value_name = "FORMAT", value_enum)]
+    log_format: Option<LogFormat>,
+
+    /// A file to append a trace of each job to, in the OTLP JSON encoding.
+    #[arg(long, value_name = "PATH")]
+    trace_file: Option<PathBuf>,
+
+    /// The URL of an OTLP collector to send a trace of each job to. Only http:// and https:// URLs
+    /// are supported.
+    #[arg(long, value_name = "URL")]
+    otlp_endpoint: Option<String>,
 }
 
 impl Default for CliOptions {
@@ -73,7 +118,15 @@ impl Default for CliOptions {
             http_port: Some(0),
             cache_root: Some(".cache/maelstrom-broker".into()),
             cache_bytes_used_target: Some(1_000_000_000),
+            cache_scrub_rate: Some(10_000_000),
+            artifact_transfer_timeout: Some(600),
+            blob_store_url: None,
+            blob_store_region: Some("us-east-1".into()),
+            blob_store_access_key_id: None,
             log_level: Some(LogLevel::Info),
+            log_format: Some(LogFormat::Text),
+            trace_file: None,
+            otlp_endpoint: None,
         }
     }
 }
@@ -85,7 +138,15 @@ impl CliOptions {
             http_port: self.http_port,
             cache_root: self.cache_root.clone(),
             cache_bytes_used_target: self.cache_bytes_used_target,
+            cache_scrub_rate: self.cache_scrub_rate,
+            artifact_transfer_timeout: self.artifact_transfer_timeout,
+            blob_store_url: self.blob_store_url.clone(),
+            blob_store_region: self.blob_store_region.clone(),
+            blob_store_access_key_id: self.blob_store_access_key_id.clone(),
             log_level: self.log_level,
+            log_format: self.log_format,
+            trace_file: self.trace_file.clone(),
+            otlp_endpoint: self.otlp_endpoint.clone(),
         }
     }
 }
@@ -112,17 +173,34 @@ fn main() -> Result<()> {
         println!("{config:#?}");
         return Ok(());
     }
-    let decorator = TermDecorator::new().build();
-    let drain = FullFormat::new(decorator).build().fuse();
-    let drain = Async::new(drain).build().fuse();
-    let level = match config.log_level {
-        LogLevel::Error => Level::Error,
-        LogLevel::Warning => Level::Warning,
-        LogLevel::Info => Level::Info,
-        LogLevel::Debug => Level::Debug,
+    let log = log::root_logger(config.log_level, config.log_format);
+    let tracer = JobTracer::new(
+        "maelstrom-broker",
+        None,
+        rand::random(),
+        config.trace_file.clone(),
+        config.otlp_endpoint.as_deref(),
+        log.clone(),
+    )
+    .context("starting job tracer")?;
+    let blob_store: Option<Arc<dyn BlobStore>> = match &config.blob_store_url {
+        None => None,
+        Some(url) => {
+            let credentials = match &config.blob_store_access_key_id {
+                None => None,
+                Some(access_key_id) => Some(S3Credentials {
+                    access_key_id: access_key_id.clone(),
+                    secret_access_key: config.blob_store_secret_access_key.clone().context(
+                        "blob_store_secret_access_key must be set with blob_store_access_key_id",
+                    )?,
+                    region: config.blob_store_region.clone(),
+                }),
+            };
+            Some(Arc::new(
+                S3BlobStore::new(url, credentials).context("creating blob store")?,
+            ))
+        }
     };
-    let drain = LevelFilter::new(drain, level).fuse();
-    let log = Logger::root(drain, o!());
     Runtime::new()
         .context("starting tokio runtime")?
         .block_on(async {
@@ -154,6 +232,10 @@ fn main() -> Result<()> {
                 http_listener,
                 config.cache_root,
                 config.cache_bytes_used_target,
+                config.cache_scrub_rate,
+                config.artifact_transfer_timeout,
+                blob_store,
+                tracer,
                 log.clone(),
             )
             .await;
@@ -0,0 +1,186 @@
+//! Render the scheduler's metrics as the text served by the HTTP server at `/metrics`, for
+//! Prometheus to scrape.
+
+use crate::scheduler_task::SchedulerMetrics;
+use maelstrom_base::stats::JobState;
+use maelstrom_util::metrics::{Encoder, MetricType};
+
+fn job_state_label(state: JobState) -> &'static str {
+    match state {
+        JobState::WaitingForArtifacts => "waiting_for_artifacts",
+        JobState::Pending => "pending",
+        JobState::Running => "running",
+        JobState::Complete => "complete",
+    }
+}
+
+pub fn encode(metrics: &SchedulerMetrics) -> String {
+    let mut encoder = Encoder::new();
+
+    encoder.family(
+        "maelstrom_broker_jobs",
+        MetricType::Gauge,
+        "Outstanding jobs, by state.",
+    );
+    for state in JobState::iter().filter(|state| *state != JobState::Complete) {
+        encoder.sample(
+            "maelstrom_broker_jobs",
+            &[("state", job_state_label(state))],
+            metrics.job_state_counts[state],
+        );
+    }
+    encoder.counter(
+        "maelstrom_broker_jobs_completed",
+        "Jobs completed since the broker started.",
+        metrics.job_state_counts[JobState::Complete],
+    );
+    encoder.gauge(
+        "maelstrom_broker_queue_depth",
+        "Jobs that have all of their artifacts and are waiting for a worker.",
+        metrics.job_state_counts[JobState::Pending],
+    );
+
+    encoder.gauge(
+        "maelstrom_broker_workers",
+        "Connected workers.",
+        metrics.workers.len(),
+    );
+    encoder.family(
+        "maelstrom_broker_worker_slots",
+        MetricType::Gauge,
+        "Job slots of each worker.",
+    );
+    for (wid, worker) in &metrics.workers {
+        let wid = wid.to_string();
+        encoder.sample(
+            "maelstrom_broker_worker_slots",
+            &[("worker", &wid)],
+            worker.slots,
+        );
+    }
+    encoder.family(
+        "maelstrom_broker_worker_jobs",
+        MetricType::Gauge,
+        "Jobs sent to each worker that it hasn't finished. This can be up to twice its slots.",
+    );
+    for (wid, worker) in &metrics.workers {
+        let wid = wid.to_string();
+        encoder.sample(
+            "maelstrom_broker_worker_jobs",
+            &[("worker", &wid)],
+            worker.jobs,
+        );
+    }
+
+    encoder.gauge(
+        "maelstrom_broker_cache_entries",
+        "Artifacts in the cache.",
+        metrics.cache.entries,
+    );
+    encoder.gauge(
+        "maelstrom_broker_cache_bytes_used",
+        "Bytes used by artifacts in the cache.",
+        metrics.cache.bytes_used,
+    );
+    encoder.counter(
+        "maelstrom_broker_cache_hits",
+        "Requests by jobs for artifacts that were in the cache.",
+        metrics.cache.hits,
+    );
+    encoder.counter(
+        "maelstrom_broker_cache_misses",
+        "Requests by jobs for artifacts that weren't in the cache.",
+        metrics.cache.misses,
+    );
+    encoder.counter(
+        "maelstrom_broker_cache_evictions",
+        "Artifacts removed to keep the cache under its target size.",
+        metrics.cache.evictions,
+    );
+
+    encoder.counter(
+        "maelstrom_broker_artifact_received_bytes",
+        "Uncompressed bytes of artifacts received from clients or the blob store.",
+        metrics.artifact_bytes_received,
+    );
+    encoder.counter(
+        "maelstrom_broker_artifact_sent_bytes",
+        "Uncompressed bytes of artifacts sent to workers.",
+        metrics.artifact_bytes_sent,
+    );
+    encoder.counter(
+        "maelstrom_broker_artifact_peer_redirects",
+        "Artifact fetches sent to another worker instead of being served by the broker.",
+        metrics.artifact_peer_redirects,
+    );
+
+    encoder.histogram(
+        "maelstrom_broker_job_wait_seconds",
+        "Time from a job being requested until it was first sent to a worker.",
+        &metrics.job_wait_seconds,
+    );
+    encoder.histogram(
+        "maelstrom_broker_job_latency_seconds",
+        "Time from a job being requested until it completed.",
+        &metrics.job_latency_seconds,
+    );
+    encoder.histogram(
+        "maelstrom_broker_job_run_seconds",
+        "Time from a job last being sent to a worker until it completed.",
+        &metrics.job_run_seconds,
+    );
+
+    encoder.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scheduler_task::WorkerMetrics;
+    use maelstrom_test::*;
+    use maelstrom_util::metrics::Histogram;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn encode_metrics() {
+        let mut metrics = SchedulerMetrics {
+            job_state_counts: Default::default(),
+            workers: BTreeMap::from([
+                (wid![1], WorkerMetrics { slots: 4, jobs: 6 }),
+                (wid![2], WorkerMetrics { slots: 2, jobs: 1 }),
+            ]),
+            cache: Default::default(),
+            artifact_bytes_received: 0,
+            artifact_bytes_sent: 1000,
+            artifact_peer_redirects: 0,
+            job_wait_seconds: Histogram::default(),
+            job_latency_seconds: Histogram::default(),
+            job_run_seconds: Histogram::default(),
+        };
+        metrics.job_state_counts[JobState::Running] = 7;
+        metrics.job_state_counts[JobState::Complete] = 12;
+        metrics.job_latency_seconds.observe(0.75);
+        let text = encode(&metrics);
+
+        let lines = Vec::from_iter(text.lines());
+        for expected in [
+            "maelstrom_broker_jobs{state=\"waiting_for_artifacts\"} 0",
+            "maelstrom_broker_jobs{state=\"running\"} 7",
+            "maelstrom_broker_jobs_completed_total 12",
+            "maelstrom_broker_workers 2",
+            "maelstrom_broker_worker_slots{worker=\"1\"} 4",
+            "maelstrom_broker_worker_jobs{worker=\"2\"} 1",
+            "maelstrom_broker_artifact_sent_bytes_total 1000",
+            "maelstrom_broker_job_latency_seconds_bucket{le=\"0.5\"} 0",
+            "maelstrom_broker_job_latency_seconds_bucket{le=\"1\"} 1",
+            "maelstrom_broker_job_latency_seconds_count 1",
+        ] {
+            assert!(
+                lines.contains(&expected),
+                "missing {expected:?} in:\n{text}"
+            );
+        }
+        assert!(!text.contains("state=\"complete\""));
+        assert_eq!(lines.last(), Some(&"# EOF"));
+    }
+}
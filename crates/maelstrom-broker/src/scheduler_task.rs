@@ -1,22 +1,38 @@
 mod cache;
+mod peers;
 mod scheduler;
 
+use crate::blob_store::BlobStoreWorkers;
 use cache::{Cache, GetArtifactForWorkerError, StdCacheFs};
-use maelstrom_base::proto::{BrokerToClient, BrokerToWorker};
+use maelstrom_base::{
+    proto::{BrokerToClient, BrokerToWorker},
+    JobId, Sha256Digest,
+};
 use maelstrom_util::{
     config::{CacheBytesUsedTarget, CacheRoot},
+    io::Sha256Reader,
     sync,
+    trace::{JobEvent, JobTracer},
 };
+pub use scheduler::{ArtifactForWorker, SchedulerMetrics, WorkerMetrics};
 use scheduler::{Message, Scheduler, SchedulerDeps};
 use slog::Logger;
 use std::{
+    fs, io,
     path::{Path, PathBuf},
     sync::mpsc as std_mpsc,
+    thread,
+    time::{Duration, Instant, SystemTime},
 };
 use tokio::sync::mpsc as tokio_mpsc;
 
-#[derive(Debug)]
-pub struct PassThroughDeps;
+pub struct PassThroughDeps {
+    blob_store: Option<BlobStoreWorkers>,
+    sender: tokio_mpsc::WeakUnboundedSender<SchedulerMessage>,
+    cache_tmp_path: PathBuf,
+    tracer: JobTracer,
+    log: Logger,
+}
 
 /// The production implementation of [SchedulerDeps]. This implementation just hands the
 /// message to the provided sender.
@@ -24,7 +40,8 @@ impl SchedulerDeps for PassThroughDeps {
     type ClientSender = tokio_mpsc::UnboundedSender<BrokerToClient>;
     type WorkerSender = tokio_mpsc::UnboundedSender<BrokerToWorker>;
     type WorkerArtifactFetcherSender =
-        std_mpsc::Sender<Result<(PathBuf, u64), GetArtifactForWorkerError>>;
+        std_mpsc::Sender<Result<ArtifactForWorker, GetArtifactForWorkerError>>;
+    type MetricsSender = tokio_mpsc::UnboundedSender<SchedulerMetrics>;
 
     fn send_message_to_client(&mut self, sender: &mut Self::ClientSender, message: BrokerToClient) {
         sender.send(message).ok();
@@ -37,10 +54,52 @@ impl SchedulerDeps for PassThroughDeps {
     fn send_message_to_worker_artifact_fetcher(
         &mut self,
         sender: &mut Self::WorkerArtifactFetcherSender,
-        message: Result<(PathBuf, u64), GetArtifactForWorkerError>,
+        message: Result<ArtifactForWorker, GetArtifactForWorkerError>,
     ) {
         sender.send(message).ok();
     }
+
+    fn fetch_artifact_from_blob_store(&mut self, digest: Sha256Digest) {
+        let Some(sender) = self.sender.upgrade() else {
+            return;
+        };
+        self.blob_store
+            .as_ref()
+            .unwrap()
+            .fetch(digest, self.cache_tmp_path.clone(), sender);
+    }
+
+    fn scrub_artifact(&mut self, digest: Sha256Digest, path: PathBuf) {
+        let Some(sender) = self.sender.upgrade() else {
+            return;
+        };
+        thread::spawn(move || {
+            let result = hash_file(&path).map_err(|err| err.to_string());
+            sender.send(Message::ScrubbedArtifact(digest, result)).ok();
+        });
+    }
+
+    fn send_metrics(&mut self, sender: &mut Self::MetricsSender, metrics: SchedulerMetrics) {
+        sender.send(metrics).ok();
+    }
+
+    fn now(&mut self) -> Instant {
+        Instant::now()
+    }
+
+    fn system_time(&mut self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn job_event(&mut self, jid: JobId, event: JobEvent) {
+        self.tracer.event(jid, event);
+    }
+}
+
+fn hash_file(path: &Path) -> io::Result<Sha256Digest> {
+    let mut reader = Sha256Reader::new(fs::File::open(path)?);
+    io::copy(&mut reader, &mut io::sink())?;
+    Ok(reader.finalize().1)
 }
 
 /// The production scheduler message type. Some [Message] arms contain a
@@ -53,6 +112,7 @@ pub type SchedulerSender = tokio_mpsc::UnboundedSender<SchedulerMessage>;
 
 pub struct SchedulerTask {
     scheduler: Scheduler<Cache<StdCacheFs>, PassThroughDeps>,
+    deps: PassThroughDeps,
     sender: SchedulerSender,
     receiver: tokio_mpsc::UnboundedReceiver<SchedulerMessage>,
     cache_tmp_path: PathBuf,
@@ -62,13 +122,28 @@ impl SchedulerTask {
     pub fn new(
         cache_root: CacheRoot,
         cache_bytes_used_target: CacheBytesUsedTarget,
+        artifact_transfer_timeout: Option<Duration>,
+        blob_store: Option<BlobStoreWorkers>,
+        tracer: JobTracer,
         log: Logger,
     ) -> Self {
         let (sender, receiver) = tokio_mpsc::unbounded_channel();
-        let cache = Cache::new(StdCacheFs::new(), cache_root, cache_bytes_used_target, log);
+        let cache = Cache::new(
+            StdCacheFs::new(),
+            cache_root,
+            cache_bytes_used_target,
+            log.clone(),
+        );
         let cache_tmp_path = cache.tmp_path();
         SchedulerTask {
-            scheduler: Scheduler::new(cache),
+            scheduler: Scheduler::new(cache, blob_store.is_some(), artifact_transfer_timeout),
+            deps: PassThroughDeps {
+                blob_store,
+                sender: sender.downgrade(),
+                cache_tmp_path: cache_tmp_path.clone(),
+                tracer,
+                log,
+            },
             sender,
             receiver,
             cache_tmp_path,
@@ -96,7 +171,7 @@ impl SchedulerTask {
     /// give us a way to return an error, for precisely this reason.
     pub async fn run(mut self) {
         sync::channel_reader(self.receiver, |msg| {
-            self.scheduler.receive_message(&mut PassThroughDeps, msg)
+            self.scheduler.receive_message(&mut self.deps, msg)
         })
         .await;
     }
@@ -6,14 +6,16 @@
 
 use anyhow::{anyhow, bail, Result};
 use bytesize::ByteSize;
-use maelstrom_base::{manifest::ManifestReader, ClientId, JobId, Sha256Digest};
+use maelstrom_base::{
+    manifest::ManifestReader, stats::ScrubStatistics, ClientId, JobId, Sha256Digest,
+};
 use maelstrom_util::{
     config::{CacheBytesUsedTarget, CacheRoot},
     heap::{Heap, HeapDeps, HeapIndex},
 };
-use slog::debug;
+use slog::{debug, warn};
 use std::{
-    collections::{hash_map, HashMap, HashSet},
+    collections::{hash_map, HashMap, HashSet, VecDeque},
     error::Error,
     fmt::{self, Debug, Display, Formatter},
     io,
@@ -215,11 +217,51 @@ fn try_read_cache_file(fs: &mut impl CacheFs, path: &Path) -> Result<(Sha256Dige
     Ok((digest, size))
 }
 
+/// The most corrupt artifacts kept in the `quarantine` directory. When another one is quarantined,
+/// the oldest one is removed.
+pub const MAX_QUARANTINED_ARTIFACTS: usize = 10;
+
+/// The state of the cache's scrubber, which re-hashes cached artifacts one at a time to check that
+/// they still match their digests. See [`Cache::scrub`].
+#[derive(Default)]
+struct Scrubber {
+    /// The artifacts left to scrub in the current pass.
+    pending: Vec<Sha256Digest>,
+    /// The artifacts the current pass came to while they were in use. Each is put back in
+    /// `pending` once it's released.
+    in_use: HashSet<Sha256Digest>,
+    /// Whether any artifact has been hashed in the current pass.
+    hashed_this_pass: bool,
+    /// Whether an artifact is being hashed. Only one is hashed at a time.
+    hashing: bool,
+    /// The artifact being hashed. This is cleared if the artifact is removed from the cache before
+    /// it's done, so that the result is ignored.
+    current: Option<Sha256Digest>,
+    /// How many more bytes may be hashed before the scrubber has to wait for more. This goes
+    /// negative when an artifact larger than what's left is started.
+    budget: i64,
+    statistics: ScrubStatistics,
+}
+
+/// A snapshot of the cache's size, and counts of what has happened to it since the broker started.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CacheMetrics {
+    /// The number of artifacts in the cache.
+    pub entries: u64,
+    pub bytes_used: u64,
+    /// The number of times a job asked for an artifact that was in the cache.
+    pub hits: u64,
+    /// The number of times a job asked for an artifact that wasn't in the cache.
+    pub misses: u64,
+    /// The number of artifacts removed to keep the cache under its target size.
+    pub evictions: u64,
+}
+
 /// The actual cache.
 ///
 /// Two caches shouldn't use the same working directory simultaneously. However, one cache can use
 /// a previous cache's directory. In other words, the directory persists across cache lifetimes.
-pub struct Cache<FsT> {
+pub struct Cache<FsT: CacheFs> {
     fs: FsT,
     root: PathBuf,
     entries: CacheMap,
@@ -227,6 +269,11 @@ pub struct Cache<FsT> {
     next_priority: u64,
     bytes_used: u64,
     bytes_used_target: u64,
+    scrubber: Scrubber,
+    quarantined: VecDeque<PathBuf>,
+    hits: u64,
+    misses: u64,
+    evictions: u64,
     log: slog::Logger,
 }
 
@@ -253,6 +300,11 @@ impl<FsT: CacheFs> Cache<FsT> {
         }
         path.pop();
 
+        path.push("quarantine");
+        fs.mkdir_recursively(&path);
+        let quarantined = VecDeque::from_iter(fs.read_dir(&path));
+        path.pop();
+
         let mut result = Cache {
             fs,
             root,
@@ -261,6 +313,11 @@ impl<FsT: CacheFs> Cache<FsT> {
             next_priority: 0,
             bytes_used: 0,
             bytes_used_target: bytes_used_target.into_inner(),
+            scrubber: Scrubber::default(),
+            quarantined,
+            hits: 0,
+            misses: 0,
+            evictions: 0,
             log,
         };
 
@@ -284,6 +341,7 @@ impl<FsT: CacheFs> Cache<FsT> {
             result.fs.remove_file(&child);
         }
         result.possibly_remove_some();
+        result.possibly_remove_some_quarantined();
 
         debug!(result.log, "cache starting";
             "entries" => %result.entries.len(),
@@ -304,6 +362,7 @@ impl<FsT: CacheFs> Cache<FsT> {
             .or_insert(CacheEntry::Waiting(Vec::default(), HashSet::default()));
         match entry {
             CacheEntry::Waiting(requests, clients) => {
+                self.misses += 1;
                 requests.push(jid);
                 if clients.insert(jid.cid) {
                     GetArtifact::Get
@@ -312,6 +371,7 @@ impl<FsT: CacheFs> Cache<FsT> {
                 }
             }
             CacheEntry::InUse { refcount, .. } => {
+                self.hits += 1;
                 *refcount = refcount.checked_add(1).unwrap();
                 GetArtifact::Success
             }
@@ -326,6 +386,7 @@ impl<FsT: CacheFs> Cache<FsT> {
                     refcount: NonZeroU32::new(1).unwrap(),
                 };
                 self.heap.remove(&mut self.entries, heap_index);
+                self.hits += 1;
                 GetArtifact::Success
             }
         }
@@ -419,6 +480,9 @@ impl<FsT: CacheFs> Cache<FsT> {
                     priority: self.next_priority,
                     heap_index: HeapIndex::default(),
                 };
+                if self.scrubber.in_use.remove(&digest) {
+                    self.scrubber.pending.push(digest.clone());
+                }
                 self.heap.push(&mut self.entries, digest);
                 self.next_priority = self.next_priority.checked_add(1).unwrap();
                 self.possibly_remove_some();
@@ -445,6 +509,37 @@ impl<FsT: CacheFs> Cache<FsT> {
         })
     }
 
+    /// Notify the cache that client `cid` couldn't push the artifact it was asked for.
+    ///
+    /// This removes and returns `cid`'s `JobId`s from the waiting cache entry, guaranteeing that
+    /// `got_artifact` will never return them. Jobs from other clients that were also asked for the
+    /// artifact keep waiting. If there are no such jobs, the entry is removed, so that the next
+    /// request for the artifact will ask for it to be pushed again.
+    pub fn artifact_transfer_failed(&mut self, digest: &Sha256Digest, cid: ClientId) -> Vec<JobId> {
+        let Some(CacheEntry::Waiting(jids, clients)) = self.entries.get_mut(digest) else {
+            return vec![];
+        };
+        let (failed, waiting) = jids.drain(..).partition(|jid| jid.cid == cid);
+        *jids = waiting;
+        clients.remove(&cid);
+        if jids.is_empty() {
+            self.entries.remove(digest);
+        }
+        failed
+    }
+
+    /// Notify the cache that a job that was waiting for an artifact no longer needs it. This
+    /// guarantees that `got_artifact` will never return `jid` for `digest`.
+    pub fn remove_waiting_job(&mut self, jid: JobId, digest: &Sha256Digest) {
+        let Some(CacheEntry::Waiting(jids, _)) = self.entries.get_mut(digest) else {
+            return;
+        };
+        jids.retain(|waiting| *waiting != jid);
+        if jids.is_empty() {
+            self.entries.remove(digest);
+        }
+    }
+
     /// Get an artifact for a worker.
     ///
     /// On success, this will give the caller a refcount as well as the path to the artifact and
@@ -467,6 +562,181 @@ impl<FsT: CacheFs> Cache<FsT> {
         Ok((self.cache_path(digest), bytes_used))
     }
 
+    /// Allow the scrubber to hash `bytes` more bytes of cached artifacts, and return the next
+    /// artifact it should hash, along with its path, if it should start one now.
+    ///
+    /// The scrubber makes passes through all of the artifacts in the cache, having each one hashed
+    /// and comparing the result against the artifact's digest. Hashing is done by the caller,
+    /// off of the scheduler's thread, and the result is given to [`Self::scrubbed_artifact`]. Only
+    /// one artifact is hashed at a time, and the next one isn't started until the bytes allowed
+    /// so far cover the ones before it. Artifacts that are in use are skipped, since jobs are
+    /// relying on them, and are hashed once they're released.
+    pub fn scrub(&mut self, bytes: u64) -> Option<(Sha256Digest, PathBuf)> {
+        // Don't let an idle scrubber save up more than one call's worth, so that it doesn't hash a
+        // lot at once when artifacts show up.
+        let bytes = i64::try_from(bytes).unwrap_or(i64::MAX);
+        self.scrubber.budget = self.scrubber.budget.saturating_add(bytes).min(bytes);
+        self.next_artifact_to_scrub()
+    }
+
+    /// Tell the cache the result of hashing an artifact returned by [`Self::scrub`] or by this
+    /// function, and return the next artifact to hash, if the scrubber should start one now.
+    ///
+    /// An artifact that doesn't match is moved into the `quarantine` directory and removed from
+    /// the cache, so that the next request for it will have a client push it again. If the
+    /// artifact was removed from the cache while it was being hashed, the result is ignored. If it
+    /// has come into use and doesn't match, it's hashed again once it's released.
+    pub fn scrubbed_artifact(
+        &mut self,
+        digest: Sha256Digest,
+        result: Result<Sha256Digest, String>,
+    ) -> Option<(Sha256Digest, PathBuf)> {
+        self.scrubber.hashing = false;
+        if self.scrubber.current.as_ref() == Some(&digest) {
+            self.scrubber.current = None;
+            let corrupt = result.as_ref() != Ok(&digest);
+            match self.entries.get(&digest) {
+                Some(CacheEntry::InHeap { bytes_used, .. }) => {
+                    self.scrubber.statistics.bytes_scrubbed += *bytes_used;
+                    if corrupt {
+                        self.quarantine(digest, result);
+                    }
+                    self.scrubber.statistics.pass_artifacts_scrubbed += 1;
+                }
+                Some(CacheEntry::InUse { bytes_used, .. }) => {
+                    self.scrubber.statistics.bytes_scrubbed += *bytes_used;
+                    if corrupt {
+                        self.scrubber.in_use.insert(digest);
+                    } else {
+                        self.scrubber.statistics.pass_artifacts_scrubbed += 1;
+                    }
+                }
+                Some(CacheEntry::Waiting(..)) | None => {
+                    panic!("Artifact being scrubbed was removed without telling the scrubber")
+                }
+            }
+        }
+        self.next_artifact_to_scrub()
+    }
+
+    /// Return the scrubber's progress so far.
+    pub fn scrub_statistics(&self) -> ScrubStatistics {
+        self.scrubber.statistics.clone()
+    }
+
+    /// Return the cache's size and counters.
+    pub fn metrics(&self) -> CacheMetrics {
+        CacheMetrics {
+            entries: self
+                .entries
+                .values()
+                .filter(|entry| !matches!(entry, CacheEntry::Waiting(..)))
+                .count() as u64,
+            bytes_used: self.bytes_used,
+            hits: self.hits,
+            misses: self.misses,
+            evictions: self.evictions,
+        }
+    }
+
+    fn next_artifact_to_scrub(&mut self) -> Option<(Sha256Digest, PathBuf)> {
+        if self.scrubber.hashing || self.scrubber.budget <= 0 {
+            return None;
+        }
+        let mut started_pass = false;
+        loop {
+            let Some(digest) = self.scrubber.pending.pop() else {
+                // If everything left in this pass is in use, wait for something to be released
+                // instead of starting pass after pass that don't hash anything. Likewise, only
+                // start one new pass at a time, so that we don't spin if the cache is empty.
+                if started_pass
+                    || (!self.scrubber.in_use.is_empty() && !self.scrubber.hashed_this_pass)
+                {
+                    return None;
+                }
+                started_pass = true;
+                self.start_scrub_pass();
+                continue;
+            };
+            match self.entries.get(&digest) {
+                Some(CacheEntry::InHeap { bytes_used, .. }) => {
+                    let bytes_used = i64::try_from(*bytes_used).unwrap_or(i64::MAX);
+                    self.scrubber.budget = self.scrubber.budget.saturating_sub(bytes_used);
+                    self.scrubber.hashing = true;
+                    self.scrubber.hashed_this_pass = true;
+                    self.scrubber.current = Some(digest.clone());
+                    let path = self.cache_path(&digest);
+                    return Some((digest, path));
+                }
+                Some(CacheEntry::InUse { .. }) => {
+                    self.scrubber.in_use.insert(digest);
+                }
+                Some(CacheEntry::Waiting(..)) | None => {
+                    self.scrubber.statistics.pass_artifacts_scrubbed += 1;
+                }
+            }
+        }
+    }
+
+    fn start_scrub_pass(&mut self) {
+        let statistics = &mut self.scrubber.statistics;
+        if statistics.pass_artifacts > 0 {
+            statistics.passes_completed += 1;
+        }
+        let mut pending = Vec::from_iter(self.entries.iter().filter_map(|(digest, entry)| {
+            (!matches!(entry, CacheEntry::Waiting(..))).then_some(digest.clone())
+        }));
+        // Scrub in digest order, popping from the back.
+        pending.sort_by(|lhs, rhs| rhs.cmp(lhs));
+        statistics.pass_artifacts = pending.len() as u64;
+        statistics.pass_artifacts_scrubbed = 0;
+        self.scrubber.pending = pending;
+        self.scrubber.in_use.clear();
+        self.scrubber.hashed_this_pass = false;
+    }
+
+    /// Move a corrupt artifact, which must be in the heap, into the quarantine directory and remove
+    /// it from the cache. `result` is what hashing the artifact came up with.
+    fn quarantine(&mut self, digest: Sha256Digest, result: Result<Sha256Digest, String>) {
+        let Some(CacheEntry::InHeap {
+            bytes_used,
+            heap_index,
+            ..
+        }) = self.entries.get(&digest)
+        else {
+            panic!("Corrupt artifact in unexpected state");
+        };
+        let (bytes_used, heap_index) = (*bytes_used, *heap_index);
+        self.heap.remove(&mut self.entries, heap_index);
+        self.entries.remove(&digest);
+        let mut quarantine_path = self.root.clone();
+        quarantine_path.push("quarantine");
+        quarantine_path.push(format!("{digest}.bin"));
+        self.fs.rename(&self.cache_path(&digest), &quarantine_path);
+        self.quarantined.retain(|path| *path != quarantine_path);
+        self.quarantined.push_back(quarantine_path);
+        self.possibly_remove_some_quarantined();
+        self.bytes_used = self.bytes_used.checked_sub(bytes_used).unwrap();
+        self.scrubber.statistics.corrupt_artifacts += 1;
+        match result {
+            Err(err) => warn!(self.log, "cache quarantined unreadable artifact";
+                "digest" => %digest,
+                "err" => %err),
+            Ok(actual) => warn!(self.log, "cache quarantined corrupt artifact";
+                "digest" => %digest,
+                "actual_digest" => %actual),
+        }
+    }
+
+    /// Remove the oldest quarantined artifacts until there are at most
+    /// [`MAX_QUARANTINED_ARTIFACTS`] of them.
+    fn possibly_remove_some_quarantined(&mut self) {
+        while self.quarantined.len() > MAX_QUARANTINED_ARTIFACTS {
+            let path = self.quarantined.pop_front().unwrap();
+            self.fs.remove_file(&path);
+        }
+    }
+
     /// Return a [`PathBuf`] that contains the temporary directory for the cache. This is where
     /// inbound artifacts should go before [`Self::got_artifact`] is called.
     pub fn tmp_path(&self) -> PathBuf {
@@ -493,7 +763,12 @@ impl<FsT: CacheFs> Cache<FsT> {
                 panic!("Entry popped off of heap was in unexpected state");
             };
             self.fs.remove_file(&self.cache_path(&digest));
+            if self.scrubber.current.as_ref() == Some(&digest) {
+                self.scrubber.current = None;
+                self.scrubber.statistics.pass_artifacts_scrubbed += 1;
+            }
             self.bytes_used = self.bytes_used.checked_sub(bytes_used).unwrap();
+            self.evictions += 1;
             debug!(self.log, "cache removed artifact";
                 "digest" => %digest,
                 "artifact_bytes_used" => %ByteSize::b(bytes_used),
@@ -508,11 +783,13 @@ impl<FsT: CacheFs> Cache<FsT> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use itertools::Itertools as _;
     use maelstrom_base::manifest::{
         ManifestEntry, ManifestEntryData, ManifestEntryMetadata, ManifestWriter, Mode,
         UnixTimestamp,
     };
     use maelstrom_test::*;
+    use maelstrom_util::io::Sha256Reader;
     use std::{cell::RefCell, rc::Rc};
     use TestMessage::*;
 
@@ -688,6 +965,8 @@ mod tests {
         fixture.expect_fs_operations(vec![
             MkdirRecursively(path_buf!("/z/tmp")),
             ReadDir(path_buf!("/z/tmp")),
+            MkdirRecursively(path_buf!("/z/quarantine")),
+            ReadDir(path_buf!("/z/quarantine")),
             MkdirRecursively(path_buf!("/z/sha256")),
             ReadDir(path_buf!("/z/sha256")),
         ]);
@@ -708,6 +987,8 @@ mod tests {
             ReadDir(path_buf!("/z/tmp")),
             Remove(path_buf!("/z/tmp/one")),
             Remove(path_buf!("/z/tmp/two")),
+            MkdirRecursively(path_buf!("/z/quarantine")),
+            ReadDir(path_buf!("/z/quarantine")),
             MkdirRecursively(path_buf!("/z/sha256")),
             ReadDir(path_buf!("/z/sha256")),
         ]);
@@ -736,6 +1017,8 @@ mod tests {
         fixture.expect_fs_operations(vec![
             MkdirRecursively(path_buf!("/z/tmp")),
             ReadDir(path_buf!("/z/tmp")),
+            MkdirRecursively(path_buf!("/z/quarantine")),
+            ReadDir(path_buf!("/z/quarantine")),
             MkdirRecursively(path_buf!("/z/sha256")),
             ReadDir(path_buf!("/z/sha256")),
             Remove(path_buf!("/z/sha256/one")),
@@ -769,6 +1052,8 @@ mod tests {
         fixture.expect_fs_operations(vec![
             MkdirRecursively(path_buf!("/z/tmp")),
             ReadDir(path_buf!("/z/tmp")),
+            MkdirRecursively(path_buf!("/z/quarantine")),
+            ReadDir(path_buf!("/z/quarantine")),
             MkdirRecursively(path_buf!("/z/sha256")),
             ReadDir(path_buf!("/z/sha256")),
             FileSize(long_path!("/z/sha256", 1, "bin")),
@@ -880,6 +1165,47 @@ mod tests {
         fixture.decrement_refcount(digest!(1), vec![Remove(long_path!("/z/sha256", 1, "bin"))]);
     }
 
+    #[test]
+    fn metrics() {
+        let fs = TestCacheFs {
+            directories: HashMap::from([(
+                path_buf!("/z/sha256"),
+                vec![long_path!("/z/sha256", 1, "bin")],
+            )]),
+            files: HashMap::from([(long_path!("/z/sha256", 1, "bin"), vec![0; 1])]),
+            ..Default::default()
+        };
+        let mut fixture = Fixture::new_and_clear_fs_operations(fs, 2);
+        fixture.get_artifact_ign(jid!(1, 1001), digest!(1));
+        fixture.get_artifact_ign(jid!(1, 1002), digest!(1));
+        fixture.get_artifact_ign(jid!(1, 1003), digest!(2));
+        fixture.get_artifact_ign(jid!(2, 1004), digest!(2));
+        assert_eq!(
+            fixture.cache.metrics(),
+            CacheMetrics {
+                entries: 1,
+                bytes_used: 1,
+                hits: 2,
+                misses: 2,
+                evictions: 0,
+            }
+        );
+
+        fixture.got_artifact_ign(digest!(2), 2, short_path!("/z/tmp", 2, "bin"));
+        fixture.cache.decrement_refcount(digest!(1));
+        fixture.cache.decrement_refcount(digest!(1));
+        assert_eq!(
+            fixture.cache.metrics(),
+            CacheMetrics {
+                entries: 1,
+                bytes_used: 2,
+                hits: 2,
+                misses: 2,
+                evictions: 1,
+            }
+        );
+    }
+
     #[test]
     fn got_artifact_no_entry() {
         let mut fixture = Fixture::new_and_clear_fs_operations(TestCacheFs::default(), 1000);
@@ -1162,6 +1488,78 @@ mod tests {
         fixture.decrement_refcount(digest!(1), vec![Remove(long_path!("/z/sha256", 1, "bin"))]);
     }
 
+    #[test]
+    fn artifact_transfer_failed_one_client() {
+        let mut fixture = Fixture::new(TestCacheFs::default(), 0);
+        fixture.get_artifact_ign(jid!(1, 1001), digest!(1));
+        fixture.get_artifact_ign(jid!(1, 1002), digest!(1));
+        assert_eq!(
+            fixture.cache.artifact_transfer_failed(&digest!(1), cid!(1)),
+            vec![jid!(1, 1001), jid!(1, 1002)]
+        );
+        fixture.get_artifact(jid!(1, 1003), digest!(1), GetArtifact::Get, vec![]);
+    }
+
+    #[test]
+    fn artifact_transfer_failed_many_clients() {
+        let mut fixture = Fixture::new(TestCacheFs::default(), 0);
+        fixture.get_artifact_ign(jid!(1, 1001), digest!(1));
+        fixture.get_artifact_ign(jid!(2, 1002), digest!(1));
+        assert_eq!(
+            fixture.cache.artifact_transfer_failed(&digest!(1), cid!(1)),
+            vec![jid!(1, 1001)]
+        );
+        fixture.get_artifact(jid!(1, 1003), digest!(1), GetArtifact::Get, vec![]);
+        fixture.got_artifact(
+            digest!(1),
+            1,
+            short_path!("/z/tmp", 1, "bin"),
+            vec![jid!(2, 1002), jid!(1, 1003)],
+            vec![Rename(
+                short_path!("/z/tmp", 1, "bin"),
+                long_path!("/z/sha256", 1, "bin"),
+            )],
+        );
+    }
+
+    #[test]
+    fn artifact_transfer_failed_in_cache() {
+        let mut fixture = Fixture::new(TestCacheFs::default(), 0);
+        fixture.get_artifact_ign(jid!(1, 1001), digest!(1));
+        fixture.got_artifact_ign(digest!(1), 1, short_path!("/z/tmp", 1, "bin"));
+        assert_eq!(
+            fixture.cache.artifact_transfer_failed(&digest!(1), cid!(1)),
+            vec![]
+        );
+        fixture.decrement_refcount(digest!(1), vec![Remove(long_path!("/z/sha256", 1, "bin"))]);
+    }
+
+    #[test]
+    fn remove_waiting_job() {
+        let mut fixture = Fixture::new(TestCacheFs::default(), 0);
+        fixture.get_artifact_ign(jid!(1, 1001), digest!(1));
+        fixture.get_artifact_ign(jid!(1, 1002), digest!(1));
+        fixture.cache.remove_waiting_job(jid!(1, 1001), &digest!(1));
+        fixture.got_artifact(
+            digest!(1),
+            1,
+            short_path!("/z/tmp", 1, "bin"),
+            vec![jid!(1, 1002)],
+            vec![Rename(
+                short_path!("/z/tmp", 1, "bin"),
+                long_path!("/z/sha256", 1, "bin"),
+            )],
+        );
+    }
+
+    #[test]
+    fn remove_last_waiting_job() {
+        let mut fixture = Fixture::new(TestCacheFs::default(), 0);
+        fixture.get_artifact_ign(jid!(1, 1001), digest!(1));
+        fixture.cache.remove_waiting_job(jid!(1, 1001), &digest!(1));
+        fixture.get_artifact(jid!(1, 1002), digest!(1), GetArtifact::Get, vec![]);
+    }
+
     #[test]
     fn get_artifact_for_worker_no_entry() {
         let mut fixture = Fixture::new(TestCacheFs::default(), 0);
@@ -1232,4 +1630,307 @@ mod tests {
             .unwrap();
         assert_eq!(reader.map(|e| e.unwrap()).collect::<Vec<_>>(), entries);
     }
+
+    fn digest_of(data: &[u8]) -> Sha256Digest {
+        let mut reader = Sha256Reader::new(data);
+        io::copy(&mut reader, &mut io::sink()).unwrap();
+        reader.finalize().1
+    }
+
+    /// Create a fixture whose cache contains the given artifacts, which are stored under the given
+    /// digests.
+    fn scrub_fixture(artifacts: Vec<(Sha256Digest, &[u8])>) -> Fixture {
+        let paths = Vec::from_iter(
+            artifacts
+                .iter()
+                .map(|(digest, _)| PathBuf::from(format!("/z/sha256/{digest}.bin"))),
+        );
+        let fs = TestCacheFs {
+            directories: HashMap::from([(path_buf!("/z/sha256"), paths.clone())]),
+            files: HashMap::from_iter(
+                paths
+                    .into_iter()
+                    .zip(artifacts.into_iter().map(|(_, data)| data.to_vec())),
+            ),
+            ..Default::default()
+        };
+        Fixture::new_and_clear_fs_operations(fs, 1000)
+    }
+
+    fn scrub_path(digest: &Sha256Digest) -> PathBuf {
+        format!("/z/sha256/{digest}.bin").into()
+    }
+
+    #[test]
+    fn scrub_good_artifact() {
+        let digest = digest_of(b"hello world");
+        let mut fixture = scrub_fixture(vec![(digest.clone(), b"hello world")]);
+
+        assert_eq!(
+            fixture.cache.scrub(5),
+            Some((digest.clone(), scrub_path(&digest)))
+        );
+        assert_eq!(
+            fixture.cache.scrub_statistics(),
+            ScrubStatistics {
+                pass_artifacts: 1,
+                ..Default::default()
+            }
+        );
+
+        // Only one artifact is hashed at a time.
+        assert_eq!(fixture.cache.scrub(100), None);
+
+        // The next call starts another pass.
+        assert_eq!(
+            fixture
+                .cache
+                .scrubbed_artifact(digest.clone(), Ok(digest.clone())),
+            Some((digest.clone(), scrub_path(&digest)))
+        );
+        fixture.expect_fs_operations(vec![]);
+        assert_eq!(
+            fixture.cache.scrub_statistics(),
+            ScrubStatistics {
+                passes_completed: 1,
+                pass_artifacts: 1,
+                bytes_scrubbed: 11,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn scrub_waits_for_enough_bytes() {
+        let first = digest_of(b"first");
+        let second = digest_of(b"second");
+        let mut fixture =
+            scrub_fixture(vec![(first.clone(), b"first"), (second.clone(), b"second")]);
+        let digests = Vec::from_iter([first.clone(), second].into_iter().sorted());
+        let len = if digests[0] == first { 5 } else { 6 };
+
+        assert_eq!(
+            fixture.cache.scrub(3),
+            Some((digests[0].clone(), scrub_path(&digests[0])))
+        );
+        assert_eq!(
+            fixture
+                .cache
+                .scrubbed_artifact(digests[0].clone(), Ok(digests[0].clone())),
+            None
+        );
+
+        // The first artifact used up more than was allowed, so the scrubber waits until that's
+        // made up.
+        assert_eq!(fixture.cache.scrub(len - 3), None);
+        assert_eq!(
+            fixture.cache.scrub(1),
+            Some((digests[1].clone(), scrub_path(&digests[1])))
+        );
+    }
+
+    #[test]
+    fn scrub_corrupt_artifact_is_quarantined() {
+        let good = digest_of(b"good");
+        let bad = digest_of(b"bad");
+        let mut fixture = scrub_fixture(vec![(good.clone(), b"good"), (bad.clone(), b"ugly")]);
+
+        // Artifacts are scrubbed in digest order.
+        let mut next = fixture.cache.scrub(100);
+        for digest in [&good, &bad].into_iter().sorted() {
+            assert_eq!(next, Some((digest.clone(), scrub_path(digest))));
+            let actual = if *digest == bad {
+                digest_of(b"ugly")
+            } else {
+                digest.clone()
+            };
+            next = fixture.cache.scrubbed_artifact(digest.clone(), Ok(actual));
+        }
+        fixture.expect_fs_operations(vec![Rename(
+            scrub_path(&bad),
+            format!("/z/quarantine/{bad}.bin").into(),
+        )]);
+        assert_eq!(
+            fixture.cache.scrub_statistics(),
+            ScrubStatistics {
+                passes_completed: 1,
+                pass_artifacts: 1,
+                bytes_scrubbed: 8,
+                corrupt_artifacts: 1,
+                ..Default::default()
+            }
+        );
+
+        // The corrupt artifact is no longer in the cache, so it must be pushed again.
+        fixture.get_artifact(jid!(1, 1001), bad, GetArtifact::Get, vec![]);
+        fixture.get_artifact(jid!(1, 1002), good, GetArtifact::Success, vec![]);
+    }
+
+    #[test]
+    fn scrub_unreadable_artifact_is_quarantined() {
+        let digest = digest_of(b"hello world");
+        let mut fixture = scrub_fixture(vec![(digest.clone(), b"hello world")]);
+
+        fixture.cache.scrub(100).unwrap();
+        fixture
+            .cache
+            .scrubbed_artifact(digest.clone(), Err("I/O error".into()));
+        fixture.expect_fs_operations(vec![Rename(
+            scrub_path(&digest),
+            format!("/z/quarantine/{digest}.bin").into(),
+        )]);
+        assert_eq!(fixture.cache.scrub_statistics().corrupt_artifacts, 1);
+    }
+
+    #[test]
+    fn scrub_skips_artifacts_in_use_until_released() {
+        let digest = digest_of(b"hello world");
+        let mut fixture = scrub_fixture(vec![(digest.clone(), b"hello world")]);
+        fixture.get_artifact_ign(jid!(1, 1001), digest.clone());
+
+        // Nothing else is in the cache, so the scrubber waits instead of starting more passes.
+        assert_eq!(fixture.cache.scrub(100), None);
+        assert_eq!(fixture.cache.scrub(100), None);
+        assert_eq!(
+            fixture.cache.scrub_statistics(),
+            ScrubStatistics {
+                pass_artifacts: 1,
+                ..Default::default()
+            }
+        );
+
+        // Once the artifact is released, it's scrubbed as part of the same pass.
+        fixture.decrement_refcount_ign(digest.clone());
+        assert_eq!(
+            fixture.cache.scrub(100),
+            Some((digest.clone(), scrub_path(&digest)))
+        );
+        fixture.cache.scrubbed_artifact(digest.clone(), Ok(digest));
+        assert_eq!(
+            fixture.cache.scrub_statistics(),
+            ScrubStatistics {
+                passes_completed: 1,
+                pass_artifacts: 1,
+                bytes_scrubbed: 11,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn scrub_corrupt_artifact_that_came_into_use_is_retried_when_released() {
+        let digest = digest_of(b"hello world");
+        let mut fixture = scrub_fixture(vec![(digest.clone(), b"hello world")]);
+
+        fixture.cache.scrub(100).unwrap();
+        fixture.get_artifact_ign(jid!(1, 1001), digest.clone());
+        assert_eq!(
+            fixture
+                .cache
+                .scrubbed_artifact(digest.clone(), Err("I/O error".into())),
+            None
+        );
+        fixture.expect_fs_operations(vec![]);
+        assert_eq!(fixture.cache.scrub_statistics().corrupt_artifacts, 0);
+
+        fixture.decrement_refcount_ign(digest.clone());
+        assert_eq!(
+            fixture.cache.scrub(100),
+            Some((digest.clone(), scrub_path(&digest)))
+        );
+        fixture
+            .cache
+            .scrubbed_artifact(digest.clone(), Err("I/O error".into()));
+        fixture.expect_fs_operations(vec![Rename(
+            scrub_path(&digest),
+            format!("/z/quarantine/{digest}.bin").into(),
+        )]);
+        assert_eq!(fixture.cache.scrub_statistics().corrupt_artifacts, 1);
+    }
+
+    #[test]
+    fn scrub_result_for_removed_artifact_is_ignored() {
+        let digest = digest_of(b"hello world");
+        let mut fixture = scrub_fixture(vec![(digest.clone(), b"hello world")]);
+
+        fixture.cache.scrub(100).unwrap();
+        // Push out the artifact being scrubbed.
+        fixture.get_artifact_ign(jid!(1, 1001), digest!(1));
+        fixture.got_artifact_ign(digest!(1), 1000, "/z/tmp/foo".into());
+        fixture.clear_fs_operations();
+        fixture
+            .cache
+            .scrubbed_artifact(digest.clone(), Err("I/O error".into()));
+        fixture.expect_fs_operations(vec![]);
+        // The pass ended without scrubbing anything, and the next one only has the new artifact.
+        assert_eq!(
+            fixture.cache.scrub_statistics(),
+            ScrubStatistics {
+                passes_completed: 1,
+                pass_artifacts: 1,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn scrub_empty_cache() {
+        let mut fixture = scrub_fixture(vec![]);
+        assert_eq!(fixture.cache.scrub(100), None);
+        fixture.expect_fs_operations(vec![]);
+        assert_eq!(fixture.cache.scrub_statistics(), ScrubStatistics::default());
+    }
+
+    #[test]
+    fn new_removes_oldest_quarantined_artifacts() {
+        let quarantined = Vec::<PathBuf>::from_iter(
+            (1..=MAX_QUARANTINED_ARTIFACTS + 2).map(|i| long_path!("/z/quarantine", i, "bin")),
+        );
+        let fs = TestCacheFs {
+            directories: HashMap::from([(path_buf!("/z/quarantine"), quarantined.clone())]),
+            ..Default::default()
+        };
+        let mut fixture = Fixture::new(fs, 1000);
+        fixture.expect_fs_operations(vec![
+            MkdirRecursively(path_buf!("/z/tmp")),
+            ReadDir(path_buf!("/z/tmp")),
+            MkdirRecursively(path_buf!("/z/quarantine")),
+            ReadDir(path_buf!("/z/quarantine")),
+            MkdirRecursively(path_buf!("/z/sha256")),
+            ReadDir(path_buf!("/z/sha256")),
+            Remove(quarantined[0].clone()),
+            Remove(quarantined[1].clone()),
+        ]);
+    }
+
+    #[test]
+    fn quarantine_removes_oldest_quarantined_artifact() {
+        let digest = digest_of(b"hello world");
+        let mut quarantined = Vec::<PathBuf>::from_iter(
+            (1..=MAX_QUARANTINED_ARTIFACTS).map(|i| long_path!("/z/quarantine", i, "bin")),
+        );
+        let mut fixture = Fixture::new_and_clear_fs_operations(
+            TestCacheFs {
+                directories: HashMap::from([
+                    (path_buf!("/z/sha256"), vec![scrub_path(&digest)]),
+                    (path_buf!("/z/quarantine"), quarantined.clone()),
+                ]),
+                files: HashMap::from([(scrub_path(&digest), b"hello world".to_vec())]),
+                ..Default::default()
+            },
+            1000,
+        );
+
+        fixture.cache.scrub(100).unwrap();
+        fixture
+            .cache
+            .scrubbed_artifact(digest.clone(), Err("I/O error".into()));
+        fixture.expect_fs_operations(vec![
+            Rename(
+                scrub_path(&digest),
+                format!("/z/quarantine/{digest}.bin").into(),
+            ),
+            Remove(quarantined.remove(0)),
+        ]);
+    }
 }
@@ -0,0 +1,201 @@
+//! Keep track of which workers have which artifacts, so that workers can fetch artifacts from each
+//! other instead of all fetching them from the broker.
+//!
+//! Workers are identified by the address they serve artifacts on. A worker is only known to have
+//! an artifact after its artifact fetcher says it has stored it, and until it says it has removed
+//! it, or disconnects. A worker that lost an artifact without saying so is forgotten about for that
+//! artifact once a fetcher fails to get it from the worker.
+
+use maelstrom_base::Sha256Digest;
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    net::SocketAddr,
+};
+
+/// The most artifacts a worker is asked to serve to other workers at once. Fetches that would go
+/// over this limit for every worker with the artifact are served by the broker instead.
+pub const MAX_TRANSFERS_PER_PEER: usize = 4;
+
+#[derive(Default)]
+pub struct Peers {
+    holders: HashMap<Sha256Digest, Vec<SocketAddr>>,
+    transfers: HashMap<SocketAddr, usize>,
+}
+
+impl Peers {
+    /// Choose a worker for the worker at `requester` to fetch `digest` from, if there is one. Of
+    /// the workers that have the artifact, the one with the fewest transfers in progress is
+    /// chosen, as long as it has fewer than [`MAX_TRANSFERS_PER_PEER`]. The chosen worker's
+    /// transfer must later be ended with [`Self::end_transfer`] or [`Self::transfer_failed`].
+    pub fn start_transfer(
+        &mut self,
+        digest: &Sha256Digest,
+        requester: SocketAddr,
+    ) -> Option<SocketAddr> {
+        let peer = *self
+            .holders
+            .get(digest)?
+            .iter()
+            .filter(|&&peer| peer != requester)
+            .map(|peer| (self.transfers.get(peer).copied().unwrap_or_default(), peer))
+            .filter(|(transfers, _)| *transfers < MAX_TRANSFERS_PER_PEER)
+            .min_by_key(|(transfers, _)| *transfers)?
+            .1;
+        *self.transfers.entry(peer).or_default() += 1;
+        Some(peer)
+    }
+
+    /// Note that a transfer started with [`Self::start_transfer`] has ended.
+    pub fn end_transfer(&mut self, peer: SocketAddr) {
+        let Entry::Occupied(mut entry) = self.transfers.entry(peer) else {
+            panic!("Transfer ended for peer without any");
+        };
+        *entry.get_mut() -= 1;
+        if *entry.get() == 0 {
+            entry.remove();
+        }
+    }
+
+    /// Note that a transfer started with [`Self::start_transfer`] has failed because `peer`
+    /// couldn't provide `digest`. The peer won't be chosen for `digest` again unless it stores
+    /// it again.
+    pub fn transfer_failed(&mut self, digest: &Sha256Digest, peer: SocketAddr) {
+        self.end_transfer(peer);
+        self.remove_holder(digest.clone(), peer);
+    }
+
+    /// Note that `peer` has stored `digests`, and can serve them to other workers.
+    pub fn stored(&mut self, digests: Vec<Sha256Digest>, peer: SocketAddr) {
+        for digest in digests {
+            let holders = self.holders.entry(digest).or_default();
+            if !holders.contains(&peer) {
+                holders.push(peer);
+            }
+        }
+    }
+
+    /// Note that `peer` has removed `digests`, and can't serve them anymore.
+    pub fn removed(&mut self, digests: Vec<Sha256Digest>, peer: SocketAddr) {
+        for digest in digests {
+            self.remove_holder(digest, peer);
+        }
+    }
+
+    /// Note that `peer` has disconnected, and can't serve anything anymore. Transfers from it that
+    /// are in progress still have to be ended.
+    pub fn disconnected(&mut self, peer: SocketAddr) {
+        self.holders.retain(|_, holders| {
+            holders.retain(|holder| *holder != peer);
+            !holders.is_empty()
+        });
+    }
+
+    fn remove_holder(&mut self, digest: Sha256Digest, peer: SocketAddr) {
+        if let Entry::Occupied(mut entry) = self.holders.entry(digest) {
+            entry.get_mut().retain(|holder| *holder != peer);
+            if entry.get().is_empty() {
+                entry.remove();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use maelstrom_test::*;
+
+    fn addr(port: u16) -> SocketAddr {
+        SocketAddr::from(([10, 0, 0, 1], port))
+    }
+
+    #[test]
+    fn no_holders() {
+        let mut peers = Peers::default();
+        assert_eq!(peers.start_transfer(&digest![1], addr(1)), None);
+    }
+
+    #[test]
+    fn requester_is_not_chosen() {
+        let mut peers = Peers::default();
+        peers.stored(vec![digest![1]], addr(1));
+        assert_eq!(peers.start_transfer(&digest![1], addr(1)), None);
+        assert_eq!(peers.start_transfer(&digest![1], addr(2)), Some(addr(1)));
+    }
+
+    #[test]
+    fn least_busy_holder_is_chosen() {
+        let mut peers = Peers::default();
+        peers.stored(vec![digest![1], digest![2]], addr(1));
+        peers.stored(vec![digest![1]], addr(2));
+        assert_eq!(peers.start_transfer(&digest![2], addr(3)), Some(addr(1)));
+        assert_eq!(peers.start_transfer(&digest![1], addr(3)), Some(addr(2)));
+        assert_eq!(peers.start_transfer(&digest![1], addr(3)), Some(addr(1)));
+        peers.end_transfer(addr(1));
+        peers.end_transfer(addr(1));
+        assert_eq!(peers.start_transfer(&digest![1], addr(3)), Some(addr(1)));
+    }
+
+    #[test]
+    fn transfers_are_limited_per_peer() {
+        let mut peers = Peers::default();
+        peers.stored(vec![digest![1]], addr(1));
+        for _ in 0..MAX_TRANSFERS_PER_PEER {
+            assert_eq!(peers.start_transfer(&digest![1], addr(2)), Some(addr(1)));
+        }
+        assert_eq!(peers.start_transfer(&digest![1], addr(2)), None);
+        peers.end_transfer(addr(1));
+        assert_eq!(peers.start_transfer(&digest![1], addr(2)), Some(addr(1)));
+    }
+
+    #[test]
+    fn failed_peer_is_forgotten_for_digest() {
+        let mut peers = Peers::default();
+        peers.stored(vec![digest![1], digest![2]], addr(1));
+        assert_eq!(peers.start_transfer(&digest![1], addr(2)), Some(addr(1)));
+        peers.transfer_failed(&digest![1], addr(1));
+        assert_eq!(peers.start_transfer(&digest![1], addr(2)), None);
+        assert_eq!(peers.start_transfer(&digest![2], addr(2)), Some(addr(1)));
+
+        peers.stored(vec![digest![1]], addr(1));
+        assert_eq!(peers.start_transfer(&digest![1], addr(2)), Some(addr(1)));
+    }
+
+    #[test]
+    fn storing_twice_adds_holder_once() {
+        let mut peers = Peers::default();
+        peers.stored(vec![digest![1]], addr(1));
+        peers.stored(vec![digest![1]], addr(1));
+        assert_eq!(peers.holders[&digest![1]], vec![addr(1)]);
+    }
+
+    #[test]
+    fn removed_artifacts_are_forgotten() {
+        let mut peers = Peers::default();
+        peers.stored(vec![digest![1], digest![2]], addr(1));
+        peers.stored(vec![digest![1]], addr(2));
+        peers.removed(vec![digest![1], digest![3]], addr(1));
+        assert_eq!(peers.start_transfer(&digest![1], addr(3)), Some(addr(2)));
+        assert_eq!(peers.start_transfer(&digest![1], addr(3)), Some(addr(2)));
+        assert_eq!(peers.start_transfer(&digest![2], addr(3)), Some(addr(1)));
+
+        peers.removed(vec![digest![1]], addr(2));
+        assert!(!peers.holders.contains_key(&digest![1]));
+    }
+
+    #[test]
+    fn disconnected_peer_is_forgotten() {
+        let mut peers = Peers::default();
+        peers.stored(vec![digest![1], digest![2]], addr(1));
+        peers.stored(vec![digest![1]], addr(2));
+        assert_eq!(peers.start_transfer(&digest![2], addr(3)), Some(addr(1)));
+        peers.disconnected(addr(1));
+        assert_eq!(peers.start_transfer(&digest![1], addr(3)), Some(addr(2)));
+        assert_eq!(peers.start_transfer(&digest![2], addr(3)), None);
+        assert!(!peers.holders.contains_key(&digest![2]));
+
+        // The transfer that was in progress still ends normally.
+        peers.end_transfer(addr(1));
+        assert!(!peers.transfers.contains_key(&addr(1)));
+    }
+}
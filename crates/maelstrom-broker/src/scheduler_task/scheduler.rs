@@ -1,26 +1,34 @@
 //! Central processing module for the broker. Receives and sends messages to and from clients and
 //! workers.
 
-use crate::scheduler_task::cache::{Cache, CacheFs, GetArtifact, GetArtifactForWorkerError};
+use crate::scheduler_task::{
+    cache::{Cache, CacheFs, CacheMetrics, GetArtifact, GetArtifactForWorkerError},
+    peers::Peers,
+};
 use anyhow::Result;
 use maelstrom_base::{
     manifest::{ManifestEntryData, ManifestReader},
     proto::{BrokerToClient, BrokerToWorker, ClientToBroker, WorkerToBroker},
     stats::{
-        BrokerStatistics, JobState, JobStateCounts, JobStatisticsSample, JobStatisticsTimeSeries,
-        WorkerStatistics,
+        BrokerStatistics, CacheStatistics, JobState, JobStateCounts, JobStatisticsSample,
+        JobStatisticsTimeSeries, ScrubStatistics, WorkerStatistics,
     },
-    ArtifactType, ClientId, ClientJobId, JobId, JobOutcomeResult, JobSpec, Sha256Digest, WorkerId,
+    ArtifactType, ClientId, ClientJobId, JobError, JobId, JobOutcomeResult, JobSpec, Sha256Digest,
+    WorkerId,
 };
 use maelstrom_util::{
     ext::{BoolExt as _, OptionExt as _},
     heap::{Heap, HeapDeps, HeapIndex},
+    metrics::Histogram,
+    trace::JobEvent,
 };
 use std::{
-    collections::{HashMap, HashSet, VecDeque},
+    collections::{hash_map::Entry, BTreeMap, HashMap, HashSet, VecDeque},
     fmt::{self, Debug, Formatter},
     io,
+    net::SocketAddr,
     path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
 };
 
 /*              _     _ _
@@ -32,19 +40,82 @@ use std::{
  *  FIGLET: public
  */
 
+/// Where a worker's artifact fetcher should get an artifact from. This is the answer to a
+/// [`Message::GetArtifactForWorker`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ArtifactForWorker {
+    /// The broker has the artifact at the given path. It has the given size.
+    Broker(PathBuf, u64),
+
+    /// The worker serving artifacts at the given address has the artifact.
+    Peer(SocketAddr),
+}
+
 /// The external dependencies for [`Scheduler`]. All of these methods must be asynchronous: they
 /// must not block the current thread.
 pub trait SchedulerDeps {
     type ClientSender;
     type WorkerSender;
     type WorkerArtifactFetcherSender;
+    type MetricsSender;
     fn send_message_to_client(&mut self, sender: &mut Self::ClientSender, message: BrokerToClient);
     fn send_message_to_worker(&mut self, sender: &mut Self::WorkerSender, message: BrokerToWorker);
     fn send_message_to_worker_artifact_fetcher(
         &mut self,
         sender: &mut Self::WorkerArtifactFetcherSender,
-        message: Result<(PathBuf, u64), GetArtifactForWorkerError>,
+        message: Result<ArtifactForWorker, GetArtifactForWorkerError>,
     );
+
+    /// Fetch the given artifact from the blob store. This is answered with a
+    /// [`Message::GotArtifact`] if the blob store has the artifact, and with a
+    /// [`Message::BlobStoreMissingArtifact`] if it doesn't.
+    fn fetch_artifact_from_blob_store(&mut self, digest: Sha256Digest);
+
+    /// Hash the cached artifact at `path` so the cache's scrubber can check it against `digest`.
+    /// This is answered with a [`Message::ScrubbedArtifact`].
+    fn scrub_artifact(&mut self, digest: Sha256Digest, path: PathBuf);
+
+    fn send_metrics(&mut self, sender: &mut Self::MetricsSender, metrics: SchedulerMetrics);
+
+    /// Return the current time. This is only used to measure how long jobs take.
+    fn now(&mut self) -> Instant;
+
+    /// Return the current wall-clock time. This is only used to timestamp statistics samples.
+    fn system_time(&mut self) -> SystemTime;
+
+    /// Note that `event` has just happened to the job `jid`. This is used for logging and tracing.
+    fn job_event(&mut self, jid: JobId, event: JobEvent);
+}
+
+/// A snapshot of the scheduler's state, along with counters and distributions that cover
+/// everything since the broker started. This is what the broker's metrics are made from.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SchedulerMetrics {
+    /// The number of jobs in each state, over all clients. Completed jobs are counted since the
+    /// broker started, including those of clients that have since disconnected.
+    pub job_state_counts: JobStateCounts,
+    pub workers: BTreeMap<WorkerId, WorkerMetrics>,
+    pub cache: CacheMetrics,
+    /// The uncompressed bytes of artifacts the cache has received, from clients or the blob store.
+    pub artifact_bytes_received: u64,
+    /// The uncompressed bytes of artifacts that workers have been sent from the cache.
+    pub artifact_bytes_sent: u64,
+    /// The number of times a worker was sent to another worker for an artifact.
+    pub artifact_peer_redirects: u64,
+    /// How long jobs took from being requested until first being sent to a worker, in seconds.
+    pub job_wait_seconds: Histogram,
+    /// How long jobs took from being requested until completing, in seconds.
+    pub job_latency_seconds: Histogram,
+    /// How long jobs took from last being sent to a worker until completing, in seconds.
+    pub job_run_seconds: Histogram,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WorkerMetrics {
+    pub slots: usize,
+    /// The number of jobs sent to the worker that it hasn't finished yet. This can be up to twice
+    /// the number of slots.
+    pub jobs: usize,
 }
 
 /// The required interface for the cache that is provided to the [`Scheduler`]. This mirrors the API
@@ -74,11 +145,33 @@ pub trait SchedulerCache {
     /// See [`super::cache::Cache::client_disconnected`].
     fn client_disconnected(&mut self, cid: ClientId);
 
+    /// See [`super::cache::Cache::artifact_transfer_failed`].
+    fn artifact_transfer_failed(&mut self, digest: &Sha256Digest, cid: ClientId) -> Vec<JobId>;
+
+    /// See [`super::cache::Cache::remove_waiting_job`].
+    fn remove_waiting_job(&mut self, jid: JobId, digest: &Sha256Digest);
+
     /// See [`super::cache::Cache::get_artifact_for_worker`].
     fn get_artifact_for_worker(
         &mut self,
         digest: &Sha256Digest,
     ) -> Result<(PathBuf, u64), GetArtifactForWorkerError>;
+
+    /// See [`super::cache::Cache::scrub`].
+    fn scrub(&mut self, bytes: u64) -> Option<(Sha256Digest, PathBuf)>;
+
+    /// See [`super::cache::Cache::scrubbed_artifact`].
+    fn scrubbed_artifact(
+        &mut self,
+        digest: Sha256Digest,
+        result: Result<Sha256Digest, String>,
+    ) -> Option<(Sha256Digest, PathBuf)>;
+
+    /// See [`super::cache::Cache::scrub_statistics`].
+    fn scrub_statistics(&self) -> ScrubStatistics;
+
+    /// See [`super::cache::Cache::metrics`].
+    fn metrics(&self) -> CacheMetrics;
 }
 
 impl<FsT: CacheFs> SchedulerCache for Cache<FsT> {
@@ -105,12 +198,40 @@ impl<FsT: CacheFs> SchedulerCache for Cache<FsT> {
         self.client_disconnected(cid)
     }
 
+    fn artifact_transfer_failed(&mut self, digest: &Sha256Digest, cid: ClientId) -> Vec<JobId> {
+        self.artifact_transfer_failed(digest, cid)
+    }
+
+    fn remove_waiting_job(&mut self, jid: JobId, digest: &Sha256Digest) {
+        self.remove_waiting_job(jid, digest)
+    }
+
     fn get_artifact_for_worker(
         &mut self,
         digest: &Sha256Digest,
     ) -> Result<(PathBuf, u64), GetArtifactForWorkerError> {
         self.get_artifact_for_worker(digest)
     }
+
+    fn scrub(&mut self, bytes: u64) -> Option<(Sha256Digest, PathBuf)> {
+        self.scrub(bytes)
+    }
+
+    fn scrubbed_artifact(
+        &mut self,
+        digest: Sha256Digest,
+        result: Result<Sha256Digest, String>,
+    ) -> Option<(Sha256Digest, PathBuf)> {
+        self.scrubbed_artifact(digest, result)
+    }
+
+    fn scrub_statistics(&self) -> ScrubStatistics {
+        self.scrub_statistics()
+    }
+
+    fn metrics(&self) -> CacheMetrics {
+        self.metrics()
+    }
 }
 
 /// The incoming messages, or events, for [`Scheduler`].
@@ -140,10 +261,40 @@ pub enum Message<DepsT: SchedulerDeps> {
     /// temporarily stored at the given path.
     GotArtifact(Sha256Digest, u64, PathBuf),
 
-    /// A worker has requested the given artifact be sent to it over the given sender. After the
-    /// contents are sent to the worker, the refcount needs to be decremented with a
+    /// Part of the artifact with the given digest has been pushed to us, so the clients asked to
+    /// push it get more time to finish.
+    ArtifactTransferProgress(Sha256Digest),
+
+    /// A worker has requested the given artifact be sent to it over the given sender. If the
+    /// worker gave the address it serves artifacts on, it may be sent to another worker that has
+    /// the artifact instead, and that transfer needs to be ended with a
+    /// [`Message::PeerTransferDone`] or [`Message::PeerTransferFailed`] message. Otherwise, after
+    /// the contents are sent to the worker, the refcount needs to be decremented with a
     /// [`Message::DecrementRefcount`] message.
-    GetArtifactForWorker(Sha256Digest, DepsT::WorkerArtifactFetcherSender),
+    GetArtifactForWorker(
+        Sha256Digest,
+        Option<SocketAddr>,
+        DepsT::WorkerArtifactFetcherSender,
+    ),
+
+    /// A worker has finished fetching an artifact from the worker serving artifacts at the given
+    /// address.
+    PeerTransferDone(SocketAddr),
+
+    /// A worker failed to fetch the given artifact from the worker serving artifacts at the given
+    /// address.
+    PeerTransferFailed(Sha256Digest, SocketAddr),
+
+    /// The worker serving artifacts at the given address has stored the given artifacts, and can
+    /// serve them to other workers.
+    PeerStoredArtifacts(Vec<Sha256Digest>, SocketAddr),
+
+    /// The worker serving artifacts at the given address has removed the given artifacts, and
+    /// can't serve them anymore.
+    PeerRemovedArtifacts(Vec<Sha256Digest>, SocketAddr),
+
+    /// The worker serving artifacts at the given address has disconnected.
+    PeerDisconnected(SocketAddr),
 
     /// A worker has been sent an artifact, and we can now release the refcount that was keeping
     /// the artifact from being removed while being transferred.
@@ -151,6 +302,24 @@ pub enum Message<DepsT: SchedulerDeps> {
 
     /// The stats heartbeat task has decided it's time to take another statistics sample.
     StatisticsHeartbeat,
+
+    /// The artifact transfer checker has decided it's time to fail the jobs waiting for artifacts
+    /// that clients haven't pushed in time.
+    CheckArtifactTransfers,
+
+    /// The cache scrub task has decided it's time to scrub the given number of bytes of the cache.
+    ScrubCache(u64),
+
+    /// An artifact handed to [`SchedulerDeps::scrub_artifact`] has been hashed. This contains the
+    /// digest it was supposed to have, and either the digest it actually has or a description of
+    /// why it couldn't be read.
+    ScrubbedArtifact(Sha256Digest, Result<Sha256Digest, String>),
+
+    /// The blob store doesn't have the given artifact, or it couldn't be fetched from it.
+    BlobStoreMissingArtifact(Sha256Digest),
+
+    /// The metrics endpoint wants a [`SchedulerMetrics`] sent to it over the given sender.
+    MetricsRequest(DepsT::MetricsSender),
 }
 
 impl<DepsT: SchedulerDeps> Debug for Message<DepsT> {
@@ -182,21 +351,67 @@ impl<DepsT: SchedulerDeps> Debug for Message<DepsT> {
                 .field(size)
                 .field(path)
                 .finish(),
-            Message::GetArtifactForWorker(digest, _sender) => {
-                f.debug_tuple("GetArtifactForWorker").field(digest).finish()
+            Message::GetArtifactForWorker(digest, requester, _sender) => f
+                .debug_tuple("GetArtifactForWorker")
+                .field(digest)
+                .field(requester)
+                .finish(),
+            Message::PeerTransferDone(peer) => {
+                f.debug_tuple("PeerTransferDone").field(peer).finish()
+            }
+            Message::PeerTransferFailed(digest, peer) => f
+                .debug_tuple("PeerTransferFailed")
+                .field(digest)
+                .field(peer)
+                .finish(),
+            Message::PeerStoredArtifacts(digests, peer) => f
+                .debug_tuple("PeerStoredArtifacts")
+                .field(digests)
+                .field(peer)
+                .finish(),
+            Message::PeerRemovedArtifacts(digests, peer) => f
+                .debug_tuple("PeerRemovedArtifacts")
+                .field(digests)
+                .field(peer)
+                .finish(),
+            Message::PeerDisconnected(peer) => {
+                f.debug_tuple("PeerDisconnected").field(peer).finish()
             }
             Message::DecrementRefcount(digest) => {
                 f.debug_tuple("DecrementRefcount").field(digest).finish()
             }
             Message::StatisticsHeartbeat => f.debug_tuple("StatisticsHeartbeat").finish(),
+            Message::ArtifactTransferProgress(digest) => f
+                .debug_tuple("ArtifactTransferProgress")
+                .field(digest)
+                .finish(),
+            Message::CheckArtifactTransfers => f.debug_tuple("CheckArtifactTransfers").finish(),
+            Message::ScrubCache(bytes) => f.debug_tuple("ScrubCache").field(bytes).finish(),
+            Message::ScrubbedArtifact(digest, result) => f
+                .debug_tuple("ScrubbedArtifact")
+                .field(digest)
+                .field(result)
+                .finish(),
+            Message::BlobStoreMissingArtifact(digest) => f
+                .debug_tuple("BlobStoreMissingArtifact")
+                .field(digest)
+                .finish(),
+            Message::MetricsRequest(_sender) => f.debug_tuple("MetricsRequest").finish(),
         }
     }
 }
 
 impl<CacheT: SchedulerCache, DepsT: SchedulerDeps> Scheduler<CacheT, DepsT> {
     /// Create a new scheduler with the given [`SchedulerCache`]. Note that [`SchedulerDeps`] are
-    /// passed in to `Self::receive_message`.
-    pub fn new(cache: CacheT) -> Self {
+    /// passed in to `Self::receive_message`. If `blob_store` is true, artifacts that aren't in the
+    /// cache are fetched from the blob store before clients are asked to push them. If
+    /// `artifact_transfer_timeout` is given, the jobs waiting for an artifact that a client hasn't
+    /// pushed that long after being asked to are failed.
+    pub fn new(
+        cache: CacheT,
+        blob_store: bool,
+        artifact_transfer_timeout: Option<Duration>,
+    ) -> Self {
         Scheduler {
             cache,
             clients: HashMap::default(),
@@ -204,6 +419,17 @@ impl<CacheT: SchedulerCache, DepsT: SchedulerDeps> Scheduler<CacheT, DepsT> {
             queued_requests: VecDeque::default(),
             worker_heap: Heap::default(),
             job_statistics: JobStatisticsTimeSeries::default(),
+            peers: Peers::default(),
+            blob_store_fetches: blob_store.then(HashMap::default),
+            artifact_transfer_timeout,
+            artifact_transfer_deadlines: BTreeMap::default(),
+            num_completed_jobs: 0,
+            artifact_bytes_received: 0,
+            artifact_bytes_sent: 0,
+            artifact_peer_redirects: 0,
+            job_wait_seconds: Histogram::default(),
+            job_latency_seconds: Histogram::default(),
+            job_run_seconds: Histogram::default(),
         }
     }
 
@@ -222,6 +448,9 @@ impl<CacheT: SchedulerCache, DepsT: SchedulerDeps> Scheduler<CacheT, DepsT> {
             Message::FromClient(cid, ClientToBroker::JobStateCountsRequest) => {
                 self.receive_client_job_state_counts(deps, cid)
             }
+            Message::FromClient(cid, ClientToBroker::ArtifactTransferFailed(digest, message)) => {
+                self.receive_client_artifact_transfer_failed(deps, cid, digest, message)
+            }
             Message::WorkerConnected(id, slots, sender) => {
                 self.receive_worker_connected(deps, id, slots, sender)
             }
@@ -232,11 +461,32 @@ impl<CacheT: SchedulerCache, DepsT: SchedulerDeps> Scheduler<CacheT, DepsT> {
             Message::GotArtifact(digest, size, path) => {
                 self.receive_got_artifact(deps, digest, size, path)
             }
-            Message::GetArtifactForWorker(digest, sender) => {
-                self.receive_get_artifact_for_worker(deps, digest, sender)
+            Message::ArtifactTransferProgress(digest) => {
+                self.receive_artifact_transfer_progress(deps, digest)
             }
+            Message::GetArtifactForWorker(digest, requester, sender) => {
+                self.receive_get_artifact_for_worker(deps, digest, requester, sender)
+            }
+            Message::PeerTransferDone(peer) => self.peers.end_transfer(peer),
+            Message::PeerTransferFailed(digest, peer) => self.peers.transfer_failed(&digest, peer),
+            Message::PeerStoredArtifacts(digests, peer) => self.peers.stored(digests, peer),
+            Message::PeerRemovedArtifacts(digests, peer) => self.peers.removed(digests, peer),
+            Message::PeerDisconnected(peer) => self.peers.disconnected(peer),
             Message::DecrementRefcount(digest) => self.receive_decrement_refcount(digest),
-            Message::StatisticsHeartbeat => self.receive_statistics_heartbeat(),
+            Message::StatisticsHeartbeat => self.receive_statistics_heartbeat(deps),
+            Message::CheckArtifactTransfers => self.receive_check_artifact_transfers(deps),
+            Message::ScrubCache(bytes) => {
+                let next = self.cache.scrub(bytes);
+                self.possibly_scrub_artifact(deps, next)
+            }
+            Message::ScrubbedArtifact(digest, result) => {
+                let next = self.cache.scrubbed_artifact(digest, result);
+                self.possibly_scrub_artifact(deps, next)
+            }
+            Message::BlobStoreMissingArtifact(digest) => {
+                self.receive_blob_store_missing_artifact(deps, digest)
+            }
+            Message::MetricsRequest(sender) => self.receive_metrics_request(deps, sender),
         }
     }
 }
@@ -276,16 +526,29 @@ struct Job {
     spec: JobSpec,
     acquired_artifacts: HashSet<Sha256Digest>,
     missing_artifacts: HashMap<Sha256Digest, IsManifest>,
+    requested_at: Instant,
+    dispatched_at: Option<Instant>,
 }
 
 impl Job {
-    fn new(spec: JobSpec) -> Self {
+    fn new(spec: JobSpec, requested_at: Instant) -> Self {
         Job {
             spec,
             acquired_artifacts: Default::default(),
             missing_artifacts: Default::default(),
+            requested_at,
+            dispatched_at: None,
         }
     }
+
+    /// Note that the job is being sent to a worker. If it's the first time, return how long it has
+    /// been since it was requested.
+    fn dispatch(&mut self, now: Instant) -> Option<Duration> {
+        self.dispatched_at
+            .replace(now)
+            .is_none()
+            .then(|| now.saturating_duration_since(self.requested_at))
+    }
 }
 
 struct Client<DepsT: SchedulerDeps> {
@@ -347,30 +610,54 @@ pub struct Scheduler<CacheT, DepsT: SchedulerDeps> {
     queued_requests: VecDeque<JobId>,
     worker_heap: Heap<WorkerMap<DepsT>>,
     job_statistics: JobStatisticsTimeSeries,
+    peers: Peers,
+    /// The artifacts being fetched from the blob store, along with the clients to ask to push each
+    /// one if the blob store doesn't have it. This is `None` if there is no blob store.
+    blob_store_fetches: Option<HashMap<Sha256Digest, HashSet<ClientId>>>,
+    artifact_transfer_timeout: Option<Duration>,
+    /// For each artifact clients have been asked to push, when each of those clients must have
+    /// made more progress pushing it by. This is empty if there is no artifact transfer timeout.
+    artifact_transfer_deadlines: BTreeMap<Sha256Digest, BTreeMap<ClientId, Instant>>,
+    num_completed_jobs: u64,
+    artifact_bytes_received: u64,
+    artifact_bytes_sent: u64,
+    artifact_peer_redirects: u64,
+    job_wait_seconds: Histogram,
+    job_latency_seconds: Histogram,
+    job_run_seconds: Histogram,
+}
+
+/// The average of the durations observed by `histogram`, if there were any.
+fn average_duration(histogram: &Histogram) -> Option<Duration> {
+    (histogram.count() > 0)
+        .then(|| Duration::from_secs_f64(histogram.sum() / histogram.count() as f64))
 }
 
 impl<CacheT: SchedulerCache, DepsT: SchedulerDeps> Scheduler<CacheT, DepsT> {
     fn possibly_start_jobs(&mut self, deps: &mut DepsT) {
         while !self.queued_requests.is_empty() && !self.workers.0.is_empty() {
-            let wid = self.worker_heap.peek().unwrap();
-            let worker = self.workers.0.get_mut(wid).unwrap();
+            let wid = *self.worker_heap.peek().unwrap();
+            let worker = self.workers.0.get_mut(&wid).unwrap();
 
             if worker.pending.len() == 2 * worker.slots {
                 break;
             }
 
             let jid = self.queued_requests.pop_front().unwrap();
-            let spec = &self
+            let job = self
                 .clients
-                .get(&jid.cid)
+                .get_mut(&jid.cid)
                 .unwrap()
                 .jobs
-                .get(&jid.cjid)
-                .unwrap()
-                .spec;
+                .get_mut(&jid.cjid)
+                .unwrap();
+            if let Some(wait) = job.dispatch(deps.now()) {
+                self.job_wait_seconds.observe(wait.as_secs_f64());
+            }
+            deps.job_event(jid, JobEvent::Dispatched(wid));
             deps.send_message_to_worker(
                 &mut worker.sender,
-                BrokerToWorker::EnqueueJob(jid, spec.clone()),
+                BrokerToWorker::EnqueueJob(jid, job.spec.clone()),
             );
 
             worker.pending.insert(jid).assert_is_true();
@@ -387,9 +674,14 @@ impl<CacheT: SchedulerCache, DepsT: SchedulerDeps> Scheduler<CacheT, DepsT> {
 
     fn receive_client_disconnected(&mut self, deps: &mut DepsT, id: ClientId) {
         self.cache.client_disconnected(id);
+        self.artifact_transfer_deadlines.retain(|_, deadlines| {
+            deadlines.remove(&id);
+            !deadlines.is_empty()
+        });
 
         let client = self.clients.remove(&id).unwrap();
-        for job in client.jobs.into_values() {
+        for (cjid, job) in client.jobs {
+            deps.job_event(JobId { cid: id, cjid }, JobEvent::Canceled);
             for artifact in job.acquired_artifacts {
                 self.cache.decrement_refcount(artifact);
             }
@@ -442,10 +734,29 @@ impl<CacheT: SchedulerCache, DepsT: SchedulerDeps> Scheduler<CacheT, DepsT> {
                 job.missing_artifacts
                     .insert(digest.clone(), is_manifest)
                     .assert_is_none();
-                deps.send_message_to_client(
-                    &mut client.sender,
-                    BrokerToClient::TransferArtifact(digest),
-                );
+                match &mut self.blob_store_fetches {
+                    None => {
+                        if let Some(timeout) = self.artifact_transfer_timeout {
+                            self.artifact_transfer_deadlines
+                                .entry(digest.clone())
+                                .or_default()
+                                .insert(jid.cid, deps.now() + timeout);
+                        }
+                        deps.send_message_to_client(
+                            &mut client.sender,
+                            BrokerToClient::TransferArtifact(digest),
+                        )
+                    }
+                    Some(fetches) => match fetches.entry(digest.clone()) {
+                        Entry::Occupied(entry) => {
+                            entry.into_mut().insert(jid.cid);
+                        }
+                        Entry::Vacant(entry) => {
+                            entry.insert(HashSet::from([jid.cid]));
+                            deps.fetch_artifact_from_blob_store(digest);
+                        }
+                    },
+                }
             }
         }
     }
@@ -460,7 +771,11 @@ impl<CacheT: SchedulerCache, DepsT: SchedulerDeps> Scheduler<CacheT, DepsT> {
         let jid = JobId { cid, cjid };
         let client = self.clients.get_mut(&cid).unwrap();
         let layers = spec.layers.clone();
-        client.jobs.insert(cjid, Job::new(spec)).assert_is_none();
+        client
+            .jobs
+            .insert(cjid, Job::new(spec, deps.now()))
+            .assert_is_none();
+        deps.job_event(jid, JobEvent::Enqueued);
 
         for (digest, type_) in layers {
             let is_manifest = IsManifest::from(type_ == ArtifactType::Manifest);
@@ -471,6 +786,7 @@ impl<CacheT: SchedulerCache, DepsT: SchedulerDeps> Scheduler<CacheT, DepsT> {
         let job = client.jobs.get(&jid.cjid).unwrap();
         let have_all_artifacts = job.missing_artifacts.is_empty();
         if have_all_artifacts {
+            deps.job_event(jid, JobEvent::ArtifactsReady);
             self.queued_requests.push_back(jid);
             self.possibly_start_jobs(deps);
         }
@@ -478,11 +794,30 @@ impl<CacheT: SchedulerCache, DepsT: SchedulerDeps> Scheduler<CacheT, DepsT> {
 
     fn receive_client_statistics_request(&mut self, deps: &mut DepsT, cid: ClientId) {
         let worker_iter = self.workers.0.iter();
+        let cache = self.cache.metrics();
         let resp = BrokerToClient::StatisticsResponse(BrokerStatistics {
             worker_statistics: worker_iter
-                .map(|(id, w)| (*id, WorkerStatistics { slots: w.slots }))
+                .map(|(id, w)| {
+                    let running = w.pending.len().min(w.slots);
+                    let statistics = WorkerStatistics {
+                        slots: w.slots,
+                        running,
+                        pending: w.pending.len() - running,
+                    };
+                    (*id, statistics)
+                })
                 .collect(),
             job_statistics: self.job_statistics.clone(),
+            scrub_statistics: self.cache.scrub_statistics(),
+            cache_statistics: CacheStatistics {
+                entries: cache.entries,
+                bytes_used: cache.bytes_used,
+                hits: cache.hits,
+                misses: cache.misses,
+                evictions: cache.evictions,
+            },
+            average_queue_wait: average_duration(&self.job_wait_seconds),
+            average_run_duration: average_duration(&self.job_run_seconds),
         });
         deps.send_message_to_client(&mut self.clients.get_mut(&cid).unwrap().sender, resp);
     }
@@ -493,6 +828,47 @@ impl<CacheT: SchedulerCache, DepsT: SchedulerDeps> Scheduler<CacheT, DepsT> {
         deps.send_message_to_client(&mut self.clients.get_mut(&cid).unwrap().sender, resp);
     }
 
+    fn receive_client_artifact_transfer_failed(
+        &mut self,
+        deps: &mut DepsT,
+        cid: ClientId,
+        digest: Sha256Digest,
+        message: String,
+    ) {
+        self.remove_artifact_transfer_deadline(cid, &digest);
+        self.fail_jobs_waiting_for_transfer(deps, cid, digest, message);
+    }
+
+    /// Fail `cid`'s jobs that are waiting for it to push `digest`, with a system error containing
+    /// `message`.
+    fn fail_jobs_waiting_for_transfer(
+        &mut self,
+        deps: &mut DepsT,
+        cid: ClientId,
+        digest: Sha256Digest,
+        message: String,
+    ) {
+        for jid in self.cache.artifact_transfer_failed(&digest, cid) {
+            let client = self.clients.get_mut(&cid).unwrap();
+            let job = client.jobs.remove(&jid.cjid).unwrap();
+            for artifact in job.acquired_artifacts {
+                self.cache.decrement_refcount(artifact);
+            }
+            for artifact in job.missing_artifacts.into_keys() {
+                if artifact != digest {
+                    self.cache.remove_waiting_job(jid, &artifact);
+                }
+            }
+            deps.send_message_to_client(
+                &mut client.sender,
+                BrokerToClient::JobResponse(jid.cjid, Err(JobError::System(message.clone()))),
+            );
+            deps.job_event(jid, JobEvent::Completed);
+            client.num_completed_jobs += 1;
+            self.num_completed_jobs += 1;
+        }
+    }
+
     fn receive_worker_connected(
         &mut self,
         deps: &mut DepsT,
@@ -544,27 +920,37 @@ impl<CacheT: SchedulerCache, DepsT: SchedulerDeps> Scheduler<CacheT, DepsT> {
             &mut client.sender,
             BrokerToClient::JobResponse(jid.cjid, result),
         );
+        deps.job_event(jid, JobEvent::Completed);
         let job = client.jobs.remove(&jid.cjid).unwrap();
         for artifact in job.acquired_artifacts {
             self.cache.decrement_refcount(artifact);
         }
         client.num_completed_jobs += 1;
+        self.num_completed_jobs += 1;
+        let now = deps.now();
+        let latency = now.saturating_duration_since(job.requested_at);
+        self.job_latency_seconds.observe(latency.as_secs_f64());
+        let run = now.saturating_duration_since(job.dispatched_at.unwrap());
+        self.job_run_seconds.observe(run.as_secs_f64());
 
         if let Some(jid) = self.queued_requests.pop_front() {
-            let spec = &self
+            let job = self
                 .clients
-                .get(&jid.cid)
+                .get_mut(&jid.cid)
                 .unwrap()
                 .jobs
-                .get(&jid.cjid)
-                .unwrap()
-                .spec;
+                .get_mut(&jid.cjid)
+                .unwrap();
+            if let Some(wait) = job.dispatch(deps.now()) {
+                self.job_wait_seconds.observe(wait.as_secs_f64());
+            }
+            deps.job_event(jid, JobEvent::Dispatched(wid));
             // If there are any queued_requests, we can just pop one off of the front of
             // the queue and not have to update the worker's used slot count or position in the
             // workers list.
             deps.send_message_to_worker(
                 &mut worker.sender,
-                BrokerToWorker::EnqueueJob(jid, spec.clone()),
+                BrokerToWorker::EnqueueJob(jid, job.spec.clone()),
             );
             worker.pending.insert(jid);
         } else {
@@ -597,6 +983,11 @@ impl<CacheT: SchedulerCache, DepsT: SchedulerDeps> Scheduler<CacheT, DepsT> {
         size: u64,
         path: PathBuf,
     ) {
+        if let Some(fetches) = &mut self.blob_store_fetches {
+            fetches.remove(&digest);
+        }
+        self.artifact_transfer_deadlines.remove(&digest);
+        self.artifact_bytes_received += size;
         for jid in self.cache.got_artifact(digest.clone(), size, &path) {
             let client = self.clients.get_mut(&jid.cid).unwrap();
             let job = client.jobs.get_mut(&jid.cjid).unwrap();
@@ -613,6 +1004,7 @@ impl<CacheT: SchedulerCache, DepsT: SchedulerDeps> Scheduler<CacheT, DepsT> {
             let client = self.clients.get_mut(&jid.cid).unwrap();
             let job = client.jobs.get_mut(&jid.cjid).unwrap();
             if job.missing_artifacts.is_empty() {
+                deps.job_event(jid, JobEvent::ArtifactsReady);
                 self.queued_requests.push_back(jid);
             }
         }
@@ -623,18 +1015,71 @@ impl<CacheT: SchedulerCache, DepsT: SchedulerDeps> Scheduler<CacheT, DepsT> {
         &mut self,
         deps: &mut DepsT,
         digest: Sha256Digest,
+        requester: Option<SocketAddr>,
         mut sender: DepsT::WorkerArtifactFetcherSender,
     ) {
-        deps.send_message_to_worker_artifact_fetcher(
-            &mut sender,
-            self.cache.get_artifact_for_worker(&digest),
-        );
+        let peer = requester.and_then(|requester| self.peers.start_transfer(&digest, requester));
+        let result = match peer {
+            Some(peer) => {
+                self.artifact_peer_redirects += 1;
+                Ok(ArtifactForWorker::Peer(peer))
+            }
+            None => self
+                .cache
+                .get_artifact_for_worker(&digest)
+                .map(|(path, size)| {
+                    self.artifact_bytes_sent += size;
+                    ArtifactForWorker::Broker(path, size)
+                }),
+        };
+        deps.send_message_to_worker_artifact_fetcher(&mut sender, result);
+    }
+
+    fn receive_blob_store_missing_artifact(&mut self, deps: &mut DepsT, digest: Sha256Digest) {
+        let Some(cids) = self
+            .blob_store_fetches
+            .as_mut()
+            .and_then(|fetches| fetches.remove(&digest))
+        else {
+            return;
+        };
+        for cid in cids {
+            let Some(client) = self.clients.get_mut(&cid) else {
+                continue;
+            };
+            if client
+                .jobs
+                .values()
+                .any(|job| job.missing_artifacts.contains_key(&digest))
+            {
+                if let Some(timeout) = self.artifact_transfer_timeout {
+                    self.artifact_transfer_deadlines
+                        .entry(digest.clone())
+                        .or_default()
+                        .insert(cid, deps.now() + timeout);
+                }
+                deps.send_message_to_client(
+                    &mut client.sender,
+                    BrokerToClient::TransferArtifact(digest.clone()),
+                );
+            }
+        }
     }
 
     fn receive_decrement_refcount(&mut self, digest: Sha256Digest) {
         self.cache.decrement_refcount(digest);
     }
 
+    fn possibly_scrub_artifact(
+        &mut self,
+        deps: &mut DepsT,
+        artifact: Option<(Sha256Digest, PathBuf)>,
+    ) {
+        if let Some((digest, path)) = artifact {
+            deps.scrub_artifact(digest, path);
+        }
+    }
+
     fn sample_job_statistics_for_client(&self, cid: ClientId) -> JobStateCounts {
         let client = self.clients.get(&cid).unwrap();
         let jobs = &client.jobs;
@@ -664,8 +1109,85 @@ impl<CacheT: SchedulerCache, DepsT: SchedulerDeps> Scheduler<CacheT, DepsT> {
         counts
     }
 
-    fn receive_statistics_heartbeat(&mut self) {
+    fn receive_metrics_request(&mut self, deps: &mut DepsT, mut sender: DepsT::MetricsSender) {
+        let mut job_state_counts = JobStateCounts::default();
+        for cid in self.clients.keys() {
+            for (state, count) in self.sample_job_statistics_for_client(*cid) {
+                job_state_counts[state] += count;
+            }
+        }
+        job_state_counts[JobState::Complete] = self.num_completed_jobs;
+        let metrics = SchedulerMetrics {
+            job_state_counts,
+            workers: self
+                .workers
+                .0
+                .iter()
+                .map(|(wid, worker)| {
+                    let metrics = WorkerMetrics {
+                        slots: worker.slots,
+                        jobs: worker.pending.len(),
+                    };
+                    (*wid, metrics)
+                })
+                .collect(),
+            cache: self.cache.metrics(),
+            artifact_bytes_received: self.artifact_bytes_received,
+            artifact_bytes_sent: self.artifact_bytes_sent,
+            artifact_peer_redirects: self.artifact_peer_redirects,
+            job_wait_seconds: self.job_wait_seconds.clone(),
+            job_latency_seconds: self.job_latency_seconds.clone(),
+            job_run_seconds: self.job_run_seconds.clone(),
+        };
+        deps.send_metrics(&mut sender, metrics);
+    }
+
+    fn remove_artifact_transfer_deadline(&mut self, cid: ClientId, digest: &Sha256Digest) {
+        if let Some(deadlines) = self.artifact_transfer_deadlines.get_mut(digest) {
+            deadlines.remove(&cid);
+            if deadlines.is_empty() {
+                self.artifact_transfer_deadlines.remove(digest);
+            }
+        }
+    }
+
+    /// We can't tell which client is pushing an artifact, so progress on it gives all of the
+    /// clients asked to push it more time.
+    fn receive_artifact_transfer_progress(&mut self, deps: &mut DepsT, digest: Sha256Digest) {
+        let (Some(timeout), Some(deadlines)) = (
+            self.artifact_transfer_timeout,
+            self.artifact_transfer_deadlines.get_mut(&digest),
+        ) else {
+            return;
+        };
+        let deadline = deps.now() + timeout;
+        for client_deadline in deadlines.values_mut() {
+            *client_deadline = deadline;
+        }
+    }
+
+    fn receive_check_artifact_transfers(&mut self, deps: &mut DepsT) {
+        let now = deps.now();
+        let expired = self
+            .artifact_transfer_deadlines
+            .iter()
+            .flat_map(|(digest, deadlines)| {
+                deadlines
+                    .iter()
+                    .filter(|(_, deadline)| **deadline <= now)
+                    .map(|(cid, _)| (*cid, digest.clone()))
+            })
+            .collect::<Vec<_>>();
+        for (cid, digest) in expired {
+            self.remove_artifact_transfer_deadline(cid, &digest);
+            let message = format!("timed out waiting for the client to push artifact {digest}");
+            self.fail_jobs_waiting_for_transfer(deps, cid, digest, message);
+        }
+    }
+
+    fn receive_statistics_heartbeat(&mut self, deps: &mut DepsT) {
         let sample = JobStatisticsSample {
+            timestamp: deps.system_time(),
             client_to_stats: self
                 .clients
                 .keys()
@@ -701,12 +1223,19 @@ mod tests {
     enum TestMessage {
         ToClient(ClientId, BrokerToClient),
         ToWorker(WorkerId, BrokerToWorker),
-        ToWorkerArtifactFetcher(u32, Result<(PathBuf, u64), GetArtifactForWorkerError>),
+        ToWorkerArtifactFetcher(u32, Result<ArtifactForWorker, GetArtifactForWorkerError>),
         CacheGetArtifact(JobId, Sha256Digest),
         CacheGotArtifact(Sha256Digest, u64, PathBuf),
         CacheDecrementRefcount(Sha256Digest),
         CacheClientDisconnected(ClientId),
+        CacheArtifactTransferFailed(Sha256Digest, ClientId),
+        CacheRemoveWaitingJob(JobId, Sha256Digest),
         CacheGetArtifactForWorker(Sha256Digest),
+        CacheScrub(u64),
+        CacheScrubbedArtifact(Sha256Digest, Result<Sha256Digest, String>),
+        FetchFromBlobStore(Sha256Digest),
+        ScrubArtifact(Sha256Digest, PathBuf),
+        ToMetrics(u32, SchedulerMetrics),
     }
 
     use TestMessage::*;
@@ -714,16 +1243,22 @@ mod tests {
     struct TestClientSender(ClientId);
     struct TestWorkerSender(WorkerId);
     struct TestWorkerArtifactFetcherSender(u32);
+    struct TestMetricsSender(u32);
 
     #[derive(Default)]
     struct TestState {
         messages: Vec<TestMessage>,
         get_artifact_returns: HashMap<(JobId, Sha256Digest), Vec<GetArtifact>>,
         got_artifact_returns: HashMap<Sha256Digest, Vec<Vec<JobId>>>,
+        artifact_transfer_failed_returns: HashMap<Sha256Digest, Vec<Vec<JobId>>>,
         #[allow(clippy::type_complexity)]
         get_artifact_for_worker_returns:
             HashMap<Sha256Digest, Vec<Result<(PathBuf, u64), GetArtifactForWorkerError>>>,
         read_manifest_returns: HashMap<Sha256Digest, Vec<ManifestEntry>>,
+        scrub_returns: Vec<(Sha256Digest, PathBuf)>,
+        cache_metrics: CacheMetrics,
+        now: Option<Instant>,
+        job_events: Vec<(JobId, JobEvent)>,
     }
 
     impl SchedulerCache for Rc<RefCell<TestState>> {
@@ -775,6 +1310,21 @@ mod tests {
                 .messages
                 .push(CacheClientDisconnected(cid));
         }
+        fn artifact_transfer_failed(&mut self, digest: &Sha256Digest, cid: ClientId) -> Vec<JobId> {
+            self.borrow_mut()
+                .messages
+                .push(CacheArtifactTransferFailed(digest.clone(), cid));
+            self.borrow_mut()
+                .artifact_transfer_failed_returns
+                .get_mut(digest)
+                .unwrap()
+                .remove(0)
+        }
+        fn remove_waiting_job(&mut self, jid: JobId, digest: &Sha256Digest) {
+            self.borrow_mut()
+                .messages
+                .push(CacheRemoveWaitingJob(jid, digest.clone()));
+        }
         fn get_artifact_for_worker(
             &mut self,
             digest: &Sha256Digest,
@@ -788,12 +1338,33 @@ mod tests {
                 .unwrap()
                 .remove(0)
         }
+        fn scrub(&mut self, bytes: u64) -> Option<(Sha256Digest, PathBuf)> {
+            self.borrow_mut().messages.push(CacheScrub(bytes));
+            self.borrow_mut().scrub_returns.pop()
+        }
+        fn scrubbed_artifact(
+            &mut self,
+            digest: Sha256Digest,
+            result: Result<Sha256Digest, String>,
+        ) -> Option<(Sha256Digest, PathBuf)> {
+            self.borrow_mut()
+                .messages
+                .push(CacheScrubbedArtifact(digest, result));
+            self.borrow_mut().scrub_returns.pop()
+        }
+        fn scrub_statistics(&self) -> ScrubStatistics {
+            ScrubStatistics::default()
+        }
+        fn metrics(&self) -> CacheMetrics {
+            self.borrow().cache_metrics.clone()
+        }
     }
 
     impl SchedulerDeps for Rc<RefCell<TestState>> {
         type ClientSender = TestClientSender;
         type WorkerSender = TestWorkerSender;
         type WorkerArtifactFetcherSender = TestWorkerArtifactFetcherSender;
+        type MetricsSender = TestMetricsSender;
 
         fn send_message_to_client(
             &mut self,
@@ -814,12 +1385,38 @@ mod tests {
         fn send_message_to_worker_artifact_fetcher(
             &mut self,
             sender: &mut TestWorkerArtifactFetcherSender,
-            message: Result<(PathBuf, u64), GetArtifactForWorkerError>,
+            message: Result<ArtifactForWorker, GetArtifactForWorkerError>,
         ) {
             self.borrow_mut()
                 .messages
                 .push(ToWorkerArtifactFetcher(sender.0, message));
         }
+
+        fn fetch_artifact_from_blob_store(&mut self, digest: Sha256Digest) {
+            self.borrow_mut().messages.push(FetchFromBlobStore(digest));
+        }
+
+        fn scrub_artifact(&mut self, digest: Sha256Digest, path: PathBuf) {
+            self.borrow_mut().messages.push(ScrubArtifact(digest, path));
+        }
+
+        fn send_metrics(&mut self, sender: &mut TestMetricsSender, metrics: SchedulerMetrics) {
+            self.borrow_mut()
+                .messages
+                .push(ToMetrics(sender.0, metrics));
+        }
+
+        fn now(&mut self) -> Instant {
+            *self.borrow_mut().now.get_or_insert_with(Instant::now)
+        }
+
+        fn system_time(&mut self) -> SystemTime {
+            SystemTime::UNIX_EPOCH
+        }
+
+        fn job_event(&mut self, jid: JobId, event: JobEvent) {
+            self.borrow_mut().job_events.push((jid, event));
+        }
     }
 
     struct Fixture {
@@ -832,7 +1429,7 @@ mod tests {
             let test_state = Rc::new(RefCell::new(TestState::default()));
             Fixture {
                 test_state: test_state.clone(),
-                scheduler: Scheduler::new(test_state),
+                scheduler: Scheduler::new(test_state, false, None),
             }
         }
     }
@@ -862,6 +1459,42 @@ mod tests {
             result
         }
 
+        fn with_artifact_transfer_failed_returns<const N: usize>(
+            self,
+            artifact_transfer_failed_returns: [(Sha256Digest, Vec<Vec<JobId>>); N],
+        ) -> Self {
+            self.test_state
+                .borrow_mut()
+                .artifact_transfer_failed_returns = HashMap::from(artifact_transfer_failed_returns);
+            self
+        }
+
+        /// Have the cache's scrubber return the given artifacts to hash, in order, and then
+        /// nothing.
+        fn with_scrub_returns<const N: usize>(
+            self,
+            scrub_returns: [(Sha256Digest, PathBuf); N],
+        ) -> Self {
+            self.test_state.borrow_mut().scrub_returns = scrub_returns.into_iter().rev().collect();
+            self
+        }
+
+        fn with_blob_store(mut self) -> Self {
+            self.scheduler = Scheduler::new(self.test_state.clone(), true, None);
+            self
+        }
+
+        fn with_artifact_transfer_timeout(mut self, timeout: Duration) -> Self {
+            self.scheduler = Scheduler::new(self.test_state.clone(), false, Some(timeout));
+            self
+        }
+
+        fn advance_time(&mut self, duration: Duration) {
+            let mut test_state = self.test_state.borrow_mut();
+            let now = test_state.now.get_or_insert_with(Instant::now);
+            *now += duration;
+        }
+
         fn expect_messages_in_any_order(&mut self, expected: Vec<TestMessage>) {
             let messages = &mut self.test_state.borrow_mut().messages;
             for perm in expected.clone().into_iter().permutations(expected.len()) {
@@ -1989,9 +2622,9 @@ mod tests {
                 ),
             ], [])
         },
-        GetArtifactForWorker(digest![42], worker_artifact_fetcher_sender![1]) => {
+        GetArtifactForWorker(digest![42], None, worker_artifact_fetcher_sender![1]) => {
             CacheGetArtifactForWorker(digest![42]),
-            ToWorkerArtifactFetcher(1, Ok(("/a/good/path".into(), 42))),
+            ToWorkerArtifactFetcher(1, Ok(ArtifactForWorker::Broker("/a/good/path".into(), 42))),
         }
     }
 
@@ -2005,84 +2638,237 @@ mod tests {
                 ),
             ], [])
         },
-        GetArtifactForWorker(digest![42], worker_artifact_fetcher_sender![1]) => {
+        GetArtifactForWorker(digest![42], None, worker_artifact_fetcher_sender![1]) => {
             CacheGetArtifactForWorker(digest![42]),
-            ToWorkerArtifactFetcher(1, Ok(("/a/good/path".into(), 42)))
+            ToWorkerArtifactFetcher(1, Ok(ArtifactForWorker::Broker("/a/good/path".into(), 42)))
         }
     }
 
-    script_test! {
-        decrement_refcount,
-        DecrementRefcount(digest![42]) => {
-            CacheDecrementRefcount(digest![42]),
-        }
+    fn peer_addr(n: u16) -> SocketAddr {
+        SocketAddr::from(([10, 0, 0, n as u8], 5000))
     }
 
     script_test! {
-        job_statistics_waiting_for_artifacts,
+        get_artifact_for_worker_without_peers_comes_from_broker,
         {
-            Fixture::new([
-                ((jid![1, 1], digest![42]), vec![GetArtifact::Wait]),
-            ], [], [], [])
+            Fixture::new([], [], [
+                (
+                    digest![42],
+                    vec![Ok(("/a/good/path".into(), 42))]
+                ),
+            ], [])
         },
-        ClientConnected(cid![1], client_sender![1]) => {};
-        WorkerConnected(wid![1], 2, worker_sender![1]) => {};
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![1], spec![1, [(42, Tar)]])) => {
-            CacheGetArtifact(jid![1, 1], digest![42]),
-        };
-        StatisticsHeartbeat => {};
-        FromClient(cid![1], ClientToBroker::StatisticsRequest) => {
-            ToClient(cid![1], BrokerToClient::StatisticsResponse(BrokerStatistics {
-                worker_statistics: hashmap! {
-                    wid![1] => WorkerStatistics { slots: 2 }
-                },
-                job_statistics: [JobStatisticsSample {
-                    client_to_stats: hashmap! {
-                        cid![1] => enum_map! {
-                            JobState::WaitingForArtifacts => 1,
-                            JobState::Pending => 0,
-                            JobState::Running => 0,
-                            JobState::Complete => 0,
-                        }
-                    }
-                }].into_iter().collect()
-            }))
+        GetArtifactForWorker(digest![42], Some(peer_addr(1)), worker_artifact_fetcher_sender![1]) => {
+            CacheGetArtifactForWorker(digest![42]),
+            ToWorkerArtifactFetcher(1, Ok(ArtifactForWorker::Broker("/a/good/path".into(), 42)))
         }
     }
 
     script_test! {
-        job_statistics_pending,
+        get_artifact_for_worker_from_peer,
         {
-            Fixture::new([
-                ((jid![1, 1], digest![1]), vec![GetArtifact::Success]),
-            ], [], [], [])
+            Fixture::new([], [], [
+                (
+                    digest![42],
+                    vec![Ok(("/a/good/path".into(), 42))]
+                ),
+            ], [])
         },
-        ClientConnected(cid![1], client_sender![1]) => {};
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![1], spec![1, Tar])) => {
-            CacheGetArtifact(jid![1, 1], digest![1]),
+        PeerStoredArtifacts(vec![digest![42], digest![43]], peer_addr(1)) => {};
+        GetArtifactForWorker(digest![42], Some(peer_addr(1)), worker_artifact_fetcher_sender![1]) => {
+            CacheGetArtifactForWorker(digest![42]),
+            ToWorkerArtifactFetcher(1, Ok(ArtifactForWorker::Broker("/a/good/path".into(), 42)))
         };
-        StatisticsHeartbeat => {};
-        FromClient(cid![1], ClientToBroker::StatisticsRequest) => {
-            ToClient(cid![1], BrokerToClient::StatisticsResponse(BrokerStatistics {
-                worker_statistics: hashmap!{},
-                job_statistics: [JobStatisticsSample {
-                    client_to_stats: hashmap! {
-                        cid![1] => enum_map! {
-                            JobState::WaitingForArtifacts => 0,
-                            JobState::Pending => 1,
-                            JobState::Running => 0,
-                            JobState::Complete => 0,
-                        }
-                    }
-                }].into_iter().collect()
-            }))
-        }
+        GetArtifactForWorker(digest![42], Some(peer_addr(2)), worker_artifact_fetcher_sender![2]) => {
+            ToWorkerArtifactFetcher(2, Ok(ArtifactForWorker::Peer(peer_addr(1))))
+        };
+        PeerTransferDone(peer_addr(1)) => {};
     }
 
     script_test! {
-        job_statistics_running,
+        get_artifact_for_worker_without_address_comes_from_broker,
         {
-            Fixture::new([
+            Fixture::new([], [], [
+                (
+                    digest![42],
+                    vec![Ok(("/a/good/path".into(), 42))]
+                ),
+            ], [])
+        },
+        PeerStoredArtifacts(vec![digest![42]], peer_addr(1)) => {};
+        GetArtifactForWorker(digest![42], None, worker_artifact_fetcher_sender![2]) => {
+            CacheGetArtifactForWorker(digest![42]),
+            ToWorkerArtifactFetcher(2, Ok(ArtifactForWorker::Broker("/a/good/path".into(), 42)))
+        };
+    }
+
+    script_test! {
+        get_artifact_for_worker_after_peer_transfer_failed,
+        {
+            Fixture::new([], [], [
+                (
+                    digest![42],
+                    vec![Ok(("/a/good/path".into(), 42))]
+                ),
+            ], [])
+        },
+        PeerStoredArtifacts(vec![digest![42]], peer_addr(1)) => {};
+        GetArtifactForWorker(digest![42], Some(peer_addr(2)), worker_artifact_fetcher_sender![2]) => {
+            ToWorkerArtifactFetcher(2, Ok(ArtifactForWorker::Peer(peer_addr(1))))
+        };
+        PeerTransferFailed(digest![42], peer_addr(1)) => {};
+        GetArtifactForWorker(digest![42], Some(peer_addr(2)), worker_artifact_fetcher_sender![2]) => {
+            CacheGetArtifactForWorker(digest![42]),
+            ToWorkerArtifactFetcher(2, Ok(ArtifactForWorker::Broker("/a/good/path".into(), 42)))
+        };
+    }
+
+    script_test! {
+        get_artifact_for_worker_after_peer_removed_artifact,
+        {
+            Fixture::new([], [], [
+                (
+                    digest![42],
+                    vec![Ok(("/a/good/path".into(), 42))]
+                ),
+            ], [])
+        },
+        PeerStoredArtifacts(vec![digest![42]], peer_addr(1)) => {};
+        PeerRemovedArtifacts(vec![digest![42]], peer_addr(1)) => {};
+        GetArtifactForWorker(digest![42], Some(peer_addr(2)), worker_artifact_fetcher_sender![2]) => {
+            CacheGetArtifactForWorker(digest![42]),
+            ToWorkerArtifactFetcher(2, Ok(ArtifactForWorker::Broker("/a/good/path".into(), 42)))
+        };
+    }
+
+    script_test! {
+        get_artifact_for_worker_after_peer_disconnected,
+        {
+            Fixture::new([], [], [
+                (
+                    digest![42],
+                    vec![Ok(("/a/good/path".into(), 42))]
+                ),
+            ], [])
+        },
+        PeerStoredArtifacts(vec![digest![42]], peer_addr(1)) => {};
+        PeerDisconnected(peer_addr(1)) => {};
+        GetArtifactForWorker(digest![42], Some(peer_addr(2)), worker_artifact_fetcher_sender![2]) => {
+            CacheGetArtifactForWorker(digest![42]),
+            ToWorkerArtifactFetcher(2, Ok(ArtifactForWorker::Broker("/a/good/path".into(), 42)))
+        };
+    }
+
+    script_test! {
+        decrement_refcount,
+        DecrementRefcount(digest![42]) => {
+            CacheDecrementRefcount(digest![42]),
+        }
+    }
+
+    script_test! {
+        scrub_cache,
+        ScrubCache(1000) => {
+            CacheScrub(1000),
+        }
+    }
+
+    script_test! {
+        scrub_cache_hashes_artifacts_off_the_scheduler,
+        {
+            Fixture::default().with_scrub_returns([
+                (digest![1], path_buf!("/z/sha256/1.bin")),
+                (digest![2], path_buf!("/z/sha256/2.bin")),
+            ])
+        },
+        ScrubCache(1000) => {
+            CacheScrub(1000),
+            ScrubArtifact(digest![1], path_buf!("/z/sha256/1.bin")),
+        };
+        ScrubbedArtifact(digest![1], Ok(digest![1])) => {
+            CacheScrubbedArtifact(digest![1], Ok(digest![1])),
+            ScrubArtifact(digest![2], path_buf!("/z/sha256/2.bin")),
+        };
+        ScrubbedArtifact(digest![2], Err("I/O error".into())) => {
+            CacheScrubbedArtifact(digest![2], Err("I/O error".into())),
+        };
+    }
+
+    script_test! {
+        job_statistics_waiting_for_artifacts,
+        {
+            Fixture::new([
+                ((jid![1, 1], digest![42]), vec![GetArtifact::Wait]),
+            ], [], [], [])
+        },
+        ClientConnected(cid![1], client_sender![1]) => {};
+        WorkerConnected(wid![1], 2, worker_sender![1]) => {};
+        FromClient(cid![1], ClientToBroker::JobRequest(cjid![1], spec![1, [(42, Tar)]])) => {
+            CacheGetArtifact(jid![1, 1], digest![42]),
+        };
+        StatisticsHeartbeat => {};
+        FromClient(cid![1], ClientToBroker::StatisticsRequest) => {
+            ToClient(cid![1], BrokerToClient::StatisticsResponse(BrokerStatistics {
+                worker_statistics: hashmap! {
+                    wid![1] => WorkerStatistics { slots: 2, running: 0, pending: 0 }
+                },
+                job_statistics: [JobStatisticsSample {
+                    timestamp: SystemTime::UNIX_EPOCH,
+                    client_to_stats: hashmap! {
+                        cid![1] => enum_map! {
+                            JobState::WaitingForArtifacts => 1,
+                            JobState::Pending => 0,
+                            JobState::Running => 0,
+                            JobState::Complete => 0,
+                        }
+                    }
+                }].into_iter().collect(),
+                scrub_statistics: ScrubStatistics::default(),
+                cache_statistics: CacheStatistics::default(),
+                average_queue_wait: None,
+                average_run_duration: None,
+            }))
+        }
+    }
+
+    script_test! {
+        job_statistics_pending,
+        {
+            Fixture::new([
+                ((jid![1, 1], digest![1]), vec![GetArtifact::Success]),
+            ], [], [], [])
+        },
+        ClientConnected(cid![1], client_sender![1]) => {};
+        FromClient(cid![1], ClientToBroker::JobRequest(cjid![1], spec![1, Tar])) => {
+            CacheGetArtifact(jid![1, 1], digest![1]),
+        };
+        StatisticsHeartbeat => {};
+        FromClient(cid![1], ClientToBroker::StatisticsRequest) => {
+            ToClient(cid![1], BrokerToClient::StatisticsResponse(BrokerStatistics {
+                worker_statistics: hashmap!{},
+                job_statistics: [JobStatisticsSample {
+                    timestamp: SystemTime::UNIX_EPOCH,
+                    client_to_stats: hashmap! {
+                        cid![1] => enum_map! {
+                            JobState::WaitingForArtifacts => 0,
+                            JobState::Pending => 1,
+                            JobState::Running => 0,
+                            JobState::Complete => 0,
+                        }
+                    }
+                }].into_iter().collect(),
+                scrub_statistics: ScrubStatistics::default(),
+                cache_statistics: CacheStatistics::default(),
+                average_queue_wait: None,
+                average_run_duration: None,
+            }))
+        }
+    }
+
+    script_test! {
+        job_statistics_running,
+        {
+            Fixture::new([
                 ((jid![1, 1], digest![1]), vec![GetArtifact::Success]),
             ], [], [], [])
         },
@@ -2096,9 +2882,10 @@ mod tests {
         FromClient(cid![1], ClientToBroker::StatisticsRequest) => {
             ToClient(cid![1], BrokerToClient::StatisticsResponse(BrokerStatistics {
                 worker_statistics: hashmap! {
-                    wid![1] => WorkerStatistics { slots: 2 }
+                    wid![1] => WorkerStatistics { slots: 2, running: 1, pending: 0 }
                 },
                 job_statistics: [JobStatisticsSample {
+                    timestamp: SystemTime::UNIX_EPOCH,
                     client_to_stats: hashmap! {
                         cid![1] => enum_map! {
                             JobState::WaitingForArtifacts => 0,
@@ -2107,7 +2894,11 @@ mod tests {
                             JobState::Complete => 0,
                         }
                     }
-                }].into_iter().collect()
+                }].into_iter().collect(),
+                scrub_statistics: ScrubStatistics::default(),
+                cache_statistics: CacheStatistics::default(),
+                average_queue_wait: Some(Duration::ZERO),
+                average_run_duration: None,
             }))
         }
     }
@@ -2133,9 +2924,10 @@ mod tests {
         FromClient(cid![1], ClientToBroker::StatisticsRequest) => {
             ToClient(cid![1], BrokerToClient::StatisticsResponse(BrokerStatistics {
                 worker_statistics: hashmap! {
-                    wid![1] => WorkerStatistics { slots: 2 }
+                    wid![1] => WorkerStatistics { slots: 2, running: 0, pending: 0 }
                 },
                 job_statistics: [JobStatisticsSample {
+                    timestamp: SystemTime::UNIX_EPOCH,
                     client_to_stats: hashmap! {
                         cid![1] => enum_map! {
                             JobState::WaitingForArtifacts => 0,
@@ -2144,7 +2936,11 @@ mod tests {
                             JobState::Complete => 1,
                         }
                     }
-                }].into_iter().collect()
+                }].into_iter().collect(),
+                scrub_statistics: ScrubStatistics::default(),
+                cache_statistics: CacheStatistics::default(),
+                average_queue_wait: Some(Duration::ZERO),
+                average_run_duration: Some(Duration::ZERO),
             }))
         }
     }
@@ -2173,4 +2969,573 @@ mod tests {
             ))
         }
     }
+
+    script_test! {
+        artifact_transfer_failed_fails_waiting_jobs,
+        {
+            Fixture::new([
+                ((jid![1, 2], digest![42]), vec![GetArtifact::Success]),
+                ((jid![1, 2], digest![43]), vec![GetArtifact::Wait]),
+                ((jid![1, 2], digest![44]), vec![GetArtifact::Get]),
+            ], [], [], []).with_artifact_transfer_failed_returns([
+                (digest![44], vec![vec![jid![1, 2]]]),
+            ])
+        },
+        WorkerConnected(wid![1], 1, worker_sender![1]) => {};
+        ClientConnected(cid![1], client_sender![1]) => {};
+
+        FromClient(
+            cid![1],
+            ClientToBroker::JobRequest(cjid![2], spec![1, [(42, Tar), (43, Tar), (44, Tar)]])
+        ) => {
+            CacheGetArtifact(jid![1, 2], digest![42]),
+            CacheGetArtifact(jid![1, 2], digest![43]),
+            CacheGetArtifact(jid![1, 2], digest![44]),
+            ToClient(cid![1], BrokerToClient::TransferArtifact(digest![44])),
+        };
+
+        FromClient(
+            cid![1],
+            ClientToBroker::ArtifactTransferFailed(digest![44], "disk full".into())
+        ) => {
+            CacheArtifactTransferFailed(digest![44], cid![1]),
+            CacheDecrementRefcount(digest![42]),
+            CacheRemoveWaitingJob(jid![1, 2], digest![43]),
+            ToClient(cid![1], BrokerToClient::JobResponse(
+                cjid![2],
+                Err(JobError::System("disk full".into()))
+            )),
+        };
+
+        FromClient(cid![1], ClientToBroker::JobStateCountsRequest) => {
+            ToClient(cid![1], BrokerToClient::JobStateCountsResponse(
+                enum_map! {
+                    JobState::WaitingForArtifacts => 0,
+                    JobState::Pending => 0,
+                    JobState::Running => 0,
+                    JobState::Complete => 1,
+                }
+            ))
+        };
+
+        ClientDisconnected(cid![1]) => {
+            CacheClientDisconnected(cid![1]),
+        }
+    }
+
+    script_test! {
+        artifact_transfer_failed_with_no_waiting_jobs,
+        {
+            Fixture::new([
+                ((jid![1, 2], digest![42]), vec![GetArtifact::Get]),
+            ], [
+                (digest![42], vec![vec![jid![1, 2]]]),
+            ], [], []).with_artifact_transfer_failed_returns([
+                (digest![42], vec![vec![]]),
+            ])
+        },
+        WorkerConnected(wid![1], 1, worker_sender![1]) => {};
+        ClientConnected(cid![1], client_sender![1]) => {};
+
+        FromClient(cid![1], ClientToBroker::JobRequest(cjid![2], spec![1, [(42, Tar)]])) => {
+            CacheGetArtifact(jid![1, 2], digest![42]),
+            ToClient(cid![1], BrokerToClient::TransferArtifact(digest![42])),
+        };
+
+        GotArtifact(digest![42], 100, "/z/tmp/foo".into()) => {
+            CacheGotArtifact(digest![42], 100, "/z/tmp/foo".into()),
+            ToWorker(wid![1], EnqueueJob(jid![1, 2], spec![1, [(42, Tar)]])),
+        };
+
+        FromClient(
+            cid![1],
+            ClientToBroker::ArtifactTransferFailed(digest![42], "connection reset".into())
+        ) => {
+            CacheArtifactTransferFailed(digest![42], cid![1]),
+        };
+    }
+
+    script_test! {
+        blob_store_has_artifact,
+        {
+            Fixture::new([
+                ((jid![1, 2], digest![42]), vec![GetArtifact::Get]),
+            ], [
+                (digest![42], vec![vec![jid![1, 2]]]),
+            ], [], []).with_blob_store()
+        },
+        WorkerConnected(wid![1], 1, worker_sender![1]) => {};
+        ClientConnected(cid![1], client_sender![1]) => {};
+
+        FromClient(cid![1], ClientToBroker::JobRequest(cjid![2], spec![1, [(42, Tar)]])) => {
+            CacheGetArtifact(jid![1, 2], digest![42]),
+            FetchFromBlobStore(digest![42]),
+        };
+
+        GotArtifact(digest![42], 100, "/z/tmp/foo".into()) => {
+            CacheGotArtifact(digest![42], 100, "/z/tmp/foo".into()),
+            ToWorker(wid![1], EnqueueJob(jid![1, 2], spec![1, [(42, Tar)]])),
+        };
+
+        BlobStoreMissingArtifact(digest![42]) => {};
+    }
+
+    script_test! {
+        blob_store_missing_artifact_is_pushed_by_clients,
+        {
+            Fixture::new([
+                ((jid![1, 2], digest![42]), vec![GetArtifact::Get]),
+                ((jid![2, 3], digest![42]), vec![GetArtifact::Get]),
+            ], [
+                (digest![42], vec![vec![jid![1, 2], jid![2, 3]]]),
+            ], [], []).with_blob_store()
+        },
+        WorkerConnected(wid![1], 2, worker_sender![1]) => {};
+        ClientConnected(cid![1], client_sender![1]) => {};
+        ClientConnected(cid![2], client_sender![2]) => {};
+
+        FromClient(cid![1], ClientToBroker::JobRequest(cjid![2], spec![1, [(42, Tar)]])) => {
+            CacheGetArtifact(jid![1, 2], digest![42]),
+            FetchFromBlobStore(digest![42]),
+        };
+
+        FromClient(cid![2], ClientToBroker::JobRequest(cjid![3], spec![1, [(42, Tar)]])) => {
+            CacheGetArtifact(jid![2, 3], digest![42]),
+        };
+
+        BlobStoreMissingArtifact(digest![42]) => {
+            ToClient(cid![1], BrokerToClient::TransferArtifact(digest![42])),
+            ToClient(cid![2], BrokerToClient::TransferArtifact(digest![42])),
+        };
+
+        GotArtifact(digest![42], 100, "/z/tmp/foo".into()) => {
+            CacheGotArtifact(digest![42], 100, "/z/tmp/foo".into()),
+            ToWorker(wid![1], EnqueueJob(jid![1, 2], spec![1, [(42, Tar)]])),
+            ToWorker(wid![1], EnqueueJob(jid![2, 3], spec![1, [(42, Tar)]])),
+        };
+    }
+
+    script_test! {
+        blob_store_missing_artifact_is_requested_once_per_client,
+        {
+            Fixture::new([
+                ((jid![1, 2], digest![42]), vec![GetArtifact::Get]),
+                ((jid![1, 3], digest![42]), vec![GetArtifact::Get]),
+            ], [], [], []).with_blob_store()
+        },
+        ClientConnected(cid![1], client_sender![1]) => {};
+
+        FromClient(cid![1], ClientToBroker::JobRequest(cjid![2], spec![1, [(42, Tar)]])) => {
+            CacheGetArtifact(jid![1, 2], digest![42]),
+            FetchFromBlobStore(digest![42]),
+        };
+
+        FromClient(cid![1], ClientToBroker::JobRequest(cjid![3], spec![1, [(42, Tar)]])) => {
+            CacheGetArtifact(jid![1, 3], digest![42]),
+        };
+
+        BlobStoreMissingArtifact(digest![42]) => {
+            ToClient(cid![1], BrokerToClient::TransferArtifact(digest![42])),
+        };
+    }
+
+    script_test! {
+        blob_store_missing_artifact_not_pushed_by_disconnected_client,
+        {
+            Fixture::new([
+                ((jid![1, 2], digest![42]), vec![GetArtifact::Get]),
+                ((jid![2, 3], digest![42]), vec![GetArtifact::Get]),
+            ], [], [], []).with_blob_store()
+        },
+        ClientConnected(cid![1], client_sender![1]) => {};
+        ClientConnected(cid![2], client_sender![2]) => {};
+
+        FromClient(cid![1], ClientToBroker::JobRequest(cjid![2], spec![1, [(42, Tar)]])) => {
+            CacheGetArtifact(jid![1, 2], digest![42]),
+            FetchFromBlobStore(digest![42]),
+        };
+
+        FromClient(cid![2], ClientToBroker::JobRequest(cjid![3], spec![1, [(42, Tar)]])) => {
+            CacheGetArtifact(jid![2, 3], digest![42]),
+        };
+
+        ClientDisconnected(cid![1]) => {
+            CacheClientDisconnected(cid![1]),
+        };
+
+        BlobStoreMissingArtifact(digest![42]) => {
+            ToClient(cid![2], BrokerToClient::TransferArtifact(digest![42])),
+        };
+    }
+
+    #[test]
+    fn metrics_request() {
+        let mut fixture = Fixture::new(
+            [
+                ((jid![1, 1], digest![1]), vec![GetArtifact::Success]),
+                ((jid![1, 2], digest![2]), vec![GetArtifact::Success]),
+                ((jid![1, 3], digest![3]), vec![GetArtifact::Get]),
+            ],
+            [],
+            [(digest![1], vec![Ok(("/a".into(), 100))])],
+            [],
+        );
+        fixture.test_state.borrow_mut().cache_metrics = CacheMetrics {
+            entries: 2,
+            bytes_used: 300,
+            hits: 2,
+            misses: 1,
+            evictions: 4,
+        };
+        fixture.receive_message(WorkerConnected(wid![1], 1, worker_sender![1]));
+        fixture.receive_message(ClientConnected(cid![1], client_sender![1]));
+        fixture.receive_message(FromClient(
+            cid![1],
+            ClientToBroker::JobRequest(cjid![1], spec![1, Tar]),
+        ));
+        fixture.receive_message(FromClient(
+            cid![1],
+            ClientToBroker::JobRequest(cjid![3], spec![3, Tar]),
+        ));
+        fixture.advance_time(Duration::from_secs(2));
+        fixture.receive_message(FromClient(
+            cid![1],
+            ClientToBroker::JobRequest(cjid![2], spec![2, Tar]),
+        ));
+        fixture.advance_time(Duration::from_secs(3));
+        fixture.receive_message(FromWorker(wid![1], WorkerToBroker(jid![1, 1], outcome![1])));
+        fixture.receive_message(GetArtifactForWorker(
+            digest![1],
+            None,
+            worker_artifact_fetcher_sender![1],
+        ));
+        fixture.test_state.borrow_mut().messages.clear();
+
+        fixture.receive_message(MetricsRequest(TestMetricsSender(1)));
+
+        let mut job_wait_seconds = Histogram::default();
+        job_wait_seconds.observe(0.);
+        job_wait_seconds.observe(0.);
+        let mut job_latency_seconds = Histogram::default();
+        job_latency_seconds.observe(5.);
+        let mut job_run_seconds = Histogram::default();
+        job_run_seconds.observe(5.);
+        fixture.expect_messages_in_any_order(vec![ToMetrics(
+            1,
+            SchedulerMetrics {
+                job_state_counts: enum_map::enum_map! {
+                    JobState::WaitingForArtifacts => 1,
+                    JobState::Pending => 0,
+                    JobState::Running => 1,
+                    JobState::Complete => 1,
+                },
+                workers: BTreeMap::from([(wid![1], WorkerMetrics { slots: 1, jobs: 1 })]),
+                cache: CacheMetrics {
+                    entries: 2,
+                    bytes_used: 300,
+                    hits: 2,
+                    misses: 1,
+                    evictions: 4,
+                },
+                artifact_bytes_received: 0,
+                artifact_bytes_sent: 100,
+                artifact_peer_redirects: 0,
+                job_wait_seconds,
+                job_latency_seconds,
+                job_run_seconds,
+            },
+        )]);
+    }
+
+    #[test]
+    fn metrics_count_completed_jobs_of_disconnected_clients() {
+        let mut fixture = Fixture::new(
+            [((jid![1, 1], digest![1]), vec![GetArtifact::Success])],
+            [],
+            [],
+            [],
+        );
+        fixture.receive_message(WorkerConnected(wid![1], 1, worker_sender![1]));
+        fixture.receive_message(ClientConnected(cid![1], client_sender![1]));
+        fixture.receive_message(FromClient(
+            cid![1],
+            ClientToBroker::JobRequest(cjid![1], spec![1, Tar]),
+        ));
+        fixture.receive_message(FromWorker(wid![1], WorkerToBroker(jid![1, 1], outcome![1])));
+        fixture.receive_message(ClientDisconnected(cid![1]));
+        fixture.test_state.borrow_mut().messages.clear();
+
+        fixture.receive_message(MetricsRequest(TestMetricsSender(1)));
+
+        let messages = fixture.test_state.borrow().messages.clone();
+        let [ToMetrics(1, metrics)] = messages.as_slice() else {
+            panic!("unexpected messages: {messages:#?}");
+        };
+        assert_eq!(metrics.job_state_counts[JobState::Complete], 1);
+        assert_eq!(metrics.job_latency_seconds.count(), 1);
+    }
+
+    #[test]
+    fn job_events() {
+        let mut fixture = Fixture::new(
+            [
+                ((jid![1, 1], digest![1]), vec![GetArtifact::Success]),
+                ((jid![1, 2], digest![2]), vec![GetArtifact::Get]),
+                ((jid![1, 3], digest![3]), vec![GetArtifact::Success]),
+            ],
+            [(digest![2], vec![vec![jid![1, 2]]])],
+            [],
+            [],
+        );
+        fixture.receive_message(WorkerConnected(wid![1], 1, worker_sender![1]));
+        fixture.receive_message(ClientConnected(cid![1], client_sender![1]));
+        fixture.receive_message(FromClient(
+            cid![1],
+            ClientToBroker::JobRequest(cjid![1], spec![1, Tar]),
+        ));
+        fixture.receive_message(FromClient(
+            cid![1],
+            ClientToBroker::JobRequest(cjid![2], spec![2, Tar]),
+        ));
+        fixture.receive_message(FromClient(
+            cid![1],
+            ClientToBroker::JobRequest(cjid![3], spec![3, Tar]),
+        ));
+        fixture.receive_message(FromWorker(wid![1], WorkerToBroker(jid![1, 1], outcome![1])));
+        fixture.receive_message(GotArtifact(digest![2], 100, "/z/tmp/foo".into()));
+        fixture.receive_message(ClientDisconnected(cid![1]));
+
+        let mut job_events = fixture.test_state.borrow().job_events.clone();
+        // Jobs are canceled in an unspecified order when their client disconnects.
+        job_events[10..].sort_by_key(|(jid, _)| jid.cjid);
+        assert_eq!(
+            job_events,
+            vec![
+                (jid![1, 1], JobEvent::Enqueued),
+                (jid![1, 1], JobEvent::ArtifactsReady),
+                (jid![1, 1], JobEvent::Dispatched(wid![1])),
+                (jid![1, 2], JobEvent::Enqueued),
+                (jid![1, 3], JobEvent::Enqueued),
+                (jid![1, 3], JobEvent::ArtifactsReady),
+                (jid![1, 3], JobEvent::Dispatched(wid![1])),
+                (jid![1, 1], JobEvent::Completed),
+                (jid![1, 2], JobEvent::ArtifactsReady),
+                (jid![1, 2], JobEvent::Dispatched(wid![1])),
+                (jid![1, 2], JobEvent::Canceled),
+                (jid![1, 3], JobEvent::Canceled),
+            ]
+        );
+    }
+
+    #[test]
+    fn statistics_request() {
+        let mut fixture = Fixture::new(
+            [
+                ((jid![1, 1], digest![1]), vec![GetArtifact::Success]),
+                ((jid![1, 2], digest![2]), vec![GetArtifact::Success]),
+                ((jid![1, 3], digest![3]), vec![GetArtifact::Success]),
+            ],
+            [],
+            [],
+            [],
+        );
+        fixture.test_state.borrow_mut().cache_metrics = CacheMetrics {
+            entries: 3,
+            bytes_used: 300,
+            hits: 3,
+            misses: 0,
+            evictions: 2,
+        };
+        fixture.receive_message(WorkerConnected(wid![1], 1, worker_sender![1]));
+        fixture.receive_message(ClientConnected(cid![1], client_sender![1]));
+        fixture.receive_message(FromClient(
+            cid![1],
+            ClientToBroker::JobRequest(cjid![1], spec![1, Tar]),
+        ));
+        fixture.receive_message(FromClient(
+            cid![1],
+            ClientToBroker::JobRequest(cjid![2], spec![2, Tar]),
+        ));
+        fixture.receive_message(FromClient(
+            cid![1],
+            ClientToBroker::JobRequest(cjid![3], spec![3, Tar]),
+        ));
+        fixture.advance_time(Duration::from_secs(3));
+        fixture.receive_message(FromWorker(wid![1], WorkerToBroker(jid![1, 1], outcome![1])));
+        fixture.test_state.borrow_mut().messages.clear();
+
+        fixture.receive_message(FromClient(cid![1], ClientToBroker::StatisticsRequest));
+
+        let messages = &fixture.test_state.borrow().messages;
+        let [ToClient(_, BrokerToClient::StatisticsResponse(statistics))] = &messages[..] else {
+            panic!("unexpected messages: {messages:#?}");
+        };
+        assert_eq!(
+            statistics.worker_statistics,
+            HashMap::from([(
+                wid![1],
+                WorkerStatistics {
+                    slots: 1,
+                    running: 1,
+                    pending: 1
+                }
+            )])
+        );
+        assert_eq!(
+            statistics.cache_statistics,
+            CacheStatistics {
+                entries: 3,
+                bytes_used: 300,
+                hits: 3,
+                misses: 0,
+                evictions: 2,
+            }
+        );
+        assert_eq!(statistics.average_queue_wait, Some(Duration::from_secs(1)));
+        assert_eq!(
+            statistics.average_run_duration,
+            Some(Duration::from_secs(3))
+        );
+    }
+
+    #[test]
+    fn artifact_transfer_times_out() {
+        let mut fixture = Fixture::new(
+            [((jid![1, 2], digest![42]), vec![GetArtifact::Get])],
+            [],
+            [],
+            [],
+        )
+        .with_artifact_transfer_failed_returns([(digest![42], vec![vec![jid![1, 2]]])])
+        .with_artifact_transfer_timeout(Duration::from_secs(10));
+        fixture.receive_message(ClientConnected(cid![1], client_sender![1]));
+        fixture.receive_message(FromClient(
+            cid![1],
+            ClientToBroker::JobRequest(cjid![2], spec![1, [(42, Tar)]]),
+        ));
+        fixture.expect_messages_in_any_order(vec![
+            CacheGetArtifact(jid![1, 2], digest![42]),
+            ToClient(cid![1], BrokerToClient::TransferArtifact(digest![42])),
+        ]);
+
+        fixture.advance_time(Duration::from_secs(9));
+        fixture.receive_message(CheckArtifactTransfers);
+        fixture.expect_messages_in_any_order(vec![]);
+
+        fixture.advance_time(Duration::from_secs(1));
+        fixture.receive_message(CheckArtifactTransfers);
+        fixture.expect_messages_in_any_order(vec![
+            CacheArtifactTransferFailed(digest![42], cid![1]),
+            ToClient(
+                cid![1],
+                BrokerToClient::JobResponse(
+                    cjid![2],
+                    Err(JobError::System(format!(
+                        "timed out waiting for the client to push artifact {}",
+                        digest![42]
+                    ))),
+                ),
+            ),
+        ]);
+
+        fixture.advance_time(Duration::from_secs(10));
+        fixture.receive_message(CheckArtifactTransfers);
+        fixture.expect_messages_in_any_order(vec![]);
+    }
+
+    #[test]
+    fn artifact_transfer_progress_extends_deadline() {
+        let mut fixture = Fixture::new(
+            [((jid![1, 2], digest![42]), vec![GetArtifact::Get])],
+            [],
+            [],
+            [],
+        )
+        .with_artifact_transfer_failed_returns([(digest![42], vec![vec![jid![1, 2]]])])
+        .with_artifact_transfer_timeout(Duration::from_secs(10));
+        fixture.receive_message(ClientConnected(cid![1], client_sender![1]));
+        fixture.receive_message(FromClient(
+            cid![1],
+            ClientToBroker::JobRequest(cjid![2], spec![1, [(42, Tar)]]),
+        ));
+        fixture.expect_messages_in_any_order(vec![
+            CacheGetArtifact(jid![1, 2], digest![42]),
+            ToClient(cid![1], BrokerToClient::TransferArtifact(digest![42])),
+        ]);
+
+        for _ in 0..3 {
+            fixture.advance_time(Duration::from_secs(9));
+            fixture.receive_message(ArtifactTransferProgress(digest![42]));
+            fixture.receive_message(CheckArtifactTransfers);
+            fixture.expect_messages_in_any_order(vec![]);
+        }
+
+        fixture.advance_time(Duration::from_secs(10));
+        fixture.receive_message(CheckArtifactTransfers);
+        fixture.expect_messages_in_any_order(vec![
+            CacheArtifactTransferFailed(digest![42], cid![1]),
+            ToClient(
+                cid![1],
+                BrokerToClient::JobResponse(
+                    cjid![2],
+                    Err(JobError::System(format!(
+                        "timed out waiting for the client to push artifact {}",
+                        digest![42]
+                    ))),
+                ),
+            ),
+        ]);
+    }
+
+    #[test]
+    fn artifact_transfer_does_not_time_out_once_pushed() {
+        let mut fixture = Fixture::new(
+            [((jid![1, 2], digest![42]), vec![GetArtifact::Get])],
+            [(digest![42], vec![vec![jid![1, 2]]])],
+            [],
+            [],
+        )
+        .with_artifact_transfer_timeout(Duration::from_secs(10));
+        fixture.receive_message(ClientConnected(cid![1], client_sender![1]));
+        fixture.receive_message(FromClient(
+            cid![1],
+            ClientToBroker::JobRequest(cjid![2], spec![1, [(42, Tar)]]),
+        ));
+        fixture.receive_message(GotArtifact(digest![42], 100, "/z/tmp/foo".into()));
+        fixture.expect_messages_in_any_order(vec![
+            CacheGetArtifact(jid![1, 2], digest![42]),
+            ToClient(cid![1], BrokerToClient::TransferArtifact(digest![42])),
+            CacheGotArtifact(digest![42], 100, "/z/tmp/foo".into()),
+        ]);
+
+        fixture.advance_time(Duration::from_secs(10));
+        fixture.receive_message(CheckArtifactTransfers);
+        fixture.expect_messages_in_any_order(vec![]);
+    }
+
+    #[test]
+    fn artifact_transfer_does_not_time_out_after_client_disconnects() {
+        let mut fixture = Fixture::new(
+            [((jid![1, 2], digest![42]), vec![GetArtifact::Get])],
+            [],
+            [],
+            [],
+        )
+        .with_artifact_transfer_timeout(Duration::from_secs(10));
+        fixture.receive_message(ClientConnected(cid![1], client_sender![1]));
+        fixture.receive_message(FromClient(
+            cid![1],
+            ClientToBroker::JobRequest(cjid![2], spec![1, [(42, Tar)]]),
+        ));
+        fixture.receive_message(ClientDisconnected(cid![1]));
+        fixture.expect_messages_in_any_order(vec![
+            CacheGetArtifact(jid![1, 2], digest![42]),
+            ToClient(cid![1], BrokerToClient::TransferArtifact(digest![42])),
+            CacheClientDisconnected(cid![1]),
+        ]);
+
+        fixture.advance_time(Duration::from_secs(10));
+        fixture.receive_message(CheckArtifactTransfers);
+        fixture.expect_messages_in_any_order(vec![]);
+    }
 }
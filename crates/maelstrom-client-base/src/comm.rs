@@ -1,9 +1,10 @@
 use crate::{spec::Layer, ClientDriverMode};
 use maelstrom_base::{
-    stats::JobStateCounts, ArtifactType, ClientJobId, JobOutcomeResult, JobSpec, Sha256Digest,
+    proto::ArtifactCompression, stats::JobStateCounts, ArtifactType, ClientJobId, JobOutcomeResult,
+    JobSpec, Sha256Digest,
 };
 use maelstrom_container::ContainerImage;
-use maelstrom_util::config::BrokerAddr;
+use maelstrom_util::config::{BrokerAddr, LogFormat, LogLevel};
 use serde::{Deserialize, Serialize};
 use std::fmt;
 use std::path::PathBuf;
@@ -73,8 +74,12 @@ pub enum Request {
     Start {
         driver_mode: ClientDriverMode,
         broker_addr: BrokerAddr,
+        artifact_compression: ArtifactCompression,
         project_dir: PathBuf,
         cache_dir: PathBuf,
+        locked: bool,
+        log_level: LogLevel,
+        log_format: LogFormat,
     },
     AddArtifact {
         path: PathBuf,
@@ -16,6 +16,13 @@ pub type JobResponseHandler = Box<dyn FnOnce(ClientJobId, JobOutcomeResult) + Se
 pub const MANIFEST_DIR: &str = "maelstrom-manifests";
 pub const STUB_MANIFEST_DIR: &str = "maelstrom-manifests/stubs";
 pub const SYMLINK_MANIFEST_DIR: &str = "maelstrom-manifests/symlinks";
+pub const FILES_MANIFEST_DIR: &str = "maelstrom-manifests/files";
+pub const FILES_DATA_DIR: &str = "maelstrom-manifests/files/data";
+pub const SQUASH_MANIFEST_DIR: &str = "maelstrom-manifests/squash";
+pub const SQUASH_TAR_MANIFEST_DIR: &str = "maelstrom-manifests/squash/tars";
+pub const SQUASH_DATA_DIR: &str = "maelstrom-manifests/squash/data";
+pub const GIT_MANIFEST_DIR: &str = "maelstrom-manifests/git";
+pub const GIT_BLOB_DIR: &str = "maelstrom-manifests/git/blobs";
 
 #[derive(Default, Debug, Serialize, Deserialize)]
 pub enum ClientDriverMode {
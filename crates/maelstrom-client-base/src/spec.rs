@@ -8,7 +8,7 @@ pub mod substitute;
 
 use anyhow::{anyhow, Error, Result};
 use enumset::{EnumSet, EnumSetType};
-use maelstrom_base::Utf8PathBuf;
+use maelstrom_base::{JobSignal, Utf8PathBuf};
 use serde::{de, Deserialize, Serialize};
 use std::{
     collections::BTreeMap,
@@ -55,6 +55,90 @@ pub struct SymlinkSpec {
     pub target: Utf8PathBuf,
 }
 
+/// The contents of a file given inline in a job specification.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub enum FileContents {
+    /// The contents as UTF-8 text.
+    Text(String),
+    /// The contents encoded as standard base64, for files that aren't text.
+    Base64(String),
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct FileSpec {
+    pub path: Utf8PathBuf,
+    pub mode: u32,
+    pub contents: FileContents,
+}
+
+impl TryFrom<UntaggedFileSpec> for FileSpec {
+    type Error = String;
+
+    fn try_from(other: UntaggedFileSpec) -> result::Result<Self, String> {
+        Ok(Self {
+            path: other.path,
+            mode: other.mode.try_into()?,
+            contents: match other.contents {
+                UntaggedFileContents::Contents(text) => FileContents::Text(text),
+                UntaggedFileContents::Base64Contents(encoded) => FileContents::Base64(encoded),
+            },
+        })
+    }
+}
+
+/// The form of [`FileSpec`] used in job specification directives. The contents are given with
+/// either a `contents` or a `base64_contents` field, and the mode can also be given as a string of
+/// octal digits, like `"755"`.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct UntaggedFileSpec {
+    pub path: Utf8PathBuf,
+    #[serde(default)]
+    pub mode: UntaggedFileMode,
+    #[serde(flatten)]
+    pub contents: UntaggedFileContents,
+}
+
+/// The mode of an [`UntaggedFileSpec`], as either a number or a string of octal digits. It isn't
+/// checked until the [`UntaggedLayer`] is converted into a [`Layer`]. An error while deserializing
+/// the layer would only be reported as the layer not matching any kind of layer.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum UntaggedFileMode {
+    Number(u32),
+    Octal(String),
+}
+
+impl Default for UntaggedFileMode {
+    fn default() -> Self {
+        Self::Number(0o644)
+    }
+}
+
+impl TryFrom<UntaggedFileMode> for u32 {
+    type Error = String;
+
+    fn try_from(other: UntaggedFileMode) -> result::Result<Self, String> {
+        let mode = match other {
+            UntaggedFileMode::Number(mode) => mode,
+            UntaggedFileMode::Octal(mode) => u32::from_str_radix(mode.trim_start_matches("0o"), 8)
+                .map_err(|_| format!("invalid octal file mode {mode:?}"))?,
+        };
+        if mode & !0o7777 != 0 {
+            return Err(format!(
+                "file mode {mode:#o} has bits other than permission bits set"
+            ));
+        }
+        Ok(mode)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UntaggedFileContents {
+    Contents(String),
+    Base64Contents(String),
+}
+
 #[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
 pub enum Layer {
     Tar {
@@ -74,12 +158,42 @@ pub enum Layer {
     Symlinks {
         symlinks: Vec<SymlinkSpec>,
     },
+    /// Files whose contents are given inline.
+    Files {
+        files: Vec<FileSpec>,
+    },
+    /// The files in the tree of `rev` in the git repository at `repo`, read straight out of the
+    /// object database. If `paths` isn't empty, only files under those paths are included.
+    Git {
+        repo: Utf8PathBuf,
+        rev: String,
+        paths: Vec<Utf8PathBuf>,
+        prefix_options: PrefixOptions,
+    },
+    /// The given layers flattened into a single layer, so the job only has to mount one. Later
+    /// layers take precedence over earlier ones, and OCI whiteout files are applied.
+    Squash {
+        layers: Vec<Layer>,
+    },
 }
 
-impl From<UntaggedLayer> for Layer {
-    fn from(other: UntaggedLayer) -> Self {
-        match other {
+impl TryFrom<UntaggedLayer> for Layer {
+    type Error = String;
+
+    fn try_from(other: UntaggedLayer) -> result::Result<Self, String> {
+        Ok(match other {
             UntaggedLayer::Tar { path } => Self::Tar { path },
+            UntaggedLayer::Git {
+                repo,
+                rev,
+                paths,
+                prefix_options,
+            } => Self::Git {
+                repo,
+                rev,
+                paths,
+                prefix_options,
+            },
             UntaggedLayer::Glob {
                 glob,
                 prefix_options,
@@ -96,7 +210,19 @@ impl From<UntaggedLayer> for Layer {
             },
             UntaggedLayer::Stubs { stubs } => Self::Stubs { stubs },
             UntaggedLayer::Symlinks { symlinks } => Self::Symlinks { symlinks },
-        }
+            UntaggedLayer::Files { files } => Self::Files {
+                files: files
+                    .into_iter()
+                    .map(FileSpec::try_from)
+                    .collect::<result::Result<_, _>>()?,
+            },
+            UntaggedLayer::Squash { squash } => Self::Squash {
+                layers: squash
+                    .into_iter()
+                    .map(Layer::try_from)
+                    .collect::<result::Result<_, _>>()?,
+            },
+        })
     }
 }
 
@@ -107,6 +233,18 @@ pub enum UntaggedLayer {
         #[serde(rename = "tar")]
         path: Utf8PathBuf,
     },
+    // This must come before the variants with flattened fields, since those would otherwise
+    // accept a git layer and ignore its extra fields.
+    Git {
+        #[serde(rename = "git")]
+        repo: Utf8PathBuf,
+        #[serde(default = "default_git_rev")]
+        rev: String,
+        #[serde(default)]
+        paths: Vec<Utf8PathBuf>,
+        #[serde(flatten)]
+        prefix_options: PrefixOptions,
+    },
     Glob {
         glob: String,
         #[serde(flatten)]
@@ -123,6 +261,16 @@ pub enum UntaggedLayer {
     Symlinks {
         symlinks: Vec<SymlinkSpec>,
     },
+    Files {
+        files: Vec<UntaggedFileSpec>,
+    },
+    Squash {
+        squash: Vec<UntaggedLayer>,
+    },
+}
+
+fn default_git_rev() -> String {
+    "HEAD".into()
 }
 
 impl<'de> serde_with::DeserializeAs<'de, Layer> for UntaggedLayer {
@@ -130,7 +278,9 @@ impl<'de> serde_with::DeserializeAs<'de, Layer> for UntaggedLayer {
     where
         D: serde::de::Deserializer<'de>,
     {
-        Ok(UntaggedLayer::deserialize(deserializer)?.into())
+        UntaggedLayer::deserialize(deserializer)?
+            .try_into()
+            .map_err(de::Error::custom)
     }
 }
 
@@ -169,6 +319,9 @@ pub struct ImageConfig {
 
     /// Optional environment variables for the container, assumed to be in `VAR=value` format.
     pub environment: Option<Vec<String>>,
+
+    /// Optional signal used to stop the container, like `SIGTERM` or `15`.
+    pub stop_signal: Option<String>,
 }
 
 /// An enum that indicates whether a value is explicitly specified, or implicitly defined to be the
@@ -189,6 +342,7 @@ pub struct ImageOption<'a> {
     layers: Vec<PathBuf>,
     environment: Option<Vec<String>>,
     working_directory: Option<Utf8PathBuf>,
+    stop_signal: Option<String>,
 }
 
 impl<'a> ImageOption<'a> {
@@ -198,20 +352,22 @@ impl<'a> ImageOption<'a> {
         image_lookup: impl FnMut(&str) -> Result<ImageConfig>,
     ) -> Result<Self> {
         let name = image_name.as_deref();
-        let (layers, environment, working_directory) =
+        let (layers, environment, working_directory, stop_signal) =
             image_name.as_deref().map(image_lookup).transpose()?.map_or(
-                (Default::default(), Default::default(), Default::default()),
+                Default::default(),
                 |ImageConfig {
                      layers,
                      environment,
                      working_directory,
-                 }| { (layers, environment, working_directory) },
+                     stop_signal,
+                 }| { (layers, environment, working_directory, stop_signal) },
             );
         Ok(ImageOption {
             name,
             layers,
             environment,
             working_directory,
+            stop_signal,
         })
     }
 
@@ -268,6 +424,21 @@ impl<'a> ImageOption<'a> {
             .clone()
             .ok_or_else(|| anyhow!("image {} has no working directory to use", self.name()))
     }
+
+    /// Return the stop signal for the image. Unlike the other parts of the image, this is used
+    /// whenever there is an image, unless the job gives its own stop signal. If there is no image,
+    /// or the image doesn't have a stop signal, this will return `None`. If the image's stop signal
+    /// isn't one that jobs support, this will return an error.
+    pub fn stop_signal(&self) -> Result<Option<JobSignal>> {
+        self.stop_signal
+            .as_deref()
+            .map(|signal| {
+                signal
+                    .parse()
+                    .map_err(|err| anyhow!("image {} has an {err}", self.name()))
+            })
+            .transpose()
+    }
 }
 
 #[cfg(test)]
@@ -308,12 +479,17 @@ mod test {
                 layers: path_buf_vec!["42", "43"],
                 working_directory: Some("/foo".into()),
                 environment: Some(string_vec!["FOO=image-foo", "BAZ=image-baz",]),
+                stop_signal: Some(string!("SIGINT")),
             }),
             "empty" => Ok(Default::default()),
             "invalid-env" => Ok(ImageConfig {
                 environment: Some(string_vec!["FOO"]),
                 ..Default::default()
             }),
+            "invalid-stop-signal" => Ok(ImageConfig {
+                stop_signal: Some(string!("SIGWINCH")),
+                ..Default::default()
+            }),
             "invalid-layer-path" => Ok(ImageConfig {
                 layers: vec![PathBuf::from(OsStr::from_bytes(b"\xff"))],
                 ..Default::default()
@@ -347,6 +523,26 @@ mod test {
             ]),
         );
         assert_eq!(io.working_directory().unwrap(), PathBuf::from("/foo"));
+        assert_eq!(io.stop_signal().unwrap(), Some(JobSignal::Sigint));
+    }
+
+    #[test]
+    fn image_option_no_image_or_no_stop_signal() {
+        let io = ImageOption::new(&None, images).unwrap();
+        assert_eq!(io.stop_signal().unwrap(), None);
+        let image_name = Some(string!("empty"));
+        let io = ImageOption::new(&image_name, images).unwrap();
+        assert_eq!(io.stop_signal().unwrap(), None);
+    }
+
+    #[test]
+    fn image_option_invalid_stop_signal() {
+        let image_name = Some(string!("invalid-stop-signal"));
+        let io = ImageOption::new(&image_name, images).unwrap();
+        assert_error(
+            io.stop_signal().unwrap_err(),
+            r#"image invalid-stop-signal has an unsupported stop signal "SIGWINCH""#,
+        );
     }
 
     #[test]
@@ -1,5 +1,5 @@
 use anyhow::{Context as _, Result};
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use figment::{
     error::Kind,
     providers::{Env, Format, Serialized, Toml},
@@ -7,19 +7,23 @@ use figment::{
 };
 use indicatif::ProgressBar;
 use maelstrom_base::{
-    ClientJobId, JobEffects, JobError, JobOutcome, JobOutcomeResult, JobOutputResult, JobStatus,
+    proto::ArtifactCompression, ClientJobId, JobEffects, JobError, JobOutcome, JobOutcomeResult,
+    JobOutputResult, JobStatus,
 };
 use maelstrom_client::{
     spec::{std_env_lookup, ImageConfig},
     Client, ClientBgProcess,
 };
 use maelstrom_client_cli::spec::job_spec_iter_from_reader;
+use maelstrom_container::{split_image_tag, update_container_tags};
 use maelstrom_util::{
-    config::BrokerAddr,
+    config::{BrokerAddr, LogFormat, LogLevel},
+    log,
     process::{ExitCode, ExitCodeAccumulator},
 };
 use serde::{Deserialize, Serialize};
 use serde_with::skip_serializing_none;
+use slog::{debug, Logger};
 use std::{
     cell::RefCell,
     io::{self, Read, Write as _},
@@ -57,12 +61,53 @@ struct CliOptions {
     /// Socket address of broker. Examples: "[::]:5000", "host.example.com:2000".
     #[arg(long, short = 'b', value_name = "SOCKADDR")]
     broker: Option<String>,
+
+    /// How to compress artifacts pushed to the broker. One of none, gzip, or zstd. If the broker
+    /// doesn't support it, artifacts are pushed uncompressed.
+    #[arg(long, value_name = "COMPRESSION")]
+    artifact_compression: Option<ArtifactCompression>,
+
+    /// Fail if a container image tag isn't already pinned in maelstrom-container-tags.lock,
+    /// instead of resolving it and adding it to the lockfile.
+    #[arg(long)]
+    locked: bool,
+
+    /// Minimum log level to output.
+    #[arg(long, value_name = "LEVEL", value_enum)]
+    log_level: Option<LogLevel>,
+
+    /// The format to write log messages in.
+    #[arg(long, value_name = "FORMAT", value_enum)]
+    log_format: Option<LogFormat>,
+
+    /// If no command is given, job specifications are read from stdin and run.
+    #[command(subcommand)]
+    command: Option<CliCommand>,
+}
+
+#[derive(Subcommand)]
+enum CliCommand {
+    /// Update the digests pinned in maelstrom-container-tags.lock to what their tags currently
+    /// resolve to, printing the changes.
+    UpdateContainerTags {
+        /// Only update these images. Each is either a name, which selects all of its tags, or a
+        /// "name:tag" pair. If none are given, every pinned tag is updated.
+        #[arg(value_name = "IMAGE")]
+        images: Vec<String>,
+
+        /// Don't modify the lockfile. Exit with a failure if any pinned digest is out of date.
+        #[arg(long)]
+        check: bool,
+    },
 }
 
 impl CliOptions {
     fn to_config_options(&self) -> ConfigOptions {
         ConfigOptions {
             broker: self.broker.clone(),
+            artifact_compression: self.artifact_compression,
+            log_level: self.log_level,
+            log_format: self.log_format,
         }
     }
 }
@@ -72,15 +117,47 @@ impl CliOptions {
 pub struct Config {
     /// Socket address of broker.
     pub broker: BrokerAddr,
+
+    /// How to compress artifacts pushed to the broker.
+    pub artifact_compression: ArtifactCompression,
+
+    /// Minimum log level to output.
+    pub log_level: LogLevel,
+
+    /// The format to write log messages in.
+    pub log_format: LogFormat,
 }
 
 #[skip_serializing_none]
-#[derive(Default, Serialize)]
+#[derive(Serialize)]
 pub struct ConfigOptions {
     pub broker: Option<String>,
+    pub artifact_compression: Option<ArtifactCompression>,
+    pub log_level: Option<LogLevel>,
+    pub log_format: Option<LogFormat>,
 }
 
-fn print_effects(cjid: ClientJobId, JobEffects { stdout, stderr }: JobEffects) -> Result<()> {
+impl Default for ConfigOptions {
+    fn default() -> Self {
+        ConfigOptions {
+            broker: None,
+            // Test binaries compress very well, so it's almost always worth compressing artifacts.
+            artifact_compression: Some(ArtifactCompression::Zstd),
+            log_level: Some(LogLevel::Warning),
+            log_format: Some(LogFormat::Text),
+        }
+    }
+}
+
+fn print_effects(
+    cjid: ClientJobId,
+    JobEffects {
+        stdout,
+        stderr,
+        diagnostics,
+        duration,
+    }: JobEffects,
+) -> Result<()> {
     match stdout {
         JobOutputResult::None => {}
         JobOutputResult::Inline(bytes) => {
@@ -102,10 +179,29 @@ fn print_effects(cjid: ClientJobId, JobEffects { stdout, stderr }: JobEffects) -
             eprintln!("job {cjid}: stderr truncated, {truncated} bytes lost");
         }
     }
+    match diagnostics {
+        JobOutputResult::None => {}
+        JobOutputResult::Inline(bytes) => {
+            eprintln!("job {cjid}: stack dump:");
+            io::stderr().lock().write_all(&bytes)?;
+        }
+        JobOutputResult::Truncated { first, truncated } => {
+            eprintln!("job {cjid}: stack dump:");
+            io::stderr().lock().write_all(&first)?;
+            eprintln!("job {cjid}: stack dump truncated, {truncated} bytes lost");
+        }
+    }
+    eprintln!("job {cjid}: ran for {:.3}s", duration.as_secs_f64());
     Ok(())
 }
 
-fn visitor(cjid: ClientJobId, result: JobOutcomeResult, accum: Arc<ExitCodeAccumulator>) {
+fn visitor(
+    cjid: ClientJobId,
+    result: JobOutcomeResult,
+    accum: Arc<ExitCodeAccumulator>,
+    log: Logger,
+) {
+    debug!(log, "job completed"; "cjid" => %cjid);
     match result {
         Ok(JobOutcome::Completed { status, effects }) => {
             print_effects(cjid, effects).ok();
@@ -129,6 +225,21 @@ fn visitor(cjid: ClientJobId, result: JobOutcomeResult, accum: Arc<ExitCodeAccum
             eprintln!("job {cjid}: timed out");
             accum.add(ExitCode::FAILURE);
         }
+        Ok(JobOutcome::TimedOutGracefully { status, effects }) => {
+            print_effects(cjid, effects).ok();
+            io::stdout().lock().flush().ok();
+            match status {
+                JobStatus::Exited(code) => {
+                    eprintln!(
+                        "job {cjid}: timed out, then exited with code {code} after its stop signal"
+                    )
+                }
+                JobStatus::Signaled(signum) => {
+                    eprintln!("job {cjid}: timed out, then was killed by signal {signum}")
+                }
+            }
+            accum.add(ExitCode::FAILURE);
+        }
         Err(JobError::Execution(err)) => {
             eprintln!("job {cjid}: execution error: {err}");
             accum.add(ExitCode::FAILURE);
@@ -151,6 +262,9 @@ fn main() -> Result<ExitCode> {
     let bg_proc = ClientBgProcess::new_from_fork()?;
 
     let cli_options = CliOptions::parse();
+    if let Some(CliCommand::UpdateContainerTags { images, check }) = &cli_options.command {
+        return update_container_tags(".", images, *check);
+    }
     let print_config = cli_options.print_config;
     let config: Config = Figment::new()
         .merge(Serialized::defaults(ConfigOptions::default()))
@@ -172,12 +286,25 @@ fn main() -> Result<ExitCode> {
         println!("{config:#?}");
         return Ok(ExitCode::SUCCESS);
     }
+    let log = log::root_logger(config.log_level, config.log_format);
+    debug!(log, "started"; "config" => ?config);
     let accum = Arc::new(ExitCodeAccumulator::default());
-    let client = Client::new(bg_proc, Default::default(), config.broker, ".", cache_dir())?;
+    let client = Client::new(
+        bg_proc,
+        Default::default(),
+        config.broker,
+        config.artifact_compression,
+        ".",
+        cache_dir(),
+        cli_options.locked,
+        config.log_level,
+        config.log_format,
+    )?;
     let client = RefCell::new(client);
     let reader: Box<dyn Read> = Box::new(io::stdin().lock());
     let image_lookup = |image: &str| {
-        let (image, version) = image.split_once(':').unwrap_or((image, "latest"));
+        let (image, version) = split_image_tag(image);
+        let version = version.unwrap_or("latest");
         let prog = ProgressBar::hidden();
         let mut client = client.borrow_mut();
         let image = client.get_container_image(image, version, prog)?;
@@ -185,6 +312,7 @@ fn main() -> Result<ExitCode> {
             layers: image.layers.clone(),
             environment: image.env().cloned(),
             working_directory: image.working_dir().map(From::from),
+            stop_signal: image.stop_signal().cloned(),
         })
     };
     let job_specs = job_spec_iter_from_reader(
@@ -195,9 +323,10 @@ fn main() -> Result<ExitCode> {
     );
     for job_spec in job_specs {
         let accum_clone = accum.clone();
+        let log_clone = log.clone();
         client.borrow_mut().add_job(
             job_spec?,
-            Box::new(move |cjid, result| visitor(cjid, result, accum_clone)),
+            Box::new(move |cjid, result| visitor(cjid, result, accum_clone, log_clone)),
         )?;
     }
     client.into_inner().wait_for_outstanding_jobs()?;
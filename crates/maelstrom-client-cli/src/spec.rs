@@ -1,7 +1,8 @@
 use anyhow::{anyhow, Error, Result};
 use maelstrom_base::{
-    ArtifactType, EnumSet, GroupId, JobDevice, JobDeviceListDeserialize, JobMount, JobSpec,
-    NonEmpty, Sha256Digest, Timeout, UserId, Utf8PathBuf,
+    ArtifactType, EnumSet, GroupId, JobDevice, JobDeviceListDeserialize, JobMount, JobNetwork,
+    JobRlimit, JobSeccomp, JobSignal, JobSpec, NonEmpty, Sha256Digest, Timeout, UserId,
+    Utf8PathBuf,
 };
 use maelstrom_client::spec::{
     incompatible, substitute, Image, ImageConfig, ImageOption, ImageUse, Layer, PossiblyImage,
@@ -66,13 +67,20 @@ struct Job {
     added_layers: Vec<Layer>,
     devices: Option<EnumSet<JobDeviceListDeserialize>>,
     mounts: Option<Vec<JobMount>>,
-    enable_loopback: Option<bool>,
+    network: Option<JobNetwork>,
     enable_writable_file_system: Option<bool>,
     working_directory: Option<PossiblyImage<Utf8PathBuf>>,
     user: Option<UserId>,
     group: Option<GroupId>,
     image: Option<String>,
     timeout: Option<u32>,
+    stop_signal: Option<JobSignal>,
+    grace_period: Option<u32>,
+    stack_dump_on_timeout: Option<bool>,
+    seccomp: Option<JobSeccomp>,
+    rlimits: Option<Vec<JobRlimit>>,
+    hostname: Option<String>,
+    domainname: Option<String>,
 }
 
 impl Job {
@@ -87,13 +95,20 @@ impl Job {
             added_environment: Default::default(),
             devices: None,
             mounts: None,
-            enable_loopback: None,
+            network: None,
             enable_writable_file_system: None,
             working_directory: None,
             user: None,
             group: None,
             image: None,
             timeout: None,
+            stop_signal: None,
+            grace_period: None,
+            stack_dump_on_timeout: None,
+            seccomp: None,
+            rlimits: None,
+            hostname: None,
+            domainname: None,
         }
     }
 
@@ -145,6 +160,10 @@ impl Job {
             Some(PossiblyImage::Explicit(working_directory)) => working_directory,
             Some(PossiblyImage::Image) => image.working_directory()?,
         };
+        let stop_signal = match self.stop_signal {
+            Some(stop_signal) => Some(stop_signal),
+            None => image.stop_signal()?,
+        };
         Ok(JobSpec {
             program: self.program,
             arguments: self.arguments.unwrap_or_default(),
@@ -157,12 +176,19 @@ impl Job {
                 .map(JobDevice::from)
                 .collect(),
             mounts: self.mounts.unwrap_or_default(),
-            enable_loopback: self.enable_loopback.unwrap_or_default(),
+            network: self.network.unwrap_or_default(),
             enable_writable_file_system: self.enable_writable_file_system.unwrap_or_default(),
             working_directory,
             user: self.user.unwrap_or(UserId::from(0)),
             group: self.group.unwrap_or(GroupId::from(0)),
             timeout: self.timeout.and_then(Timeout::new),
+            stop_signal,
+            grace_period: self.grace_period,
+            stack_dump_on_timeout: self.stack_dump_on_timeout.unwrap_or_default(),
+            seccomp: self.seccomp,
+            rlimits: self.rlimits.unwrap_or_default(),
+            hostname: self.hostname,
+            domainname: self.domainname,
         })
     }
 }
@@ -178,6 +204,7 @@ enum JobField {
     AddedLayers,
     Devices,
     Mounts,
+    Network,
     EnableLoopback,
     EnableWritableFileSystem,
     WorkingDirectory,
@@ -185,6 +212,13 @@ enum JobField {
     Group,
     Image,
     Timeout,
+    StopSignal,
+    GracePeriod,
+    StackDumpOnTimeout,
+    Seccomp,
+    Rlimits,
+    Hostname,
+    Domainname,
 }
 
 struct JobVisitor;
@@ -223,6 +257,7 @@ impl<'de> de::Visitor<'de> for JobVisitor {
         let mut added_layers = None;
         let mut devices = None;
         let mut mounts = None;
+        let mut network = None;
         let mut enable_loopback = None;
         let mut enable_writable_file_system = None;
         let mut working_directory = None;
@@ -230,6 +265,13 @@ impl<'de> de::Visitor<'de> for JobVisitor {
         let mut group = None;
         let mut image = None;
         let mut timeout = None;
+        let mut stop_signal = None;
+        let mut grace_period = None;
+        let mut stack_dump_on_timeout = None;
+        let mut seccomp = None;
+        let mut rlimits = None;
+        let mut hostname = None;
+        let mut domainname = None;
         while let Some(key) = map.next_key()? {
             match key {
                 JobField::Program => {
@@ -288,6 +330,9 @@ impl<'de> de::Visitor<'de> for JobVisitor {
                 JobField::Mounts => {
                     mounts = Some(map.next_value()?);
                 }
+                JobField::Network => {
+                    network = Some(map.next_value()?);
+                }
                 JobField::EnableLoopback => {
                     enable_loopback = Some(map.next_value()?);
                 }
@@ -313,6 +358,27 @@ impl<'de> de::Visitor<'de> for JobVisitor {
                 JobField::Timeout => {
                     timeout = Some(map.next_value()?);
                 }
+                JobField::StopSignal => {
+                    stop_signal = Some(map.next_value()?);
+                }
+                JobField::GracePeriod => {
+                    grace_period = Some(map.next_value()?);
+                }
+                JobField::StackDumpOnTimeout => {
+                    stack_dump_on_timeout = Some(map.next_value()?);
+                }
+                JobField::Seccomp => {
+                    seccomp = Some(map.next_value()?);
+                }
+                JobField::Rlimits => {
+                    rlimits = Some(map.next_value()?);
+                }
+                JobField::Hostname => {
+                    hostname = Some(map.next_value()?);
+                }
+                JobField::Domainname => {
+                    domainname = Some(map.next_value()?);
+                }
                 JobField::Image => {
                     let i = map.next_value::<Image>()?;
                     image = Some(i.name);
@@ -344,6 +410,17 @@ impl<'de> de::Visitor<'de> for JobVisitor {
                 }
             }
         }
+        // `enable_loopback` predates `network`, and is still accepted as a shorthand for it.
+        let network = match (network, enable_loopback) {
+            (Some(_), Some(_)) => {
+                return Err(de::Error::custom(format_args!(
+                    "field `enable_loopback` cannot be set with `network` field"
+                )));
+            }
+            (None, Some(true)) => Some(JobNetwork::Loopback),
+            (None, Some(false)) => Some(JobNetwork::Disabled),
+            (network, None) => network,
+        };
         Ok(Job {
             program: program.ok_or_else(|| de::Error::missing_field("program"))?,
             arguments,
@@ -353,13 +430,20 @@ impl<'de> de::Visitor<'de> for JobVisitor {
             added_layers: added_layers.unwrap_or_default(),
             devices,
             mounts,
-            enable_loopback,
+            network,
             enable_writable_file_system,
             working_directory,
             user,
             group,
             image,
             timeout,
+            stop_signal,
+            grace_period,
+            stack_dump_on_timeout,
+            seccomp,
+            rlimits,
+            hostname,
+            domainname,
         })
     }
 }
@@ -377,7 +461,12 @@ impl<'de> de::Deserialize<'de> for Job {
 mod test {
     use super::*;
     use assert_matches::assert_matches;
-    use maelstrom_base::{enum_set, nonempty, JobMountFsType};
+    use maelstrom_base::{
+        enum_set, nonempty, JobMountFsType, JobNatOptions, JobPortForward, JobPortProtocol,
+        JobRlimitResource, JobRlimitValue, JobSeccompAction, JobSeccompPolicy, JobSeccompRule,
+        TmpfsOptions,
+    };
+    use maelstrom_client::spec::{FileContents, FileSpec};
     use maelstrom_test::{digest, path_buf_vec, string, string_vec, tar_layer, utf8_path_buf};
 
     fn layer_mapper(layer: Layer) -> Result<(Sha256Digest, ArtifactType)> {
@@ -403,11 +492,17 @@ mod test {
                 layers: path_buf_vec!["42", "43"],
                 working_directory: Some("/foo".into()),
                 environment: Some(string_vec!["FOO=image-foo", "BAZ=image-baz",]),
+                ..Default::default()
             }),
             "image-with-env-substitutions" => Ok(ImageConfig {
                 environment: Some(string_vec!["PATH=$env{PATH}"]),
                 ..Default::default()
             }),
+            "image-with-stop-signal" => Ok(ImageConfig {
+                layers: path_buf_vec!["42"],
+                stop_signal: Some(string!("SIGINT")),
+                ..Default::default()
+            }),
             "empty" => Ok(Default::default()),
             _ => Err(anyhow!("no container named {name} found")),
         }
@@ -434,7 +529,7 @@ mod test {
                 ]))),
                 devices: Some(enum_set! {JobDeviceListDeserialize::Null}),
                 mounts: Some(vec![JobMount {
-                    fs_type: JobMountFsType::Tmp,
+                    fs_type: JobMountFsType::Tmp(TmpfsOptions::default()),
                     mount_point: utf8_path_buf!("/tmp"),
                 }]),
                 working_directory: Some(PossiblyImage::Explicit("/working-directory".into())),
@@ -449,7 +544,7 @@ mod test {
                 .environment(["BAR=bar", "FOO=foo"])
                 .devices(enum_set! {JobDevice::Null})
                 .mounts([JobMount {
-                    fs_type: JobMountFsType::Tmp,
+                    fs_type: JobMountFsType::Tmp(TmpfsOptions::default()),
                     mount_point: utf8_path_buf!("/tmp"),
                 }])
                 .working_directory("/working-directory")
@@ -459,16 +554,16 @@ mod test {
     }
 
     #[test]
-    fn enable_loopback_into_job_spec() {
+    fn network_into_job_spec() {
         assert_eq!(
             Job {
-                enable_loopback: Some(true),
+                network: Some(JobNetwork::Loopback),
                 ..Job::new(utf8_path_buf!("program"), nonempty![tar_layer!("1")])
             }
             .into_job_spec(layer_mapper, env, images)
             .unwrap(),
             JobSpec::new("program", nonempty![(digest!(1), ArtifactType::Tar)])
-                .enable_loopback(true),
+                .network(JobNetwork::Loopback),
         );
     }
 
@@ -716,6 +811,57 @@ mod test {
         );
     }
 
+    #[test]
+    fn files_layer() {
+        assert_eq!(
+            parse_job(
+                r#"{
+                    "program": "/bin/sh",
+                    "layers": [
+                        {
+                            "files": [
+                                { "path": "/etc/foo.conf", "contents": "foo = 1\n" },
+                                { "path": "/bin/run", "mode": "755", "base64_contents": "IyEvYmluL3No" }
+                            ]
+                        }
+                    ]
+                }"#
+            )
+            .unwrap()
+            .layers,
+            PossiblyImage::Explicit(nonempty![Layer::Files {
+                files: vec![
+                    FileSpec {
+                        path: utf8_path_buf!("/etc/foo.conf"),
+                        mode: 0o644,
+                        contents: FileContents::Text(string!("foo = 1\n")),
+                    },
+                    FileSpec {
+                        path: utf8_path_buf!("/bin/run"),
+                        mode: 0o755,
+                        contents: FileContents::Base64(string!("IyEvYmluL3No")),
+                    },
+                ],
+            }]),
+        );
+    }
+
+    #[test]
+    fn files_layer_bad_mode() {
+        assert_error(
+            parse_job(
+                r#"{
+                    "program": "/bin/sh",
+                    "layers": [
+                        { "files": [ { "path": "/foo", "mode": "9", "contents": "" } ] }
+                    ]
+                }"#,
+            )
+            .unwrap_err(),
+            r#"invalid octal file mode "9""#,
+        );
+    }
+
     #[test]
     fn added_layers_before_image_with_layers() {
         assert_error(
@@ -1045,12 +1191,84 @@ mod test {
                 nonempty![(digest!(1), ArtifactType::Tar)]
             )
             .mounts([JobMount {
-                fs_type: JobMountFsType::Tmp,
+                fs_type: JobMountFsType::Tmp(TmpfsOptions::default()),
+                mount_point: utf8_path_buf!("/tmp"),
+            }])
+        )
+    }
+
+    #[test]
+    fn tmpfs_mount_with_options() {
+        assert_eq!(
+            parse_job(
+                r#"{
+                    "program": "/bin/sh",
+                    "layers": [ { "tar": "1" } ],
+                    "mounts": [
+                        {
+                            "fs_type": { "tmp": { "size": "64KiB", "nr_inodes": 10, "mode": "700" } },
+                            "mount_point": "/tmp"
+                        }
+                    ]
+                }"#,
+            )
+            .unwrap()
+            .into_job_spec(layer_mapper, env, images)
+            .unwrap(),
+            JobSpec::new(
+                string!("/bin/sh"),
+                nonempty![(digest!(1), ArtifactType::Tar)]
+            )
+            .mounts([JobMount {
+                fs_type: JobMountFsType::Tmp(TmpfsOptions {
+                    size: Some(64 << 10),
+                    nr_inodes: Some(10),
+                    mode: Some(0o700),
+                }),
                 mount_point: utf8_path_buf!("/tmp"),
             }])
         )
     }
 
+    #[test]
+    fn bind_mount() {
+        assert_eq!(
+            parse_job(
+                r#"{
+                    "program": "/bin/sh",
+                    "layers": [ { "tar": "1" } ],
+                    "mounts": [
+                        {
+                            "fs_type": { "bind": { "source": "/data", "read_only": true } },
+                            "mount_point": "/data"
+                        },
+                        { "fs_type": "devpts", "mount_point": "/dev/pts" }
+                    ]
+                }"#,
+            )
+            .unwrap()
+            .into_job_spec(layer_mapper, env, images)
+            .unwrap(),
+            JobSpec::new(
+                string!("/bin/sh"),
+                nonempty![(digest!(1), ArtifactType::Tar)]
+            )
+            .mounts([
+                JobMount {
+                    fs_type: JobMountFsType::Bind {
+                        source: utf8_path_buf!("/data"),
+                        read_only: true,
+                    },
+                    mount_point: utf8_path_buf!("/data"),
+                },
+                JobMount {
+                    fs_type: JobMountFsType::Devpts,
+                    mount_point: utf8_path_buf!("/dev/pts"),
+                },
+            ])
+        )
+    }
+
     #[test]
     fn enable_loopback() {
         assert_eq!(
@@ -1068,7 +1286,187 @@ mod test {
                 string!("/bin/sh"),
                 nonempty![(digest!(1), ArtifactType::Tar)]
             )
-            .enable_loopback(true),
+            .network(JobNetwork::Loopback),
+        )
+    }
+
+    #[test]
+    fn network() {
+        assert_eq!(
+            parse_job(
+                r#"{
+                    "program": "/bin/sh",
+                    "layers": [ { "tar": "1" } ],
+                    "network": "nat"
+                }"#,
+            )
+            .unwrap()
+            .into_job_spec(layer_mapper, env, images)
+            .unwrap(),
+            JobSpec::new(
+                string!("/bin/sh"),
+                nonempty![(digest!(1), ArtifactType::Tar)]
+            )
+            .network(JobNetwork::Nat(JobNatOptions::default())),
+        )
+    }
+
+    #[test]
+    fn network_nat_with_options() {
+        assert_eq!(
+            parse_job(
+                r#"{
+                    "program": "/bin/sh",
+                    "layers": [ { "tar": "1" } ],
+                    "network": {
+                        "nat": {
+                            "port_forwards": [
+                                { "host_port": 8080, "job_port": 80 },
+                                { "protocol": "udp", "host_port": 5353, "job_port": 53 }
+                            ],
+                            "allowed_hosts": [ "mirror.example.com", "10.1.0.0/16" ]
+                        }
+                    }
+                }"#,
+            )
+            .unwrap()
+            .into_job_spec(layer_mapper, env, images)
+            .unwrap(),
+            JobSpec::new(
+                string!("/bin/sh"),
+                nonempty![(digest!(1), ArtifactType::Tar)]
+            )
+            .network(JobNetwork::Nat(JobNatOptions {
+                port_forwards: vec![
+                    JobPortForward {
+                        protocol: JobPortProtocol::Tcp,
+                        host_port: 8080,
+                        job_port: 80,
+                    },
+                    JobPortForward {
+                        protocol: JobPortProtocol::Udp,
+                        host_port: 5353,
+                        job_port: 53,
+                    },
+                ],
+                allowed_hosts: vec![string!("mirror.example.com"), string!("10.1.0.0/16")],
+            })),
+        )
+    }
+
+    #[test]
+    fn network_and_enable_loopback() {
+        assert_error(
+            parse_job(
+                r#"{
+                    "program": "/bin/sh",
+                    "layers": [ { "tar": "1" } ],
+                    "network": "loopback",
+                    "enable_loopback": true
+                }"#,
+            )
+            .unwrap_err(),
+            "field `enable_loopback` cannot be set with `network` field",
+        );
+    }
+
+    #[test]
+    fn seccomp() {
+        assert_eq!(
+            parse_job(
+                r#"{
+                    "program": "/bin/sh",
+                    "layers": [ { "tar": "1" } ],
+                    "seccomp": "default"
+                }"#,
+            )
+            .unwrap()
+            .into_job_spec(layer_mapper, env, images)
+            .unwrap(),
+            JobSpec::new(
+                string!("/bin/sh"),
+                nonempty![(digest!(1), ArtifactType::Tar)]
+            )
+            .seccomp(JobSeccomp::Named(string!("default"))),
+        )
+    }
+
+    #[test]
+    fn rlimits() {
+        assert_eq!(
+            parse_job(
+                r#"{
+                    "program": "/bin/sh",
+                    "layers": [ { "tar": "1" } ],
+                    "rlimits": [
+                        { "resource": "nofile", "soft": 1024, "hard": "unlimited" }
+                    ]
+                }"#,
+            )
+            .unwrap()
+            .into_job_spec(layer_mapper, env, images)
+            .unwrap(),
+            JobSpec::new(
+                string!("/bin/sh"),
+                nonempty![(digest!(1), ArtifactType::Tar)]
+            )
+            .rlimits([JobRlimit {
+                resource: JobRlimitResource::Nofile,
+                soft: JobRlimitValue::Limited(1024),
+                hard: JobRlimitValue::Unlimited,
+            }]),
+        )
+    }
+
+    #[test]
+    fn hostname_and_domainname() {
+        assert_eq!(
+            parse_job(
+                r#"{
+                    "program": "/bin/sh",
+                    "layers": [ { "tar": "1" } ],
+                    "hostname": "foo",
+                    "domainname": "example.com"
+                }"#,
+            )
+            .unwrap()
+            .into_job_spec(layer_mapper, env, images)
+            .unwrap(),
+            JobSpec::new(
+                string!("/bin/sh"),
+                nonempty![(digest!(1), ArtifactType::Tar)]
+            )
+            .hostname(string!("foo"))
+            .domainname(string!("example.com")),
+        )
+    }
+
+    #[test]
+    fn inline_seccomp() {
+        assert_eq!(
+            parse_job(
+                r#"{
+                    "program": "/bin/sh",
+                    "layers": [ { "tar": "1" } ],
+                    "seccomp": {
+                        "rules": [ { "syscalls": [ "mount", "umount2" ], "action": { "errno": 1 } } ]
+                    }
+                }"#,
+            )
+            .unwrap()
+            .into_job_spec(layer_mapper, env, images)
+            .unwrap(),
+            JobSpec::new(
+                string!("/bin/sh"),
+                nonempty![(digest!(1), ArtifactType::Tar)]
+            )
+            .seccomp(JobSeccomp::Inline(JobSeccompPolicy {
+                default_action: JobSeccompAction::Allow,
+                rules: vec![JobSeccompRule {
+                    syscalls: vec![string!("mount"), string!("umount2")],
+                    action: JobSeccompAction::Errno(1),
+                }],
+            })),
         )
     }
 
@@ -1259,4 +1657,91 @@ mod test {
             .timeout(Timeout::new(0)),
         )
     }
+
+    #[test]
+    fn stop_signal_and_grace_period() {
+        assert_eq!(
+            parse_job(
+                r#"{
+                    "program": "/bin/sh",
+                    "layers": [ { "tar": "1" } ],
+                    "stop_signal": "SIGTERM",
+                    "grace_period": 5
+                }"#,
+            )
+            .unwrap()
+            .into_job_spec(layer_mapper, env, images)
+            .unwrap(),
+            JobSpec::new(
+                string!("/bin/sh"),
+                nonempty![(digest!(1), ArtifactType::Tar)]
+            )
+            .stop_signal(JobSignal::Sigterm)
+            .grace_period(5),
+        )
+    }
+
+    #[test]
+    fn stack_dump_on_timeout() {
+        assert_eq!(
+            parse_job(
+                r#"{
+                    "program": "/bin/sh",
+                    "layers": [ { "tar": "1" } ],
+                    "stack_dump_on_timeout": true
+                }"#,
+            )
+            .unwrap()
+            .into_job_spec(layer_mapper, env, images)
+            .unwrap(),
+            JobSpec::new(
+                string!("/bin/sh"),
+                nonempty![(digest!(1), ArtifactType::Tar)]
+            )
+            .stack_dump_on_timeout(true),
+        )
+    }
+
+    #[test]
+    fn stop_signal_from_image() {
+        assert_eq!(
+            parse_job(
+                r#"{
+                    "program": "/bin/sh",
+                    "layers": [ { "tar": "1" } ],
+                    "image": { "name": "image-with-stop-signal", "use": [] }
+                }"#,
+            )
+            .unwrap()
+            .into_job_spec(layer_mapper, env, images)
+            .unwrap(),
+            JobSpec::new(
+                string!("/bin/sh"),
+                nonempty![(digest!(1), ArtifactType::Tar)]
+            )
+            .stop_signal(JobSignal::Sigint),
+        )
+    }
+
+    #[test]
+    fn stop_signal_overrides_image() {
+        assert_eq!(
+            parse_job(
+                r#"{
+                    "program": "/bin/sh",
+                    "layers": [ { "tar": "1" } ],
+                    "image": { "name": "image-with-stop-signal", "use": [] },
+                    "stop_signal": "SIGUSR1"
+                }"#,
+            )
+            .unwrap()
+            .into_job_spec(layer_mapper, env, images)
+            .unwrap(),
+            JobSpec::new(
+                string!("/bin/sh"),
+                nonempty![(digest!(1), ArtifactType::Tar)]
+            )
+            .stop_signal(JobSignal::Sigusr1),
+        )
+    }
 }
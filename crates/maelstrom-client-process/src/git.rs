@@ -0,0 +1,187 @@
+//! Read trees and blobs directly out of a git repository's object database, without checking
+//! anything out. This shells out to the `git` command.
+
+use anyhow::{anyhow, bail, Context as _, Result};
+use maelstrom_base::Utf8PathBuf;
+use std::{
+    io::{self, BufRead as _, BufReader, Read as _, Write as _},
+    path::Path,
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+};
+
+fn git(repo: &Path) -> Command {
+    let mut cmd = Command::new("git");
+    cmd.arg("-C").arg(repo);
+    cmd
+}
+
+fn run(mut cmd: Command) -> Result<Vec<u8>> {
+    let output = cmd.output().context("running git")?;
+    if !output.status.success() {
+        bail!(
+            "git failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(output.stdout)
+}
+
+/// Resolve `rev` to the hash of its tree object.
+pub fn resolve_tree(repo: &Path, rev: &str) -> Result<String> {
+    let mut cmd = git(repo);
+    cmd.args(["rev-parse", "--verify", "--end-of-options"])
+        .arg(format!("{rev}^{{tree}}"));
+    let stdout = run(cmd)?;
+    Ok(String::from_utf8(stdout)?.trim().to_owned())
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TreeEntryKind {
+    Blob,
+    /// A submodule. The object is a commit in some other repository.
+    Commit,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TreeEntry {
+    pub path: Utf8PathBuf,
+    pub mode: u32,
+    pub kind: TreeEntryKind,
+    pub object: String,
+    pub size: u64,
+}
+
+impl TreeEntry {
+    pub fn is_symlink(&self) -> bool {
+        self.mode & 0o170000 == 0o120000
+    }
+}
+
+fn parse_tree_entry(line: &[u8]) -> Result<TreeEntry> {
+    let line = std::str::from_utf8(line).map_err(|_| anyhow!("non-utf8 path in git tree"))?;
+    let (info, path) = line
+        .split_once('\t')
+        .ok_or_else(|| anyhow!("malformed git ls-tree line {line:?}"))?;
+    let [mode, kind, object, size] = info.split_whitespace().collect::<Vec<_>>()[..] else {
+        bail!("malformed git ls-tree line {line:?}");
+    };
+    let kind = match kind {
+        "blob" => TreeEntryKind::Blob,
+        "commit" => TreeEntryKind::Commit,
+        _ => bail!("unexpected object type {kind:?} in git tree"),
+    };
+    Ok(TreeEntry {
+        path: path.into(),
+        mode: u32::from_str_radix(mode, 8)?,
+        kind,
+        object: object.into(),
+        size: if size == "-" { 0 } else { size.parse()? },
+    })
+}
+
+/// List the files in `tree`, recursively. If `paths` is non-empty, only entries under those paths
+/// are returned. Paths are relative to the root of the repository. Entries are returned in git's
+/// tree order, so the listing for a given tree is always the same.
+pub fn list_tree(repo: &Path, tree: &str, paths: &[Utf8PathBuf]) -> Result<Vec<TreeEntry>> {
+    let mut cmd = git(repo);
+    cmd.args(["ls-tree", "-r", "-l", "-z", "--full-tree", tree, "--"])
+        .args(paths);
+    run(cmd)?
+        .split(|b| *b == b'\0')
+        .filter(|line| !line.is_empty())
+        .map(parse_tree_entry)
+        .collect()
+}
+
+/// Streams the contents of blobs out of a repository using a single `git cat-file --batch`
+/// process.
+pub struct BlobReader {
+    child: Child,
+    stdin: Option<ChildStdin>,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl BlobReader {
+    pub fn new(repo: &Path) -> Result<Self> {
+        let mut child = git(repo)
+            .args(["cat-file", "--batch"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .context("running git cat-file")?;
+        let stdin = child.stdin.take();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        Ok(Self {
+            child,
+            stdin,
+            stdout,
+        })
+    }
+
+    /// Write the contents of the blob `object` to `out`, returning its size.
+    pub fn read_blob(&mut self, object: &str, mut out: impl io::Write) -> Result<u64> {
+        let stdin = self.stdin.as_mut().unwrap();
+        writeln!(stdin, "{object}")?;
+        stdin.flush()?;
+
+        let mut header = String::new();
+        self.stdout.read_line(&mut header)?;
+        let header = header.trim_end();
+        let size = match header.split(' ').collect::<Vec<_>>()[..] {
+            [_, "blob", size] => size.parse()?,
+            _ => bail!("couldn't read git blob {object}: {header}"),
+        };
+        let copied = io::copy(&mut (&mut self.stdout).take(size), &mut out)?;
+        if copied != size {
+            bail!("git blob {object} was truncated");
+        }
+        let mut newline = [0u8];
+        self.stdout.read_exact(&mut newline)?;
+        Ok(size)
+    }
+}
+
+impl Drop for BlobReader {
+    fn drop(&mut self) {
+        drop(self.stdin.take());
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_blob_entry() {
+        assert_eq!(
+            parse_tree_entry(b"100755 blob 0123abcd     42\tfoo/bar baz").unwrap(),
+            TreeEntry {
+                path: "foo/bar baz".into(),
+                mode: 0o100755,
+                kind: TreeEntryKind::Blob,
+                object: "0123abcd".into(),
+                size: 42,
+            }
+        );
+    }
+
+    #[test]
+    fn parse_submodule_entry() {
+        let entry = parse_tree_entry(b"160000 commit 0123abcd       -\tvendor/lib").unwrap();
+        assert_eq!(entry.kind, TreeEntryKind::Commit);
+        assert_eq!(entry.size, 0);
+    }
+
+    #[test]
+    fn parse_symlink_entry() {
+        let entry = parse_tree_entry(b"120000 blob 0123abcd       3\tlink").unwrap();
+        assert!(entry.is_symlink());
+    }
+
+    #[test]
+    fn parse_malformed_entry() {
+        assert!(parse_tree_entry(b"100644 blob 0123abcd").is_err());
+        assert!(parse_tree_entry(b"100644 tree 0123abcd -\tdir").is_err());
+    }
+}
@@ -1,44 +1,58 @@
+mod git;
 mod rpc;
+mod squash;
 mod test;
 
 pub use rpc::run_process_client;
 
-use anyhow::{anyhow, Context as _, Result};
+use anyhow::{anyhow, bail, Context as _, Result};
+use base64::Engine as _;
 use chrono::{DateTime, Utc};
 use itertools::Itertools as _;
 use maelstrom_base::{
     manifest::{
-        ManifestEntry, ManifestEntryData, ManifestEntryMetadata, ManifestWriter, Mode,
-        UnixTimestamp,
+        ManifestEntry, ManifestEntryData, ManifestEntryMetadata, ManifestReader, ManifestWriter,
+        Mode, UnixTimestamp,
     },
     proto::{
-        ArtifactPusherToBroker, BrokerToArtifactPusher, BrokerToClient, ClientToBroker, Hello,
+        ArtifactCompression, ArtifactPusherToBroker, BrokerToArtifactPusher, BrokerToClient,
+        ClientToBroker, Hello,
     },
     stats::JobStateCounts,
     ArtifactType, ClientJobId, JobSpec, Sha256Digest, Utf8Path, Utf8PathBuf,
 };
 use maelstrom_client_base::{
-    spec::{Layer, PrefixOptions, SymlinkSpec},
-    ClientDriverMode, JobResponseHandler, MANIFEST_DIR, STUB_MANIFEST_DIR, SYMLINK_MANIFEST_DIR,
+    spec::{FileContents, FileSpec, Layer, PrefixOptions, SymlinkSpec},
+    ClientDriverMode, JobResponseHandler, FILES_DATA_DIR, FILES_MANIFEST_DIR, GIT_BLOB_DIR,
+    GIT_MANIFEST_DIR, MANIFEST_DIR, SQUASH_DATA_DIR, SQUASH_MANIFEST_DIR, SQUASH_TAR_MANIFEST_DIR,
+    STUB_MANIFEST_DIR, SYMLINK_MANIFEST_DIR,
 };
 use maelstrom_container::{ContainerImage, ContainerImageDepot, ProgressTracker};
 use maelstrom_util::{
-    config::BrokerAddr, ext::OptionExt as _, fs::Fs, io::FixedSizeReader,
-    manifest::ManifestBuilder, net,
+    config::BrokerAddr,
+    ext::OptionExt as _,
+    fs::Fs,
+    io::{ChunkedWriter, CompressingWriter, FixedSizeReader, Sha256Reader},
+    manifest::ManifestBuilder,
+    net,
 };
 use serde::{Deserialize, Serialize};
 use serde_repr::{Deserialize_repr, Serialize_repr};
 use serde_with::{serde_as, DisplayFromStr};
 use sha2::{Digest as _, Sha256};
+use slog::{debug, info, warn, Logger};
 use std::{
     collections::{HashMap, HashSet, VecDeque},
     fmt,
     io::{self, Read as _, Seek as _, SeekFrom, Write as _},
     net::TcpStream,
     path::{Path, PathBuf},
-    sync::mpsc::{self, Receiver, SyncSender},
+    sync::{
+        mpsc::{self, Receiver, SyncSender},
+        Arc, Mutex,
+    },
     thread::{self, JoinHandle},
-    time::SystemTime,
+    time::{Duration, SystemTime},
 };
 use test::client_driver::SingleThreadedClientDriver;
 
@@ -49,22 +63,121 @@ fn new_driver(mode: ClientDriverMode) -> Box<dyn ClientDriver + Send + Sync> {
     }
 }
 
-fn push_one_artifact(broker_addr: BrokerAddr, path: PathBuf, digest: Sha256Digest) -> Result<()> {
-    let mut stream = TcpStream::connect(broker_addr.inner())?;
-    net::write_message_to_socket(&mut stream, Hello::ArtifactPusher)?;
+/// An error pushing an artifact that retrying the push won't fix, because the file is missing or its
+/// contents no longer match its digest.
+#[derive(Debug)]
+struct PermanentPushError(anyhow::Error);
 
+impl fmt::Display for PermanentPushError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#}", self.0)
+    }
+}
+
+impl std::error::Error for PermanentPushError {}
+
+fn push_one_artifact(
+    broker_addr: BrokerAddr,
+    compression: ArtifactCompression,
+    path: &Path,
+    digest: &Sha256Digest,
+) -> Result<()> {
     let fs = Fs::new();
-    let file = fs.open_file(path)?;
-    let size = file.metadata()?.len();
-    let mut file = FixedSizeReader::new(file, size);
+    let (file, size) = fs
+        .open_file(path)
+        .and_then(|file| {
+            let size = file.metadata()?.len();
+            Ok((file, size))
+        })
+        .map_err(PermanentPushError)?;
+    let mut file = Sha256Reader::new(FixedSizeReader::new(file, size));
 
-    net::write_message_to_socket(&mut stream, ArtifactPusherToBroker(digest, size))?;
-    let copied = io::copy(&mut file, &mut stream)?;
-    assert_eq!(copied, size);
+    let mut stream = TcpStream::connect(broker_addr.inner())?;
+    net::write_message_to_socket(&mut stream, Hello::ArtifactPusher)?;
+
+    net::write_message_to_socket(
+        &mut stream,
+        ArtifactPusherToBroker(digest.clone(), size, compression),
+    )?;
+    // Uncompressed bodies aren't chunked, since their size is known up front. This is also what
+    // brokers that don't support compression expect.
+    if compression == ArtifactCompression::None {
+        let copied = io::copy(&mut file, &mut stream)?;
+        assert_eq!(copied, size);
+    } else {
+        let mut writer = CompressingWriter::new(
+            ChunkedWriter::new(&mut stream, ARTIFACT_PUSH_MAX_CHUNK_SIZE),
+            compression,
+        )?;
+        let copied = io::copy(&mut file, &mut writer)?;
+        assert_eq!(copied, size);
+        writer.finish()?.finish()?;
+    }
+    // The file may have changed since its digest was calculated, in which case the broker will
+    // reject it. There's no point in waiting to hear that, or in trying again.
+    let (_, actual_digest) = file.finalize();
+    actual_digest
+        .verify(digest)
+        .map_err(|err| PermanentPushError(anyhow!("{}: {err}", path.display())))?;
     let BrokerToArtifactPusher(resp) = net::read_message_from_socket(&mut stream)?;
     resp.map_err(|e| anyhow!("Error from broker: {e}"))
 }
 
+/// How big the chunks of a compressed artifact push can be.
+const ARTIFACT_PUSH_MAX_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// How many times to try pushing an artifact before giving up on it.
+const ARTIFACT_PUSH_ATTEMPTS: u32 = 5;
+
+/// How long to wait before retrying a failed push. This doubles after every attempt.
+const ARTIFACT_PUSH_INITIAL_BACKOFF: Duration = Duration::from_millis(250);
+
+/// Push an artifact, retrying with exponential backoff. The error from the last attempt is
+/// returned if all attempts fail. A [`PermanentPushError`] is returned right away.
+///
+/// A broker that doesn't support the compression we push with fails the push, so if a compressed
+/// push fails, it is immediately retried uncompressed. If that works, `compression` is set to
+/// [`ArtifactCompression::None`] so that later pushes don't bother compressing.
+fn push_one_artifact_with_retries(
+    broker_addr: BrokerAddr,
+    compression: &Mutex<ArtifactCompression>,
+    path: &Path,
+    digest: &Sha256Digest,
+    log: &Logger,
+) -> Result<()> {
+    let initial_compression = *compression.lock().unwrap();
+    let mut attempt_compression = initial_compression;
+    let mut backoff = ARTIFACT_PUSH_INITIAL_BACKOFF;
+    let mut attempt = 1;
+    loop {
+        let result = push_one_artifact(broker_addr, attempt_compression, path, digest);
+        if result.is_ok() && attempt_compression != initial_compression {
+            info!(log, "broker doesn't accept compressed artifacts, pushing uncompressed";
+                "compression" => ?initial_compression);
+            *compression.lock().unwrap() = attempt_compression;
+        }
+        let Err(err) = result else {
+            debug!(log, "pushed artifact"; "path" => %path.display(), "digest" => %digest);
+            return Ok(());
+        };
+        if err.is::<PermanentPushError>() || attempt == ARTIFACT_PUSH_ATTEMPTS {
+            warn!(log, "giving up pushing artifact";
+                "path" => %path.display(), "digest" => %digest, "err" => %format!("{err:#}"));
+            return Err(err);
+        }
+        warn!(log, "error pushing artifact, retrying";
+            "path" => %path.display(), "digest" => %digest, "attempt" => attempt,
+            "err" => %format!("{err:#}"));
+        attempt += 1;
+        if attempt_compression != ArtifactCompression::None {
+            attempt_compression = ArtifactCompression::None;
+        } else {
+            thread::sleep(backoff);
+            backoff *= 2;
+        }
+    }
+}
+
 fn calculate_digest(path: &Path) -> Result<(SystemTime, Sha256Digest)> {
     let fs = Fs::new();
     let mut hasher = Sha256::new();
@@ -78,6 +191,7 @@ fn calculate_digest(path: &Path) -> Result<(SystemTime, Sha256Digest)> {
 enum DispatcherMessage {
     BrokerToClient(BrokerToClient),
     AddArtifact(PathBuf, Sha256Digest),
+    ArtifactPushFailed(Sha256Digest, String),
     AddJob(JobSpec, JobResponseHandler),
     GetJobStateCounts(SyncSender<JobStateCounts>),
     Stop,
@@ -90,14 +204,26 @@ struct ArtifactPushRequest {
 
 struct ArtifactPusher {
     broker_addr: BrokerAddr,
+    compression: Arc<Mutex<ArtifactCompression>>,
     receiver: Receiver<ArtifactPushRequest>,
+    dispatcher_sender: SyncSender<DispatcherMessage>,
+    log: Logger,
 }
 
 impl ArtifactPusher {
-    fn new(broker_addr: BrokerAddr, receiver: Receiver<ArtifactPushRequest>) -> Self {
+    fn new(
+        broker_addr: BrokerAddr,
+        compression: ArtifactCompression,
+        receiver: Receiver<ArtifactPushRequest>,
+        dispatcher_sender: SyncSender<DispatcherMessage>,
+        log: Logger,
+    ) -> Self {
         Self {
             broker_addr,
+            compression: Arc::new(Mutex::new(compression)),
             receiver,
+            dispatcher_sender,
+            log,
         }
     }
 
@@ -109,8 +235,20 @@ impl ArtifactPusher {
     {
         if let Ok(msg) = self.receiver.recv() {
             let broker_addr = self.broker_addr;
-            // N.B. We are ignoring this Result<_>
-            scope.spawn(move || push_one_artifact(broker_addr, msg.path, msg.digest));
+            let compression = self.compression.clone();
+            let dispatcher_sender = self.dispatcher_sender.clone();
+            let log = self.log.clone();
+            scope.spawn(move || {
+                let ArtifactPushRequest { path, digest } = msg;
+                if let Err(err) =
+                    push_one_artifact_with_retries(broker_addr, &compression, &path, &digest, &log)
+                {
+                    let message = format!("failed to push artifact {}: {err:#}", path.display());
+                    dispatcher_sender
+                        .send(DispatcherMessage::ArtifactPushFailed(digest, message))
+                        .ok();
+                }
+            });
             true
         } else {
             false
@@ -127,6 +265,7 @@ struct Dispatcher {
     artifacts: HashMap<Sha256Digest, PathBuf>,
     handlers: HashMap<ClientJobId, JobResponseHandler>,
     stats_reqs: VecDeque<SyncSender<JobStateCounts>>,
+    log: Logger,
 }
 
 impl Dispatcher {
@@ -134,6 +273,7 @@ impl Dispatcher {
         receiver: Receiver<DispatcherMessage>,
         stream: TcpStream,
         artifact_pusher: SyncSender<ArtifactPushRequest>,
+        log: Logger,
     ) -> Self {
         Self {
             receiver,
@@ -144,6 +284,7 @@ impl Dispatcher {
             artifacts: Default::default(),
             handlers: Default::default(),
             stats_reqs: Default::default(),
+            log,
         }
     }
 
@@ -162,6 +303,7 @@ impl Dispatcher {
     fn handle_message(&mut self, msg: DispatcherMessage) -> Result<bool> {
         match msg {
             DispatcherMessage::BrokerToClient(BrokerToClient::JobResponse(cjid, result)) => {
+                debug!(self.log, "job completed"; "cjid" => %cjid);
                 self.handlers.remove(&cjid).unwrap()(cjid, result);
                 if self.stop_when_all_completed && self.handlers.is_empty() {
                     return Ok(false);
@@ -175,6 +317,8 @@ impl Dispatcher {
                         panic!("got request for unknown artifact with digest {digest}")
                     })
                     .clone();
+                debug!(self.log, "broker requested artifact";
+                    "path" => %path.display(), "digest" => %digest);
                 self.artifact_pusher
                     .send(ArtifactPushRequest { path, digest })?;
             }
@@ -187,8 +331,15 @@ impl Dispatcher {
             DispatcherMessage::AddArtifact(path, digest) => {
                 self.artifacts.insert(digest, path);
             }
+            DispatcherMessage::ArtifactPushFailed(digest, message) => {
+                net::write_message_to_socket(
+                    &mut self.stream,
+                    ClientToBroker::ArtifactTransferFailed(digest, message),
+                )?;
+            }
             DispatcherMessage::AddJob(spec, handler) => {
-                let cjid = self.next_client_job_id.into();
+                let cjid: ClientJobId = self.next_client_job_id.into();
+                debug!(self.log, "job enqueued"; "cjid" => %cjid, "program" => %spec.program);
                 self.handlers.insert(cjid, handler).assert_is_none();
                 self.next_client_job_id = self.next_client_job_id.checked_add(1).unwrap();
                 net::write_message_to_socket(
@@ -333,6 +484,50 @@ fn digest_repository_simple_add_get_after_modify() {
     assert_eq!(repo.get(&foo_path).unwrap(), None);
 }
 
+#[test]
+fn push_falls_back_to_no_compression() {
+    let log = Logger::root(slog::Discard, slog::o!());
+    // A broker that hangs up on compressed pushes, like one that doesn't support compression.
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let broker_addr = BrokerAddr::new(listener.local_addr().unwrap());
+    let broker = thread::spawn(move || {
+        let mut compressions = vec![];
+        for stream in listener.incoming().take(3) {
+            let mut stream = stream.unwrap();
+            let _: Hello = net::read_message_from_socket(&mut stream).unwrap();
+            let ArtifactPusherToBroker(_, size, compression) =
+                net::read_message_from_socket(&mut stream).unwrap();
+            compressions.push(compression);
+            if compression == ArtifactCompression::None {
+                let mut body = vec![];
+                (&mut stream).take(size).read_to_end(&mut body).unwrap();
+                assert_eq!(body, b"foo");
+                net::write_message_to_socket(&mut stream, BrokerToArtifactPusher(Ok(()))).unwrap();
+            }
+        }
+        compressions
+    });
+
+    let fs = Fs::new();
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let foo_path = tmp_dir.path().join("foo.tar");
+    fs.write(&foo_path, "foo").unwrap();
+    let (_, digest) = calculate_digest(&foo_path).unwrap();
+    let compression = Mutex::new(ArtifactCompression::Zstd);
+    push_one_artifact_with_retries(broker_addr, &compression, &foo_path, &digest, &log).unwrap();
+    assert_eq!(*compression.lock().unwrap(), ArtifactCompression::None);
+    push_one_artifact_with_retries(broker_addr, &compression, &foo_path, &digest, &log).unwrap();
+
+    assert_eq!(
+        broker.join().unwrap(),
+        vec![
+            ArtifactCompression::Zstd,
+            ArtifactCompression::None,
+            ArtifactCompression::None
+        ]
+    );
+}
+
 struct SocketReader {
     stream: TcpStream,
     channel: SyncSender<DispatcherMessage>,
@@ -361,7 +556,11 @@ struct ClientDeps {
 }
 
 impl ClientDeps {
-    fn new(broker_addr: BrokerAddr) -> Result<Self> {
+    fn new(
+        broker_addr: BrokerAddr,
+        artifact_compression: ArtifactCompression,
+        log: Logger,
+    ) -> Result<Self> {
         let mut stream = TcpStream::connect(broker_addr.inner())
             .with_context(|| format!("failed to connect to {broker_addr}"))?;
         net::write_message_to_socket(&mut stream, Hello::Client)?;
@@ -370,8 +569,19 @@ impl ClientDeps {
         let (artifact_send, artifact_recv) = mpsc::sync_channel(1000);
         let stream_clone = stream.try_clone()?;
         Ok(Self {
-            dispatcher: Dispatcher::new(dispatcher_receiver, stream_clone, artifact_send),
-            artifact_pusher: ArtifactPusher::new(broker_addr, artifact_recv),
+            dispatcher: Dispatcher::new(
+                dispatcher_receiver,
+                stream_clone,
+                artifact_send,
+                log.clone(),
+            ),
+            artifact_pusher: ArtifactPusher::new(
+                broker_addr,
+                artifact_compression,
+                artifact_recv,
+                dispatcher_sender.clone(),
+                log,
+            ),
             socket_reader: SocketReader::new(stream, dispatcher_sender.clone()),
             dispatcher_sender,
         })
@@ -482,6 +692,147 @@ fn expand_braces(expr: &str) -> Result<Vec<String>> {
 /// I picked this time arbitrarily 2024-1-11 11:11:11
 const ARBITRARY_TIME: UnixTimestamp = UnixTimestamp(1705000271);
 
+/// The most bytes of git blobs kept in [`GIT_BLOB_DIR`] between runs. See [`prune_git_blobs`].
+const GIT_BLOB_DIR_MAX_SIZE: u64 = 1 << 30;
+
+/// The most bytes of extracted tar layers, and the manifests built from them, kept in
+/// [`SQUASH_MANIFEST_DIR`] between runs. See [`prune_squash_manifests`].
+const SQUASH_DIR_MAX_SIZE: u64 = 10 << 30;
+
+/// The lock file that guards the parts of the cache directory that get pruned, which is shared by
+/// every client using the cache directory. Each client holds it shared for as long as it runs,
+/// since it may upload any of the files in them at any time. Pruning needs it exclusive.
+const CACHE_LOCK_FILE: &str = ".cache.flock";
+
+/// Lock the cache directory for this client's use, first pruning it with [`prune_git_blobs`] and
+/// [`prune_squash_manifests`] if no other client is using it. The returned file holds the lock
+/// until it is dropped.
+fn lock_cache_dir(
+    fs: &Fs,
+    cache_dir: &Path,
+    git_blob_max_size: u64,
+    squash_max_size: u64,
+) -> Result<std::fs::File> {
+    let lock_file = fs.open_or_create_file(cache_dir.join(CACHE_LOCK_FILE))?;
+    if lock_file.try_lock_exclusive().is_ok() {
+        prune_git_blobs(fs, &cache_dir.join(GIT_BLOB_DIR), git_blob_max_size)?;
+        prune_squash_manifests(fs, cache_dir, squash_max_size)?;
+    }
+    lock_file.lock_shared()?;
+    Ok(lock_file.into_inner())
+}
+
+/// Remove the blobs extracted longest ago from `blob_dir` until the rest take up at most
+/// `max_size` bytes, along with any temporary files left behind by an interrupted extraction.
+/// Blobs are extracted again as git layers need them. The caller must hold the lock returned by
+/// [`lock_cache_dir`] exclusively.
+fn prune_git_blobs(fs: &Fs, blob_dir: &Path, max_size: u64) -> Result<()> {
+    let mut blobs = vec![];
+    for entry in fs.read_dir(blob_dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let metadata = entry.metadata()?;
+        if path.file_name().unwrap().to_string_lossy().starts_with('.') {
+            fs.remove_file(&path)?;
+        } else {
+            blobs.push((metadata.modified()?, metadata.len(), path));
+        }
+    }
+    blobs.sort();
+    let mut total_size: u64 = blobs.iter().map(|(_, size, _)| size).sum();
+    for (_, size, path) in blobs {
+        if total_size <= max_size {
+            break;
+        }
+        fs.remove_file(&path)?;
+        total_size -= size;
+    }
+    Ok(())
+}
+
+/// Remove the squash manifests and tar layer manifests used longest ago, along with the extracted
+/// contents in [`SQUASH_DATA_DIR`] that only they refer to, until the rest take up at most
+/// `max_size` bytes. Temporary files left behind by an interrupted build are removed too.
+/// Everything removed is built again as squash layers need it. The caller must hold the lock
+/// returned by [`lock_cache_dir`] exclusively.
+fn prune_squash_manifests(fs: &Fs, cache_dir: &Path, max_size: u64) -> Result<()> {
+    let mut data = HashMap::new();
+    for entry in fs.read_dir(cache_dir.join(SQUASH_DATA_DIR))? {
+        let entry = entry?;
+        let path = entry.path();
+        match path.file_name().unwrap().to_str().unwrap_or("").parse() {
+            Ok(digest) => {
+                data.insert(digest, (entry.metadata()?.len(), path));
+            }
+            Err(_) => fs.remove_file(&path)?,
+        }
+    }
+
+    let mut manifests = vec![];
+    for dir in [SQUASH_MANIFEST_DIR, SQUASH_TAR_MANIFEST_DIR] {
+        for entry in fs.read_dir(cache_dir.join(dir))? {
+            let entry = entry?;
+            let path = entry.path();
+            let metadata = entry.metadata()?;
+            if metadata.is_dir() {
+                continue;
+            }
+            if path.file_name().unwrap().to_string_lossy().starts_with('.') {
+                fs.remove_file(&path)?;
+            } else {
+                manifests.push((metadata.modified()?, metadata.len(), path));
+            }
+        }
+    }
+
+    let total_size: u64 = data.values().map(|(size, _)| size).sum::<u64>()
+        + manifests.iter().map(|(_, size, _)| size).sum::<u64>();
+    if total_size <= max_size {
+        return Ok(());
+    }
+
+    // Keep the most recently used manifests, and what they refer to, until the limit is reached.
+    manifests.sort();
+    let mut kept_size = 0;
+    let mut kept_data = HashSet::new();
+    let mut full = false;
+    for (_, size, path) in manifests.into_iter().rev() {
+        if !full {
+            let mut new_data = HashSet::new();
+            for entry in ManifestReader::new(fs.open_file(&path)?)? {
+                if let ManifestEntryData::File(Some(digest)) = entry?.data {
+                    if data.contains_key(&digest) && !kept_data.contains(&digest) {
+                        new_data.insert(digest);
+                    }
+                }
+            }
+            let new_size = size + new_data.iter().map(|d| data[d].0).sum::<u64>();
+            if kept_size + new_size <= max_size {
+                kept_size += new_size;
+                kept_data.extend(new_data);
+                continue;
+            }
+            full = true;
+        }
+        fs.remove_file(&path)?;
+    }
+    for (digest, (_, path)) in data {
+        if !kept_data.contains(&digest) {
+            fs.remove_file(&path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Record that the squash manifest at `path` was just used, so it's pruned last.
+fn touch_squash_manifest(path: &Path) -> Result<()> {
+    std::fs::File::options()
+        .write(true)
+        .open(path)?
+        .set_modified(SystemTime::now())?;
+    Ok(())
+}
+
 struct Client {
     dispatcher_sender: SyncSender<DispatcherMessage>,
     driver: Box<dyn ClientDriver + Send + Sync>,
@@ -491,38 +842,62 @@ struct Client {
     cache_dir: PathBuf,
     project_dir: PathBuf,
     cached_layers: HashMap<Layer, (Sha256Digest, ArtifactType)>,
+    _cache_lock: std::fs::File,
 }
 
 impl Client {
     fn new(
         driver_mode: ClientDriverMode,
         broker_addr: BrokerAddr,
+        artifact_compression: ArtifactCompression,
         project_dir: impl AsRef<Path>,
         cache_dir: impl AsRef<Path>,
+        locked: bool,
+        log: Logger,
     ) -> Result<Self> {
         let mut driver = new_driver(driver_mode);
-        let deps = ClientDeps::new(broker_addr)?;
+        let deps = ClientDeps::new(broker_addr, artifact_compression, log)?;
         let dispatcher_sender = deps.dispatcher_sender.clone();
         driver.drive(deps);
 
         let fs = Fs::new();
-        for d in [MANIFEST_DIR, STUB_MANIFEST_DIR, SYMLINK_MANIFEST_DIR] {
+        for d in [
+            MANIFEST_DIR,
+            STUB_MANIFEST_DIR,
+            SYMLINK_MANIFEST_DIR,
+            FILES_MANIFEST_DIR,
+            FILES_DATA_DIR,
+            SQUASH_MANIFEST_DIR,
+            SQUASH_TAR_MANIFEST_DIR,
+            SQUASH_DATA_DIR,
+            GIT_MANIFEST_DIR,
+            GIT_BLOB_DIR,
+        ] {
             fs.create_dir_all(cache_dir.as_ref().join(d))?;
         }
+        let cache_lock = lock_cache_dir(
+            &fs,
+            cache_dir.as_ref(),
+            GIT_BLOB_DIR_MAX_SIZE,
+            SQUASH_DIR_MAX_SIZE,
+        )?;
 
         Ok(Client {
             dispatcher_sender,
             driver,
             digest_repo: DigestRespository::new(cache_dir.as_ref()),
-            container_image_depot: ContainerImageDepot::new(project_dir.as_ref())?,
+            container_image_depot: ContainerImageDepot::new(project_dir.as_ref(), locked)?,
             processed_artifact_paths: HashSet::default(),
             cache_dir: cache_dir.as_ref().to_owned(),
             project_dir: project_dir.as_ref().to_owned(),
             cached_layers: HashMap::new(),
+            _cache_lock: cache_lock,
         })
     }
 
-    fn add_artifact(&mut self, path: &Path) -> Result<Sha256Digest> {
+    /// Return the canonical path and the digest of the file at `path`, without adding it as an
+    /// artifact.
+    fn artifact_digest(&mut self, path: &Path) -> Result<(PathBuf, Sha256Digest)> {
         let fs = Fs::new();
         let path = fs.canonicalize(path)?;
 
@@ -533,6 +908,11 @@ impl Client {
             self.digest_repo.add(path.clone(), mtime, digest.clone())?;
             digest
         };
+        Ok((path, digest))
+    }
+
+    fn add_artifact(&mut self, path: &Path) -> Result<Sha256Digest> {
+        let (path, digest) = self.artifact_digest(path)?;
         if !self.processed_artifact_paths.contains(&path) {
             self.dispatcher_sender
                 .send(DispatcherMessage::AddArtifact(path.clone(), digest.clone()))?;
@@ -559,6 +939,18 @@ impl Client {
             .join(format!("{name}.manifest"))
     }
 
+    fn build_files_manifest_path(&self, name: &impl fmt::Display) -> PathBuf {
+        self.cache_dir
+            .join(FILES_MANIFEST_DIR)
+            .join(format!("{name}.manifest"))
+    }
+
+    fn build_git_manifest_path(&self, name: &impl fmt::Display) -> PathBuf {
+        self.cache_dir
+            .join(GIT_MANIFEST_DIR)
+            .join(format!("{name}.manifest"))
+    }
+
     fn build_manifest(
         &mut self,
         paths: impl Iterator<Item = Result<impl AsRef<Path>>>,
@@ -655,20 +1047,303 @@ impl Client {
         Ok(manifest_path)
     }
 
-    fn add_layer(&mut self, layer: Layer) -> Result<(Sha256Digest, ArtifactType)> {
-        if let Some(l) = self.cached_layers.get(&layer) {
-            return Ok(l.clone());
+    /// Write the contents out to a file named by their digest, so they can be uploaded like any
+    /// other artifact.
+    fn get_file_data_path(&self, contents: &[u8], digest: &Sha256Digest) -> Result<PathBuf> {
+        let fs = Fs::new();
+        let data_dir = self.cache_dir.join(FILES_DATA_DIR);
+        let data_path = data_dir.join(digest.to_string());
+        if !fs.exists(&data_path) {
+            let tmp_file_path = data_dir.join(format!(".{digest}.temp"));
+            fs.write(&tmp_file_path, contents)?;
+            fs.rename(tmp_file_path, &data_path)?;
+        }
+        Ok(data_path)
+    }
+
+    fn build_files_manifest(&mut self, files: Vec<FileSpec>) -> Result<PathBuf> {
+        let fs = Fs::new();
+        let tmp_file_path = self.build_manifest_path(&".temp");
+        let mut writer = ManifestWriter::new(fs.create_file(&tmp_file_path)?)?;
+        let mut path_hasher = PathHasher::new();
+        for FileSpec {
+            path,
+            mode,
+            contents,
+        } in files
+        {
+            let contents = match contents {
+                FileContents::Text(text) => text.into_bytes(),
+                FileContents::Base64(encoded) => base64::engine::general_purpose::STANDARD
+                    .decode(encoded)
+                    .with_context(|| format!("decoding base64 contents of {path}"))?,
+            };
+            let digest = Sha256Digest::new(Sha256::digest(&contents).into());
+            path_hasher.hash_path(&path);
+            path_hasher.hash_path(Utf8Path::new(&format!("{mode:o}")));
+            path_hasher.hash_path(Utf8Path::new(&digest.to_string()));
+
+            let data = if contents.is_empty() {
+                ManifestEntryData::File(None)
+            } else {
+                let data_path = self.get_file_data_path(&contents, &digest)?;
+                ManifestEntryData::File(Some(self.add_artifact(&data_path)?))
+            };
+            let metadata = ManifestEntryMetadata {
+                size: contents.len() as u64,
+                mode: Mode(mode),
+                mtime: ARBITRARY_TIME,
+            };
+            let entry = ManifestEntry {
+                path,
+                metadata,
+                data,
+            };
+            writer.write_entry(&entry)?;
+        }
+
+        let manifest_path = self.build_files_manifest_path(&path_hasher.finish());
+        fs.rename(tmp_file_path, &manifest_path)?;
+        Ok(manifest_path)
+    }
+
+    /// Write the blob out to a file named by its git object hash, so it can be uploaded like any
+    /// other artifact. Blobs are immutable, so a file that already exists can be reused. Other
+    /// clients may be extracting the same blob, so each uses its own temporary file.
+    fn get_git_blob_path(&self, blobs: &mut git::BlobReader, object: &str) -> Result<PathBuf> {
+        let fs = Fs::new();
+        let blob_dir = self.cache_dir.join(GIT_BLOB_DIR);
+        let blob_path = blob_dir.join(object);
+        if !fs.exists(&blob_path) {
+            let tmp_file_path = blob_dir.join(format!(".{object}.{}.temp", std::process::id()));
+            blobs.read_blob(object, fs.create_file(&tmp_file_path)?)?;
+            fs.rename(tmp_file_path, &blob_path)?;
+        }
+        Ok(blob_path)
+    }
+
+    fn build_git_manifest(
+        &mut self,
+        repo: Utf8PathBuf,
+        rev: String,
+        paths: Vec<Utf8PathBuf>,
+        prefix_options: PrefixOptions,
+    ) -> Result<PathBuf> {
+        if prefix_options.canonicalize {
+            bail!("canonicalize is not supported for git layers");
+        }
+        let fs = Fs::new();
+        let repo = self.project_dir.join(repo);
+        let tree = git::resolve_tree(&repo, &rev)
+            .with_context(|| format!("resolving {rev:?} in {}", repo.display()))?;
+        let entries = git::list_tree(&repo, &tree, &paths)?;
+        let mut blobs = git::BlobReader::new(&repo)?;
+
+        // The manifest is named for everything that determines its contents, so the same tree
+        // always produces the same layer, no matter which commit or branch it came from.
+        let mut path_hasher = PathHasher::new();
+        path_hasher.hash_path(Utf8Path::new(&tree));
+        for path in &paths {
+            path_hasher.hash_path(path);
+        }
+        for prefix in [&prefix_options.strip_prefix, &prefix_options.prepend_prefix] {
+            path_hasher.hash_path(prefix.as_deref().unwrap_or(Utf8Path::new("")));
+        }
+
+        let tmp_file_path = self.build_manifest_path(&".temp");
+        let mut writer = ManifestWriter::new(fs.create_file(&tmp_file_path)?)?;
+        for entry in entries {
+            let (data, mode) = match entry.kind {
+                // Submodules aren't in this repository's object database.
+                git::TreeEntryKind::Commit => continue,
+                git::TreeEntryKind::Blob if entry.is_symlink() => {
+                    let mut target = vec![];
+                    blobs.read_blob(&entry.object, &mut target)?;
+                    (ManifestEntryData::Symlink(target), 0o120777)
+                }
+                git::TreeEntryKind::Blob => {
+                    let digest = (entry.size > 0)
+                        .then(|| {
+                            let blob_path = self.get_git_blob_path(&mut blobs, &entry.object)?;
+                            self.add_artifact(&blob_path)
+                        })
+                        .transpose()?;
+                    (ManifestEntryData::File(digest), entry.mode)
+                }
+            };
+            let metadata = ManifestEntryMetadata {
+                size: if entry.is_symlink() { 0 } else { entry.size },
+                mode: Mode(mode),
+                mtime: ARBITRARY_TIME,
+            };
+            let entry = ManifestEntry {
+                path: calculate_manifest_entry_path(&entry.path, &repo, &prefix_options)?,
+                metadata,
+                data,
+            };
+            writer.write_entry(&entry)?;
+        }
+
+        let manifest_path = self.build_git_manifest_path(&path_hasher.finish());
+        fs.rename(tmp_file_path, &manifest_path)?;
+        Ok(manifest_path)
+    }
+
+    /// Move the contents read from `reader` into a file named by their digest, so they can be
+    /// uploaded like any other artifact.
+    fn add_squash_data(&mut self, reader: impl io::Read) -> Result<Sha256Digest> {
+        let fs = Fs::new();
+        let data_dir = self.cache_dir.join(SQUASH_DATA_DIR);
+        let tmp_file_path = data_dir.join(".temp");
+        let mut reader = Sha256Reader::new(reader);
+        io::copy(&mut reader, &mut fs.create_file(&tmp_file_path)?)?;
+        let (_, digest) = reader.finalize();
+        let data_path = data_dir.join(digest.to_string());
+        fs.rename(tmp_file_path, &data_path)?;
+        self.add_artifact(&data_path)?;
+        Ok(digest)
+    }
+
+    /// Convert a tar layer into a manifest, so it can be squashed with other layers. The contents
+    /// of the files are extracted into the cache. Since this can be slow for large image layers,
+    /// the manifest is kept and reused, keyed by the digest of the tar file.
+    fn build_tar_manifest(
+        &mut self,
+        tar_path: &Path,
+        tar_digest: &Sha256Digest,
+    ) -> Result<PathBuf> {
+        let fs = Fs::new();
+        let manifest_dir = self.cache_dir.join(SQUASH_TAR_MANIFEST_DIR);
+        let manifest_path = manifest_dir.join(format!("{tar_digest}.manifest"));
+        if fs.exists(&manifest_path) {
+            touch_squash_manifest(&manifest_path)?;
+            // The extracted contents still have to be made available for uploading.
+            let data_dir = self.cache_dir.join(SQUASH_DATA_DIR);
+            for entry in ManifestReader::new(fs.open_file(&manifest_path)?)? {
+                if let ManifestEntryData::File(Some(digest)) = entry?.data {
+                    self.add_artifact(&data_dir.join(digest.to_string()))?;
+                }
+            }
+            return Ok(manifest_path);
+        }
+
+        let tmp_file_path = manifest_dir.join(".temp");
+        let mut writer = ManifestWriter::new(fs.create_file(&tmp_file_path)?)?;
+        let mut archive = tar::Archive::new(fs.open_file(tar_path)?);
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let header = entry.header();
+            let entry_type = header.entry_type();
+            let permissions = header.mode()? & 0o7777;
+            let mtime = UnixTimestamp(header.mtime()?.try_into()?);
+            let size = header.size()?;
+            let path = Utf8PathBuf::try_from(entry.path()?.into_owned())?;
+            let (data, mode, size) = match entry_type {
+                tar::EntryType::Directory => (ManifestEntryData::Directory, 0o40000, 0),
+                tar::EntryType::Regular | tar::EntryType::Continuous => {
+                    let digest = (size > 0)
+                        .then(|| self.add_squash_data(&mut entry))
+                        .transpose()?;
+                    (ManifestEntryData::File(digest), 0o100000, size)
+                }
+                tar::EntryType::Symlink => {
+                    let target = entry
+                        .link_name_bytes()
+                        .ok_or_else(|| anyhow!("symlink {path} has no target"))?;
+                    (ManifestEntryData::Symlink(target.into_owned()), 0o120000, 0)
+                }
+                tar::EntryType::Link => {
+                    let target = entry
+                        .link_name()?
+                        .ok_or_else(|| anyhow!("hard link {path} has no target"))?;
+                    let target = Utf8PathBuf::try_from(target.into_owned())?;
+                    (ManifestEntryData::Hardlink(target), 0o100000, 0)
+                }
+                tar::EntryType::XGlobalHeader => continue,
+                other => bail!(
+                    "can't squash {path} in {}: unsupported entry type {other:?}",
+                    tar_path.display()
+                ),
+            };
+            let entry = ManifestEntry {
+                path,
+                metadata: ManifestEntryMetadata {
+                    size,
+                    mode: Mode(mode | permissions),
+                    mtime,
+                },
+                data,
+            };
+            writer.write_entry(&entry)?;
+        }
+        drop(writer);
+
+        fs.rename(tmp_file_path, &manifest_path)?;
+        Ok(manifest_path)
+    }
+
+    fn build_squash_manifest_path(&self, name: &impl fmt::Display) -> PathBuf {
+        self.cache_dir
+            .join(SQUASH_MANIFEST_DIR)
+            .join(format!("{name}.manifest"))
+    }
+
+    fn build_squash_manifest(&mut self, layers: Vec<Layer>) -> Result<PathBuf> {
+        if layers.is_empty() {
+            bail!("a squash layer must contain at least one layer");
+        }
+        let fs = Fs::new();
+
+        // The squashed manifest is named for the digests of the layers that went into it, so if
+        // they haven't changed, the manifest from last time can be reused. The layers themselves
+        // aren't added as artifacts, since only the squashed manifest is sent to the broker.
+        // Building them still adds the files they refer to.
+        let mut path_hasher = PathHasher::new();
+        let mut layer_manifest_paths = vec![];
+        for layer in layers {
+            let (path, artifact_type) = self.build_layer(layer)?;
+            let (path, digest) = self.artifact_digest(&path)?;
+            path_hasher.hash_path(Utf8Path::new(&digest.to_string()));
+            layer_manifest_paths.push(match artifact_type {
+                ArtifactType::Tar => self.build_tar_manifest(&path, &digest)?,
+                ArtifactType::Manifest => path,
+            });
+        }
+        let manifest_path = self.build_squash_manifest_path(&path_hasher.finish());
+        if fs.exists(&manifest_path) {
+            touch_squash_manifest(&manifest_path)?;
+            return Ok(manifest_path);
+        }
+
+        let mut squasher = squash::Squasher::new();
+        for layer_manifest_path in layer_manifest_paths {
+            let entries = ManifestReader::new(fs.open_file(&layer_manifest_path)?)?;
+            squasher.add_layer(entries.collect::<io::Result<Vec<_>>>()?)?;
         }
 
-        let res = match layer.clone() {
-            Layer::Tar { path } => (self.add_artifact(path.as_std_path())?, ArtifactType::Tar),
+        let tmp_file_path = self.build_manifest_path(&".temp");
+        let mut writer = ManifestWriter::new(fs.create_file(&tmp_file_path)?)?;
+        for entry in squasher.finish() {
+            writer.write_entry(&entry)?;
+        }
+        drop(writer);
+
+        fs.rename(tmp_file_path, &manifest_path)?;
+        Ok(manifest_path)
+    }
+
+    /// Build the artifact for `layer`, without adding it. The artifact's path and type are
+    /// returned.
+    fn build_layer(&mut self, layer: Layer) -> Result<(PathBuf, ArtifactType)> {
+        Ok(match layer {
+            Layer::Tar { path } => (path.into_std_path_buf(), ArtifactType::Tar),
             Layer::Paths {
                 paths,
                 prefix_options,
-            } => {
-                let manifest_path = self.build_manifest(paths.iter().map(Ok), prefix_options)?;
-                (self.add_artifact(&manifest_path)?, ArtifactType::Manifest)
-            }
+            } => (
+                self.build_manifest(paths.iter().map(Ok), prefix_options)?,
+                ArtifactType::Manifest,
+            ),
             Layer::Glob {
                 glob,
                 prefix_options,
@@ -682,17 +1357,36 @@ impl Client {
                         .map(|p| p.map(|p| p.strip_prefix(&project_dir).unwrap().to_owned())),
                     prefix_options,
                 )?;
-                (self.add_artifact(&manifest_path)?, ArtifactType::Manifest)
-            }
-            Layer::Stubs { stubs } => {
-                let manifest_path = self.build_stub_manifest(stubs)?;
-                (self.add_artifact(&manifest_path)?, ArtifactType::Manifest)
+                (manifest_path, ArtifactType::Manifest)
             }
-            Layer::Symlinks { symlinks } => {
-                let manifest_path = self.build_symlink_manifest(symlinks)?;
-                (self.add_artifact(&manifest_path)?, ArtifactType::Manifest)
+            Layer::Stubs { stubs } => (self.build_stub_manifest(stubs)?, ArtifactType::Manifest),
+            Layer::Symlinks { symlinks } => (
+                self.build_symlink_manifest(symlinks)?,
+                ArtifactType::Manifest,
+            ),
+            Layer::Files { files } => (self.build_files_manifest(files)?, ArtifactType::Manifest),
+            Layer::Git {
+                repo,
+                rev,
+                paths,
+                prefix_options,
+            } => (
+                self.build_git_manifest(repo, rev, paths, prefix_options)?,
+                ArtifactType::Manifest,
+            ),
+            Layer::Squash { layers } => {
+                (self.build_squash_manifest(layers)?, ArtifactType::Manifest)
             }
-        };
+        })
+    }
+
+    fn add_layer(&mut self, layer: Layer) -> Result<(Sha256Digest, ArtifactType)> {
+        if let Some(l) = self.cached_layers.get(&layer) {
+            return Ok(l.clone());
+        }
+
+        let (path, artifact_type) = self.build_layer(layer.clone())?;
+        let res = (self.add_artifact(&path)?, artifact_type);
 
         self.cached_layers.insert(layer, res.clone());
         Ok(res)
@@ -756,3 +1450,175 @@ pub fn main() -> Result<()> {
     println!("started");
     run_process_client(listener.accept()?.0)
 }
+
+#[test]
+fn prune_git_blobs_removes_oldest_and_temporary_files() {
+    let fs = Fs::new();
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let blob_dir = tmp_dir.path();
+    for (name, secs) in [("a", 300), ("b", 100), ("c", 200), (".d.temp", 400)] {
+        let path = blob_dir.join(name);
+        fs.write(&path, [0u8; 10]).unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(secs))
+            .unwrap();
+    }
+
+    prune_git_blobs(&fs, blob_dir, 25).unwrap();
+    let mut remaining = Vec::from_iter(
+        fs.read_dir(blob_dir)
+            .unwrap()
+            .map(|e| e.unwrap().path().file_name().unwrap().to_owned()),
+    );
+    remaining.sort();
+    assert_eq!(remaining, vec!["a", "c"]);
+}
+
+#[test]
+fn git_blobs_are_not_pruned_while_another_client_uses_them() {
+    let fs = Fs::new();
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let cache_dir = tmp_dir.path();
+    let blob_dir = cache_dir.join(GIT_BLOB_DIR);
+    for dir in [GIT_BLOB_DIR, SQUASH_TAR_MANIFEST_DIR, SQUASH_DATA_DIR] {
+        fs.create_dir_all(cache_dir.join(dir)).unwrap();
+    }
+
+    let lock = lock_cache_dir(&fs, cache_dir, 0, 0).unwrap();
+    fs.write(blob_dir.join("a"), [0u8; 10]).unwrap();
+    fs.write(blob_dir.join(".b.1.temp"), [0u8; 10]).unwrap();
+    drop(lock_cache_dir(&fs, cache_dir, 0, 0).unwrap());
+    assert!(fs.exists(blob_dir.join("a")));
+    assert!(fs.exists(blob_dir.join(".b.1.temp")));
+
+    drop(lock);
+    drop(lock_cache_dir(&fs, cache_dir, 0, 0).unwrap());
+    assert!(!fs.exists(blob_dir.join("a")));
+    assert!(!fs.exists(blob_dir.join(".b.1.temp")));
+}
+
+#[cfg(test)]
+fn write_squash_test_manifest(fs: &Fs, path: &Path, files: &[&Sha256Digest], secs: u64) {
+    let mut writer = ManifestWriter::new(fs.create_file(path).unwrap()).unwrap();
+    for (i, digest) in files.iter().enumerate() {
+        writer
+            .write_entry(&ManifestEntry {
+                path: format!("file{i}").into(),
+                metadata: ManifestEntryMetadata {
+                    size: 10,
+                    mode: Mode(0o100644),
+                    mtime: ARBITRARY_TIME,
+                },
+                data: ManifestEntryData::File(Some((*digest).clone())),
+            })
+            .unwrap();
+    }
+    drop(writer);
+    std::fs::File::options()
+        .write(true)
+        .open(path)
+        .unwrap()
+        .set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(secs))
+        .unwrap();
+}
+
+#[test]
+fn prune_squash_manifests_removes_oldest_and_unreferenced_data() {
+    let fs = Fs::new();
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let cache_dir = tmp_dir.path();
+    let squash_dir = cache_dir.join(SQUASH_MANIFEST_DIR);
+    let tar_dir = cache_dir.join(SQUASH_TAR_MANIFEST_DIR);
+    let data_dir = cache_dir.join(SQUASH_DATA_DIR);
+    for dir in [&tar_dir, &data_dir] {
+        fs.create_dir_all(dir).unwrap();
+    }
+
+    let [a, b, c] = [1u64, 2, 3].map(Sha256Digest::from);
+    for digest in [&a, &b, &c] {
+        fs.write(data_dir.join(digest.to_string()), [0u8; 1000])
+            .unwrap();
+    }
+    fs.write(data_dir.join(".temp"), [0u8; 10]).unwrap();
+    write_squash_test_manifest(&fs, &tar_dir.join("old.manifest"), &[&a, &b], 100);
+    write_squash_test_manifest(&fs, &tar_dir.join("new.manifest"), &[&b], 300);
+    write_squash_test_manifest(&fs, &squash_dir.join("squashed.manifest"), &[&b, &c], 200);
+
+    // There's room for the two newest manifests and the data they refer to, but not the oldest.
+    prune_squash_manifests(&fs, cache_dir, 2500).unwrap();
+    let listing = |dir: &Path| {
+        let mut listing = Vec::from_iter(fs.read_dir(dir).unwrap().map(|e| {
+            e.unwrap()
+                .path()
+                .file_name()
+                .unwrap()
+                .to_string_lossy()
+                .into_owned()
+        }));
+        listing.sort();
+        listing
+    };
+    assert_eq!(listing(&tar_dir), vec!["new.manifest"]);
+    assert_eq!(
+        listing(&squash_dir),
+        vec!["data", "squashed.manifest", "tars"]
+    );
+    let mut expected_data = vec![b.to_string(), c.to_string()];
+    expected_data.sort();
+    assert_eq!(listing(&data_dir), expected_data);
+}
+
+#[test]
+fn push_of_missing_file_is_not_retried() {
+    let log = Logger::root(slog::Discard, slog::o!());
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.set_nonblocking(true).unwrap();
+    let broker_addr = BrokerAddr::new(listener.local_addr().unwrap());
+
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let compression = Mutex::new(ArtifactCompression::None);
+    let err = push_one_artifact_with_retries(
+        broker_addr,
+        &compression,
+        &tmp_dir.path().join("missing.tar"),
+        &Sha256Digest::from(1u64),
+        &log,
+    )
+    .unwrap_err();
+    assert!(err.is::<PermanentPushError>(), "{err:#}");
+    assert_eq!(
+        listener.accept().unwrap_err().kind(),
+        io::ErrorKind::WouldBlock
+    );
+}
+
+#[test]
+fn push_of_changed_file_is_not_retried() {
+    let log = Logger::root(slog::Discard, slog::o!());
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let broker_addr = BrokerAddr::new(listener.local_addr().unwrap());
+    let broker = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let _: Hello = net::read_message_from_socket(&mut stream).unwrap();
+        let _: ArtifactPusherToBroker = net::read_message_from_socket(&mut stream).unwrap();
+        // The client hangs up without waiting for an answer.
+        io::copy(&mut stream, &mut io::sink()).unwrap();
+        listener.set_nonblocking(true).unwrap();
+        listener.accept().unwrap_err().kind()
+    });
+
+    let fs = Fs::new();
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let foo_path = tmp_dir.path().join("foo.tar");
+    fs.write(&foo_path, "foo").unwrap();
+    let (_, digest) = calculate_digest(&foo_path).unwrap();
+    fs.write(&foo_path, "bar").unwrap();
+    let compression = Mutex::new(ArtifactCompression::None);
+    let err = push_one_artifact_with_retries(broker_addr, &compression, &foo_path, &digest, &log)
+        .unwrap_err();
+    assert!(err.is::<PermanentPushError>(), "{err:#}");
+    assert_eq!(broker.join().unwrap(), io::ErrorKind::WouldBlock);
+}
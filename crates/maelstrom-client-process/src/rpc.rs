@@ -3,6 +3,7 @@ use anyhow::{bail, Result};
 use maelstrom_base::proto;
 use maelstrom_client_base::comm;
 use maelstrom_container::ProgressTracker;
+use maelstrom_util::log;
 use std::os::unix::net::UnixStream;
 use std::sync::{Arc, Mutex};
 use std::thread;
@@ -216,8 +217,12 @@ pub fn run_process_client(mut sock: UnixStream) -> Result<()> {
             comm::Request::Start {
                 driver_mode,
                 broker_addr,
+                artifact_compression,
                 project_dir,
                 cache_dir,
+                locked,
+                log_level,
+                log_format,
             },
     } = req
     else {
@@ -227,8 +232,17 @@ pub fn run_process_client(mut sock: UnixStream) -> Result<()> {
     let mut read_sock = sock.try_clone()?;
 
     let sender = ProcessClientSender::new(sock);
+    let log = log::root_logger(log_level, log_format);
 
-    let client = match ProcessClient::new(driver_mode, broker_addr, project_dir, cache_dir) {
+    let client = match ProcessClient::new(
+        driver_mode,
+        broker_addr,
+        artifact_compression,
+        project_dir,
+        cache_dir,
+        locked,
+        log,
+    ) {
         Ok(c) => {
             sender.send(start_message_id, comm::Response::Start(Ok(())))?;
             c
@@ -0,0 +1,255 @@
+//! Flatten a stack of layers into a single list of manifest entries, the same way overlayfs would
+//! present them.
+
+use anyhow::{bail, Result};
+use maelstrom_base::{
+    manifest::{ManifestEntry, ManifestEntryData},
+    Utf8Path, Utf8PathBuf,
+};
+use std::collections::BTreeMap;
+
+const WHITEOUT_PREFIX: &str = ".wh.";
+const OPAQUE_WHITEOUT: &str = ".wh..wh..opq";
+
+/// Normalize a path so the same file is found no matter how a layer spelled it. Manifests use
+/// both absolute and relative paths to mean the same place in the container.
+fn normalize(path: &Utf8Path) -> Utf8PathBuf {
+    path.components()
+        .filter(|c| !matches!(c.as_str(), "/" | "."))
+        .collect()
+}
+
+/// Accumulates the entries of each layer in turn. Entries from later layers replace entries from
+/// earlier layers with the same path. OCI whiteout files remove entries added by earlier layers.
+#[derive(Default)]
+pub struct Squasher {
+    entries: BTreeMap<Utf8PathBuf, ManifestEntry>,
+}
+
+impl Squasher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn remove_descendants(&mut self, dir: &Utf8Path) {
+        self.entries
+            .retain(|path, _| path == dir || !path.starts_with(dir));
+    }
+
+    fn remove_tree(&mut self, path: &Utf8Path) {
+        self.entries.retain(|p, _| !p.starts_with(path));
+    }
+
+    /// Add all of the entries of one layer. The entries must all come from the same layer, since
+    /// whiteouts in a layer only apply to the layers below it.
+    pub fn add_layer(&mut self, entries: impl IntoIterator<Item = ManifestEntry>) -> Result<()> {
+        let mut whiteouts = vec![];
+        let mut opaque_dirs = vec![];
+        let mut layer_entries = vec![];
+        for mut entry in entries {
+            entry.path = normalize(&entry.path);
+            match entry.path.file_name() {
+                Some(OPAQUE_WHITEOUT) => {
+                    opaque_dirs.push(entry.path.parent().unwrap().to_owned());
+                }
+                Some(name) if name.starts_with(WHITEOUT_PREFIX) => {
+                    whiteouts.push(entry.path.with_file_name(&name[WHITEOUT_PREFIX.len()..]));
+                }
+                _ => layer_entries.push(entry),
+            }
+        }
+
+        // Whiteouts hide what is below them, not what is next to them in the same layer, so they
+        // have to be applied before the layer's own entries.
+        for dir in opaque_dirs {
+            self.remove_descendants(&dir);
+        }
+        for path in whiteouts {
+            self.remove_tree(&path);
+        }
+
+        for mut entry in layer_entries {
+            if let ManifestEntryData::Hardlink(target) = &entry.data {
+                // The target may be replaced or removed by a later layer, so the link is resolved
+                // to the target's contents now.
+                let target = normalize(target);
+                match self.entries.get(&target) {
+                    Some(ManifestEntry {
+                        data: data @ ManifestEntryData::File(_),
+                        metadata,
+                        ..
+                    }) => {
+                        entry.data = data.clone();
+                        entry.metadata = metadata.clone();
+                    }
+                    _ => bail!("hard link {} to missing file {target}", entry.path),
+                }
+            }
+
+            // A directory is merged with a directory below it, but anything else hides whatever
+            // was there before, including the contents of a directory.
+            let is_dir = matches!(entry.data, ManifestEntryData::Directory);
+            let was_dir = matches!(
+                self.entries.get(&entry.path),
+                Some(ManifestEntry {
+                    data: ManifestEntryData::Directory,
+                    ..
+                })
+            );
+            if was_dir && !is_dir {
+                self.remove_descendants(&entry.path);
+            }
+            self.entries.insert(entry.path.clone(), entry);
+        }
+        Ok(())
+    }
+
+    /// The squashed entries, in path order. Parent directories always come before their contents.
+    pub fn finish(self) -> impl Iterator<Item = ManifestEntry> {
+        self.entries.into_values()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use maelstrom_base::manifest::{ManifestEntryMetadata, Mode, UnixTimestamp};
+    use maelstrom_test::digest;
+
+    fn entry(path: &str, data: ManifestEntryData) -> ManifestEntry {
+        ManifestEntry {
+            path: path.into(),
+            metadata: ManifestEntryMetadata {
+                size: 0,
+                mode: Mode(0o644),
+                mtime: UnixTimestamp(0),
+            },
+            data,
+        }
+    }
+
+    fn file(path: &str, d: u64) -> ManifestEntry {
+        entry(path, ManifestEntryData::File(Some(digest!(d))))
+    }
+
+    fn dir(path: &str) -> ManifestEntry {
+        entry(path, ManifestEntryData::Directory)
+    }
+
+    fn squash(layers: Vec<Vec<ManifestEntry>>) -> Result<Vec<(String, ManifestEntryData)>> {
+        let mut squasher = Squasher::new();
+        for layer in layers {
+            squasher.add_layer(layer)?;
+        }
+        Ok(squasher
+            .finish()
+            .map(|e| (e.path.into_string(), e.data))
+            .collect())
+    }
+
+    #[test]
+    fn later_layers_win() {
+        assert_eq!(
+            squash(vec![
+                vec![file("/a", 1), file("b", 2)],
+                vec![file("a", 3), file("./c", 4)],
+            ])
+            .unwrap(),
+            vec![
+                ("a".into(), ManifestEntryData::File(Some(digest!(3)))),
+                ("b".into(), ManifestEntryData::File(Some(digest!(2)))),
+                ("c".into(), ManifestEntryData::File(Some(digest!(4)))),
+            ]
+        );
+    }
+
+    #[test]
+    fn directories_merge() {
+        assert_eq!(
+            squash(vec![
+                vec![dir("d"), file("d/a", 1)],
+                vec![dir("d"), file("d/b", 2)],
+            ])
+            .unwrap(),
+            vec![
+                ("d".into(), ManifestEntryData::Directory),
+                ("d/a".into(), ManifestEntryData::File(Some(digest!(1)))),
+                ("d/b".into(), ManifestEntryData::File(Some(digest!(2)))),
+            ]
+        );
+    }
+
+    #[test]
+    fn file_hides_directory() {
+        assert_eq!(
+            squash(vec![
+                vec![dir("d"), file("d/a", 1), file("d-a", 2)],
+                vec![file("d", 3)],
+            ])
+            .unwrap(),
+            vec![
+                ("d".into(), ManifestEntryData::File(Some(digest!(3)))),
+                ("d-a".into(), ManifestEntryData::File(Some(digest!(2)))),
+            ]
+        );
+    }
+
+    #[test]
+    fn whiteout_removes_tree() {
+        assert_eq!(
+            squash(vec![
+                vec![dir("d"), file("d/a", 1), file("da", 2), file("e", 3)],
+                vec![file("/.wh.d", 0), file(".wh.e", 0), file("e", 4)],
+            ])
+            .unwrap(),
+            vec![
+                ("da".into(), ManifestEntryData::File(Some(digest!(2)))),
+                ("e".into(), ManifestEntryData::File(Some(digest!(4)))),
+            ]
+        );
+    }
+
+    #[test]
+    fn opaque_whiteout_removes_contents() {
+        assert_eq!(
+            squash(vec![
+                vec![dir("d"), file("d/a", 1), file("d/b", 2)],
+                vec![file("d/b", 3), file("d/.wh..wh..opq", 0)],
+            ])
+            .unwrap(),
+            vec![
+                ("d".into(), ManifestEntryData::Directory),
+                ("d/b".into(), ManifestEntryData::File(Some(digest!(3)))),
+            ]
+        );
+    }
+
+    #[test]
+    fn hardlinks_are_resolved() {
+        assert_eq!(
+            squash(vec![
+                vec![file("a", 1)],
+                vec![entry("b", ManifestEntryData::Hardlink("/a".into()))],
+                vec![file("a", 2)],
+            ])
+            .unwrap(),
+            vec![
+                ("a".into(), ManifestEntryData::File(Some(digest!(2)))),
+                ("b".into(), ManifestEntryData::File(Some(digest!(1)))),
+            ]
+        );
+    }
+
+    #[test]
+    fn hardlink_to_missing_file() {
+        assert_eq!(
+            squash(vec![vec![entry(
+                "b",
+                ManifestEntryData::Hardlink("a".into())
+            )]])
+            .unwrap_err()
+            .to_string(),
+            "hard link b to missing file a"
+        );
+    }
+}
@@ -4,10 +4,14 @@ pub use maelstrom_client_base::{spec, ClientDriverMode, JobResponseHandler, MANI
 
 use anyhow::{anyhow, Result};
 use indicatif::ProgressBar;
-use maelstrom_base::{proto, stats::JobStateCounts, ArtifactType, JobSpec, Sha256Digest};
+use maelstrom_base::{
+    proto::{self, ArtifactCompression},
+    stats::JobStateCounts,
+    ArtifactType, JobSpec, Sha256Digest,
+};
 use maelstrom_client_base::comm;
 use maelstrom_container::ContainerImage;
-use maelstrom_util::config::BrokerAddr;
+use maelstrom_util::config::{BrokerAddr, LogFormat, LogLevel};
 use spec::Layer;
 use std::collections::HashMap;
 use std::os::unix::net::UnixStream;
@@ -247,12 +251,17 @@ pub struct Client {
 }
 
 impl Client {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         mut process_handle: ClientBgProcess,
         driver_mode: ClientDriverMode,
         broker_addr: BrokerAddr,
+        artifact_compression: ArtifactCompression,
         project_dir: impl AsRef<Path>,
         cache_dir: impl AsRef<Path>,
+        locked: bool,
+        log_level: LogLevel,
+        log_format: LogFormat,
     ) -> Result<Self> {
         let (send, recv) = channel();
 
@@ -267,8 +276,12 @@ impl Client {
         send_sync!(s, Start,
             driver_mode: driver_mode,
             broker_addr: broker_addr,
+            artifact_compression: artifact_compression,
             project_dir: project_dir.as_ref().to_owned(),
-            cache_dir: cache_dir.as_ref().to_owned()
+            cache_dir: cache_dir.as_ref().to_owned(),
+            locked: locked,
+            log_level: log_level,
+            log_format: log_format
         )?;
         Ok(s)
     }
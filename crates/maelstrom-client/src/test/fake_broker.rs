@@ -1,15 +1,21 @@
 use assert_matches::assert_matches;
 use maelstrom_base::{
     proto::{
-        self, ArtifactPusherToBroker, BrokerToArtifactPusher, BrokerToClient, ClientToBroker, Hello,
+        self, ArtifactCompression, ArtifactPusherToBroker, BrokerToArtifactPusher, BrokerToClient,
+        ClientToBroker, Hello,
     },
     stats::JobStateCounts,
     ClientJobId, JobOutcomeResult, JobSpec,
 };
-use maelstrom_util::{config::BrokerAddr, ext::OptionExt as _, fs::Fs, io::FixedSizeReader};
+use maelstrom_util::{
+    config::BrokerAddr,
+    ext::OptionExt as _,
+    fs::Fs,
+    io::{ChunkedReader, DecompressingReader, FixedSizeReader},
+};
 use serde::{de::DeserializeOwned, Serialize};
 use std::collections::HashMap;
-use std::io::{self, Read as _, Write as _};
+use std::io::{self, BufReader, Read as _, Write as _};
 use std::net::{Ipv6Addr, SocketAddrV6, TcpListener, TcpStream};
 use std::path::Path;
 
@@ -111,13 +117,22 @@ impl FakeBroker {
         let msg: Hello = messages.next().unwrap();
         assert_matches!(msg, Hello::ArtifactPusher);
 
-        let ArtifactPusherToBroker(digest, size) = messages.next().unwrap();
+        let ArtifactPusherToBroker(digest, size, compression) = messages.next().unwrap();
         let destination = digest_dir.join(digest.to_string());
 
         let fs = Fs::new();
         let mut stream = messages.into_inner();
         let mut file = fs.create_file(destination).unwrap();
-        io::copy(&mut FixedSizeReader::new(&mut stream, size), &mut file).unwrap();
+        if compression == ArtifactCompression::None {
+            io::copy(&mut FixedSizeReader::new(&mut stream, size), &mut file).unwrap();
+        } else {
+            let mut chunked_reader = ChunkedReader::new(&mut stream);
+            let mut decompressor =
+                DecompressingReader::new(BufReader::new(&mut chunked_reader), compression).unwrap();
+            io::copy(&mut decompressor, &mut file).unwrap();
+            drop(decompressor);
+            io::copy(&mut chunked_reader, &mut io::sink()).unwrap();
+        }
         send_message(&stream, &BrokerToArtifactPusher(Ok(())));
     }
 }
@@ -1,22 +1,29 @@
 use maelstrom_base::{
     manifest::{ManifestEntry, ManifestEntryData, ManifestReader, Mode},
+    proto::ArtifactCompression,
     ArtifactType, JobEffects, JobOutcome, JobOutputResult, JobSpec, JobStatus, Sha256Digest,
     Utf8Path, Utf8PathBuf,
 };
 use maelstrom_client::{
-    spec::{Layer, PrefixOptions, SymlinkSpec},
+    spec::{FileContents, FileSpec, Layer, PrefixOptions, SymlinkSpec},
     test::fake_broker::{FakeBroker, FakeBrokerJobAction, FakeBrokerState, JobSpecMatcher},
     Client, ClientBgProcess, ClientDriverMode,
 };
 use maelstrom_test::utf8_path_buf;
-use maelstrom_util::fs::Fs;
+use maelstrom_util::{
+    config::{LogFormat, LogLevel},
+    fs::Fs,
+};
 use maplit::hashmap;
 use nonempty::{nonempty, NonEmpty};
 use sha2::{Digest as _, Sha256};
 use std::collections::HashMap;
+use std::os::unix::fs::PermissionsExt as _;
 use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
 use std::sync::mpsc;
 use std::thread;
+use std::time::Duration;
 use tempfile::tempdir;
 
 fn basic_job_test(
@@ -36,6 +43,8 @@ fn basic_job_test(
         effects: JobEffects {
             stdout: JobOutputResult::None,
             stderr: JobOutputResult::Inline(Box::new(*b"this output should be ignored")),
+            diagnostics: JobOutputResult::None,
+            duration: Duration::ZERO,
         },
     };
 
@@ -53,8 +62,12 @@ fn basic_job_test(
         bg_proc,
         ClientDriverMode::SingleThreaded,
         broker.address().clone(),
+        ArtifactCompression::Zstd,
         &artifact_dir,
         cache_dir,
+        false,
+        LogLevel::Warning,
+        LogFormat::Text,
     )
     .unwrap();
     let mut broker_conn = broker.accept();
@@ -69,12 +82,19 @@ fn basic_job_test(
         layers: layers.clone(),
         devices: Default::default(),
         mounts: vec![],
-        enable_loopback: false,
+        network: Default::default(),
         enable_writable_file_system: false,
         working_directory: utf8_path_buf!("."),
         user: 1000.into(),
         group: 1000.into(),
         timeout: Default::default(),
+        stop_signal: Default::default(),
+        grace_period: Default::default(),
+        stack_dump_on_timeout: Default::default(),
+        seccomp: Default::default(),
+        rlimits: Default::default(),
+        hostname: Default::default(),
+        domainname: Default::default(),
     };
     let (send, recv) = mpsc::channel();
     client
@@ -599,3 +619,370 @@ fn symlink_test() {
         },
     );
 }
+
+#[test]
+fn files_layer_test() {
+    basic_job_test(
+        |client, _| {
+            let err = client
+                .add_layer(Layer::Files {
+                    files: vec![FileSpec {
+                        path: "/foo".into(),
+                        mode: 0o644,
+                        contents: FileContents::Base64("not base64!".into()),
+                    }],
+                })
+                .unwrap_err();
+            assert!(
+                format!("{err:#}").contains("decoding base64 contents of /foo"),
+                "{err:#}"
+            );
+
+            let digest_and_type = client
+                .add_layer(Layer::Files {
+                    files: vec![
+                        FileSpec {
+                            path: "/etc/foo.conf".into(),
+                            mode: 0o644,
+                            contents: FileContents::Text("foo = 1\n".into()),
+                        },
+                        FileSpec {
+                            path: "/bin/run".into(),
+                            mode: 0o755,
+                            contents: FileContents::Base64("IyEvYmluL3NoCg==".into()),
+                        },
+                        FileSpec {
+                            path: "/empty".into(),
+                            mode: 0o444,
+                            contents: FileContents::Text("".into()),
+                        },
+                    ],
+                })
+                .unwrap();
+            nonempty![digest_and_type]
+        },
+        |artifact_dir, layers| {
+            let digest = &layers[0].0;
+            verify_manifest(
+                &artifact_dir.join(digest.to_string()),
+                vec![
+                    ExpectedManifestEntry::new(
+                        "/etc/foo.conf",
+                        0o644,
+                        ManifestEntryData::File(Some(hash_data(b"foo = 1\n"))),
+                    ),
+                    ExpectedManifestEntry::new(
+                        "/bin/run",
+                        0o755,
+                        ManifestEntryData::File(Some(hash_data(b"#!/bin/sh\n"))),
+                    ),
+                    ExpectedManifestEntry::new("/empty", 0o444, ManifestEntryData::File(None)),
+                ],
+            )
+        },
+    );
+}
+
+fn build_tar(path: &Path) {
+    let fs = Fs::new();
+    let mut builder = tar::Builder::new(fs.create_file(path).unwrap());
+    let mut append =
+        |path: &str, entry_type: tar::EntryType, mode: u32, link: &str, data: &[u8]| {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(entry_type);
+            header.set_mode(mode);
+            header.set_size(data.len() as u64);
+            if !link.is_empty() {
+                header.set_link_name(link).unwrap();
+            }
+            builder.append_data(&mut header, path, data).unwrap();
+        };
+    append("d/", tar::EntryType::Directory, 0o755, "", b"");
+    append("d/a", tar::EntryType::Regular, 0o644, "", b"a");
+    append("d/b", tar::EntryType::Regular, 0o755, "", b"b");
+    append("d/h", tar::EntryType::Link, 0o644, "d/a", b"");
+    append("d/s", tar::EntryType::Symlink, 0o777, "a", b"");
+    append("e", tar::EntryType::Regular, 0o644, "", b"e");
+    builder.finish().unwrap();
+}
+
+#[test]
+fn squash_layer_test() {
+    basic_job_test(
+        |client, artifact_dir| {
+            let tar_path = artifact_dir.join("base.tar");
+            build_tar(&tar_path);
+
+            let digest_and_type = client
+                .add_layer(Layer::Squash {
+                    layers: vec![
+                        Layer::Tar {
+                            path: tar_path.try_into().unwrap(),
+                        },
+                        Layer::Files {
+                            files: vec![
+                                FileSpec {
+                                    path: "/d/.wh.b".into(),
+                                    mode: 0o644,
+                                    contents: FileContents::Text("".into()),
+                                },
+                                FileSpec {
+                                    path: "/d/a".into(),
+                                    mode: 0o600,
+                                    contents: FileContents::Text("new a".into()),
+                                },
+                            ],
+                        },
+                        Layer::Stubs {
+                            stubs: vec!["/f/".into()],
+                        },
+                    ],
+                })
+                .unwrap();
+            nonempty![digest_and_type]
+        },
+        |artifact_dir, layers| {
+            let digest = &layers[0].0;
+            verify_manifest(
+                &artifact_dir.join(digest.to_string()),
+                vec![
+                    ExpectedManifestEntry::new("d", 0o40755, ManifestEntryData::Directory),
+                    ExpectedManifestEntry::new(
+                        "d/a",
+                        0o600,
+                        ManifestEntryData::File(Some(hash_data(b"new a"))),
+                    ),
+                    ExpectedManifestEntry::new(
+                        "d/h",
+                        0o100644,
+                        ManifestEntryData::File(Some(hash_data(b"a"))),
+                    ),
+                    ExpectedManifestEntry::new(
+                        "d/s",
+                        0o120777,
+                        ManifestEntryData::Symlink(b"a".to_vec()),
+                    ),
+                    ExpectedManifestEntry::new(
+                        "e",
+                        0o100644,
+                        ManifestEntryData::File(Some(hash_data(b"e"))),
+                    ),
+                    ExpectedManifestEntry::new("f", 0o555, ManifestEntryData::Directory),
+                ],
+            )
+        },
+    );
+}
+
+#[test]
+fn squash_layer_digest_depends_only_on_contents() {
+    basic_job_test(
+        |client, artifact_dir| {
+            let fs = Fs::new();
+            fs.write(artifact_dir.join("foo.txt"), b"foo").unwrap();
+            let paths = Layer::Paths {
+                paths: vec!["foo.txt".into()],
+                prefix_options: Default::default(),
+            };
+            let stubs = Layer::Stubs {
+                stubs: vec!["/bar".into()],
+            };
+
+            let glob = Layer::Glob {
+                glob: "foo.txt".into(),
+                prefix_options: Default::default(),
+            };
+
+            // These are all different ways of specifying the same files, so they should all
+            // squash to the same layer.
+            let first = client
+                .add_layer(Layer::Squash {
+                    layers: vec![paths.clone(), stubs.clone()],
+                })
+                .unwrap();
+            let second = client
+                .add_layer(Layer::Squash {
+                    layers: vec![glob, stubs.clone()],
+                })
+                .unwrap();
+            let nested = client
+                .add_layer(Layer::Squash {
+                    layers: vec![
+                        Layer::Squash {
+                            layers: vec![paths],
+                        },
+                        stubs,
+                    ],
+                })
+                .unwrap();
+            assert_eq!(first, second);
+            assert_eq!(first, nested);
+            nonempty![second]
+        },
+        |artifact_dir, layers| {
+            let digest = &layers[0].0;
+            verify_manifest(
+                &artifact_dir.join(digest.to_string()),
+                vec![
+                    ExpectedManifestEntry::new("bar", 0o444, ManifestEntryData::File(None)),
+                    ExpectedManifestEntry::new(
+                        "foo.txt",
+                        0o100644,
+                        ManifestEntryData::File(Some(hash_data(b"foo"))),
+                    ),
+                ],
+            )
+        },
+    );
+}
+
+fn git(repo: &Path, args: &[&str]) {
+    let status = Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+        .args(args)
+        .stdout(Stdio::null())
+        .status()
+        .unwrap();
+    assert!(status.success(), "git {args:?} failed");
+}
+
+fn init_git_repo(repo: &Path) {
+    let fs = Fs::new();
+    git(repo, &["init", "-q"]);
+    fs.create_dir_all(repo.join("fixtures")).unwrap();
+    fs.write(repo.join("fixtures/a.txt"), b"hello world")
+        .unwrap();
+    fs.write(repo.join("fixtures/empty.txt"), b"").unwrap();
+    fs.write(repo.join("fixtures/run.sh"), b"#!/bin/sh\n")
+        .unwrap();
+    std::fs::set_permissions(
+        repo.join("fixtures/run.sh"),
+        std::fs::Permissions::from_mode(0o755),
+    )
+    .unwrap();
+    fs.symlink("a.txt", repo.join("fixtures/link")).unwrap();
+    fs.write(repo.join("other.txt"), b"not a fixture").unwrap();
+    git(repo, &["add", "."]);
+    git(repo, &["commit", "-q", "-m", "first"]);
+}
+
+#[test]
+fn git_layer_test() {
+    basic_job_test(
+        |client, artifact_dir| {
+            init_git_repo(artifact_dir);
+
+            // Neither untracked files nor changes in the working tree should end up in the layer.
+            let fs = Fs::new();
+            fs.write(artifact_dir.join("fixtures/junk.txt"), b"junk")
+                .unwrap();
+            fs.write(artifact_dir.join("fixtures/a.txt"), b"modified")
+                .unwrap();
+
+            let digest_and_type = client
+                .add_layer(Layer::Git {
+                    repo: ".".into(),
+                    rev: "HEAD".into(),
+                    paths: vec!["fixtures".into()],
+                    prefix_options: PrefixOptions {
+                        strip_prefix: Some("fixtures".into()),
+                        prepend_prefix: Some("/data".into()),
+                        ..Default::default()
+                    },
+                })
+                .unwrap();
+            nonempty![digest_and_type]
+        },
+        |artifact_dir, layers| {
+            let digest = &layers[0].0;
+            verify_manifest(
+                &artifact_dir.join(digest.to_string()),
+                vec![
+                    ExpectedManifestEntry::new(
+                        "/data/a.txt",
+                        0o100644,
+                        ManifestEntryData::File(Some(hash_data(b"hello world"))),
+                    ),
+                    ExpectedManifestEntry::new(
+                        "/data/empty.txt",
+                        0o100644,
+                        ManifestEntryData::File(None),
+                    ),
+                    ExpectedManifestEntry::new(
+                        "/data/link",
+                        0o120777,
+                        ManifestEntryData::Symlink(b"a.txt".to_vec()),
+                    ),
+                    ExpectedManifestEntry::new(
+                        "/data/run.sh",
+                        0o100755,
+                        ManifestEntryData::File(Some(hash_data(b"#!/bin/sh\n"))),
+                    ),
+                ],
+            )
+        },
+    );
+}
+
+#[test]
+fn git_layer_digest_depends_only_on_tree() {
+    basic_job_test(
+        |client, artifact_dir| {
+            init_git_repo(artifact_dir);
+            git(
+                artifact_dir,
+                &["commit", "-q", "--allow-empty", "-m", "second"],
+            );
+
+            let mut add_git_layer = |rev: &str| {
+                client
+                    .add_layer(Layer::Git {
+                        repo: ".".into(),
+                        rev: rev.into(),
+                        paths: vec![],
+                        prefix_options: Default::default(),
+                    })
+                    .unwrap()
+            };
+            let first = add_git_layer("HEAD~1");
+            let second = add_git_layer("HEAD");
+            assert_eq!(first, second);
+            nonempty![second]
+        },
+        |artifact_dir, layers| {
+            let digest = &layers[0].0;
+            verify_manifest(
+                &artifact_dir.join(digest.to_string()),
+                vec![
+                    ExpectedManifestEntry::new(
+                        "fixtures/a.txt",
+                        0o100644,
+                        ManifestEntryData::File(Some(hash_data(b"hello world"))),
+                    ),
+                    ExpectedManifestEntry::new(
+                        "fixtures/empty.txt",
+                        0o100644,
+                        ManifestEntryData::File(None),
+                    ),
+                    ExpectedManifestEntry::new(
+                        "fixtures/link",
+                        0o120777,
+                        ManifestEntryData::Symlink(b"a.txt".to_vec()),
+                    ),
+                    ExpectedManifestEntry::new(
+                        "fixtures/run.sh",
+                        0o100755,
+                        ManifestEntryData::File(Some(hash_data(b"#!/bin/sh\n"))),
+                    ),
+                    ExpectedManifestEntry::new(
+                        "other.txt",
+                        0o100644,
+                        ManifestEntryData::File(Some(hash_data(b"not a fixture"))),
+                    ),
+                ],
+            )
+        },
+    );
+}
@@ -1,8 +1,11 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
 use async_compression::tokio::bufread::GzipDecoder;
 use core::task::Poll;
 use futures::stream::TryStreamExt as _;
-use maelstrom_util::fs::Fs;
+use maelstrom_util::{
+    fs::{is_not_found_err, File, Fs},
+    process::ExitCode,
+};
 use oci_spec::image::{Arch, Descriptor, ImageIndex, ImageManifest, Os, Platform, RootFs};
 use serde::{Deserialize, Serialize};
 use serde_repr::{Deserialize_repr, Serialize_repr};
@@ -11,7 +14,9 @@ use std::{
     collections::{BTreeMap, HashMap},
     fmt,
     io::{self, Read as _, Seek as _, SeekFrom, Write as _},
+    os::unix::fs::MetadataExt as _,
     path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 use tokio::{io::AsyncWrite, task};
 use tokio_util::compat::FuturesAsyncReadCompatExt as _;
@@ -297,6 +302,13 @@ impl ContainerImage {
             .as_ref()
             .and_then(|c| c.working_dir.as_ref())
     }
+
+    pub fn stop_signal(&self) -> Option<&String> {
+        self.config
+            .config
+            .as_ref()
+            .and_then(|c| c.stop_signal.as_ref())
+    }
 }
 
 fn download_layer_on_task(
@@ -386,7 +398,7 @@ pub enum LockedContainerImageTagsVersion {
 struct LockedContainerImageTags {
     version: LockedContainerImageTagsVersion,
     #[serde(flatten)]
-    map: BTreeMap<String, HashMap<String, String>>,
+    map: BTreeMap<String, BTreeMap<String, String>>,
 }
 
 impl LockedContainerImageTags {
@@ -402,6 +414,46 @@ impl LockedContainerImageTags {
     fn add(&mut self, name: String, tag: String, digest: String) {
         self.map.entry(name).or_default().insert(tag, digest);
     }
+
+    fn iter(&self) -> impl Iterator<Item = (&String, &String, &String)> {
+        self.map
+            .iter()
+            .flat_map(|(name, tags)| tags.iter().map(move |(tag, digest)| (name, tag, digest)))
+    }
+}
+
+/// Split an image reference of the form `name` or `name:tag` into its name and tag. The tag is only
+/// looked for after the last `/`, so that the port in a name like `localhost:5000/foo` isn't taken
+/// for one.
+pub fn split_image_tag(image: &str) -> (&str, Option<&str>) {
+    let name_start = image.rfind('/').map_or(0, |slash| slash + 1);
+    match image[name_start..].find(':') {
+        Some(colon) => {
+            let colon = name_start + colon;
+            (&image[..colon], Some(&image[colon + 1..]))
+        }
+        None => (image, None),
+    }
+}
+
+/// A change to a pinned digest in the tags lockfile, as made by
+/// [`ContainerImageDepot::update_locked_tags`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LockedTagUpdate {
+    pub name: String,
+    pub tag: String,
+    pub old_digest: String,
+    pub new_digest: String,
+}
+
+impl fmt::Display for LockedTagUpdate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}: {} -> {}",
+            self.name, self.tag, self.old_digest, self.new_digest
+        )
+    }
 }
 
 pub trait ContainerImageDepotOps {
@@ -449,11 +501,18 @@ pub struct ContainerImageDepot<ContainerImageDepotOpsT = DefaultContainerImageDe
     project_dir: PathBuf,
     ops: ContainerImageDepotOpsT,
     cache: HashMap<(String, String), ContainerImage>,
+    locked: bool,
+    max_cache_size: Option<u64>,
+    /// Shared locks on the images we've handed out, which keep other clients from removing them
+    /// while we're still using them. They're held until the depot is dropped.
+    image_locks: HashMap<String, std::fs::File>,
 }
 
 impl ContainerImageDepot<DefaultContainerImageDepotOps> {
-    pub fn new(project_dir: impl AsRef<Path>) -> Result<Self> {
-        Self::new_with(
+    /// Create a new depot. If `locked` is true, tags which aren't already pinned in the project's
+    /// tags lockfile are an error instead of being resolved and added to it.
+    pub fn new(project_dir: impl AsRef<Path>, locked: bool) -> Result<Self> {
+        let mut depot = Self::new_with(
             project_dir,
             directories::BaseDirs::new()
                 .expect("failed to find cache dir")
@@ -461,12 +520,78 @@ impl ContainerImageDepot<DefaultContainerImageDepotOps> {
                 .join("maelstrom")
                 .join("containers"),
             DefaultContainerImageDepotOps::new(),
-        )
+        )?;
+        depot.locked = locked;
+        Ok(depot)
     }
 }
 
 const TAG_FILE_NAME: &str = "maelstrom-container-tags.lock";
 
+/// The default for [`ContainerImageDepot::set_max_cache_size`]: 10 GiB.
+pub const DEFAULT_MAX_CACHE_SIZE: u64 = 10 << 30;
+const LAST_USED_FILE_NAME: &str = ".last-used";
+
+/// An image stored in the depot's cache directory, along with the bookkeeping used to decide when
+/// it should be pruned.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CachedContainerImage {
+    pub image: ContainerImage,
+    /// The number of bytes the image takes up on disk.
+    pub size: u64,
+    /// The last time a client used the image.
+    pub last_used: SystemTime,
+}
+
+/// Policy for [`ContainerImageDepot::prune`]. Images are considered in least-recently-used order,
+/// and an image is removed if it violates any of the given limits.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PruneOptions {
+    /// Remove least-recently-used images until the cache takes up at most this many bytes.
+    pub max_size: Option<u64>,
+    /// Remove images that haven't been used in longer than this.
+    pub max_age: Option<Duration>,
+}
+
+/// Treat a file that doesn't exist as if it had been found to be absent. Other clients may remove
+/// images while we're looking at the cache directory.
+fn ignore_not_found<T>(result: Result<T>) -> Result<Option<T>> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(err) if is_not_found_err(&err) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// The number of bytes the files under `path` take up. Files that are removed while we're
+/// walking the tree aren't counted.
+fn dir_size(fs: &Fs, path: &Path) -> Result<u64> {
+    let mut size = 0;
+    let Some(entries) = ignore_not_found(fs.read_dir(path))? else {
+        return Ok(0);
+    };
+    for entry in entries {
+        let Some(entry) = ignore_not_found(entry)? else {
+            continue;
+        };
+        let Some(metadata) = ignore_not_found(entry.metadata())? else {
+            continue;
+        };
+        size += if metadata.is_dir() {
+            dir_size(fs, &entry.path())?
+        } else {
+            metadata.len()
+        };
+    }
+    Ok(size)
+}
+
+#[derive(Clone, Copy)]
+enum CacheLock {
+    Shared,
+    Exclusive,
+}
+
 impl<ContainerImageDepotOpsT: ContainerImageDepotOps> ContainerImageDepot<ContainerImageDepotOpsT> {
     fn new_with(
         project_dir: impl AsRef<Path>,
@@ -485,6 +610,9 @@ impl<ContainerImageDepotOpsT: ContainerImageDepotOps> ContainerImageDepot<Contai
             cache_dir: cache_dir.to_owned(),
             cache: Default::default(),
             ops,
+            locked: false,
+            max_cache_size: Some(DEFAULT_MAX_CACHE_SIZE),
+            image_locks: Default::default(),
         })
     }
 
@@ -496,6 +624,8 @@ impl<ContainerImageDepotOpsT: ContainerImageDepotOps> ContainerImageDepot<Contai
     ) -> Result<String> {
         Ok(if let Some(digest) = locked_tags.get(name, tag) {
             digest.into()
+        } else if self.locked {
+            bail!("container image {name}:{tag} is not pinned in {TAG_FILE_NAME}");
         } else {
             let digest = self.ops.resolve_tag(name, tag)?;
             locked_tags.add(name.into(), tag.into(), digest.clone());
@@ -507,6 +637,25 @@ impl<ContainerImageDepotOpsT: ContainerImageDepotOps> ContainerImageDepot<Contai
         ContainerImage::from_dir(&self.fs, self.cache_dir.join(digest))
     }
 
+    fn touch_image(&self, digest: &str) -> Result<()> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        self.fs.write(
+            self.cache_dir.join(digest).join(LAST_USED_FILE_NAME),
+            now.to_string(),
+        )
+    }
+
+    fn image_last_used(&self, digest: &str) -> SystemTime {
+        let image_dir = self.cache_dir.join(digest);
+        self.fs
+            .read_to_string(image_dir.join(LAST_USED_FILE_NAME))
+            .ok()
+            .and_then(|s| s.trim().parse().ok())
+            .map(|secs| UNIX_EPOCH + Duration::from_secs(secs))
+            .or_else(|| self.fs.metadata(image_dir).ok()?.modified().ok())
+            .unwrap_or(UNIX_EPOCH)
+    }
+
     fn download_image(
         &self,
         name: &str,
@@ -528,17 +677,71 @@ impl<ContainerImageDepotOpsT: ContainerImageDepotOps> ContainerImageDepot<Contai
         Ok(img)
     }
 
+    fn cache_lock_path(&self, digest: &str) -> PathBuf {
+        self.cache_dir.join(format!(".{digest}.flock"))
+    }
+
+    /// Open and lock the lock file for the image with the given digest. Clients hold the lock
+    /// shared while they use the image, and exclusive while they download or remove it. If `wait`
+    /// is false and the lock can't be taken right away, return `None` instead of waiting for it.
+    ///
+    /// [`Self::remove_image`] removes the lock file along with the image. Another client may have
+    /// opened the old file by then, so once we have the lock, we check that the file we locked is
+    /// still the one at the path, and try again if it isn't.
+    fn lock_cache_file(
+        &self,
+        digest: &str,
+        lock: CacheLock,
+        wait: bool,
+    ) -> Result<Option<File<'_>>> {
+        let lock_file_path = self.cache_lock_path(digest);
+        loop {
+            let lock_file = self.fs.create_file(&lock_file_path)?;
+            match (lock, wait) {
+                (CacheLock::Shared, true) => lock_file.lock_shared()?,
+                (CacheLock::Exclusive, true) => lock_file.lock_exclusive()?,
+                (CacheLock::Shared, false) => {
+                    if lock_file.try_lock_shared().is_err() {
+                        return Ok(None);
+                    }
+                }
+                (CacheLock::Exclusive, false) => {
+                    if lock_file.try_lock_exclusive().is_err() {
+                        return Ok(None);
+                    }
+                }
+            }
+            let locked = lock_file.metadata()?;
+            if let Ok(current) = self.fs.metadata(&lock_file_path) {
+                if (current.dev(), current.ino()) == (locked.dev(), locked.ino()) {
+                    return Ok(Some(lock_file));
+                }
+            }
+        }
+    }
+
     fn with_cache_lock<Ret>(
         &self,
         digest: &str,
         body: impl FnOnce() -> Result<Ret>,
     ) -> Result<Ret> {
-        let lock_file_path = self.cache_dir.join(format!(".{digest}.flock"));
-        let lock_file = self.fs.create_file(lock_file_path)?;
-        lock_file.lock_exclusive()?;
+        let _lock_file = self.lock_cache_file(digest, CacheLock::Exclusive, true)?;
         body()
     }
 
+    /// Like `with_cache_lock`, but if some other client holds the lock, shared or exclusive,
+    /// return `None` instead of waiting for it.
+    fn try_with_cache_lock<Ret>(
+        &self,
+        digest: &str,
+        body: impl FnOnce() -> Result<Ret>,
+    ) -> Result<Option<Ret>> {
+        let Some(_lock_file) = self.lock_cache_file(digest, CacheLock::Exclusive, false)? else {
+            return Ok(None);
+        };
+        body().map(Some)
+    }
+
     fn with_locked_tags<Ret>(
         &self,
         body: impl FnOnce(&mut LockedContainerImageTags) -> Result<Ret>,
@@ -554,13 +757,67 @@ impl<ContainerImageDepotOpsT: ContainerImageDepotOps> ContainerImageDepot<Contai
 
         let ret = body(&mut locked_tags);
 
-        lock_file.seek(SeekFrom::Start(0))?;
-        lock_file.set_len(0)?;
-        lock_file.write_all(toml::to_string_pretty(&locked_tags).unwrap().as_bytes())?;
+        let new_contents = toml::to_string_pretty(&locked_tags).unwrap();
+        // Don't bother writing out a lockfile that doesn't pin anything.
+        if new_contents != contents && !(contents.is_empty() && locked_tags.map.is_empty()) {
+            lock_file.seek(SeekFrom::Start(0))?;
+            lock_file.set_len(0)?;
+            lock_file.write_all(new_contents.as_bytes())?;
+        }
 
         ret
     }
 
+    /// Re-resolve the tags pinned in the tags lockfile and update any that now point at a
+    /// different digest. `images` selects which entries to consider: each element is either a
+    /// name, matching all of its tags, or a `name:tag` pair. If `images` is empty, every entry is
+    /// considered. If `check_only` is true, the lockfile is left alone. Returns the entries whose
+    /// digests changed.
+    pub fn update_locked_tags(
+        &mut self,
+        images: &[String],
+        check_only: bool,
+    ) -> Result<Vec<LockedTagUpdate>> {
+        let selected = |name: &str, tag: &str| {
+            images.is_empty()
+                || images.iter().any(|image| match split_image_tag(image) {
+                    (n, Some(t)) => n == name && t == tag,
+                    (n, None) => n == name,
+                })
+        };
+        let updates = self.with_locked_tags(|locked_tags| {
+            let mut updates = vec![];
+            for (name, tag, old_digest) in locked_tags.iter() {
+                if !selected(name, tag) {
+                    continue;
+                }
+                let new_digest = self.ops.resolve_tag(name, tag)?;
+                if &new_digest != old_digest {
+                    updates.push(LockedTagUpdate {
+                        name: name.clone(),
+                        tag: tag.clone(),
+                        old_digest: old_digest.clone(),
+                        new_digest,
+                    });
+                }
+            }
+            if !check_only {
+                for update in &updates {
+                    locked_tags.add(
+                        update.name.clone(),
+                        update.tag.clone(),
+                        update.new_digest.clone(),
+                    );
+                }
+            }
+            Ok(updates)
+        })?;
+        if !check_only {
+            self.cache.clear();
+        }
+        Ok(updates)
+    }
+
     pub fn get_container_image(
         &mut self,
         name: &str,
@@ -572,21 +829,166 @@ impl<ContainerImageDepotOpsT: ContainerImageDepotOps> ContainerImageDepot<Contai
             return Ok(img.clone());
         }
 
-        let img = self.with_locked_tags(|locked_tags| {
-            let digest = self.get_image_digest(locked_tags, name, tag)?;
-
-            self.with_cache_lock(&digest, || {
-                Ok(if let Some(img) = self.get_cached_image(&digest) {
-                    img
-                } else {
-                    self.download_image(name, &digest, prog)?
-                })
-            })
-        })?;
+        let digest =
+            self.with_locked_tags(|locked_tags| self.get_image_digest(locked_tags, name, tag))?;
+
+        // If we already hold a shared lock on the image, we'd never get the exclusive lock needed
+        // to download it again if it turns out to be corrupt.
+        self.image_locks.remove(&digest);
+        let (img, lock_file, downloaded) = self.get_and_lock_image(name, &digest, prog)?;
+        self.image_locks.insert(digest, lock_file);
+
+        // The cache only grows when we download something, so that's when we enforce its limit.
+        // The images we hold locks on, including the one just downloaded, won't be removed.
+        if downloaded && self.max_cache_size.is_some() {
+            let options = PruneOptions {
+                max_size: self.max_cache_size,
+                ..Default::default()
+            };
+            self.prune(&options)?;
+        }
 
         self.cache.insert(cache_key, img.clone());
         Ok(img)
     }
+
+    /// Get the image with the given digest from the cache directory, downloading it if it isn't
+    /// there. Return it along with a shared lock on it, which keeps other clients from removing it,
+    /// and whether we downloaded it.
+    fn get_and_lock_image(
+        &self,
+        name: &str,
+        digest: &str,
+        prog: impl ProgressTracker,
+    ) -> Result<(ContainerImage, std::fs::File, bool)> {
+        let mut prog = Some(prog);
+        let mut downloaded = false;
+        loop {
+            let lock_file = self
+                .lock_cache_file(digest, CacheLock::Shared, true)?
+                .unwrap();
+            if let Some(img) = self.get_cached_image(digest) {
+                self.touch_image(digest)?;
+                return Ok((img, lock_file.into_inner(), downloaded));
+            }
+            drop(lock_file);
+
+            // Another client may download the image, or remove it again, between our releasing the
+            // exclusive lock and getting the shared one, so we check again at the top of the loop.
+            self.with_cache_lock(digest, || {
+                if self.get_cached_image(digest).is_none() {
+                    match prog.take() {
+                        Some(prog) => self.download_image(name, digest, prog)?,
+                        None => self.download_image(name, digest, NullProgressTracker)?,
+                    };
+                    downloaded = true;
+                }
+                Ok(())
+            })?;
+        }
+    }
+
+    /// List the images in the cache directory, least-recently-used first. Partially downloaded or
+    /// corrupt images are skipped, as are images that other clients remove while we're looking.
+    pub fn list_images(&self) -> Result<Vec<CachedContainerImage>> {
+        let mut images = vec![];
+        for entry in self.fs.read_dir(&self.cache_dir)? {
+            let Some(entry) = ignore_not_found(entry)? else {
+                continue;
+            };
+            let path = entry.path();
+            let Some(metadata) = ignore_not_found(self.fs.metadata(&path))? else {
+                continue;
+            };
+            if !metadata.is_dir() {
+                continue;
+            }
+            let Some(image) = ContainerImage::from_dir(&self.fs, &path) else {
+                continue;
+            };
+            images.push(CachedContainerImage {
+                size: dir_size(&self.fs, &path)?,
+                last_used: self.image_last_used(&image.digest),
+                image,
+            });
+        }
+        images.sort_by(|a, b| (a.last_used, &a.image.digest).cmp(&(b.last_used, &b.image.digest)));
+        Ok(images)
+    }
+
+    /// Set the most bytes the cache directory may take up. Whenever an image is downloaded,
+    /// least-recently-used images are removed, as by [`Self::prune`], until the cache fits. Images
+    /// in use by this or any other client, including the one just downloaded, are never removed.
+    /// `None` means there's no limit.
+    pub fn set_max_cache_size(&mut self, max_cache_size: Option<u64>) {
+        self.max_cache_size = max_cache_size;
+    }
+
+    /// Remove the image with the given digest from the cache directory, along with its lock file.
+    /// Returns `false` if the image is currently locked, because some client is using or
+    /// downloading it, in which case nothing is removed.
+    pub fn remove_image(&mut self, digest: &str) -> Result<bool> {
+        let image_dir = self.cache_dir.join(digest);
+        let removed = self
+            .try_with_cache_lock(digest, || {
+                if self.fs.exists(&image_dir) {
+                    self.fs.remove_dir_all(&image_dir)?;
+                }
+                // Remove the lock file while we still hold the lock. See `lock_cache_file`.
+                self.fs.remove_file(self.cache_lock_path(digest))
+            })?
+            .is_some();
+        if removed {
+            self.cache.retain(|_, img| img.digest != digest);
+        }
+        Ok(removed)
+    }
+
+    /// Remove images from the cache directory according to `options`. Images that are locked,
+    /// because this or another client is using them, are skipped. Returns the images that were
+    /// removed.
+    pub fn prune(&mut self, options: &PruneOptions) -> Result<Vec<CachedContainerImage>> {
+        let now = SystemTime::now();
+        let images = self.list_images()?;
+        let mut total_size: u64 = images.iter().map(|cached| cached.size).sum();
+        let mut removed = vec![];
+        for cached in images {
+            let too_old = options.max_age.is_some_and(|max_age| {
+                now.duration_since(cached.last_used).unwrap_or_default() > max_age
+            });
+            let too_big = options
+                .max_size
+                .is_some_and(|max_size| total_size > max_size);
+            if !too_old && !too_big {
+                continue;
+            }
+            if self.remove_image(&cached.image.digest)? {
+                total_size -= cached.size;
+                removed.push(cached);
+            }
+        }
+        Ok(removed)
+    }
+}
+
+/// Update the tags pinned in the tags lockfile in `project_dir`, as described in
+/// [`ContainerImageDepot::update_locked_tags`], and print each change. If `check` is true, the
+/// lockfile is left alone, and failure is returned if anything would have changed.
+pub fn update_container_tags(
+    project_dir: impl AsRef<Path>,
+    images: &[String],
+    check: bool,
+) -> Result<ExitCode> {
+    let mut depot = ContainerImageDepot::new(project_dir, false /* locked */)?;
+    let updates = depot.update_locked_tags(images, check)?;
+    for update in &updates {
+        println!("{update}");
+    }
+    Ok(if check && !updates.is_empty() {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    })
 }
 
 #[cfg(test)]
@@ -879,6 +1281,471 @@ fn container_image_depot_update_image_but_nothing_to_do() {
     );
 }
 
+#[cfg(test)]
+fn set_last_used(fs: &Fs, image_dir: &Path, digest: &str, secs: u64) {
+    fs.write(
+        image_dir.join(digest).join(LAST_USED_FILE_NAME),
+        secs.to_string(),
+    )
+    .unwrap();
+}
+
+#[cfg(test)]
+fn depot_with_three_images(
+    project_dir: &Path,
+    image_dir: &Path,
+) -> ContainerImageDepot<FakeContainerImageDepotOps> {
+    let fs = Fs::new();
+    let ops = FakeContainerImageDepotOps(maplit::hashmap! {
+        "foo-latest".into() => "sha256:abcdef".into(),
+        "bar-latest".into() => "sha256:ghijk".into(),
+        "baz-latest".into() => "sha256:lmnop".into(),
+    });
+    let mut depot = ContainerImageDepot::new_with(project_dir, image_dir, ops.clone()).unwrap();
+    for name in ["foo", "bar", "baz"] {
+        depot
+            .get_container_image(name, "latest", NullProgressTracker)
+            .unwrap();
+    }
+    for (digest, secs) in [
+        ("sha256:abcdef", 300),
+        ("sha256:ghijk", 100),
+        ("sha256:lmnop", 200),
+    ] {
+        set_last_used(&fs, image_dir, digest, secs);
+        fs.write(image_dir.join(digest).join("layer_0.tar"), [0u8; 1000])
+            .unwrap();
+    }
+
+    // Return a depot that isn't using any of the images.
+    drop(depot);
+    ContainerImageDepot::new_with(project_dir, image_dir, ops).unwrap()
+}
+
+#[test]
+fn container_image_depot_list_images_lru_order() {
+    let project_dir = tempfile::tempdir().unwrap();
+    let image_dir = tempfile::tempdir().unwrap();
+    let depot = depot_with_three_images(project_dir.path(), image_dir.path());
+
+    let images = depot.list_images().unwrap();
+    assert_eq!(
+        Vec::from_iter(images.iter().map(|c| c.image.name.as_str())),
+        vec!["bar", "baz", "foo"]
+    );
+    assert_eq!(images[0].last_used, UNIX_EPOCH + Duration::from_secs(100));
+    assert!(images.iter().all(|c| c.size > 1000));
+}
+
+#[test]
+fn container_image_depot_list_images_skips_corrupt() {
+    let fs = Fs::new();
+    let project_dir = tempfile::tempdir().unwrap();
+    let image_dir = tempfile::tempdir().unwrap();
+    let depot = depot_with_three_images(project_dir.path(), image_dir.path());
+    fs.remove_file(image_dir.path().join("sha256:abcdef").join("config.json"))
+        .unwrap();
+
+    let images = depot.list_images().unwrap();
+    assert_eq!(
+        Vec::from_iter(images.iter().map(|c| c.image.name.as_str())),
+        vec!["bar", "baz"]
+    );
+}
+
+#[test]
+fn container_image_depot_prune_by_size() {
+    let fs = Fs::new();
+    let project_dir = tempfile::tempdir().unwrap();
+    let image_dir = tempfile::tempdir().unwrap();
+    let mut depot = depot_with_three_images(project_dir.path(), image_dir.path());
+    let sizes = depot.list_images().unwrap();
+    let total: u64 = sizes.iter().map(|c| c.size).sum();
+
+    let removed = depot
+        .prune(&PruneOptions {
+            max_size: Some(total - 1),
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(
+        Vec::from_iter(removed.iter().map(|c| c.image.name.as_str())),
+        vec!["bar"]
+    );
+    assert_eq!(
+        sorted_dir_listing(&fs, image_dir.path()),
+        vec!["sha256:abcdef", "sha256:lmnop"]
+    );
+}
+
+#[test]
+fn container_image_depot_prune_by_age() {
+    let fs = Fs::new();
+    let project_dir = tempfile::tempdir().unwrap();
+    let image_dir = tempfile::tempdir().unwrap();
+    let mut depot = depot_with_three_images(project_dir.path(), image_dir.path());
+    set_last_used(
+        &fs,
+        image_dir.path(),
+        "sha256:lmnop",
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs(),
+    );
+
+    let removed = depot
+        .prune(&PruneOptions {
+            max_age: Some(Duration::from_secs(3600)),
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(
+        Vec::from_iter(removed.iter().map(|c| c.image.name.as_str())),
+        vec!["bar", "foo"]
+    );
+    assert_eq!(
+        sorted_dir_listing(&fs, image_dir.path()),
+        vec!["sha256:lmnop"]
+    );
+}
+
+#[test]
+fn container_image_depot_prune_skips_locked() {
+    let fs = Fs::new();
+    let project_dir = tempfile::tempdir().unwrap();
+    let image_dir = tempfile::tempdir().unwrap();
+    let mut depot = depot_with_three_images(project_dir.path(), image_dir.path());
+
+    let lock_file = fs
+        .create_file(image_dir.path().join(".sha256:ghijk.flock"))
+        .unwrap();
+    lock_file.lock_exclusive().unwrap();
+
+    let removed = depot
+        .prune(&PruneOptions {
+            max_size: Some(0),
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(
+        Vec::from_iter(removed.iter().map(|c| c.image.name.as_str())),
+        vec!["baz", "foo"]
+    );
+    assert_eq!(
+        sorted_dir_listing(&fs, image_dir.path()),
+        vec!["sha256:ghijk"]
+    );
+}
+
+#[test]
+fn container_image_depot_prune_skips_images_in_use() {
+    let fs = Fs::new();
+    let project_dir = tempfile::tempdir().unwrap();
+    let image_dir = tempfile::tempdir().unwrap();
+    let mut depot = depot_with_three_images(project_dir.path(), image_dir.path());
+
+    let mut other_depot = depot_with_three_images(project_dir.path(), image_dir.path());
+    other_depot
+        .get_container_image("bar", "latest", NullProgressTracker)
+        .unwrap();
+
+    let removed = depot
+        .prune(&PruneOptions {
+            max_size: Some(0),
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(
+        Vec::from_iter(removed.iter().map(|c| c.image.name.as_str())),
+        vec!["baz", "foo"]
+    );
+    assert_eq!(
+        sorted_dir_listing(&fs, image_dir.path()),
+        vec!["sha256:ghijk"]
+    );
+
+    // Once the other client is done with it, it can be removed.
+    drop(other_depot);
+    assert!(depot.remove_image("sha256:ghijk").unwrap());
+}
+
+#[test]
+fn container_image_depot_list_images_skips_removed() {
+    let fs = Fs::new();
+    let project_dir = tempfile::tempdir().unwrap();
+    let image_dir = tempfile::tempdir().unwrap();
+    let depot = depot_with_three_images(project_dir.path(), image_dir.path());
+
+    // An entry that goes away between reading the directory and looking at it.
+    fs.symlink(
+        image_dir.path().join("sha256:removed"),
+        image_dir.path().join("sha256:dangling"),
+    )
+    .unwrap();
+    assert_eq!(
+        dir_size(&fs, &image_dir.path().join("sha256:removed")).unwrap(),
+        0
+    );
+
+    let images = depot.list_images().unwrap();
+    assert_eq!(
+        Vec::from_iter(images.iter().map(|c| c.image.name.as_str())),
+        vec!["bar", "baz", "foo"]
+    );
+}
+
+#[test]
+fn container_image_depot_prune_then_get_redownloads() {
+    let fs = Fs::new();
+    let project_dir = tempfile::tempdir().unwrap();
+    let image_dir = tempfile::tempdir().unwrap();
+    let mut depot = depot_with_three_images(project_dir.path(), image_dir.path());
+
+    depot
+        .prune(&PruneOptions {
+            max_size: Some(0),
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(
+        sorted_dir_listing(&fs, image_dir.path()),
+        Vec::<String>::new()
+    );
+
+    depot
+        .get_container_image("foo", "latest", NullProgressTracker)
+        .unwrap();
+    assert_eq!(
+        sorted_dir_listing(&fs, image_dir.path()),
+        vec!["sha256:abcdef"]
+    );
+}
+
+#[test]
+fn container_image_depot_remove_image_removes_lock_file() {
+    let fs = Fs::new();
+    let project_dir = tempfile::tempdir().unwrap();
+    let image_dir = tempfile::tempdir().unwrap();
+    let mut depot = depot_with_three_images(project_dir.path(), image_dir.path());
+    let lock_file_path = image_dir.path().join(".sha256:abcdef.flock");
+    assert!(fs.exists(&lock_file_path));
+
+    assert!(depot.remove_image("sha256:abcdef").unwrap());
+    assert!(!fs.exists(&lock_file_path));
+    assert!(fs.exists(image_dir.path().join(".sha256:ghijk.flock")));
+}
+
+#[test]
+fn container_image_depot_download_enforces_max_cache_size() {
+    let fs = Fs::new();
+    let project_dir = tempfile::tempdir().unwrap();
+    let image_dir = tempfile::tempdir().unwrap();
+    let mut depot = depot_with_three_images(project_dir.path(), image_dir.path());
+    depot.set_max_cache_size(Some(0));
+
+    // Getting an image that's already cached doesn't prune anything.
+    depot
+        .get_container_image("bar", "latest", NullProgressTracker)
+        .unwrap();
+    assert_eq!(
+        sorted_dir_listing(&fs, image_dir.path()),
+        vec!["sha256:abcdef", "sha256:ghijk", "sha256:lmnop"]
+    );
+
+    // Downloading one removes the others, but not the new one or the ones still in use.
+    assert!(depot.remove_image("sha256:abcdef").unwrap());
+    depot
+        .get_container_image("foo", "latest", NullProgressTracker)
+        .unwrap();
+    assert_eq!(
+        sorted_dir_listing(&fs, image_dir.path()),
+        vec!["sha256:abcdef", "sha256:ghijk"]
+    );
+}
+
+#[test]
+fn container_image_depot_locked_unpinned_tag_is_error() {
+    let fs = Fs::new();
+    let project_dir = tempfile::tempdir().unwrap();
+    let image_dir = tempfile::tempdir().unwrap();
+
+    let mut depot = ContainerImageDepot::new_with(
+        project_dir.path(),
+        image_dir.path(),
+        PanicContainerImageDepotOps,
+    )
+    .unwrap();
+    depot.locked = true;
+    let err = depot
+        .get_container_image("foo", "latest", NullProgressTracker)
+        .unwrap_err();
+
+    assert_eq!(
+        err.to_string(),
+        format!("container image foo:latest is not pinned in {TAG_FILE_NAME}")
+    );
+    assert_eq!(
+        fs.read_to_string(project_dir.path().join(TAG_FILE_NAME))
+            .unwrap(),
+        ""
+    );
+}
+
+#[test]
+fn container_image_depot_locked_pinned_tag() {
+    let project_dir = tempfile::tempdir().unwrap();
+    let image_dir = tempfile::tempdir().unwrap();
+
+    let mut depot = ContainerImageDepot::new_with(
+        project_dir.path(),
+        image_dir.path(),
+        FakeContainerImageDepotOps(maplit::hashmap! {
+            "foo-latest".into() => "sha256:abcdef".into(),
+        }),
+    )
+    .unwrap();
+    depot
+        .get_container_image("foo", "latest", NullProgressTracker)
+        .unwrap();
+    drop(depot);
+
+    let mut depot = ContainerImageDepot::new_with(
+        project_dir.path(),
+        image_dir.path(),
+        PanicContainerImageDepotOps,
+    )
+    .unwrap();
+    depot.locked = true;
+    let img = depot
+        .get_container_image("foo", "latest", NullProgressTracker)
+        .unwrap();
+    assert_eq!(img.digest, "sha256:abcdef");
+}
+
+#[cfg(test)]
+fn depot_with_stale_tags(
+    project_dir: &Path,
+    image_dir: &Path,
+) -> ContainerImageDepot<FakeContainerImageDepotOps> {
+    let mut depot = ContainerImageDepot::new_with(
+        project_dir,
+        image_dir,
+        FakeContainerImageDepotOps(maplit::hashmap! {
+            "foo-latest".into() => "sha256:abcdef".into(),
+            "foo-1".into() => "sha256:ghijk".into(),
+            "bar-latest".into() => "sha256:lmnop".into(),
+        }),
+    )
+    .unwrap();
+    for (name, tag) in [("foo", "latest"), ("foo", "1"), ("bar", "latest")] {
+        depot
+            .get_container_image(name, tag, NullProgressTracker)
+            .unwrap();
+    }
+    depot.ops = FakeContainerImageDepotOps(maplit::hashmap! {
+        "foo-latest".into() => "sha256:qrstu".into(),
+        "foo-1".into() => "sha256:ghijk".into(),
+        "bar-latest".into() => "sha256:vwxyz".into(),
+    });
+    depot
+}
+
+#[test]
+fn container_image_depot_update_all_locked_tags() {
+    let fs = Fs::new();
+    let project_dir = tempfile::tempdir().unwrap();
+    let image_dir = tempfile::tempdir().unwrap();
+    let mut depot = depot_with_stale_tags(project_dir.path(), image_dir.path());
+
+    let updates = depot.update_locked_tags(&[], false).unwrap();
+    assert_eq!(
+        Vec::from_iter(updates.iter().map(ToString::to_string)),
+        vec![
+            "bar:latest: sha256:lmnop -> sha256:vwxyz",
+            "foo:latest: sha256:abcdef -> sha256:qrstu",
+        ]
+    );
+    assert_eq!(
+        fs.read_to_string(project_dir.path().join(TAG_FILE_NAME))
+            .unwrap(),
+        "\
+            version = 0\n\
+            \n\
+            [bar]\n\
+            latest = \"sha256:vwxyz\"\n\
+            \n\
+            [foo]\n\
+            1 = \"sha256:ghijk\"\n\
+            latest = \"sha256:qrstu\"\n\
+        "
+    );
+
+    let img = depot
+        .get_container_image("foo", "latest", NullProgressTracker)
+        .unwrap();
+    assert_eq!(img.digest, "sha256:qrstu");
+}
+
+#[test]
+fn container_image_depot_update_selected_locked_tags() {
+    let project_dir = tempfile::tempdir().unwrap();
+    let image_dir = tempfile::tempdir().unwrap();
+    let mut depot = depot_with_stale_tags(project_dir.path(), image_dir.path());
+
+    let updates = depot
+        .update_locked_tags(&["foo".into(), "bar:1".into()], false)
+        .unwrap();
+    assert_eq!(
+        Vec::from_iter(updates.iter().map(ToString::to_string)),
+        vec!["foo:latest: sha256:abcdef -> sha256:qrstu"]
+    );
+
+    let updates = depot
+        .update_locked_tags(&["bar:latest".into()], false)
+        .unwrap();
+    assert_eq!(
+        Vec::from_iter(updates.iter().map(ToString::to_string)),
+        vec!["bar:latest: sha256:lmnop -> sha256:vwxyz"]
+    );
+
+    assert_eq!(depot.update_locked_tags(&[], false).unwrap(), vec![]);
+}
+
+#[test]
+fn container_image_depot_check_locked_tags() {
+    let fs = Fs::new();
+    let project_dir = tempfile::tempdir().unwrap();
+    let image_dir = tempfile::tempdir().unwrap();
+    let mut depot = depot_with_stale_tags(project_dir.path(), image_dir.path());
+    let before = fs
+        .read_to_string(project_dir.path().join(TAG_FILE_NAME))
+        .unwrap();
+
+    let updates = depot.update_locked_tags(&[], true).unwrap();
+    assert_eq!(updates.len(), 2);
+    assert_eq!(
+        fs.read_to_string(project_dir.path().join(TAG_FILE_NAME))
+            .unwrap(),
+        before
+    );
+}
+
+#[test]
+fn split_image_tag_with_and_without_tag() {
+    assert_eq!(split_image_tag("foo"), ("foo", None));
+    assert_eq!(split_image_tag("foo:1"), ("foo", Some("1")));
+    assert_eq!(split_image_tag("library/foo:1"), ("library/foo", Some("1")));
+    assert_eq!(
+        split_image_tag("localhost:5000/foo"),
+        ("localhost:5000/foo", None)
+    );
+    assert_eq!(
+        split_image_tag("localhost:5000/foo:1"),
+        ("localhost:5000/foo", Some("1"))
+    );
+}
+
 #[tokio::main]
 pub async fn resolve_tag_sync(client: &reqwest::Client, name: &str, tag: &str) -> Result<String> {
     resolve_tag(client, name, tag).await
@@ -1,31 +1,140 @@
-use anyhow::Result;
-use clap::Parser;
-use maelstrom_container::download_image;
-use std::path::PathBuf;
+use anyhow::{bail, Result};
+use bytesize::ByteSize;
+use clap::{Parser, Subcommand};
+use maelstrom_container::{ContainerImageDepot, PruneOptions};
+use std::{
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
 
+/// Manage the local cache of container images.
 #[derive(Parser)]
 #[command(styles=maelstrom_util::clap::styles())]
 struct CliOptions {
-    package_name: String,
-    version: String,
-    layer_dir: PathBuf,
+    /// Directory containing the maelstrom-container-tags.lock file used to resolve tags.
+    #[arg(long, value_name = "PATH", default_value = ".")]
+    project_dir: PathBuf,
+
+    /// After downloading an image, remove least-recently-used images until the cache is at most
+    /// this size (e.g. "10GiB"). The image just downloaded is kept. Defaults to 10GiB
+    #[arg(long, value_name = "SIZE")]
+    max_cache_size: Option<ByteSize>,
+
+    #[command(subcommand)]
+    command: CliCommand,
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    let opt = CliOptions::parse();
+#[derive(Subcommand)]
+enum CliCommand {
+    /// List the images in the cache, least-recently-used first
+    List,
+
+    /// Remove images from the cache
+    Prune {
+        /// Remove least-recently-used images until the cache is at most this size (e.g. "10GiB")
+        #[arg(long, value_name = "SIZE")]
+        max_size: Option<ByteSize>,
+
+        /// Remove images that haven't been used in this many days
+        #[arg(long, value_name = "DAYS")]
+        max_age_days: Option<u64>,
+
+        /// Remove every image that isn't in use
+        #[arg(long, conflicts_with_all = ["max_size", "max_age_days"])]
+        all: bool,
+    },
 
-    let ind = indicatif::ProgressBar::new(0);
-    let client = reqwest::Client::new();
-    let image = download_image(
-        &client,
-        &opt.package_name,
-        &opt.version,
-        &opt.layer_dir,
-        ind,
-    )
-    .await?;
-    println!("{image:#?}");
+    /// Download an image into the cache, if it isn't already there
+    Pull {
+        name: String,
+        #[arg(default_value = "latest")]
+        tag: String,
+    },
+
+    /// Print the details of cached images, selected by name or digest
+    Inspect { image: String },
+}
+
+fn format_age(last_used: SystemTime) -> String {
+    let secs = SystemTime::now()
+        .duration_since(last_used)
+        .unwrap_or_default()
+        .as_secs();
+    match secs {
+        0..=59 => format!("{secs}s ago"),
+        60..=3599 => format!("{}m ago", secs / 60),
+        3600..=86399 => format!("{}h ago", secs / 3600),
+        _ => format!("{}d ago", secs / 86400),
+    }
+}
+
+fn main() -> Result<()> {
+    let opt = CliOptions::parse();
+    let mut depot = ContainerImageDepot::new(&opt.project_dir, false)?;
+    if let Some(max_cache_size) = opt.max_cache_size {
+        depot.set_max_cache_size(Some(max_cache_size.as_u64()));
+    }
 
+    match opt.command {
+        CliCommand::List => {
+            for cached in depot.list_images()? {
+                println!(
+                    "{:<20} {} {:>10} {}",
+                    cached.image.name,
+                    cached.image.digest,
+                    ByteSize::b(cached.size).to_string(),
+                    format_age(cached.last_used),
+                );
+            }
+        }
+        CliCommand::Prune {
+            max_size,
+            max_age_days,
+            all,
+        } => {
+            let options = PruneOptions {
+                max_size: if all {
+                    Some(0)
+                } else {
+                    max_size.map(|s| s.as_u64())
+                },
+                max_age: max_age_days.map(|days| Duration::from_secs(days * 24 * 60 * 60)),
+            };
+            if options == PruneOptions::default() {
+                bail!("one of --max-size, --max-age-days, or --all must be given");
+            }
+            let removed = depot.prune(&options)?;
+            let freed: u64 = removed.iter().map(|cached| cached.size).sum();
+            for cached in &removed {
+                println!("removed {} {}", cached.image.name, cached.image.digest);
+            }
+            println!(
+                "removed {} images, freed {}",
+                removed.len(),
+                ByteSize::b(freed)
+            );
+        }
+        CliCommand::Pull { name, tag } => {
+            let prog = indicatif::ProgressBar::new(0);
+            let image = depot.get_container_image(&name, &tag, prog)?;
+            println!("{} {}", image.name, image.digest);
+        }
+        CliCommand::Inspect { image } => {
+            let matches = Vec::from_iter(
+                depot
+                    .list_images()?
+                    .into_iter()
+                    .filter(|cached| cached.image.name == image || cached.image.digest == image),
+            );
+            if matches.is_empty() {
+                bail!("no cached image matching {image:?}");
+            }
+            for cached in matches {
+                println!("{:#?}", cached.image);
+                println!("size: {}", ByteSize::b(cached.size));
+                println!("last used: {}", format_age(cached.last_used));
+            }
+        }
+    }
     Ok(())
 }
@@ -4,7 +4,7 @@
 #[cfg(feature = "std")]
 extern crate std;
 
-use core::{ffi::CStr, fmt, mem, ptr, time::Duration};
+use core::{ffi::CStr, fmt, marker::PhantomData, mem, ptr, time::Duration};
 use derive_more::{BitOr, Display, Into};
 use libc::{
     c_char, c_int, c_long, c_short, c_uint, c_ulong, c_void, gid_t, mode_t, nfds_t, pid_t, pollfd,
@@ -17,6 +17,14 @@ extern "C" {
     fn strerrordesc_np(errnum: c_int) -> *const c_char;
 }
 
+#[derive(Clone, Copy)]
+pub struct Capability(c_int);
+
+impl Capability {
+    pub const NET_ADMIN: Self = Self(12);
+    pub const NET_RAW: Self = Self(13);
+}
+
 #[derive(Clone)]
 #[repr(transparent)]
 pub struct CloneArgs(libc::clone_args);
@@ -52,6 +60,7 @@ impl CloneFlags {
     pub const NEWNS: Self = Self(libc::CLONE_NEWNS);
     pub const NEWPID: Self = Self(libc::CLONE_NEWPID);
     pub const NEWUSER: Self = Self(libc::CLONE_NEWUSER);
+    pub const NEWUTS: Self = Self(libc::CLONE_NEWUTS);
 
     fn as_u64(&self) -> u64 {
         self.0.try_into().unwrap()
@@ -87,6 +96,10 @@ pub enum CloseRangeLast {
 pub struct Errno(c_int);
 
 impl Errno {
+    pub const EINVAL: Self = Self(libc::EINVAL);
+    pub const EPERM: Self = Self(libc::EPERM);
+    pub const EPROTO: Self = Self(libc::EPROTO);
+
     pub fn from_u64(errno: u64) -> Self {
         Errno(errno.try_into().unwrap())
     }
@@ -141,6 +154,13 @@ impl fmt::Display for Errno {
 #[cfg(feature = "std")]
 impl std::error::Error for Errno {}
 
+#[cfg(feature = "std")]
+impl From<Errno> for std::io::Error {
+    fn from(errno: Errno) -> Self {
+        std::io::Error::from_raw_os_error(errno.0)
+    }
+}
+
 /// The sentinel value indicates that a function failed and more detailed
 /// information about the error can be found in `errno`
 pub trait ErrnoSentinel: Sized {
@@ -187,6 +207,14 @@ impl Fd {
     pub const STDOUT: Self = Self(libc::STDOUT_FILENO);
     pub const STDERR: Self = Self(libc::STDERR_FILENO);
 
+    pub fn from_raw(fd: c_int) -> Self {
+        Self(fd)
+    }
+
+    pub fn as_c_int(self) -> c_int {
+        self.0
+    }
+
     fn from_c_long(fd: c_long) -> Self {
         Self(fd.try_into().unwrap())
     }
@@ -239,6 +267,11 @@ impl MountFlags {
     pub const NOSUID: Self = Self(libc::MS_NOSUID);
     pub const NOEXEC: Self = Self(libc::MS_NOEXEC);
     pub const NODEV: Self = Self(libc::MS_NODEV);
+    pub const NOATIME: Self = Self(libc::MS_NOATIME);
+    pub const NODIRATIME: Self = Self(libc::MS_NODIRATIME);
+    pub const RELATIME: Self = Self(libc::MS_RELATIME);
+    pub const PRIVATE: Self = Self(libc::MS_PRIVATE);
+    pub const REC: Self = Self(libc::MS_REC);
 }
 
 #[repr(C)]
@@ -328,12 +361,55 @@ impl PollFd {
     }
 }
 
-#[derive(Clone, Copy, Default, Into)]
+#[derive(Clone, Copy)]
+pub struct Rlimit(libc::rlimit);
+
+impl Rlimit {
+    pub const INFINITY: u64 = libc::RLIM_INFINITY;
+
+    pub fn new(current: u64, max: u64) -> Self {
+        Self(libc::rlimit {
+            rlim_cur: current,
+            rlim_max: max,
+        })
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct RlimitResource(libc::__rlimit_resource_t);
+
+impl RlimitResource {
+    pub const AS: Self = Self(libc::RLIMIT_AS);
+    pub const CORE: Self = Self(libc::RLIMIT_CORE);
+    pub const CPU: Self = Self(libc::RLIMIT_CPU);
+    pub const DATA: Self = Self(libc::RLIMIT_DATA);
+    pub const FSIZE: Self = Self(libc::RLIMIT_FSIZE);
+    pub const LOCKS: Self = Self(libc::RLIMIT_LOCKS);
+    pub const MEMLOCK: Self = Self(libc::RLIMIT_MEMLOCK);
+    pub const MSGQUEUE: Self = Self(libc::RLIMIT_MSGQUEUE);
+    pub const NICE: Self = Self(libc::RLIMIT_NICE);
+    pub const NOFILE: Self = Self(libc::RLIMIT_NOFILE);
+    pub const NPROC: Self = Self(libc::RLIMIT_NPROC);
+    pub const RSS: Self = Self(libc::RLIMIT_RSS);
+    pub const RTPRIO: Self = Self(libc::RLIMIT_RTPRIO);
+    pub const RTTIME: Self = Self(libc::RLIMIT_RTTIME);
+    pub const SIGPENDING: Self = Self(libc::RLIMIT_SIGPENDING);
+    pub const STACK: Self = Self(libc::RLIMIT_STACK);
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, Into, PartialEq)]
 pub struct Signal(c_int);
 
 impl Signal {
     pub const CHLD: Self = Self(libc::SIGCHLD);
+    pub const HUP: Self = Self(libc::SIGHUP);
+    pub const INT: Self = Self(libc::SIGINT);
     pub const KILL: Self = Self(libc::SIGKILL);
+    pub const QUIT: Self = Self(libc::SIGQUIT);
+    pub const SYS: Self = Self(libc::SIGSYS);
+    pub const TERM: Self = Self(libc::SIGTERM);
+    pub const USR1: Self = Self(libc::SIGUSR1);
+    pub const USR2: Self = Self(libc::SIGUSR2);
 
     pub fn as_u8(&self) -> u8 {
         self.0.try_into().unwrap()
@@ -360,6 +436,42 @@ impl fmt::Display for Signal {
     }
 }
 
+#[derive(Clone, Copy)]
+pub struct SignalSet(libc::sigset_t);
+
+impl SignalSet {
+    pub fn full() -> Self {
+        let mut set = mem::MaybeUninit::uninit();
+        unsafe { libc::sigfillset(set.as_mut_ptr()) };
+        Self(unsafe { set.assume_init() })
+    }
+}
+
+#[derive(Clone, Copy)]
+#[repr(transparent)]
+pub struct SockFilter(libc::sock_filter);
+
+impl SockFilter {
+    pub fn new(code: u16, jt: u8, jf: u8, k: u32) -> Self {
+        Self(libc::sock_filter { code, jt, jf, k })
+    }
+}
+
+/// A BPF program, as passed to `seccomp`. It borrows the instructions it points to.
+pub struct SockFprog<'a>(libc::sock_fprog, PhantomData<&'a [SockFilter]>);
+
+impl<'a> SockFprog<'a> {
+    pub fn new(filter: &'a [SockFilter]) -> Self {
+        Self(
+            libc::sock_fprog {
+                len: filter.len().try_into().unwrap(),
+                filter: filter.as_ptr() as *mut libc::sock_filter,
+            },
+            PhantomData,
+        )
+    }
+}
+
 #[derive(Clone, Copy)]
 pub struct SocketDomain(c_int);
 
@@ -475,6 +587,24 @@ pub fn getuid() -> Uid {
     Uid(unsafe { libc::getuid() })
 }
 
+pub fn ioctl_ficlone(dest: Fd, src: Fd) -> Result<(), Errno> {
+    Errno::result(unsafe { libc::ioctl(dest.0, libc::FICLONE, src.0) }).map(drop)
+}
+
+pub fn ioctl_siocgifindex(fd: Fd, name: &CStr) -> Result<u32, Errno> {
+    let name = name.to_bytes_with_nul();
+    let mut ifreq: libc::ifreq = unsafe { mem::zeroed() };
+    if name.len() > ifreq.ifr_name.len() {
+        return Err(Errno::EINVAL);
+    }
+    for (dest, src) in ifreq.ifr_name.iter_mut().zip(name) {
+        *dest = *src as c_char;
+    }
+    let ifreq_ptr = &mut ifreq as *mut libc::ifreq;
+    Errno::result(unsafe { libc::ioctl(fd.0, libc::SIOCGIFINDEX, ifreq_ptr) })?;
+    Ok(unsafe { ifreq.ifr_ifru.ifru_ifindex } as u32)
+}
+
 pub fn kill(pid: Pid, signal: Signal) -> Result<(), Errno> {
     Errno::result(unsafe { libc::kill(pid.0, signal.0) }).map(drop)
 }
@@ -499,6 +629,29 @@ pub fn mount(
         .map(drop)
 }
 
+/// Return the flags of the mount containing `path`, as reported by statvfs(3). These are the
+/// flags that have to be preserved when remounting a bind mount of `path` in a user namespace.
+pub fn statvfs_mount_flags(path: &CStr) -> Result<MountFlags, Errno> {
+    let path_ptr = path.as_ptr();
+    let mut buf: libc::statvfs = unsafe { mem::zeroed() };
+    Errno::result(unsafe { libc::statvfs(path_ptr, &mut buf) })?;
+    let mut flags = MountFlags::default();
+    for (st_flag, ms_flag) in [
+        (libc::ST_RDONLY, MountFlags::RDONLY),
+        (libc::ST_NOSUID, MountFlags::NOSUID),
+        (libc::ST_NODEV, MountFlags::NODEV),
+        (libc::ST_NOEXEC, MountFlags::NOEXEC),
+        (libc::ST_NOATIME, MountFlags::NOATIME),
+        (libc::ST_NODIRATIME, MountFlags::NODIRATIME),
+        (libc::ST_RELATIME, MountFlags::RELATIME),
+    ] {
+        if buf.f_flag & st_flag != 0 {
+            flags = flags | ms_flag;
+        }
+    }
+    Ok(flags)
+}
+
 pub fn open(path: &CStr, flags: OpenFlags, mode: FileMode) -> Result<Fd, Errno> {
     let path_ptr = path.as_ptr();
     Errno::result(unsafe { libc::open(path_ptr, flags.0, mode.0) }).map(Fd)
@@ -538,6 +691,15 @@ pub fn prctl_set_pdeathsig(signal: Signal) -> Result<(), Errno> {
     Errno::result(unsafe { libc::prctl(libc::PR_SET_PDEATHSIG, signal) }).map(drop)
 }
 
+pub fn prctl_capbset_drop(capability: Capability) -> Result<(), Errno> {
+    let capability = capability.0 as c_ulong;
+    Errno::result(unsafe { libc::prctl(libc::PR_CAPBSET_DROP, capability, 0, 0, 0) }).map(drop)
+}
+
+pub fn prctl_set_no_new_privs() -> Result<(), Errno> {
+    Errno::result(unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) }).map(drop)
+}
+
 pub fn raise(signal: Signal) -> Result<(), Errno> {
     Errno::result(unsafe { libc::raise(signal.0) }).map(drop)
 }
@@ -548,10 +710,52 @@ pub fn read(fd: Fd, buf: &mut [u8]) -> Result<usize, Errno> {
     Errno::result(unsafe { libc::read(fd.0, buf_ptr, buf_len) }).map(|ret| ret as usize)
 }
 
+pub fn seccomp_set_mode_filter(prog: &SockFprog) -> Result<(), Errno> {
+    let prog_ptr = &prog.0 as *const libc::sock_fprog;
+    let flags = 0 as c_uint;
+    Errno::result(unsafe {
+        libc::syscall(
+            libc::SYS_seccomp,
+            libc::SECCOMP_SET_MODE_FILTER,
+            flags,
+            prog_ptr,
+        )
+    })
+    .map(drop)
+}
+
+pub fn setdomainname(name: &[u8]) -> Result<(), Errno> {
+    let name_ptr = name.as_ptr() as *const c_char;
+    let name_len = name.len();
+    Errno::result(unsafe { libc::setdomainname(name_ptr, name_len) }).map(drop)
+}
+
+pub fn sethostname(name: &[u8]) -> Result<(), Errno> {
+    let name_ptr = name.as_ptr() as *const c_char;
+    let name_len = name.len();
+    Errno::result(unsafe { libc::sethostname(name_ptr, name_len) }).map(drop)
+}
+
+pub fn setrlimit(resource: RlimitResource, rlimit: &Rlimit) -> Result<(), Errno> {
+    Errno::result(unsafe { libc::setrlimit(resource.0, &rlimit.0) }).map(drop)
+}
+
 pub fn setsid() -> Result<(), Errno> {
     Errno::result(unsafe { libc::setsid() }).map(drop)
 }
 
+/// Set the signal mask of the calling thread to `set`, returning the previous mask.
+pub fn sigprocmask(set: &SignalSet) -> Result<SignalSet, Errno> {
+    let mut old = mem::MaybeUninit::uninit();
+    Errno::result(unsafe { libc::sigprocmask(libc::SIG_SETMASK, &set.0, old.as_mut_ptr()) })
+        .map(|_| SignalSet(unsafe { old.assume_init() }))
+}
+
+/// Wait for one of the signals in `set`, which should be blocked, and return it.
+pub fn sigwaitinfo(set: &SignalSet) -> Result<Signal, Errno> {
+    Errno::result(unsafe { libc::sigwaitinfo(&set.0, ptr::null_mut()) }).map(Signal)
+}
+
 pub fn socket(
     domain: SocketDomain,
     type_: SocketType,
@@ -560,6 +764,10 @@ pub fn socket(
     Errno::result(unsafe { libc::socket(domain.0, type_.0, protocol.0) }).map(Fd)
 }
 
+pub fn syncfs(fd: Fd) -> Result<(), Errno> {
+    Errno::result(unsafe { libc::syncfs(fd.0) }).map(drop)
+}
+
 pub fn umount2(path: &CStr, flags: UmountFlags) -> Result<(), Errno> {
     let path_ptr = path.as_ptr();
     Errno::result(unsafe { libc::umount2(path_ptr, flags.0) }).map(drop)
@@ -595,6 +803,21 @@ pub fn wait() -> Result<WaitResult, Errno> {
     })
 }
 
+/// Like [`wait`], but return `None` instead of blocking if no child has terminated yet.
+pub fn try_wait() -> Result<Option<WaitResult>, Errno> {
+    let inner = |status: &mut c_int| {
+        let status_ptr = status as *mut c_int;
+        unsafe { libc::waitpid(-1, status_ptr, libc::WNOHANG) }
+    };
+    let mut status = 0;
+    Errno::result(inner(&mut status)).map(|pid| {
+        (pid != 0).then(|| WaitResult {
+            pid: Pid(pid),
+            status: extract_wait_status(status),
+        })
+    })
+}
+
 pub fn waitpid(pid: Pid) -> Result<WaitStatus, Errno> {
     let inner = |status: &mut c_int| {
         let status_ptr = status as *mut c_int;
@@ -76,6 +76,8 @@ macro_rules! outcome {
             effects: maelstrom_base::JobEffects {
                 stdout: maelstrom_base::JobOutputResult::None,
                 stderr: maelstrom_base::JobOutputResult::None,
+                diagnostics: maelstrom_base::JobOutputResult::None,
+                duration: std::time::Duration::ZERO,
             }
         })
     };
@@ -85,6 +87,8 @@ macro_rules! outcome {
             effects: maelstrom_base::JobEffects {
                 stdout: maelstrom_base::JobOutputResult::None,
                 stderr: maelstrom_base::JobOutputResult::None,
+                diagnostics: maelstrom_base::JobOutputResult::None,
+                duration: std::time::Duration::ZERO,
             }
         })
     };
@@ -94,6 +98,8 @@ macro_rules! outcome {
             effects: maelstrom_base::JobEffects {
                 stdout: maelstrom_base::JobOutputResult::None,
                 stderr: maelstrom_base::JobOutputResult::None,
+                diagnostics: maelstrom_base::JobOutputResult::None,
+                duration: std::time::Duration::ZERO,
             }
         })
     };
@@ -103,6 +109,8 @@ macro_rules! outcome {
             effects: maelstrom_base::JobEffects {
                 stdout: maelstrom_base::JobOutputResult::None,
                 stderr: maelstrom_base::JobOutputResult::None,
+                diagnostics: maelstrom_base::JobOutputResult::None,
+                duration: std::time::Duration::ZERO,
             }
         })
     };
@@ -100,3 +100,13 @@ pub enum LogLevel {
     Info,
     Debug,
 }
+
+/// How log messages are written to standard error: either as human-readable text, or as one JSON
+/// object per line, for log collectors.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, ValueEnum)]
+#[clap(rename_all = "kebab_case")]
+#[serde(rename_all = "kebab-case")]
+pub enum LogFormat {
+    Text,
+    Json,
+}
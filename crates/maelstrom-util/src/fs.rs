@@ -189,7 +189,9 @@ impl std::os::unix::fs::MetadataExt for Metadata {
     }
 }
 
-fn is_not_found_err(err: &anyhow::Error) -> bool {
+/// Returns true if `err`, as returned by one of the functions in this module, is because the file
+/// didn't exist.
+pub fn is_not_found_err(err: &anyhow::Error) -> bool {
     let std_err = err.root_cause().downcast_ref::<std::io::Error>();
     matches!(std_err, Some(e) if e.kind() == std::io::ErrorKind::NotFound)
 }
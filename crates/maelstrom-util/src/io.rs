@@ -1,9 +1,10 @@
-//! Useful [`Read`]ers.
+//! Useful [`Read`]ers and [`Write`]ers.
 
 use byteorder::{BigEndian, ReadBytesExt as _, WriteBytesExt as _};
-use maelstrom_base::Sha256Digest;
+use flate2::{bufread::MultiGzDecoder, write::GzEncoder};
+use maelstrom_base::{proto::ArtifactCompression, Sha256Digest};
 use sha2::{Digest as _, Sha256};
-use std::io::{self, Chain, Read, Repeat, Take};
+use std::io::{self, BufRead, Chain, Read, Repeat, Take, Write};
 
 /// A [`Read`]er wrapper that will always reads a specific number of bytes, except on error. If the
 /// inner, wrapped, reader returns EOF before the specified number of bytes have been returned,
@@ -56,6 +57,39 @@ impl<InnerT: Read> Read for Sha256Reader<InnerT> {
     }
 }
 
+/// A [`Write`]r wrapper that computes the SHA-256 digest of the bytes that are written to its
+/// inner writer.
+pub struct Sha256Writer<InnerT> {
+    inner: InnerT,
+    hasher: Sha256,
+}
+
+impl<InnerT> Sha256Writer<InnerT> {
+    pub fn new(inner: InnerT) -> Self {
+        Sha256Writer {
+            inner,
+            hasher: Sha256::new(),
+        }
+    }
+
+    /// Deconstruct the writer and return the inner writer and computed digest.
+    pub fn finalize(self) -> (InnerT, Sha256Digest) {
+        (self.inner, Sha256Digest::new(self.hasher.finalize().into()))
+    }
+}
+
+impl<InnerT: Write> Write for Sha256Writer<InnerT> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let size = self.inner.write(buf)?;
+        self.hasher.update(&buf[..size]);
+        Ok(size)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
 struct Chunk<ReaderT> {
     reader: io::Take<ReaderT>,
 }
@@ -268,3 +302,120 @@ fn chunk_reader_and_writer() {
 
     assert_eq!(&decoded, &test_data);
 }
+
+/// A [`Write`]r wrapper that compresses what's written to it before writing it to the inner
+/// writer. [`Self::finish`] must be called to write out the end of the compressed stream.
+pub enum CompressingWriter<WriterT: Write> {
+    None(WriterT),
+    Gzip(GzEncoder<WriterT>),
+    Zstd(zstd::Encoder<'static, WriterT>),
+}
+
+impl<WriterT: Write> CompressingWriter<WriterT> {
+    pub fn new(writer: WriterT, compression: ArtifactCompression) -> io::Result<Self> {
+        Ok(match compression {
+            ArtifactCompression::None => Self::None(writer),
+            ArtifactCompression::Gzip => {
+                Self::Gzip(GzEncoder::new(writer, flate2::Compression::default()))
+            }
+            ArtifactCompression::Zstd => Self::Zstd(zstd::Encoder::new(writer, 0)?),
+        })
+    }
+
+    /// Write out the end of the compressed stream and return the inner writer.
+    pub fn finish(self) -> io::Result<WriterT> {
+        match self {
+            Self::None(writer) => Ok(writer),
+            Self::Gzip(encoder) => encoder.finish(),
+            Self::Zstd(encoder) => encoder.finish(),
+        }
+    }
+}
+
+impl<WriterT: Write> Write for CompressingWriter<WriterT> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::None(writer) => writer.write(buf),
+            Self::Gzip(encoder) => encoder.write(buf),
+            Self::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::None(writer) => writer.flush(),
+            Self::Gzip(encoder) => encoder.flush(),
+            Self::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// A [`Read`]er wrapper that decompresses what it reads from the inner reader. This is the
+/// counterpart of [`CompressingWriter`]. It reads until the inner reader returns EOF.
+pub enum DecompressingReader<ReaderT: BufRead> {
+    None(ReaderT),
+    Gzip(MultiGzDecoder<ReaderT>),
+    Zstd(zstd::Decoder<'static, ReaderT>),
+}
+
+impl<ReaderT: BufRead> DecompressingReader<ReaderT> {
+    pub fn new(reader: ReaderT, compression: ArtifactCompression) -> io::Result<Self> {
+        Ok(match compression {
+            ArtifactCompression::None => Self::None(reader),
+            ArtifactCompression::Gzip => Self::Gzip(MultiGzDecoder::new(reader)),
+            ArtifactCompression::Zstd => Self::Zstd(zstd::Decoder::with_buffer(reader)?),
+        })
+    }
+}
+
+impl<ReaderT: BufRead> Read for DecompressingReader<ReaderT> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::None(reader) => reader.read(buf),
+            Self::Gzip(decoder) => decoder.read(buf),
+            Self::Zstd(decoder) => decoder.read(buf),
+        }
+    }
+}
+
+#[test]
+fn compressing_writer_and_decompressing_reader() {
+    let test_data = Vec::from_iter((0u8..=255).cycle().take(100_000));
+    for compression in [
+        ArtifactCompression::None,
+        ArtifactCompression::Gzip,
+        ArtifactCompression::Zstd,
+    ] {
+        let mut encoded = vec![];
+        let mut writer =
+            CompressingWriter::new(ChunkedWriter::new(&mut encoded, 1000), compression).unwrap();
+        writer.write_all(&test_data).unwrap();
+        writer.finish().unwrap().finish().unwrap();
+        if compression != ArtifactCompression::None {
+            assert!(encoded.len() < test_data.len() / 10, "{compression}");
+        }
+
+        let mut reader = DecompressingReader::new(
+            io::BufReader::new(ChunkedReader::new(&encoded[..])),
+            compression,
+        )
+        .unwrap();
+        let mut decoded = vec![];
+        reader.read_to_end(&mut decoded).unwrap();
+        assert_eq!(decoded, test_data, "{compression}");
+    }
+}
+
+#[test]
+fn decompressing_reader_truncated() {
+    for compression in [ArtifactCompression::Gzip, ArtifactCompression::Zstd] {
+        let mut encoded = vec![];
+        let mut writer = CompressingWriter::new(&mut encoded, compression).unwrap();
+        writer.write_all(&[42; 10_000]).unwrap();
+        writer.finish().unwrap();
+
+        let truncated = &encoded[..encoded.len() - 4];
+        let mut reader = DecompressingReader::new(truncated, compression).unwrap();
+        reader.read_to_end(&mut vec![]).unwrap_err();
+    }
+}
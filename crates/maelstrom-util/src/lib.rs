@@ -8,7 +8,10 @@ pub mod ext;
 pub mod fs;
 pub mod heap;
 pub mod io;
+pub mod log;
 pub mod manifest;
+pub mod metrics;
 pub mod net;
 pub mod process;
 pub mod sync;
+pub mod trace;
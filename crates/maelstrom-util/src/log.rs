@@ -0,0 +1,27 @@
+//! Construction of the root loggers used by the binaries.
+
+use crate::config::{LogFormat, LogLevel};
+use slog::{o, Drain as _, Level, LevelFilter, Logger};
+use slog_async::Async;
+use slog_json::Json;
+use slog_term::{FullFormat, TermDecorator};
+use std::io;
+
+/// Create a root logger that writes messages at `level` or above to standard error, in `format`.
+/// Messages are written on a separate thread.
+pub fn root_logger(level: LogLevel, format: LogFormat) -> Logger {
+    let drain = match format {
+        LogFormat::Text => {
+            let decorator = TermDecorator::new().build();
+            Async::new(FullFormat::new(decorator).build().fuse()).build()
+        }
+        LogFormat::Json => Async::new(Json::default(io::stderr()).fuse()).build(),
+    };
+    let level = match level {
+        LogLevel::Error => Level::Error,
+        LogLevel::Warning => Level::Warning,
+        LogLevel::Info => Level::Info,
+        LogLevel::Debug => Level::Debug,
+    };
+    Logger::root(LevelFilter::new(drain.fuse(), level).fuse(), o!())
+}
@@ -0,0 +1,228 @@
+//! Types for exposing metrics in the [OpenMetrics] text format, which is what Prometheus scrapes.
+//!
+//! [OpenMetrics]: https://github.com/OpenObservability/OpenMetrics/blob/main/specification/OpenMetrics.md
+
+use std::fmt::{Display, Write as _};
+
+/// The `Content-Type` of the text produced by [`Encoder`].
+pub const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// The upper bounds, in seconds, of the buckets of a [`Histogram`] made with
+/// [`Histogram::default`]. These are Prometheus's defaults, extended to cover long-running jobs.
+pub const DEFAULT_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 300.0, 600.0,
+];
+
+/// A distribution of observed values, counted in buckets with fixed upper bounds.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Histogram {
+    bounds: &'static [f64],
+    /// The number of observations in each bucket, not cumulatively. There is one more of these
+    /// than there are bounds: the last one is for observations above all of the bounds.
+    counts: Vec<u64>,
+    sum: f64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self::new(DEFAULT_BUCKETS)
+    }
+}
+
+impl Histogram {
+    /// Create a new, empty histogram with buckets that have the given upper bounds, which must be
+    /// sorted.
+    pub fn new(bounds: &'static [f64]) -> Self {
+        assert!(bounds.windows(2).all(|pair| pair[0] < pair[1]));
+        Histogram {
+            bounds,
+            counts: vec![0; bounds.len() + 1],
+            sum: 0.,
+        }
+    }
+
+    /// Record one observation of `value`.
+    pub fn observe(&mut self, value: f64) {
+        self.counts[self.bounds.partition_point(|bound| *bound < value)] += 1;
+        self.sum += value;
+    }
+
+    /// The number of observations recorded.
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// The sum of the observations recorded.
+    pub fn sum(&self) -> f64 {
+        self.sum
+    }
+}
+
+/// The type of a metric family.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MetricType {
+    Counter,
+    Gauge,
+    Histogram,
+}
+
+impl MetricType {
+    fn as_str(&self) -> &'static str {
+        match self {
+            MetricType::Counter => "counter",
+            MetricType::Gauge => "gauge",
+            MetricType::Histogram => "histogram",
+        }
+    }
+}
+
+/// Build up an OpenMetrics exposition, one metric family at a time.
+#[derive(Default)]
+pub struct Encoder(String);
+
+impl Encoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start a new metric family. The samples of the family must be written next, with
+    /// [`Self::sample`]. Counter samples have names that end in `_total`, but the family's name
+    /// doesn't.
+    pub fn family(&mut self, name: &str, metric_type: MetricType, help: &str) {
+        writeln!(self.0, "# TYPE {name} {}", metric_type.as_str()).unwrap();
+        writeln!(self.0, "# HELP {name} {}", escape(help, false)).unwrap();
+    }
+
+    /// Write a sample of the current metric family.
+    pub fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: impl Display) {
+        self.0.push_str(name);
+        if !labels.is_empty() {
+            let labels = Vec::from_iter(
+                labels
+                    .iter()
+                    .map(|(name, value)| format!("{name}=\"{}\"", escape(value, true))),
+            );
+            write!(self.0, "{{{}}}", labels.join(",")).unwrap();
+        }
+        writeln!(self.0, " {value}").unwrap();
+    }
+
+    /// Write a family with a single, unlabeled gauge.
+    pub fn gauge(&mut self, name: &str, help: &str, value: impl Display) {
+        self.family(name, MetricType::Gauge, help);
+        self.sample(name, &[], value);
+    }
+
+    /// Write a family with a single, unlabeled counter.
+    pub fn counter(&mut self, name: &str, help: &str, value: impl Display) {
+        self.family(name, MetricType::Counter, help);
+        self.sample(&format!("{name}_total"), &[], value);
+    }
+
+    /// Write a family with a single, unlabeled histogram.
+    pub fn histogram(&mut self, name: &str, help: &str, histogram: &Histogram) {
+        self.family(name, MetricType::Histogram, help);
+        let bucket_name = format!("{name}_bucket");
+        let mut cumulative = 0;
+        for (bound, count) in histogram.bounds.iter().zip(&histogram.counts) {
+            cumulative += count;
+            self.sample(&bucket_name, &[("le", &bound.to_string())], cumulative);
+        }
+        let count = histogram.count();
+        self.sample(&bucket_name, &[("le", "+Inf")], count);
+        self.sample(&format!("{name}_count"), &[], count);
+        self.sample(&format!("{name}_sum"), &[], histogram.sum);
+    }
+
+    /// Finish the exposition and return its text.
+    pub fn finish(mut self) -> String {
+        self.0.push_str("# EOF\n");
+        self.0
+    }
+}
+
+fn escape(text: &str, quotes: bool) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '"' if quotes => escaped.push_str("\\\""),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histogram_buckets_include_upper_bound() {
+        let mut histogram = Histogram::new(&[1., 2.]);
+        for value in [0.5, 1., 1.5, 2., 3.] {
+            histogram.observe(value);
+        }
+        assert_eq!(histogram.counts, vec![2, 2, 1]);
+        assert_eq!(histogram.count(), 5);
+        assert_eq!(histogram.sum(), 8.);
+    }
+
+    #[test]
+    fn encode_gauge_and_counter() {
+        let mut encoder = Encoder::new();
+        encoder.gauge("queue_depth", "Jobs in the queue.", 3);
+        encoder.counter("bytes", "Bytes received.", 1024);
+        assert_eq!(
+            encoder.finish(),
+            "\
+            # TYPE queue_depth gauge\n\
+            # HELP queue_depth Jobs in the queue.\n\
+            queue_depth 3\n\
+            # TYPE bytes counter\n\
+            # HELP bytes Bytes received.\n\
+            bytes_total 1024\n\
+            # EOF\n"
+        );
+    }
+
+    #[test]
+    fn encode_labeled_samples() {
+        let mut encoder = Encoder::new();
+        encoder.family("jobs", MetricType::Gauge, "Jobs\\by \"state\"\n.");
+        encoder.sample("jobs", &[("state", "running"), ("worker", "1")], 2);
+        encoder.sample("jobs", &[("state", "a\"b\\c\nd")], 0);
+        assert_eq!(
+            encoder.finish(),
+            "\
+            # TYPE jobs gauge\n\
+            # HELP jobs Jobs\\\\by \"state\"\\n.\n\
+            jobs{state=\"running\",worker=\"1\"} 2\n\
+            jobs{state=\"a\\\"b\\\\c\\nd\"} 0\n\
+            # EOF\n"
+        );
+    }
+
+    #[test]
+    fn encode_histogram() {
+        let mut histogram = Histogram::new(&[0.5, 1.]);
+        histogram.observe(0.25);
+        histogram.observe(0.75);
+        histogram.observe(2.);
+        let mut encoder = Encoder::new();
+        encoder.histogram("latency_seconds", "Latency.", &histogram);
+        assert_eq!(
+            encoder.finish(),
+            "\
+            # TYPE latency_seconds histogram\n\
+            # HELP latency_seconds Latency.\n\
+            latency_seconds_bucket{le=\"0.5\"} 1\n\
+            latency_seconds_bucket{le=\"1\"} 2\n\
+            latency_seconds_bucket{le=\"+Inf\"} 3\n\
+            latency_seconds_count 3\n\
+            latency_seconds_sum 3\n\
+            # EOF\n"
+        );
+    }
+}
@@ -0,0 +1,651 @@
+//! Tracing of jobs through the broker and workers. Each reports the points in a job's lifecycle to
+//! a [`JobTracer`], which logs them, tagged with the job's ID. If it's configured to, it also turns
+//! them into spans and exports them in the [OTLP] JSON encoding, to a file, a collector, or both.
+//!
+//! A job's trace ID is derived from its [`JobId`] and a nonce the broker picks when it starts, and
+//! so is the ID of each service's span for the job. The broker sends its nonce to each worker when
+//! the worker connects. This way, the spans the broker and a worker record for a job end up in the
+//! same trace, with the worker's under the broker's, without any trace context being passed along
+//! with each job. The nonce keeps apart the traces of jobs from different runs of the broker,
+//! which otherwise would share IDs, since client IDs start over when the broker is restarted.
+//!
+//! [OTLP]: https://opentelemetry.io/docs/specs/otlp/
+
+use anyhow::{bail, Context as _, Result};
+use maelstrom_base::{JobId, WorkerId};
+use reqwest::{blocking::Client, header::CONTENT_TYPE, Url};
+use serde_json::{json, Value};
+use sha2::{Digest as _, Sha256};
+use slog::{debug, warn, Logger};
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::Write as _,
+    path::PathBuf,
+    sync::mpsc::{self, Receiver, SyncSender, TrySendError},
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// How many finished jobs' spans can be waiting to be exported. If the exporter falls further
+/// behind than this, say because the collector is slow, spans are dropped instead of piling up.
+const EXPORT_QUEUE_SIZE: usize = 1024;
+
+/// How long we wait to connect to the OTLP collector.
+const OTLP_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long we wait for the OTLP collector to respond to a request, including connecting.
+const OTLP_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A point in a job's lifecycle, as seen by the broker or a worker.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JobEvent {
+    /// The job was received from the client, or by the worker from the broker.
+    Enqueued,
+    /// All of the job's artifacts are available.
+    ArtifactsReady,
+    /// The broker sent the job to a worker. This happens again if the worker disconnects before
+    /// the job completes.
+    Dispatched(WorkerId),
+    /// The worker started executing the job.
+    Started,
+    /// The job's result was sent on.
+    Completed,
+    /// The job was dropped without a result, because it was canceled or its client disconnected.
+    Canceled,
+}
+
+impl JobEvent {
+    fn is_final(&self) -> bool {
+        matches!(self, JobEvent::Completed | JobEvent::Canceled)
+    }
+
+    /// The name of the span that starts with this event and ends with the next one.
+    fn span_name(&self) -> &'static str {
+        match self {
+            JobEvent::Enqueued => "waiting for artifacts",
+            JobEvent::ArtifactsReady => "queued",
+            JobEvent::Dispatched(_) => "dispatched",
+            JobEvent::Started => "executing",
+            JobEvent::Completed | JobEvent::Canceled => unreachable!(),
+        }
+    }
+}
+
+/// The trace ID of the job `jid` in the broker run with the given nonce.
+fn trace_id(nonce: u64, jid: JobId) -> [u8; 16] {
+    let hash = Sha256::digest(format!("maelstrom job {nonce:016x} {jid}"));
+    hash[..16].try_into().unwrap()
+}
+
+/// The ID of the span with the given index that `service` records for the job `jid` in the broker
+/// run with the given nonce. The job's root span has index 0.
+fn span_id(nonce: u64, jid: JobId, service: &str, index: usize) -> [u8; 8] {
+    let hash = Sha256::digest(format!(
+        "maelstrom job {nonce:016x} {jid} {service} {index}"
+    ));
+    hash[..8].try_into().unwrap()
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Span {
+    span_id: [u8; 8],
+    parent_span_id: Option<[u8; 8]>,
+    name: &'static str,
+    start: SystemTime,
+    end: SystemTime,
+    attributes: Vec<(&'static str, String)>,
+}
+
+/// Turn the events `service` recorded for a job into spans: a root span covering the whole job,
+/// with a child for the time between each event and the next. The events must end with a final
+/// event.
+fn job_spans(
+    service: &str,
+    parent_service: Option<&str>,
+    nonce: u64,
+    jid: JobId,
+    events: &[(JobEvent, SystemTime)],
+) -> Vec<Span> {
+    let (first, last) = (events.first().unwrap(), events.last().unwrap());
+    let root_span_id = span_id(nonce, jid, service, 0);
+    let outcome = match last.0 {
+        JobEvent::Canceled => "canceled",
+        _ => "completed",
+    };
+    let mut spans = vec![Span {
+        span_id: root_span_id,
+        parent_span_id: parent_service.map(|parent| span_id(nonce, jid, parent, 0)),
+        name: "job",
+        start: first.1,
+        end: last.1,
+        attributes: vec![
+            ("maelstrom.jid", jid.to_string()),
+            ("maelstrom.outcome", outcome.into()),
+        ],
+    }];
+    for (index, pair) in events.windows(2).enumerate() {
+        let [(event, start), (_, end)] = pair else {
+            unreachable!()
+        };
+        let mut attributes = vec![];
+        if let JobEvent::Dispatched(wid) = event {
+            attributes.push(("maelstrom.wid", wid.to_string()));
+        }
+        spans.push(Span {
+            span_id: span_id(nonce, jid, service, index + 1),
+            parent_span_id: Some(root_span_id),
+            name: event.span_name(),
+            start: *start,
+            end: *end,
+            attributes,
+        });
+    }
+    spans
+}
+
+fn unix_nanos(time: SystemTime) -> String {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+        .to_string()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Encode the spans for the job `jid` as an OTLP `ExportTraceServiceRequest`.
+fn encode(service: &str, nonce: u64, jid: JobId, spans: &[Span]) -> Value {
+    let trace_id = hex(&trace_id(nonce, jid));
+    let spans = Vec::from_iter(spans.iter().map(|span| {
+        let attributes = Vec::from_iter(
+            span.attributes
+                .iter()
+                .map(|(key, value)| json!({ "key": key, "value": { "stringValue": value } })),
+        );
+        let mut encoded = json!({
+            "traceId": trace_id,
+            "spanId": hex(&span.span_id),
+            "name": span.name,
+            // SPAN_KIND_INTERNAL
+            "kind": 1,
+            "startTimeUnixNano": unix_nanos(span.start),
+            "endTimeUnixNano": unix_nanos(span.end),
+            "attributes": attributes,
+        });
+        if let Some(parent_span_id) = span.parent_span_id {
+            encoded["parentSpanId"] = hex(&parent_span_id).into();
+        }
+        encoded
+    }));
+    json!({
+        "resourceSpans": [{
+            "resource": {
+                "attributes": [{ "key": "service.name", "value": { "stringValue": service } }],
+            },
+            "scopeSpans": [{
+                "scope": { "name": "maelstrom" },
+                "spans": spans,
+            }],
+        }],
+    })
+}
+
+/// The URL to send OTLP requests to, parsed from an `http://` or `https://` URL. Like other OTLP
+/// exporters, we send traces to `/v1/traces` under the URL.
+#[derive(Debug, PartialEq)]
+struct OtlpEndpoint(Url);
+
+impl OtlpEndpoint {
+    fn parse(url: &str) -> Result<Self> {
+        let mut parsed =
+            Url::parse(url).with_context(|| format!("parsing OTLP endpoint {url:?}"))?;
+        if !matches!(parsed.scheme(), "http" | "https") {
+            bail!("OTLP endpoint {url:?} must be an http:// or https:// URL");
+        }
+        if parsed.host_str().unwrap_or_default().is_empty() {
+            bail!("OTLP endpoint {url:?} has no host");
+        }
+        let path = format!("{}/v1/traces", parsed.path().trim_end_matches('/'));
+        parsed.set_path(&path);
+        Ok(OtlpEndpoint(parsed))
+    }
+
+    fn post(&self, client: &Client, body: String) -> Result<()> {
+        let response = client
+            .post(self.0.clone())
+            .header(CONTENT_TYPE, "application/json")
+            .body(body)
+            .send()?;
+        let status = response.status();
+        if !status.is_success() {
+            bail!("collector responded with {status}");
+        }
+        Ok(())
+    }
+}
+
+/// Write each request received on `receiver` to `file`, one per line, and send it to `endpoint`.
+/// This runs on its own thread, since the blocking HTTP client can't be used from within an async
+/// runtime.
+fn exporter_main(
+    receiver: Receiver<String>,
+    mut file: Option<File>,
+    endpoint: Option<OtlpEndpoint>,
+    log: Logger,
+) {
+    let client = match endpoint
+        .as_ref()
+        .map(|_| {
+            Client::builder()
+                .connect_timeout(OTLP_CONNECT_TIMEOUT)
+                .timeout(OTLP_REQUEST_TIMEOUT)
+                .build()
+        })
+        .transpose()
+    {
+        Ok(client) => client,
+        Err(err) => {
+            warn!(log, "error creating HTTP client, not exporting traces"; "err" => %err);
+            None
+        }
+    };
+    for request in receiver {
+        if let Some(file) = &mut file {
+            if let Err(err) = writeln!(file, "{request}") {
+                warn!(log, "error writing trace file"; "err" => %err);
+            }
+        }
+        if let (Some(endpoint), Some(client)) = (&endpoint, &client) {
+            if let Err(err) = endpoint.post(client, request) {
+                warn!(log, "error exporting traces"; "err" => %err);
+            }
+        }
+    }
+}
+
+/// Log the events of each job, and, if configured to, export them as spans once the job is done.
+pub struct JobTracer {
+    service: &'static str,
+    parent_service: Option<&'static str>,
+    nonce: u64,
+    sender: Option<SyncSender<String>>,
+    dropping: bool,
+    jobs: HashMap<JobId, Vec<(JobEvent, SystemTime)>>,
+    log: Logger,
+}
+
+impl JobTracer {
+    /// Create a new tracer for `service`, whose root span for each job is the child of
+    /// `parent_service`'s root span for the job. `nonce` is the broker's nonce for its current run,
+    /// which goes into every trace and span ID. Spans are appended to `trace_file` and sent to the
+    /// OTLP collector at `otlp_endpoint`, which must be an `http://` or `https://` URL. If neither
+    /// is given, events are only logged.
+    pub fn new(
+        service: &'static str,
+        parent_service: Option<&'static str>,
+        nonce: u64,
+        trace_file: Option<PathBuf>,
+        otlp_endpoint: Option<&str>,
+        log: Logger,
+    ) -> Result<Self> {
+        let file = trace_file
+            .map(|path| {
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&path)
+                    .with_context(|| format!("opening trace file {}", path.display()))
+            })
+            .transpose()?;
+        let endpoint = otlp_endpoint.map(OtlpEndpoint::parse).transpose()?;
+        let sender = (file.is_some() || endpoint.is_some()).then(|| {
+            let (sender, receiver) = mpsc::sync_channel(EXPORT_QUEUE_SIZE);
+            let log = log.clone();
+            thread::spawn(move || exporter_main(receiver, file, endpoint, log));
+            sender
+        });
+        Ok(JobTracer {
+            service,
+            parent_service,
+            nonce,
+            sender,
+            dropping: false,
+            jobs: HashMap::default(),
+            log,
+        })
+    }
+
+    /// The nonce that goes into every trace and span ID.
+    pub fn nonce(&self) -> u64 {
+        self.nonce
+    }
+
+    /// Record that `event` happened to the job `jid` just now.
+    pub fn event(&mut self, jid: JobId, event: JobEvent) {
+        match event {
+            JobEvent::Enqueued => debug!(self.log, "job enqueued"; "jid" => %jid),
+            JobEvent::ArtifactsReady => debug!(self.log, "job has all artifacts"; "jid" => %jid),
+            JobEvent::Dispatched(wid) => {
+                debug!(self.log, "job dispatched"; "jid" => %jid, "wid" => %wid)
+            }
+            JobEvent::Started => debug!(self.log, "job started"; "jid" => %jid),
+            JobEvent::Completed => debug!(self.log, "job completed"; "jid" => %jid),
+            JobEvent::Canceled => debug!(self.log, "job canceled"; "jid" => %jid),
+        }
+
+        let Some(sender) = &self.sender else {
+            return;
+        };
+        let events = self.jobs.entry(jid).or_default();
+        events.push((event, SystemTime::now()));
+        if event.is_final() {
+            let events = self.jobs.remove(&jid).unwrap();
+            let spans = job_spans(self.service, self.parent_service, self.nonce, jid, &events);
+            let request = encode(self.service, self.nonce, jid, &spans).to_string();
+            match sender.try_send(request) {
+                Ok(()) => self.dropping = false,
+                Err(TrySendError::Full(_)) => {
+                    if !self.dropping {
+                        warn!(
+                            self.log,
+                            "trace exporter is falling behind, dropping traces"
+                        );
+                        self.dropping = true;
+                    }
+                }
+                Err(TrySendError::Disconnected(_)) => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use maelstrom_base::{ClientId, ClientJobId};
+    use slog::{o, Discard};
+    use std::{
+        fs,
+        io::{BufRead as _, BufReader, Read as _},
+        net::TcpListener,
+        time::{Duration, Instant},
+    };
+    use tempfile::TempDir;
+
+    fn jid(cid: u32, cjid: u32) -> JobId {
+        JobId {
+            cid: ClientId::from(cid),
+            cjid: ClientJobId::from(cjid),
+        }
+    }
+
+    fn time(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    const NONCE: u64 = 0x0123456789abcdef;
+
+    #[test]
+    fn ids_are_derived_from_nonce_and_job_id() {
+        assert_eq!(trace_id(NONCE, jid(1, 2)), trace_id(NONCE, jid(1, 2)));
+        assert_ne!(trace_id(NONCE, jid(1, 2)), trace_id(NONCE, jid(2, 1)));
+        assert_ne!(trace_id(NONCE, jid(1, 2)), trace_id(NONCE + 1, jid(1, 2)));
+        assert_ne!(
+            span_id(NONCE, jid(1, 2), "broker", 0),
+            span_id(NONCE, jid(1, 2), "worker", 0)
+        );
+        assert_ne!(
+            span_id(NONCE, jid(1, 2), "broker", 0),
+            span_id(NONCE, jid(1, 2), "broker", 1)
+        );
+        assert_ne!(
+            span_id(NONCE, jid(1, 2), "broker", 0),
+            span_id(NONCE + 1, jid(1, 2), "broker", 0)
+        );
+    }
+
+    #[test]
+    fn spans_for_completed_job() {
+        let jid = jid(1, 2);
+        let spans = job_spans(
+            "worker",
+            Some("broker"),
+            NONCE,
+            jid,
+            &[
+                (JobEvent::Enqueued, time(10)),
+                (JobEvent::ArtifactsReady, time(12)),
+                (JobEvent::Started, time(13)),
+                (JobEvent::Completed, time(20)),
+            ],
+        );
+        let root = span_id(NONCE, jid, "worker", 0);
+        assert_eq!(
+            spans,
+            vec![
+                Span {
+                    span_id: root,
+                    parent_span_id: Some(span_id(NONCE, jid, "broker", 0)),
+                    name: "job",
+                    start: time(10),
+                    end: time(20),
+                    attributes: vec![
+                        ("maelstrom.jid", "1.2".into()),
+                        ("maelstrom.outcome", "completed".into()),
+                    ],
+                },
+                Span {
+                    span_id: span_id(NONCE, jid, "worker", 1),
+                    parent_span_id: Some(root),
+                    name: "waiting for artifacts",
+                    start: time(10),
+                    end: time(12),
+                    attributes: vec![],
+                },
+                Span {
+                    span_id: span_id(NONCE, jid, "worker", 2),
+                    parent_span_id: Some(root),
+                    name: "queued",
+                    start: time(12),
+                    end: time(13),
+                    attributes: vec![],
+                },
+                Span {
+                    span_id: span_id(NONCE, jid, "worker", 3),
+                    parent_span_id: Some(root),
+                    name: "executing",
+                    start: time(13),
+                    end: time(20),
+                    attributes: vec![],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn spans_for_canceled_job() {
+        let jid = jid(1, 2);
+        let spans = job_spans(
+            "broker",
+            None,
+            NONCE,
+            jid,
+            &[
+                (JobEvent::Enqueued, time(10)),
+                (JobEvent::ArtifactsReady, time(10)),
+                (JobEvent::Dispatched(WorkerId::from(3)), time(11)),
+                (JobEvent::Canceled, time(15)),
+            ],
+        );
+        assert_eq!(spans.len(), 4);
+        assert_eq!(spans[0].parent_span_id, None);
+        assert_eq!(
+            spans[0].attributes[1],
+            ("maelstrom.outcome", "canceled".into())
+        );
+        assert_eq!(spans[3].name, "dispatched");
+        assert_eq!(spans[3].attributes, vec![("maelstrom.wid", "3".into())]);
+    }
+
+    #[test]
+    fn encode_spans() {
+        let jid = jid(1, 2);
+        let spans = job_spans(
+            "broker",
+            None,
+            NONCE,
+            jid,
+            &[
+                (JobEvent::Enqueued, time(1)),
+                (JobEvent::Completed, time(2)),
+            ],
+        );
+        let request = encode("broker", NONCE, jid, &spans);
+        let resource_spans = &request["resourceSpans"][0];
+        assert_eq!(
+            resource_spans["resource"]["attributes"][0],
+            json!({ "key": "service.name", "value": { "stringValue": "broker" } })
+        );
+        let spans = &resource_spans["scopeSpans"][0]["spans"];
+        assert_eq!(spans.as_array().unwrap().len(), 2);
+        let root = &spans[0];
+        assert_eq!(root["traceId"], hex(&trace_id(NONCE, jid)));
+        assert_eq!(root["traceId"].as_str().unwrap().len(), 32);
+        assert_eq!(root["spanId"].as_str().unwrap().len(), 16);
+        assert_eq!(root.get("parentSpanId"), None);
+        assert_eq!(root["startTimeUnixNano"], "1000000000");
+        assert_eq!(root["endTimeUnixNano"], "2000000000");
+        assert_eq!(spans[1]["parentSpanId"], root["spanId"]);
+        assert_eq!(spans[1]["name"], "waiting for artifacts");
+    }
+
+    #[test]
+    fn parse_otlp_endpoint() {
+        let parse = |url| OtlpEndpoint::parse(url).map(|endpoint| endpoint.0.to_string());
+        assert_eq!(
+            parse("http://localhost:4318").unwrap(),
+            "http://localhost:4318/v1/traces"
+        );
+        assert_eq!(
+            parse("https://collector:4318/otlp/").unwrap(),
+            "https://collector:4318/otlp/v1/traces"
+        );
+        assert!(parse("ftp://collector:4318").is_err());
+        assert!(parse("http://").is_err());
+        assert!(parse("collector:4318").is_err());
+    }
+
+    /// Wait for `path` to have `lines` lines, and return them.
+    fn wait_for_lines(path: &std::path::Path, lines: usize) -> Vec<String> {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        loop {
+            let contents = fs::read_to_string(path).unwrap_or_default();
+            let found = Vec::from_iter(contents.lines().map(String::from));
+            if found.len() >= lines || Instant::now() > deadline {
+                return found;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn file_exporter() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("traces.jsonl");
+        let log = Logger::root(Discard, o!());
+        let mut tracer =
+            JobTracer::new("broker", None, NONCE, Some(path.clone()), None, log).unwrap();
+        tracer.event(jid(1, 1), JobEvent::Enqueued);
+        tracer.event(jid(1, 2), JobEvent::Enqueued);
+        tracer.event(jid(1, 1), JobEvent::ArtifactsReady);
+        tracer.event(jid(1, 1), JobEvent::Completed);
+        tracer.event(jid(1, 2), JobEvent::Canceled);
+
+        let lines = wait_for_lines(&path, 2);
+        assert_eq!(lines.len(), 2);
+        let requests = Vec::from_iter(
+            lines
+                .iter()
+                .map(|line| serde_json::from_str::<Value>(line).unwrap()),
+        );
+        let spans = |request: &Value| {
+            request["resourceSpans"][0]["scopeSpans"][0]["spans"]
+                .as_array()
+                .unwrap()
+                .clone()
+        };
+        assert_eq!(spans(&requests[0]).len(), 3);
+        assert_eq!(
+            spans(&requests[0])[0]["traceId"],
+            hex(&trace_id(NONCE, jid(1, 1)))
+        );
+        assert_eq!(spans(&requests[1]).len(), 2);
+        assert_eq!(
+            spans(&requests[1])[0]["traceId"],
+            hex(&trace_id(NONCE, jid(1, 2)))
+        );
+        assert!(tracer.jobs.is_empty());
+    }
+
+    #[test]
+    fn tracer_without_exporters_only_logs() {
+        let log = Logger::root(Discard, o!());
+        let mut tracer = JobTracer::new("broker", None, NONCE, None, None, log).unwrap();
+        tracer.event(jid(1, 1), JobEvent::Enqueued);
+        assert!(tracer.sender.is_none());
+        assert!(tracer.jobs.is_empty());
+    }
+
+    #[test]
+    fn otlp_exporter() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let log = Logger::root(Discard, o!());
+        let mut tracer = JobTracer::new("worker", None, NONCE, None, Some(&endpoint), log).unwrap();
+        tracer.event(jid(1, 1), JobEvent::Enqueued);
+        tracer.event(jid(1, 1), JobEvent::Completed);
+
+        let (mut socket, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(socket.try_clone().unwrap());
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line).unwrap();
+        assert_eq!(request_line, "POST /v1/traces HTTP/1.1\r\n");
+        let mut content_length = 0;
+        loop {
+            let mut header = String::new();
+            reader.read_line(&mut header).unwrap();
+            if header == "\r\n" {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                if name.eq_ignore_ascii_case("content-length") {
+                    content_length = value.trim().parse().unwrap();
+                }
+            }
+        }
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body).unwrap();
+        let request: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            request["resourceSpans"][0]["scopeSpans"][0]["spans"][0]["traceId"],
+            hex(&trace_id(NONCE, jid(1, 1)))
+        );
+        socket.write_all(b"HTTP/1.1 200 OK\r\n\r\n").unwrap();
+    }
+
+    #[test]
+    fn exporter_drops_traces_when_it_falls_behind() {
+        // The collector never responds, so the exporter gets stuck on the first request.
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let log = Logger::root(Discard, o!());
+        let mut tracer = JobTracer::new("worker", None, NONCE, None, Some(&endpoint), log).unwrap();
+        for cjid in 0..EXPORT_QUEUE_SIZE as u32 + 10 {
+            tracer.event(jid(1, cjid), JobEvent::Enqueued);
+            tracer.event(jid(1, cjid), JobEvent::Completed);
+        }
+        assert!(tracer.dropping);
+    }
+}
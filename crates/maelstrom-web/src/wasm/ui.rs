@@ -1,15 +1,18 @@
 use crate::wasm::rpc::ClientConnection;
 use anyhow::{bail, Result};
 use eframe::{App, CreationContext, Frame};
-use egui::{Align2, CentralPanel, CollapsingHeader, Color32, Context, ScrollArea, Ui};
+use egui::{Align2, CentralPanel, CollapsingHeader, Color32, Context, Grid, ScrollArea, Ui};
 use egui_gauge::Gauge;
 use egui_toast::{Toast, ToastKind, ToastOptions, Toasts};
 use maelstrom_base::{
     proto::{BrokerToClient, ClientToBroker},
-    stats::{BrokerStatistics, JobState, JobStateCounts, BROKER_STATISTICS_INTERVAL},
+    stats::{BrokerStatistics, JobState, JobStateCounts, BROKER_STATISTICS_INTERVAL, CAPACITY},
+};
+use maelstrom_plot::{Legend, Line, Plot, PlotBounds, PlotPoints, PlotUi, StackedLine};
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    time::{Duration, SystemTime},
 };
-use maelstrom_plot::{Legend, Plot, PlotBounds, PlotPoints, PlotUi, StackedLine};
-use std::{collections::BTreeSet, time::Duration};
 
 const REFRESH_INTERVAL: Duration = BROKER_STATISTICS_INTERVAL;
 
@@ -20,9 +23,28 @@ fn merge_job_state_counts(mut a: JobStateCounts, b: &JobStateCounts) -> JobState
     a
 }
 
+fn format_duration(duration: Option<Duration>) -> String {
+    duration.map_or("-".into(), |duration| {
+        format!("{:.1}s", duration.as_secs_f64())
+    })
+}
+
+/// The broker only keeps a history of job statistics. The rest of what we plot over time we
+/// collect ourselves, one sample for each job statistics sample we see.
+struct BrokerSample {
+    timestamp: SystemTime,
+    slots: usize,
+    running: usize,
+    pending: usize,
+    cache_bytes_used: u64,
+    average_queue_wait: Option<Duration>,
+    average_run_duration: Option<Duration>,
+}
+
 pub struct UiHandler<RpcConnectionT> {
     rpc: Option<RpcConnectionT>,
     stats: Option<BrokerStatistics>,
+    history: VecDeque<BrokerSample>,
     freshness: f64,
 }
 
@@ -83,6 +105,7 @@ impl<RpcConnectionT: ClientConnection> UiHandler<RpcConnectionT> {
         Self {
             rpc: Some(rpc),
             stats: None,
+            history: VecDeque::with_capacity(CAPACITY),
             freshness: 0.0,
         }
     }
@@ -159,6 +182,169 @@ impl<RpcConnectionT: ClientConnection> UiHandler<RpcConnectionT> {
         }
     }
 
+    fn record_sample(&mut self, stats: &BrokerStatistics) {
+        let Some(last_stat) = stats.job_statistics.iter().last() else {
+            return;
+        };
+        if self
+            .history
+            .back()
+            .is_some_and(|sample| sample.timestamp == last_stat.timestamp)
+        {
+            return;
+        }
+        if self.history.len() == CAPACITY {
+            self.history.pop_front();
+        }
+        let workers = stats.worker_statistics.values();
+        self.history.push_back(BrokerSample {
+            timestamp: last_stat.timestamp,
+            slots: workers.clone().map(|w| w.slots).sum(),
+            running: workers.clone().map(|w| w.running).sum(),
+            pending: workers.map(|w| w.pending).sum(),
+            cache_bytes_used: stats.cache_statistics.bytes_used,
+            average_queue_wait: stats.average_queue_wait,
+            average_run_duration: stats.average_run_duration,
+        });
+    }
+
+    /// Plot `f` of each sample in our history against how many seconds before the latest sample it
+    /// was taken.
+    fn history_points(&self, f: impl Fn(&BrokerSample) -> Option<f64>) -> Vec<[f64; 2]> {
+        let Some(latest) = self.history.back() else {
+            return vec![];
+        };
+        self.history
+            .iter()
+            .filter_map(|sample| {
+                let age = latest
+                    .timestamp
+                    .duration_since(sample.timestamp)
+                    .unwrap_or_default();
+                f(sample).map(|y| [-age.as_secs_f64(), y])
+            })
+            .collect()
+    }
+
+    fn draw_worker_utilization(&self, ui: &mut Ui, stats: &BrokerStatistics) {
+        CollapsingHeader::new("Worker Utilization")
+            .default_open(true)
+            .show(ui, |ui| {
+                Plot::new("worker_utilization")
+                    .width(1000.0)
+                    .height(200.0)
+                    .legend(Legend::default())
+                    .x_axis_label("seconds")
+                    .include_y(0.0)
+                    .show(ui, |plot_ui| {
+                        let running = self.history_points(|s| Some(s.running as f64));
+                        let pending = self.history_points(|s| Some((s.running + s.pending) as f64));
+                        plot_ui.stacked_line(
+                            StackedLine::new(PlotPoints::new(running.clone())).name("running"),
+                        );
+                        plot_ui.stacked_line(
+                            StackedLine::new(PlotPoints::new(pending))
+                                .stacked_on(running)
+                                .name("pending"),
+                        );
+                        plot_ui.line(
+                            Line::new(self.history_points(|s| Some(s.slots as f64))).name("slots"),
+                        );
+                    });
+
+                let workers: BTreeMap<_, _> = stats.worker_statistics.iter().collect();
+                Grid::new("worker_statistics").striped(true).show(ui, |ui| {
+                    ui.strong("worker");
+                    ui.strong("slots");
+                    ui.strong("running");
+                    ui.strong("pending");
+                    ui.end_row();
+                    for (wid, worker) in workers {
+                        ui.label(wid.to_string());
+                        ui.label(worker.slots.to_string());
+                        ui.label(worker.running.to_string());
+                        ui.label(worker.pending.to_string());
+                        ui.end_row();
+                    }
+                });
+            });
+    }
+
+    fn draw_cache_health(&self, ui: &mut Ui, stats: &BrokerStatistics) {
+        let cache = &stats.cache_statistics;
+        let requests = cache.hits + cache.misses;
+        let hit_rate = if requests > 0 {
+            format!("{:.1}%", cache.hits as f64 * 100.0 / requests as f64)
+        } else {
+            "-".into()
+        };
+        CollapsingHeader::new("Cache Health")
+            .default_open(true)
+            .show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    for (value, label) in [
+                        (cache.entries.to_string(), "artifact(s) cached"),
+                        (hit_rate, "hit rate"),
+                        (cache.evictions.to_string(), "artifact(s) evicted"),
+                        (
+                            stats.scrub_statistics.corrupt_artifacts.to_string(),
+                            "corrupt artifact(s) quarantined",
+                        ),
+                    ] {
+                        ui.vertical(|ui| {
+                            ui.heading(value);
+                            ui.label(label);
+                        });
+                    }
+                });
+                Plot::new("cache_bytes_used")
+                    .width(1000.0)
+                    .height(200.0)
+                    .legend(Legend::default())
+                    .x_axis_label("seconds")
+                    .include_y(0.0)
+                    .show(ui, |plot_ui| {
+                        plot_ui.line(
+                            Line::new(
+                                self.history_points(|s| {
+                                    Some(s.cache_bytes_used as f64 / 1_000_000.0)
+                                }),
+                            )
+                            .name("MB used"),
+                        );
+                    });
+            });
+    }
+
+    fn draw_job_durations(&self, ui: &mut Ui) {
+        CollapsingHeader::new("Job Durations")
+            .default_open(true)
+            .show(ui, |ui| {
+                Plot::new("job_durations")
+                    .width(1000.0)
+                    .height(200.0)
+                    .legend(Legend::default())
+                    .x_axis_label("seconds")
+                    .include_y(0.0)
+                    .show(ui, |plot_ui| {
+                        plot_ui.line(
+                            Line::new(
+                                self.history_points(|s| {
+                                    s.average_queue_wait.map(|d| d.as_secs_f64())
+                                }),
+                            )
+                            .name("average queue wait (s)"),
+                        );
+                        plot_ui.line(
+                            Line::new(self.history_points(|s| {
+                                s.average_run_duration.map(|d| d.as_secs_f64())
+                            }))
+                            .name("average run duration (s)"),
+                        );
+                    });
+            });
+    }
+
     fn draw_stats(&self, ui: &mut Ui, stats: &BrokerStatistics) {
         let last_stat = stats.job_statistics.iter().last();
         let num_clients = last_stat.map(|s| s.client_to_stats.len()).unwrap_or(0);
@@ -199,6 +385,10 @@ impl<RpcConnectionT: ClientConnection> UiHandler<RpcConnectionT> {
                 ui.label("worker(s) connected");
                 ui.heading(num_total_jobs.to_string());
                 ui.label("total job(s)");
+                ui.heading(format_duration(stats.average_queue_wait));
+                ui.label("average queue wait");
+                ui.heading(format_duration(stats.average_run_duration));
+                ui.label("average run duration");
             });
 
             if num_slots > 0 {
@@ -209,6 +399,9 @@ impl<RpcConnectionT: ClientConnection> UiHandler<RpcConnectionT> {
             }
         });
 
+        self.draw_worker_utilization(ui, stats);
+        self.draw_cache_health(ui, stats);
+        self.draw_job_durations(ui);
         self.draw_all_clients_graph(ui, stats);
         self.draw_client_graphs(ui, stats);
     }
@@ -223,7 +416,10 @@ impl<RpcConnectionT: ClientConnection> UiHandler<RpcConnectionT> {
 
             if let Some(msg) = rpc.try_recv()? {
                 match msg {
-                    BrokerToClient::StatisticsResponse(stats) => self.stats = Some(stats),
+                    BrokerToClient::StatisticsResponse(stats) => {
+                        self.record_sample(&stats);
+                        self.stats = Some(stats);
+                    }
                     r => bail!("unexpected response: {r:?}"),
                 }
             }
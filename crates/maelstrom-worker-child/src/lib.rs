@@ -8,20 +8,33 @@
 
 use core::{ffi::CStr, result};
 use maelstrom_linux::{
-    self as linux, CloseRangeFirst, CloseRangeFlags, CloseRangeLast, Errno, Fd, FileMode,
-    MountFlags, NetlinkSocketAddr, OpenFlags, SocketDomain, SocketProtocol, SocketType,
-    UmountFlags,
+    self as linux, Capability, CloneArgs, CloneFlags, CloseRangeFirst, CloseRangeFlags,
+    CloseRangeLast, Errno, ExitCode, Fd, FileMode, MountFlags, NetlinkSocketAddr, OpenFlags, Pid,
+    Rlimit, RlimitResource, Signal, SignalSet, SockFprog, SocketDomain, SocketProtocol, SocketType,
+    UmountFlags, WaitStatus,
 };
 
 /// A syscall to call. This should be part of slice, which we refer to as a script. Some variants
-/// deal with a value. This is a `usize` local variable that can be written to and read from.
+/// deal with a saved file descriptor or a saved interface index. These are local variables that can
+/// be written to and read from.
 pub enum Syscall<'a> {
     OpenAndSaveFd(&'a CStr, OpenFlags, FileMode),
     SocketAndSaveFd(SocketDomain, SocketType, SocketProtocol),
     BindNetlinkUsingSavedFd(&'a NetlinkSocketAddr),
+    Read(Fd, &'a mut [u8]),
     ReadUsingSavedFd(&'a mut [u8]),
+    ReceiveNetlinkAckUsingSavedFd(&'a mut [u8]),
+    Write(Fd, &'a [u8]),
     WriteUsingSavedFd(&'a [u8]),
+    GetInterfaceIndexUsingSavedFdAndSaveIt(&'a CStr),
+    /// Write the saved interface index into the buffer at the given offset, then write the buffer
+    /// to the saved file descriptor.
+    WriteWithSavedInterfaceIndexUsingSavedFd(&'a mut [u8], usize),
+    DropBoundingCapability(Capability),
     SetSid,
+    ForkAndBecomeInit(Fd),
+    SetHostname(&'a [u8]),
+    SetDomainname(&'a [u8]),
     Dup2(Fd, Fd),
     CloseRange(CloseRangeFirst, CloseRangeLast, CloseRangeFlags),
     Mount(
@@ -35,11 +48,18 @@ pub enum Syscall<'a> {
     Mkdir(&'a CStr, FileMode),
     PivotRoot(&'a CStr, &'a CStr),
     Umount2(&'a CStr, UmountFlags),
+    SetRlimit(RlimitResource, Rlimit),
+    SetNoNewPrivs,
+    SeccompSetModeFilter(&'a SockFprog<'a>),
     Execve(&'a CStr, &'a [Option<&'a u8>], &'a [Option<&'a u8>]),
 }
 
 impl<'a> Syscall<'a> {
-    fn call(&mut self, saved_fd: &mut Fd) -> result::Result<(), Errno> {
+    fn call(
+        &mut self,
+        saved_fd: &mut Fd,
+        saved_interface_index: &mut u32,
+    ) -> result::Result<(), Errno> {
         match self {
             Syscall::SocketAndSaveFd(domain, sock_type, protocol) => {
                 linux::socket(*domain, *sock_type, *protocol).map(|fd| {
@@ -47,13 +67,32 @@ impl<'a> Syscall<'a> {
                 })
             }
             Syscall::BindNetlinkUsingSavedFd(sockaddr) => linux::bind_netlink(*saved_fd, sockaddr),
+            Syscall::Read(fd, buf) => linux::read(*fd, buf).map(drop),
             Syscall::ReadUsingSavedFd(buf) => linux::read(*saved_fd, buf).map(drop),
+            Syscall::ReceiveNetlinkAckUsingSavedFd(buf) => {
+                let len = linux::read(*saved_fd, buf)?;
+                netlink_ack_result(&buf[..len])
+            }
+            Syscall::Write(fd, buf) => linux::write(*fd, buf).map(drop),
             Syscall::OpenAndSaveFd(filename, flags, mode) => linux::open(filename, *flags, *mode)
                 .map(|fd| {
                     *saved_fd = fd;
                 }),
             Syscall::WriteUsingSavedFd(buf) => linux::write(*saved_fd, buf).map(drop),
+            Syscall::GetInterfaceIndexUsingSavedFdAndSaveIt(name) => {
+                linux::ioctl_siocgifindex(*saved_fd, name).map(|index| {
+                    *saved_interface_index = index;
+                })
+            }
+            Syscall::WriteWithSavedInterfaceIndexUsingSavedFd(buf, offset) => {
+                buf[*offset..*offset + 4].copy_from_slice(&saved_interface_index.to_ne_bytes());
+                linux::write(*saved_fd, buf).map(drop)
+            }
+            Syscall::DropBoundingCapability(capability) => linux::prctl_capbset_drop(*capability),
             Syscall::SetSid => linux::setsid(),
+            Syscall::ForkAndBecomeInit(status_fd) => fork_and_become_init(*status_fd),
+            Syscall::SetHostname(name) => linux::sethostname(name),
+            Syscall::SetDomainname(name) => linux::setdomainname(name),
             Syscall::Dup2(from, to) => linux::dup2(*from, *to).map(drop),
             Syscall::CloseRange(first, last, flags) => linux::close_range(*first, *last, *flags),
             Syscall::Mount(source, target, fstype, flags, data) => {
@@ -63,6 +102,9 @@ impl<'a> Syscall<'a> {
             Syscall::Mkdir(path, mode) => linux::mkdir(path, *mode),
             Syscall::PivotRoot(new_root, put_old) => linux::pivot_root(new_root, put_old),
             Syscall::Umount2(path, flags) => linux::umount2(path, *flags),
+            Syscall::SetRlimit(resource, rlimit) => linux::setrlimit(*resource, rlimit),
+            Syscall::SetNoNewPrivs => linux::prctl_set_no_new_privs(),
+            Syscall::SeccompSetModeFilter(prog) => linux::seccomp_set_mode_filter(prog),
             Syscall::Execve(program, arguments, environment) => {
                 linux::execve(program, arguments, environment)
             }
@@ -70,13 +112,84 @@ impl<'a> Syscall<'a> {
     }
 }
 
+/// Check the reply to an rtnetlink request made with `NLM_F_ACK`. The reply is an `NLMSG_ERROR`
+/// message: a 16-byte `nlmsghdr` followed by a negated errno, which is 0 on success.
+fn netlink_ack_result(reply: &[u8]) -> result::Result<(), Errno> {
+    const NLMSG_ERROR: u16 = 2;
+    if reply.len() < 20 || u16::from_ne_bytes([reply[4], reply[5]]) != NLMSG_ERROR {
+        return Err(Errno::EPROTO);
+    }
+    match i32::from_ne_bytes([reply[16], reply[17], reply[18], reply[19]]) {
+        0 => Ok(()),
+        error => Err(Errno::from_u64(error.unsigned_abs().into())),
+    }
+}
+
+/// Fork the process that will become the job. The child returns and carries on with the script.
+/// The parent, which is the init process of the job's pid namespace, never returns: it forwards
+/// the signals it gets to the job and reaps orphans until the job terminates, then writes the job's
+/// status to `status_fd` and exits.
+///
+/// This is needed because the kernel drops signals sent to the init process of a pid namespace
+/// unless it has installed a handler for them, which most programs don't. The exception is
+/// `SIGKILL`, which still kills the init process, and with it every process in the namespace.
+///
+/// The status is written as two bytes: 0 and the exit code if the job exited, or 1 and the signal
+/// number if it was killed by a signal.
+fn fork_and_become_init(status_fd: Fd) -> result::Result<(), Errno> {
+    // Block signals before forking, so that none sent to us are lost before we wait for them.
+    let old_mask = linux::sigprocmask(&SignalSet::full())?;
+    // The job shouldn't run any of the worker's signal handlers if it's signaled before it execs.
+    let mut clone_args = CloneArgs::default()
+        .flags(CloneFlags::CLEAR_SIGHAND)
+        .exit_signal(Signal::CHLD);
+    match linux::clone3(&mut clone_args)? {
+        None => linux::sigprocmask(&old_mask).map(drop),
+        Some(job_pid) => run_init(job_pid, status_fd),
+    }
+}
+
+fn run_init(job_pid: Pid, status_fd: Fd) -> ! {
+    // Don't hold on to the job's output pipes or any other file descriptors.
+    let _ = linux::dup2(status_fd, Fd::STDIN);
+    let _ = linux::close_range(
+        CloseRangeFirst::Fd(Fd::STDOUT),
+        CloseRangeLast::Max,
+        CloseRangeFlags::default(),
+    );
+    let signals = SignalSet::full();
+    loop {
+        let Ok(signal) = linux::sigwaitinfo(&signals) else {
+            continue;
+        };
+        if signal != Signal::CHLD {
+            let _ = linux::kill(job_pid, signal);
+            continue;
+        }
+        while let Ok(Some(result)) = linux::try_wait() {
+            if result.pid != job_pid {
+                continue;
+            }
+            let (status, exit_code) = match result.status {
+                WaitStatus::Exited(code) => ([0, code.as_u8()], code.as_u8()),
+                WaitStatus::Signaled(signal) => {
+                    ([1, signal.as_u8()], 128u8.saturating_add(signal.as_u8()))
+                }
+            };
+            let _ = linux::write(Fd::STDIN, &status);
+            linux::_exit(ExitCode::from_u8(exit_code));
+        }
+    }
+}
+
 /// The guts of the child code. This function shouldn't return on success, because in that case,
 /// the last syscall should be an execve. If this function returns, than an error was encountered.
 /// In that case, the script item index and the errno will be returned.
 fn start_and_exec_in_child_inner(syscalls: &mut [Syscall]) -> (usize, Errno) {
     let mut saved_fd = Fd::STDIN;
+    let mut saved_interface_index = 0;
     for (index, syscall) in syscalls.iter_mut().enumerate() {
-        if let Err(errno) = syscall.call(&mut saved_fd) {
+        if let Err(errno) = syscall.call(&mut saved_fd, &mut saved_interface_index) {
             return (index, errno);
         }
     }
@@ -6,11 +6,15 @@ use maelstrom_util::{
     config::{CacheBytesUsedTarget, CacheRoot},
     heap::{Heap, HeapDeps, HeapIndex},
 };
-use slog::{debug, Logger};
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
+use slog::{debug, info, Logger};
 use std::{
     cmp::Ordering,
-    collections::{hash_map::Entry, HashMap},
-    fs, mem,
+    collections::{hash_map::Entry, HashMap, HashSet},
+    fs,
+    io::Write as _,
+    mem,
     num::NonZeroU32,
     ops::{Deref, DerefMut},
     path::{Path, PathBuf},
@@ -44,6 +48,17 @@ pub trait CacheFs {
     /// Return and iterator that will yield all of the children of a directory. Panic on file
     /// system error or if `path` doesn't exist or isn't a directory.
     fn read_dir(&self, path: &Path) -> Box<dyn Iterator<Item = PathBuf>>;
+
+    /// Return the contents of the file at `path`. Panic on file system error.
+    fn read_file(&self, path: &Path) -> Vec<u8>;
+
+    /// Replace the contents of the file at `path` with `contents`, creating it if it doesn't
+    /// exist. A reader must see either the old contents or the new contents, never a mix of the
+    /// two, even after a crash. Panic on file system error.
+    fn write_file(&mut self, path: &Path, contents: &[u8]);
+
+    /// Remove the file at `path`. Panic on file system error.
+    fn remove_file(&mut self, path: &Path);
 }
 
 /// The standard implementation of CacheFs that uses [std] and [rand].
@@ -63,7 +78,13 @@ impl CacheFs for StdCacheFs {
     }
 
     fn remove_recursively_on_thread(&mut self, path: PathBuf) {
-        thread::spawn(move || fs::remove_dir_all(path).unwrap());
+        thread::spawn(move || {
+            if fs::symlink_metadata(&path).unwrap().is_dir() {
+                fs::remove_dir_all(path).unwrap()
+            } else {
+                fs::remove_file(path).unwrap()
+            }
+        });
     }
 
     fn mkdir_recursively(&mut self, path: &Path) {
@@ -73,6 +94,28 @@ impl CacheFs for StdCacheFs {
     fn read_dir(&self, path: &Path) -> Box<dyn Iterator<Item = PathBuf>> {
         Box::new(fs::read_dir(path).unwrap().map(|de| de.unwrap().path()))
     }
+
+    fn read_file(&self, path: &Path) -> Vec<u8> {
+        fs::read(path).unwrap()
+    }
+
+    fn write_file(&mut self, path: &Path, contents: &[u8]) {
+        // Make sure the contents are on disk before the rename, and the rename is on disk before
+        // we return, so that a crash leaves either the old file or the complete new one.
+        let tmp_path = path.with_extension("tmp");
+        let mut tmp_file = fs::File::create(&tmp_path).unwrap();
+        tmp_file.write_all(contents).unwrap();
+        tmp_file.sync_all().unwrap();
+        fs::rename(tmp_path, path).unwrap();
+        fs::File::open(path.parent().unwrap())
+            .unwrap()
+            .sync_all()
+            .unwrap();
+    }
+
+    fn remove_file(&mut self, path: &Path) {
+        fs::remove_file(path).unwrap()
+    }
 }
 
 /// Type returned from [Cache::get_artifact].
@@ -95,6 +138,28 @@ pub enum GetArtifact {
     Get(PathBuf),
 }
 
+/// How much space a fetched artifact uses. This is provided to [Cache::got_artifact_success].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ArtifactSize {
+    /// The bytes used by the artifact's directory, not counting the contents of files linked from
+    /// the file store.
+    pub bytes_used: u64,
+
+    /// The files in the file store that the artifact holds onto, along with their sizes. For
+    /// manifests, these are the files the artifact's directory links to. For tars, this is the tar
+    /// itself, if it's kept so that it can be served to other workers.
+    pub files: Vec<(Sha256Digest, u64)>,
+}
+
+impl From<u64> for ArtifactSize {
+    fn from(bytes_used: u64) -> Self {
+        ArtifactSize {
+            bytes_used,
+            files: vec![],
+        }
+    }
+}
+
 /// An entry for a specific [Sha256Digest] in the [Cache]'s hash table. There is one of these for
 /// every subdirectory in the `sha256` subdirectory of the [Cache]'s root directory.
 enum CacheEntry {
@@ -108,6 +173,7 @@ enum CacheEntry {
     /// multiple jobs using the same artifact.
     InUse {
         bytes_used: u64,
+        files: Vec<Sha256Digest>,
         ref_count: NonZeroU32,
     },
 
@@ -116,11 +182,39 @@ enum CacheEntry {
     /// determine which entry should be removed first when freeing up space.
     InHeap {
         bytes_used: u64,
+        files: Vec<Sha256Digest>,
         priority: u64,
         heap_index: HeapIndex,
     },
 }
 
+/// The contents of an artifact's completion marker, which is the file `{digest}.complete` next to
+/// the artifact's subdirectory in `sha256`. The marker is written once the artifact has been
+/// completely fetched and synced to disk. It has everything a later [Cache] needs to adopt the
+/// artifact.
+#[serde_as]
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+struct CompletionMarker {
+    bytes_used: u64,
+    #[serde_as(as = "Vec<(DisplayFromStr, _)>")]
+    files: Vec<(Sha256Digest, u64)>,
+    /// When the artifact was last used, relative to the other artifacts in the cache. Artifacts
+    /// are adopted in this order, so that the least recently used ones are removed first. Writing
+    /// the marker takes a couple of fsyncs, so this isn't updated every time an artifact stops
+    /// being used. Instead, it is updated for all such artifacts when the [Cache] is dropped.
+    last_used: u64,
+}
+
+const COMPLETION_MARKER_EXTENSION: &str = "complete";
+
+/// An entry for a specific [Sha256Digest] in the file store, which is the `files` subdirectory of
+/// the [Cache]'s root directory. The file is kept as long as some artifact in the cache links to
+/// it. Its size is counted once, no matter how many artifacts link to it.
+struct FileEntry {
+    bytes_used: u64,
+    ref_count: NonZeroU32,
+}
+
 /// An implementation of the "newtype" pattern so that we can implement [HeapDeps] on a [HashMap].
 #[derive(Default)]
 struct CacheMap(HashMap<Sha256Digest, CacheEntry>);
@@ -162,25 +256,52 @@ impl HeapDeps for CacheMap {
     }
 }
 
+/// A snapshot of the cache's size, and counts of what has happened to it since the worker started.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CacheMetrics {
+    /// The number of artifacts in the cache.
+    pub entries: u64,
+    /// The number of files in the file store.
+    pub files: u64,
+    /// The bytes used by artifacts and files in the file store.
+    pub bytes_used: u64,
+    /// The number of times a job asked for an artifact that was in the cache.
+    pub hits: u64,
+    /// The number of times a job asked for an artifact that wasn't in the cache.
+    pub misses: u64,
+    /// The number of artifacts removed to keep the cache under its target size.
+    pub evictions: u64,
+}
+
 /// Manage a directory of downloaded, extracted artifacts. Coordinate fetching of these artifacts,
 /// and removing them when they are no longer in use and the amount of space used by the directory
 /// has grown too large.
-pub struct Cache<FsT> {
+pub struct Cache<FsT: CacheFs> {
     fs: FsT,
     root: PathBuf,
     entries: CacheMap,
+    files: HashMap<Sha256Digest, FileEntry>,
     heap: Heap<CacheMap>,
     next_priority: u64,
     bytes_used: u64,
     bytes_used_target: u64,
+    hits: u64,
+    misses: u64,
+    evictions: u64,
+    removed_files: Vec<Sha256Digest>,
+    /// Artifacts whose completion markers have an out-of-date `last_used`.
+    stale_markers: HashSet<Sha256Digest>,
     log: Logger,
 }
 
 impl<FsT: CacheFs> Cache<FsT> {
     /// Create a new [Cache] rooted at `root`. The directory `root` and all necessary ancestors
-    /// will be created, along with `{root}/removing` and `{root}/sha256`. Any pre-existing entries
-    /// in `{root}/removing` and `{root}/sha256` will be removed. That implies that the [Cache]
-    /// doesn't currently keep data stored across invocations.
+    /// will be created, along with `{root}/removing`, `{root}/sha256`, and `{root}/files`.
+    ///
+    /// Artifacts left in `{root}/sha256` by a previous [Cache] are adopted if they have a
+    /// completion marker, along with the files in `{root}/files` they link to. Everything else,
+    /// including artifacts that were still being extracted, is removed. Adopted artifacts are
+    /// put in the heap in the order they were last used.
     ///
     /// `bytes_used_target` is the goal on-disk size for the cache. The cache will periodically grow
     /// larger than this size, but then shrink back down to this size. Ideally, the cache would use
@@ -201,23 +322,30 @@ impl<FsT: CacheFs> Cache<FsT> {
         }
         path.pop();
 
-        path.push("sha256");
-        if fs.file_exists(&path) {
-            Self::remove_in_background(&mut fs, &root, &path);
+        for subdir in ["sha256", "files"] {
+            path.push(subdir);
+            fs.mkdir_recursively(&path);
+            path.pop();
         }
-        fs.mkdir_recursively(&path);
-        path.pop();
 
-        Cache {
+        let mut cache = Cache {
             fs,
             root,
             entries: CacheMap::default(),
+            files: HashMap::default(),
             heap: Heap::default(),
             next_priority: 0,
             bytes_used: 0,
             bytes_used_target: bytes_used_target.into_inner(),
+            hits: 0,
+            misses: 0,
+            evictions: 0,
+            removed_files: vec![],
+            stale_markers: HashSet::default(),
             log,
-        }
+        };
+        cache.adopt_previous_entries();
+        cache
     }
 
     /// Attempt to fetch `artifact` from the cache. See [GetArtifact] for the meaning of the return
@@ -226,6 +354,7 @@ impl<FsT: CacheFs> Cache<FsT> {
         let cache_path = Self::cache_path(&self.root, &digest);
         match self.entries.entry(digest) {
             Entry::Vacant(entry) => {
+                self.misses += 1;
                 entry.insert(CacheEntry::DownloadingAndExtracting(vec![jid]));
                 GetArtifact::Get(cache_path)
             }
@@ -233,15 +362,18 @@ impl<FsT: CacheFs> Cache<FsT> {
                 let entry = entry.into_mut();
                 match entry {
                     CacheEntry::DownloadingAndExtracting(jobs) => {
+                        self.misses += 1;
                         jobs.push(jid);
                         GetArtifact::Wait
                     }
                     CacheEntry::InUse { ref_count, .. } => {
+                        self.hits += 1;
                         *ref_count = ref_count.checked_add(1).unwrap();
                         GetArtifact::Success(cache_path)
                     }
                     CacheEntry::InHeap {
                         bytes_used,
+                        files,
                         heap_index,
                         ..
                     } => {
@@ -249,8 +381,10 @@ impl<FsT: CacheFs> Cache<FsT> {
                         *entry = CacheEntry::InUse {
                             ref_count: NonZeroU32::new(1).unwrap(),
                             bytes_used: *bytes_used,
+                            files: mem::take(files),
                         };
                         self.heap.remove(&mut self.entries, heap_index);
+                        self.hits += 1;
                         GetArtifact::Success(cache_path)
                     }
                 }
@@ -273,11 +407,15 @@ impl<FsT: CacheFs> Cache<FsT> {
 
     /// Notify the cache that an artifact fetch has successfully completed. The returned vector
     /// lists the jobs that are affected, and the path they can use to access the artifact.
+    ///
+    /// The files in `size` must be in the file store. They will be kept there until this artifact
+    /// and all other artifacts that link to them are removed from the cache.
     pub fn got_artifact_success(
         &mut self,
         digest: &Sha256Digest,
-        bytes_used: u64,
+        size: ArtifactSize,
     ) -> (PathBuf, Vec<JobId>) {
+        let ArtifactSize { bytes_used, files } = size;
         let entry = self
             .entries
             .get_mut(digest)
@@ -288,11 +426,17 @@ impl<FsT: CacheFs> Cache<FsT> {
         let ref_count = jobs.len().try_into().unwrap();
         let jobs = mem::take(jobs);
         // Reference count must be > 0 since we don't allow cancellation of gets.
+        let file_digests = Vec::from_iter(files.iter().map(|(file, _)| file.clone()));
         *entry = CacheEntry::InUse {
             bytes_used,
+            files: file_digests.clone(),
             ref_count: NonZeroU32::new(ref_count).unwrap(),
         };
         self.bytes_used = self.bytes_used.checked_add(bytes_used).unwrap();
+        for (file, file_bytes_used) in files {
+            self.acquire_file(file, file_bytes_used);
+        }
+        self.write_completion_marker(digest, bytes_used, &file_digests, self.next_priority);
         debug!(self.log, "cache added artifact";
             "digest" => %digest,
             "artifact_bytes_used" => %ByteSize::b(bytes_used),
@@ -312,6 +456,7 @@ impl<FsT: CacheFs> Cache<FsT> {
             .expect("Got decrement_ref_count in unexpected state");
         let CacheEntry::InUse {
             bytes_used,
+            files,
             ref_count,
         } = entry
         else {
@@ -320,11 +465,17 @@ impl<FsT: CacheFs> Cache<FsT> {
         match NonZeroU32::new(ref_count.get() - 1) {
             Some(new_ref_count) => *ref_count = new_ref_count,
             None => {
-                *entry = CacheEntry::InHeap {
-                    bytes_used: *bytes_used,
-                    priority: self.next_priority,
-                    heap_index: HeapIndex::default(),
-                };
+                let (bytes_used, files) = (*bytes_used, mem::take(files));
+                self.stale_markers.insert(digest.clone());
+                self.entries.insert(
+                    digest.clone(),
+                    CacheEntry::InHeap {
+                        bytes_used,
+                        files,
+                        priority: self.next_priority,
+                        heap_index: HeapIndex::default(),
+                    },
+                );
                 self.heap.push(&mut self.entries, digest.clone());
                 self.next_priority = self.next_priority.checked_add(1).unwrap();
                 self.possibly_remove_some();
@@ -332,6 +483,33 @@ impl<FsT: CacheFs> Cache<FsT> {
         }
     }
 
+    /// Return the files that have been removed from the file store since the last call.
+    pub fn take_removed_files(&mut self) -> Vec<Sha256Digest> {
+        mem::take(&mut self.removed_files)
+    }
+
+    /// Return the cache's size and counters.
+    pub fn metrics(&self) -> CacheMetrics {
+        CacheMetrics {
+            entries: self
+                .entries
+                .values()
+                .filter(|entry| !matches!(entry, CacheEntry::DownloadingAndExtracting(_)))
+                .count() as u64,
+            files: self.files.len() as u64,
+            bytes_used: self.bytes_used,
+            hits: self.hits,
+            misses: self.misses,
+            evictions: self.evictions,
+        }
+    }
+
+    /// Return the directory where the files linked to by manifest artifacts, and tars kept to serve
+    /// to other workers, are stored, each named by its digest.
+    pub fn file_store_path(&self) -> PathBuf {
+        self.root.join("files")
+    }
+
     /// Remove all files and directories rooted in `source` in a separate thread.
     fn remove_in_background(fs: &mut impl CacheFs, root: &Path, source: &Path) {
         let mut target = root.to_owned();
@@ -349,6 +527,192 @@ impl<FsT: CacheFs> Cache<FsT> {
         fs.remove_recursively_on_thread(target);
     }
 
+    /// Adopt the artifacts left behind by a previous [Cache]. See [Cache::new].
+    fn adopt_previous_entries(&mut self) {
+        let mut directories = vec![];
+        let mut markers = vec![];
+        for child in self.fs.read_dir(&self.root.join("sha256")) {
+            match Self::digest_from_path(&child) {
+                Some((digest, None)) => directories.push((digest, child)),
+                Some((digest, Some(COMPLETION_MARKER_EXTENSION))) => markers.push((digest, child)),
+                _ => Self::remove_in_background(&mut self.fs, &self.root, &child),
+            }
+        }
+
+        let mut store = vec![];
+        for child in self.fs.read_dir(&self.file_store_path()) {
+            match Self::digest_from_path(&child) {
+                Some((digest, None)) => store.push((digest, child)),
+                _ => Self::remove_in_background(&mut self.fs, &self.root, &child),
+            }
+        }
+
+        let directory_digests =
+            HashSet::<&Sha256Digest>::from_iter(directories.iter().map(|(d, _)| d));
+        let store_digests = HashSet::<&Sha256Digest>::from_iter(store.iter().map(|(d, _)| d));
+        let mut adopted = HashMap::new();
+        for (digest, path) in markers {
+            let marker = serde_json::from_slice::<CompletionMarker>(&self.fs.read_file(&path))
+                .ok()
+                .filter(|marker| {
+                    directory_digests.contains(&digest)
+                        && marker
+                            .files
+                            .iter()
+                            .all(|(file, _)| store_digests.contains(file))
+                });
+            match marker {
+                Some(marker) => {
+                    adopted.insert(digest, marker);
+                }
+                None => self.fs.remove_file(&path),
+            }
+        }
+
+        for (digest, path) in &directories {
+            if !adopted.contains_key(digest) {
+                Self::remove_in_background(&mut self.fs, &self.root, path);
+            }
+        }
+
+        let mut adopted = Vec::from_iter(adopted);
+        adopted.sort_by_key(|(_, marker)| marker.last_used);
+        for (digest, marker) in adopted {
+            let CompletionMarker {
+                bytes_used, files, ..
+            } = marker;
+            self.bytes_used = self.bytes_used.checked_add(bytes_used).unwrap();
+            let file_digests = Vec::from_iter(files.iter().map(|(file, _)| file.clone()));
+            for (file, file_bytes_used) in files {
+                self.acquire_file(file, file_bytes_used);
+            }
+            self.entries.insert(
+                digest.clone(),
+                CacheEntry::InHeap {
+                    bytes_used,
+                    files: file_digests,
+                    priority: self.next_priority,
+                    heap_index: HeapIndex::default(),
+                },
+            );
+            self.heap.push(&mut self.entries, digest);
+            self.next_priority = self.next_priority.checked_add(1).unwrap();
+        }
+
+        for (digest, path) in &store {
+            if !self.files.contains_key(digest) {
+                Self::remove_in_background(&mut self.fs, &self.root, path);
+            }
+        }
+
+        info!(self.log, "cache adopted artifacts from previous run";
+            "entries" => %self.entries.len(),
+            "files" => %self.files.len(),
+            "bytes_used" => %ByteSize::b(self.bytes_used),
+            "byte_used_target" => %ByteSize::b(self.bytes_used_target)
+        );
+        self.possibly_remove_some();
+    }
+
+    /// Return the digest named by the last component of `path`, along with the component's
+    /// extension, if it has one.
+    fn digest_from_path(path: &Path) -> Option<(Sha256Digest, Option<&str>)> {
+        let digest = path.file_stem()?.to_str()?.parse().ok()?;
+        match path.extension() {
+            None => Some((digest, None)),
+            Some(extension) => Some((digest, Some(extension.to_str()?))),
+        }
+    }
+
+    /// Write the completion marker for an artifact. The sizes of `files` are taken from the file
+    /// store, so they must already be in it.
+    fn write_completion_marker(
+        &mut self,
+        digest: &Sha256Digest,
+        bytes_used: u64,
+        files: &[Sha256Digest],
+        last_used: u64,
+    ) {
+        let marker = CompletionMarker {
+            bytes_used,
+            files: files
+                .iter()
+                .map(|file| (file.clone(), self.files[file].bytes_used))
+                .collect(),
+            last_used,
+        };
+        self.fs.write_file(
+            &Self::completion_marker_path(&self.root, digest),
+            &serde_json::to_vec(&marker).unwrap(),
+        );
+    }
+
+    /// Rewrite the completion markers whose `last_used` is out of date.
+    fn save_last_used(&mut self) {
+        for digest in mem::take(&mut self.stale_markers) {
+            let (bytes_used, files, last_used) = match &self.entries[&digest] {
+                CacheEntry::InHeap {
+                    bytes_used,
+                    files,
+                    priority,
+                    ..
+                } => (*bytes_used, files.clone(), *priority),
+                CacheEntry::InUse {
+                    bytes_used, files, ..
+                } => (*bytes_used, files.clone(), self.next_priority),
+                CacheEntry::DownloadingAndExtracting(_) => {
+                    panic!("Stale marker for artifact in unexpected state")
+                }
+            };
+            self.write_completion_marker(&digest, bytes_used, &files, last_used);
+        }
+    }
+
+    /// Add an artifact's reference to a file in the file store, counting the file's size if this
+    /// is the first artifact to link to it.
+    fn acquire_file(&mut self, file: Sha256Digest, bytes_used: u64) {
+        match self.files.entry(file) {
+            Entry::Vacant(entry) => {
+                entry.insert(FileEntry {
+                    bytes_used,
+                    ref_count: NonZeroU32::new(1).unwrap(),
+                });
+                self.bytes_used = self.bytes_used.checked_add(bytes_used).unwrap();
+            }
+            Entry::Occupied(entry) => {
+                let ref_count = &mut entry.into_mut().ref_count;
+                *ref_count = ref_count.checked_add(1).unwrap();
+            }
+        }
+    }
+
+    /// Drop an artifact's reference to a file in the file store, removing the file if it was the
+    /// last one. The file may already be gone if a fetcher linked to it right before it was
+    /// removed for a previous artifact, and then reported it as part of a new one.
+    fn release_file(&mut self, file: Sha256Digest) {
+        let Entry::Occupied(mut entry) = self.files.entry(file) else {
+            panic!("File released that isn't in the file store");
+        };
+        if let Some(ref_count) = NonZeroU32::new(entry.get().ref_count.get() - 1) {
+            entry.get_mut().ref_count = ref_count;
+            return;
+        }
+        let (file, FileEntry { bytes_used, .. }) = entry.remove_entry();
+        let path = self.file_store_path().join(file.to_string());
+        if self.fs.file_exists(&path) {
+            Self::remove_in_background(&mut self.fs, &self.root, &path);
+        }
+        self.bytes_used = self.bytes_used.checked_sub(bytes_used).unwrap();
+        self.removed_files.push(file.clone());
+        debug!(self.log, "cache removed file";
+            "digest" => %file,
+            "file_bytes_used" => %ByteSize::b(bytes_used),
+            "files" => %self.files.len(),
+            "bytes_used" => %ByteSize::b(self.bytes_used),
+            "byte_used_target" => %ByteSize::b(self.bytes_used_target)
+        );
+    }
+
     /// Return the directory path for the artifact referenced by `digest`.
     fn cache_path(root: &Path, digest: &Sha256Digest) -> PathBuf {
         let mut path = root.to_owned();
@@ -357,6 +721,11 @@ impl<FsT: CacheFs> Cache<FsT> {
         path
     }
 
+    /// Return the path of the completion marker for the artifact referenced by `digest`.
+    fn completion_marker_path(root: &Path, digest: &Sha256Digest) -> PathBuf {
+        Self::cache_path(root, digest).with_extension(COMPLETION_MARKER_EXTENSION)
+    }
+
     /// Check to see if the cache is over its goal size, and if so, try to remove the least
     /// recently used artifacts.
     fn possibly_remove_some(&mut self) {
@@ -364,15 +733,27 @@ impl<FsT: CacheFs> Cache<FsT> {
             let Some(digest) = self.heap.pop(&mut self.entries) else {
                 break;
             };
-            let Some(CacheEntry::InHeap { bytes_used, .. }) = self.entries.remove(&digest) else {
+            let Some(CacheEntry::InHeap {
+                bytes_used, files, ..
+            }) = self.entries.remove(&digest)
+            else {
                 panic!("Entry popped off of heap was in unexpected state");
             };
+            // Remove the marker first, so that a later Cache never adopts a partially removed
+            // artifact.
+            self.stale_markers.remove(&digest);
+            self.fs
+                .remove_file(&Self::completion_marker_path(&self.root, &digest));
             Self::remove_in_background(
                 &mut self.fs,
                 &self.root,
                 &Self::cache_path(&self.root, &digest),
             );
             self.bytes_used = self.bytes_used.checked_sub(bytes_used).unwrap();
+            self.evictions += 1;
+            for file in files {
+                self.release_file(file);
+            }
             debug!(self.log, "cache removed artifact";
                 "digest" => %digest,
                 "artifact_bytes_used" => %ByteSize::b(bytes_used),
@@ -384,6 +765,12 @@ impl<FsT: CacheFs> Cache<FsT> {
     }
 }
 
+impl<FsT: CacheFs> Drop for Cache<FsT> {
+    fn drop(&mut self) {
+        self.save_last_used();
+    }
+}
+
 /*  _            _
  * | |_ ___  ___| |_ ___
  * | __/ _ \/ __| __/ __|
@@ -408,6 +795,9 @@ mod tests {
         RemoveRecursively(PathBuf),
         MkdirRecursively(PathBuf),
         ReadDir(PathBuf),
+        ReadFile(PathBuf),
+        WriteFile(PathBuf, String),
+        RemoveFile(PathBuf),
     }
 
     #[derive(Default)]
@@ -415,6 +805,7 @@ mod tests {
         messages: Rc<RefCell<Vec<TestMessage>>>,
         existing_files: HashSet<PathBuf>,
         directories: HashMap<PathBuf, Vec<PathBuf>>,
+        file_contents: HashMap<PathBuf, Vec<u8>>,
         last_random_number: u64,
     }
 
@@ -457,6 +848,52 @@ mod tests {
                     .into_iter(),
             )
         }
+
+        fn read_file(&self, path: &Path) -> Vec<u8> {
+            self.messages.borrow_mut().push(ReadFile(path.to_owned()));
+            self.file_contents.get(path).unwrap().clone()
+        }
+
+        fn write_file(&mut self, path: &Path, contents: &[u8]) {
+            self.messages.borrow_mut().push(WriteFile(
+                path.to_owned(),
+                String::from_utf8(contents.to_owned()).unwrap(),
+            ));
+        }
+
+        fn remove_file(&mut self, path: &Path) {
+            self.messages.borrow_mut().push(RemoveFile(path.to_owned()));
+        }
+    }
+
+    fn marker_contents(bytes_used: u64, files: Vec<(u64, u64)>, last_used: u64) -> String {
+        serde_json::to_string(&CompletionMarker {
+            bytes_used,
+            files: files
+                .into_iter()
+                .map(|(file, size)| (digest!(file), size))
+                .collect(),
+            last_used,
+        })
+        .unwrap()
+    }
+
+    /// The message for writing the completion marker of the artifact `digest!(n)`.
+    fn write_marker(
+        n: u64,
+        bytes_used: u64,
+        files: Vec<(u64, u64)>,
+        last_used: u64,
+    ) -> TestMessage {
+        WriteFile(
+            long_path!("/z/sha256", n, "complete"),
+            marker_contents(bytes_used, files, last_used),
+        )
+    }
+
+    /// The message for removing the completion marker of the artifact `digest!(n)`.
+    fn remove_marker(n: u64) -> TestMessage {
+        RemoveFile(long_path!("/z/sha256", n, "complete"))
     }
 
     struct Fixture {
@@ -536,11 +973,11 @@ mod tests {
         fn got_artifact_success(
             &mut self,
             digest: Sha256Digest,
-            bytes_used: u64,
+            size: impl Into<ArtifactSize>,
             expected: (PathBuf, Vec<JobId>),
             expected_fs_operations: Vec<TestMessage>,
         ) {
-            let result = self.cache.got_artifact_success(&digest, bytes_used);
+            let result = self.cache.got_artifact_success(&digest, size.into());
             assert_eq!(result, expected);
             self.expect_messages_in_any_order(expected_fs_operations);
         }
@@ -556,8 +993,12 @@ mod tests {
             self.expect_messages_in_any_order(expected_fs_operations);
         }
 
-        fn got_artifact_success_ign(&mut self, digest: Sha256Digest, bytes_used: u64) {
-            self.cache.got_artifact_success(&digest, bytes_used);
+        fn got_artifact_success_ign(
+            &mut self,
+            digest: Sha256Digest,
+            size: impl Into<ArtifactSize>,
+        ) {
+            self.cache.got_artifact_success(&digest, size.into());
             self.clear_messages();
         }
 
@@ -585,7 +1026,7 @@ mod tests {
             digest!(42),
             100,
             (long_path!("/z/sha256", 42), vec![jid!(1)]),
-            vec![],
+            vec![write_marker(42, 100, vec![], 0)],
         );
     }
 
@@ -598,12 +1039,13 @@ mod tests {
             digest!(42),
             10000,
             (long_path!("/z/sha256", 42), vec![jid!(1)]),
-            vec![],
+            vec![write_marker(42, 10000, vec![], 0)],
         );
 
         fixture.decrement_ref_count(
             digest!(42),
             vec![
+                remove_marker(42),
                 FileExists(short_path!("/z/removing", 1)),
                 Rename(long_path!("/z/sha256", 42), short_path!("/z/removing", 1)),
                 RemoveRecursively(short_path!("/z/removing", 1)),
@@ -629,6 +1071,8 @@ mod tests {
             4,
             (long_path!("/z/sha256", 3), vec![jid!(3)]),
             vec![
+                write_marker(3, 4, vec![], 2),
+                remove_marker(1),
                 FileExists(short_path!("/z/removing", 1)),
                 Rename(long_path!("/z/sha256", 1), short_path!("/z/removing", 1)),
                 RemoveRecursively(short_path!("/z/removing", 1)),
@@ -642,6 +1086,8 @@ mod tests {
             4,
             (long_path!("/z/sha256", 4), vec![jid!(4)]),
             vec![
+                write_marker(4, 4, vec![], 3),
+                remove_marker(2),
                 FileExists(short_path!("/z/removing", 2)),
                 Rename(long_path!("/z/sha256", 2), short_path!("/z/removing", 2)),
                 RemoveRecursively(short_path!("/z/removing", 2)),
@@ -650,6 +1096,79 @@ mod tests {
         fixture.decrement_ref_count(digest!(4), vec![]);
     }
 
+    #[test]
+    fn metrics() {
+        let mut fixture = Fixture::new_and_clear_messages(10);
+
+        fixture.get_artifact_ign(digest!(1), jid!(1));
+        fixture.get_artifact_ign(digest!(1), jid!(2));
+        fixture.got_artifact_success_ign(
+            digest!(1),
+            ArtifactSize {
+                bytes_used: 4,
+                files: vec![(digest!(10), 2)],
+            },
+        );
+        fixture.decrement_ref_count_ign(digest!(1));
+        fixture.decrement_ref_count_ign(digest!(1));
+        fixture.get_artifact_ign(digest!(1), jid!(3));
+        assert_eq!(
+            fixture.cache.metrics(),
+            CacheMetrics {
+                entries: 1,
+                files: 1,
+                bytes_used: 6,
+                hits: 1,
+                misses: 2,
+                evictions: 0,
+            }
+        );
+
+        fixture.decrement_ref_count_ign(digest!(1));
+        fixture.get_artifact_ign(digest!(2), jid!(4));
+        fixture.got_artifact_success_ign(digest!(2), 8);
+        assert_eq!(
+            fixture.cache.metrics(),
+            CacheMetrics {
+                entries: 1,
+                files: 0,
+                bytes_used: 8,
+                hits: 1,
+                misses: 3,
+                evictions: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn last_use_is_saved_when_dropped() {
+        let mut fixture = Fixture::new_and_clear_messages(1000);
+
+        fixture.get_artifact_ign(digest!(1), jid!(1));
+        fixture.got_artifact_success_ign(digest!(1), 3);
+        fixture.get_artifact_ign(digest!(2), jid!(2));
+        fixture.got_artifact_success_ign(digest!(2), 3);
+        fixture.get_artifact_ign(digest!(3), jid!(3));
+        fixture.got_artifact_success_ign(digest!(3), 3);
+
+        fixture.decrement_ref_count(digest!(2), vec![]);
+        fixture.decrement_ref_count(digest!(1), vec![]);
+        fixture.get_artifact(
+            digest!(2),
+            jid!(4),
+            GetArtifact::Success(long_path!("/z/sha256", 2)),
+        );
+
+        let Fixture { messages, cache } = fixture;
+        drop(cache);
+        let mut messages = messages.take();
+        messages.sort_by_key(|message| format!("{message:?}"));
+        assert_eq!(
+            messages,
+            vec![write_marker(1, 3, vec![], 1), write_marker(2, 3, vec![], 2)]
+        );
+    }
+
     #[test]
     fn lru_order_augmented_by_last_use() {
         let mut fixture = Fixture::new_and_clear_messages(10);
@@ -673,6 +1192,8 @@ mod tests {
             3,
             (long_path!("/z/sha256", 4), vec![jid!(4)]),
             vec![
+                write_marker(4, 3, vec![], 3),
+                remove_marker(3),
                 FileExists(short_path!("/z/removing", 1)),
                 Rename(long_path!("/z/sha256", 3), short_path!("/z/removing", 1)),
                 RemoveRecursively(short_path!("/z/removing", 1)),
@@ -692,7 +1213,7 @@ mod tests {
             digest!(42),
             100,
             (long_path!("/z/sha256", 42), vec![jid!(1), jid!(2), jid!(3)]),
-            vec![],
+            vec![write_marker(42, 100, vec![], 0)],
         );
     }
 
@@ -708,7 +1229,7 @@ mod tests {
             digest!(42),
             10000,
             (long_path!("/z/sha256", 42), vec![jid!(1), jid!(2), jid!(3)]),
-            vec![],
+            vec![write_marker(42, 10000, vec![], 0)],
         );
 
         fixture.decrement_ref_count(digest!(42), vec![]);
@@ -716,6 +1237,7 @@ mod tests {
         fixture.decrement_ref_count(
             digest!(42),
             vec![
+                remove_marker(42),
                 FileExists(short_path!("/z/removing", 1)),
                 Rename(long_path!("/z/sha256", 42), short_path!("/z/removing", 1)),
                 RemoveRecursively(short_path!("/z/removing", 1)),
@@ -740,6 +1262,7 @@ mod tests {
         fixture.decrement_ref_count(
             digest!(42),
             vec![
+                remove_marker(42),
                 FileExists(short_path!("/z/removing", 1)),
                 Rename(long_path!("/z/sha256", 42), short_path!("/z/removing", 1)),
                 RemoveRecursively(short_path!("/z/removing", 1)),
@@ -769,12 +1292,13 @@ mod tests {
             digest!(43),
             100,
             (long_path!("/z/sha256", 43), vec![jid!(3)]),
-            vec![],
+            vec![write_marker(43, 100, vec![], 1)],
         );
 
         fixture.decrement_ref_count(
             digest!(42),
             vec![
+                remove_marker(42),
                 FileExists(short_path!("/z/removing", 1)),
                 Rename(long_path!("/z/sha256", 42), short_path!("/z/removing", 1)),
                 RemoveRecursively(short_path!("/z/removing", 1)),
@@ -914,8 +1438,10 @@ mod tests {
         fixture.expect_messages_in_specific_order(vec![
             MkdirRecursively(path_buf!("/z/removing")),
             ReadDir(path_buf!("/z/removing")),
-            FileExists(path_buf!("/z/sha256")),
             MkdirRecursively(path_buf!("/z/sha256")),
+            MkdirRecursively(path_buf!("/z/files")),
+            ReadDir(path_buf!("/z/sha256")),
+            ReadDir(path_buf!("/z/files")),
         ]);
     }
 
@@ -935,24 +1461,264 @@ mod tests {
             ReadDir(path_buf!("/z/removing")),
             RemoveRecursively(short_path!("/z/removing", 10)),
             RemoveRecursively(short_path!("/z/removing", 20)),
-            FileExists(path_buf!("/z/sha256")),
             MkdirRecursively(path_buf!("/z/sha256")),
+            MkdirRecursively(path_buf!("/z/files")),
+            ReadDir(path_buf!("/z/sha256")),
+            ReadDir(path_buf!("/z/files")),
         ]);
     }
 
     #[test]
-    fn new_removes_old_sha256_if_it_exists() {
+    fn new_removes_unmarked_artifacts_and_unlinked_files() {
         let mut test_cache_fs = TestCacheFs::default();
-        test_cache_fs.existing_files.insert(path_buf!("/z/sha256"));
+        test_cache_fs.directories.insert(
+            path_buf!("/z/sha256"),
+            vec![
+                long_path!("/z/sha256", 1, "tmp"),
+                long_path!("/z/sha256", 2),
+            ],
+        );
+        test_cache_fs
+            .directories
+            .insert(path_buf!("/z/files"), vec![long_path!("/z/files", 10)]);
         let mut fixture = Fixture::new(test_cache_fs, 1000);
         fixture.expect_messages_in_specific_order(vec![
             MkdirRecursively(path_buf!("/z/removing")),
             ReadDir(path_buf!("/z/removing")),
-            FileExists(path_buf!("/z/sha256")),
+            MkdirRecursively(path_buf!("/z/sha256")),
+            MkdirRecursively(path_buf!("/z/files")),
+            ReadDir(path_buf!("/z/sha256")),
             FileExists(short_path!("/z/removing", 1)),
-            Rename(path_buf!("/z/sha256"), short_path!("/z/removing", 1)),
+            Rename(
+                long_path!("/z/sha256", 1, "tmp"),
+                short_path!("/z/removing", 1),
+            ),
             RemoveRecursively(short_path!("/z/removing", 1)),
+            ReadDir(path_buf!("/z/files")),
+            FileExists(short_path!("/z/removing", 2)),
+            Rename(long_path!("/z/sha256", 2), short_path!("/z/removing", 2)),
+            RemoveRecursively(short_path!("/z/removing", 2)),
+            FileExists(short_path!("/z/removing", 3)),
+            Rename(long_path!("/z/files", 10), short_path!("/z/removing", 3)),
+            RemoveRecursively(short_path!("/z/removing", 3)),
+        ]);
+
+        fixture.get_artifact(
+            digest!(2),
+            jid!(1),
+            GetArtifact::Get(long_path!("/z/sha256", 2)),
+        );
+    }
+
+    #[test]
+    fn new_adopts_marked_artifacts_in_lru_order() {
+        let mut test_cache_fs = TestCacheFs::default();
+        test_cache_fs.directories.insert(
+            path_buf!("/z/sha256"),
+            vec![
+                long_path!("/z/sha256", 1),
+                long_path!("/z/sha256", 1, "complete"),
+                long_path!("/z/sha256", 2),
+                long_path!("/z/sha256", 2, "complete"),
+            ],
+        );
+        test_cache_fs.directories.insert(
+            path_buf!("/z/files"),
+            vec![long_path!("/z/files", 10), long_path!("/z/files", 11)],
+        );
+        test_cache_fs.file_contents.insert(
+            long_path!("/z/sha256", 1, "complete"),
+            marker_contents(3, vec![(10, 5)], 7).into(),
+        );
+        test_cache_fs.file_contents.insert(
+            long_path!("/z/sha256", 2, "complete"),
+            marker_contents(4, vec![(10, 5), (11, 6)], 3).into(),
+        );
+        test_cache_fs
+            .existing_files
+            .insert(long_path!("/z/files", 11));
+        let mut fixture = Fixture::new(test_cache_fs, 1000);
+        fixture.expect_messages_in_specific_order(vec![
+            MkdirRecursively(path_buf!("/z/removing")),
+            ReadDir(path_buf!("/z/removing")),
             MkdirRecursively(path_buf!("/z/sha256")),
+            MkdirRecursively(path_buf!("/z/files")),
+            ReadDir(path_buf!("/z/sha256")),
+            ReadDir(path_buf!("/z/files")),
+            ReadFile(long_path!("/z/sha256", 1, "complete")),
+            ReadFile(long_path!("/z/sha256", 2, "complete")),
+        ]);
+
+        // The cache holds 3 + 4 + 5 + 6 = 18 bytes. Adding 990 more pushes out the least recently
+        // used artifact, which is 2, along with the file only it links to.
+        fixture.get_artifact_ign(digest!(3), jid!(1));
+        fixture.cache.got_artifact_success(&digest!(3), 990.into());
+        fixture.expect_messages_in_specific_order(vec![
+            write_marker(3, 990, vec![], 2),
+            remove_marker(2),
+            FileExists(short_path!("/z/removing", 1)),
+            Rename(long_path!("/z/sha256", 2), short_path!("/z/removing", 1)),
+            RemoveRecursively(short_path!("/z/removing", 1)),
+            FileExists(long_path!("/z/files", 11)),
+            FileExists(short_path!("/z/removing", 2)),
+            Rename(long_path!("/z/files", 11), short_path!("/z/removing", 2)),
+            RemoveRecursively(short_path!("/z/removing", 2)),
+        ]);
+
+        fixture.get_artifact(
+            digest!(1),
+            jid!(2),
+            GetArtifact::Success(long_path!("/z/sha256", 1)),
+        );
+    }
+
+    #[test]
+    fn new_discards_artifacts_with_bad_markers() {
+        let mut test_cache_fs = TestCacheFs::default();
+        test_cache_fs.directories.insert(
+            path_buf!("/z/sha256"),
+            vec![
+                long_path!("/z/sha256", 1),
+                long_path!("/z/sha256", 1, "complete"),
+                long_path!("/z/sha256", 2),
+                long_path!("/z/sha256", 2, "complete"),
+                long_path!("/z/sha256", 3, "complete"),
+            ],
+        );
+        // Artifact 1's marker is garbage, artifact 2 links to a file that isn't in the file store,
+        // and artifact 3 has no directory.
+        test_cache_fs
+            .file_contents
+            .insert(long_path!("/z/sha256", 1, "complete"), b"{\"bytes".to_vec());
+        test_cache_fs.file_contents.insert(
+            long_path!("/z/sha256", 2, "complete"),
+            marker_contents(4, vec![(10, 5)], 0).into(),
+        );
+        test_cache_fs.file_contents.insert(
+            long_path!("/z/sha256", 3, "complete"),
+            marker_contents(4, vec![], 0).into(),
+        );
+        let mut fixture = Fixture::new(test_cache_fs, 1000);
+        fixture.expect_messages_in_specific_order(vec![
+            MkdirRecursively(path_buf!("/z/removing")),
+            ReadDir(path_buf!("/z/removing")),
+            MkdirRecursively(path_buf!("/z/sha256")),
+            MkdirRecursively(path_buf!("/z/files")),
+            ReadDir(path_buf!("/z/sha256")),
+            ReadDir(path_buf!("/z/files")),
+            ReadFile(long_path!("/z/sha256", 1, "complete")),
+            RemoveFile(long_path!("/z/sha256", 1, "complete")),
+            ReadFile(long_path!("/z/sha256", 2, "complete")),
+            RemoveFile(long_path!("/z/sha256", 2, "complete")),
+            ReadFile(long_path!("/z/sha256", 3, "complete")),
+            RemoveFile(long_path!("/z/sha256", 3, "complete")),
+            FileExists(short_path!("/z/removing", 1)),
+            Rename(long_path!("/z/sha256", 1), short_path!("/z/removing", 1)),
+            RemoveRecursively(short_path!("/z/removing", 1)),
+            FileExists(short_path!("/z/removing", 2)),
+            Rename(long_path!("/z/sha256", 2), short_path!("/z/removing", 2)),
+            RemoveRecursively(short_path!("/z/removing", 2)),
+        ]);
+    }
+
+    #[test]
+    fn files_are_removed_with_last_artifact_that_links_to_them() {
+        let mut test_cache_fs = TestCacheFs::default();
+        test_cache_fs
+            .existing_files
+            .insert(long_path!("/z/files", 10));
+        test_cache_fs
+            .existing_files
+            .insert(long_path!("/z/files", 11));
+        let mut fixture = Fixture::new_with_fs_and_clear_messages(test_cache_fs, 10);
+
+        fixture.get_artifact_ign(digest!(1), jid!(1));
+        fixture.got_artifact_success_ign(
+            digest!(1),
+            ArtifactSize {
+                bytes_used: 1,
+                files: vec![(digest!(10), 5)],
+            },
+        );
+        fixture.get_artifact_ign(digest!(2), jid!(2));
+        fixture.got_artifact_success_ign(
+            digest!(2),
+            ArtifactSize {
+                bytes_used: 1,
+                files: vec![(digest!(10), 5), (digest!(11), 5)],
+            },
+        );
+
+        fixture.decrement_ref_count(
+            digest!(1),
+            vec![
+                remove_marker(1),
+                FileExists(short_path!("/z/removing", 1)),
+                Rename(long_path!("/z/sha256", 1), short_path!("/z/removing", 1)),
+                RemoveRecursively(short_path!("/z/removing", 1)),
+            ],
+        );
+        assert_eq!(fixture.cache.take_removed_files(), vec![]);
+        fixture.cache.decrement_ref_count(&digest!(2));
+        fixture.expect_messages_in_specific_order(vec![
+            remove_marker(2),
+            FileExists(short_path!("/z/removing", 2)),
+            Rename(long_path!("/z/sha256", 2), short_path!("/z/removing", 2)),
+            RemoveRecursively(short_path!("/z/removing", 2)),
+            FileExists(long_path!("/z/files", 10)),
+            FileExists(short_path!("/z/removing", 3)),
+            Rename(long_path!("/z/files", 10), short_path!("/z/removing", 3)),
+            RemoveRecursively(short_path!("/z/removing", 3)),
+            FileExists(long_path!("/z/files", 11)),
+            FileExists(short_path!("/z/removing", 4)),
+            Rename(long_path!("/z/files", 11), short_path!("/z/removing", 4)),
+            RemoveRecursively(short_path!("/z/removing", 4)),
+        ]);
+        assert_eq!(
+            fixture.cache.take_removed_files(),
+            vec![digest!(10), digest!(11)]
+        );
+        assert_eq!(fixture.cache.take_removed_files(), vec![]);
+    }
+
+    #[test]
+    fn shared_files_are_only_counted_once() {
+        let mut fixture = Fixture::new_and_clear_messages(10);
+
+        fixture.get_artifact_ign(digest!(1), jid!(1));
+        fixture.got_artifact_success_ign(
+            digest!(1),
+            ArtifactSize {
+                bytes_used: 0,
+                files: vec![(digest!(10), 8)],
+            },
+        );
+        fixture.decrement_ref_count(digest!(1), vec![]);
+
+        fixture.get_artifact_ign(digest!(2), jid!(2));
+        fixture.got_artifact_success_ign(
+            digest!(2),
+            ArtifactSize {
+                bytes_used: 0,
+                files: vec![(digest!(10), 8)],
+            },
+        );
+        fixture.decrement_ref_count(digest!(2), vec![]);
+
+        fixture.get_artifact_ign(digest!(3), jid!(3));
+        let result = fixture.cache.got_artifact_success(&digest!(3), 5.into());
+        assert_eq!(result, (long_path!("/z/sha256", 3), vec![jid!(3)]));
+        fixture.expect_messages_in_specific_order(vec![
+            write_marker(3, 5, vec![], 2),
+            remove_marker(1),
+            FileExists(short_path!("/z/removing", 1)),
+            Rename(long_path!("/z/sha256", 1), short_path!("/z/removing", 1)),
+            RemoveRecursively(short_path!("/z/removing", 1)),
+            remove_marker(2),
+            FileExists(short_path!("/z/removing", 2)),
+            Rename(long_path!("/z/sha256", 2), short_path!("/z/removing", 2)),
+            RemoveRecursively(short_path!("/z/removing", 2)),
+            FileExists(long_path!("/z/files", 10)),
         ]);
     }
 }
@@ -1,6 +1,7 @@
 use bytesize::ByteSize;
 use derive_more::From;
-use maelstrom_util::config::{BrokerAddr, CacheBytesUsedTarget, CacheRoot, LogLevel};
+use maelstrom_base::proto::ArtifactCompression;
+use maelstrom_util::config::{BrokerAddr, CacheBytesUsedTarget, CacheRoot, LogFormat, LogLevel};
 use serde::{Deserialize, Serialize};
 use serde_with::skip_serializing_none;
 use std::{
@@ -79,6 +80,56 @@ pub struct Config {
 
     /// Minimum log level to output.
     pub log_level: LogLevel,
+
+    /// The format to write log messages in.
+    pub log_format: LogFormat,
+
+    /// Host paths that jobs may bind mount. A job may bind mount any of these paths, or anything
+    /// under them.
+    pub allowed_bind_mounts: Vec<PathBuf>,
+
+    /// The maximum size in bytes of the tmpfs that holds the writable layer of jobs that have
+    /// `enable_writable_file_system` set. Jobs that write more than this get `ENOSPC`. If not
+    /// given, the kernel's default of half of RAM is used.
+    pub writable_file_system_size: Option<u64>,
+
+    /// The slirp4netns program used to give jobs NAT networking. If it isn't a path, it's looked
+    /// up in `PATH`.
+    pub slirp4netns: PathBuf,
+
+    /// Whether to map the worker user's subordinate uid and gid ranges, from `/etc/subuid` and
+    /// `/etc/subgid`, into jobs' user namespaces. This lets jobs switch users and keeps the
+    /// ownership of files in image layers. If the ranges can't be mapped, jobs only get their one
+    /// uid and gid, as if this were off.
+    pub subordinate_ids: bool,
+
+    /// The program used to collect userspace backtraces of jobs that time out and ask for a stack
+    /// dump. It's run as `<program> -p <pid>` for each of the job's processes, and should print
+    /// the backtraces of all of the process's threads, like `eu-stack` from elfutils does. If it
+    /// isn't a path, it's looked up in `PATH`. If not given, jobs don't get stack dumps. The worker
+    /// mounts its own `/proc` only when this is given.
+    pub stack_dumper: Option<PathBuf>,
+
+    /// How to compress artifacts fetched from the broker. Compression is worth it when the link
+    /// to the broker is slow.
+    pub artifact_compression: ArtifactCompression,
+
+    /// The port to serve stored artifacts to other workers on. If 0, a port is chosen by the
+    /// operating system. Other workers must be able to connect to this port at the address the
+    /// broker sees this worker connect from. If not given, artifacts aren't served to other
+    /// workers, and this worker fetches all of its artifacts from the broker.
+    pub peer_port: Option<u16>,
+
+    /// The port to serve the worker's metrics on over HTTP, for Prometheus to scrape. If not
+    /// given, metrics aren't served.
+    pub metrics_port: Option<u16>,
+
+    /// A file to append a trace of each job to, in the OTLP JSON encoding.
+    pub trace_file: Option<PathBuf>,
+
+    /// The URL of an OTLP collector to send a trace of each job to. Only `http://` and `https://`
+    /// URLs are supported.
+    pub otlp_endpoint: Option<String>,
 }
 
 #[skip_serializing_none]
@@ -90,6 +141,17 @@ pub struct ConfigOptions {
     pub cache_bytes_used_target: Option<u64>,
     pub inline_limit: Option<u64>,
     pub log_level: Option<LogLevel>,
+    pub log_format: Option<LogFormat>,
+    pub allowed_bind_mounts: Option<Vec<PathBuf>>,
+    pub writable_file_system_size: Option<u64>,
+    pub slirp4netns: Option<PathBuf>,
+    pub subordinate_ids: Option<bool>,
+    pub stack_dumper: Option<PathBuf>,
+    pub artifact_compression: Option<ArtifactCompression>,
+    pub peer_port: Option<u16>,
+    pub metrics_port: Option<u16>,
+    pub trace_file: Option<PathBuf>,
+    pub otlp_endpoint: Option<String>,
 }
 
 impl Default for ConfigOptions {
@@ -101,6 +163,17 @@ impl Default for ConfigOptions {
             cache_bytes_used_target: Some(1_000_000_000),
             inline_limit: Some(1_000_000),
             log_level: Some(LogLevel::Info),
+            log_format: Some(LogFormat::Text),
+            allowed_bind_mounts: Some(vec![]),
+            writable_file_system_size: None,
+            slirp4netns: Some("slirp4netns".into()),
+            subordinate_ids: Some(false),
+            stack_dumper: None,
+            artifact_compression: Some(ArtifactCompression::Zstd),
+            peer_port: None,
+            metrics_port: None,
+            trace_file: None,
+            otlp_endpoint: None,
         }
     }
 }
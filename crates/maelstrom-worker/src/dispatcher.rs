@@ -4,23 +4,25 @@
 mod tracker;
 
 use crate::{
-    cache::{Cache, CacheFs, GetArtifact},
+    cache::{ArtifactSize, Cache, CacheFs, CacheMetrics, GetArtifact},
     config::Slots,
+    seccomp,
 };
 use anyhow::{Error, Result};
 use maelstrom_base::{
     proto::{BrokerToWorker, WorkerToBroker},
-    ArtifactType, JobEffects, JobError, JobId, JobOutcome, JobOutputResult, JobResult, JobSpec,
-    JobStatus, NonEmpty, Sha256Digest,
+    ArtifactType, JobEffects, JobError, JobId, JobOutcome, JobOutputResult, JobResult, JobSignal,
+    JobSpec, JobStatus, NonEmpty, Sha256Digest,
 };
-use maelstrom_linux::Pid;
-use maelstrom_util::ext::OptionExt as _;
+use maelstrom_linux::{Pid, Signal};
+use maelstrom_util::{ext::OptionExt as _, metrics::Histogram, trace::JobEvent};
 use std::{
     collections::{hash_map::Entry, HashMap, HashSet, VecDeque},
     mem,
     path::PathBuf,
     result::Result as StdResult,
-    time::Duration,
+    sync::mpsc::Sender,
+    time::{Duration, Instant},
 };
 use tracker::{FetcherResult, LayerTracker};
 
@@ -40,7 +42,8 @@ pub trait DispatcherDeps {
     /// separate messages to know when the job has completed: [`Message::PidStatus`],
     /// [`Message::JobStdout`], and [`Message::JobStderr`]. These messages don't have to come in
     /// any particular order, but the dispatcher won't proceed with the next job until all three
-    /// have arrived.
+    /// have arrived. If the job fails to finish starting up after this returns, a
+    /// [`Message::JobStartupFailed`] message must be sent before the [`Message::JobStdout`].
     fn start_job(
         &mut self,
         jid: JobId,
@@ -52,6 +55,18 @@ pub trait DispatcherDeps {
     /// resilient in the case where the process has already completed.
     fn kill_job(&mut self, pid: Pid);
 
+    /// Send a running job its stop signal using the [`Pid`] obtained from [`JobResult::Ok`]. Like
+    /// [`kill_job`], this must be resilient in the case where the process has already completed.
+    fn signal_job(&mut self, pid: Pid, signal: Signal);
+
+    /// Start collecting a stack dump of a running job using the [`Pid`] obtained from
+    /// [`JobResult::Ok`]. When it's done, this must send a [`Message::JobStackDump`] message, even
+    /// if the process has already completed.
+    fn start_stack_dump(&mut self, jid: JobId, pid: Pid);
+
+    /// Return the current time. This is used to measure how long jobs run.
+    fn now(&mut self) -> Instant;
+
     /// A handle used to cancel a timer.
     type TimerHandle;
 
@@ -67,8 +82,17 @@ pub trait DispatcherDeps {
     /// Send a message to the broker.
     fn send_message_to_broker(&mut self, message: WorkerToBroker);
 
+    /// Tell the broker that the files `digests` have been removed from the cache's file store, so
+    /// that other workers aren't sent here for them anymore.
+    fn report_removed_files(&mut self, digests: Vec<Sha256Digest>);
+
     /// Start a thread that will download an artifact from the broker and extract it into `path`.
+    /// Files in manifest artifacts are taken from, or fetched into, the cache's file store, as are
+    /// tars if the worker serves artifacts to other workers.
     fn start_artifact_fetch(&mut self, digest: Sha256Digest, type_: ArtifactType, path: PathBuf);
+
+    /// Note that `event` has just happened to the job `jid`. This is used for logging and tracing.
+    fn job_event(&mut self, jid: JobId, event: JobEvent);
 }
 
 /// The [`Cache`] dependency for [`Dispatcher`]. This should be exactly the same as [`Cache`]'s
@@ -79,9 +103,11 @@ pub trait DispatcherCache {
     fn got_artifact_success(
         &mut self,
         digest: &Sha256Digest,
-        bytes_used: u64,
+        size: ArtifactSize,
     ) -> (PathBuf, Vec<JobId>);
     fn decrement_ref_count(&mut self, digest: &Sha256Digest);
+    fn take_removed_files(&mut self) -> Vec<Sha256Digest>;
+    fn metrics(&self) -> CacheMetrics;
 }
 
 /// The standard implementation of [`DispatcherCache`] that just calls into [`Cache`].
@@ -97,26 +123,62 @@ impl<FsT: CacheFs> DispatcherCache for Cache<FsT> {
     fn got_artifact_success(
         &mut self,
         digest: &Sha256Digest,
-        bytes_used: u64,
+        size: ArtifactSize,
     ) -> (PathBuf, Vec<JobId>) {
-        self.got_artifact_success(digest, bytes_used)
+        self.got_artifact_success(digest, size)
     }
 
     fn decrement_ref_count(&mut self, digest: &Sha256Digest) {
         self.decrement_ref_count(digest)
     }
+
+    fn take_removed_files(&mut self) -> Vec<Sha256Digest> {
+        self.take_removed_files()
+    }
+
+    fn metrics(&self) -> CacheMetrics {
+        self.metrics()
+    }
+}
+
+/// A snapshot of the dispatcher's state, and counts of what it has done since the worker started.
+/// This is what the worker's metrics endpoint serves.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DispatcherMetrics {
+    pub slots: usize,
+    /// The number of jobs waiting for their artifacts to be fetched.
+    pub awaiting_layers: usize,
+    /// The number of jobs that have their artifacts and are waiting for a free slot.
+    pub queued: usize,
+    /// The number of jobs that have been started and haven't yet terminated.
+    pub executing: usize,
+    /// The number of jobs that have terminated, including those that were canceled or timed out.
+    pub jobs_completed: u64,
+    /// How long jobs ran, from being started until terminating.
+    pub job_run_seconds: Histogram,
+    /// The number of artifact fetches started, and how many of those failed.
+    pub artifact_fetches: u64,
+    pub artifact_fetch_failures: u64,
+    /// The bytes used on disk by artifacts that were successfully fetched.
+    pub artifact_fetched_bytes: u64,
+    pub cache: CacheMetrics,
 }
 
 /// An input message for the dispatcher. These come from the broker, an executor, or an artifact
 /// fetcher.
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug)]
 pub enum Message {
     Broker(BrokerToWorker),
     PidStatus(Pid, JobStatus),
     JobStdout(JobId, StdResult<JobOutputResult, String>),
     JobStderr(JobId, StdResult<JobOutputResult, String>),
+    JobStartupFailed(JobId, JobError<String>),
     JobTimer(JobId),
-    ArtifactFetcher(Sha256Digest, Result<u64>),
+    JobStackDump(JobId, String),
+    ArtifactFetcher(Sha256Digest, Result<ArtifactSize>),
+    /// A request for the dispatcher's metrics, which are sent back on the given channel.
+    MetricsRequest(Sender<DispatcherMetrics>),
 }
 
 impl<DepsT: DispatcherDeps, CacheT: DispatcherCache> Dispatcher<DepsT, CacheT> {
@@ -131,6 +193,11 @@ impl<DepsT: DispatcherDeps, CacheT: DispatcherCache> Dispatcher<DepsT, CacheT> {
             queued: VecDeque::default(),
             executing: HashMap::default(),
             executing_pids: HashMap::default(),
+            jobs_completed: 0,
+            job_run_seconds: Histogram::default(),
+            artifact_fetches: 0,
+            artifact_fetch_failures: 0,
+            artifact_fetched_bytes: 0,
         }
     }
 
@@ -145,13 +212,20 @@ impl<DepsT: DispatcherDeps, CacheT: DispatcherCache> Dispatcher<DepsT, CacheT> {
             Message::PidStatus(pid, status) => self.receive_pid_status(pid, status),
             Message::JobStdout(jid, result) => self.receive_job_stdout(jid, result),
             Message::JobStderr(jid, result) => self.receive_job_stderr(jid, result),
+            Message::JobStartupFailed(jid, err) => self.receive_job_startup_failed(jid, err),
             Message::JobTimer(jid) => self.receive_job_timer(jid),
+            Message::JobStackDump(jid, dump) => self.receive_job_stack_dump(jid, dump),
             Message::ArtifactFetcher(digest, Err(err)) => {
                 self.receive_artifact_failure(digest, err)
             }
-            Message::ArtifactFetcher(digest, Ok(bytes_used)) => {
-                self.receive_artifact_success(digest, bytes_used)
+            Message::ArtifactFetcher(digest, Ok(size)) => {
+                self.receive_artifact_success(digest, size)
             }
+            Message::MetricsRequest(sender) => self.receive_metrics_request(sender),
+        }
+        let removed_files = self.cache.take_removed_files();
+        if !removed_files.is_empty() {
+            self.deps.report_removed_files(removed_files);
         }
     }
 }
@@ -183,30 +257,118 @@ enum ExecutingJobState<DepsT: DispatcherDeps> {
         timer: Option<DepsT::TimerHandle>,
     },
     Canceled,
+    // The job timed out and we're waiting for its stack dump before stopping it.
+    DumpingStack {
+        pid: Pid,
+    },
+    // The job was canceled and was sent its stop signal. `timer` is for its grace period.
+    CanceledStopping {
+        pid: Pid,
+        timer: DepsT::TimerHandle,
+    },
+    // The job timed out and was sent its stop signal. `timer` is for its grace period.
+    Stopping {
+        pid: Pid,
+        timer: DepsT::TimerHandle,
+    },
+    // The job timed out and was killed, or it had already terminated.
     TimedOut,
 }
 
 struct ExecutingJob<DepsT: DispatcherDeps> {
     state: ExecutingJobState<DepsT>,
     status: Option<JobStatus>,
+    // When the job was started, and how long it ran, which is known once we have its status.
+    start: Instant,
+    duration: Option<Duration>,
     stdout: Option<StdResult<JobOutputResult, String>>,
     stderr: Option<StdResult<JobOutputResult, String>>,
+    // Set if the job was killed because it failed to finish starting up.
+    startup_error: Option<JobError<String>>,
     digests: HashSet<Sha256Digest>,
+    // Whether the job's seccomp profile can kill it. If so, a job killed by SIGSYS was killed by
+    // the profile.
+    seccomp_may_kill: bool,
+    // The job's stop signal and grace period. If this is `None`, the job is killed right away.
+    stop: Option<(Signal, Duration)>,
+    // Whether to collect a stack dump of the job before stopping it when it times out.
+    stack_dump_on_timeout: bool,
+    stack_dump: Option<String>,
 }
 
 impl<DepsT: DispatcherDeps> ExecutingJob<DepsT> {
-    fn new(pid: Pid, digests: HashSet<Sha256Digest>, timer: Option<DepsT::TimerHandle>) -> Self {
+    fn new(
+        pid: Pid,
+        start: Instant,
+        digests: HashSet<Sha256Digest>,
+        timer: Option<DepsT::TimerHandle>,
+        seccomp_may_kill: bool,
+        stop: Option<(Signal, Duration)>,
+        stack_dump_on_timeout: bool,
+    ) -> Self {
         ExecutingJob {
             state: ExecutingJobState::Ok { pid, timer },
             status: None,
+            start,
+            duration: None,
             stdout: None,
             stderr: None,
+            startup_error: None,
             digests,
+            seccomp_may_kill,
+            stop,
+            stack_dump_on_timeout,
+            stack_dump: None,
         }
     }
 
     fn is_complete(&self) -> bool {
-        self.status.is_some() && self.stdout.is_some() && self.stderr.is_some()
+        self.status.is_some()
+            && self.stdout.is_some()
+            && self.stderr.is_some()
+            && !matches!(self.state, ExecutingJobState::DumpingStack { .. })
+    }
+}
+
+/// How long a job has to terminate after being sent its stop signal, if its spec doesn't say.
+const DEFAULT_GRACE_PERIOD: Duration = Duration::from_secs(10);
+
+fn stop_signal_and_grace_period(spec: &JobSpec) -> Option<(Signal, Duration)> {
+    if spec.grace_period == Some(0) {
+        return None;
+    }
+    let signal = match spec.stop_signal? {
+        JobSignal::Sighup => Signal::HUP,
+        JobSignal::Sigint => Signal::INT,
+        JobSignal::Sigquit => Signal::QUIT,
+        JobSignal::Sigkill => return None,
+        JobSignal::Sigusr1 => Signal::USR1,
+        JobSignal::Sigusr2 => Signal::USR2,
+        JobSignal::Sigterm => Signal::TERM,
+    };
+    let grace_period = spec.grace_period.map_or(DEFAULT_GRACE_PERIOD, |seconds| {
+        Duration::from_secs(seconds.into())
+    });
+    Some((signal, grace_period))
+}
+
+/// Ask a job to stop. If it has a stop signal, send it and start a timer for its grace period.
+/// Otherwise, kill it.
+fn stop_job<DepsT: DispatcherDeps>(
+    deps: &mut DepsT,
+    jid: JobId,
+    pid: Pid,
+    stop: Option<(Signal, Duration)>,
+) -> Option<DepsT::TimerHandle> {
+    match stop {
+        Some((signal, grace_period)) => {
+            deps.signal_job(pid, signal);
+            Some(deps.start_timer(jid, grace_period))
+        }
+        None => {
+            deps.kill_job(pid);
+            None
+        }
     }
 }
 
@@ -223,6 +385,11 @@ pub struct Dispatcher<DepsT: DispatcherDeps, CacheT> {
     queued: VecDeque<QueuedEntry>,
     executing: HashMap<JobId, ExecutingJob<DepsT>>,
     executing_pids: HashMap<Pid, JobId>,
+    jobs_completed: u64,
+    job_run_seconds: Histogram,
+    artifact_fetches: u64,
+    artifact_fetch_failures: u64,
+    artifact_fetched_bytes: u64,
 }
 
 impl<DepsT: DispatcherDeps, CacheT: DispatcherCache> Dispatcher<DepsT, CacheT> {
@@ -235,12 +402,21 @@ impl<DepsT: DispatcherDeps, CacheT: DispatcherCache> Dispatcher<DepsT, CacheT> {
                 digests,
             } = self.queued.pop_front().unwrap();
             let timeout = spec.timeout;
+            let seccomp_may_kill = spec.seccomp.as_ref().is_some_and(seccomp::may_kill);
+            let stop = stop_signal_and_grace_period(&spec);
+            let stack_dump_on_timeout = spec.stack_dump_on_timeout;
             match self.deps.start_job(jid, spec, paths) {
                 Ok(pid) => {
+                    self.deps.job_event(jid, JobEvent::Started);
+                    let start = self.deps.now();
                     let executing_job = ExecutingJob::new(
                         pid,
+                        start,
                         digests,
                         timeout.map(|timeout| self.deps.start_timer(jid, Duration::from(timeout))),
+                        seccomp_may_kill,
+                        stop,
+                        stack_dump_on_timeout,
                     );
                     self.executing.insert(jid, executing_job).assert_is_none();
                     self.executing_pids.insert(pid, jid).assert_is_none();
@@ -251,12 +427,14 @@ impl<DepsT: DispatcherDeps, CacheT: DispatcherCache> Dispatcher<DepsT, CacheT> {
                     }
                     self.deps
                         .send_message_to_broker(WorkerToBroker(jid, Err(e)));
+                    self.deps.job_event(jid, JobEvent::Completed);
                 }
             }
         }
     }
 
     fn enqueue_job_with_all_layers(&mut self, jid: JobId, spec: JobSpec, tracker: LayerTracker) {
+        self.deps.job_event(jid, JobEvent::ArtifactsReady);
         let (paths, digests) = tracker.into_paths_and_digests();
         self.queued.push_back(QueuedEntry {
             jid,
@@ -268,11 +446,13 @@ impl<DepsT: DispatcherDeps, CacheT: DispatcherCache> Dispatcher<DepsT, CacheT> {
     }
 
     fn receive_enqueue_job(&mut self, jid: JobId, spec: JobSpec) {
+        self.deps.job_event(jid, JobEvent::Enqueued);
         let tracker = LayerTracker::new(&spec.layers, |digest, type_| -> FetcherResult {
             match self.cache.get_artifact(digest.clone(), jid) {
                 GetArtifact::Success(path) => FetcherResult::Got(path),
                 GetArtifact::Wait => FetcherResult::Pending,
                 GetArtifact::Get(path) => {
+                    self.artifact_fetches += 1;
                     self.deps.start_artifact_fetch(digest.clone(), type_, path);
                     FetcherResult::Pending
                 }
@@ -293,19 +473,43 @@ impl<DepsT: DispatcherDeps, CacheT: DispatcherCache> Dispatcher<DepsT, CacheT> {
             for digest in entry.tracker.into_digests() {
                 self.cache.decrement_ref_count(&digest);
             }
-        } else if let Some(&mut ExecutingJob { ref mut state, .. }) = self.executing.get_mut(&jid) {
-            // The job was executing. We kill the job and cancel a timer if there is one, but we
+            self.deps.job_event(jid, JobEvent::Canceled);
+        } else if let Some(&mut ExecutingJob {
+            ref mut state,
+            stop,
+            ..
+        }) = self.executing.get_mut(&jid)
+        {
+            // The job was executing. We stop the job and cancel a timer if there is one, but we
             // wait around until it's actually teriminated. We don't want to release the layers
             // until then. If we didn't it would be possible for us to try to remove a directory
             // that was still in use, which would fail.
-            if let ExecutingJobState::Ok { pid, timer } =
-                mem::replace(state, ExecutingJobState::Canceled)
-            {
-                self.deps.kill_job(pid);
-                if let Some(handle) = timer {
-                    self.deps.cancel_timer(handle)
+            *state = match mem::replace(state, ExecutingJobState::Canceled) {
+                ExecutingJobState::Ok { pid, timer } => {
+                    if let Some(handle) = timer {
+                        self.deps.cancel_timer(handle)
+                    }
+                    match stop_job(&mut self.deps, jid, pid, stop) {
+                        Some(timer) => ExecutingJobState::CanceledStopping { pid, timer },
+                        None => ExecutingJobState::Canceled,
+                    }
                 }
-            }
+                // Nobody will see the stack dump now, so we stop the job without waiting for it.
+                ExecutingJobState::DumpingStack { pid } => {
+                    match stop_job(&mut self.deps, jid, pid, stop) {
+                        Some(timer) => ExecutingJobState::CanceledStopping { pid, timer },
+                        None => ExecutingJobState::Canceled,
+                    }
+                }
+                // The job has already been sent its stop signal. Its grace period continues.
+                ExecutingJobState::Stopping { pid, timer }
+                | ExecutingJobState::CanceledStopping { pid, timer } => {
+                    ExecutingJobState::CanceledStopping { pid, timer }
+                }
+                ExecutingJobState::Canceled | ExecutingJobState::TimedOut => {
+                    ExecutingJobState::Canceled
+                }
+            };
         } else {
             // It may be the queue.
             self.queued.retain_mut(|entry| {
@@ -315,6 +519,7 @@ impl<DepsT: DispatcherDeps, CacheT: DispatcherCache> Dispatcher<DepsT, CacheT> {
                     for digest in &entry.digests {
                         self.cache.decrement_ref_count(digest);
                     }
+                    self.deps.job_event(jid, JobEvent::Canceled);
                     false
                 }
             });
@@ -336,28 +541,81 @@ impl<DepsT: DispatcherDeps, CacheT: DispatcherCache> Dispatcher<DepsT, CacheT> {
                 ExecutingJob {
                     state,
                     status,
+                    start,
+                    duration,
                     stdout,
                     stderr,
+                    startup_error,
                     digests,
+                    seccomp_may_kill,
+                    stop: _,
+                    stack_dump_on_timeout: _,
+                    stack_dump,
                 },
             ) = oe.remove_entry();
-            let effects_result = match (stdout.unwrap(), stderr.unwrap()) {
-                (StdResult::Ok(stdout), StdResult::Ok(stderr)) => Ok(JobEffects { stdout, stderr }),
-                (StdResult::Err(e), _) | (_, StdResult::Err(e)) => Err(JobError::System(e)),
+            // The duration is recorded along with the job's status, so we should always have it.
+            // Just in case, fall back to measuring it now.
+            let duration =
+                duration.unwrap_or_else(|| self.deps.now().saturating_duration_since(start));
+            self.jobs_completed += 1;
+            self.job_run_seconds.observe(duration.as_secs_f64());
+            let diagnostics = stack_dump.map_or(JobOutputResult::None, |dump| {
+                JobOutputResult::Inline(dump.into_bytes().into_boxed_slice())
+            });
+            let effects_result = match (startup_error, stdout.unwrap(), stderr.unwrap()) {
+                (Some(e), _, _) => Err(e),
+                (None, StdResult::Ok(stdout), StdResult::Ok(stderr)) => Ok(JobEffects {
+                    stdout,
+                    stderr,
+                    diagnostics,
+                    duration,
+                }),
+                (None, StdResult::Err(e), _) | (None, _, StdResult::Err(e)) => {
+                    Err(JobError::System(e))
+                }
             };
 
+            let event = match state {
+                ExecutingJobState::Canceled | ExecutingJobState::CanceledStopping { .. } => {
+                    JobEvent::Canceled
+                }
+                _ => JobEvent::Completed,
+            };
             match state {
                 ExecutingJobState::Ok { pid: _, timer } => {
                     if let Some(handle) = timer {
                         self.deps.cancel_timer(handle)
                     }
                     let status = status.unwrap();
+                    let result = if seccomp_may_kill
+                        && status == JobStatus::Signaled(Signal::SYS.as_u8())
+                    {
+                        Err(JobError::Execution(
+                            "job was killed by its seccomp profile for making a forbidden system call"
+                                .into(),
+                        ))
+                    } else {
+                        effects_result.map(|effects| JobOutcome::Completed { status, effects })
+                    };
+                    self.deps
+                        .send_message_to_broker(WorkerToBroker(jid, result));
+                }
+                ExecutingJobState::Canceled => {}
+                ExecutingJobState::DumpingStack { .. } => {
+                    unreachable!("job {jid:?} completed while dumping its stack");
+                }
+                ExecutingJobState::CanceledStopping { pid: _, timer } => {
+                    self.deps.cancel_timer(timer);
+                }
+                ExecutingJobState::Stopping { pid: _, timer } => {
+                    self.deps.cancel_timer(timer);
+                    let status = status.unwrap();
                     self.deps.send_message_to_broker(WorkerToBroker(
                         jid,
-                        effects_result.map(|effects| JobOutcome::Completed { status, effects }),
+                        effects_result
+                            .map(|effects| JobOutcome::TimedOutGracefully { status, effects }),
                     ));
                 }
-                ExecutingJobState::Canceled => {}
                 ExecutingJobState::TimedOut => {
                     self.deps.send_message_to_broker(WorkerToBroker(
                         jid,
@@ -365,6 +623,7 @@ impl<DepsT: DispatcherDeps, CacheT: DispatcherCache> Dispatcher<DepsT, CacheT> {
                     ));
                 }
             }
+            self.deps.job_event(jid, event);
             for digest in digests {
                 self.cache.decrement_ref_count(&digest);
             }
@@ -374,8 +633,10 @@ impl<DepsT: DispatcherDeps, CacheT: DispatcherCache> Dispatcher<DepsT, CacheT> {
 
     fn receive_pid_status(&mut self, pid: Pid, status: JobStatus) {
         if let Some(jid) = self.executing_pids.remove(&pid) {
+            let now = self.deps.now();
             self.update_entry_and_potentially_finish_job(jid, move |entry| {
-                entry.status = Some(status)
+                entry.status = Some(status);
+                entry.duration = Some(now.saturating_duration_since(entry.start));
             });
         }
     }
@@ -388,33 +649,90 @@ impl<DepsT: DispatcherDeps, CacheT: DispatcherCache> Dispatcher<DepsT, CacheT> {
         self.update_entry_and_potentially_finish_job(jid, move |entry| entry.stderr = Some(result));
     }
 
+    fn receive_job_startup_failed(&mut self, jid: JobId, err: JobError<String>) {
+        self.update_entry_and_potentially_finish_job(jid, move |entry| {
+            entry.startup_error = Some(err)
+        });
+    }
+
     fn receive_job_timer(&mut self, jid: JobId) {
         if let Some(&mut ExecutingJob {
             ref mut state,
             ref status,
+            stop,
+            stack_dump_on_timeout,
             ..
         }) = self.executing.get_mut(&jid)
         {
-            // The job was executing. We kill the job, but we wait around until it's actually
+            // The job was executing. We stop the job, but we wait around until it's actually
             // teriminated. We don't want to release the layers until the job has terminated. If we
             // didn't it would be possible for us to try to remove a directory that was still in
             // use, which would fail.
-            match state {
+            *state = match mem::replace(state, ExecutingJobState::TimedOut) {
+                ExecutingJobState::Ok { pid, timer: _ }
+                    if status.is_none() && stack_dump_on_timeout =>
+                {
+                    // We stop the job once we have its stack dump.
+                    self.deps.start_stack_dump(jid, pid);
+                    ExecutingJobState::DumpingStack { pid }
+                }
                 ExecutingJobState::Ok { pid, timer: _ } => {
+                    match status
+                        .is_none()
+                        .then(|| stop_job(&mut self.deps, jid, pid, stop))
+                    {
+                        Some(Some(timer)) => ExecutingJobState::Stopping { pid, timer },
+                        _ => ExecutingJobState::TimedOut,
+                    }
+                }
+                ExecutingJobState::Stopping { pid, timer } => {
+                    // The grace period is over. If the job hasn't terminated yet, we kill it.
                     if status.is_none() {
-                        self.deps.kill_job(*pid);
+                        self.deps.kill_job(pid);
+                        ExecutingJobState::TimedOut
+                    } else {
+                        ExecutingJobState::Stopping { pid, timer }
                     }
-                    *state = ExecutingJobState::TimedOut;
                 }
-                ExecutingJobState::TimedOut => {
+                ExecutingJobState::CanceledStopping { pid, timer: _ } => {
+                    // This may be a timeout timer that fired before we could cancel it, in which
+                    // case we kill the job before its grace period is over. It's been canceled, so
+                    // nobody will see the difference.
+                    if status.is_none() {
+                        self.deps.kill_job(pid);
+                    }
+                    ExecutingJobState::Canceled
+                }
+                ExecutingJobState::TimedOut | ExecutingJobState::DumpingStack { .. } => {
                     panic!("two timer expirations for job {jid:?}");
                 }
-                ExecutingJobState::Canceled => {}
-            }
+                ExecutingJobState::Canceled => ExecutingJobState::Canceled,
+            };
         }
     }
 
+    fn receive_job_stack_dump(&mut self, jid: JobId, dump: String) {
+        // If the job was canceled while we were collecting the dump, there's nothing to do.
+        let Some(job) = self.executing.get_mut(&jid) else {
+            return;
+        };
+        let ExecutingJobState::DumpingStack { pid } = job.state else {
+            return;
+        };
+        job.stack_dump = Some(dump);
+        job.state = match job
+            .status
+            .is_none()
+            .then(|| stop_job(&mut self.deps, jid, pid, job.stop))
+        {
+            Some(Some(timer)) => ExecutingJobState::Stopping { pid, timer },
+            _ => ExecutingJobState::TimedOut,
+        };
+        self.update_entry_and_potentially_finish_job(jid, |_| {});
+    }
+
     fn receive_artifact_failure(&mut self, digest: Sha256Digest, err: Error) {
+        self.artifact_fetch_failures += 1;
         for jid in self.cache.got_artifact_failure(&digest) {
             if let Some(entry) = self.awaiting_layers.remove(&jid) {
                 // If this was the first layer error for this request, then we'll find something in the
@@ -428,6 +746,7 @@ impl<DepsT: DispatcherDeps, CacheT: DispatcherCache> Dispatcher<DepsT, CacheT> {
                         "Failed to download and extract layer artifact {digest}: {err}"
                     ))),
                 ));
+                self.deps.job_event(jid, JobEvent::Completed);
                 for digest in entry.tracker.into_digests() {
                     self.cache.decrement_ref_count(&digest);
                 }
@@ -435,8 +754,14 @@ impl<DepsT: DispatcherDeps, CacheT: DispatcherCache> Dispatcher<DepsT, CacheT> {
         }
     }
 
-    fn receive_artifact_success(&mut self, digest: Sha256Digest, bytes_used: u64) {
-        let (path, jobs) = self.cache.got_artifact_success(&digest, bytes_used);
+    fn receive_artifact_success(&mut self, digest: Sha256Digest, size: ArtifactSize) {
+        self.artifact_fetched_bytes += size.bytes_used
+            + size
+                .files
+                .iter()
+                .map(|(_, file_bytes_used)| file_bytes_used)
+                .sum::<u64>();
+        let (path, jobs) = self.cache.got_artifact_success(&digest, size);
         for jid in jobs {
             match self.awaiting_layers.entry(jid) {
                 Entry::Vacant(_) => {
@@ -456,6 +781,23 @@ impl<DepsT: DispatcherDeps, CacheT: DispatcherCache> Dispatcher<DepsT, CacheT> {
             }
         }
     }
+
+    fn receive_metrics_request(&mut self, sender: Sender<DispatcherMetrics>) {
+        sender
+            .send(DispatcherMetrics {
+                slots: self.slots,
+                awaiting_layers: self.awaiting_layers.len(),
+                queued: self.queued.len(),
+                executing: self.executing.len(),
+                jobs_completed: self.jobs_completed,
+                job_run_seconds: self.job_run_seconds.clone(),
+                artifact_fetches: self.artifact_fetches,
+                artifact_fetch_failures: self.artifact_fetch_failures,
+                artifact_fetched_bytes: self.artifact_fetched_bytes,
+                cache: self.cache.metrics(),
+            })
+            .ok();
+    }
 }
 
 /*  _            _
@@ -471,20 +813,25 @@ mod tests {
     use super::{Message::*, *};
     use anyhow::anyhow;
     use itertools::Itertools;
+    use maelstrom_base::JobSeccomp;
     use maelstrom_test::*;
     use std::{cell::RefCell, rc::Rc, time::Duration};
     use BrokerToWorker::*;
 
+    #[allow(clippy::large_enum_variant)]
     #[derive(Clone, Debug, PartialEq)]
     enum TestMessage {
         StartJob(JobId, JobSpec, Vec<PathBuf>),
         SendMessageToBroker(WorkerToBroker),
+        ReportRemovedFiles(Vec<Sha256Digest>),
         StartArtifactFetch(Sha256Digest, ArtifactType, PathBuf),
         CacheGetArtifact(Sha256Digest, JobId),
-        CacheGotArtifactSuccess(Sha256Digest, u64),
+        CacheGotArtifactSuccess(Sha256Digest, ArtifactSize),
         CacheGotArtifactFailure(Sha256Digest),
         CacheDecrementRefCount(Sha256Digest),
         Kill(Pid),
+        SignalJob(Pid, Signal),
+        StartStackDump(JobId, Pid),
         StartTimer(JobId, Duration),
         CancelTimer(JobId),
     }
@@ -493,10 +840,14 @@ mod tests {
 
     struct TestState {
         messages: Vec<TestMessage>,
+        now: Instant,
         start_job_returns: Vec<JobResult<Pid, String>>,
         get_artifact_returns: HashMap<Sha256Digest, GetArtifact>,
         got_artifact_success_returns: HashMap<Sha256Digest, (PathBuf, Vec<JobId>)>,
         got_artifact_failure_returns: HashMap<Sha256Digest, Vec<JobId>>,
+        cache_metrics: CacheMetrics,
+        removed_files: Vec<Sha256Digest>,
+        job_events: Vec<(JobId, JobEvent)>,
     }
 
     impl DispatcherDeps for Rc<RefCell<TestState>> {
@@ -517,6 +868,18 @@ mod tests {
             self.borrow_mut().messages.push(Kill(pid));
         }
 
+        fn signal_job(&mut self, pid: Pid, signal: Signal) {
+            self.borrow_mut().messages.push(SignalJob(pid, signal));
+        }
+
+        fn start_stack_dump(&mut self, jid: JobId, pid: Pid) {
+            self.borrow_mut().messages.push(StartStackDump(jid, pid));
+        }
+
+        fn now(&mut self) -> Instant {
+            self.borrow().now
+        }
+
         type TimerHandle = JobId;
 
         fn start_timer(&mut self, jid: JobId, duration: Duration) -> Self::TimerHandle {
@@ -539,11 +902,19 @@ mod tests {
                 .push(StartArtifactFetch(digest, type_, path));
         }
 
+        fn job_event(&mut self, jid: JobId, event: JobEvent) {
+            self.borrow_mut().job_events.push((jid, event));
+        }
+
         fn send_message_to_broker(&mut self, message: WorkerToBroker) {
             self.borrow_mut()
                 .messages
                 .push(SendMessageToBroker(message));
         }
+
+        fn report_removed_files(&mut self, digests: Vec<Sha256Digest>) {
+            self.borrow_mut().messages.push(ReportRemovedFiles(digests));
+        }
     }
 
     impl DispatcherCache for Rc<RefCell<TestState>> {
@@ -570,11 +941,11 @@ mod tests {
         fn got_artifact_success(
             &mut self,
             digest: &Sha256Digest,
-            bytes_used: u64,
+            size: ArtifactSize,
         ) -> (PathBuf, Vec<JobId>) {
             self.borrow_mut()
                 .messages
-                .push(CacheGotArtifactSuccess(digest.clone(), bytes_used));
+                .push(CacheGotArtifactSuccess(digest.clone(), size));
             self.borrow_mut()
                 .got_artifact_success_returns
                 .remove(digest)
@@ -586,6 +957,14 @@ mod tests {
                 .messages
                 .push(CacheDecrementRefCount(digest.clone()))
         }
+
+        fn take_removed_files(&mut self) -> Vec<Sha256Digest> {
+            mem::take(&mut self.borrow_mut().removed_files)
+        }
+
+        fn metrics(&self) -> CacheMetrics {
+            self.borrow().cache_metrics.clone()
+        }
     }
 
     struct Fixture {
@@ -603,10 +982,14 @@ mod tests {
         ) -> Self {
             let test_state = Rc::new(RefCell::new(TestState {
                 messages: Vec::default(),
+                now: Instant::now(),
                 start_job_returns: Vec::from(start_job_returns),
                 get_artifact_returns: HashMap::from(get_artifact_returns),
                 got_artifact_success_returns: HashMap::from(got_artifact_success_returns),
                 got_artifact_failure_returns: HashMap::from(got_artifact_failure_returns),
+                cache_metrics: CacheMetrics::default(),
+                removed_files: Vec::default(),
+                job_events: Vec::default(),
             }));
             let dispatcher = Dispatcher::new(
                 test_state.clone(),
@@ -1025,6 +1408,22 @@ mod tests {
         };
     }
 
+    #[test]
+    fn files_removed_from_cache_are_reported() {
+        let mut fixture = Fixture::new(1, [], [], [], []);
+        fixture.test_state.borrow_mut().removed_files = vec![digest!(1), digest!(2)];
+        fixture
+            .dispatcher
+            .receive_message(Broker(CancelJob(jid!(1))));
+        fixture
+            .expect_messages_in_any_order(vec![ReportRemovedFiles(vec![digest!(1), digest!(2)])]);
+
+        fixture
+            .dispatcher
+            .receive_message(Broker(CancelJob(jid!(1))));
+        fixture.expect_messages_in_any_order(vec![]);
+    }
+
     #[test]
     #[should_panic(expected = "missing entry for JobId")]
     fn receive_job_stdout_unknown() {
@@ -1073,6 +1472,8 @@ mod tests {
                         first: boxed_u8!(b"stderr"),
                         truncated: 100,
                     },
+                    diagnostics: JobOutputResult::None,
+                    duration: Duration::ZERO,
                 }
             }))),
             CacheDecrementRefCount(digest!(1)),
@@ -1110,6 +1511,8 @@ mod tests {
                         first: boxed_u8!(b"stderr"),
                         truncated: 100,
                     },
+                    diagnostics: JobOutputResult::None,
+                    duration: Duration::ZERO,
                 }
             }))),
             CacheDecrementRefCount(digest!(1)),
@@ -1147,6 +1550,8 @@ mod tests {
                         first: boxed_u8!(b"stderr"),
                         truncated: 100,
                     },
+                    diagnostics: JobOutputResult::None,
+                    duration: Duration::ZERO,
                 }
             }))),
             CacheDecrementRefCount(digest!(1)),
@@ -1184,6 +1589,8 @@ mod tests {
                         first: boxed_u8!(b"stderr"),
                         truncated: 100,
                     },
+                    diagnostics: JobOutputResult::None,
+                    duration: Duration::ZERO,
                 }
             }))),
             CacheDecrementRefCount(digest!(1)),
@@ -1221,6 +1628,8 @@ mod tests {
                         first: boxed_u8!(b"stderr"),
                         truncated: 100,
                     },
+                    diagnostics: JobOutputResult::None,
+                    duration: Duration::ZERO,
                 }
             }))),
             CacheDecrementRefCount(digest!(1)),
@@ -1258,6 +1667,8 @@ mod tests {
                         first: boxed_u8!(b"stderr"),
                         truncated: 100,
                     },
+                    diagnostics: JobOutputResult::None,
+                    duration: Duration::ZERO,
                 }
             }))),
             CacheDecrementRefCount(digest!(1)),
@@ -1322,6 +1733,34 @@ mod tests {
         };
     }
 
+    script_test! {
+        complete_startup_failed,
+        Fixture::new(1, [
+            Ok(pid!(1)),
+            Ok(pid!(2)),
+        ], [
+            (digest!(1), GetArtifact::Success(path_buf!("/a"))),
+            (digest!(2), GetArtifact::Success(path_buf!("/b"))),
+        ], [], []),
+        Broker(EnqueueJob(jid!(1), spec!(1, Tar))) => {
+            CacheGetArtifact(digest!(1), jid!(1)),
+            StartJob(jid!(1), spec!(1, Tar), path_buf_vec!["/a"]),
+        };
+        Broker(EnqueueJob(jid!(2), spec!(2, Tar))) => {
+            CacheGetArtifact(digest!(2), jid!(2)),
+        };
+        JobStartupFailed(jid!(1), JobError::Execution(string!("port in use"))) => {};
+        PidStatus(pid!(1), JobStatus::Signaled(9)) => {};
+        JobStdout(jid!(1), Ok(JobOutputResult::None)) => {};
+        JobStderr(jid!(1), Ok(JobOutputResult::None)) => {
+            SendMessageToBroker(
+                WorkerToBroker(jid!(1), Err(JobError::Execution(string!("port in use"))))
+            ),
+            CacheDecrementRefCount(digest!(1)),
+            StartJob(jid!(2), spec!(2, Tar), path_buf_vec!["/b"]),
+        };
+    }
+
     script_test! {
         complete_bad_stdout_and_stderr,
         Fixture::new(1, [
@@ -1369,6 +1808,8 @@ mod tests {
                 effects: JobEffects {
                     stdout: JobOutputResult::None,
                     stderr: JobOutputResult::None,
+                    diagnostics: JobOutputResult::None,
+                    duration: Duration::ZERO,
                 }
             }))),
             CacheDecrementRefCount(digest!(1)),
@@ -1376,6 +1817,54 @@ mod tests {
         };
     }
 
+    script_test! {
+        seccomp_kill_is_execution_error,
+        Fixture::new(1, [
+            Ok(pid!(1)),
+        ], [
+            (digest!(1), GetArtifact::Success(path_buf!("/a"))),
+        ], [], []),
+        Broker(EnqueueJob(jid!(1), spec!(1, Tar).seccomp(JobSeccomp::Named("strict".into())))) => {
+            CacheGetArtifact(digest!(1), jid!(1)),
+            StartJob(jid!(1), spec!(1, Tar).seccomp(JobSeccomp::Named("strict".into())), path_buf_vec!["/a"]),
+        };
+        JobStdout(jid!(1), Ok(JobOutputResult::None)) => {};
+        JobStderr(jid!(1), Ok(JobOutputResult::None)) => {};
+        PidStatus(pid!(1), JobStatus::Signaled(31)) => {
+            SendMessageToBroker(WorkerToBroker(jid!(1), Err(JobError::Execution(
+                "job was killed by its seccomp profile for making a forbidden system call".into()
+            )))),
+            CacheDecrementRefCount(digest!(1)),
+        };
+    }
+
+    script_test! {
+        seccomp_errno_does_not_change_sigsys,
+        Fixture::new(1, [
+            Ok(pid!(1)),
+        ], [
+            (digest!(1), GetArtifact::Success(path_buf!("/a"))),
+        ], [], []),
+        Broker(EnqueueJob(jid!(1), spec!(1, Tar).seccomp(JobSeccomp::Named("default".into())))) => {
+            CacheGetArtifact(digest!(1), jid!(1)),
+            StartJob(jid!(1), spec!(1, Tar).seccomp(JobSeccomp::Named("default".into())), path_buf_vec!["/a"]),
+        };
+        JobStdout(jid!(1), Ok(JobOutputResult::None)) => {};
+        JobStderr(jid!(1), Ok(JobOutputResult::None)) => {};
+        PidStatus(pid!(1), JobStatus::Signaled(31)) => {
+            SendMessageToBroker(WorkerToBroker(jid!(1), Ok(JobOutcome::Completed {
+                status: JobStatus::Signaled(31),
+                effects: JobEffects {
+                    stdout: JobOutputResult::None,
+                    stderr: JobOutputResult::None,
+                    diagnostics: JobOutputResult::None,
+                    duration: Duration::ZERO,
+                }
+            }))),
+            CacheDecrementRefCount(digest!(1)),
+        };
+    }
+
     script_test! {
         timer_scheduled_then_canceled_on_cancellation,
         Fixture::new(1, [
@@ -1421,6 +1910,8 @@ mod tests {
             SendMessageToBroker(WorkerToBroker(jid!(1), Ok(JobOutcome::TimedOut(JobEffects {
                 stdout: JobOutputResult::None,
                 stderr: JobOutputResult::None,
+                diagnostics: JobOutputResult::None,
+                duration: Duration::ZERO,
             })))),
             StartJob(jid!(2), spec!(2, Tar), path_buf_vec!["/2"]),
         };
@@ -1453,6 +1944,8 @@ mod tests {
             SendMessageToBroker(WorkerToBroker(jid!(1), Ok(JobOutcome::TimedOut(JobEffects {
                 stdout: JobOutputResult::None,
                 stderr: JobOutputResult::None,
+                diagnostics: JobOutputResult::None,
+                duration: Duration::ZERO,
             })))),
             StartJob(jid!(2), spec!(2, Tar), path_buf_vec!["/2"]),
         };
@@ -1483,6 +1976,8 @@ mod tests {
             SendMessageToBroker(WorkerToBroker(jid!(1), Ok(JobOutcome::TimedOut(JobEffects {
                 stdout: JobOutputResult::Inline(boxed_u8!(b"stdout")),
                 stderr: JobOutputResult::Inline(boxed_u8!(b"stderr")),
+                diagnostics: JobOutputResult::None,
+                duration: Duration::ZERO,
             })))),
             StartJob(jid!(2), spec!(2, Tar), path_buf_vec!["/2"]),
         };
@@ -1515,6 +2010,8 @@ mod tests {
                 effects: JobEffects {
                     stdout: JobOutputResult::None,
                     stderr: JobOutputResult::None,
+                    diagnostics: JobOutputResult::None,
+                    duration: Duration::ZERO,
                 }
             }))),
             StartJob(jid!(2), spec!(2, Tar), path_buf_vec!["/2"]),
@@ -1552,6 +2049,430 @@ mod tests {
         };
     }
 
+    script_test! {
+        time_out_with_stop_signal_terminates_gracefully,
+        Fixture::new(1, [
+            Ok(pid!(1)),
+            Ok(pid!(2)),
+        ], [
+            (digest!(1), GetArtifact::Success(path_buf!("/1"))),
+            (digest!(2), GetArtifact::Success(path_buf!("/2"))),
+        ], [], []),
+        Broker(EnqueueJob(jid!(1), spec!(1, Tar).timeout(timeout!(1)).stop_signal(JobSignal::Sigterm).grace_period(5))) => {
+            CacheGetArtifact(digest!(1), jid!(1)),
+            StartJob(jid!(1), spec!(1, Tar).timeout(timeout!(1)).stop_signal(JobSignal::Sigterm).grace_period(5), path_buf_vec!["/1"]),
+            StartTimer(jid!(1), Duration::from_secs(1))
+        };
+        Broker(EnqueueJob(jid!(2), spec!(2, Tar))) => {
+            CacheGetArtifact(digest!(2), jid!(2)),
+        };
+        JobTimer(jid!(1)) => {
+            SignalJob(pid!(1), Signal::TERM),
+            StartTimer(jid!(1), Duration::from_secs(5)),
+        };
+        JobStdout(jid!(1), Ok(JobOutputResult::Inline(boxed_u8!(b"stdout")))) => {};
+        JobStderr(jid!(1), Ok(JobOutputResult::None)) => {};
+        PidStatus(pid!(1), JobStatus::Signaled(15)) => {
+            CacheDecrementRefCount(digest!(1)),
+            CancelTimer(jid!(1)),
+            SendMessageToBroker(WorkerToBroker(jid!(1), Ok(JobOutcome::TimedOutGracefully {
+                status: JobStatus::Signaled(15),
+                effects: JobEffects {
+                    stdout: JobOutputResult::Inline(boxed_u8!(b"stdout")),
+                    stderr: JobOutputResult::None,
+                    diagnostics: JobOutputResult::None,
+                    duration: Duration::ZERO,
+                }
+            }))),
+            StartJob(jid!(2), spec!(2, Tar), path_buf_vec!["/2"]),
+        };
+    }
+
+    script_test! {
+        time_out_with_stop_signal_killed_after_grace_period,
+        Fixture::new(1, [
+            Ok(pid!(1)),
+        ], [
+            (digest!(1), GetArtifact::Success(path_buf!("/1"))),
+        ], [], []),
+        Broker(EnqueueJob(jid!(1), spec!(1, Tar).timeout(timeout!(1)).stop_signal(JobSignal::Sigint).grace_period(5))) => {
+            CacheGetArtifact(digest!(1), jid!(1)),
+            StartJob(jid!(1), spec!(1, Tar).timeout(timeout!(1)).stop_signal(JobSignal::Sigint).grace_period(5), path_buf_vec!["/1"]),
+            StartTimer(jid!(1), Duration::from_secs(1))
+        };
+        JobTimer(jid!(1)) => {
+            SignalJob(pid!(1), Signal::INT),
+            StartTimer(jid!(1), Duration::from_secs(5)),
+        };
+        JobTimer(jid!(1)) => {
+            Kill(pid!(1)),
+        };
+        JobStdout(jid!(1), Ok(JobOutputResult::None)) => {};
+        JobStderr(jid!(1), Ok(JobOutputResult::None)) => {};
+        PidStatus(pid!(1), JobStatus::Signaled(9)) => {
+            CacheDecrementRefCount(digest!(1)),
+            SendMessageToBroker(WorkerToBroker(jid!(1), Ok(JobOutcome::TimedOut(JobEffects {
+                stdout: JobOutputResult::None,
+                stderr: JobOutputResult::None,
+                diagnostics: JobOutputResult::None,
+                duration: Duration::ZERO,
+            })))),
+        };
+    }
+
+    script_test! {
+        time_out_with_stop_signal_exits_just_before_grace_period_ends,
+        Fixture::new(1, [
+            Ok(pid!(1)),
+        ], [
+            (digest!(1), GetArtifact::Success(path_buf!("/1"))),
+        ], [], []),
+        Broker(EnqueueJob(jid!(1), spec!(1, Tar).timeout(timeout!(1)).stop_signal(JobSignal::Sigterm))) => {
+            CacheGetArtifact(digest!(1), jid!(1)),
+            StartJob(jid!(1), spec!(1, Tar).timeout(timeout!(1)).stop_signal(JobSignal::Sigterm), path_buf_vec!["/1"]),
+            StartTimer(jid!(1), Duration::from_secs(1))
+        };
+        JobTimer(jid!(1)) => {
+            SignalJob(pid!(1), Signal::TERM),
+            StartTimer(jid!(1), Duration::from_secs(10)),
+        };
+        PidStatus(pid!(1), JobStatus::Exited(1)) => {};
+        JobTimer(jid!(1)) => {};
+        JobStdout(jid!(1), Ok(JobOutputResult::None)) => {};
+        JobStderr(jid!(1), Ok(JobOutputResult::None)) => {
+            CacheDecrementRefCount(digest!(1)),
+            CancelTimer(jid!(1)),
+            SendMessageToBroker(WorkerToBroker(jid!(1), Ok(JobOutcome::TimedOutGracefully {
+                status: JobStatus::Exited(1),
+                effects: JobEffects {
+                    stdout: JobOutputResult::None,
+                    stderr: JobOutputResult::None,
+                    diagnostics: JobOutputResult::None,
+                    duration: Duration::ZERO,
+                }
+            }))),
+        };
+    }
+
+    script_test! {
+        sigkill_stop_signal_kills_right_away,
+        Fixture::new(1, [
+            Ok(pid!(1)),
+        ], [
+            (digest!(1), GetArtifact::Success(path_buf!("/1"))),
+        ], [], []),
+        Broker(EnqueueJob(jid!(1), spec!(1, Tar).timeout(timeout!(1)).stop_signal(JobSignal::Sigkill))) => {
+            CacheGetArtifact(digest!(1), jid!(1)),
+            StartJob(jid!(1), spec!(1, Tar).timeout(timeout!(1)).stop_signal(JobSignal::Sigkill), path_buf_vec!["/1"]),
+            StartTimer(jid!(1), Duration::from_secs(1))
+        };
+        JobTimer(jid!(1)) => {
+            Kill(pid!(1)),
+        };
+    }
+
+    script_test! {
+        zero_grace_period_kills_right_away,
+        Fixture::new(1, [
+            Ok(pid!(1)),
+        ], [
+            (digest!(1), GetArtifact::Success(path_buf!("/1"))),
+        ], [], []),
+        Broker(EnqueueJob(jid!(1), spec!(1, Tar).timeout(timeout!(1)).stop_signal(JobSignal::Sigterm).grace_period(0))) => {
+            CacheGetArtifact(digest!(1), jid!(1)),
+            StartJob(jid!(1), spec!(1, Tar).timeout(timeout!(1)).stop_signal(JobSignal::Sigterm).grace_period(0), path_buf_vec!["/1"]),
+            StartTimer(jid!(1), Duration::from_secs(1))
+        };
+        JobTimer(jid!(1)) => {
+            Kill(pid!(1)),
+        };
+    }
+
+    script_test! {
+        cancel_with_stop_signal,
+        Fixture::new(1, [
+            Ok(pid!(1)),
+        ], [
+            (digest!(1), GetArtifact::Success(path_buf!("/1"))),
+        ], [], []),
+        Broker(EnqueueJob(jid!(1), spec!(1, Tar).timeout(timeout!(1)).stop_signal(JobSignal::Sigterm))) => {
+            CacheGetArtifact(digest!(1), jid!(1)),
+            StartJob(jid!(1), spec!(1, Tar).timeout(timeout!(1)).stop_signal(JobSignal::Sigterm), path_buf_vec!["/1"]),
+            StartTimer(jid!(1), Duration::from_secs(1))
+        };
+        Broker(CancelJob(jid!(1))) => {
+            CancelTimer(jid!(1)),
+            SignalJob(pid!(1), Signal::TERM),
+            StartTimer(jid!(1), Duration::from_secs(10)),
+        };
+        JobTimer(jid!(1)) => {
+            Kill(pid!(1)),
+        };
+        JobStdout(jid!(1), Ok(JobOutputResult::None)) => {};
+        JobStderr(jid!(1), Ok(JobOutputResult::None)) => {};
+        PidStatus(pid!(1), JobStatus::Signaled(9)) => {
+            CacheDecrementRefCount(digest!(1)),
+        };
+    }
+
+    script_test! {
+        cancel_while_stopping_after_time_out,
+        Fixture::new(1, [
+            Ok(pid!(1)),
+        ], [
+            (digest!(1), GetArtifact::Success(path_buf!("/1"))),
+        ], [], []),
+        Broker(EnqueueJob(jid!(1), spec!(1, Tar).timeout(timeout!(1)).stop_signal(JobSignal::Sigterm))) => {
+            CacheGetArtifact(digest!(1), jid!(1)),
+            StartJob(jid!(1), spec!(1, Tar).timeout(timeout!(1)).stop_signal(JobSignal::Sigterm), path_buf_vec!["/1"]),
+            StartTimer(jid!(1), Duration::from_secs(1))
+        };
+        JobTimer(jid!(1)) => {
+            SignalJob(pid!(1), Signal::TERM),
+            StartTimer(jid!(1), Duration::from_secs(10)),
+        };
+        Broker(CancelJob(jid!(1))) => {};
+        JobStdout(jid!(1), Ok(JobOutputResult::None)) => {};
+        JobStderr(jid!(1), Ok(JobOutputResult::None)) => {};
+        PidStatus(pid!(1), JobStatus::Signaled(15)) => {
+            CacheDecrementRefCount(digest!(1)),
+            CancelTimer(jid!(1)),
+        };
+    }
+
+    script_test! {
+        time_out_with_stack_dump,
+        Fixture::new(1, [
+            Ok(pid!(1)),
+        ], [
+            (digest!(1), GetArtifact::Success(path_buf!("/1"))),
+        ], [], []),
+        Broker(EnqueueJob(jid!(1), spec!(1, Tar).timeout(timeout!(1)).stack_dump_on_timeout(true))) => {
+            CacheGetArtifact(digest!(1), jid!(1)),
+            StartJob(jid!(1), spec!(1, Tar).timeout(timeout!(1)).stack_dump_on_timeout(true), path_buf_vec!["/1"]),
+            StartTimer(jid!(1), Duration::from_secs(1))
+        };
+        JobTimer(jid!(1)) => {
+            StartStackDump(jid!(1), pid!(1)),
+        };
+        JobStackDump(jid!(1), string!("stack")) => {
+            Kill(pid!(1)),
+        };
+        JobStdout(jid!(1), Ok(JobOutputResult::None)) => {};
+        JobStderr(jid!(1), Ok(JobOutputResult::None)) => {};
+        PidStatus(pid!(1), JobStatus::Signaled(9)) => {
+            CacheDecrementRefCount(digest!(1)),
+            SendMessageToBroker(WorkerToBroker(jid!(1), Ok(JobOutcome::TimedOut(JobEffects {
+                stdout: JobOutputResult::None,
+                stderr: JobOutputResult::None,
+                diagnostics: JobOutputResult::Inline(boxed_u8!(b"stack")),
+                duration: Duration::ZERO,
+            })))),
+        };
+    }
+
+    script_test! {
+        time_out_with_stack_dump_and_stop_signal,
+        Fixture::new(1, [
+            Ok(pid!(1)),
+        ], [
+            (digest!(1), GetArtifact::Success(path_buf!("/1"))),
+        ], [], []),
+        Broker(EnqueueJob(jid!(1), spec!(1, Tar).timeout(timeout!(1)).stop_signal(JobSignal::Sigterm).stack_dump_on_timeout(true))) => {
+            CacheGetArtifact(digest!(1), jid!(1)),
+            StartJob(jid!(1), spec!(1, Tar).timeout(timeout!(1)).stop_signal(JobSignal::Sigterm).stack_dump_on_timeout(true), path_buf_vec!["/1"]),
+            StartTimer(jid!(1), Duration::from_secs(1))
+        };
+        JobTimer(jid!(1)) => {
+            StartStackDump(jid!(1), pid!(1)),
+        };
+        JobStackDump(jid!(1), string!("stack")) => {
+            SignalJob(pid!(1), Signal::TERM),
+            StartTimer(jid!(1), Duration::from_secs(10)),
+        };
+        JobStdout(jid!(1), Ok(JobOutputResult::None)) => {};
+        JobStderr(jid!(1), Ok(JobOutputResult::None)) => {};
+        PidStatus(pid!(1), JobStatus::Signaled(15)) => {
+            CacheDecrementRefCount(digest!(1)),
+            CancelTimer(jid!(1)),
+            SendMessageToBroker(WorkerToBroker(jid!(1), Ok(JobOutcome::TimedOutGracefully {
+                status: JobStatus::Signaled(15),
+                effects: JobEffects {
+                    stdout: JobOutputResult::None,
+                    stderr: JobOutputResult::None,
+                    diagnostics: JobOutputResult::Inline(boxed_u8!(b"stack")),
+                    duration: Duration::ZERO,
+                }
+            }))),
+        };
+    }
+
+    script_test! {
+        time_out_with_stack_dump_exits_while_dumping,
+        Fixture::new(1, [
+            Ok(pid!(1)),
+        ], [
+            (digest!(1), GetArtifact::Success(path_buf!("/1"))),
+        ], [], []),
+        Broker(EnqueueJob(jid!(1), spec!(1, Tar).timeout(timeout!(1)).stack_dump_on_timeout(true))) => {
+            CacheGetArtifact(digest!(1), jid!(1)),
+            StartJob(jid!(1), spec!(1, Tar).timeout(timeout!(1)).stack_dump_on_timeout(true), path_buf_vec!["/1"]),
+            StartTimer(jid!(1), Duration::from_secs(1))
+        };
+        JobTimer(jid!(1)) => {
+            StartStackDump(jid!(1), pid!(1)),
+        };
+        JobStdout(jid!(1), Ok(JobOutputResult::None)) => {};
+        JobStderr(jid!(1), Ok(JobOutputResult::None)) => {};
+        PidStatus(pid!(1), JobStatus::Exited(0)) => {};
+        JobStackDump(jid!(1), string!("")) => {
+            CacheDecrementRefCount(digest!(1)),
+            SendMessageToBroker(WorkerToBroker(jid!(1), Ok(JobOutcome::TimedOut(JobEffects {
+                stdout: JobOutputResult::None,
+                stderr: JobOutputResult::None,
+                diagnostics: JobOutputResult::Inline(boxed_u8!(b"")),
+                duration: Duration::ZERO,
+            })))),
+        };
+    }
+
+    #[test]
+    fn duration_is_measured_until_status() {
+        let mut fixture = Fixture::new(
+            1,
+            [Ok(pid!(1))],
+            [(digest!(1), GetArtifact::Success(path_buf!("/1")))],
+            [],
+            [],
+        );
+        fixture
+            .dispatcher
+            .receive_message(Broker(EnqueueJob(jid!(1), spec!(1, Tar))));
+        fixture.expect_messages_in_any_order(vec![
+            CacheGetArtifact(digest!(1), jid!(1)),
+            StartJob(jid!(1), spec!(1, Tar), path_buf_vec!["/1"]),
+        ]);
+        fixture.test_state.borrow_mut().now += Duration::from_secs(5);
+        fixture
+            .dispatcher
+            .receive_message(PidStatus(pid!(1), JobStatus::Exited(0)));
+        fixture.test_state.borrow_mut().now += Duration::from_secs(1);
+        fixture
+            .dispatcher
+            .receive_message(JobStdout(jid!(1), Ok(JobOutputResult::None)));
+        fixture
+            .dispatcher
+            .receive_message(JobStderr(jid!(1), Ok(JobOutputResult::None)));
+        fixture.expect_messages_in_any_order(vec![
+            CacheDecrementRefCount(digest!(1)),
+            SendMessageToBroker(WorkerToBroker(
+                jid!(1),
+                Ok(JobOutcome::Completed {
+                    status: JobStatus::Exited(0),
+                    effects: JobEffects {
+                        stdout: JobOutputResult::None,
+                        stderr: JobOutputResult::None,
+                        diagnostics: JobOutputResult::None,
+                        duration: Duration::from_secs(5),
+                    },
+                }),
+            )),
+        ]);
+    }
+
+    #[test]
+    fn metrics_request() {
+        let mut fixture = Fixture::new(
+            1,
+            [Ok(pid!(1)), Ok(pid!(2))],
+            [
+                (digest!(1), GetArtifact::Success(path_buf!("/1"))),
+                (digest!(2), GetArtifact::Get(path_buf!("/2"))),
+                (digest!(3), GetArtifact::Get(path_buf!("/3"))),
+            ],
+            [(digest!(2), (path_buf!("/2"), vec![jid!(2)]))],
+            [(digest!(3), vec![jid!(3)])],
+        );
+        fixture.test_state.borrow_mut().cache_metrics.hits = 1;
+        for (jid, spec) in [
+            (jid!(1), spec!(1, Tar)),
+            (jid!(2), spec!(2, Tar)),
+            (jid!(3), spec!(3, Tar)),
+        ] {
+            fixture
+                .dispatcher
+                .receive_message(Broker(EnqueueJob(jid, spec)));
+        }
+        fixture.dispatcher.receive_message(ArtifactFetcher(
+            digest!(2),
+            Ok(ArtifactSize {
+                bytes_used: 10,
+                files: vec![(digest!(4), 5)],
+            }),
+        ));
+        fixture
+            .dispatcher
+            .receive_message(ArtifactFetcher(digest!(3), Err(anyhow!("foo"))));
+        fixture.test_state.borrow_mut().now += Duration::from_millis(750);
+        for msg in [
+            PidStatus(pid!(1), JobStatus::Exited(0)),
+            JobStdout(jid!(1), Ok(JobOutputResult::None)),
+            JobStderr(jid!(1), Ok(JobOutputResult::None)),
+        ] {
+            fixture.dispatcher.receive_message(msg);
+        }
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        fixture.dispatcher.receive_message(MetricsRequest(sender));
+        let mut job_run_seconds = Histogram::default();
+        job_run_seconds.observe(0.75);
+        assert_eq!(
+            receiver.try_recv().unwrap(),
+            DispatcherMetrics {
+                slots: 1,
+                awaiting_layers: 0,
+                queued: 0,
+                executing: 1,
+                jobs_completed: 1,
+                job_run_seconds,
+                artifact_fetches: 2,
+                artifact_fetch_failures: 1,
+                artifact_fetched_bytes: 15,
+                cache: CacheMetrics {
+                    hits: 1,
+                    ..Default::default()
+                },
+            }
+        );
+    }
+
+    script_test! {
+        cancel_while_dumping_stack,
+        Fixture::new(1, [
+            Ok(pid!(1)),
+        ], [
+            (digest!(1), GetArtifact::Success(path_buf!("/1"))),
+        ], [], []),
+        Broker(EnqueueJob(jid!(1), spec!(1, Tar).timeout(timeout!(1)).stack_dump_on_timeout(true))) => {
+            CacheGetArtifact(digest!(1), jid!(1)),
+            StartJob(jid!(1), spec!(1, Tar).timeout(timeout!(1)).stack_dump_on_timeout(true), path_buf_vec!["/1"]),
+            StartTimer(jid!(1), Duration::from_secs(1))
+        };
+        JobTimer(jid!(1)) => {
+            StartStackDump(jid!(1), pid!(1)),
+        };
+        Broker(CancelJob(jid!(1))) => {
+            Kill(pid!(1)),
+        };
+        JobStackDump(jid!(1), string!("stack")) => {};
+        JobStdout(jid!(1), Ok(JobOutputResult::None)) => {};
+        JobStderr(jid!(1), Ok(JobOutputResult::None)) => {};
+        PidStatus(pid!(1), JobStatus::Signaled(9)) => {
+            CacheDecrementRefCount(digest!(1)),
+        };
+        JobStackDump(jid!(1), string!("stack")) => {};
+    }
+
     script_test! {
         error_cache_responses,
         Fixture::new(2, [], [
@@ -1572,8 +2493,8 @@ mod tests {
             CacheGetArtifact(digest!(43), jid!(1)),
             CacheGetArtifact(digest!(44), jid!(1)),
         };
-        ArtifactFetcher(digest!(41), Ok(101)) => {
-            CacheGotArtifactSuccess(digest!(41), 101),
+        ArtifactFetcher(digest!(41), Ok(101.into())) => {
+            CacheGotArtifactSuccess(digest!(41), 101.into()),
         };
         ArtifactFetcher(digest!(42), Err(anyhow!("foo"))) => {
             CacheGotArtifactFailure(digest!(42)),
@@ -1581,8 +2502,8 @@ mod tests {
                 string!("Failed to download and extract layer artifact 000000000000000000000000000000000000000000000000000000000000002a: foo"))))),
             CacheDecrementRefCount(digest!(41))
         };
-        ArtifactFetcher(digest!(43), Ok(103)) => {
-            CacheGotArtifactSuccess(digest!(43), 103),
+        ArtifactFetcher(digest!(43), Ok(103.into())) => {
+            CacheGotArtifactSuccess(digest!(43), 103.into()),
             CacheDecrementRefCount(digest!(43))
         };
         ArtifactFetcher(digest!(44), Err(anyhow!("foo"))) => {
@@ -1631,8 +2552,79 @@ mod tests {
                 effects: JobEffects {
                     stdout: JobOutputResult::None,
                     stderr: JobOutputResult::None,
+                    diagnostics: JobOutputResult::None,
+                    duration: Duration::ZERO,
                 },
             }))),
         };
     }
+
+    #[test]
+    fn job_events() {
+        let mut fixture = Fixture::new(
+            1,
+            [Ok(pid!(1)), Ok(pid!(2))],
+            [
+                (digest!(1), GetArtifact::Success(path_buf!("/1"))),
+                (digest!(2), GetArtifact::Get(path_buf!("/2"))),
+                (digest!(3), GetArtifact::Get(path_buf!("/3"))),
+                (digest!(4), GetArtifact::Success(path_buf!("/4"))),
+            ],
+            [(digest!(2), (path_buf!("/2"), vec![jid!(2)]))],
+            [(digest!(3), vec![jid!(3)])],
+        );
+        for (jid, spec) in [
+            (jid!(1), spec!(1, Tar)),
+            (jid!(2), spec!(2, Tar)),
+            (jid!(3), spec!(3, Tar)),
+            (jid!(4), spec!(4, Tar)),
+        ] {
+            fixture
+                .dispatcher
+                .receive_message(Broker(EnqueueJob(jid, spec)));
+        }
+        fixture.dispatcher.receive_message(ArtifactFetcher(
+            digest!(2),
+            Ok(ArtifactSize {
+                bytes_used: 10,
+                files: vec![],
+            }),
+        ));
+        fixture
+            .dispatcher
+            .receive_message(ArtifactFetcher(digest!(3), Err(anyhow!("foo"))));
+        fixture
+            .dispatcher
+            .receive_message(Broker(CancelJob(jid!(4))));
+        for msg in [
+            PidStatus(pid!(1), JobStatus::Exited(0)),
+            JobStdout(jid!(1), Ok(JobOutputResult::None)),
+            JobStderr(jid!(1), Ok(JobOutputResult::None)),
+            Broker(CancelJob(jid!(2))),
+            PidStatus(pid!(2), JobStatus::Signaled(9)),
+            JobStdout(jid!(2), Ok(JobOutputResult::None)),
+            JobStderr(jid!(2), Ok(JobOutputResult::None)),
+        ] {
+            fixture.dispatcher.receive_message(msg);
+        }
+
+        assert_eq!(
+            fixture.test_state.borrow().job_events,
+            vec![
+                (jid!(1), JobEvent::Enqueued),
+                (jid!(1), JobEvent::ArtifactsReady),
+                (jid!(1), JobEvent::Started),
+                (jid!(2), JobEvent::Enqueued),
+                (jid!(3), JobEvent::Enqueued),
+                (jid!(4), JobEvent::Enqueued),
+                (jid!(4), JobEvent::ArtifactsReady),
+                (jid!(2), JobEvent::ArtifactsReady),
+                (jid!(3), JobEvent::Completed),
+                (jid!(4), JobEvent::Canceled),
+                (jid!(1), JobEvent::Completed),
+                (jid!(2), JobEvent::Started),
+                (jid!(2), JobEvent::Canceled),
+            ]
+        );
+    }
 }
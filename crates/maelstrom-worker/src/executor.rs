@@ -1,6 +1,10 @@
 //! Easily start and stop processes.
 
-use crate::config::InlineLimit;
+use crate::{
+    config::InlineLimit,
+    seccomp,
+    subid::{self, SubordinateIds},
+};
 use anyhow::{anyhow, Error, Result};
 use bumpalo::{
     collections::{String as BumpString, Vec as BumpVec},
@@ -9,30 +13,44 @@ use bumpalo::{
 use c_str_macro::c_str;
 use futures::ready;
 use maelstrom_base::{
-    EnumSet, GroupId, JobDevice, JobError, JobMount, JobMountFsType, JobOutputResult, JobResult,
-    NonEmpty, Timeout, UserId, Utf8PathBuf,
+    EnumSet, GroupId, JobDevice, JobError, JobMount, JobMountFsType, JobNatOptions, JobNetwork,
+    JobOutputResult, JobPortForward, JobPortProtocol, JobResult, JobRlimit, JobRlimitResource,
+    JobRlimitValue, JobSeccomp, JobStatus, NonEmpty, Timeout, TmpfsOptions, UserId, Utf8PathBuf,
 };
 use maelstrom_linux::{
-    self as linux, CloneArgs, CloneFlags, CloseRangeFirst, CloseRangeFlags, CloseRangeLast, Errno,
-    Fd, FileMode, MountFlags, NetlinkSocketAddr, OpenFlags, OwnedFd, Pid, Signal, SocketDomain,
-    SocketProtocol, SocketType, UmountFlags,
+    self as linux, Capability, CloneArgs, CloneFlags, CloseRangeFirst, CloseRangeFlags,
+    CloseRangeLast, Errno, Fd, FileMode, MountFlags, NetlinkSocketAddr, OpenFlags, OwnedFd, Pid,
+    Rlimit, RlimitResource, Signal, SockFprog, SocketDomain, SocketProtocol, SocketType,
+    UmountFlags,
 };
 use maelstrom_worker_child::Syscall;
 use netlink_packet_core::{NetlinkMessage, NLM_F_ACK, NLM_F_CREATE, NLM_F_EXCL, NLM_F_REQUEST};
-use netlink_packet_route::{rtnl::constants::RTM_SETLINK, LinkMessage, RtnlMessage, IFF_UP};
+use netlink_packet_route::{
+    nlas::{address, route},
+    AddressMessage, LinkMessage, RouteMessage, RtnlMessage, AF_INET, IFF_UP, RTN_UNICAST,
+    RTPROT_BOOT, RT_SCOPE_UNIVERSE, RT_TABLE_MAIN,
+};
+use serde_json::json;
 use std::{
+    collections::{HashMap, HashSet},
     ffi::{CStr, CString},
     fmt::Write as _,
-    fs::File,
-    io::Read as _,
+    fs::{self, File},
+    io::{Read as _, Write as _},
     iter, mem,
-    os::unix::ffi::OsStrExt as _,
+    net::{Ipv4Addr, Shutdown, SocketAddr, TcpListener, ToSocketAddrs as _, UdpSocket},
+    os::unix::{ffi::OsStrExt as _, net::UnixStream, process::CommandExt as _},
     path::{Path, PathBuf},
     pin::Pin,
+    process::{Command, Stdio},
+    sync::{Arc, Mutex},
     task::{Context, Poll},
+    thread,
 };
+use tempfile::TempDir;
 use tokio::{
     io::{self, unix::AsyncFd, AsyncRead, AsyncReadExt as _, ReadBuf},
+    sync::oneshot,
     task,
 };
 use tuple::Map as _;
@@ -54,12 +72,16 @@ pub struct JobSpec {
     pub layers: NonEmpty<PathBuf>,
     pub devices: EnumSet<JobDevice>,
     pub mounts: Vec<JobMount>,
-    pub enable_loopback: bool,
+    pub network: JobNetwork,
     pub enable_writable_file_system: bool,
     pub working_directory: Utf8PathBuf,
     pub user: UserId,
     pub group: GroupId,
     pub timeout: Option<Timeout>,
+    pub seccomp: Option<JobSeccomp>,
+    pub rlimits: Vec<JobRlimit>,
+    pub hostname: Option<String>,
+    pub domainname: Option<String>,
 }
 
 impl JobSpec {
@@ -71,12 +93,19 @@ impl JobSpec {
             layers: _,
             devices,
             mounts,
-            enable_loopback,
+            network,
             enable_writable_file_system,
             working_directory,
             user,
             group,
             timeout,
+            stop_signal: _,
+            grace_period: _,
+            stack_dump_on_timeout: _,
+            seccomp,
+            rlimits,
+            hostname,
+            domainname,
         } = spec;
         JobSpec {
             program,
@@ -85,12 +114,81 @@ impl JobSpec {
             layers,
             devices,
             mounts,
-            enable_loopback,
+            network,
             enable_writable_file_system,
             working_directory,
             user,
             group,
             timeout,
+            seccomp,
+            rlimits,
+            hostname,
+            domainname,
+        }
+    }
+}
+
+/// The pipes that jobs' init processes report their jobs' statuses on, by the init processes' pids.
+/// The pid [`Executor::start`] returns is that of the job's init process, which forwards signals to
+/// the job and exits when the job terminates. Whoever reaps the init process should pass its status
+/// through [`JobStatusPipes::job_status`] to get the job's. This also releases the host ports
+/// that were forwarded into the job.
+#[derive(Clone, Default)]
+pub struct JobStatusPipes(Arc<Mutex<RunningJobs>>);
+
+#[derive(Default)]
+struct RunningJobs {
+    status_pipes: HashMap<Pid, File>,
+    // The host ports forwarded into jobs with [`JobNetwork::Nat`], and the pids of the jobs' init
+    // processes. A host port can only be forwarded into one job at a time.
+    host_ports: HashMap<(JobPortProtocol, u16), Pid>,
+}
+
+impl RunningJobs {
+    /// Check that the host ports of a job's port forwards aren't forwarded twice, and aren't
+    /// already taken by another job or some other process on the worker.
+    fn check_host_ports(&self, port_forwards: &[JobPortForward]) -> Result<()> {
+        for (i, port_forward) in port_forwards.iter().enumerate() {
+            let key = (port_forward.protocol, port_forward.host_port);
+            let proto = port_protocol_name(port_forward.protocol);
+            let port = port_forward.host_port;
+            if port_forwards[..i]
+                .iter()
+                .any(|other| (other.protocol, other.host_port) == key)
+            {
+                return Err(anyhow!(
+                    "{proto} host port {port} is forwarded more than once"
+                ));
+            }
+            if self.host_ports.contains_key(&key) {
+                return Err(anyhow!(
+                    "{proto} host port {port} can't be forwarded into the job because it's already \
+                    forwarded into another job on the worker"
+                ));
+            }
+            check_host_port_is_free(port_forward)?;
+        }
+        Ok(())
+    }
+}
+
+impl JobStatusPipes {
+    /// Return the status of the job whose init process `pid` terminated with `status`. If `pid`
+    /// isn't a job's init process, or if the init process was killed before it could report the
+    /// job's status, `status` is returned unchanged.
+    pub fn job_status(&self, pid: Pid, status: JobStatus) -> JobStatus {
+        let mut running_jobs = self.0.lock().unwrap();
+        running_jobs.host_ports.retain(|_, owner| *owner != pid);
+        let Some(mut pipe) = running_jobs.status_pipes.remove(&pid) else {
+            return status;
+        };
+        drop(running_jobs);
+        // The init process writes the job's status before it exits, so it's already in the pipe.
+        let mut buf = [0; 2];
+        match pipe.read(&mut buf) {
+            Ok(2) if buf[0] == 0 => JobStatus::Exited(buf[1]),
+            Ok(2) => JobStatus::Signaled(buf[1]),
+            _ => status,
         }
     }
 }
@@ -105,10 +203,25 @@ pub struct Executor {
     comma_upperdir_comma_workdir: String,
     netlink_socket_addr: NetlinkSocketAddr,
     netlink_message: Box<[u8]>,
+    nat_link_message: Box<[u8]>,
+    nat_address_message: Box<[u8]>,
+    allowed_bind_mounts: Vec<PathBuf>,
+    writable_file_system_tmpfs_data: Option<CString>,
+    slirp4netns: PathBuf,
+    subordinate_ids: SubordinateIds,
+    job_status_pipes: JobStatusPipes,
 }
 
 impl Executor {
-    pub fn new(mount_dir: PathBuf, tmpfs_dir: PathBuf) -> Result<Self> {
+    pub fn new(
+        mount_dir: PathBuf,
+        tmpfs_dir: PathBuf,
+        allowed_bind_mounts: Vec<PathBuf>,
+        writable_file_system_size: Option<u64>,
+        slirp4netns: PathBuf,
+        subordinate_ids: SubordinateIds,
+        job_status_pipes: JobStatusPipes,
+    ) -> Result<Self> {
         // Set up stdin to be a file that will always return EOF. We could do something similar
         // by opening /dev/null but then we would depend on /dev being mounted. The fewer
         // dependencies, the better.
@@ -149,16 +262,29 @@ impl Executor {
         let upper_dir = CString::new(upper_dir.as_os_str().as_bytes())?;
         let work_dir = CString::new(work_dir.as_os_str().as_bytes())?;
         let netlink_socket_addr = NetlinkSocketAddr::default();
-        let mut netlink_message = LinkMessage::default();
-        netlink_message.header.index = 1;
-        netlink_message.header.flags |= IFF_UP;
-        netlink_message.header.change_mask |= IFF_UP;
-        let mut netlink_message = NetlinkMessage::from(RtnlMessage::SetLink(netlink_message));
-        netlink_message.header.flags = NLM_F_REQUEST | NLM_F_ACK | NLM_F_EXCL | NLM_F_CREATE;
-        netlink_message.header.length = netlink_message.buffer_len() as u32;
-        netlink_message.header.message_type = RTM_SETLINK;
-        let mut buffer = vec![0; netlink_message.buffer_len()].into_boxed_slice();
-        netlink_message.serialize(&mut buffer[..]);
+        let netlink_message = link_up_message(1);
+        // The NAT interface's index is filled in by the child once slirp4netns has created it.
+        let nat_link_message = link_up_message(0);
+        let mut nat_address_message = AddressMessage::default();
+        nat_address_message.header.family = AF_INET as u8;
+        nat_address_message.header.prefix_len = NAT_PREFIX_LEN;
+        nat_address_message
+            .nlas
+            .push(address::Nla::Local(NAT_ADDRESS.octets().to_vec()));
+        nat_address_message
+            .nlas
+            .push(address::Nla::Address(NAT_ADDRESS.octets().to_vec()));
+        let nat_address_message = netlink_request(RtnlMessage::NewAddress(nat_address_message));
+
+        // Bind mount sources are canonicalized before they are checked, so the allowed paths need
+        // to be too. A path that doesn't exist yet is kept as it is.
+        let allowed_bind_mounts = allowed_bind_mounts
+            .into_iter()
+            .map(|path| path.canonicalize().unwrap_or(path))
+            .collect();
+        let writable_file_system_tmpfs_data = writable_file_system_size
+            .map(|size| CString::new(format!("size={size}")))
+            .transpose()?;
 
         Ok(Executor {
             user,
@@ -169,7 +295,14 @@ impl Executor {
             work_dir,
             comma_upperdir_comma_workdir,
             netlink_socket_addr,
-            netlink_message: buffer,
+            netlink_message,
+            nat_link_message,
+            nat_address_message,
+            allowed_bind_mounts,
+            writable_file_system_tmpfs_data,
+            slirp4netns,
+            subordinate_ids,
+            job_status_pipes,
         })
     }
 }
@@ -186,7 +319,10 @@ impl Executor {
     /// production, that role will be filled by [`crate::reaper::main`].
     ///
     /// This function is designed to be callable in an async context, even though it temporarily
-    /// blocks the calling thread while the child is starting up.
+    /// blocks the calling thread while the child is starting up. A job with [`JobNetwork::Nat`]
+    /// finishes starting up after this function returns, once slirp4netns is ready. If that fails,
+    /// the job never runs, and `startup_failed` is called on another thread before the stdout
+    /// callback is. Otherwise, `startup_failed` is never called.
     ///
     /// If this function returns [`JobResult::Ok`], then the child process obviously will be
     /// started and the caller will need to waitid(2) on the child eventually. However, if this
@@ -200,8 +336,9 @@ impl Executor {
         inline_limit: InlineLimit,
         stdout_done: impl FnOnce(Result<JobOutputResult>) + Send + 'static,
         stderr_done: impl FnOnce(Result<JobOutputResult>) + Send + 'static,
+        startup_failed: impl FnOnce(JobError<Error>) + Send + 'static,
     ) -> JobResult<Pid, Error> {
-        self.start_inner(spec, inline_limit, stdout_done, stderr_done)
+        self.start_inner(spec, inline_limit, stdout_done, stderr_done, startup_failed)
     }
 }
 
@@ -259,28 +396,62 @@ async fn output_reader(
     }
 }
 
-/// Task main for the output reader: Read the output and then call the callback.
+/// Task main for the output reader: Read the output and then call the callback. If `startup_done`
+/// is given, wait for it first, so that a job that fails to start reports that before its output.
 async fn output_reader_task_main(
     inline_limit: InlineLimit,
     stream: impl AsyncRead + std::marker::Unpin,
+    startup_done: Option<oneshot::Receiver<()>>,
     done: impl FnOnce(Result<JobOutputResult>) + Send + 'static,
 ) {
-    done(output_reader(inline_limit, stream).await);
+    let result = output_reader(inline_limit, stream).await;
+    if let Some(startup_done) = startup_done {
+        let _ = startup_done.await;
+    }
+    done(result);
 }
 
+/// Make the read side of a stdout or stderr pipe non-blocking so that we can use it with Tokio.
+fn output_file(fd: OwnedFd) -> Result<AsyncFile> {
+    linux::fcntl_setfl(fd.as_fd(), OpenFlags::NONBLOCK)?;
+    Ok(AsyncFile(AsyncFd::new(fd.into_file())?))
+}
+
+/// An error transformer for a script item that may fail after [`Executor::start`] has returned.
+/// These are run on another thread, so they can't borrow from the bump.
+type LateErrorTransformer = Box<dyn Fn(&'static str) -> JobError<Error> + Send>;
+
 struct ScriptBuilder<'a> {
+    bump: &'a Bump,
     syscalls: BumpVec<'a, Syscall<'a>>,
     error_transformers: BumpVec<'a, &'a dyn Fn(&'static str) -> JobError<Error>>,
+    late_error_transformers: Vec<LateErrorTransformer>,
 }
 
 impl<'a> ScriptBuilder<'a> {
     fn new(bump: &'a Bump) -> Self {
         ScriptBuilder {
+            bump,
             syscalls: BumpVec::new_in(bump),
             error_transformers: BumpVec::new_in(bump),
+            late_error_transformers: vec![],
         }
     }
 
+    /// Push a script item that may fail after [`Executor::start`] has returned. All of these have
+    /// to come after the ones pushed with [`Self::push`].
+    fn push_late(
+        &mut self,
+        syscall: Syscall<'a>,
+        error_transformer: impl Fn(&'static str) -> JobError<Error> + Copy + Send + 'static,
+    ) {
+        self.syscalls.push(syscall);
+        self.error_transformers
+            .push(self.bump.alloc(error_transformer));
+        self.late_error_transformers
+            .push(Box::new(error_transformer));
+    }
+
     fn push(
         &mut self,
         syscall: Syscall<'a>,
@@ -302,17 +473,497 @@ fn bump_c_str_from_bytes<'bump>(bump: &'bump Bump, bytes: &[u8]) -> Result<&'bum
     CStr::from_bytes_with_nul(vec.into_bump_slice()).map_err(Error::new)
 }
 
+/// Build the data argument for mounting a tmpfs with the given options. Returns `None` if all of
+/// the options are the kernel's defaults.
+fn tmpfs_mount_data<'bump>(bump: &'bump Bump, options: &TmpfsOptions) -> Option<&'bump [u8]> {
+    if *options == TmpfsOptions::default() {
+        return None;
+    }
+    let mut data = BumpString::new_in(bump);
+    if let Some(size) = options.size {
+        write!(data, "size={size},").unwrap();
+    }
+    if let Some(nr_inodes) = options.nr_inodes {
+        write!(data, "nr_inodes={nr_inodes},").unwrap();
+    }
+    if let Some(mode) = options.mode {
+        write!(data, "mode={mode:o},").unwrap();
+    }
+    data.pop();
+    data.push('\0');
+    Some(data.into_bytes().into_bump_slice())
+}
+
+fn rlimit_resource(resource: JobRlimitResource) -> (RlimitResource, &'static str) {
+    match resource {
+        JobRlimitResource::As => (RlimitResource::AS, "as"),
+        JobRlimitResource::Core => (RlimitResource::CORE, "core"),
+        JobRlimitResource::Cpu => (RlimitResource::CPU, "cpu"),
+        JobRlimitResource::Data => (RlimitResource::DATA, "data"),
+        JobRlimitResource::Fsize => (RlimitResource::FSIZE, "fsize"),
+        JobRlimitResource::Locks => (RlimitResource::LOCKS, "locks"),
+        JobRlimitResource::Memlock => (RlimitResource::MEMLOCK, "memlock"),
+        JobRlimitResource::Msgqueue => (RlimitResource::MSGQUEUE, "msgqueue"),
+        JobRlimitResource::Nice => (RlimitResource::NICE, "nice"),
+        JobRlimitResource::Nofile => (RlimitResource::NOFILE, "nofile"),
+        JobRlimitResource::Nproc => (RlimitResource::NPROC, "nproc"),
+        JobRlimitResource::Rss => (RlimitResource::RSS, "rss"),
+        JobRlimitResource::Rtprio => (RlimitResource::RTPRIO, "rtprio"),
+        JobRlimitResource::Rttime => (RlimitResource::RTTIME, "rttime"),
+        JobRlimitResource::Sigpending => (RlimitResource::SIGPENDING, "sigpending"),
+        JobRlimitResource::Stack => (RlimitResource::STACK, "stack"),
+    }
+}
+
+fn rlimit_value(value: JobRlimitValue) -> u64 {
+    match value {
+        JobRlimitValue::Limited(limit) => limit,
+        JobRlimitValue::Unlimited => Rlimit::INFINITY,
+    }
+}
+
+/// The names a job's UTS namespace gets when the job doesn't specify them. The domain name is what
+/// the kernel starts with.
+const DEFAULT_HOSTNAME: &str = "maelstrom";
+const DEFAULT_DOMAINNAME: &str = "(none)";
+
+/// The interface slirp4netns creates in the network namespace of a job with [`JobNetwork::Nat`].
+const NAT_INTERFACE: &str = "tap0";
+
+/// Where the interface index goes in an rtnetlink link or address message: right after the 16-byte
+/// `nlmsghdr` and the first four bytes of the `ifinfomsg` or `ifaddrmsg`.
+const NETLINK_INTERFACE_INDEX_OFFSET: usize = 20;
+
+/// The addresses slirp4netns expects the job to use. The gateway also forwards to the worker's
+/// loopback interface.
+const NAT_ADDRESS: Ipv4Addr = Ipv4Addr::new(10, 0, 2, 100);
+const NAT_PREFIX_LEN: u8 = 24;
+const NAT_GATEWAY: Ipv4Addr = Ipv4Addr::new(10, 0, 2, 2);
+
+/// The name of slirp4netns's API socket in its private directory.
+const API_SOCKET: &str = "api.sock";
+
+/// Serialize an rtnetlink request that asks for an acknowledgement.
+fn netlink_request(message: RtnlMessage) -> Box<[u8]> {
+    let mut message = NetlinkMessage::from(message);
+    message.header.flags = NLM_F_REQUEST | NLM_F_ACK | NLM_F_EXCL | NLM_F_CREATE;
+    message.finalize();
+    let mut buffer = vec![0; message.buffer_len()].into_boxed_slice();
+    message.serialize(&mut buffer[..]);
+    buffer
+}
+
+fn link_up_message(index: u32) -> Box<[u8]> {
+    let mut message = LinkMessage::default();
+    message.header.index = index;
+    message.header.flags |= IFF_UP;
+    message.header.change_mask |= IFF_UP;
+    netlink_request(RtnlMessage::SetLink(message))
+}
+
+/// Build a message that routes `destination` through the NAT gateway.
+fn nat_route_message((destination, prefix_len): (Ipv4Addr, u8)) -> Box<[u8]> {
+    let mut message = RouteMessage::default();
+    message.header.address_family = AF_INET as u8;
+    message.header.destination_prefix_length = prefix_len;
+    message.header.table = RT_TABLE_MAIN;
+    message.header.protocol = RTPROT_BOOT;
+    message.header.scope = RT_SCOPE_UNIVERSE;
+    message.header.kind = RTN_UNICAST;
+    if prefix_len != 0 {
+        message
+            .nlas
+            .push(route::Nla::Destination(destination.octets().to_vec()));
+    }
+    message
+        .nlas
+        .push(route::Nla::Gateway(NAT_GATEWAY.octets().to_vec()));
+    netlink_request(RtnlMessage::NewRoute(message))
+}
+
+/// Turn a job's `allowed_hosts` into the destinations to route through the NAT gateway. The job
+/// gets no default route, so everything else is unreachable. With no allowed hosts, the job gets
+/// just a default route.
+fn nat_routes(allowed_hosts: &[String]) -> Result<Vec<(Ipv4Addr, u8)>> {
+    if allowed_hosts.is_empty() {
+        return Ok(vec![(Ipv4Addr::UNSPECIFIED, 0)]);
+    }
+    let mut routes = vec![];
+    for host in allowed_hosts {
+        if let Some((address, prefix_len)) = host.split_once('/') {
+            let address = address
+                .parse::<Ipv4Addr>()
+                .map_err(|err| anyhow!("allowed host {host}: {err}"))?;
+            let prefix_len = prefix_len
+                .parse::<u8>()
+                .ok()
+                .filter(|prefix_len| *prefix_len <= 32)
+                .ok_or_else(|| anyhow!("allowed host {host}: invalid prefix length"))?;
+            routes.push((
+                Ipv4Addr::from(u32::from(address) & prefix_mask(prefix_len)),
+                prefix_len,
+            ));
+        } else if let Ok(address) = host.parse() {
+            routes.push((address, 32));
+        } else {
+            let resolved = (host.as_str(), 0)
+                .to_socket_addrs()
+                .map_err(|err| anyhow!("resolving allowed host {host}: {err}"))?
+                .filter_map(|address| match address {
+                    SocketAddr::V4(address) => Some((*address.ip(), 32)),
+                    SocketAddr::V6(_) => None,
+                })
+                .collect::<Vec<_>>();
+            if resolved.is_empty() {
+                return Err(anyhow!("allowed host {host} has no IPv4 addresses"));
+            }
+            routes.extend(resolved);
+        }
+    }
+    Ok(routes)
+}
+
+/// The mask of an IPv4 network with the given prefix length. The kernel won't add a route whose
+/// destination has bits set past the prefix.
+fn prefix_mask(prefix_len: u8) -> u32 {
+    u32::MAX
+        .checked_shl(32 - u32::from(prefix_len))
+        .unwrap_or(0)
+}
+
+/// Return whether any of the routes covers `address`.
+fn nat_routes_cover(routes: &[(Ipv4Addr, u8)], address: Ipv4Addr) -> bool {
+    routes.iter().any(|(destination, prefix_len)| {
+        u32::from(address) & prefix_mask(*prefix_len) == u32::from(*destination)
+    })
+}
+
+fn port_protocol_name(protocol: JobPortProtocol) -> &'static str {
+    match protocol {
+        JobPortProtocol::Tcp => "tcp",
+        JobPortProtocol::Udp => "udp",
+    }
+}
+
+/// Check that the host port of a port forward can be bound on the worker's loopback interface. This
+/// catches ports that some other process on the worker is using. Ports that have been handed to
+/// another job's slirp4netns, which might not have bound them yet, are tracked in
+/// [`JobStatusPipes`] instead.
+fn check_host_port_is_free(port_forward: &JobPortForward) -> Result<()> {
+    let address = (Ipv4Addr::LOCALHOST, port_forward.host_port);
+    let result = match port_forward.protocol {
+        JobPortProtocol::Tcp => TcpListener::bind(address).map(drop),
+        JobPortProtocol::Udp => UdpSocket::bind(address).map(drop),
+    };
+    result.map_err(|err| {
+        anyhow!(
+            "{} host port {} can't be forwarded into the job because it's in use on the worker: \
+            {err}",
+            port_protocol_name(port_forward.protocol),
+            port_forward.host_port,
+        )
+    })
+}
+
+/// Ask slirp4netns to forward ports on the worker's loopback interface into the job.
+fn add_port_forwards(api_socket: &Path, port_forwards: &[JobPortForward]) -> Result<()> {
+    for port_forward in port_forwards {
+        let proto = port_protocol_name(port_forward.protocol);
+        let request = json!({
+            "execute": "add_hostfwd",
+            "arguments": {
+                "proto": proto,
+                "host_addr": "127.0.0.1",
+                "host_port": port_forward.host_port,
+                "guest_port": port_forward.job_port,
+            },
+        });
+        // slirp4netns handles one request per connection.
+        let mut stream = UnixStream::connect(api_socket)?;
+        stream.write_all(request.to_string().as_bytes())?;
+        stream.shutdown(Shutdown::Write)?;
+        let response: serde_json::Value = serde_json::from_reader(stream)?;
+        if let Some(error) = response.get("error") {
+            // The port may have been taken since we checked it.
+            check_host_port_is_free(port_forward)?;
+            return Err(anyhow!(
+                "forwarding {proto} host port {} to job port {}: {error}",
+                port_forward.host_port,
+                port_forward.job_port
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// The extra pipes a job with [`JobNetwork::Nat`] needs. The job inherits the write end of the
+/// exit pipe, and slirp4netns exits once it's closed. That happens when the job and all of its
+/// descendants are gone. The child waits on the ready pipe until slirp4netns has created the NAT
+/// interface and its port forwards.
+struct NatPipes {
+    exit_read_fd: OwnedFd,
+    exit_write_fd: OwnedFd,
+    ready_read_fd: OwnedFd,
+    ready_write_fd: OwnedFd,
+}
+
+impl NatPipes {
+    fn new() -> Result<Self> {
+        let (exit_read_fd, exit_write_fd) = linux::pipe()?.map(OwnedFd::from_fd);
+        let (ready_read_fd, ready_write_fd) = linux::pipe()?.map(OwnedFd::from_fd);
+        Ok(NatPipes {
+            exit_read_fd,
+            exit_write_fd,
+            ready_read_fd,
+            ready_write_fd,
+        })
+    }
+}
+
+/// What the child of a job with [`JobNetwork::Nat`] writes to the exec result pipe once it is
+/// waiting for its network. It can't be confused with an error, since the index would be invalid.
+const EXEC_RESULT_AWAITING_NETWORK: u64 = u64::MAX;
+
+/// Read the next 8-byte record from the exec result pipe. This returns `None` if the pipe was
+/// closed without one, which means the child exec-ed successfully.
+fn read_exec_result(exec_result: &mut File) -> Result<Option<u64>> {
+    let mut buf = [0u8; 8];
+    let mut len = 0;
+    while len < buf.len() {
+        match exec_result.read(&mut buf[len..])? {
+            0 if len == 0 => return Ok(None),
+            0 => {
+                return Err(anyhow!(
+                    "couldn't parse exec result pipe's contents: {:?}",
+                    &buf[..len]
+                ))
+            }
+            read => len += read,
+        }
+    }
+    Ok(Some(u64::from_ne_bytes(buf)))
+}
+
+/// Split an exec result record into the index of the script item that failed and its errno.
+fn exec_result_error(result: u64) -> (usize, &'static str) {
+    let index = (result >> 32) as usize;
+    let errno = result & 0xffffffff;
+    (
+        index,
+        Errno::from_u64(errno).desc().unwrap_or("Unknown error"),
+    )
+}
+
+/// Wait for slirp4netns to create the NAT interface, then ask it to forward ports through the API
+/// socket in `api_socket_dir`. The directory is removed when this returns.
+fn wait_for_slirp4netns(
+    ready_read_fd: OwnedFd,
+    api_socket_dir: Option<TempDir>,
+    port_forwards: &[JobPortForward],
+) -> JobResult<(), Error> {
+    let mut ready = [0u8; 1];
+    let read = ready_read_fd
+        .into_file()
+        .read(&mut ready)
+        .map_err(Error::from)
+        .map_err(JobError::System)?;
+    if read == 0 {
+        return Err(JobError::System(anyhow!(
+            "slirp4netns exited before it was ready"
+        )));
+    }
+    if let Some(api_socket_dir) = api_socket_dir {
+        add_port_forwards(&api_socket_dir.path().join(API_SOCKET), port_forwards)
+            .map_err(JobError::Execution)?;
+    }
+    Ok(())
+}
+
+/// Everything a job with [`JobNetwork::Nat`] still needs after [`Executor::start`] returns. The
+/// child is waiting on `nat_ready_write_fd`, and then it will set up its network and exec.
+struct NatStartup {
+    child_pid: Pid,
+    slirp4netns_ready_read_fd: OwnedFd,
+    api_socket_dir: Option<TempDir>,
+    port_forwards: Vec<JobPortForward>,
+    nat_ready_write_fd: OwnedFd,
+    exec_result: File,
+    // The error transformers for the script items after the child started waiting on its network.
+    error_transformers: Vec<LateErrorTransformer>,
+    error_transformers_start: usize,
+}
+
+impl NatStartup {
+    fn finish(self) -> JobResult<(), Error> {
+        // Until we write to the ready pipe, the child is waiting on us. If anything goes wrong
+        // before then, kill it so that it never starts the job. After that, it exits on its own if
+        // it fails.
+        let ready = wait_for_slirp4netns(
+            self.slirp4netns_ready_read_fd,
+            self.api_socket_dir,
+            &self.port_forwards,
+        )
+        .and_then(|()| {
+            linux::write(self.nat_ready_write_fd.as_fd(), &[1])
+                .map_err(Error::from)
+                .map_err(JobError::System)
+        });
+        if let Err(err) = ready {
+            let _ = linux::kill(self.child_pid, Signal::KILL);
+            return Err(err);
+        }
+        let mut exec_result = self.exec_result;
+        match read_exec_result(&mut exec_result).map_err(JobError::System)? {
+            None => Ok(()),
+            Some(result) => {
+                let (index, errno) = exec_result_error(result);
+                let transformer = index
+                    .checked_sub(self.error_transformers_start)
+                    .and_then(|index| self.error_transformers.get(index))
+                    .ok_or_else(|| {
+                        JobError::System(anyhow!("unexpected exec result {result:#x}"))
+                    })?;
+                Err(transformer(errno))
+            }
+        }
+    }
+
+    /// Finish starting the job on its own thread. If that fails, report the error through
+    /// `startup_failed`. Either way, `done` is sent afterwards.
+    fn spawn(
+        self,
+        startup_failed: impl FnOnce(JobError<Error>) + Send + 'static,
+        done: oneshot::Sender<()>,
+    ) {
+        thread::spawn(move || {
+            if let Err(err) = self.finish() {
+                startup_failed(err);
+            }
+            let _ = done.send(());
+        });
+    }
+}
+
 impl Executor {
+    /// Write the uid and gid maps of the job with pid `child_pid`, mapping the subordinate ids
+    /// along with the job's user and group.
+    fn write_id_maps(&self, child_pid: Pid, spec: &JobSpec) -> Result<()> {
+        let maps = [
+            (
+                "uid_map",
+                subid::job_id_map(
+                    spec.user.as_u32(),
+                    self.user.as_u32(),
+                    self.subordinate_ids.uids,
+                ),
+            ),
+            (
+                "gid_map",
+                subid::job_id_map(
+                    spec.group.as_u32(),
+                    self.group.as_u32(),
+                    self.subordinate_ids.gids,
+                ),
+            ),
+        ];
+        for (file, contents) in maps {
+            let path = format!("/proc/{child_pid}/{file}");
+            fs::write(&path, contents).map_err(|err| anyhow!("writing to {path}: {err}"))?;
+        }
+        Ok(())
+    }
+
+    /// Start slirp4netns for the job with pid `child_pid`. This returns the read end of its ready
+    /// pipe, and the private directory its API socket is in, if it needs one for port forwards.
+    /// If `disable_host_loopback` is true, the job can't reach the worker's loopback interface
+    /// through the gateway.
+    fn spawn_slirp4netns(
+        &self,
+        child_pid: Pid,
+        options: &JobNatOptions,
+        disable_host_loopback: bool,
+        exit_read_fd: OwnedFd,
+    ) -> Result<(OwnedFd, Option<TempDir>)> {
+        let (ready_read_fd, ready_write_fd) = linux::pipe()?.map(OwnedFd::from_fd);
+        // The API socket lets anyone who can connect to it forward ports into the job, so it goes
+        // in a directory only we can get into.
+        let api_socket_dir = (!options.port_forwards.is_empty())
+            .then(|| {
+                tempfile::Builder::new()
+                    .prefix("maelstrom-slirp4netns-")
+                    .tempdir()
+            })
+            .transpose()?;
+
+        let mut command = Command::new(&self.slirp4netns);
+        command.arg("--ready-fd=3").arg("--exit-fd=0");
+        if disable_host_loopback {
+            command.arg("--disable-host-loopback");
+        }
+        if let Some(api_socket_dir) = &api_socket_dir {
+            command
+                .arg("--api-socket")
+                .arg(api_socket_dir.path().join(API_SOCKET));
+        }
+        command
+            .arg(child_pid.to_string())
+            .arg(NAT_INTERFACE)
+            .stdin(Stdio::from(exit_read_fd.into_file()))
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+        let ready_write = ready_write_fd.as_fd();
+        // SAFETY: This runs in the child after fork, so it may only make async-signal-safe calls.
+        // It just makes a couple of syscalls. We mark everything past the ready fd close-on-exec
+        // because the worker's other pipes aren't, and slirp4netns would otherwise hold them open.
+        unsafe {
+            command.pre_exec(move || {
+                linux::dup2(ready_write, Fd::from_raw(3))?;
+                linux::close_range(
+                    CloseRangeFirst::Fd(Fd::from_raw(4)),
+                    CloseRangeLast::Max,
+                    CloseRangeFlags::CLOEXEC,
+                )?;
+                Ok(())
+            });
+        }
+        // We don't wait on slirp4netns. The reaper will reap it when it exits.
+        command
+            .spawn()
+            .map_err(|err| anyhow!("starting {}: {err}", self.slirp4netns.display()))?;
+        Ok((ready_read_fd, api_socket_dir))
+    }
+
+    /// Resolve the source of a bind mount on the host, and check that it is under one of the
+    /// allowed paths. The source is canonicalized first so that symlinks can't be used to get
+    /// outside of the allowed paths.
+    fn check_bind_mount_source(&self, source: &Utf8PathBuf) -> Result<PathBuf> {
+        let canonical = Path::new(source)
+            .canonicalize()
+            .map_err(|err| anyhow!("bind mount source {source}: {err}"))?;
+        if !self
+            .allowed_bind_mounts
+            .iter()
+            .any(|allowed| canonical.starts_with(allowed))
+        {
+            return Err(anyhow!(
+                "bind mount source {source} is not allowed by this worker"
+            ));
+        }
+        Ok(canonical)
+    }
+
     fn start_inner(
         &self,
         spec: &JobSpec,
         inline_limit: InlineLimit,
         stdout_done: impl FnOnce(Result<JobOutputResult>) + Send + 'static,
         stderr_done: impl FnOnce(Result<JobOutputResult>) + Send + 'static,
+        startup_failed: impl FnOnce(JobError<Error>) + Send + 'static,
     ) -> JobResult<Pid, Error> {
-        // We're going to need three pipes: one for stdout, one for stderr, and one to convey back any
-        // error that occurs in the child before it execs. It's easiest to create the pipes in the
-        // parent before cloning and then closing the unnecessary ends in the parent and child.
+        // We're going to need four pipes: one for stdout, one for stderr, one to convey back any
+        // error that occurs in the child before it execs, and one for the job's init process to
+        // report the job's status on. It's easiest to create the pipes in the parent before cloning
+        // and then closing the unnecessary ends in the parent and child.
         let (stdout_read_fd, stdout_write_fd) = linux::pipe()
             .map_err(Error::from)
             .map_err(JobError::System)?
@@ -325,6 +976,65 @@ impl Executor {
             .map_err(Error::from)
             .map_err(JobError::System)?
             .map(OwnedFd::from_fd);
+        let (status_read_fd, status_write_fd) = linux::pipe()
+            .map_err(Error::from)
+            .map_err(JobError::System)?
+            .map(OwnedFd::from_fd);
+        // Another job's slirp4netns may inherit the write end, so we can't count on getting an EOF.
+        linux::fcntl_setfl(status_read_fd.as_fd(), OpenFlags::NONBLOCK)
+            .map_err(Error::from)
+            .map_err(JobError::System)?;
+        let nat = match &spec.network {
+            JobNetwork::Nat(options) => Some((options, NatPipes::new().map_err(JobError::System)?)),
+            JobNetwork::Disabled | JobNetwork::Loopback => None,
+        };
+        // With an allowlist, the job only gets routes to the allowed hosts. It loses the
+        // capabilities it would need to change its routes or to send raw packets, so it can't get
+        // around them. The gateway is on the NAT interface's network, so it's reachable without a
+        // route, and slirp4netns has to be told to keep the job off of the worker's loopback
+        // interface unless the gateway is allowed.
+        let (nat_route_messages, disable_host_loopback) = match &nat {
+            Some((options, _)) => {
+                let routes = nat_routes(&options.allowed_hosts).map_err(JobError::Execution)?;
+                let disable_host_loopback =
+                    !options.allowed_hosts.is_empty() && !nat_routes_cover(&routes, NAT_GATEWAY);
+                // The kernel won't add the same route twice, and it has already added one for the
+                // NAT interface's own network.
+                let nat_network = (
+                    Ipv4Addr::from(u32::from(NAT_ADDRESS) & prefix_mask(NAT_PREFIX_LEN)),
+                    NAT_PREFIX_LEN,
+                );
+                let routes = routes
+                    .into_iter()
+                    .filter(|(destination, prefix_len)| {
+                        *prefix_len < NAT_PREFIX_LEN
+                            || !nat_routes_cover(&[nat_network], *destination)
+                    })
+                    .collect::<HashSet<_>>();
+                (
+                    routes.into_iter().map(nat_route_message).collect(),
+                    disable_host_loopback,
+                )
+            }
+            None => (vec![], false),
+        };
+        // With subordinate ids, the parent writes the child's id maps, since the child can only map
+        // its one id itself. The child waits on the ready pipe until the parent has done that.
+        let parent_writes_id_maps = self.subordinate_ids != SubordinateIds::default();
+        let ready_pipe = if parent_writes_id_maps {
+            Some(
+                linux::pipe()
+                    .map_err(Error::from)
+                    .map_err(JobError::System)?
+                    .map(OwnedFd::from_fd),
+            )
+        } else {
+            None
+        };
+        let seccomp_programs = match &spec.seccomp {
+            Some(seccomp) => seccomp::compile(seccomp).map_err(JobError::Execution)?,
+            None => vec![],
+        };
 
         // Now we set up the script. This will be run in the child where we have to follow some
         // very stringent rules to avoid deadlocking. This comes about because we're going to clone
@@ -345,7 +1055,7 @@ impl Executor {
         let bump = Bump::new();
         let mut builder = ScriptBuilder::new(&bump);
 
-        if spec.enable_loopback {
+        if spec.network != JobNetwork::Disabled {
             // In order to have a loopback network interface, we need to create a netlink socket and
             // configure things with the kernel. This creates the socket.
             builder.push(
@@ -366,78 +1076,92 @@ impl Executor {
                 Syscall::WriteUsingSavedFd(self.netlink_message.as_ref()),
                 &|err| JobError::System(anyhow!("writing rtnetlink message: {err}")),
             );
-            // This receives the reply from the kernel.
-            // TODO: actually parse the reply to validate that we set up the loopback interface.
+            // This receives the acknowledgement from the kernel, and checks that it worked.
             let rtnetlink_response = bump.alloc_slice_fill_default(1024);
-            builder.push(Syscall::ReadUsingSavedFd(rtnetlink_response), &|err| {
-                JobError::System(anyhow!("receiving rtnetlink message: {err}"))
-            });
+            builder.push(
+                Syscall::ReceiveNetlinkAckUsingSavedFd(rtnetlink_response),
+                &|err| JobError::System(anyhow!("setting up loopback interface: {err}")),
+            );
             // We don't need to close the socket because that will happen automatically for us when we
             // exec.
         }
 
-        // We now need to set up the new user namespace. This first set of syscalls sets up the
-        // uid mapping.
-        let mut uid_map_contents = BumpString::with_capacity_in(24, &bump);
-        writeln!(uid_map_contents, "{} {} 1", spec.user, self.user)
-            .map_err(Error::new)
-            .map_err(JobError::System)?;
-        builder.push(
-            Syscall::OpenAndSaveFd(
-                c_str!("/proc/self/uid_map"),
-                OpenFlags::WRONLY | OpenFlags::TRUNC,
-                FileMode::default(),
-            ),
-            &|err| JobError::System(anyhow!("opening /proc/self/uid_map for writing: {err}")),
-        );
-        builder.push(
-            Syscall::WriteUsingSavedFd(uid_map_contents.into_bump_str().as_bytes()),
-            &|err| JobError::System(anyhow!("writing to /proc/self/uid_map: {err}")),
-        );
-        // We don't need to close the file because that will happen automatically for us when we
-        // exec.
-
-        // This set of syscalls disables setgroups, which is required for setting up the gid
-        // mapping.
-        builder.push(
-            Syscall::OpenAndSaveFd(
-                c_str!("/proc/self/setgroups"),
-                OpenFlags::WRONLY | OpenFlags::TRUNC,
-                FileMode::default(),
-            ),
-            &|err| JobError::System(anyhow!("opening /proc/self/setgroups for writing: {err}")),
-        );
-        builder.push(Syscall::WriteUsingSavedFd(b"deny\n"), &|err| {
-            JobError::System(anyhow!("writing to /proc/self/setgroups: {err}"))
-        });
-        // We don't need to close the file because that will happen automatically for us when we
-        // exec.
+        if !parent_writes_id_maps {
+            // We now need to set up the new user namespace. This first set of syscalls sets up the
+            // uid mapping.
+            let mut uid_map_contents = BumpString::with_capacity_in(24, &bump);
+            writeln!(uid_map_contents, "{} {} 1", spec.user, self.user)
+                .map_err(Error::new)
+                .map_err(JobError::System)?;
+            builder.push(
+                Syscall::OpenAndSaveFd(
+                    c_str!("/proc/self/uid_map"),
+                    OpenFlags::WRONLY | OpenFlags::TRUNC,
+                    FileMode::default(),
+                ),
+                &|err| JobError::System(anyhow!("opening /proc/self/uid_map for writing: {err}")),
+            );
+            builder.push(
+                Syscall::WriteUsingSavedFd(uid_map_contents.into_bump_str().as_bytes()),
+                &|err| JobError::System(anyhow!("writing to /proc/self/uid_map: {err}")),
+            );
+            // We don't need to close the file because that will happen automatically for us when we
+            // exec.
 
-        // Finally, we set up the gid mapping.
-        let mut gid_map_contents = BumpString::with_capacity_in(24, &bump);
-        writeln!(gid_map_contents, "{} {} 1", spec.group, self.group)
-            .map_err(Error::new)
-            .map_err(JobError::System)?;
-        builder.push(
-            Syscall::OpenAndSaveFd(
-                c_str!("/proc/self/gid_map"),
-                OpenFlags::WRONLY | OpenFlags::TRUNC,
-                FileMode::default(),
-            ),
-            &|err| JobError::System(anyhow!("opening /proc/self/gid_map for writing: {err}")),
-        );
-        builder.push(
-            Syscall::WriteUsingSavedFd(gid_map_contents.into_bump_str().as_bytes()),
-            &|err| JobError::System(anyhow!("writing to /proc/self/setgroups: {err}")),
-        );
-        // We don't need to close the file because that will happen automatically for us when we
-        // exec.
+            // This set of syscalls disables setgroups, which is required for setting up the gid
+            // mapping.
+            builder.push(
+                Syscall::OpenAndSaveFd(
+                    c_str!("/proc/self/setgroups"),
+                    OpenFlags::WRONLY | OpenFlags::TRUNC,
+                    FileMode::default(),
+                ),
+                &|err| JobError::System(anyhow!("opening /proc/self/setgroups for writing: {err}")),
+            );
+            builder.push(Syscall::WriteUsingSavedFd(b"deny\n"), &|err| {
+                JobError::System(anyhow!("writing to /proc/self/setgroups: {err}"))
+            });
+            // We don't need to close the file because that will happen automatically for us when we
+            // exec.
 
-        // Make the child process the leader of a new session and process group. If we didn't do
-        // this, then the process would be a member of a process group and session headed by a
-        // process outside of the pid namespace, which would be confusing.
-        builder.push(Syscall::SetSid, &|err| {
-            JobError::System(anyhow!("setsid: {err}"))
+            // Finally, we set up the gid mapping.
+            let mut gid_map_contents = BumpString::with_capacity_in(24, &bump);
+            writeln!(gid_map_contents, "{} {} 1", spec.group, self.group)
+                .map_err(Error::new)
+                .map_err(JobError::System)?;
+            builder.push(
+                Syscall::OpenAndSaveFd(
+                    c_str!("/proc/self/gid_map"),
+                    OpenFlags::WRONLY | OpenFlags::TRUNC,
+                    FileMode::default(),
+                ),
+                &|err| JobError::System(anyhow!("opening /proc/self/gid_map for writing: {err}")),
+            );
+            builder.push(
+                Syscall::WriteUsingSavedFd(gid_map_contents.into_bump_str().as_bytes()),
+                &|err| JobError::System(anyhow!("writing to /proc/self/setgroups: {err}")),
+            );
+            // We don't need to close the file because that will happen automatically for us when we
+            // exec.
+        }
+
+        if let Some((ready_read_fd, _)) = &ready_pipe {
+            // Wait for the parent to tell us that it has written our id maps.
+            builder.push(
+                Syscall::Read(ready_read_fd.as_fd(), bump.alloc_slice_fill_default(1)),
+                &|err| JobError::System(anyhow!("waiting for parent: {err}")),
+            );
+        }
+
+        // Set the hostname and domain name in the job's UTS namespace. A new UTS namespace starts
+        // with the worker's names, so we always set them to keep them from leaking into the job.
+        let hostname = spec.hostname.as_deref().unwrap_or(DEFAULT_HOSTNAME);
+        builder.push(Syscall::SetHostname(hostname.as_bytes()), &|err| {
+            JobError::Execution(anyhow!("sethostname: {err}"))
+        });
+        let domainname = spec.domainname.as_deref().unwrap_or(DEFAULT_DOMAINNAME);
+        builder.push(Syscall::SetDomainname(domainname.as_bytes()), &|err| {
+            JobError::Execution(anyhow!("setdomainname: {err}"))
         });
 
         // Dup2 the pipe file descriptors to be stdout and stderr. This will close the old stdout
@@ -449,19 +1173,46 @@ impl Executor {
             JobError::System(anyhow!("dup2-ing to stderr: {err}"))
         });
 
-        // Set close-on-exec for all file descriptors excecpt stdin, stdout, and stederr.
-        builder.push(
-            Syscall::CloseRange(
-                CloseRangeFirst::AfterStderr,
-                CloseRangeLast::Max,
-                CloseRangeFlags::CLOEXEC,
-            ),
-            &|err| {
-                JobError::System(anyhow!(
-                    "setting CLOEXEC on range of open file descriptors: {err}"
-                ))
-            },
-        );
+        // Set close-on-exec for all file descriptors excecpt stdin, stdout, and stederr. With NAT,
+        // the job also keeps the write end of the slirp4netns exit pipe.
+        let close_range_error = &|err: &'static str| {
+            JobError::System(anyhow!(
+                "setting CLOEXEC on range of open file descriptors: {err}"
+            ))
+        };
+        match &nat {
+            None => {
+                builder.push(
+                    Syscall::CloseRange(
+                        CloseRangeFirst::AfterStderr,
+                        CloseRangeLast::Max,
+                        CloseRangeFlags::CLOEXEC,
+                    ),
+                    close_range_error,
+                );
+            }
+            Some((_, pipes)) => {
+                let exit_fd = pipes.exit_write_fd.as_fd().as_c_int();
+                if exit_fd > Fd::STDERR.as_c_int() + 1 {
+                    builder.push(
+                        Syscall::CloseRange(
+                            CloseRangeFirst::AfterStderr,
+                            CloseRangeLast::Fd(Fd::from_raw(exit_fd - 1)),
+                            CloseRangeFlags::CLOEXEC,
+                        ),
+                        close_range_error,
+                    );
+                }
+                builder.push(
+                    Syscall::CloseRange(
+                        CloseRangeFirst::Fd(Fd::from_raw(exit_fd + 1)),
+                        CloseRangeLast::Max,
+                        CloseRangeFlags::CLOEXEC,
+                    ),
+                    close_range_error,
+                );
+            }
+        }
 
         // We can't use overlayfs with only a single layer. So we have to diverge based on how many
         // layers there are. If enable_writable_file_system, we're going to need at least two
@@ -521,7 +1272,15 @@ impl Executor {
             // both of them.
             if spec.enable_writable_file_system {
                 builder.push(
-                    Syscall::Mount(None, self.tmpfs_dir.as_c_str(), Some(c_str!("tmpfs")), MountFlags::default(), None),
+                    Syscall::Mount(
+                        None,
+                        self.tmpfs_dir.as_c_str(),
+                        Some(c_str!("tmpfs")),
+                        MountFlags::default(),
+                        self.writable_file_system_tmpfs_data
+                            .as_ref()
+                            .map(|data| data.as_bytes_with_nul()),
+                    ),
                     &|err| {
                         JobError::System(anyhow!(
                             "mounting tmpfs file system for overlayfs's upperdir and workdir: {err}"))
@@ -582,6 +1341,60 @@ impl Executor {
             );
         }
 
+        // Bind mounts from the host have to be done before pivot_root, while the sources are
+        // still reachable. That means they happen before all of the other mounts, regardless of
+        // where they appear in the spec.
+        for mount in &spec.mounts {
+            let JobMountFsType::Bind { source, read_only } = &mount.fs_type else {
+                continue;
+            };
+            let source = self
+                .check_bind_mount_source(source)
+                .map_err(JobError::Execution)?;
+            let source_cstr = bump_c_str_from_bytes(&bump, source.as_os_str().as_bytes())
+                .map_err(JobError::System)?;
+            let mut target =
+                BumpString::with_capacity_in(mount.mount_point.as_str().len() + 2, &bump);
+            target.push_str("./");
+            target.push_str(mount.mount_point.as_str().trim_start_matches('/'));
+            let target = bump_c_str(&bump, target.into_bump_str()).map_err(JobError::System)?;
+            let mount_point = mount.mount_point.as_str();
+            builder.push(
+                Syscall::Mount(Some(source_cstr), target, None, MountFlags::BIND, None),
+                // We have to be careful doing bump.alloc here with the move. The drop method is
+                // not going to be run on the closure, which means drop won't be run on any
+                // captured-and-moved variables. Since mount_point is just a reference, we're
+                // okay.
+                bump.alloc(move |err| {
+                    JobError::Execution(anyhow!("bind mount to {mount_point}: {err}"))
+                }),
+            );
+            if *read_only {
+                // MS_RDONLY is ignored on the initial bind mount, so it takes a remount. In a user
+                // namespace, the remount also has to keep any flags the source's mount has, since
+                // those are locked. Otherwise, the remount fails with EPERM.
+                let locked_flags = linux::statvfs_mount_flags(source_cstr)
+                    .map_err(|err| {
+                        anyhow!("statvfs of bind mount source {}: {err}", source.display())
+                    })
+                    .map_err(JobError::Execution)?;
+                builder.push(
+                    Syscall::Mount(
+                        None,
+                        target,
+                        None,
+                        MountFlags::REMOUNT | MountFlags::BIND | MountFlags::RDONLY | locked_flags,
+                        None,
+                    ),
+                    bump.alloc(move |err| {
+                        JobError::Execution(anyhow!(
+                            "remounting bind mount at {mount_point} as read-only: {err}"
+                        ))
+                    }),
+                );
+            }
+        }
+
         // Pivot root to be the new root. See man 2 pivot_root.
         builder.push(Syscall::PivotRoot(c_str!("."), c_str!(".")), &|err| {
             JobError::System(anyhow!("pivot_root: {err}"))
@@ -600,18 +1413,38 @@ impl Executor {
         for (mount, mount_point) in iter::zip(spec.mounts.iter(), child_mount_points) {
             let mount_point_cstr = mount_point.map_err(Error::from).map_err(JobError::System)?;
 
-            let (fs_type, flags, type_name) = match mount.fs_type {
+            let (fs_type, flags, data, type_name) = match mount.fs_type {
                 JobMountFsType::Proc => (
                     c_str!("proc"),
                     MountFlags::NOSUID | MountFlags::NOEXEC | MountFlags::NODEV,
+                    None,
                     "proc",
                 ),
-                JobMountFsType::Tmp => (c_str!("tmpfs"), MountFlags::default(), "tmpfs"),
-                JobMountFsType::Sys => (c_str!("sysfs"), MountFlags::default(), "sysfs"),
+                JobMountFsType::Tmp(options) => (
+                    c_str!("tmpfs"),
+                    MountFlags::default(),
+                    tmpfs_mount_data(&bump, &options),
+                    "tmpfs",
+                ),
+                JobMountFsType::Sys => (c_str!("sysfs"), MountFlags::default(), None, "sysfs"),
+                JobMountFsType::Devpts => (
+                    c_str!("devpts"),
+                    MountFlags::NOSUID | MountFlags::NOEXEC,
+                    Some(b"newinstance,ptmxmode=0666,mode=0620\0".as_slice()),
+                    "devpts",
+                ),
+                JobMountFsType::Mqueue => (
+                    c_str!("mqueue"),
+                    MountFlags::NOSUID | MountFlags::NOEXEC | MountFlags::NODEV,
+                    None,
+                    "mqueue",
+                ),
+                // These were done before pivot_root.
+                JobMountFsType::Bind { .. } => continue,
             };
             let mount_point = mount.mount_point.as_str();
             builder.push(
-                Syscall::Mount(None, mount_point_cstr, Some(fs_type), flags, None),
+                Syscall::Mount(None, mount_point_cstr, Some(fs_type), flags, data),
                 // We have to be careful doing bump.alloc here with the move. The drop method is
                 // not going to be run on the closure, which means drop won't be run on any
                 // captured-and-moved variables. Since we're using a static string for `type_name`,
@@ -640,6 +1473,119 @@ impl Executor {
             });
         }
 
+        // With NAT, everything after this may happen after we've returned to the caller. We tell
+        // the parent that we're waiting for slirp4netns, which joins our user namespace, so this
+        // has to come after the id mappings. Then we look up the interface slirp4netns created,
+        // bring it up, give it an address, and add the routes. This has to come before the
+        // resource limits, which could keep us from opening the rtnetlink socket.
+        let late_error_transformers_start = builder.syscalls.len();
+        if let Some((options, pipes)) = &nat {
+            builder.push_late(
+                Syscall::Write(
+                    exec_result_write_fd.as_fd(),
+                    bump.alloc(EXEC_RESULT_AWAITING_NETWORK.to_ne_bytes()),
+                ),
+                |err| JobError::System(anyhow!("writing to exec result pipe: {err}")),
+            );
+            builder.push_late(
+                Syscall::Read(
+                    pipes.ready_read_fd.as_fd(),
+                    bump.alloc_slice_fill_default(1),
+                ),
+                |err| JobError::System(anyhow!("waiting for slirp4netns: {err}")),
+            );
+            builder.push_late(
+                Syscall::SocketAndSaveFd(
+                    SocketDomain::NETLINK,
+                    SocketType::RAW,
+                    SocketProtocol::NETLINK_ROUTE,
+                ),
+                |err| JobError::System(anyhow!("opening rtnetlink socket: {err}")),
+            );
+            builder.push_late(
+                Syscall::BindNetlinkUsingSavedFd(&self.netlink_socket_addr),
+                |err| JobError::System(anyhow!("binding rtnetlink socket: {err}")),
+            );
+            builder.push_late(
+                Syscall::GetInterfaceIndexUsingSavedFdAndSaveIt(
+                    bump_c_str(&bump, NAT_INTERFACE).map_err(JobError::System)?,
+                ),
+                |err| JobError::System(anyhow!("finding NAT interface: {err}")),
+            );
+            let messages = [
+                (&self.nat_link_message, "bringing up NAT interface"),
+                (&self.nat_address_message, "adding NAT interface address"),
+            ];
+            for (message, what) in messages {
+                builder.push_late(
+                    Syscall::WriteWithSavedInterfaceIndexUsingSavedFd(
+                        bump.alloc_slice_copy(message),
+                        NETLINK_INTERFACE_INDEX_OFFSET,
+                    ),
+                    |err| JobError::System(anyhow!("writing rtnetlink message: {err}")),
+                );
+                let rtnetlink_response = bump.alloc_slice_fill_default(1024);
+                builder.push_late(
+                    Syscall::ReceiveNetlinkAckUsingSavedFd(rtnetlink_response),
+                    move |err| JobError::System(anyhow!("{what}: {err}")),
+                );
+            }
+            for message in &nat_route_messages {
+                builder.push_late(Syscall::WriteUsingSavedFd(message.as_ref()), |err| {
+                    JobError::System(anyhow!("writing rtnetlink message: {err}"))
+                });
+                let rtnetlink_response = bump.alloc_slice_fill_default(1024);
+                builder.push_late(
+                    Syscall::ReceiveNetlinkAckUsingSavedFd(rtnetlink_response),
+                    |err| JobError::System(anyhow!("adding NAT route: {err}")),
+                );
+            }
+            if !options.allowed_hosts.is_empty() {
+                for capability in [Capability::NET_ADMIN, Capability::NET_RAW] {
+                    builder.push_late(Syscall::DropBoundingCapability(capability), |err| {
+                        JobError::System(anyhow!("dropping network capabilities: {err}"))
+                    });
+                }
+            }
+        }
+
+        // Fork the job. The child process stays behind as the init process of the pid namespace,
+        // since the kernel only delivers signals to an init process that has handlers for them. It
+        // forwards the signals it gets to the job, and reports the job's status when it terminates.
+        // Everything after this is done by the job itself, and doesn't apply to the init process.
+        builder.push_late(Syscall::ForkAndBecomeInit(status_write_fd.as_fd()), |err| {
+            JobError::System(anyhow!("forking job: {err}"))
+        });
+
+        // Make the job the leader of a new session and process group. If we didn't do this, then
+        // the job would be a member of the init process's process group and session.
+        builder.push_late(Syscall::SetSid, |err| {
+            JobError::System(anyhow!("setsid: {err}"))
+        });
+
+        // Set the resource limits.
+        for rlimit in &spec.rlimits {
+            let (resource, name) = rlimit_resource(rlimit.resource);
+            let rlimit = Rlimit::new(rlimit_value(rlimit.soft), rlimit_value(rlimit.hard));
+            builder.push_late(Syscall::SetRlimit(resource, rlimit), move |err| {
+                JobError::Execution(anyhow!("setrlimit of {name}: {err}"))
+            });
+        }
+
+        // Install the seccomp filters. This has to be the last thing before the exec, since the
+        // filters apply to everything we do after installing them.
+        if !seccomp_programs.is_empty() {
+            builder.push_late(Syscall::SetNoNewPrivs, |err| {
+                JobError::System(anyhow!("prctl(PR_SET_NO_NEW_PRIVS): {err}"))
+            });
+            for program in &seccomp_programs {
+                let program = bump.alloc(SockFprog::new(bump.alloc_slice_copy(program)));
+                builder.push_late(Syscall::SeccompSetModeFilter(program), |err| {
+                    JobError::System(anyhow!("installing seccomp filter: {err}"))
+                });
+            }
+        }
+
         // Finally, do the exec.
         let program = bump_c_str(&bump, spec.program.as_str()).map_err(JobError::System)?;
         let mut arguments =
@@ -657,13 +1603,13 @@ impl Executor {
             environment.push(Some(&var_cstr.to_bytes_with_nul()[0]));
         }
         environment.push(None);
-        builder.push(
+        builder.push_late(
             Syscall::Execve(
                 program,
                 arguments.into_bump_slice(),
                 environment.into_bump_slice(),
             ),
-            &|err| JobError::Execution(anyhow!("execvc: {err}")),
+            |err| JobError::Execution(anyhow!("execvc: {err}")),
         );
 
         // Do the clone.
@@ -674,9 +1620,20 @@ impl Executor {
                     | CloneFlags::NEWNET
                     | CloneFlags::NEWNS
                     | CloneFlags::NEWPID
-                    | CloneFlags::NEWUSER,
+                    | CloneFlags::NEWUSER
+                    | CloneFlags::NEWUTS,
             )
             .exit_signal(Signal::CHLD);
+        // Hold the lock until we've registered the status pipe, so the child can't be reaped first,
+        // and until we've claimed the job's host ports, so another job can't claim them too.
+        let mut running_jobs = self.job_status_pipes.0.lock().unwrap();
+        let port_forwards = match &nat {
+            Some((options, _)) => options.port_forwards.as_slice(),
+            None => &[],
+        };
+        running_jobs
+            .check_host_ports(port_forwards)
+            .map_err(JobError::Execution)?;
         let child_pid = match linux::clone3(&mut clone_args) {
             Ok(Some(child_pid)) => child_pid,
             Ok(None) => {
@@ -690,6 +1647,15 @@ impl Executor {
                 return Err(JobError::System(err.into()));
             }
         };
+        running_jobs
+            .status_pipes
+            .insert(child_pid, status_read_fd.into_file());
+        for port_forward in port_forwards {
+            running_jobs
+                .host_ports
+                .insert((port_forward.protocol, port_forward.host_port), child_pid);
+        }
+        drop(running_jobs);
 
         // At this point, it's still okay to return early in the parent. The child will continue to
         // execute, but that's okay. If it writes to one of the pipes, it will receive a SIGPIPE.
@@ -701,56 +1667,102 @@ impl Executor {
         drop(stdout_write_fd);
         drop(stderr_write_fd);
         drop(exec_result_write_fd);
+        drop(status_write_fd);
+
+        if let Some((ready_read_fd, ready_write_fd)) = ready_pipe {
+            drop(ready_read_fd);
+            // Until we write to the ready pipe, the child hasn't started the job. If anything goes
+            // wrong before then, kill it so that it never does.
+            if let Err(err) = self.write_id_maps(child_pid, spec) {
+                let _ = linux::kill(child_pid, Signal::KILL);
+                return Err(JobError::System(err));
+            }
+            linux::write(ready_write_fd.as_fd(), &[1])
+                .map_err(Error::from)
+                .map_err(JobError::System)?;
+        }
 
         // Read (in a blocking manner) from the exec result pipe. The child will write to the pipe if
         // it has an error exec-ing. The child will mark the write side of the pipe exec-on-close, so
-        // we'll read an immediate EOF if the exec is successful.
-        let mut exec_result_buf = vec![];
-        exec_result_read_fd
-            .into_file()
-            .read_to_end(&mut exec_result_buf)
-            .map_err(Error::from)
-            .map_err(JobError::System)?;
-        if !exec_result_buf.is_empty() {
-            if exec_result_buf.len() != 8 {
-                return Err(JobError::System(anyhow!(
-                    "couldn't parse exec result pipe's contents: {exec_result_buf:?}"
-                )));
-            }
-            let result = u64::from_ne_bytes(exec_result_buf.try_into().unwrap());
-            let index = (result >> 32) as usize;
-            let errno = result & 0xffffffff;
-            return Err(builder.error_transformers[index](
-                Errno::from_u64(errno).desc().unwrap_or("Unknown error"),
-            ));
+        // we'll read an immediate EOF if the exec is successful. With NAT, the child instead tells
+        // us when it's waiting for its network.
+        let mut exec_result = exec_result_read_fd.into_file();
+        if let Some(result) = read_exec_result(&mut exec_result)
+            .map_err(JobError::System)?
+            .filter(|result| *result != EXEC_RESULT_AWAITING_NETWORK)
+        {
+            let (index, errno) = exec_result_error(result);
+            return Err(builder.error_transformers[index](errno));
         }
 
-        // Make the read side of the stdout and stderr pipes non-blocking so that we can use them with
-        // Tokio.
-        linux::fcntl_setfl(stdout_read_fd.as_fd(), OpenFlags::NONBLOCK)
-            .map_err(Error::from)
-            .map_err(JobError::System)?;
-        linux::fcntl_setfl(stderr_read_fd.as_fd(), OpenFlags::NONBLOCK)
-            .map_err(Error::from)
-            .map_err(JobError::System)?;
+        // With NAT, the child won't go any further until slirp4netns is ready. Starting slirp4netns
+        // is quick, but waiting for it isn't, so that's left to another thread, along with reading
+        // the rest of the exec result. From here on, we have to kill the child if we fail, since it
+        // would otherwise wait forever.
+        let nat_startup = match nat {
+            None => None,
+            Some((options, pipes)) => {
+                let NatPipes {
+                    exit_read_fd,
+                    exit_write_fd,
+                    ready_read_fd,
+                    ready_write_fd,
+                } = pipes;
+                drop(exit_write_fd);
+                drop(ready_read_fd);
+                match self.spawn_slirp4netns(
+                    child_pid,
+                    options,
+                    disable_host_loopback,
+                    exit_read_fd,
+                ) {
+                    Ok((slirp4netns_ready_read_fd, api_socket_dir)) => Some(NatStartup {
+                        child_pid,
+                        slirp4netns_ready_read_fd,
+                        api_socket_dir,
+                        port_forwards: options.port_forwards.clone(),
+                        nat_ready_write_fd: ready_write_fd,
+                        exec_result,
+                        error_transformers: mem::take(&mut builder.late_error_transformers),
+                        error_transformers_start: late_error_transformers_start,
+                    }),
+                    Err(err) => {
+                        let _ = linux::kill(child_pid, Signal::KILL);
+                        return Err(JobError::System(err));
+                    }
+                }
+            }
+        };
+
+        let output_files = output_file(stdout_read_fd)
+            .and_then(|stdout| Ok((stdout, output_file(stderr_read_fd)?)));
+        let (stdout, stderr) = match output_files {
+            Ok(output_files) => output_files,
+            Err(err) => {
+                if nat_startup.is_some() {
+                    let _ = linux::kill(child_pid, Signal::KILL);
+                }
+                return Err(JobError::System(err));
+            }
+        };
+
+        let startup_done = nat_startup.map(|nat_startup| {
+            let (startup_done_sender, startup_done) = oneshot::channel();
+            nat_startup.spawn(startup_failed, startup_done_sender);
+            startup_done
+        });
 
         // Spawn reader tasks to consume stdout and stderr.
         task::spawn(output_reader_task_main(
             inline_limit,
-            AsyncFile(
-                AsyncFd::new(stdout_read_fd.into_file())
-                    .map_err(Error::from)
-                    .map_err(JobError::System)?,
-            ),
+            stdout,
+            startup_done,
             stdout_done,
         ));
         task::spawn(output_reader_task_main(
             inline_limit,
-            AsyncFile(
-                AsyncFd::new(stderr_read_fd.into_file())
-                    .map_err(Error::from)
-                    .map_err(JobError::System)?,
-            ),
+            stderr,
+            None,
             stderr_done,
         ));
 
@@ -771,22 +1783,29 @@ mod tests {
     use super::*;
     use crate::reaper::{self, ReaperDeps};
     use assert_matches::*;
-    use maelstrom_base::{nonempty, ArtifactType, JobStatus};
+    use maelstrom_base::{
+        nonempty, ArtifactType, JobSeccompAction, JobSeccompPolicy, JobSeccompRule, JobStatus,
+    };
     use maelstrom_test::{boxed_u8, digest, utf8_path_buf};
     use serial_test::serial;
-    use std::ops::ControlFlow;
+    use std::{ops::ControlFlow, os::unix::fs::PermissionsExt as _};
     use tar::Archive;
     use tempfile::TempDir;
     use tokio::sync::oneshot;
 
     struct ReaperAdapter {
         pid: Pid,
+        job_status_pipes: JobStatusPipes,
         result: Option<JobStatus>,
     }
 
     impl ReaperAdapter {
-        fn new(pid: Pid) -> Self {
-            ReaperAdapter { pid, result: None }
+        fn new(pid: Pid, job_status_pipes: JobStatusPipes) -> Self {
+            ReaperAdapter {
+                pid,
+                job_status_pipes,
+                result: None,
+            }
         }
     }
 
@@ -799,7 +1818,7 @@ mod tests {
         }
         fn on_child_termination(&mut self, pid: Pid, status: JobStatus) -> ControlFlow<()> {
             if self.pid == pid {
-                self.result = Some(status);
+                self.result = Some(self.job_status_pipes.job_status(pid, status));
                 ControlFlow::Break(())
             } else {
                 ControlFlow::Continue(())
@@ -819,20 +1838,32 @@ mod tests {
 
     struct Test {
         spec: JobSpec,
+        allowed_bind_mounts: Vec<PathBuf>,
+        writable_file_system_size: Option<u64>,
+        slirp4netns: PathBuf,
+        subordinate_ids: SubordinateIds,
         inline_limit: InlineLimit,
         expected_status: JobStatus,
         expected_stdout: JobOutputResult,
         expected_stderr: JobOutputResult,
+        expected_startup_error: Option<JobError<String>>,
+        signal: Option<Signal>,
     }
 
     impl Test {
         fn new(spec: JobSpec) -> Self {
             Test {
                 spec,
+                allowed_bind_mounts: vec![],
+                writable_file_system_size: None,
+                slirp4netns: "slirp4netns".into(),
+                subordinate_ids: SubordinateIds::default(),
                 inline_limit: InlineLimit::from(1000),
                 expected_status: JobStatus::Exited(0),
                 expected_stdout: JobOutputResult::None,
                 expected_stderr: JobOutputResult::None,
+                expected_startup_error: None,
+                signal: None,
             }
         }
 
@@ -841,6 +1872,26 @@ mod tests {
             Self::new(spec)
         }
 
+        fn allowed_bind_mounts(mut self, allowed_bind_mounts: Vec<PathBuf>) -> Self {
+            self.allowed_bind_mounts = allowed_bind_mounts;
+            self
+        }
+
+        fn writable_file_system_size(mut self, size: u64) -> Self {
+            self.writable_file_system_size = Some(size);
+            self
+        }
+
+        fn slirp4netns(mut self, slirp4netns: impl Into<PathBuf>) -> Self {
+            self.slirp4netns = slirp4netns.into();
+            self
+        }
+
+        fn subordinate_ids(mut self, uids: u32, gids: u32) -> Self {
+            self.subordinate_ids = SubordinateIds { uids, gids };
+            self
+        }
+
         fn inline_limit(mut self, inline_limit: impl Into<InlineLimit>) -> Self {
             self.inline_limit = inline_limit.into();
             self
@@ -861,13 +1912,30 @@ mod tests {
             self
         }
 
+        fn signal(mut self, signal: Signal) -> Self {
+            self.signal = Some(signal);
+            self
+        }
+
+        fn expected_startup_error(mut self, expected_startup_error: JobError<String>) -> Self {
+            self.expected_startup_error = Some(expected_startup_error);
+            self
+        }
+
         async fn run(&self) {
             let dummy_child_pid = reaper::clone_dummy_child().unwrap();
             let (stdout_tx, stdout_rx) = oneshot::channel();
             let (stderr_tx, stderr_rx) = oneshot::channel();
+            let (startup_tx, startup_rx) = std::sync::mpsc::channel();
+            let job_status_pipes = JobStatusPipes::default();
             let start_result = Executor::new(
                 tempfile::tempdir().unwrap().into_path(),
                 tempfile::tempdir().unwrap().into_path(),
+                self.allowed_bind_mounts.clone(),
+                self.writable_file_system_size,
+                self.slirp4netns.clone(),
+                self.subordinate_ids,
+                job_status_pipes.clone(),
             )
             .unwrap()
             .start(
@@ -875,23 +1943,29 @@ mod tests {
                 self.inline_limit,
                 |stdout| stdout_tx.send(stdout.unwrap()).unwrap(),
                 |stderr| stderr_tx.send(stderr.unwrap()).unwrap(),
+                move |err| startup_tx.send(err.map(|err| err.to_string())).unwrap(),
             );
             assert_matches!(start_result, Ok(_));
             let Ok(pid) = start_result else {
                 unreachable!();
             };
+            if let Some(signal) = self.signal {
+                linux::kill(pid, signal).unwrap();
+            }
             let reaper = task::spawn_blocking(move || {
-                let mut adapter = ReaperAdapter::new(pid);
+                let mut adapter = ReaperAdapter::new(pid, job_status_pipes.clone());
                 reaper::main(&mut adapter, dummy_child_pid);
                 let result = adapter.result.unwrap();
                 linux::kill(dummy_child_pid, Signal::KILL).ok();
-                let mut adapter = ReaperAdapter::new(dummy_child_pid);
+                let mut adapter = ReaperAdapter::new(dummy_child_pid, job_status_pipes);
                 reaper::main(&mut adapter, Pid::new_for_test(0));
                 result
             });
             assert_eq!(reaper.await.unwrap(), self.expected_status);
             assert_eq!(stdout_rx.await.unwrap(), self.expected_stdout);
             assert_eq!(stderr_rx.await.unwrap(), self.expected_stderr);
+            // A startup error is always reported before stdout.
+            assert_eq!(startup_rx.try_recv().ok(), self.expected_startup_error);
         }
     }
 
@@ -927,7 +2001,7 @@ mod tests {
     // input from stdin.
     #[tokio::test]
     #[serial]
-    async fn signaled_11() {
+    async fn signaled_6() {
         Test::from_spec(python_spec(concat!(
             "import os;",
             "import sys;",
@@ -937,7 +2011,7 @@ mod tests {
             "sys.stderr.flush();",
             "os.abort()",
         )))
-        .expected_status(JobStatus::Signaled(11))
+        .expected_status(JobStatus::Signaled(6))
         .expected_stdout(JobOutputResult::Inline(boxed_u8!(b"a\n")))
         .expected_stderr(JobOutputResult::Inline(boxed_u8!(b"b\n")))
         .run()
@@ -1054,10 +2128,10 @@ mod tests {
     #[tokio::test]
     #[serial]
     async fn pid_ppid_pgid_and_sid() {
-        // We should be pid 1, that is, init for our namespace).
-        // We should have ppid 0, indicating that our parent isn't accessible in our namespace.
-        // We should have pgid 1, indicating that we're the group leader.
-        // We should have sid 1, indicating that we're the session leader.
+        // We should be pid 2, since pid 1 is the init process for our namespace.
+        // We should have ppid 1, since the init process forked us.
+        // We should have pgid 2, indicating that we're the group leader.
+        // We should have sid 2, indicating that we're the session leader.
         Test::from_spec(python_spec(concat!(
             "import os;",
             "print('pid:', os.getpid());",
@@ -1066,12 +2140,24 @@ mod tests {
             "print('sid:', os.getsid(0));",
         )))
         .expected_stdout(JobOutputResult::Inline(boxed_u8!(
-            b"pid: 1\nppid: 0\npgid: 1\nsid: 1\n"
+            b"pid: 2\nppid: 1\npgid: 2\nsid: 2\n"
         )))
         .run()
         .await;
     }
 
+    #[tokio::test]
+    #[serial]
+    async fn signal_is_forwarded_to_job() {
+        // The job doesn't install a handler for SIGTERM, so it would be dropped if the job were
+        // the init process of its pid namespace.
+        Test::from_spec(python_spec("import time; time.sleep(100)"))
+            .signal(Signal::TERM)
+            .expected_status(JobStatus::Signaled(Signal::TERM.as_u8()))
+            .run()
+            .await;
+    }
+
     #[tokio::test]
     #[serial]
     async fn no_loopback() {
@@ -1101,13 +2187,399 @@ mod tests {
                     fs_type: JobMountFsType::Sys,
                     mount_point: utf8_path_buf!("/sys"),
                 }])
-                .enable_loopback(true),
+                .network(JobNetwork::Loopback),
         )
         .expected_stdout(JobOutputResult::Inline(boxed_u8!(b"1\n")))
         .run()
         .await;
     }
 
+    fn ptrace_spec() -> maelstrom_base::JobSpec {
+        python_spec(concat!(
+            "import ctypes;",
+            "libc = ctypes.CDLL(None, use_errno=True);",
+            "print(libc.ptrace(0, 0, 0, 0), ctypes.get_errno())",
+        ))
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn no_seccomp() {
+        Test::from_spec(ptrace_spec())
+            .expected_stdout(JobOutputResult::Inline(boxed_u8!(b"0 0\n")))
+            .run()
+            .await;
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn seccomp_default_profile() {
+        Test::from_spec(ptrace_spec().seccomp(JobSeccomp::Named("default".into())))
+            .expected_stdout(JobOutputResult::Inline(boxed_u8!(b"-1 1\n")))
+            .run()
+            .await;
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn seccomp_inline_errno() {
+        Test::from_spec(ptrace_spec().seccomp(JobSeccomp::Inline(JobSeccompPolicy {
+            default_action: JobSeccompAction::Allow,
+            rules: vec![JobSeccompRule {
+                syscalls: vec!["ptrace".into()],
+                action: JobSeccompAction::Errno(22),
+            }],
+        })))
+        .expected_stdout(JobOutputResult::Inline(boxed_u8!(b"-1 22\n")))
+        .run()
+        .await;
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn seccomp_strict_profile_kills() {
+        Test::from_spec(ptrace_spec().seccomp(JobSeccomp::Named("strict".into())))
+            .expected_status(JobStatus::Signaled(Signal::SYS.as_u8()))
+            .run()
+            .await;
+    }
+
+    /// Write a stand-in for slirp4netns to `dir`. It records its arguments in `dir/args`, creates
+    /// the interface, says it's ready, and then waits for its exit fd to be closed before touching
+    /// `dir/exited`. The interface is just a tap device with nothing on the other end.
+    fn fake_slirp4netns(dir: &Path) -> PathBuf {
+        let slirp4netns = dir.join("slirp4netns");
+        std::fs::write(
+            &slirp4netns,
+            format!(
+                concat!(
+                    "#!/bin/sh\n",
+                    "echo \"$@\" > {dir}/args\n",
+                    "eval pid=\\${{$(($# - 1))}} interface=\\${{$#}}\n",
+                    "nsenter --preserve-credentials -U -n -t \"$pid\" ",
+                    "ip tuntap add \"$interface\" mode tap\n",
+                    "printf 1 >&3\n",
+                    "cat >/dev/null\n",
+                    "touch {dir}/exited\n",
+                ),
+                dir = dir.display()
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&slirp4netns, std::fs::Permissions::from_mode(0o755)).unwrap();
+        slirp4netns
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn nat_runs_slirp4netns_until_job_exits() {
+        let dir = TempDir::new().unwrap();
+        let slirp4netns = fake_slirp4netns(dir.path());
+
+        Test::from_spec(
+            test_spec("/bin/cat")
+                .arguments(["/sys/class/net/tap0/flags"])
+                .mounts([JobMount {
+                    fs_type: JobMountFsType::Sys,
+                    mount_point: utf8_path_buf!("/sys"),
+                }])
+                .network(JobNetwork::Nat(Default::default())),
+        )
+        .slirp4netns(&slirp4netns)
+        .expected_stdout(JobOutputResult::Inline(boxed_u8!(b"0x1003\n")))
+        .run()
+        .await;
+
+        let exited = dir.path().join("exited");
+        for _ in 0..100 {
+            if exited.exists() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+        assert!(exited.exists());
+        let args = std::fs::read_to_string(dir.path().join("args")).unwrap();
+        assert!(args.starts_with("--ready-fd=3 --exit-fd=0 "), "{args}");
+        assert!(args.ends_with(" tap0\n"), "{args}");
+    }
+
+    #[test]
+    #[serial]
+    fn nat_without_slirp4netns_is_a_system_error() {
+        let spec = JobSpec::from_spec_and_layers(
+            test_spec("/bin/true").network(JobNetwork::Nat(Default::default())),
+            NonEmpty::new(extract_dependencies()),
+        );
+        let result = Executor::new(
+            tempfile::tempdir().unwrap().into_path(),
+            tempfile::tempdir().unwrap().into_path(),
+            vec![],
+            None,
+            "/a/slirp4netns/that/does/not/exist".into(),
+            SubordinateIds::default(),
+            JobStatusPipes::default(),
+        )
+        .unwrap()
+        .start(
+            &spec,
+            0.into(),
+            |_| unreachable!(),
+            |_| unreachable!(),
+            |_| unreachable!(),
+        );
+        assert_matches!(
+            result,
+            Err(JobError::System(err)) if err.to_string().starts_with("starting /a/slirp4netns")
+        );
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn nat_interface_missing() {
+        // A stand-in for slirp4netns that says it's ready without creating the interface.
+        let dir = TempDir::new().unwrap();
+        let slirp4netns = dir.path().join("slirp4netns");
+        std::fs::write(&slirp4netns, "#!/bin/sh\nprintf 1 >&3\ncat >/dev/null\n").unwrap();
+        std::fs::set_permissions(&slirp4netns, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        Test::from_spec(test_spec("/bin/true").network(JobNetwork::Nat(Default::default())))
+            .slirp4netns(&slirp4netns)
+            .expected_status(JobStatus::Exited(1))
+            .expected_startup_error(JobError::System(
+                "finding NAT interface: No such device".into(),
+            ))
+            .run()
+            .await;
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn nat_allowed_hosts_are_enforced() {
+        let dir = TempDir::new().unwrap();
+        let slirp4netns = fake_slirp4netns(dir.path());
+
+        // The job prints its routes, whether it can still get the network capabilities after exec,
+        // and whether it can open a raw socket.
+        Test::from_spec(
+            python_spec(concat!(
+                "import socket, struct\n",
+                "def ip(h): return socket.inet_ntoa(struct.pack('<I', int(h, 16)))\n",
+                "for line in sorted(open('/proc/net/route').readlines()[1:]):\n",
+                "    f = line.split(); print(ip(f[1]), ip(f[2]), ip(f[7]))\n",
+                "status = open('/proc/self/status').read().splitlines()\n",
+                "bounding = [l for l in status if l.startswith('CapBnd:')][0]\n",
+                "print(int(bounding.split()[1], 16) >> 12 & 3)\n",
+                "try:\n",
+                "    socket.socket(socket.AF_PACKET, socket.SOCK_RAW)\n",
+                "except PermissionError:\n",
+                "    print('no raw sockets')\n",
+            ))
+            .mounts([JobMount {
+                fs_type: JobMountFsType::Proc,
+                mount_point: utf8_path_buf!("/proc"),
+            }])
+            .network(JobNetwork::Nat(JobNatOptions {
+                allowed_hosts: vec!["192.0.2.7/24".into()],
+                ..Default::default()
+            })),
+        )
+        .slirp4netns(&slirp4netns)
+        .expected_stdout(JobOutputResult::Inline(boxed_u8!(
+            b"10.0.2.0 0.0.0.0 255.255.255.0\n192.0.2.0 10.0.2.2 255.255.255.0\n0\nno raw sockets\n"
+        )))
+        .run()
+        .await;
+
+        let args = std::fs::read_to_string(dir.path().join("args")).unwrap();
+        assert!(args.contains(" --disable-host-loopback "), "{args}");
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn nat_allowed_gateway_keeps_host_loopback() {
+        let dir = TempDir::new().unwrap();
+        let slirp4netns = fake_slirp4netns(dir.path());
+
+        Test::from_spec(bash_spec("exit 0").network(JobNetwork::Nat(JobNatOptions {
+            allowed_hosts: vec!["10.0.2.2".into(), "10.0.2.0/24".into(), "10.0.2.2".into()],
+            ..Default::default()
+        })))
+        .slirp4netns(&slirp4netns)
+        .run()
+        .await;
+
+        let args = std::fs::read_to_string(dir.path().join("args")).unwrap();
+        assert!(!args.contains("--disable-host-loopback"), "{args}");
+    }
+
+    #[test]
+    #[serial]
+    fn nat_bad_allowed_host_is_an_execution_error() {
+        let spec = test_spec("/bin/true").network(JobNetwork::Nat(JobNatOptions {
+            allowed_hosts: vec!["10.0.0.0/33".into()],
+            ..Default::default()
+        }));
+        assert_eq!(
+            start_with_execution_error(spec, vec![]),
+            "allowed host 10.0.0.0/33: invalid prefix length"
+        );
+    }
+
+    #[test]
+    fn nat_routes_default() {
+        assert_eq!(nat_routes(&[]).unwrap(), vec![(Ipv4Addr::UNSPECIFIED, 0)]);
+    }
+
+    #[test]
+    fn nat_routes_addresses_and_networks() {
+        assert_eq!(
+            nat_routes(&[
+                "192.168.1.10".into(),
+                "10.1.2.3/16".into(),
+                "0.0.0.0/0".into(),
+                "localhost".into(),
+            ])
+            .unwrap(),
+            vec![
+                (Ipv4Addr::new(192, 168, 1, 10), 32),
+                (Ipv4Addr::new(10, 1, 0, 0), 16),
+                (Ipv4Addr::UNSPECIFIED, 0),
+                (Ipv4Addr::LOCALHOST, 32),
+            ]
+        );
+    }
+
+    #[test]
+    fn nat_routes_bad_address() {
+        assert_eq!(
+            nat_routes(&["10.0.0.256/8".into()])
+                .unwrap_err()
+                .to_string(),
+            "allowed host 10.0.0.256/8: invalid IPv4 address syntax"
+        );
+    }
+
+    #[test]
+    fn nat_routes_cover_gateway() {
+        let gateway = NAT_GATEWAY;
+        assert!(nat_routes_cover(&[(Ipv4Addr::UNSPECIFIED, 0)], gateway));
+        assert!(nat_routes_cover(
+            &[(Ipv4Addr::new(10, 0, 0, 0), 8)],
+            gateway
+        ));
+        assert!(nat_routes_cover(&[(gateway, 32)], gateway));
+        assert!(!nat_routes_cover(
+            &[(Ipv4Addr::new(10, 0, 2, 3), 32)],
+            gateway
+        ));
+        assert!(!nat_routes_cover(&[], gateway));
+    }
+
+    fn port_forward(protocol: JobPortProtocol, host_port: u16) -> JobPortForward {
+        JobPortForward {
+            protocol,
+            host_port,
+            job_port: 80,
+        }
+    }
+
+    #[test]
+    #[serial]
+    fn nat_host_port_in_use_is_an_execution_error() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let spec = test_spec("/bin/true").network(JobNetwork::Nat(JobNatOptions {
+            port_forwards: vec![port_forward(JobPortProtocol::Tcp, port)],
+            ..Default::default()
+        }));
+        let err = start_with_execution_error(spec, vec![]);
+        assert!(
+            err.starts_with(&format!(
+                "tcp host port {port} can't be forwarded into the job because it's in use on the \
+                worker: "
+            )),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn nat_host_port_forwarded_twice() {
+        let running_jobs = RunningJobs::default();
+        assert_eq!(
+            running_jobs
+                .check_host_ports(&[
+                    port_forward(JobPortProtocol::Udp, 5353),
+                    port_forward(JobPortProtocol::Udp, 5353),
+                ])
+                .unwrap_err()
+                .to_string(),
+            "udp host port 5353 is forwarded more than once"
+        );
+    }
+
+    #[test]
+    fn nat_host_port_is_released_when_job_is_reaped() {
+        let port = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let port_forwards = [port_forward(JobPortProtocol::Tcp, port)];
+        let pid = Pid::new_for_test(1234);
+        let job_status_pipes = JobStatusPipes::default();
+        job_status_pipes
+            .0
+            .lock()
+            .unwrap()
+            .host_ports
+            .insert((JobPortProtocol::Tcp, port), pid);
+
+        assert_eq!(
+            job_status_pipes
+                .0
+                .lock()
+                .unwrap()
+                .check_host_ports(&port_forwards)
+                .unwrap_err()
+                .to_string(),
+            format!(
+                "tcp host port {port} can't be forwarded into the job because it's already \
+                forwarded into another job on the worker"
+            )
+        );
+        // The same port number can still be forwarded with the other protocol.
+        job_status_pipes
+            .0
+            .lock()
+            .unwrap()
+            .check_host_ports(&[port_forward(JobPortProtocol::Udp, port)])
+            .unwrap();
+
+        assert_eq!(
+            job_status_pipes.job_status(pid, JobStatus::Exited(0)),
+            JobStatus::Exited(0)
+        );
+        job_status_pipes
+            .0
+            .lock()
+            .unwrap()
+            .check_host_ports(&port_forwards)
+            .unwrap();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn nat_slirp4netns_exits_before_ready() {
+        Test::from_spec(test_spec("/bin/true").network(JobNetwork::Nat(Default::default())))
+            .slirp4netns("/bin/true")
+            .expected_status(JobStatus::Signaled(9))
+            .expected_startup_error(JobError::System(
+                "slirp4netns exited before it was ready".into(),
+            ))
+            .run()
+            .await;
+    }
+
     #[tokio::test]
     #[serial]
     async fn user_and_group_0() {
@@ -1138,6 +2610,155 @@ mod tests {
         .await;
     }
 
+    fn setuid_spec() -> maelstrom_base::JobSpec {
+        python_spec(concat!(
+            "import os;",
+            "os.setgroups([]);",
+            "os.setgid(2000);",
+            "os.setuid(2000);",
+            "print(os.getuid(), os.getgid())",
+        ))
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn setuid_with_subordinate_ids() {
+        Test::from_spec(setuid_spec())
+            .subordinate_ids(65536, 65536)
+            .expected_stdout(JobOutputResult::Inline(boxed_u8!(b"2000 2000\n")))
+            .run()
+            .await;
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn setuid_without_subordinate_ids() {
+        Test::from_spec(python_spec(concat!(
+            "import os\n",
+            "try: os.setuid(2000)\n",
+            "except OSError as e: print(e.errno)\n",
+        )))
+        .expected_stdout(JobOutputResult::Inline(boxed_u8!(b"22\n")))
+        .run()
+        .await;
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn subordinate_ids_maps() {
+        Test::from_spec(
+            python_spec(concat!(
+                "print(' '.join(open('/proc/self/uid_map').read().split()));",
+                "print(' '.join(open('/proc/self/gid_map').read().split()))",
+            ))
+            .mounts([JobMount {
+                fs_type: JobMountFsType::Proc,
+                mount_point: utf8_path_buf!("/proc"),
+            }])
+            .user(UserId::from(43))
+            .group(GroupId::from(100)),
+        )
+        .subordinate_ids(1000, 10)
+        .expected_stdout(JobOutputResult::Inline(boxed_u8!(
+            b"43 0 1 0 43 1 1 1 42 44 44 957\n100 0 1 1 1 10\n"
+        )))
+        .run()
+        .await;
+    }
+
+    fn rlimit_spec(
+        resources: &str,
+        rlimits: impl IntoIterator<Item = JobRlimit>,
+    ) -> maelstrom_base::JobSpec {
+        python_spec(&format!(
+            "import resource; [print(resource.getrlimit(getattr(resource, r))) for r in {resources:?}.split()]"
+        ))
+        .rlimits(rlimits)
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn rlimits() {
+        Test::from_spec(rlimit_spec(
+            "RLIMIT_NOFILE RLIMIT_CORE",
+            [
+                JobRlimit {
+                    resource: JobRlimitResource::Nofile,
+                    soft: JobRlimitValue::Limited(100),
+                    hard: JobRlimitValue::Limited(200),
+                },
+                JobRlimit {
+                    resource: JobRlimitResource::Core,
+                    soft: JobRlimitValue::Limited(0),
+                    hard: JobRlimitValue::Unlimited,
+                },
+            ],
+        ))
+        .expected_stdout(JobOutputResult::Inline(boxed_u8!(b"(100, 200)\n(0, -1)\n")))
+        .run()
+        .await;
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn later_rlimits_win() {
+        let nofile = |limit| JobRlimit {
+            resource: JobRlimitResource::Nofile,
+            soft: JobRlimitValue::Limited(limit),
+            hard: JobRlimitValue::Limited(limit),
+        };
+        Test::from_spec(rlimit_spec("RLIMIT_NOFILE", [nofile(200), nofile(100)]))
+            .expected_stdout(JobOutputResult::Inline(boxed_u8!(b"(100, 100)\n")))
+            .run()
+            .await;
+    }
+
+    fn uname_spec() -> maelstrom_base::JobSpec {
+        python_spec("import os; u = os.uname(); print(u.nodename, open('/proc/sys/kernel/domainname').read().strip())")
+            .mounts([JobMount {
+                fs_type: JobMountFsType::Proc,
+                mount_point: utf8_path_buf!("/proc"),
+            }])
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn default_hostname_and_domainname() {
+        Test::from_spec(uname_spec())
+            .expected_stdout(JobOutputResult::Inline(boxed_u8!(b"maelstrom (none)\n")))
+            .run()
+            .await;
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn hostname_and_domainname() {
+        Test::from_spec(
+            uname_spec()
+                .hostname("foo".to_string())
+                .domainname("example.com".to_string()),
+        )
+        .expected_stdout(JobOutputResult::Inline(boxed_u8!(b"foo example.com\n")))
+        .run()
+        .await;
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn changing_hostname_does_not_affect_worker() {
+        let hostname = std::fs::read_to_string("/proc/sys/kernel/hostname").unwrap();
+        Test::from_spec(python_spec(
+            "import socket; socket.sethostname('bar'); print(socket.gethostname())",
+        ))
+        .expected_stdout(JobOutputResult::Inline(boxed_u8!(b"bar\n")))
+        .run()
+        .await;
+        assert_eq!(
+            std::fs::read_to_string("/proc/sys/kernel/hostname").unwrap(),
+            hostname
+        );
+    }
+
     #[tokio::test]
     #[serial]
     async fn close_range() {
@@ -1426,7 +3047,7 @@ mod tests {
                         mount_point: utf8_path_buf!("/proc"),
                     },
                     JobMount {
-                        fs_type: JobMountFsType::Tmp,
+                        fs_type: JobMountFsType::Tmp(TmpfsOptions::default()),
                         mount_point: utf8_path_buf!("/tmp"),
                     },
                 ]),
@@ -1436,6 +3057,76 @@ mod tests {
         .await;
     }
 
+    fn tmpfs_spec(script: &str, options: TmpfsOptions) -> maelstrom_base::JobSpec {
+        python_spec(script).mounts([JobMount {
+            fs_type: JobMountFsType::Tmp(options),
+            mount_point: utf8_path_buf!("/tmp"),
+        }])
+    }
+
+    const WRITE_UNTIL_ENOSPC: &str = concat!(
+        "import errno\n",
+        "try:\n",
+        "    with open('/tmp/foo', 'wb') as f:\n",
+        "        for _ in range(1024):\n",
+        "            f.write(b'x' * 4096)\n",
+        "            f.flush()\n",
+        "except OSError as e:\n",
+        "    print(errno.errorcode[e.errno])\n",
+    );
+
+    #[tokio::test]
+    #[serial]
+    async fn tmpfs_size() {
+        Test::from_spec(tmpfs_spec(
+            WRITE_UNTIL_ENOSPC,
+            TmpfsOptions {
+                size: Some(64 << 10),
+                ..Default::default()
+            },
+        ))
+        .expected_stdout(JobOutputResult::Inline(boxed_u8!(b"ENOSPC\n")))
+        .run()
+        .await;
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn tmpfs_nr_inodes_and_mode() {
+        Test::from_spec(tmpfs_spec(
+            concat!(
+                "import errno, os\n",
+                "print(oct(os.stat('/tmp').st_mode & 0o7777))\n",
+                "try:\n",
+                "    for i in range(10):\n",
+                "        open(f'/tmp/{i}', 'w').close()\n",
+                "except OSError as e:\n",
+                "    print(i, errno.errorcode[e.errno])\n",
+            ),
+            TmpfsOptions {
+                nr_inodes: Some(4),
+                mode: Some(0o700),
+                ..Default::default()
+            },
+        ))
+        .expected_stdout(JobOutputResult::Inline(boxed_u8!(b"0o700\n3 ENOSPC\n")))
+        .run()
+        .await;
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn writable_file_system_size() {
+        Test::from_spec(
+            python_spec(&WRITE_UNTIL_ENOSPC.replace("/tmp/foo", "/foo"))
+                .enable_writable_file_system(true),
+        )
+        .writable_file_system_size(64 << 10)
+        .expected_stdout(JobOutputResult::Inline(boxed_u8!(b"ENOSPC\n")))
+        .run()
+        .await;
+    }
+
     #[tokio::test]
     #[serial]
     async fn no_sysfs() {
@@ -1474,6 +3165,192 @@ mod tests {
         .await;
     }
 
+    fn bind_mount_spec(script: &str, source: &Path, read_only: bool) -> maelstrom_base::JobSpec {
+        bash_spec(script).mounts([
+            JobMount {
+                fs_type: JobMountFsType::Proc,
+                mount_point: utf8_path_buf!("/proc"),
+            },
+            JobMount {
+                fs_type: JobMountFsType::Bind {
+                    source: source.to_str().unwrap().into(),
+                    read_only,
+                },
+                mount_point: utf8_path_buf!("/tmp"),
+            },
+        ])
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn bind_mount() {
+        let host_dir = TempDir::new().unwrap();
+        std::fs::write(host_dir.path().join("input"), "from host\n").unwrap();
+        Test::from_spec(bind_mount_spec(
+            "cat /tmp/input && echo from job > /tmp/output",
+            host_dir.path(),
+            false,
+        ))
+        .allowed_bind_mounts(vec![host_dir.path().to_owned()])
+        .expected_stdout(JobOutputResult::Inline(boxed_u8!(b"from host\n")))
+        .run()
+        .await;
+        assert_eq!(
+            std::fs::read_to_string(host_dir.path().join("output")).unwrap(),
+            "from job\n"
+        );
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn bind_mount_under_allowed_path() {
+        let host_dir = TempDir::new().unwrap();
+        std::fs::create_dir(host_dir.path().join("sub")).unwrap();
+        std::fs::write(host_dir.path().join("sub/input"), "from host\n").unwrap();
+        Test::from_spec(bind_mount_spec(
+            "cat /tmp/input",
+            &host_dir.path().join("sub"),
+            false,
+        ))
+        .allowed_bind_mounts(vec![host_dir.path().to_owned()])
+        .expected_stdout(JobOutputResult::Inline(boxed_u8!(b"from host\n")))
+        .run()
+        .await;
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn read_only_bind_mount() {
+        let host_dir = TempDir::new().unwrap();
+        std::fs::write(host_dir.path().join("input"), "from host\n").unwrap();
+        Test::from_spec(bind_mount_spec(
+            concat!(
+                "cat /tmp/input;",
+                "touch /tmp/output 2>&1 | grep -q \"Read-only file system\" && echo read-only;",
+                "awk '$2 == \"/tmp\" { split($4, opts, \",\"); print opts[1] }' /proc/self/mounts",
+            ),
+            host_dir.path(),
+            true,
+        ))
+        .allowed_bind_mounts(vec![host_dir.path().to_owned()])
+        .expected_stdout(JobOutputResult::Inline(boxed_u8!(
+            b"from host\nread-only\nro\n"
+        )))
+        .run()
+        .await;
+        assert!(!host_dir.path().join("output").exists());
+    }
+
+    #[test]
+    #[serial]
+    fn bind_mount_not_allowed() {
+        let allowed_dir = TempDir::new().unwrap();
+        let other_dir = TempDir::new().unwrap();
+        assert_eq!(
+            start_with_execution_error(
+                bind_mount_spec("true", other_dir.path(), false),
+                vec![allowed_dir.path().to_owned()],
+            ),
+            format!(
+                "bind mount source {} is not allowed by this worker",
+                other_dir.path().display()
+            )
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn bind_mount_with_no_allowed_paths() {
+        let host_dir = TempDir::new().unwrap();
+        assert_eq!(
+            start_with_execution_error(bind_mount_spec("true", host_dir.path(), false), vec![]),
+            format!(
+                "bind mount source {} is not allowed by this worker",
+                host_dir.path().display()
+            )
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn bind_mount_symlink_out_of_allowed_path() {
+        let allowed_dir = TempDir::new().unwrap();
+        let other_dir = TempDir::new().unwrap();
+        let link = allowed_dir.path().join("link");
+        std::os::unix::fs::symlink(other_dir.path(), &link).unwrap();
+        assert_eq!(
+            start_with_execution_error(
+                bind_mount_spec("true", &link, false),
+                vec![allowed_dir.path().to_owned()],
+            ),
+            format!(
+                "bind mount source {} is not allowed by this worker",
+                link.display()
+            )
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn bind_mount_parent_dir_out_of_allowed_path() {
+        let allowed_dir = TempDir::new().unwrap();
+        let source = allowed_dir.path().join("..");
+        assert_eq!(
+            start_with_execution_error(
+                bind_mount_spec("true", &source, false),
+                vec![allowed_dir.path().to_owned()],
+            ),
+            format!(
+                "bind mount source {} is not allowed by this worker",
+                source.display()
+            )
+        );
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn devpts() {
+        Test::from_spec(
+            test_spec("/bin/awk")
+                .arguments([r#"/^none \/tmp/ { print $1, $2, $3 }"#, "/proc/self/mounts"])
+                .mounts([
+                    JobMount {
+                        fs_type: JobMountFsType::Proc,
+                        mount_point: utf8_path_buf!("/proc"),
+                    },
+                    JobMount {
+                        fs_type: JobMountFsType::Devpts,
+                        mount_point: utf8_path_buf!("/tmp"),
+                    },
+                ]),
+        )
+        .expected_stdout(JobOutputResult::Inline(boxed_u8!(b"none /tmp devpts\n")))
+        .run()
+        .await;
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn mqueue() {
+        Test::from_spec(
+            test_spec("/bin/awk")
+                .arguments([r#"/^none \/tmp/ { print $1, $2, $3 }"#, "/proc/self/mounts"])
+                .mounts([
+                    JobMount {
+                        fs_type: JobMountFsType::Proc,
+                        mount_point: utf8_path_buf!("/proc"),
+                    },
+                    JobMount {
+                        fs_type: JobMountFsType::Mqueue,
+                        mount_point: utf8_path_buf!("/tmp"),
+                    },
+                ]),
+        )
+        .expected_stdout(JobOutputResult::Inline(boxed_u8!(b"none /tmp mqueue\n")))
+        .run()
+        .await;
+    }
+
     #[tokio::test]
     #[serial]
     async fn no_procfs() {
@@ -1534,17 +3411,33 @@ mod tests {
             .await;
     }
 
-    fn assert_execution_error(spec: maelstrom_base::JobSpec) {
+    fn start_with_execution_error(
+        spec: maelstrom_base::JobSpec,
+        allowed_bind_mounts: Vec<PathBuf>,
+    ) -> String {
         let spec = JobSpec::from_spec_and_layers(spec, NonEmpty::new(extract_dependencies()));
-        assert_matches!(
-            Executor::new(
-                tempfile::tempdir().unwrap().into_path(),
-                tempfile::tempdir().unwrap().into_path()
-            )
-            .unwrap()
-            .start(&spec, 0.into(), |_| unreachable!(), |_| unreachable!()),
-            Err(JobError::Execution(_))
+        let result = Executor::new(
+            tempfile::tempdir().unwrap().into_path(),
+            tempfile::tempdir().unwrap().into_path(),
+            allowed_bind_mounts,
+            None,
+            "slirp4netns".into(),
+            SubordinateIds::default(),
+            JobStatusPipes::default(),
+        )
+        .unwrap()
+        .start(
+            &spec,
+            0.into(),
+            |_| unreachable!(),
+            |_| unreachable!(),
+            |_| unreachable!(),
         );
+        assert_matches!(result, Err(JobError::Execution(err)) => err.to_string())
+    }
+
+    fn assert_execution_error(spec: maelstrom_base::JobSpec) {
+        start_with_execution_error(spec, vec![]);
     }
 
     #[test]
@@ -1558,4 +3451,41 @@ mod tests {
     fn bad_working_directory_is_an_execution_error() {
         assert_execution_error(test_spec("/bin/cat").working_directory("/dev/null"));
     }
+
+    #[test]
+    #[serial]
+    fn unknown_seccomp_profile_is_an_execution_error() {
+        assert_eq!(
+            start_with_execution_error(
+                test_spec("/bin/cat").seccomp(JobSeccomp::Named("foo".into())),
+                vec![],
+            ),
+            r#"unknown seccomp profile "foo""#
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn hostname_too_long_is_an_execution_error() {
+        assert_eq!(
+            start_with_execution_error(test_spec("/bin/true").hostname("a".repeat(65)), vec![]),
+            "sethostname: Invalid argument"
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn soft_rlimit_above_hard_is_an_execution_error() {
+        assert_eq!(
+            start_with_execution_error(
+                test_spec("/bin/true").rlimits([JobRlimit {
+                    resource: JobRlimitResource::Nofile,
+                    soft: JobRlimitValue::Limited(200),
+                    hard: JobRlimitValue::Limited(100),
+                }]),
+                vec![],
+            ),
+            "setrlimit of nofile: Invalid argument"
+        );
+    }
 }
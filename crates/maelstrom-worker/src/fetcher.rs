@@ -1,42 +1,838 @@
-use anyhow::{anyhow, Result};
+use crate::{cache::ArtifactSize, subid::SubordinateIds};
+use anyhow::{anyhow, bail, Result};
 use maelstrom_base::{
-    proto::{ArtifactFetcherToBroker, BrokerToArtifactFetcher, Hello},
+    manifest::{ManifestEntry, ManifestEntryData, ManifestEntryMetadata, ManifestReader},
+    proto::{
+        ArtifactCompression, ArtifactFetcherToBroker, ArtifactFetcherToPeer, ArtifactSource,
+        BrokerToArtifactFetcher, Hello, PeerToArtifactFetcher,
+    },
     ArtifactType, Sha256Digest,
 };
-use maelstrom_util::{config::BrokerAddr, io::ChunkedReader, net};
-use slog::{debug, Logger};
+use maelstrom_linux::{self as linux, Fd};
+use maelstrom_util::{
+    config::BrokerAddr,
+    io::{ChunkedReader, DecompressingReader, Sha256Reader},
+    net,
+};
+use slog::{debug, warn, Logger};
 use std::{
-    io::{self, BufReader},
-    net::TcpStream,
-    path::PathBuf,
+    collections::{hash_map::Entry as HashEntry, HashMap},
+    ffi::OsStr,
+    fs::{self, File, Permissions},
+    io::{self, BufReader, Read, Seek as _},
+    net::{SocketAddr, TcpStream},
+    os::unix::{
+        ffi::OsStrExt as _,
+        fs::{self as unix_fs, MetadataExt as _, PermissionsExt as _},
+        io::AsRawFd as _,
+    },
+    path::{Component, Path, PathBuf},
+    time::{Duration, SystemTime},
 };
-use tar::Archive;
+use tar::{Archive, Entry, EntryType};
 
+/// Fetch the artifact `digest` from the broker and unpack it into `path`. For manifests, the
+/// contents of files come from the shared `file_store`, and only the files that aren't already
+/// there are fetched.
+///
+/// If the worker serves artifacts to other workers on `peer_port`, tars are kept in the file store
+/// too, so that they can be served. Tars and the files of manifests may then be fetched from other
+/// workers, if the broker knows of one that has them. Once an artifact is unpacked, the broker is
+/// told that the files it keeps in the file store can be fetched from this worker. Without a
+/// `peer_port`, tars are unpacked directly as they're fetched from the broker.
+#[allow(clippy::too_many_arguments)]
 pub fn main(
     digest: &Sha256Digest,
     type_: ArtifactType,
     path: PathBuf,
+    file_store: PathBuf,
     broker_addr: BrokerAddr,
+    peer_port: Option<u16>,
+    compression: ArtifactCompression,
+    subordinate_ids: SubordinateIds,
     log: &mut Logger,
-) -> Result<u64> {
+) -> Result<ArtifactSize> {
+    let mut connection = Connection::new(broker_addr, peer_port, compression, log.clone())?;
+    let artifact_size = match type_ {
+        ArtifactType::Tar if peer_port.is_none() => {
+            connection.fetch(digest, |body| unpack_counted(body, &path, subordinate_ids))?
+        }
+        ArtifactType::Tar => {
+            let store_path = file_store.join(digest.to_string());
+            let file = match File::open(&store_path) {
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                    fetch_into_file_store(digest, &store_path, None, &mut |digest, file| {
+                        connection.fetch_file(digest, file)
+                    })?;
+                    File::open(&store_path)?
+                }
+                result => result?,
+            };
+            let size = file.metadata()?.len();
+            let mut artifact_size =
+                unpack_counted(&mut BufReader::new(file), &path, subordinate_ids)?;
+            artifact_size.files.push((digest.clone(), size));
+            artifact_size
+        }
+        ArtifactType::Manifest => {
+            let mut manifest = vec![];
+            connection.fetch(digest, |body| Ok(body.read_to_end(&mut manifest)?))?;
+            let size = manifest.len() as u64;
+            let entries = ManifestReader::new(io::Cursor::new(manifest))?;
+            let mut artifact_size =
+                unpack_manifest(entries, &path, &file_store, |digest, file| {
+                    connection.fetch_file(digest, file)
+                })?;
+            artifact_size.bytes_used += size;
+            artifact_size
+        }
+    };
+    // The cache writes the artifact's completion marker once we return, so everything the marker
+    // vouches for, including the files in the file store, has to be on disk first.
+    linux::syncfs(Fd::from_raw(File::open(&path)?.as_raw_fd()))?;
+    if peer_port.is_some() {
+        connection.send(ArtifactFetcherToBroker::Stored(
+            artifact_size
+                .files
+                .iter()
+                .map(|(digest, _)| digest.clone())
+                .collect(),
+        ))?;
+    }
+    Ok(artifact_size)
+}
+
+/// Tell the broker that the files `digests` have been removed from the file store, and that this
+/// worker, which serves artifacts on `peer_port`, can't serve them anymore.
+pub fn report_removed(
+    digests: Vec<Sha256Digest>,
+    broker_addr: BrokerAddr,
+    peer_port: u16,
+) -> Result<()> {
     let mut writer = TcpStream::connect(broker_addr.inner())?;
-    let mut reader = BufReader::new(writer.try_clone()?);
-    net::write_message_to_socket(&mut writer, Hello::ArtifactFetcher)?;
+    let peer_port = Some(peer_port);
+    net::write_message_to_socket(&mut writer, Hello::ArtifactFetcher { peer_port })?;
+    net::write_message_to_socket(&mut writer, ArtifactFetcherToBroker::Removed(digests))
+}
+
+/// How long to wait when connecting to another worker before fetching from the broker instead.
+const PEER_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long to wait for another worker to accept or send any data before giving up on it and
+/// fetching from the broker instead.
+const PEER_IO_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A connection to the broker over which any number of artifacts can be fetched, one at a time.
+/// Connections to the other workers that the broker sends us to are kept open too, since files for
+/// the same manifest are likely to come from the same workers.
+struct Connection {
+    writer: TcpStream,
+    reader: BufReader<TcpStream>,
+    compression: ArtifactCompression,
+    peers: HashMap<SocketAddr, PeerConnection>,
+    log: Logger,
+}
 
-    let msg = ArtifactFetcherToBroker(digest.clone(), type_);
-    debug!(log, "artifact fetcher sending message"; "msg" => ?msg);
+impl Connection {
+    fn new(
+        broker_addr: BrokerAddr,
+        peer_port: Option<u16>,
+        compression: ArtifactCompression,
+        log: Logger,
+    ) -> Result<Self> {
+        let mut writer = TcpStream::connect(broker_addr.inner())?;
+        let reader = BufReader::new(writer.try_clone()?);
+        net::write_message_to_socket(&mut writer, Hello::ArtifactFetcher { peer_port })?;
+        Ok(Connection {
+            writer,
+            reader,
+            compression,
+            peers: HashMap::new(),
+            log,
+        })
+    }
 
-    net::write_message_to_socket(&mut writer, msg)?;
-    let msg = net::read_message_from_socket::<BrokerToArtifactFetcher>(&mut reader)?;
-    debug!(log, "artifact fetcher received message"; "msg" => ?msg);
-    msg.0
-        .map_err(|e| anyhow!("Broker error reading artifact: {e}"))?;
+    fn send(&mut self, msg: ArtifactFetcherToBroker) -> Result<()> {
+        debug!(self.log, "artifact fetcher sending message"; "msg" => ?msg);
+        net::write_message_to_socket(&mut self.writer, msg)
+    }
+
+    fn receive(&mut self) -> Result<ArtifactSource> {
+        let msg = net::read_message_from_socket::<BrokerToArtifactFetcher>(&mut self.reader)?;
+        debug!(self.log, "artifact fetcher received message"; "msg" => ?msg);
+        msg.0
+            .map_err(|e| anyhow!("Broker error reading artifact: {e}"))
+    }
+
+    /// Fetch the artifact `digest` from the broker, and give its decompressed body to `read_body`.
+    fn fetch<T>(
+        &mut self,
+        digest: &Sha256Digest,
+        read_body: impl FnOnce(&mut dyn Read) -> Result<T>,
+    ) -> Result<T> {
+        self.send(ArtifactFetcherToBroker::Fetch(
+            digest.clone(),
+            self.compression,
+        ))?;
+        match self.receive()? {
+            ArtifactSource::Broker => {
+                read_artifact_body(&mut self.reader, self.compression, read_body)
+            }
+            ArtifactSource::Peer(addr) => bail!("broker unexpectedly sent us to peer {addr}"),
+        }
+    }
+
+    /// Fetch the file `digest` into `file`, from another worker if the broker knows of one that
+    /// has it. If fetching from the other worker fails, the file is fetched from the broker
+    /// instead.
+    fn fetch_file(&mut self, digest: &Sha256Digest, file: &mut File) -> Result<()> {
+        self.send(ArtifactFetcherToBroker::FetchFromPeerOrBroker(
+            digest.clone(),
+            self.compression,
+        ))?;
+        let addr = match self.receive()? {
+            ArtifactSource::Broker => {
+                return read_artifact_body(&mut self.reader, self.compression, |body| {
+                    copy_verified(digest, body, file)
+                });
+            }
+            ArtifactSource::Peer(addr) => addr,
+        };
+        match self.fetch_file_from_peer(addr, digest, file) {
+            Ok(()) => Ok(()),
+            Err(err) => {
+                warn!(self.log, "error fetching file from peer";
+                    "peer" => %addr, "file_digest" => %digest, "err" => %err);
+                self.peers.remove(&addr);
+                file.set_len(0)?;
+                file.rewind()?;
+                self.fetch(digest, |body| copy_verified(digest, body, file))
+            }
+        }
+    }
+
+    fn fetch_file_from_peer(
+        &mut self,
+        addr: SocketAddr,
+        digest: &Sha256Digest,
+        file: &mut File,
+    ) -> Result<()> {
+        let peer = match self.peers.entry(addr) {
+            HashEntry::Occupied(entry) => entry.into_mut(),
+            HashEntry::Vacant(entry) => entry.insert(PeerConnection::new(addr)?),
+        };
+        let msg = ArtifactFetcherToPeer(digest.clone(), self.compression);
+        debug!(self.log, "artifact fetcher sending peer message"; "peer" => %addr, "msg" => ?msg);
+        net::write_message_to_socket(&mut peer.writer, msg)?;
+        let msg = net::read_message_from_socket::<PeerToArtifactFetcher>(&mut peer.reader)?;
+        debug!(self.log, "artifact fetcher received peer message"; "peer" => %addr, "msg" => ?msg);
+        msg.0
+            .map_err(|e| anyhow!("Peer error reading artifact: {e}"))?;
+        read_artifact_body(&mut peer.reader, self.compression, |body| {
+            copy_verified(digest, body, file)
+        })
+    }
+}
+
+/// A connection to another worker over which any number of files can be fetched, one at a time.
+struct PeerConnection {
+    writer: TcpStream,
+    reader: BufReader<TcpStream>,
+}
+
+impl PeerConnection {
+    fn new(addr: SocketAddr) -> Result<Self> {
+        let writer = TcpStream::connect_timeout(&addr, PEER_CONNECT_TIMEOUT)?;
+        writer.set_read_timeout(Some(PEER_IO_TIMEOUT))?;
+        writer.set_write_timeout(Some(PEER_IO_TIMEOUT))?;
+        let reader = BufReader::new(writer.try_clone()?);
+        Ok(PeerConnection { writer, reader })
+    }
+}
 
-    let mut reader = countio::Counter::new(ChunkedReader::new(reader));
-    Archive::new(&mut reader).unpack(path)?;
+/// Read the body of an artifact from `reader`, and give it decompressed to `read_body`. The whole
+/// body is read, even if `read_body` doesn't read all of it.
+fn read_artifact_body<T>(
+    reader: &mut BufReader<TcpStream>,
+    compression: ArtifactCompression,
+    read_body: impl FnOnce(&mut dyn Read) -> Result<T>,
+) -> Result<T> {
+    let mut chunked_reader = ChunkedReader::new(reader);
+    let mut decompressor =
+        DecompressingReader::new(BufReader::new(&mut chunked_reader), compression)?;
+    let result = read_body(&mut decompressor)?;
+    io::copy(&mut decompressor, &mut io::sink())?;
+
+    // N.B. Make sure artifact wasn't truncated by reading ending chunk.
+    drop(decompressor);
+    io::copy(&mut chunked_reader, &mut io::sink())?;
+
+    Ok(result)
+}
+
+/// Copy `body` into `file`, failing if it doesn't have the digest `digest`.
+fn copy_verified(digest: &Sha256Digest, body: impl Read, file: &mut File) -> Result<()> {
+    let mut reader = Sha256Reader::new(body);
+    io::copy(&mut reader, file)?;
+    let (_, actual) = reader.finalize();
+    if actual != *digest {
+        bail!("file {digest} has mismatched digest {actual}");
+    }
+    Ok(())
+}
+
+/// Return `path` joined with just the normal components of `entry_path`, so that entries can't
+/// escape `path`.
+fn join_normal(path: &Path, entry_path: &Path) -> PathBuf {
+    path.join(PathBuf::from_iter(
+        entry_path
+            .components()
+            .filter(|component| matches!(component, Component::Normal(_))),
+    ))
+}
+
+fn system_time(mtime: i64) -> SystemTime {
+    let secs = Duration::from_secs(mtime.unsigned_abs());
+    if mtime >= 0 {
+        SystemTime::UNIX_EPOCH + secs
+    } else {
+        SystemTime::UNIX_EPOCH - secs
+    }
+}
+
+fn set_metadata(file: &File, metadata: &ManifestEntryMetadata) -> io::Result<()> {
+    file.set_modified(system_time(metadata.mtime.into()))?;
+    file.set_permissions(Permissions::from_mode(u32::from(metadata.mode) & 0o7777))
+}
+
+/// Unpack the entries of a manifest into `path`. The contents of files are taken from
+/// `file_store`, which has a file for each digest, and which is shared between manifests. Files
+/// that aren't in the file store are first fetched with `fetch_file`, which must write the file's
+/// verified contents.
+///
+/// Files are reflinked from the file store if the file system supports it. Otherwise, they are hard
+/// linked if the file in the file store has the right mode, and copied if it doesn't. Hard links
+/// share their modification time with the file in the file store, which comes from whichever
+/// manifest the file was first fetched for.
+fn unpack_manifest(
+    entries: impl IntoIterator<Item = io::Result<ManifestEntry>>,
+    path: &Path,
+    file_store: &Path,
+    mut fetch_file: impl FnMut(&Sha256Digest, &mut File) -> Result<()>,
+) -> Result<ArtifactSize> {
+    fs::create_dir_all(path)?;
+    let canonical_path = fs::canonicalize(path)?;
+    let mut bytes_used = 0;
+    let mut files = HashMap::new();
+    let mut directories = vec![];
+    for entry in entries {
+        let ManifestEntry {
+            path: entry_path,
+            metadata,
+            data,
+        } = entry?;
+        let entry_path = join_normal(path, entry_path.as_std_path());
+        if entry_path == path {
+            continue;
+        }
+        // Like Entry::unpack_in, make sure that symlinks in the artifact can't be used to create
+        // entries outside of it.
+        let parent = entry_path.parent().unwrap();
+        fs::create_dir_all(parent)?;
+        if !fs::canonicalize(parent)?.starts_with(&canonical_path) {
+            bail!(
+                "manifest entry {} is outside of artifact",
+                entry_path.display()
+            );
+        }
+        if !matches!(data, ManifestEntryData::Directory) {
+            remove_existing_file(&entry_path)?;
+        }
+        match data {
+            ManifestEntryData::Directory => {
+                fs::create_dir_all(&entry_path)?;
+                directories.push((entry_path, metadata));
+            }
+            ManifestEntryData::File(None) => {
+                set_metadata(&create_file(&entry_path)?, &metadata)?;
+            }
+            ManifestEntryData::File(Some(digest)) => {
+                let store_path = file_store.join(digest.to_string());
+                if !store_path.try_exists()? {
+                    fetch_into_file_store(&digest, &store_path, Some(&metadata), &mut fetch_file)?;
+                }
+                let copied = match place_file(&store_path, &entry_path, &metadata) {
+                    // The file was removed from the file store after we checked for it.
+                    Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                        fetch_into_file_store(
+                            &digest,
+                            &store_path,
+                            Some(&metadata),
+                            &mut fetch_file,
+                        )?;
+                        place_file(&store_path, &entry_path, &metadata)?
+                    }
+                    result => result?,
+                };
+                if copied {
+                    bytes_used += metadata.size;
+                }
+                files.insert(digest, metadata.size);
+            }
+            ManifestEntryData::Symlink(target) => {
+                unix_fs::symlink(OsStr::from_bytes(&target), &entry_path)?;
+            }
+            ManifestEntryData::Hardlink(target) => {
+                // Like Entry::unpack_in, make sure that symlinks in the artifact can't be used to
+                // link to files outside of it, like other artifacts or the file store.
+                let target = join_normal(path, target.as_std_path());
+                let target_parent = target.parent().unwrap();
+                if !fs::canonicalize(target_parent)?.starts_with(&canonical_path) {
+                    bail!(
+                        "manifest entry {} links to {}, which is outside of artifact",
+                        entry_path.display(),
+                        target.display()
+                    );
+                }
+                fs::hard_link(target, &entry_path)?;
+            }
+        }
+    }
+
+    // Like Archive::unpack, set directories' metadata at the end, so that their permissions don't
+    // get in the way of unpacking their contents, and so their contents don't change their
+    // modification times.
+    for (directory, metadata) in directories.into_iter().rev() {
+        set_metadata(&File::open(directory)?, &metadata)?;
+    }
+
+    Ok(ArtifactSize {
+        bytes_used,
+        files: files.into_iter().collect(),
+    })
+}
+
+/// Remove whatever is at `path`, if it isn't a directory, so that a later entry for the same path
+/// replaces an earlier one, like it would in a tar. Opening the earlier entry instead could modify a
+/// file in the file store through a hard link.
+fn remove_existing_file(path: &Path) -> io::Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if !metadata.is_dir() => fs::remove_file(path),
+        _ => Ok(()),
+    }
+}
 
-    // N.B. Make sure archive wasn't truncated by reading ending chunk.
+fn create_file(path: &Path) -> io::Result<File> {
+    File::options().write(true).create_new(true).open(path)
+}
+
+/// Fetch a file into the file store, giving it `metadata` if there is any. The file is written to a
+/// temporary file first and then renamed into place, since other fetchers may be linking to or
+/// fetching the same file.
+fn fetch_into_file_store(
+    digest: &Sha256Digest,
+    store_path: &Path,
+    metadata: Option<&ManifestEntryMetadata>,
+    fetch_file: &mut impl FnMut(&Sha256Digest, &mut File) -> Result<()>,
+) -> Result<()> {
+    let tmp_path = store_path.with_extension(format!("{:016x}.tmp", rand::random::<u64>()));
+    let result = (|| {
+        let mut file = File::create(&tmp_path)?;
+        fetch_file(digest, &mut file)?;
+        if let Some(metadata) = metadata {
+            set_metadata(&file, metadata)?;
+        }
+        fs::rename(&tmp_path, store_path)?;
+        Ok(())
+    })();
+    if result.is_err() {
+        fs::remove_file(&tmp_path).ok();
+    }
+    result
+}
+
+/// Create `entry_path` with the contents of `store_path`. Return whether the contents had to be
+/// copied.
+fn place_file(
+    store_path: &Path,
+    entry_path: &Path,
+    metadata: &ManifestEntryMetadata,
+) -> io::Result<bool> {
+    let mut store_file = File::open(store_path)?;
+    let file = create_file(entry_path)?;
+    let dest = Fd::from_raw(file.as_raw_fd());
+    if linux::ioctl_ficlone(dest, Fd::from_raw(store_file.as_raw_fd())).is_ok() {
+        set_metadata(&file, metadata)?;
+        return Ok(false);
+    }
+    drop(file);
+    fs::remove_file(entry_path)?;
+
+    let mode = store_file.metadata()?.mode() & 0o7777;
+    if mode == u32::from(metadata.mode) & 0o7777 && fs::hard_link(store_path, entry_path).is_ok() {
+        return Ok(false);
+    }
+
+    let mut file = create_file(entry_path)?;
+    io::copy(&mut store_file, &mut file)?;
+    set_metadata(&file, metadata)?;
+    Ok(true)
+}
+
+/// Unpack a tar archive into `path`, and return the size of the archive as the artifact's size. The
+/// whole archive is read, even past the end of the tar.
+fn unpack_counted(
+    reader: &mut dyn Read,
+    path: &Path,
+    subordinate_ids: SubordinateIds,
+) -> Result<ArtifactSize> {
+    let mut reader = countio::Counter::new(reader);
+    unpack(&mut reader, path, subordinate_ids)?;
     io::copy(&mut reader, &mut io::sink())?;
+    Ok((reader.reader_bytes() as u64).into())
+}
+
+/// Unpack a tar archive into `path`. Without subordinate ids, everything is owned by the worker.
+/// With them, entries are given the owners they have in the archive, as long as those ids are
+/// mapped into the worker's user namespace. Jobs' user namespaces map those ids to themselves, so
+/// jobs see the same owners.
+fn unpack(reader: impl Read, path: &Path, subordinate_ids: SubordinateIds) -> Result<()> {
+    let mut archive = Archive::new(reader);
+    if subordinate_ids == SubordinateIds::default() {
+        archive.unpack(path)?;
+        return Ok(());
+    }
+
+    // Like Archive::unpack, delay directories until the end, so that their permissions don't get in
+    // the way of unpacking their contents.
+    fs::create_dir_all(path)?;
+    let mut directories = vec![];
+    for entry in archive.entries()? {
+        let entry = entry?;
+        if entry.header().entry_type() == EntryType::Directory {
+            directories.push(entry);
+        } else {
+            unpack_entry(entry, path, subordinate_ids)?;
+        }
+    }
+    for entry in directories {
+        unpack_entry(entry, path, subordinate_ids)?;
+    }
+    Ok(())
+}
+
+fn unpack_entry(
+    mut entry: Entry<impl Read>,
+    path: &Path,
+    subordinate_ids: SubordinateIds,
+) -> Result<()> {
+    let uid = u32::try_from(entry.header().uid()?)
+        .ok()
+        .filter(|&uid| uid != 0 && uid <= subordinate_ids.uids);
+    let gid = u32::try_from(entry.header().gid()?)
+        .ok()
+        .filter(|&gid| gid != 0 && gid <= subordinate_ids.gids);
+    // Entry::unpack_in skips everything but normal components, and refuses to unpack entries with
+    // parent components, returning false.
+    let entry_path = join_normal(path, &entry.path()?);
+    if entry.unpack_in(path)? && (uid.is_some() || gid.is_some()) {
+        unix_fs::lchown(entry_path, uid, gid)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use maelstrom_base::manifest::{Mode, UnixTimestamp};
+    use tar::{Builder, Header};
+    use tempfile::TempDir;
+
+    fn archive(entries: &[(&str, u64, u64)]) -> Vec<u8> {
+        let mut builder = Builder::new(vec![]);
+        for &(path, uid, gid) in entries {
+            let mut header = Header::new_gnu();
+            header.set_size(0);
+            header.set_mode(0o644);
+            header.set_uid(uid);
+            header.set_gid(gid);
+            header.set_cksum();
+            builder.append_data(&mut header, path, io::empty()).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    fn owner(path: &Path) -> (u32, u32) {
+        let metadata = fs::symlink_metadata(path).unwrap();
+        (metadata.uid(), metadata.gid())
+    }
+
+    #[test]
+    fn unpack_without_subordinate_ids_ignores_owners() {
+        let dir = TempDir::new().unwrap();
+        let archive = archive(&[("foo", 1000, 1000)]);
+        unpack(archive.as_slice(), dir.path(), SubordinateIds::default()).unwrap();
+        let me = owner(dir.path());
+        assert_eq!(owner(&dir.path().join("foo")), me);
+    }
+
+    #[test]
+    fn unpack_with_subordinate_ids_keeps_mapped_owners() {
+        let dir = TempDir::new().unwrap();
+        let archive = archive(&[("foo", 1000, 2000), ("bar", 70000, 100), ("baz", 0, 0)]);
+        let subordinate_ids = SubordinateIds {
+            uids: 65536,
+            gids: 1000,
+        };
+        unpack(archive.as_slice(), dir.path(), subordinate_ids).unwrap();
+        let me = owner(dir.path());
+        assert_eq!(owner(&dir.path().join("foo")), (1000, me.1));
+        assert_eq!(owner(&dir.path().join("bar")), (me.0, 100));
+        assert_eq!(owner(&dir.path().join("baz")), me);
+    }
+
+    fn manifest_entry(path: &str, size: u64, mode: u32, data: ManifestEntryData) -> ManifestEntry {
+        ManifestEntry {
+            path: path.into(),
+            metadata: ManifestEntryMetadata {
+                size,
+                mode: Mode(mode),
+                mtime: UnixTimestamp(1705538554),
+            },
+            data,
+        }
+    }
+
+    fn file_entry(path: &str, contents: &[u8], mode: u32) -> ManifestEntry {
+        manifest_entry(
+            path,
+            contents.len() as u64,
+            mode,
+            ManifestEntryData::File(Some(digest(contents))),
+        )
+    }
+
+    fn digest(contents: &[u8]) -> Sha256Digest {
+        let mut reader = Sha256Reader::new(contents);
+        io::copy(&mut reader, &mut io::sink()).unwrap();
+        reader.finalize().1
+    }
+
+    /// Unpack `entries` into `path`, fetching files from `contents`, and return the resulting size
+    /// along with the digests of the files that were fetched.
+    fn unpack_manifest_from(
+        entries: Vec<ManifestEntry>,
+        path: &Path,
+        file_store: &Path,
+        contents: &[&[u8]],
+    ) -> (ArtifactSize, Vec<Sha256Digest>) {
+        let mut fetched = vec![];
+        let mut size = unpack_manifest(
+            entries.into_iter().map(Ok),
+            path,
+            file_store,
+            |digest, file| {
+                fetched.push(digest.clone());
+                let body = contents
+                    .iter()
+                    .find(|c| self::digest(c) == *digest)
+                    .unwrap();
+                copy_verified(digest, *body, file)
+            },
+        )
+        .unwrap();
+        size.files.sort_by(|lhs, rhs| lhs.0.cmp(&rhs.0));
+        (size, fetched)
+    }
+
+    fn assert_file(path: &Path, contents: &[u8], mode: u32) {
+        assert_eq!(fs::read(path).unwrap(), contents);
+        let metadata = fs::symlink_metadata(path).unwrap();
+        assert!(metadata.is_file());
+        assert_eq!(metadata.mode() & 0o7777, mode);
+    }
+
+    #[test]
+    fn unpack_manifest_files_directories_and_links() {
+        let dir = TempDir::new().unwrap();
+        let (path, file_store) = (dir.path().join("artifact"), dir.path().join("files"));
+        fs::create_dir(&file_store).unwrap();
+        let entries = vec![
+            file_entry("/foobar/a_file", b"hello world", 0o555),
+            manifest_entry("foobar/empty", 0, 0o444, ManifestEntryData::File(None)),
+            manifest_entry(
+                "foobar/a_symlink",
+                0,
+                0o777,
+                ManifestEntryData::Symlink(b"./a_file".to_vec()),
+            ),
+            manifest_entry(
+                "foobar/a_hardlink",
+                0,
+                0o555,
+                ManifestEntryData::Hardlink("foobar/a_file".into()),
+            ),
+            manifest_entry("foobar", 0, 0o555, ManifestEntryData::Directory),
+            manifest_entry("baz", 0, 0o700, ManifestEntryData::Directory),
+        ];
+        let (size, fetched) = unpack_manifest_from(entries, &path, &file_store, &[b"hello world"]);
+        assert_eq!(fetched, vec![digest(b"hello world")]);
+        assert_eq!(size.files, vec![(digest(b"hello world"), 11)]);
+
+        assert_file(&path.join("foobar/a_file"), b"hello world", 0o555);
+        assert_file(&path.join("foobar/empty"), b"", 0o444);
+        assert_eq!(
+            fs::read_link(path.join("foobar/a_symlink")).unwrap(),
+            Path::new("./a_file")
+        );
+        assert_eq!(
+            fs::metadata(path.join("foobar/a_file")).unwrap().ino(),
+            fs::metadata(path.join("foobar/a_hardlink")).unwrap().ino()
+        );
+        assert_eq!(
+            fs::metadata(path.join("foobar/empty")).unwrap().mtime(),
+            1705538554
+        );
+        assert_eq!(
+            fs::metadata(path.join("foobar")).unwrap().mode() & 0o7777,
+            0o555
+        );
+        assert_eq!(
+            fs::metadata(path.join("baz")).unwrap().mode() & 0o7777,
+            0o700
+        );
+        assert_file(
+            &file_store.join(digest(b"hello world").to_string()),
+            b"hello world",
+            0o555,
+        );
+    }
+
+    #[test]
+    fn unpack_manifest_shares_files_between_artifacts() {
+        let dir = TempDir::new().unwrap();
+        let file_store = dir.path().join("files");
+        fs::create_dir(&file_store).unwrap();
+        let contents: &[&[u8]] = &[b"shared library", b"first binary", b"second binary"];
+
+        let (first, fetched) = unpack_manifest_from(
+            vec![
+                file_entry("lib.so", b"shared library", 0o755),
+                file_entry("test", b"first binary", 0o755),
+            ],
+            &dir.path().join("first"),
+            &file_store,
+            contents,
+        );
+        assert_eq!(fetched.len(), 2);
+        assert_eq!(first.files.len(), 2);
+
+        let (second, fetched) = unpack_manifest_from(
+            vec![
+                file_entry("lib.so", b"shared library", 0o755),
+                file_entry("other.so", b"shared library", 0o644),
+                file_entry("test", b"second binary", 0o755),
+            ],
+            &dir.path().join("second"),
+            &file_store,
+            contents,
+        );
+        assert_eq!(fetched, vec![digest(b"second binary")]);
+        assert!(second.files.contains(&(digest(b"shared library"), 14)));
+        assert_eq!(second.files.len(), 2);
+
+        assert_file(&dir.path().join("second/lib.so"), b"shared library", 0o755);
+        assert_file(
+            &dir.path().join("second/other.so"),
+            b"shared library",
+            0o644,
+        );
+        assert_file(&dir.path().join("second/test"), b"second binary", 0o755);
+    }
+
+    #[test]
+    fn unpack_manifest_does_not_modify_file_store_through_later_entries() {
+        let dir = TempDir::new().unwrap();
+        let (path, file_store) = (dir.path().join("artifact"), dir.path().join("files"));
+        fs::create_dir(&file_store).unwrap();
+        unpack_manifest_from(
+            vec![
+                file_entry("foo", b"contents", 0o644),
+                manifest_entry("foo", 0, 0o644, ManifestEntryData::File(None)),
+            ],
+            &path,
+            &file_store,
+            &[b"contents"],
+        );
+        assert_file(&path.join("foo"), b"", 0o644);
+        assert_file(
+            &file_store.join(digest(b"contents").to_string()),
+            b"contents",
+            0o644,
+        );
+    }
+
+    #[test]
+    fn unpack_manifest_refuses_entries_through_symlinks() {
+        let dir = TempDir::new().unwrap();
+        let (path, file_store) = (dir.path().join("artifact"), dir.path().join("files"));
+        fs::create_dir(&file_store).unwrap();
+        let entries = vec![
+            manifest_entry(
+                "escape",
+                0,
+                0o777,
+                ManifestEntryData::Symlink(dir.path().as_os_str().as_bytes().to_vec()),
+            ),
+            manifest_entry("escape/foo", 0, 0o644, ManifestEntryData::File(None)),
+        ];
+        let result = unpack_manifest(
+            entries.into_iter().map(Ok),
+            &path,
+            &file_store,
+            |_, _| panic!(),
+        );
+        assert!(result.is_err());
+        assert!(!dir.path().join("foo").exists());
+    }
+
+    #[test]
+    fn unpack_manifest_refuses_hardlinks_through_symlinks() {
+        let dir = TempDir::new().unwrap();
+        let (path, file_store) = (dir.path().join("artifact"), dir.path().join("files"));
+        fs::create_dir(&file_store).unwrap();
+        fs::write(dir.path().join("secret"), b"secret").unwrap();
+        let entries = vec![
+            manifest_entry(
+                "escape",
+                0,
+                0o777,
+                ManifestEntryData::Symlink(dir.path().as_os_str().as_bytes().to_vec()),
+            ),
+            manifest_entry(
+                "stolen",
+                0,
+                0o644,
+                ManifestEntryData::Hardlink("escape/secret".into()),
+            ),
+        ];
+        let err = unpack_manifest(
+            entries.into_iter().map(Ok),
+            &path,
+            &file_store,
+            |_, _| panic!(),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("outside of artifact"), "{err}");
+        assert!(!path.join("stolen").exists());
+        assert_eq!(fs::metadata(dir.path().join("secret")).unwrap().nlink(), 1);
+    }
 
-    Ok(reader.reader_bytes() as u64)
+    #[test]
+    fn copy_verified_rejects_wrong_digest() {
+        let dir = TempDir::new().unwrap();
+        let mut file = File::create(dir.path().join("foo")).unwrap();
+        copy_verified(&digest(b"foo"), b"foo".as_slice(), &mut file).unwrap();
+        copy_verified(&digest(b"foo"), b"bar".as_slice(), &mut file).unwrap_err();
+    }
 }
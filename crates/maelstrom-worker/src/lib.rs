@@ -5,15 +5,20 @@ pub mod config;
 mod dispatcher;
 mod executor;
 mod fetcher;
+mod metrics_server;
+mod peer_server;
 mod reaper;
+mod seccomp;
+mod stack_dump;
+pub mod subid;
 
 use anyhow::Result;
 use cache::{Cache, StdCacheFs};
 use config::{Config, InlineLimit};
 use dispatcher::{Dispatcher, DispatcherDeps, Message};
-use executor::Executor;
+use executor::{Executor, JobStatusPipes};
 use maelstrom_base::{
-    proto::{Hello, WorkerToBroker},
+    proto::{ArtifactCompression, Hello, WorkerToBroker, WorkerWelcome},
     ArtifactType, JobId, JobResult, JobSpec, JobStatus, NonEmpty, Sha256Digest,
 };
 use maelstrom_linux::{self as linux, Errno, Pid, Signal};
@@ -21,10 +26,19 @@ use maelstrom_util::{
     config::{BrokerAddr, CacheRoot},
     fs::Fs,
     net, sync,
+    trace::{JobEvent, JobTracer},
 };
 use reaper::ReaperDeps;
 use slog::{debug, error, info, o, warn, Logger};
-use std::{ops::ControlFlow, path::PathBuf, process, thread, time::Duration};
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    ops::ControlFlow,
+    path::PathBuf,
+    process, thread,
+    time::{Duration, Instant},
+};
+use subid::SubordinateIds;
 use tokio::{
     io::BufReader,
     net::TcpStream,
@@ -42,20 +56,37 @@ struct DispatcherAdapter {
     dispatcher_sender: DispatcherSender,
     broker_socket_sender: BrokerSocketSender,
     broker_addr: BrokerAddr,
+    peer_port: Option<u16>,
     inline_limit: InlineLimit,
     log: Logger,
     executor: Executor,
+    file_store: PathBuf,
+    artifact_compression: ArtifactCompression,
+    subordinate_ids: SubordinateIds,
+    stack_dumper: Option<PathBuf>,
+    tracer: JobTracer,
 }
 
 impl DispatcherAdapter {
+    #[allow(clippy::too_many_arguments)]
     fn new(
         dispatcher_sender: DispatcherSender,
         broker_socket_sender: BrokerSocketSender,
         broker_addr: BrokerAddr,
+        peer_port: Option<u16>,
         inline_limit: InlineLimit,
         log: Logger,
         mount_dir: PathBuf,
         tmpfs_dir: PathBuf,
+        allowed_bind_mounts: Vec<PathBuf>,
+        writable_file_system_size: Option<u64>,
+        slirp4netns: PathBuf,
+        job_status_pipes: JobStatusPipes,
+        file_store: PathBuf,
+        artifact_compression: ArtifactCompression,
+        subordinate_ids: SubordinateIds,
+        stack_dumper: Option<PathBuf>,
+        tracer: JobTracer,
     ) -> Result<Self> {
         let fs = Fs::new();
         fs.create_dir_all(&mount_dir)?;
@@ -64,9 +95,23 @@ impl DispatcherAdapter {
             dispatcher_sender,
             broker_socket_sender,
             broker_addr,
+            peer_port,
             inline_limit,
             log,
-            executor: Executor::new(mount_dir, tmpfs_dir)?,
+            executor: Executor::new(
+                mount_dir,
+                tmpfs_dir,
+                allowed_bind_mounts,
+                writable_file_system_size,
+                slirp4netns,
+                subordinate_ids,
+                job_status_pipes,
+            )?,
+            file_store,
+            artifact_compression,
+            subordinate_ids,
+            stack_dumper,
+            tracer,
         })
     }
 }
@@ -80,11 +125,13 @@ impl DispatcherDeps for DispatcherAdapter {
     ) -> JobResult<Pid, String> {
         let sender = self.dispatcher_sender.clone();
         let sender2 = sender.clone();
+        let sender3 = sender.clone();
         let log = self
             .log
-            .new(o!("jid" => format!("{jid:?}"), "spec" => format!("{spec:?}")));
+            .new(o!("jid" => jid.to_string(), "spec" => format!("{spec:?}")));
         debug!(log, "job starting");
         let log2 = log.clone();
+        let log3 = log.clone();
         let spec = executor::JobSpec::from_spec_and_layers(spec, layers);
         self.executor
             .start(
@@ -102,6 +149,12 @@ impl DispatcherDeps for DispatcherAdapter {
                         .send(Message::JobStderr(jid, result.map_err(|e| e.to_string())))
                         .ok();
                 },
+                move |err| {
+                    debug!(log3, "job startup failed"; "err" => ?err);
+                    sender3
+                        .send(Message::JobStartupFailed(jid, err.map(|e| e.to_string())))
+                        .ok();
+                },
             )
             .map_err(|e| e.map(|inner| inner.to_string()))
     }
@@ -110,6 +163,28 @@ impl DispatcherDeps for DispatcherAdapter {
         linux::kill(pid, Signal::KILL).ok();
     }
 
+    fn signal_job(&mut self, pid: Pid, signal: Signal) {
+        // This is the job's init process, which forwards the signal to the job.
+        linux::kill(pid, signal).ok();
+    }
+
+    fn start_stack_dump(&mut self, jid: JobId, pid: Pid) {
+        let sender = self.dispatcher_sender.clone();
+        let Some(stack_dumper) = self.stack_dumper.clone() else {
+            let dump = "stack dumps aren't enabled on this worker\n".into();
+            sender.send(Message::JobStackDump(jid, dump)).ok();
+            return;
+        };
+        thread::spawn(move || {
+            let dump = stack_dump::stack_dump(pid, &stack_dumper);
+            sender.send(Message::JobStackDump(jid, dump)).ok();
+        });
+    }
+
+    fn now(&mut self) -> Instant {
+        Instant::now()
+    }
+
     type TimerHandle = JoinHandle<()>;
 
     fn start_timer(&mut self, jid: JobId, duration: Duration) -> Self::TimerHandle {
@@ -127,13 +202,27 @@ impl DispatcherDeps for DispatcherAdapter {
     fn start_artifact_fetch(&mut self, digest: Sha256Digest, type_: ArtifactType, path: PathBuf) {
         let sender = self.dispatcher_sender.clone();
         let broker_addr = self.broker_addr;
+        let peer_port = self.peer_port;
+        let file_store = self.file_store.clone();
+        let compression = self.artifact_compression;
+        let subordinate_ids = self.subordinate_ids;
         let mut log = self.log.new(o!(
             "digest" => digest.to_string(),
             "broker_addr" => broker_addr.inner().to_string()
         ));
         debug!(log, "artifact fetcher starting");
         thread::spawn(move || {
-            let result = fetcher::main(&digest, type_, path, broker_addr, &mut log);
+            let result = fetcher::main(
+                &digest,
+                type_,
+                path,
+                file_store,
+                broker_addr,
+                peer_port,
+                compression,
+                subordinate_ids,
+                &mut log,
+            );
             debug!(log, "artifact fetcher completed"; "result" => ?result);
             sender.send(Message::ArtifactFetcher(digest, result)).ok();
         });
@@ -142,14 +231,37 @@ impl DispatcherDeps for DispatcherAdapter {
     fn send_message_to_broker(&mut self, message: WorkerToBroker) {
         self.broker_socket_sender.send(message).ok();
     }
+
+    fn report_removed_files(&mut self, digests: Vec<Sha256Digest>) {
+        let Some(peer_port) = self.peer_port else {
+            return;
+        };
+        let broker_addr = self.broker_addr;
+        let log = self.log.clone();
+        thread::spawn(move || {
+            if let Err(err) = fetcher::report_removed(digests, broker_addr, peer_port) {
+                warn!(log, "error reporting removed files to broker"; "err" => %err);
+            }
+        });
+    }
+
+    fn job_event(&mut self, jid: JobId, event: JobEvent) {
+        self.tracer.event(jid, event);
+    }
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn dispatcher_main(
     config: Config,
+    peer_listener: Option<(std::net::TcpListener, u16)>,
+    metrics_listener: Option<std::net::TcpListener>,
     dispatcher_receiver: DispatcherReceiver,
     dispatcher_sender: DispatcherSender,
     broker_socket_sender: BrokerSocketSender,
+    tracer: JobTracer,
     log: Logger,
+    subordinate_ids: SubordinateIds,
+    job_status_pipes: JobStatusPipes,
 ) {
     let mount_dir = config.cache_root.inner().join("mount");
     let tmpfs_dir = config.cache_root.inner().join("upper");
@@ -161,14 +273,40 @@ async fn dispatcher_main(
         config.cache_bytes_used_target,
         log.clone(),
     );
+    let file_store = cache.file_store_path();
+    let peer_port = peer_listener.map(|(peer_listener, peer_port)| {
+        let peer_server_file_store = file_store.clone();
+        let peer_server_log = log.new(o!("task" => "peer_server"));
+        thread::spawn(move || {
+            peer_server::main(peer_listener, peer_server_file_store, peer_server_log)
+        });
+        peer_port
+    });
+    if let Some(metrics_listener) = metrics_listener {
+        let metrics_server_sender = dispatcher_sender.clone();
+        let metrics_server_log = log.new(o!("task" => "metrics_server"));
+        thread::spawn(move || {
+            metrics_server::main(metrics_listener, metrics_server_sender, metrics_server_log)
+        });
+    }
     match DispatcherAdapter::new(
         dispatcher_sender,
         broker_socket_sender,
         config.broker,
+        peer_port,
         config.inline_limit,
         log.clone(),
         mount_dir,
         tmpfs_dir,
+        config.allowed_bind_mounts,
+        config.writable_file_system_size,
+        config.slirp4netns,
+        job_status_pipes,
+        file_store,
+        config.artifact_compression,
+        subordinate_ids,
+        config.stack_dumper,
+        tracer,
     ) {
         Err(err) => {
             error!(log, "could not start executor"; "err" => ?err);
@@ -191,6 +329,7 @@ async fn signal_handler(kind: SignalKind, log: Logger, signame: &'static str) {
 struct ReaperAdapter {
     log: Logger,
     sender: DispatcherSender,
+    job_status_pipes: JobStatusPipes,
 }
 
 impl ReaperDeps for ReaperAdapter {
@@ -203,16 +342,34 @@ impl ReaperDeps for ReaperAdapter {
     }
     fn on_child_termination(&mut self, pid: Pid, status: JobStatus) -> ControlFlow<()> {
         debug!(self.log, "waitid returned"; "pid" => %pid, "status" => ?status);
+        let status = self.job_status_pipes.job_status(pid, status);
         self.sender
             .send(Message::PidStatus(pid, status))
             .map_or(ControlFlow::Break(()), |_| ControlFlow::Continue(()))
     }
 }
 
+/// Bind the listener that artifacts are served to other workers on, and return it along with the
+/// port it's bound to. Other workers reach us at the address the broker sees us connect from, so
+/// the listener has the same address family as the broker's address.
+fn bind_peer_listener(
+    broker_addr: BrokerAddr,
+    port: u16,
+) -> io::Result<(std::net::TcpListener, u16)> {
+    let ip = if broker_addr.inner().is_ipv4() {
+        IpAddr::from(Ipv4Addr::UNSPECIFIED)
+    } else {
+        IpAddr::from(Ipv6Addr::UNSPECIFIED)
+    };
+    let listener = std::net::TcpListener::bind((ip, port))?;
+    let port = listener.local_addr()?.port();
+    Ok((listener, port))
+}
+
 /// The main function for the worker. This should be called on a task of its own. It will return
 /// when a signal is received or when one of the worker tasks completes because of an error.
-pub async fn main(config: Config, log: Logger) -> Result<()> {
-    info!(log, "started"; "config" => ?config, "pid" => process::id());
+pub async fn main(config: Config, log: Logger, subordinate_ids: SubordinateIds) -> Result<()> {
+    info!(log, "started"; "config" => ?config, "pid" => process::id(), "subordinate_ids" => ?subordinate_ids);
 
     let (read_stream, mut write_stream) = TcpStream::connect(config.broker.inner())
         .await
@@ -221,12 +378,31 @@ pub async fn main(config: Config, log: Logger) -> Result<()> {
             err
         })?
         .into_split();
-    let read_stream = BufReader::new(read_stream);
+    let mut read_stream = BufReader::new(read_stream);
+
+    let peer_listener = config
+        .peer_port
+        .map(|port| bind_peer_listener(config.broker, port))
+        .transpose()
+        .map_err(|err| {
+            error!(log, "error binding peer port"; "err" => %err);
+            err
+        })?;
+
+    let metrics_listener = config
+        .metrics_port
+        .map(|port| std::net::TcpListener::bind(("::", port)))
+        .transpose()
+        .map_err(|err| {
+            error!(log, "error binding metrics port"; "err" => %err);
+            err
+        })?;
 
     net::write_message_to_async_socket(
         &mut write_stream,
         Hello::Worker {
             slots: (*config.slots.inner()).into(),
+            peer_port: peer_listener.as_ref().map(|(_, port)| *port),
         },
     )
     .await
@@ -235,14 +411,36 @@ pub async fn main(config: Config, log: Logger) -> Result<()> {
         err
     })?;
 
+    let welcome: WorkerWelcome = net::read_message_from_async_socket(&mut read_stream)
+        .await
+        .map_err(|err| {
+            error!(log, "error reading welcome message"; "err" => %err);
+            err
+        })?;
+
+    let tracer = JobTracer::new(
+        "maelstrom-worker",
+        Some("maelstrom-broker"),
+        welcome.broker_nonce,
+        config.trace_file.clone(),
+        config.otlp_endpoint.as_deref(),
+        log.clone(),
+    )
+    .map_err(|err| {
+        error!(log, "error starting job tracer"; "err" => %err);
+        err
+    })?;
+
     let (dispatcher_sender, dispatcher_receiver) = mpsc::unbounded_channel();
     let (broker_socket_sender, broker_socket_receiver) = mpsc::unbounded_channel();
 
     let dummy_pid = reaper::clone_dummy_child()?;
 
+    let job_status_pipes = JobStatusPipes::default();
     let reaper_adapter = ReaperAdapter {
         log: log.clone(),
         sender: dispatcher_sender.clone(),
+        job_status_pipes: job_status_pipes.clone(),
     };
     thread::spawn(move || reaper::main(reaper_adapter, dummy_pid));
 
@@ -268,10 +466,15 @@ pub async fn main(config: Config, log: Logger) -> Result<()> {
     ));
     join_set.spawn(dispatcher_main(
         config,
+        peer_listener,
+        metrics_listener,
         dispatcher_receiver,
         dispatcher_sender,
         broker_socket_sender,
+        tracer,
         log.clone(),
+        subordinate_ids,
+        job_status_pipes,
     ));
     join_set.spawn(signal_handler(
         SignalKind::interrupt(),
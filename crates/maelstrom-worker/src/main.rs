@@ -1,18 +1,26 @@
 use anyhow::{Context as _, Result};
+use c_str_macro::c_str;
 use clap::Parser;
 use figment::{
     error::Kind,
     providers::{Env, Format, Serialized, Toml},
     Figment,
 };
+use maelstrom_base::proto::ArtifactCompression;
 use maelstrom_linux::{
-    self as linux, CloneArgs, CloneFlags, PollEvents, PollFd, Signal, WaitStatus,
+    self as linux, CloneArgs, CloneFlags, Fd, Gid, MountFlags, Pid, PollEvents, PollFd, Signal,
+    Uid, WaitStatus,
 };
-use maelstrom_util::{config::LogLevel, fs::Fs};
-use maelstrom_worker::config::{Config, ConfigOptions};
-use slog::{o, Drain, Level, LevelFilter, Logger};
-use slog_async::Async;
-use slog_term::{FullFormat, TermDecorator};
+use maelstrom_util::{
+    config::{LogFormat, LogLevel},
+    fs::Fs,
+    log,
+};
+use maelstrom_worker::{
+    config::{Config, ConfigOptions},
+    subid::{self, SubordinateIds, SubordinateRanges},
+};
+use slog::warn;
 use std::{path::PathBuf, process, slice, time::Duration};
 use tokio::runtime::Runtime;
 
@@ -67,6 +75,57 @@ struct CliOptions {
     /// Minimum log level to output.
     #[arg(long, short = 'l', value_name = "LEVEL", value_enum)]
     log_level: Option<LogLevel>,
+
+    /// The format to write log messages in.
+    #[arg(long, value_name = "FORMAT", value_enum)]
+    log_format: Option<LogFormat>,
+
+    /// A host path that jobs may bind mount. Jobs may bind mount the path or anything under it.
+    /// This option can be given multiple times.
+    #[arg(long = "allowed-bind-mount", value_name = "PATH")]
+    allowed_bind_mounts: Vec<PathBuf>,
+
+    /// The maximum size of the writable layer of jobs that have a writable file system. Jobs that
+    /// write more than this get ENOSPC.
+    #[arg(long, value_name = "BYTES")]
+    writable_file_system_size: Option<u64>,
+
+    /// The slirp4netns program used to give jobs NAT networking.
+    #[arg(long, value_name = "PATH")]
+    slirp4netns: Option<PathBuf>,
+
+    /// Map the worker user's subordinate uid and gid ranges, from /etc/subuid and /etc/subgid,
+    /// into jobs' user namespaces.
+    #[arg(long)]
+    subordinate_ids: bool,
+
+    /// The program used to collect backtraces of jobs that time out and ask for a stack dump, such
+    /// as eu-stack. If not given, jobs don't get stack dumps.
+    #[arg(long, value_name = "PATH")]
+    stack_dumper: Option<PathBuf>,
+
+    /// How to compress artifacts fetched from the broker. One of none, gzip, or zstd.
+    #[arg(long, value_name = "COMPRESSION")]
+    artifact_compression: Option<ArtifactCompression>,
+
+    /// The port to serve stored artifacts to other workers on. If 0, a port is chosen by the
+    /// operating system. If not given, artifacts aren't served to other workers, and are all
+    /// fetched from the broker.
+    #[arg(long, value_name = "PORT")]
+    peer_port: Option<u16>,
+
+    /// The port to serve metrics on over HTTP, at /metrics. If not given, metrics aren't served.
+    #[arg(long, value_name = "PORT")]
+    metrics_port: Option<u16>,
+
+    /// A file to append a trace of each job to, in the OTLP JSON encoding.
+    #[arg(long, value_name = "PATH")]
+    trace_file: Option<PathBuf>,
+
+    /// The URL of an OTLP collector to send a trace of each job to. Only http:// and https:// URLs
+    /// are supported.
+    #[arg(long, value_name = "URL")]
+    otlp_endpoint: Option<String>,
 }
 
 impl CliOptions {
@@ -78,6 +137,62 @@ impl CliOptions {
             cache_bytes_used_target: self.cache_bytes_used_target,
             inline_limit: self.inline_limit,
             log_level: self.log_level,
+            log_format: self.log_format,
+            allowed_bind_mounts: (!self.allowed_bind_mounts.is_empty())
+                .then(|| self.allowed_bind_mounts.clone()),
+            writable_file_system_size: self.writable_file_system_size,
+            slirp4netns: self.slirp4netns.clone(),
+            subordinate_ids: self.subordinate_ids.then_some(true),
+            stack_dumper: self.stack_dumper.clone(),
+            artifact_compression: self.artifact_compression,
+            peer_port: self.peer_port,
+            metrics_port: self.metrics_port,
+            trace_file: self.trace_file.clone(),
+            otlp_endpoint: self.otlp_endpoint.clone(),
+        }
+    }
+}
+
+/// Map the subordinate id ranges into the user namespace of `child_pid`. This has to be done from
+/// outside of the namespace. Return a message for the child with the number of uids and gids
+/// mapped, followed by a description of any errors. A count of 0 means the child has to map its
+/// single id itself.
+fn map_subordinate_ids(
+    ranges: &SubordinateRanges,
+    child_pid: Pid,
+    parent_uid: Uid,
+    parent_gid: Gid,
+) -> Vec<u8> {
+    let mut errors = vec![];
+    let mut map = |program, id, range: subid::IdRange| {
+        subid::run_new_id_map(program, child_pid, id, range)
+            .map(|()| range.count)
+            .unwrap_or_else(|err| {
+                errors.push(format!("{err:#}"));
+                0
+            })
+    };
+    let uids = map(&ranges.newuidmap, parent_uid.as_u32(), ranges.uids);
+    let gids = map(&ranges.newgidmap, parent_gid.as_u32(), ranges.gids);
+    let mut message = Vec::from_iter(uids.to_ne_bytes().into_iter().chain(gids.to_ne_bytes()));
+    message.extend(errors.join("; ").into_bytes());
+    message
+}
+
+fn write_all(fd: Fd, mut buf: &[u8]) -> Result<()> {
+    while !buf.is_empty() {
+        buf = &buf[linux::write(fd, buf)?..];
+    }
+    Ok(())
+}
+
+fn read_to_end(fd: Fd) -> Result<Vec<u8>> {
+    let mut contents = vec![];
+    let mut buf = [0u8; 1024];
+    loop {
+        match linux::read(fd, &mut buf)? {
+            0 => break Ok(contents),
+            n => contents.extend_from_slice(&buf[..n]),
         }
     }
 }
@@ -87,8 +202,19 @@ impl CliOptions {
 /// The child process will also be in a new user namespace, and have uid 0, gid 0 in that
 /// namespace. The user namespace is required in order to create the pid namespace.
 ///
+/// If `subordinate_ranges` is given, the parent also maps those ranges into the new user namespace
+/// as ids 1 and up. The number of ids mapped is returned, along with an error describing why the
+/// ranges couldn't be mapped, if they couldn't.
+///
+/// If `private_proc` is true, the child process will also be in a new mount namespace with its own
+/// `/proc`, so that the pids in `/proc` are the same as the ones the child sees. This is only
+/// needed for stack dumps. If it can't be done, an error describing why is returned.
+///
 /// WARNING: This function must only be called while the program is single-threaded.
-fn clone_into_pid_and_user_namespace() -> Result<()> {
+fn clone_into_pid_and_user_namespace(
+    subordinate_ranges: Option<&SubordinateRanges>,
+    private_proc: bool,
+) -> Result<(SubordinateIds, Option<String>, Option<String>)> {
     let parent_uid = linux::getuid();
     let parent_gid = linux::getgid();
 
@@ -96,10 +222,15 @@ fn clone_into_pid_and_user_namespace() -> Result<()> {
     // early.
     let parent_pidfd = linux::pidfd_open(linux::getpid())?;
 
+    // Create a pipe the parent uses to tell the child which subordinate ids it mapped.
+    let subid_pipe = subordinate_ranges.map(|_| linux::pipe()).transpose()?;
+
     // Clone a new process into new user and pid namespaces.
-    let mut clone_args = CloneArgs::default()
-        .flags(CloneFlags::NEWUSER | CloneFlags::NEWPID)
-        .exit_signal(Signal::CHLD);
+    let mut flags = CloneFlags::NEWUSER | CloneFlags::NEWPID;
+    if private_proc {
+        flags = flags | CloneFlags::NEWNS;
+    }
+    let mut clone_args = CloneArgs::default().flags(flags).exit_signal(Signal::CHLD);
     match linux::clone3(&mut clone_args)? {
         None => {
             // Child.
@@ -116,13 +247,59 @@ fn clone_into_pid_and_user_namespace() -> Result<()> {
             // We are done with the parent_pidfd now.
             linux::close(parent_pidfd)?;
 
-            // Map uid and guid.
+            // Wait for the parent to map the subordinate ids.
+            let mut subordinate_ids = SubordinateIds::default();
+            let mut subordinate_ids_error = None;
+            if let Some((read_fd, write_fd)) = subid_pipe {
+                linux::close(write_fd)?;
+                let message = read_to_end(read_fd)?;
+                linux::close(read_fd)?;
+                if message.len() < 8 {
+                    subordinate_ids_error = Some("parent exited before mapping ids".into());
+                } else {
+                    let (counts, errors) = message.split_at(8);
+                    subordinate_ids.uids = u32::from_ne_bytes(counts[..4].try_into().unwrap());
+                    subordinate_ids.gids = u32::from_ne_bytes(counts[4..].try_into().unwrap());
+                    subordinate_ids_error =
+                        (!errors.is_empty()).then(|| String::from_utf8_lossy(errors).into_owned());
+                }
+            }
+
+            // Map uid and guid, unless the parent already did. Mapping a single gid requires
+            // denying setgroups.
             let fs = Fs::new();
-            fs.write("/proc/self/setgroups", "deny\n")?;
-            fs.write("/proc/self/uid_map", format!("0 {parent_uid} 1\n"))?;
-            fs.write("/proc/self/gid_map", format!("0 {parent_gid} 1\n"))?;
+            if subordinate_ids.uids == 0 {
+                fs.write("/proc/self/uid_map", format!("0 {parent_uid} 1\n"))?;
+            }
+            if subordinate_ids.gids == 0 {
+                fs.write("/proc/self/setgroups", "deny\n")?;
+                fs.write("/proc/self/gid_map", format!("0 {parent_gid} 1\n"))?;
+            }
 
-            Ok(())
+            // Mount a /proc for the new pid namespace. Without this, the worker would look up
+            // jobs' pids in the parent's pid namespace. Make our mounts private first so that the
+            // new /proc doesn't propagate back to the parent's mount namespace.
+            let private_proc_error = private_proc
+                .then(|| {
+                    linux::mount(
+                        None,
+                        c_str!("/"),
+                        None,
+                        MountFlags::REC | MountFlags::PRIVATE,
+                        None,
+                    )?;
+                    linux::mount(
+                        Some(c_str!("proc")),
+                        c_str!("/proc"),
+                        Some(c_str!("proc")),
+                        MountFlags::NOSUID | MountFlags::NOEXEC | MountFlags::NODEV,
+                        None,
+                    )
+                })
+                .and_then(|result| result.err())
+                .map(|err| err.to_string());
+
+            Ok((subordinate_ids, subordinate_ids_error, private_proc_error))
         }
         Some(child_pid) => {
             // Parent.
@@ -131,6 +308,17 @@ fn clone_into_pid_and_user_namespace() -> Result<()> {
             linux::close(parent_pidfd)
                 .unwrap_or_else(|err| panic!("unexpected error closing pidfd: {}", err));
 
+            // Map the subordinate ids and tell the child how it went.
+            if let (Some(ranges), Some((read_fd, write_fd))) = (subordinate_ranges, subid_pipe) {
+                linux::close(read_fd)
+                    .unwrap_or_else(|err| panic!("unexpected error closing pipe: {}", err));
+                let message = map_subordinate_ids(ranges, child_pid, parent_uid, parent_gid);
+                // If the child has died, it doesn't matter whether it got the message.
+                write_all(write_fd, &message).ok();
+                linux::close(write_fd)
+                    .unwrap_or_else(|err| panic!("unexpected error closing pipe: {}", err));
+            }
+
             // Wait for the child and mimick how it terminated.
             match linux::waitpid(child_pid).unwrap_or_else(|e| {
                 panic!("unexpected error waiting on child process {child_pid}: {e}")
@@ -151,7 +339,7 @@ fn clone_into_pid_and_user_namespace() -> Result<()> {
 
 fn main() -> Result<()> {
     let cli_options = CliOptions::parse();
-    let config: Config = Figment::new()
+    let mut config: Config = Figment::new()
         .merge(Serialized::defaults(ConfigOptions::default()))
         .merge(Toml::file(&cli_options.config_file))
         .merge(Env::prefixed("MAELSTROM_WORKER_"))
@@ -170,21 +358,30 @@ fn main() -> Result<()> {
         println!("{config:#?}");
         return Ok(());
     }
-    clone_into_pid_and_user_namespace()?;
-    let decorator = TermDecorator::new().build();
-    let drain = FullFormat::new(decorator).build().fuse();
-    let drain = Async::new(drain).build().fuse();
-    let level = match config.log_level {
-        LogLevel::Error => Level::Error,
-        LogLevel::Warning => Level::Warning,
-        LogLevel::Info => Level::Info,
-        LogLevel::Debug => Level::Debug,
-    };
-    let drain = LevelFilter::new(drain, level).fuse();
-    let log = Logger::root(drain, o!());
+    let subordinate_ranges = config
+        .subordinate_ids
+        .then(|| SubordinateRanges::for_user(linux::getuid().as_u32()));
+    let (subordinate_ids, subordinate_ids_error, private_proc_error) =
+        clone_into_pid_and_user_namespace(
+            subordinate_ranges
+                .as_ref()
+                .and_then(|ranges| ranges.as_ref().ok()),
+            config.stack_dumper.is_some(),
+        )?;
+    let log = log::root_logger(config.log_level, config.log_format);
+    if let Some(Err(err)) = &subordinate_ranges {
+        warn!(log, "couldn't find subordinate ids, jobs will only have one uid and gid"; "err" => %format!("{err:#}"));
+    }
+    if let Some(err) = subordinate_ids_error {
+        warn!(log, "couldn't map subordinate ids"; "err" => err, "subordinate_ids" => ?subordinate_ids);
+    }
+    if let Some(err) = private_proc_error {
+        warn!(log, "couldn't mount /proc for the worker's pid namespace, jobs won't get stack dumps"; "err" => err);
+        config.stack_dumper = None;
+    }
     Runtime::new()
         .context("starting tokio runtime")?
-        .block_on(async move { maelstrom_worker::main(config, log).await })?;
+        .block_on(async move { maelstrom_worker::main(config, log, subordinate_ids).await })?;
     Ok(())
 }
 
@@ -0,0 +1,256 @@
+//! Serve the worker's metrics over HTTP at `/metrics`, for Prometheus to scrape. This is only
+//! started if the worker is configured with a metrics port.
+
+use crate::{
+    dispatcher::{DispatcherMetrics, Message},
+    DispatcherSender,
+};
+use anyhow::{anyhow, Result};
+use maelstrom_util::metrics::{Encoder, MetricType, CONTENT_TYPE};
+use slog::{debug, o, warn, Logger};
+use std::{
+    io::{BufRead as _, BufReader, Write as _},
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+    thread,
+};
+
+/// Accept HTTP connections on `listener`, forever, handling each one on its own thread. Metrics
+/// are requested from the dispatcher through `dispatcher_sender`.
+pub fn main(listener: TcpListener, dispatcher_sender: DispatcherSender, log: Logger) {
+    for socket in listener.incoming() {
+        let socket = match socket {
+            Ok(socket) => socket,
+            Err(err) => {
+                warn!(log, "error accepting metrics connection"; "err" => %err);
+                continue;
+            }
+        };
+        let dispatcher_sender = dispatcher_sender.clone();
+        let log = match socket.peer_addr() {
+            Ok(peer_addr) => log.new(o!("peer_addr" => peer_addr.to_string())),
+            Err(_) => log.clone(),
+        };
+        thread::spawn(move || {
+            if let Err(err) = connection_main(socket, &dispatcher_sender) {
+                debug!(log, "error serving metrics"; "err" => %err);
+            }
+        });
+    }
+}
+
+/// Serve a single request, then close the connection.
+fn connection_main(socket: TcpStream, dispatcher_sender: &DispatcherSender) -> Result<()> {
+    let mut reader = BufReader::new(socket.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Skip the headers. We don't need any of them.
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim_end().is_empty() {
+            break;
+        }
+    }
+
+    let mut writer = socket;
+    let mut parts = request_line.split_whitespace();
+    let (status, content_type, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => {
+            let (sender, receiver) = mpsc::channel();
+            dispatcher_sender
+                .send(Message::MetricsRequest(sender))
+                .map_err(|_| anyhow!("dispatcher has exited"))?;
+            let metrics = receiver.recv()?;
+            ("200 OK", CONTENT_TYPE, encode(&metrics))
+        }
+        _ => ("404 Not Found", "text/plain", "Not Found\n".into()),
+    };
+    write!(
+        writer,
+        "HTTP/1.1 {status}\r\n\
+        Content-Type: {content_type}\r\n\
+        Content-Length: {}\r\n\
+        Connection: close\r\n\
+        \r\n\
+        {body}",
+        body.len()
+    )?;
+    writer.flush()?;
+    Ok(())
+}
+
+fn encode(metrics: &DispatcherMetrics) -> String {
+    let mut encoder = Encoder::new();
+
+    encoder.gauge(
+        "maelstrom_worker_slots",
+        "Jobs the worker can run at once.",
+        metrics.slots,
+    );
+    encoder.family(
+        "maelstrom_worker_jobs",
+        MetricType::Gauge,
+        "Outstanding jobs, by state.",
+    );
+    for (state, count) in [
+        ("awaiting_layers", metrics.awaiting_layers),
+        ("queued", metrics.queued),
+        ("executing", metrics.executing),
+    ] {
+        encoder.sample("maelstrom_worker_jobs", &[("state", state)], count);
+    }
+    encoder.counter(
+        "maelstrom_worker_jobs_completed",
+        "Jobs that have terminated since the worker started.",
+        metrics.jobs_completed,
+    );
+    encoder.histogram(
+        "maelstrom_worker_job_run_seconds",
+        "Time from a job being started until it terminated.",
+        &metrics.job_run_seconds,
+    );
+
+    encoder.counter(
+        "maelstrom_worker_artifact_fetches",
+        "Artifact fetches started.",
+        metrics.artifact_fetches,
+    );
+    encoder.counter(
+        "maelstrom_worker_artifact_fetch_failures",
+        "Artifact fetches that failed.",
+        metrics.artifact_fetch_failures,
+    );
+    encoder.counter(
+        "maelstrom_worker_artifact_fetched_bytes",
+        "Bytes used on disk by artifacts that were fetched.",
+        metrics.artifact_fetched_bytes,
+    );
+
+    encoder.gauge(
+        "maelstrom_worker_cache_entries",
+        "Artifacts in the cache.",
+        metrics.cache.entries,
+    );
+    encoder.gauge(
+        "maelstrom_worker_cache_files",
+        "Files in the cache's file store.",
+        metrics.cache.files,
+    );
+    encoder.gauge(
+        "maelstrom_worker_cache_bytes_used",
+        "Bytes used by artifacts and files in the cache.",
+        metrics.cache.bytes_used,
+    );
+    encoder.counter(
+        "maelstrom_worker_cache_hits",
+        "Requests by jobs for artifacts that were in the cache.",
+        metrics.cache.hits,
+    );
+    encoder.counter(
+        "maelstrom_worker_cache_misses",
+        "Requests by jobs for artifacts that weren't in the cache.",
+        metrics.cache.misses,
+    );
+    encoder.counter(
+        "maelstrom_worker_cache_evictions",
+        "Artifacts removed to keep the cache under its target size.",
+        metrics.cache.evictions,
+    );
+
+    encoder.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::CacheMetrics;
+    use maelstrom_util::metrics::Histogram;
+    use std::io::Read as _;
+    use tokio::sync::mpsc as tokio_mpsc;
+
+    fn metrics() -> DispatcherMetrics {
+        let mut job_run_seconds = Histogram::default();
+        job_run_seconds.observe(2.);
+        DispatcherMetrics {
+            slots: 4,
+            awaiting_layers: 1,
+            queued: 0,
+            executing: 3,
+            jobs_completed: 1,
+            job_run_seconds,
+            artifact_fetches: 2,
+            artifact_fetch_failures: 0,
+            artifact_fetched_bytes: 1000,
+            cache: CacheMetrics {
+                entries: 2,
+                bytes_used: 1000,
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Start a server whose dispatcher answers every metrics request with [`metrics`], and send it
+    /// `request`. Return the response.
+    fn request(request: &str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (dispatcher_sender, mut dispatcher_receiver) = tokio_mpsc::unbounded_channel();
+        let log = Logger::root(slog::Discard, o!());
+        thread::spawn(move || main(listener, dispatcher_sender, log));
+        thread::spawn(move || {
+            while let Some(msg) = dispatcher_receiver.blocking_recv() {
+                let Message::MetricsRequest(sender) = msg else {
+                    panic!("unexpected message {msg:?}");
+                };
+                sender.send(metrics()).unwrap();
+            }
+        });
+
+        let mut socket = TcpStream::connect(addr).unwrap();
+        socket.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        socket.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn get_metrics() {
+        let response = request("GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(head.contains(&format!("Content-Type: {CONTENT_TYPE}\r\n")));
+        assert!(head.contains(&format!("Content-Length: {}\r\n", body.len())));
+        assert_eq!(body, encode(&metrics()));
+    }
+
+    #[test]
+    fn other_paths_are_not_found() {
+        let response = request("GET / HTTP/1.1\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+
+    #[test]
+    fn encode_metrics() {
+        let text = encode(&metrics());
+        let lines = Vec::from_iter(text.lines());
+        for expected in [
+            "maelstrom_worker_slots 4",
+            "maelstrom_worker_jobs{state=\"awaiting_layers\"} 1",
+            "maelstrom_worker_jobs{state=\"executing\"} 3",
+            "maelstrom_worker_jobs_completed_total 1",
+            "maelstrom_worker_job_run_seconds_bucket{le=\"1\"} 0",
+            "maelstrom_worker_job_run_seconds_bucket{le=\"2.5\"} 1",
+            "maelstrom_worker_job_run_seconds_sum 2",
+            "maelstrom_worker_artifact_fetched_bytes_total 1000",
+            "maelstrom_worker_cache_entries 2",
+            "maelstrom_worker_cache_bytes_used 1000",
+            "maelstrom_worker_cache_evictions_total 0",
+        ] {
+            assert!(
+                lines.contains(&expected),
+                "missing {expected:?} in:\n{text}"
+            );
+        }
+        assert_eq!(lines.last(), Some(&"# EOF"));
+    }
+}
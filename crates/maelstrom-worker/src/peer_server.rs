@@ -0,0 +1,126 @@
+//! Serve the files in the file store to other workers' artifact fetchers. The broker sends
+//! artifact fetchers here once we've told it that we've stored a file.
+
+use anyhow::Result;
+use maelstrom_base::proto::{ArtifactFetcherToPeer, PeerToArtifactFetcher};
+use maelstrom_util::{
+    io::{ChunkedWriter, CompressingWriter},
+    net,
+};
+use slog::{debug, o, warn, Logger};
+use std::{
+    fs::File,
+    io::{self, BufReader},
+    net::{TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    thread,
+};
+
+const MAX_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Accept connections from other workers' artifact fetchers on `listener`, forever, handling each
+/// one on its own thread.
+pub fn main(listener: TcpListener, file_store: PathBuf, log: Logger) {
+    for socket in listener.incoming() {
+        let socket = match socket {
+            Ok(socket) => socket,
+            Err(err) => {
+                warn!(log, "error accepting peer connection"; "err" => %err);
+                continue;
+            }
+        };
+        let file_store = file_store.clone();
+        let log = match socket.peer_addr() {
+            Ok(peer_addr) => log.new(o!("peer_addr" => peer_addr.to_string())),
+            Err(_) => log.clone(),
+        };
+        thread::spawn(move || {
+            debug!(log, "peer artifact fetcher connected");
+            let err = connection_main(socket, &file_store, &log).unwrap_err();
+            debug!(log, "peer artifact fetcher disconnected"; "err" => %err);
+        });
+    }
+}
+
+fn connection_main(socket: TcpStream, file_store: &Path, log: &Logger) -> Result<()> {
+    let mut reader = BufReader::new(socket.try_clone()?);
+    let mut writer = socket;
+    loop {
+        let msg = net::read_message_from_socket::<ArtifactFetcherToPeer>(&mut reader)?;
+        debug!(log, "received peer artifact fetcher message"; "msg" => ?msg);
+        let ArtifactFetcherToPeer(digest, compression) = msg;
+
+        let file = File::open(file_store.join(digest.to_string()));
+        let msg = PeerToArtifactFetcher(file.as_ref().map(drop).map_err(|e| e.to_string()));
+        debug!(log, "sending peer artifact fetcher message"; "msg" => ?msg);
+        net::write_message_to_socket(&mut writer, msg)?;
+
+        let mut file = file?;
+        let mut body =
+            CompressingWriter::new(ChunkedWriter::new(&mut writer, MAX_CHUNK_SIZE), compression)?;
+        io::copy(&mut file, &mut body)?;
+        body.finish()?.finish()?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use maelstrom_base::proto::ArtifactCompression;
+    use maelstrom_test::*;
+    use maelstrom_util::io::{ChunkedReader, DecompressingReader};
+    use std::{fs, io::Read as _};
+    use tempfile::TempDir;
+
+    fn start_server() -> (TempDir, TcpStream, BufReader<TcpStream>) {
+        let file_store = TempDir::new().unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let path = file_store.path().to_owned();
+        thread::spawn(move || main(listener, path, Logger::root(slog::Discard, o!())));
+        let writer = TcpStream::connect(addr).unwrap();
+        let reader = BufReader::new(writer.try_clone().unwrap());
+        (file_store, writer, reader)
+    }
+
+    fn fetch(
+        writer: &mut TcpStream,
+        reader: &mut BufReader<TcpStream>,
+        msg: ArtifactFetcherToPeer,
+    ) -> std::result::Result<Vec<u8>, String> {
+        let compression = msg.1;
+        net::write_message_to_socket(writer, msg).unwrap();
+        let msg: PeerToArtifactFetcher = net::read_message_from_socket(reader).unwrap();
+        msg.0?;
+        let mut body = vec![];
+        DecompressingReader::new(BufReader::new(ChunkedReader::new(reader)), compression)
+            .unwrap()
+            .read_to_end(&mut body)
+            .unwrap();
+        Ok(body)
+    }
+
+    #[test]
+    fn serves_files_from_file_store() {
+        let (file_store, mut writer, mut reader) = start_server();
+        let foo = b"foo".repeat(1000);
+        fs::write(file_store.path().join(digest![1].to_string()), &foo).unwrap();
+        fs::write(file_store.path().join(digest![2].to_string()), b"bar").unwrap();
+
+        for (digest, compression, expected) in [
+            (digest![1], ArtifactCompression::Zstd, foo.as_slice()),
+            (digest![2], ArtifactCompression::None, b"bar"),
+            (digest![1], ArtifactCompression::Gzip, foo.as_slice()),
+        ] {
+            let msg = ArtifactFetcherToPeer(digest, compression);
+            assert_eq!(fetch(&mut writer, &mut reader, msg).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn missing_file_is_an_error() {
+        let (_file_store, mut writer, mut reader) = start_server();
+        let msg = ArtifactFetcherToPeer(digest![1], ArtifactCompression::None);
+        fetch(&mut writer, &mut reader, msg).unwrap_err();
+    }
+}
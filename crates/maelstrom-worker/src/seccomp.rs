@@ -0,0 +1,248 @@
+//! Compile job seccomp profiles into BPF programs.
+
+use anyhow::{anyhow, Result};
+use maelstrom_base::{JobSeccomp, JobSeccompAction, JobSeccompPolicy, JobSeccompRule};
+use maelstrom_linux::{Errno, SockFilter};
+use seccompiler::TargetArch;
+use serde_json::{json, Value};
+use std::env;
+
+/// System calls that the built-in profiles forbid. These are the ones that either reach outside of
+/// the job's container, or that a test has no business making.
+const RESTRICTED_SYSCALLS: &[&str] = &[
+    "acct",
+    "add_key",
+    "bpf",
+    "clock_adjtime",
+    "clock_settime",
+    "delete_module",
+    "finit_module",
+    "fsconfig",
+    "fsmount",
+    "fsopen",
+    "fspick",
+    "init_module",
+    "kexec_file_load",
+    "kexec_load",
+    "keyctl",
+    "mount",
+    "move_mount",
+    "open_tree",
+    "perf_event_open",
+    "pivot_root",
+    "process_vm_readv",
+    "process_vm_writev",
+    "ptrace",
+    "reboot",
+    "request_key",
+    "setns",
+    "settimeofday",
+    "swapoff",
+    "swapon",
+    "umount2",
+    "unshare",
+    "userfaultfd",
+];
+
+fn restricted(action: JobSeccompAction) -> JobSeccompPolicy {
+    JobSeccompPolicy {
+        default_action: JobSeccompAction::Allow,
+        rules: vec![JobSeccompRule {
+            syscalls: RESTRICTED_SYSCALLS.iter().map(|&s| s.into()).collect(),
+            action,
+        }],
+    }
+}
+
+/// Look up one of the worker's built-in profiles. The `default` profile fails the restricted
+/// system calls with `EPERM`, and the `strict` profile kills the job if it makes one.
+fn builtin(name: &str) -> Option<JobSeccompPolicy> {
+    match name {
+        "default" => Some(restricted(JobSeccompAction::Errno(
+            Errno::EPERM.as_u64().try_into().unwrap(),
+        ))),
+        "strict" => Some(restricted(JobSeccompAction::Kill)),
+        _ => None,
+    }
+}
+
+fn resolve(seccomp: &JobSeccomp) -> Result<JobSeccompPolicy> {
+    match seccomp {
+        JobSeccomp::Named(name) => {
+            builtin(name).ok_or_else(|| anyhow!("unknown seccomp profile {name:?}"))
+        }
+        JobSeccomp::Inline(policy) => Ok(policy.clone()),
+    }
+}
+
+/// Return whether the profile can kill the job. The dispatcher uses this to decide whether a job
+/// that died with `SIGSYS` was killed by its profile.
+pub fn may_kill(seccomp: &JobSeccomp) -> bool {
+    let Ok(policy) = resolve(seccomp) else {
+        return false;
+    };
+    policy.default_action == JobSeccompAction::Kill
+        || policy
+            .rules
+            .iter()
+            .any(|rule| rule.action == JobSeccompAction::Kill)
+}
+
+fn json_action(action: JobSeccompAction) -> Value {
+    match action {
+        JobSeccompAction::Allow => json!("allow"),
+        JobSeccompAction::Errno(errno) => json!({ "errno": errno }),
+        JobSeccompAction::Log => json!("log"),
+        JobSeccompAction::Kill => json!("kill_process"),
+    }
+}
+
+fn compile_one(
+    arch: TargetArch,
+    mismatch_action: JobSeccompAction,
+    match_action: JobSeccompAction,
+    syscalls: &[&str],
+) -> Result<Vec<SockFilter>> {
+    let filter = json!({
+        "job": {
+            "mismatch_action": json_action(mismatch_action),
+            "match_action": json_action(match_action),
+            "filter": syscalls
+                .iter()
+                .map(|syscall| json!({ "syscall": syscall }))
+                .collect::<Vec<_>>(),
+        }
+    });
+    let mut programs = seccompiler::compile_from_json(filter.to_string().as_bytes(), arch)
+        .map_err(|err| anyhow!("compiling seccomp profile: {err}"))?;
+    Ok(programs
+        .remove("job")
+        .unwrap()
+        .into_iter()
+        .map(|insn| SockFilter::new(insn.code, insn.jt, insn.jf, insn.k))
+        .collect())
+}
+
+/// Compile a job's seccomp profile into BPF programs, all of which need to be installed. The
+/// kernel runs every installed program for each system call and takes the most restrictive
+/// result, so we compile one program for the default action, and one for each other action used
+/// by a rule.
+pub fn compile(seccomp: &JobSeccomp) -> Result<Vec<Vec<SockFilter>>> {
+    let policy = resolve(seccomp)?;
+    let arch = TargetArch::try_from(env::consts::ARCH)
+        .map_err(|_| anyhow!("seccomp isn't supported on {}", env::consts::ARCH))?;
+
+    let mut programs = vec![];
+    if policy.default_action != JobSeccompAction::Allow {
+        let syscalls = Vec::from_iter(
+            policy
+                .rules
+                .iter()
+                .flat_map(|rule| rule.syscalls.iter().map(String::as_str)),
+        );
+        programs.push(compile_one(
+            arch,
+            policy.default_action,
+            JobSeccompAction::Allow,
+            &syscalls,
+        )?);
+    }
+
+    let mut actions = vec![];
+    for rule in &policy.rules {
+        if rule.action != JobSeccompAction::Allow && !actions.contains(&rule.action) {
+            actions.push(rule.action);
+        }
+    }
+    for action in actions {
+        let syscalls = Vec::from_iter(
+            policy
+                .rules
+                .iter()
+                .filter(|rule| rule.action == action)
+                .flat_map(|rule| rule.syscalls.iter().map(String::as_str)),
+        );
+        programs.push(compile_one(
+            arch,
+            JobSeccompAction::Allow,
+            action,
+            &syscalls,
+        )?);
+    }
+    Ok(programs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use maelstrom_test::string;
+
+    #[test]
+    fn unconfined_policy_compiles_to_nothing() {
+        let seccomp = JobSeccomp::Inline(JobSeccompPolicy {
+            default_action: JobSeccompAction::Allow,
+            rules: vec![],
+        });
+        assert!(compile(&seccomp).unwrap().is_empty());
+        assert!(!may_kill(&seccomp));
+    }
+
+    #[test]
+    fn one_program_per_action() {
+        let seccomp = JobSeccomp::Inline(JobSeccompPolicy {
+            default_action: JobSeccompAction::Errno(1),
+            rules: vec![
+                JobSeccompRule {
+                    syscalls: vec![string!("read"), string!("write")],
+                    action: JobSeccompAction::Allow,
+                },
+                JobSeccompRule {
+                    syscalls: vec![string!("ptrace")],
+                    action: JobSeccompAction::Kill,
+                },
+                JobSeccompRule {
+                    syscalls: vec![string!("mount")],
+                    action: JobSeccompAction::Kill,
+                },
+            ],
+        });
+        assert_eq!(compile(&seccomp).unwrap().len(), 2);
+        assert!(may_kill(&seccomp));
+    }
+
+    #[test]
+    fn builtin_profiles() {
+        assert_eq!(
+            compile(&JobSeccomp::Named(string!("default")))
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(!may_kill(&JobSeccomp::Named(string!("default"))));
+        assert!(may_kill(&JobSeccomp::Named(string!("strict"))));
+    }
+
+    #[test]
+    fn unknown_profile() {
+        assert_eq!(
+            compile(&JobSeccomp::Named(string!("foo")))
+                .err()
+                .unwrap()
+                .to_string(),
+            r#"unknown seccomp profile "foo""#
+        );
+    }
+
+    #[test]
+    fn unknown_syscall() {
+        let seccomp = JobSeccomp::Inline(JobSeccompPolicy {
+            default_action: JobSeccompAction::Allow,
+            rules: vec![JobSeccompRule {
+                syscalls: vec![string!("frobnicate")],
+                action: JobSeccompAction::Errno(1),
+            }],
+        });
+        let err = compile(&seccomp).err().unwrap().to_string();
+        assert!(err.contains("frobnicate"), "{err}");
+    }
+}
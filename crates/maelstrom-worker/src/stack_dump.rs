@@ -0,0 +1,191 @@
+//! Describe what a hung job's threads are doing, so that a job that times out can report more than
+//! whatever output it happened to flush.
+
+use maelstrom_linux::Pid;
+use std::{
+    collections::VecDeque,
+    fmt::Write as _,
+    fs,
+    io::Read as _,
+    path::Path,
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How long the stack dumper gets for each process before we give up on it.
+const STACK_DUMPER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Describe every thread of the process `pid` and of its descendants. For each process, this gives
+/// the name, scheduler state, and kernel wait function of each of its threads, followed by the
+/// userspace backtraces that `stack_dumper` prints for it. Anything that can't be read is skipped,
+/// since the processes may be exiting while we look at them.
+pub fn stack_dump(pid: Pid, stack_dumper: &Path) -> String {
+    let mut dump = String::new();
+    let mut pids = VecDeque::from([pid.to_string()]);
+    while let Some(pid) = pids.pop_front() {
+        let Some(tids) = tids(&pid) else {
+            continue;
+        };
+        let comm = read_trimmed(&format!("/proc/{pid}/comm")).unwrap_or_default();
+        writeln!(dump, "process {pid} ({comm}):").unwrap();
+        for tid in tids {
+            let task = format!("/proc/{pid}/task/{tid}");
+            describe_thread(&mut dump, &tid, &task);
+            if let Some(children) = read_trimmed(&format!("{task}/children")) {
+                pids.extend(children.split_whitespace().map(str::to_owned));
+            }
+        }
+        match run_stack_dumper(stack_dumper, &pid) {
+            Ok(backtraces) => {
+                for line in backtraces.lines() {
+                    writeln!(dump, "    {line}").unwrap();
+                }
+            }
+            Err(err) => writeln!(dump, "    backtraces unavailable: {err}").unwrap(),
+        }
+    }
+    dump
+}
+
+fn read_trimmed(path: &str) -> Option<String> {
+    fs::read_to_string(path)
+        .ok()
+        .map(|contents| contents.trim().to_owned())
+}
+
+fn tids(pid: &str) -> Option<Vec<String>> {
+    let mut tids = fs::read_dir(format!("/proc/{pid}/task"))
+        .ok()?
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .collect::<Vec<_>>();
+    tids.sort_by_key(|tid| tid.parse::<u64>().unwrap_or(u64::MAX));
+    Some(tids)
+}
+
+/// Return the state from the contents of a `stat` file. The state comes right after the command
+/// name, which is in parentheses and may itself contain parentheses or spaces.
+fn state(stat: &str) -> Option<&'static str> {
+    let (_, rest) = stat.rsplit_once(')')?;
+    Some(match rest.split_whitespace().next()? {
+        "R" => "running",
+        "S" => "sleeping",
+        "D" => "waiting on disk",
+        "T" => "stopped",
+        "t" => "stopped by tracer",
+        "Z" => "zombie",
+        "X" => "dead",
+        "I" => "idle",
+        _ => "unknown",
+    })
+}
+
+fn describe_thread(dump: &mut String, tid: &str, task: &str) {
+    let comm = read_trimmed(&format!("{task}/comm")).unwrap_or_default();
+    write!(dump, "  thread {tid} ({comm})").unwrap();
+    if let Some(state) = read_trimmed(&format!("{task}/stat")).and_then(|stat| state(&stat)) {
+        write!(dump, ": {state}").unwrap();
+    }
+    if let Some(wchan) = read_trimmed(&format!("{task}/wchan")).filter(|wchan| wchan != "0") {
+        write!(dump, " in {wchan}").unwrap();
+    }
+    writeln!(dump).unwrap();
+}
+
+/// Run `stack_dumper -p <pid>` and return what it prints. It attaches to the process with ptrace,
+/// which the worker is allowed to do since the job is in a user namespace the worker owns. A
+/// dumper that doesn't finish in time is killed, since the process it's looking at may be wedged.
+fn run_stack_dumper(stack_dumper: &Path, pid: &str) -> Result<String, String> {
+    let mut child = Command::new(stack_dumper)
+        .arg("-p")
+        .arg(pid)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|err| format!("starting {}: {err}", stack_dumper.display()))?;
+    let mut stdout = child.stdout.take().unwrap();
+    let reader = thread::spawn(move || {
+        let mut output = String::new();
+        stdout.read_to_string(&mut output).map(|_| output)
+    });
+    let deadline = Instant::now() + STACK_DUMPER_TIMEOUT;
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if Instant::now() < deadline => thread::sleep(Duration::from_millis(10)),
+            Ok(None) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!("{} timed out", stack_dumper.display()));
+            }
+            Err(err) => return Err(err.to_string()),
+        }
+    };
+    let output = reader
+        .join()
+        .unwrap()
+        .map_err(|err| format!("reading output of {}: {err}", stack_dumper.display()))?;
+    if !status.success() && output.is_empty() {
+        return Err(format!("{} {status}", stack_dumper.display()));
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use maelstrom_linux as linux;
+
+    #[test]
+    fn state_with_odd_command_name() {
+        assert_eq!(state("42 (a) b (c) S 1 42 42 0"), Some("sleeping"));
+        assert_eq!(state("42 (test) R 1"), Some("running"));
+        assert_eq!(state("garbage"), None);
+    }
+
+    #[test]
+    fn dump_includes_threads_descendants_and_backtraces() {
+        let mut child = Command::new("sleep").arg("100").spawn().unwrap();
+        let pid = linux::getpid();
+        // `echo` stands in for a real stack dumper, and just prints its arguments.
+        let dump = stack_dump(pid, Path::new("echo"));
+        child.kill().unwrap();
+        child.wait().unwrap();
+
+        assert!(dump.starts_with(&format!("process {pid} (")), "{dump}");
+        assert!(dump.contains(&format!("  thread {pid} (")), "{dump}");
+        assert!(dump.contains(&format!("    -p {pid}\n")), "{dump}");
+        assert!(
+            dump.contains(&format!("process {} (", child.id())),
+            "{dump}"
+        );
+        assert!(dump.contains(&format!("    -p {}\n", child.id())), "{dump}");
+    }
+
+    #[test]
+    fn dump_with_missing_stack_dumper() {
+        let pid = linux::getpid();
+        let dump = stack_dump(pid, Path::new("/a/stack/dumper/that/does/not/exist"));
+        assert!(
+            dump.contains(
+                "    backtraces unavailable: starting /a/stack/dumper/that/does/not/exist"
+            ),
+            "{dump}"
+        );
+    }
+
+    #[test]
+    fn dump_of_missing_process_is_empty() {
+        let mut child = Command::new("true").spawn().unwrap();
+        let pid = child.id();
+        child.wait().unwrap();
+        assert_eq!(
+            stack_dump(
+                Pid::new_for_test(pid.try_into().unwrap()),
+                Path::new("echo")
+            ),
+            ""
+        );
+    }
+}
@@ -0,0 +1,202 @@
+//! Map ranges of subordinate user and group ids, from `/etc/subuid` and `/etc/subgid`, into the
+//! worker's user namespace, and from there into jobs' user namespaces.
+
+use anyhow::{anyhow, bail, Context as _, Result};
+use maelstrom_linux::Pid;
+use std::{
+    env,
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// The number of extra user and group ids mapped into the worker's user namespace. The worker is
+/// id 0 in its namespace, and the extra ids are 1 through the count. A count of 0 means that just
+/// the worker's own id is mapped.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct SubordinateIds {
+    pub uids: u32,
+    pub gids: u32,
+}
+
+/// A range of subordinate ids on the host.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct IdRange {
+    pub start: u32,
+    pub count: u32,
+}
+
+/// Everything needed to map subordinate ids into the worker's user namespace.
+#[derive(Debug)]
+pub struct SubordinateRanges {
+    pub uids: IdRange,
+    pub gids: IdRange,
+    pub newuidmap: PathBuf,
+    pub newgidmap: PathBuf,
+}
+
+impl SubordinateRanges {
+    /// Look up the subordinate id ranges for the user with the given uid, and find `newuidmap` and
+    /// `newgidmap` in `PATH`.
+    pub fn for_user(uid: u32) -> Result<Self> {
+        let passwd = fs::read_to_string("/etc/passwd").unwrap_or_default();
+        let name = user_name(&passwd, uid);
+        let lookup = |path: &str| -> Result<IdRange> {
+            let contents = fs::read_to_string(path).with_context(|| format!("reading {path}"))?;
+            find_range(&contents, name, uid)
+                .ok_or_else(|| anyhow!("no entry for uid {uid} in {path}"))
+        };
+        Ok(SubordinateRanges {
+            uids: lookup("/etc/subuid")?,
+            gids: lookup("/etc/subgid")?,
+            newuidmap: find_in_path("newuidmap")?,
+            newgidmap: find_in_path("newgidmap")?,
+        })
+    }
+}
+
+fn user_name(passwd: &str, uid: u32) -> Option<&str> {
+    passwd.lines().find_map(|line| {
+        let mut fields = line.split(':');
+        let name = fields.next()?;
+        let line_uid = fields.nth(1)?;
+        (line_uid.parse() == Ok(uid)).then_some(name)
+    })
+}
+
+/// Find the first range in the contents of `/etc/subuid` or `/etc/subgid` for the given user. An
+/// entry can name the user either by name or by uid.
+fn find_range(contents: &str, name: Option<&str>, uid: u32) -> Option<IdRange> {
+    contents.lines().find_map(|line| {
+        let mut fields = line.trim().split(':');
+        let owner = fields.next()?;
+        if Some(owner) != name && owner.parse() != Ok(uid) {
+            return None;
+        }
+        let start = fields.next()?.parse().ok()?;
+        let count = fields.next()?.parse().ok()?;
+        (count > 0).then_some(IdRange { start, count })
+    })
+}
+
+fn find_in_path(program: &str) -> Result<PathBuf> {
+    env::var_os("PATH")
+        .and_then(|path| {
+            env::split_paths(&path)
+                .map(|dir| dir.join(program))
+                .find(|candidate| candidate.is_file())
+        })
+        .ok_or_else(|| anyhow!("couldn't find {program} in PATH"))
+}
+
+/// Run `newuidmap` or `newgidmap` to map `id` to 0 and `range` to 1 and up in the user namespace
+/// of `pid`.
+pub fn run_new_id_map(program: &Path, pid: Pid, id: u32, range: IdRange) -> Result<()> {
+    let status = Command::new(program)
+        .arg(pid.to_string())
+        .args([0, id, 1, 1, range.start, range.count].map(|n| n.to_string()))
+        .status()
+        .with_context(|| format!("running {}", program.display()))?;
+    if !status.success() {
+        bail!("{} failed: {status}", program.display());
+    }
+    Ok(())
+}
+
+/// Build the contents of a job's `uid_map` or `gid_map`. The job's id maps to `worker_id`, so
+/// files created by the worker, like those in the job's writable layer, are owned by the job's user
+/// or group. The subordinate ids, 1 through `count`, map to themselves, so files in layers keep
+/// their owners, except that the job's id and 0 are swapped.
+pub fn job_id_map(job_id: u32, worker_id: u32, count: u32) -> String {
+    let mut map = String::new();
+    writeln!(map, "{job_id} {worker_id} 1").unwrap();
+    if job_id == 0 || job_id > count {
+        if count > 0 {
+            writeln!(map, "1 1 {count}").unwrap();
+        }
+    } else {
+        writeln!(map, "0 {job_id} 1").unwrap();
+        if job_id > 1 {
+            writeln!(map, "1 1 {}", job_id - 1).unwrap();
+        }
+        if count > job_id {
+            writeln!(map, "{} {} {}", job_id + 1, job_id + 1, count - job_id).unwrap();
+        }
+    }
+    map
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn user_name_found() {
+        let passwd = "root:x:0:0:root:/root:/bin/bash\nalice:x:1000:1000::/home/alice:/bin/sh\n";
+        assert_eq!(user_name(passwd, 1000), Some("alice"));
+        assert_eq!(user_name(passwd, 0), Some("root"));
+        assert_eq!(user_name(passwd, 1001), None);
+    }
+
+    #[test]
+    fn find_range_by_name_or_uid() {
+        let contents = "bob:100000:65536\nalice:165536:65536\n1002:231072:1000\n";
+        assert_eq!(
+            find_range(contents, Some("alice"), 1000),
+            Some(IdRange {
+                start: 165536,
+                count: 65536
+            })
+        );
+        assert_eq!(
+            find_range(contents, None, 1002),
+            Some(IdRange {
+                start: 231072,
+                count: 1000
+            })
+        );
+        assert_eq!(find_range(contents, Some("carol"), 1003), None);
+    }
+
+    #[test]
+    fn find_range_skips_malformed_and_empty_entries() {
+        let contents = "alice:x:65536\nalice:100000:0\nalice:200000:10\n";
+        assert_eq!(
+            find_range(contents, Some("alice"), 1000),
+            Some(IdRange {
+                start: 200000,
+                count: 10
+            })
+        );
+    }
+
+    #[test]
+    fn job_id_map_single() {
+        assert_eq!(job_id_map(1000, 0, 0), "1000 0 1\n");
+    }
+
+    #[test]
+    fn job_id_map_root() {
+        assert_eq!(job_id_map(0, 0, 65536), "0 0 1\n1 1 65536\n");
+    }
+
+    #[test]
+    fn job_id_map_in_the_middle() {
+        assert_eq!(
+            job_id_map(1000, 0, 65536),
+            "1000 0 1\n0 1000 1\n1 1 999\n1001 1001 64536\n"
+        );
+    }
+
+    #[test]
+    fn job_id_map_at_the_ends() {
+        assert_eq!(job_id_map(1, 0, 10), "1 0 1\n0 1 1\n2 2 9\n");
+        assert_eq!(job_id_map(10, 0, 10), "10 0 1\n0 10 1\n1 1 9\n");
+    }
+
+    #[test]
+    fn job_id_map_above_range() {
+        assert_eq!(job_id_map(70000, 0, 65536), "70000 0 1\n1 1 65536\n");
+    }
+}